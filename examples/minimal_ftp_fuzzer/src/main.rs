@@ -24,7 +24,8 @@ use butterfly::{
     HasCrossoverReplaceMutation, PacketCrossoverReplaceMutator,
     HasSpliceMutation, PacketSpliceMutator,
     HasHavocMutation, PacketHavocMutator, supported_havoc_mutations,
-    HasPcapRepresentation, load_pcaps, GraphvizMonitor,
+    HasPostMutationFixup,
+    HasPcapRepresentation, load_pcaps, GraphvizMonitor, Throttle,
 };
 use serde::{Serialize, Deserialize};
 use std::marker::PhantomData;
@@ -130,6 +131,8 @@ where
     }
 }
 
+impl HasPostMutationFixup for FTPCommand {}
+
 impl<MT, S> HasHavocMutation<MT, S> for FTPCommand
 where
    MT: MutatorsTuple<BytesInput, S>,
@@ -257,6 +260,7 @@ where
 {
     observers: OT,
     buf: Vec<u8>,
+    throttle: Throttle,
     phantom: PhantomData<S>,
 }
 
@@ -268,6 +272,9 @@ where
         Self {
             observers,
             buf: vec![0; 4096],
+            // Apparently, if we establish too many connections in a short amount of time
+            // LightFTP stops working.
+            throttle: Throttle::new().with_max_connections(20.0),
             phantom: PhantomData,
         }
     }
@@ -441,20 +448,19 @@ where
         let mut cmd_conn: TcpStream;
         let mut data_conn: Option<TcpStream> = None;
         
-        // Apparently, if we establish too many connections in a short amount of time
-        // LightFTP stops working.
-        std::thread::sleep(std::time::Duration::from_millis(50));
-        
         // connect to the server
+        self.throttle.wait_execution();
+        self.throttle.wait_connection();
         cmd_conn = TcpStream::connect("127.0.0.1:2121").expect("command connection");
         
         // initial 220 reply.
         // if we don't get a 220 we will most likely get
         // the message "MAXIMUM ALLOWED USERS CONNECTED"
-        // so we just cancel the execution.
+        // so we just cancel the execution and slow down.
         match self.get_response(&mut cmd_conn) {
             Some(220) => {},
             _ => {
+                self.throttle.overload();
                 return Ok(ExitKind::Ok);
             },
         }
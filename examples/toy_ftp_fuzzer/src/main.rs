@@ -0,0 +1,273 @@
+//! A fuzzer for the ToyFtpServer that comes with butterfly.
+//! Use it as a template for your own harnesses.
+
+use butterfly::{
+    supported_havoc_mutations, HasCrossoverInsertMutation, HasCrossoverReplaceMutation, HasHavocMutation, HasPackets, HasPostMutationFixup, HasSpliceMutation, PacketCrossoverInsertMutator, PacketCrossoverReplaceMutator, PacketDeleteMutator, PacketDuplicateMutator,
+    PacketHavocMutator, PacketMutationScheduler, PacketReorderMutator, PacketSpliceMutator, StateFeedback, StateMonitor, StateObserver, ToyFtpServer,
+};
+use libafl::{
+    bolts::{
+        rands::StdRand,
+        tuples::{tuple_list, MatchName},
+        HasLen,
+    },
+    corpus::InMemoryCorpus,
+    events::SimpleEventManager,
+    executors::{Executor, ExitKind, HasObservers},
+    feedbacks::CrashFeedback,
+    fuzzer::Evaluator,
+    inputs::{BytesInput, HasBytesVec, Input},
+    mutators::{MutationResult, MutatorsTuple},
+    observers::ObserversTuple,
+    schedulers::QueueScheduler,
+    stages::StdMutationalStage,
+    state::{HasMaxSize, HasRand, StdState},
+    Error, Fuzzer, StdFuzzer,
+};
+use serde::{Deserialize, Serialize};
+use std::fmt::{Debug, Formatter};
+use std::io::{BufRead, BufReader, Write};
+use std::marker::PhantomData;
+use std::net::{SocketAddr, TcpStream};
+
+#[derive(Hash, Debug, Clone, Serialize, Deserialize)]
+enum ToyCommand {
+    User(BytesInput),
+    Pass(BytesInput),
+    Cwd(BytesInput),
+    List,
+    Quit,
+}
+
+impl ToyCommand {
+    fn inner_data(&self) -> Option<&BytesInput> {
+        match self {
+            ToyCommand::User(data) | ToyCommand::Pass(data) | ToyCommand::Cwd(data) => Some(data),
+            _ => None,
+        }
+    }
+
+    fn inner_data_mut(&mut self) -> Option<&mut BytesInput> {
+        match self {
+            ToyCommand::User(data) | ToyCommand::Pass(data) | ToyCommand::Cwd(data) => Some(data),
+            _ => None,
+        }
+    }
+}
+
+impl<S> HasCrossoverInsertMutation<S> for ToyCommand
+where
+    S: HasRand + HasMaxSize,
+{
+    fn mutate_crossover_insert(&mut self, state: &mut S, other: &Self, stage_idx: i32) -> Result<MutationResult, Error> {
+        match (self.inner_data_mut(), other.inner_data()) {
+            (Some(data), Some(other_data)) => data.mutate_crossover_insert(state, other_data, stage_idx),
+            _ => Ok(MutationResult::Skipped),
+        }
+    }
+}
+
+impl<S> HasCrossoverReplaceMutation<S> for ToyCommand
+where
+    S: HasRand + HasMaxSize,
+{
+    fn mutate_crossover_replace(&mut self, state: &mut S, other: &Self, stage_idx: i32) -> Result<MutationResult, Error> {
+        match (self.inner_data_mut(), other.inner_data()) {
+            (Some(data), Some(other_data)) => data.mutate_crossover_replace(state, other_data, stage_idx),
+            _ => Ok(MutationResult::Skipped),
+        }
+    }
+}
+
+impl<S> HasSpliceMutation<S> for ToyCommand
+where
+    S: HasRand + HasMaxSize,
+{
+    fn mutate_splice(&mut self, state: &mut S, other: &Self, stage_idx: i32) -> Result<MutationResult, Error> {
+        match (self.inner_data_mut(), other.inner_data()) {
+            (Some(data), Some(other_data)) => data.mutate_splice(state, other_data, stage_idx),
+            _ => Ok(MutationResult::Skipped),
+        }
+    }
+}
+
+impl HasPostMutationFixup for ToyCommand {}
+
+impl<MT, S> HasHavocMutation<MT, S> for ToyCommand
+where
+    MT: MutatorsTuple<BytesInput, S>,
+    S: HasRand + HasMaxSize,
+{
+    fn mutate_havoc(&mut self, state: &mut S, mutations: &mut MT, mutation: usize, stage_idx: i32) -> Result<MutationResult, Error> {
+        match self.inner_data_mut() {
+            Some(data) => data.mutate_havoc(state, mutations, mutation, stage_idx),
+            None => Ok(MutationResult::Skipped),
+        }
+    }
+}
+
+#[derive(Hash, Debug, Clone, Serialize, Deserialize)]
+struct ToyInput {
+    packets: Vec<ToyCommand>,
+}
+
+impl Input for ToyInput {
+    fn generate_name(&self, idx: usize) -> String {
+        format!("toyinput-{}", idx)
+    }
+}
+
+impl HasPackets<ToyCommand> for ToyInput {
+    fn packets(&self) -> &[ToyCommand] {
+        &self.packets
+    }
+
+    fn packets_mut(&mut self) -> &mut Vec<ToyCommand> {
+        &mut self.packets
+    }
+}
+
+impl HasLen for ToyInput {
+    fn len(&self) -> usize {
+        self.packets.len()
+    }
+}
+
+struct ToyExecutor<OT, S>
+where
+    OT: ObserversTuple<ToyInput, S>,
+{
+    observers: OT,
+    addr: SocketAddr,
+    phantom: PhantomData<S>,
+}
+
+impl<OT, S> ToyExecutor<OT, S>
+where
+    OT: ObserversTuple<ToyInput, S>,
+{
+    fn new(observers: OT, addr: SocketAddr) -> Self {
+        Self {
+            observers,
+            addr,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Read a reply and record its status code. Returns false if the server hung up.
+    fn get_response(&mut self, reader: &mut BufReader<TcpStream>) -> bool {
+        let mut line = Vec::new();
+
+        match reader.read_until(b'\n', &mut line) {
+            Ok(0) | Err(_) => false,
+            Ok(_) => {
+                let code = std::str::from_utf8(&line[0..3]).unwrap().parse::<u32>().unwrap();
+                let state_observer: &mut StateObserver<u32> = self.observers.match_name_mut("state").unwrap();
+                state_observer.record(&code);
+                true
+            },
+        }
+    }
+}
+
+impl<OT, S> Debug for ToyExecutor<OT, S>
+where
+    OT: ObserversTuple<ToyInput, S>,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
+        write!(f, "ToyExecutor {{ }}")
+    }
+}
+
+impl<OT, S> HasObservers<ToyInput, OT, S> for ToyExecutor<OT, S>
+where
+    OT: ObserversTuple<ToyInput, S>,
+{
+    fn observers(&self) -> &OT {
+        &self.observers
+    }
+
+    fn observers_mut(&mut self) -> &mut OT {
+        &mut self.observers
+    }
+}
+
+impl<OT, S, EM, Z> Executor<EM, ToyInput, S, Z> for ToyExecutor<OT, S>
+where
+    OT: ObserversTuple<ToyInput, S>,
+{
+    fn run_target(&mut self, _fuzzer: &mut Z, _state: &mut S, _mgr: &mut EM, input: &ToyInput) -> Result<ExitKind, Error> {
+        let mut conn = TcpStream::connect(self.addr)?;
+        let mut reader = BufReader::new(conn.try_clone()?);
+
+        if !self.get_response(&mut reader) {
+            return Ok(ExitKind::Crash);
+        }
+
+        for packet in input.packets() {
+            let (verb, arg): (&[u8], Option<&BytesInput>) = match packet {
+                ToyCommand::User(data) => (b"USER", Some(data)),
+                ToyCommand::Pass(data) => (b"PASS", Some(data)),
+                ToyCommand::Cwd(data) => (b"CWD", Some(data)),
+                ToyCommand::List => (b"LIST", None),
+                ToyCommand::Quit => (b"QUIT", None),
+            };
+
+            let mut line = verb.to_vec();
+            if let Some(arg) = arg {
+                line.push(b' ');
+                line.extend(arg.bytes().iter().filter(|c| **c != b'\n'));
+            }
+            line.extend_from_slice(b"\r\n");
+
+            if conn.write_all(&line).is_err() || !self.get_response(&mut reader) {
+                return Ok(ExitKind::Crash);
+            }
+
+            if let ToyCommand::Quit = packet {
+                break;
+            }
+        }
+
+        Ok(ExitKind::Ok)
+    }
+}
+
+fn seed() -> ToyInput {
+    ToyInput {
+        packets: vec![ToyCommand::User(BytesInput::new(b"anonymous".to_vec())), ToyCommand::Pass(BytesInput::new(b"anonymous".to_vec())), ToyCommand::Cwd(BytesInput::new(b"/tmp".to_vec())), ToyCommand::List, ToyCommand::Quit],
+    }
+}
+
+fn main() {
+    // The target runs in a background thread, no setup needed
+    let server = ToyFtpServer::spawn().unwrap();
+
+    let mut mgr = SimpleEventManager::new(StateMonitor::new());
+    let state_observer = StateObserver::<u32>::new("state");
+    let mut feedback = StateFeedback::new(&state_observer);
+    let mut objective = CrashFeedback::new();
+    let mut state = StdState::new(StdRand::with_seed(0), InMemoryCorpus::new(), InMemoryCorpus::new(), &mut feedback, &mut objective).unwrap();
+    let mut fuzzer = StdFuzzer::new(QueueScheduler::new(), feedback, objective);
+    let mutator = PacketMutationScheduler::new(tuple_list!(
+        PacketReorderMutator::new(),
+        PacketDeleteMutator::new(1),
+        PacketDuplicateMutator::new(16),
+        PacketCrossoverInsertMutator::new(),
+        PacketCrossoverReplaceMutator::new(),
+        PacketSpliceMutator::new(1),
+        PacketHavocMutator::new(supported_havoc_mutations())
+    ));
+    let mut stages = tuple_list!(StdMutationalStage::new(mutator));
+    let mut executor = ToyExecutor::new(tuple_list!(state_observer), server.addr());
+
+    // Load the corpus
+    fuzzer.evaluate_input(&mut state, &mut executor, &mut mgr, seed()).unwrap();
+
+    // Start the campaign
+    fuzzer.fuzz_loop_for(&mut stages, &mut executor, &mut state, &mut mgr, 1000).unwrap();
+
+    // Manually print the stategraph
+    let state_observer: &StateObserver<u32> = executor.observers().match_name("state").unwrap();
+    println!("{}", state_observer.get_statemachine());
+}
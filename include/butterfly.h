@@ -0,0 +1,62 @@
+/*
+ * C API of butterfly, available with the cargo feature "ffi".
+ * See the documentation of the module butterfly::ffi for details.
+ */
+
+#ifndef BUTTERFLY_H
+#define BUTTERFLY_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Return values of the callbacks */
+#define BUTTERFLY_OK      0
+#define BUTTERFLY_CRASH   1
+#define BUTTERFLY_TIMEOUT 2
+#define BUTTERFLY_ABORT   3
+
+/* A StateObserver<u64> */
+typedef struct ButterflyObserver ButterflyObserver;
+
+/* Called before every execution */
+typedef int (*ButterflyResetCallback)(void* user_data);
+
+/* Called for every packet of an input */
+typedef int (*ButterflyPacketCallback)(void* user_data, ButterflyObserver* observer, const uint8_t* data, size_t len);
+
+/* Create a new observer, returns NULL if name is NULL or not valid UTF-8 */
+ButterflyObserver* butterfly_observer_new(const char* name);
+
+/* Release an observer created with butterfly_observer_new() */
+void butterfly_observer_free(ButterflyObserver* observer);
+
+/* The target has entered state */
+void butterfly_observer_record(ButterflyObserver* observer, uint64_t state);
+
+/* The target has entered state after it processed the packet with the index packet_idx */
+void butterfly_observer_record_with_context(ButterflyObserver* observer, uint64_t state, size_t packet_idx);
+
+/* Label the next transition with the input that the target is about to process */
+void butterfly_observer_record_input(ButterflyObserver* observer, const char* label);
+
+/* The target process has been restarted in the middle of an execution */
+void butterfly_observer_target_restart(ButterflyObserver* observer);
+
+/* Number of vertices and edges in the state-graph */
+void butterfly_observer_info(const ButterflyObserver* observer, size_t* nodes, size_t* edges);
+
+/* The state-graph in DOT format, must be released with butterfly_string_free() */
+char* butterfly_observer_statemachine(const ButterflyObserver* observer);
+
+/* Release a string returned by butterfly */
+void butterfly_string_free(char* string);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* BUTTERFLY_H */
@@ -0,0 +1,234 @@
+use crate::{executor::HasPayload, input::HasPackets};
+use libafl::{
+    corpus::Corpus,
+    inputs::Input,
+    mutators::Tokens,
+    stages::Stage,
+    state::{HasCorpus, HasMetadata},
+    Error,
+};
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::path::Path;
+
+/// Lengths of the magic byte sequences taken from the start of packets
+const MAGIC_LENGTHS: [usize; 2] = [2, 4];
+
+#[inline]
+fn is_token_byte(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || matches!(byte, b'_' | b'-' | b'.' | b'/' | b':')
+}
+
+/// Add the tokens of an AFL-style dictionary file at `path` to the [`Tokens`](libafl::mutators::Tokens)
+/// metadata of the state, where `TokenInsert` and `TokenReplace` pick them up.
+/// Returns the number of tokens that were new.
+///
+/// # Example
+/// ```
+/// // ftp.dict contains lines like: user="USER "
+/// load_tokens(&mut state, "ftp.dict")?;
+/// let mutator = PacketHavocMutator::new(supported_havoc_mutations_with_tokens());
+/// ```
+pub fn load_tokens<S, P>(state: &mut S, path: P) -> Result<usize, Error>
+where
+    S: HasMetadata,
+    P: AsRef<Path>,
+{
+    let tokens = Tokens::from_file(path)?;
+
+    if !state.has_metadata::<Tokens>() {
+        state.add_metadata(Tokens::new());
+    }
+
+    let dict = state.metadata_mut().get_mut::<Tokens>().unwrap();
+    Ok(tokens.tokens().iter().filter(|token| dict.add_token(token)).count())
+}
+
+/// Extracts tokens for LibAFLs token mutators from packets, similar to the autodict of AFL++.
+///
+/// Two kinds of tokens are collected:
+/// - ASCII words, i.e. runs of alphanumeric characters and `_-./:`
+/// - magic byte sequences: the first 2 and 4 bytes of binary packets
+///
+/// Only tokens that occur at least `min_count` times survive and
+/// the most frequent ones are stored in the [`Tokens`](libafl::mutators::Tokens) metadata of the state,
+/// where `TokenInsert` and `TokenReplace` pick them up.
+///
+/// # Example
+/// ```
+/// load_pcaps(&mut state, &mut fuzzer, &mut executor, &mut mgr, "pcaps").unwrap();
+/// let new_tokens = TokenExtractor::new().with_min_count(3).extract_from_corpus::<_, _, NetworkPacket<BytesInput>>(&mut state).unwrap();
+/// ```
+#[derive(Clone, Debug)]
+pub struct TokenExtractor {
+    min_len: usize,
+    max_len: usize,
+    min_count: usize,
+    max_tokens: usize,
+}
+
+impl TokenExtractor {
+    /// Create a new TokenExtractor that keeps up to 256 words of 3 to 32 characters
+    /// and magic bytes that occur at least twice
+    pub fn new() -> Self {
+        Self {
+            min_len: 3,
+            max_len: 32,
+            min_count: 2,
+            max_tokens: 256,
+        }
+    }
+
+    /// Only keep ASCII words with `min_len` to `max_len` characters
+    pub fn with_word_len(mut self, min_len: usize, max_len: usize) -> Self {
+        self.min_len = min_len.max(1);
+        self.max_len = max_len.max(self.min_len);
+        self
+    }
+
+    /// Only keep tokens that occur at least `min_count` times
+    pub fn with_min_count(mut self, min_count: usize) -> Self {
+        self.min_count = min_count;
+        self
+    }
+
+    /// Keep at most `max_tokens` of the most frequent tokens
+    pub fn with_max_tokens(mut self, max_tokens: usize) -> Self {
+        self.max_tokens = max_tokens;
+        self
+    }
+
+    /// Extract the tokens from a list of packet payloads, most frequent first
+    pub fn extract<'a, IT>(&self, payloads: IT) -> Vec<Vec<u8>>
+    where
+        IT: IntoIterator<Item = &'a [u8]>,
+    {
+        let mut counts = HashMap::<&[u8], usize>::new();
+
+        for payload in payloads {
+            for word in payload.split(|byte| !is_token_byte(*byte)) {
+                if (self.min_len..=self.max_len).contains(&word.len()) {
+                    *counts.entry(word).or_default() += 1;
+                }
+            }
+
+            for len in MAGIC_LENGTHS {
+                if let Some(magic) = payload.get(..len) {
+                    if magic.iter().any(|byte| !byte.is_ascii_graphic()) {
+                        *counts.entry(magic).or_default() += 1;
+                    }
+                }
+            }
+        }
+
+        let mut tokens: Vec<(&[u8], usize)> = counts.into_iter().filter(|(_, count)| *count >= self.min_count).collect();
+        tokens.sort_unstable_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+        tokens.into_iter().take(self.max_tokens).map(|(token, _)| token.to_vec()).collect()
+    }
+
+    /// Extract the tokens from all packets in the corpus and add them to the
+    /// [`Tokens`](libafl::mutators::Tokens) metadata of the state.
+    /// Returns the number of tokens that were new.
+    pub fn extract_from_corpus<I, S, P>(&self, state: &mut S) -> Result<usize, Error>
+    where
+        I: Input + HasPackets<P>,
+        S: HasCorpus<I> + HasMetadata,
+        P: HasPayload,
+    {
+        let mut payloads = Vec::new();
+
+        for idx in 0..state.corpus().count() {
+            let mut testcase = state.corpus().get(idx)?.borrow_mut();
+            payloads.extend(testcase.load_input()?.packets().iter().map(|packet| packet.payload()));
+        }
+
+        let tokens = self.extract(payloads.iter().map(|payload| payload.as_slice()));
+
+        if !state.has_metadata::<Tokens>() {
+            state.add_metadata(Tokens::new());
+        }
+
+        let dict = state.metadata_mut().get_mut::<Tokens>().unwrap();
+        Ok(tokens.iter().filter(|token| dict.add_token(token)).count())
+    }
+}
+
+/// A stage that periodically runs a [`TokenExtractor`] over the corpus,
+/// so that tokens of inputs found during fuzzing end up in the dictionary, too.
+///
+/// # Example
+/// ```
+/// // Extract tokens every 1000 iterations of the fuzzing loop
+/// let mut stages = tuple_list!(
+///     TokenExtractionStage::<_, NetworkPacket<BytesInput>>::new(TokenExtractor::new(), 1000),
+///     StdMutationalStage::new(mutator),
+/// );
+/// ```
+#[derive(Clone, Debug)]
+pub struct TokenExtractionStage<I, P> {
+    extractor: TokenExtractor,
+    interval: usize,
+    iterations: usize,
+    phantom: PhantomData<(I, P)>,
+}
+
+impl<I, P> TokenExtractionStage<I, P> {
+    /// Create a new TokenExtractionStage that runs `extractor` every `interval` iterations
+    pub fn new(extractor: TokenExtractor, interval: usize) -> Self {
+        Self {
+            extractor,
+            interval: interval.max(1),
+            iterations: 0,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<E, EM, I, P, S, Z> Stage<E, EM, S, Z> for TokenExtractionStage<I, P>
+where
+    I: Input + HasPackets<P>,
+    S: HasCorpus<I> + HasMetadata,
+    P: HasPayload,
+{
+    fn perform(&mut self, _fuzzer: &mut Z, _executor: &mut E, state: &mut S, _manager: &mut EM, _corpus_idx: usize) -> Result<(), Error> {
+        self.iterations += 1;
+
+        if self.iterations.checked_rem(self.interval) == Some(0) {
+            let new_tokens = self.extractor.extract_from_corpus(state)?;
+
+            if new_tokens > 0 {
+                println!("[butterfly] Extracted {} new tokens from the corpus", new_tokens);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libafl::{bolts::rands::StdRand, corpus::InMemoryCorpus, inputs::BytesInput, state::StdState};
+
+    #[test]
+    fn test_extract() {
+        let payloads: [&[u8]; 4] = [b"USER anonymous\r\n", b"USER ftp\r\n", b"\x00\x01\x02\x03data", b"\x00\x01\x02\x03USER"];
+        let tokens = TokenExtractor::new().extract(payloads);
+
+        assert_eq!(tokens, vec![b"USER".to_vec(), b"\x00\x01".to_vec(), b"\x00\x01\x02\x03".to_vec()]);
+        assert_eq!(TokenExtractor::new().with_min_count(1).with_max_tokens(1).extract(payloads), vec![b"USER".to_vec()]);
+    }
+
+    #[test]
+    fn test_load_tokens() {
+        let path = std::env::temp_dir().join(format!("butterfly-tokens-{}.dict", std::process::id()));
+        std::fs::write(&path, "# FTP\nuser=\"USER \"\nsite=\"SITE EXEC\"\n").unwrap();
+        let mut state = StdState::new(StdRand::with_seed(0), InMemoryCorpus::<BytesInput>::new(), InMemoryCorpus::<BytesInput>::new(), &mut (), &mut ()).unwrap();
+
+        assert_eq!(load_tokens(&mut state, &path).unwrap(), 2);
+        assert_eq!(load_tokens(&mut state, &path).unwrap(), 0);
+        let _ = std::fs::remove_file(&path);
+
+        assert_eq!(state.metadata().get::<Tokens>().unwrap().tokens(), &[b"USER ".to_vec(), b"SITE EXEC".to_vec()]);
+    }
+}
@@ -0,0 +1,237 @@
+use crate::{
+    event::{register_user_stat, UserStatFormat, USER_STAT_EDGES, USER_STAT_NODES},
+    observer::StateObserver,
+};
+use libafl::{
+    bolts::tuples::Named,
+    events::{Event, EventFirer},
+    executors::ExitKind,
+    feedbacks::Feedback,
+    inputs::Input,
+    monitors::UserStats,
+    observers::ObserversTuple,
+    state::HasClientPerfMonitor,
+    Error,
+};
+use serde::{Deserialize, Serialize};
+use std::cmp::Eq;
+use std::fmt::Debug;
+use std::hash::Hash;
+use std::marker::PhantomData;
+
+/// One state signal of a target, the building block of a [`MultiStateFeedback`].
+/// It refers to the [`StateObserver`] that records the signal.
+#[derive(Debug)]
+pub struct StateChannel<PS> {
+    observer_name: String,
+    phantom: PhantomData<PS>,
+}
+
+impl<PS> StateChannel<PS>
+where
+    PS: Debug + Clone + Eq + Hash + Serialize + for<'a> Deserialize<'a>,
+{
+    /// Create a new StateChannel for the states of `observer`
+    pub fn new(observer: &StateObserver<PS>) -> Self {
+        Self {
+            observer_name: observer.name().to_string(),
+            phantom: PhantomData,
+        }
+    }
+}
+
+/// A tuple of [`StateChannel`]s, created with LibAFLs `tuple_list!`.
+pub trait StateChannelsTuple: Debug {
+    /// Append whether the state-graph of each channel grew in the last run and its number of vertices and edges to `out`.
+    /// With `hit_buckets` a transition whose hit count reached a new bucket counts as growth.
+    fn evaluate_all<I, S, OT>(&self, observers: &OT, hit_buckets: bool, out: &mut Vec<(bool, usize, usize)>) -> Result<(), Error>
+    where
+        I: Input,
+        S: HasClientPerfMonitor,
+        OT: ObserversTuple<I, S>;
+
+    /// Append the names of the observers of all channels to `out`
+    fn names_all(&self, out: &mut Vec<String>);
+}
+
+impl StateChannelsTuple for () {
+    fn evaluate_all<I, S, OT>(&self, _observers: &OT, _hit_buckets: bool, _out: &mut Vec<(bool, usize, usize)>) -> Result<(), Error>
+    where
+        I: Input,
+        S: HasClientPerfMonitor,
+        OT: ObserversTuple<I, S>,
+    {
+        Ok(())
+    }
+
+    fn names_all(&self, _out: &mut Vec<String>) {}
+}
+
+impl<PS, Tail> StateChannelsTuple for (StateChannel<PS>, Tail)
+where
+    PS: Debug + Clone + Eq + Hash + Serialize + for<'a> Deserialize<'a>,
+    Tail: StateChannelsTuple,
+{
+    fn evaluate_all<I, S, OT>(&self, observers: &OT, hit_buckets: bool, out: &mut Vec<(bool, usize, usize)>) -> Result<(), Error>
+    where
+        I: Input,
+        S: HasClientPerfMonitor,
+        OT: ObserversTuple<I, S>,
+    {
+        let observer = observers.match_name::<StateObserver<PS>>(&self.0.observer_name).ok_or_else(|| Error::key_not_found(format!("No StateObserver named \"{}\" with the given state type", self.0.observer_name)))?;
+        let grew = observer.had_new_transitions() || observer.had_new_ngrams() || (hit_buckets && observer.had_new_hit_buckets());
+        let (nodes, edges) = observer.info();
+        out.push((grew, nodes, edges));
+        self.1.evaluate_all::<I, S, OT>(observers, hit_buckets, out)
+    }
+
+    fn names_all(&self, out: &mut Vec<String>) {
+        out.push(self.0.observer_name.clone());
+        self.1.names_all(out);
+    }
+}
+
+/// Determines that an input is interesting if it led to new transitions in any of several
+/// independent state signals of the target, e.g. the response code, an internal phase variable
+/// and the number of open connections.
+///
+/// Every signal is recorded by its own [`StateObserver`] with its own state type, so the state-graphs stay small
+/// instead of growing with the product of all signals, like they would if the signals were packed into one tuple.
+///
+/// Besides the user stats [`USER_STAT_NODES`](crate::USER_STAT_NODES) and [`USER_STAT_EDGES`](crate::USER_STAT_EDGES),
+/// which hold the sums over all channels, it reports the size of every state-graph with the keys
+/// `statemachine_nodes_<observer name>` and `statemachine_edges_<observer name>`. They are registered with
+/// [`register_user_stat()`](crate::register_user_stat) when the feedback is created. If the monitor runs in a different
+/// process, register them there yourself with [`UserStatFormat::Average`](crate::UserStatFormat::Average).
+///
+/// # Example
+/// ```
+/// let code_observer = StateObserver::<u32>::new("code");
+/// let phase_observer = StateObserver::<u8>::new("phase");
+/// let mut feedback = MultiStateFeedback::new(tuple_list!(StateChannel::new(&code_observer), StateChannel::new(&phase_observer)));
+/// ```
+#[derive(Debug)]
+pub struct MultiStateFeedback<CT> {
+    channels: CT,
+    names: Vec<String>,
+    hit_buckets: bool,
+    reports: Vec<(bool, usize, usize)>,
+}
+
+impl<CT> MultiStateFeedback<CT> {
+    /// Create a new MultiStateFeedback from a tuple of [`StateChannel`]s
+    pub fn new(channels: CT) -> Self
+    where
+        CT: StateChannelsTuple,
+    {
+        let mut names = Vec::new();
+        channels.names_all(&mut names);
+
+        for name in &names {
+            register_user_stat(&format!("{}_{}", USER_STAT_NODES, name), &format!("{} nodes", name), UserStatFormat::Average);
+            register_user_stat(&format!("{}_{}", USER_STAT_EDGES, name), &format!("{} edges", name), UserStatFormat::Average);
+        }
+
+        Self {
+            channels,
+            names,
+            hit_buckets: false,
+            reports: Vec::new(),
+        }
+    }
+
+    /// Also consider inputs interesting if the hit count of a transition reached a new bucket in any channel,
+    /// see [`StateFeedback::with_hit_buckets()`](crate::StateFeedback::with_hit_buckets)
+    pub fn with_hit_buckets(mut self) -> Self {
+        self.hit_buckets = true;
+        self
+    }
+}
+
+impl<CT> Named for MultiStateFeedback<CT> {
+    fn name(&self) -> &str {
+        "MultiStateFeedback"
+    }
+}
+
+impl<I, S, CT> Feedback<I, S> for MultiStateFeedback<CT>
+where
+    I: Input,
+    S: HasClientPerfMonitor,
+    CT: StateChannelsTuple,
+{
+    fn is_interesting<EM, OT>(&mut self, state: &mut S, mgr: &mut EM, _input: &I, observers: &OT, _exit_kind: &ExitKind) -> Result<bool, Error>
+    where
+        EM: EventFirer<I>,
+        OT: ObserversTuple<I, S>,
+    {
+        self.reports.clear();
+        self.channels.evaluate_all::<I, S, OT>(observers, self.hit_buckets, &mut self.reports)?;
+
+        if !self.reports.iter().any(|(grew, _, _)| *grew) {
+            return Ok(false);
+        }
+
+        let mut stats = vec![(USER_STAT_NODES.to_string(), self.reports.iter().map(|(_, nodes, _)| nodes).sum::<usize>()), (USER_STAT_EDGES.to_string(), self.reports.iter().map(|(_, _, edges)| edges).sum::<usize>())];
+
+        for (name, (_, nodes, edges)) in self.names.iter().zip(&self.reports) {
+            stats.push((format!("{}_{}", USER_STAT_NODES, name), *nodes));
+            stats.push((format!("{}_{}", USER_STAT_EDGES, name), *edges));
+        }
+
+        for (name, value) in stats {
+            mgr.fire(
+                state,
+                Event::UpdateUserStats {
+                    name,
+                    value: UserStats::Number(value as u64),
+                    phantom: PhantomData,
+                },
+            )?;
+        }
+
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libafl::{
+        bolts::{rands::StdRand, tuples::tuple_list},
+        corpus::InMemoryCorpus,
+        events::NopEventManager,
+        inputs::BytesInput,
+        observers::Observer,
+        state::StdState,
+    };
+
+    #[test]
+    fn test_multi_state_feedback() {
+        let mut state = StdState::new(StdRand::with_seed(0), InMemoryCorpus::<BytesInput>::new(), InMemoryCorpus::<BytesInput>::new(), &mut (), &mut ()).unwrap();
+        let mut mgr = NopEventManager {};
+        let input = BytesInput::new(b"USER a".to_vec());
+        let code_observer = StateObserver::<u32>::new("code");
+        let phase_observer = StateObserver::<u8>::new("phase");
+        let mut feedback = MultiStateFeedback::new(tuple_list!(StateChannel::new(&code_observer), StateChannel::new(&phase_observer)));
+        let mut observers = tuple_list!(code_observer, phase_observer);
+
+        let mut run = |observers: &mut (StateObserver<u32>, (StateObserver<u8>, ())), codes: &[u32], phases: &[u8]| {
+            observers.0.pre_exec(&mut state, &input).unwrap();
+            observers.1 .0.pre_exec(&mut state, &input).unwrap();
+            codes.iter().for_each(|code| observers.0.record(code));
+            phases.iter().for_each(|phase| observers.1 .0.record(phase));
+            feedback.is_interesting(&mut state, &mut mgr, &input, observers, &ExitKind::Ok).unwrap()
+        };
+
+        assert!(run(&mut observers, &[220, 331], &[0, 0]));
+        assert!(!run(&mut observers, &[220, 331], &[0, 0]));
+
+        // Novelty in either channel counts
+        assert!(run(&mut observers, &[220, 331], &[0, 1]));
+        assert!(run(&mut observers, &[220, 230], &[0, 1]));
+        assert!(!run(&mut observers, &[220, 230], &[0, 1]));
+
+        assert_eq!(feedback.names, vec!["code", "phase"]);
+    }
+}
@@ -0,0 +1,214 @@
+use crate::{config::CampaignConfig, observer::StateObserver, output::client_dir};
+use libafl::{
+    corpus::Corpus,
+    inputs::Input,
+    state::{HasCorpus, HasExecutions, HasMetadata, HasSolutions},
+    Error,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::cmp::Eq;
+use std::fmt::Debug;
+use std::fs::File;
+use std::hash::Hash;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+const STATE_FILE: &str = "state.postcard";
+const OBSERVER_FILE: &str = "observer.postcard";
+const STATS_FILE: &str = "stats.txt";
+const CONFIG_FILE: &str = "config.txt";
+
+/// Saves and restores named snapshots of a campaign.
+///
+/// A checkpoint contains the fuzzer state, i.e. the corpus, the solutions,
+/// the metadata of the scheduler and all other metadata, as well as the state-graph
+/// of a [`StateObserver`] and a human-readable `stats.txt`. If the configuration of the campaign
+/// was recorded in a [`CampaignConfig`], it is additionally written to a human-readable `config.txt`.
+/// Corpora that live on disk are only saved as references to their files, so
+/// don't delete the corpus directory of a campaign you want to restore later.
+///
+/// Checkpoints are written into a temporary directory that is renamed once
+/// everything has been written, so a crash while saving never leaves a half-written checkpoint behind.
+///
+/// This makes it possible to branch long campaigns, e.g. to try a different
+/// mutator mix starting from the same midpoint:
+/// ```
+/// let checkpoints = Checkpoints::new("checkpoints");
+/// checkpoints.save("after-24h", &state, &state_observer).unwrap();
+///
+/// // Later, in a different process
+/// let mut state: StdState<...> = checkpoints.restore("after-24h", &mut state_observer).unwrap();
+/// ```
+/// Types that are stored in the metadata of the state must be registered in
+/// LibAFLs [`RegistryBuilder`](libafl::bolts::serdeany::RegistryBuilder) before restoring.
+#[derive(Clone, Debug)]
+pub struct Checkpoints {
+    dir: PathBuf,
+}
+
+impl Checkpoints {
+    /// Create a new Checkpoints handle that stores all checkpoints in `dir`
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Self {
+        Self {
+            dir: dir.into(),
+        }
+    }
+
+    /// Store the checkpoints in `<dir>/client-<client_id>` so that the clients of a multi-core campaign
+    /// don't overwrite each other's checkpoints, see [`client_dir()`](crate::client_dir)
+    pub fn with_client_id(mut self, client_id: usize) -> Self {
+        self.dir = client_dir(&self.dir, client_id);
+        self
+    }
+
+    fn checked_path(&self, name: &str) -> Result<PathBuf, Error> {
+        if name.is_empty() || name.starts_with('.') || name.contains(['/', '\\']) {
+            return Err(Error::illegal_argument(format!("Invalid checkpoint name '{}'", name)));
+        }
+
+        Ok(self.dir.join(name))
+    }
+
+    /// Save a checkpoint with the given name. An existing checkpoint with the same name gets replaced.
+    /// Returns the directory of the checkpoint.
+    pub fn save<I, S, PS>(&self, name: &str, state: &S, observer: &StateObserver<PS>) -> Result<PathBuf, Error>
+    where
+        I: Input,
+        S: HasCorpus<I> + HasSolutions<I> + HasExecutions + HasMetadata + Serialize,
+        PS: Clone + Debug + Eq + Hash + Serialize + for<'a> Deserialize<'a>,
+    {
+        let path = self.checked_path(name)?;
+        let tmp = self.dir.join(format!(".{}.tmp", name));
+        let old = self.dir.join(format!(".{}.old", name));
+
+        if tmp.exists() {
+            std::fs::remove_dir_all(&tmp)?;
+        }
+        std::fs::create_dir_all(&tmp)?;
+
+        write_synced(&tmp.join(STATE_FILE), &postcard::to_allocvec(state)?)?;
+        write_synced(&tmp.join(OBSERVER_FILE), &postcard::to_allocvec(observer)?)?;
+
+        let (nodes, edges) = observer.info();
+        let stats = format!("executions={}\ncorpus={}\nsolutions={}\nnodes={}\nedges={}\nabstraction_level={}\n", state.executions(), state.corpus().count(), state.solutions().count(), nodes, edges, observer.abstraction_level());
+        write_synced(&tmp.join(STATS_FILE), stats.as_bytes())?;
+
+        if let Some(config) = state.metadata().get::<CampaignConfig>() {
+            write_synced(&tmp.join(CONFIG_FILE), config.to_string().as_bytes())?;
+        }
+
+        if path.exists() {
+            if old.exists() {
+                std::fs::remove_dir_all(&old)?;
+            }
+            std::fs::rename(&path, &old)?;
+        }
+
+        std::fs::rename(&tmp, &path)?;
+
+        if old.exists() {
+            std::fs::remove_dir_all(&old)?;
+        }
+
+        println!("[butterfly] Saved checkpoint {}", path.display());
+        Ok(path)
+    }
+
+    /// Restore the checkpoint with the given name.
+    /// The state-graph is loaded into `observer`, which must have the same abstraction levels
+    /// as the observer that was saved. The fuzzer state is returned.
+    pub fn restore<S, PS>(&self, name: &str, observer: &mut StateObserver<PS>) -> Result<S, Error>
+    where
+        S: DeserializeOwned,
+        PS: Clone + Debug + Eq + Hash + Serialize + for<'a> Deserialize<'a>,
+    {
+        let path = self.checked_path(name)?;
+
+        if !path.is_dir() {
+            return Err(Error::key_not_found(format!("Checkpoint {} does not exist", path.display())));
+        }
+
+        let state = postcard::from_bytes(&std::fs::read(path.join(STATE_FILE))?)?;
+        observer.restore(postcard::from_bytes(&std::fs::read(path.join(OBSERVER_FILE))?)?)?;
+
+        println!("[butterfly] Restored checkpoint {}", path.display());
+        Ok(state)
+    }
+
+    /// Returns the names of all saved checkpoints in alphabetical order
+    pub fn list(&self) -> Result<Vec<String>, Error> {
+        let mut names = Vec::new();
+
+        if !self.dir.is_dir() {
+            return Ok(names);
+        }
+
+        for entry in std::fs::read_dir(&self.dir)? {
+            let entry = entry?;
+
+            if let Some(name) = entry.file_name().to_str() {
+                if !name.starts_with('.') && entry.path().join(STATE_FILE).is_file() {
+                    names.push(name.to_string());
+                }
+            }
+        }
+
+        names.sort();
+        Ok(names)
+    }
+}
+
+fn write_synced(path: &Path, data: &[u8]) -> Result<(), Error> {
+    let mut file = File::create(path)?;
+    file.write_all(data)?;
+    file.sync_all()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libafl::{
+        bolts::rands::StdRand,
+        corpus::{InMemoryCorpus, Testcase},
+        inputs::BytesInput,
+        state::StdState,
+    };
+
+    #[test]
+    fn test_checkpoints() {
+        let dir = std::env::temp_dir().join(format!("butterfly-checkpoints-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let mut corpus = InMemoryCorpus::<BytesInput>::new();
+        corpus.add(Testcase::new(BytesInput::new(b"seed".to_vec()))).unwrap();
+        let mut state = StdState::new(StdRand::with_seed(0), corpus, InMemoryCorpus::<BytesInput>::new(), &mut (), &mut ()).unwrap();
+        *state.executions_mut() = 1234;
+        state.add_metadata(CampaignConfig {
+            components: vec![crate::ComponentConfig::new("NetworkExecutor").with_setting("retries", 3)],
+        });
+
+        let mut observer = StateObserver::<u32>::new("state");
+        for s in [1, 2, 3] {
+            observer.record(&s);
+        }
+
+        let checkpoints = Checkpoints::new(&dir);
+        checkpoints.save("midpoint", &state, &observer).unwrap();
+        checkpoints.save("midpoint", &state, &observer).unwrap();
+        assert!(checkpoints.save("../escape", &state, &observer).is_err());
+        assert_eq!(checkpoints.list().unwrap(), ["midpoint"]);
+        assert_eq!(std::fs::read_to_string(dir.join("midpoint").join(CONFIG_FILE)).unwrap(), "NetworkExecutor\n  retries = 3\n");
+
+        let mut restored_observer = StateObserver::<u32>::new("state");
+        let restored: StdState<InMemoryCorpus<BytesInput>, BytesInput, StdRand, InMemoryCorpus<BytesInput>> = checkpoints.restore("midpoint", &mut restored_observer).unwrap();
+        assert_eq!(*restored.executions(), 1234);
+        assert_eq!(restored.corpus().count(), 1);
+        assert_eq!(restored_observer.info(), (3, 2));
+
+        let mut other_levels = StateObserver::<u32>::new("state").with_abstraction_level(|s| s / 2);
+        assert!(checkpoints.restore::<StdState<InMemoryCorpus<BytesInput>, BytesInput, StdRand, InMemoryCorpus<BytesInput>>, _>("midpoint", &mut other_levels).is_err());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
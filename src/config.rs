@@ -0,0 +1,182 @@
+use libafl::{impl_serdeany, state::HasMetadata};
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+
+/// The effective configuration of a single component, e.g. a mutator, the scheduler or the executor.
+///
+/// Components that consist of other components, like the [`PacketMutationScheduler`](crate::PacketMutationScheduler),
+/// list their configuration as children.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ComponentConfig {
+    /// Name of the component
+    pub name: String,
+    /// Parameters of the component as `(name, value)` pairs in a human-readable format
+    pub settings: Vec<(String, String)>,
+    /// Configurations of the components this one consists of
+    pub children: Vec<ComponentConfig>,
+}
+
+impl ComponentConfig {
+    /// Create a new ComponentConfig without any settings
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            settings: Vec::new(),
+            children: Vec::new(),
+        }
+    }
+
+    /// Add a setting with the given name and value
+    pub fn with_setting<V: Display>(mut self, name: &str, value: V) -> Self {
+        self.settings.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// Add the configuration of a component this one consists of
+    pub fn with_child(mut self, child: ComponentConfig) -> Self {
+        self.children.push(child);
+        self
+    }
+
+    /// Returns the value of the setting `name`
+    pub fn setting(&self, name: &str) -> Option<&str> {
+        self.settings.iter().find(|(key, _)| key == name).map(|(_, value)| value.as_str())
+    }
+
+    fn write(&self, f: &mut Formatter<'_>, depth: usize) -> std::fmt::Result {
+        writeln!(f, "{:indent$}{}", "", self.name, indent = 2 * depth)?;
+
+        for (name, value) in &self.settings {
+            writeln!(f, "{:indent$}{} = {}", "", name, value, indent = 2 * depth + 2)?;
+        }
+
+        for child in &self.children {
+            child.write(f, depth + 1)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Display for ComponentConfig {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        self.write(f, 0)
+    }
+}
+
+/// Signifies that a component can describe its effective configuration, so that results
+/// can be attributed to the exact configuration that produced them.
+///
+/// Already implemented for:
+/// - all packet mutators of butterfly, like the [`PacketHavocMutator`](crate::PacketHavocMutator) or the [`PacketDeleteMutator`](crate::PacketDeleteMutator)
+/// - [`PacketMutationScheduler`](crate::PacketMutationScheduler): if all of its mutators implement this
+/// - [`NetworkExecutor`](crate::NetworkExecutor)
+pub trait HasConfig {
+    /// Returns the current configuration of the component
+    fn config(&self) -> ComponentConfig;
+}
+
+/// A tuple of components that all implement [`HasConfig`], e.g. the mutators of a scheduler
+pub trait ConfigTuple {
+    /// Returns the configurations of all components in order
+    fn configs(&self) -> Vec<ComponentConfig>;
+}
+
+impl ConfigTuple for () {
+    fn configs(&self) -> Vec<ComponentConfig> {
+        Vec::new()
+    }
+}
+
+impl<Head, Tail> ConfigTuple for (Head, Tail)
+where
+    Head: HasConfig,
+    Tail: ConfigTuple,
+{
+    fn configs(&self) -> Vec<ComponentConfig> {
+        let mut configs = vec![self.0.config()];
+        configs.extend(self.1.configs());
+        configs
+    }
+}
+
+/// The configuration of a campaign, stored in the metadata of the state.
+///
+/// Since it is part of the state, it is saved with every checkpoint of [`Checkpoints`](crate::Checkpoints),
+/// which additionally write it to a human-readable `config.txt`.
+///
+/// # Example
+/// ```
+/// CampaignConfig::record(&mut state, &scheduler);
+/// CampaignConfig::record(&mut state, &executor);
+/// println!("{}", state.metadata().get::<CampaignConfig>().unwrap());
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CampaignConfig {
+    /// The configurations of all recorded components
+    pub components: Vec<ComponentConfig>,
+}
+
+impl_serdeany!(CampaignConfig);
+
+impl CampaignConfig {
+    /// Record the configuration of `component` in the state.
+    /// It replaces an earlier configuration of a component with the same name.
+    pub fn record<S, C>(state: &mut S, component: &C)
+    where
+        S: HasMetadata,
+        C: HasConfig,
+    {
+        if !state.has_metadata::<Self>() {
+            state.add_metadata(Self::default());
+        }
+
+        let config = component.config();
+        let components = &mut state.metadata_mut().get_mut::<Self>().unwrap().components;
+
+        match components.iter_mut().find(|component| component.name == config.name) {
+            Some(component) => *component = config,
+            None => components.push(config),
+        }
+    }
+}
+
+impl Display for CampaignConfig {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for component in &self.components {
+            write!(f, "{}", component)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{test_utils::TestInput, PacketDeleteMutator, PacketMutationScheduler, PacketRotateMutator};
+    use libafl::{
+        bolts::{rands::StdRand, tuples::tuple_list},
+        corpus::InMemoryCorpus,
+        state::StdState,
+    };
+
+    #[test]
+    fn test_campaign_config() {
+        let mut state = StdState::new(StdRand::with_seed(0), InMemoryCorpus::<TestInput>::new(), InMemoryCorpus::<TestInput>::new(), &mut (), &mut ()).unwrap();
+        let scheduler = PacketMutationScheduler::<TestInput, _, StdState<InMemoryCorpus<TestInput>, TestInput, StdRand, InMemoryCorpus<TestInput>>>::new(tuple_list!(PacketDeleteMutator::new(2), PacketRotateMutator::new())).with_weights(vec![1.0, 3.0]);
+
+        CampaignConfig::record(&mut state, &scheduler);
+        CampaignConfig::record(&mut state, &scheduler.with_adaptive_selection());
+
+        let config = state.metadata().get::<CampaignConfig>().unwrap();
+        assert_eq!(config.components.len(), 1);
+
+        let scheduler = &config.components[0];
+        assert_eq!(scheduler.name, "PacketMutationScheduler");
+        assert_eq!(scheduler.setting("adaptive"), Some("true"));
+        assert_eq!(scheduler.children.len(), 2);
+        assert_eq!(scheduler.children[0].setting("min_packets"), Some("2"));
+        assert!(config.to_string().contains("  PacketRotateMutator\n"));
+    }
+}
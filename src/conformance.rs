@@ -0,0 +1,189 @@
+use libafl::{
+    bolts::tuples::Named,
+    corpus::Testcase,
+    events::EventFirer,
+    executors::ExitKind,
+    feedbacks::Feedback,
+    impl_serdeany,
+    inputs::Input,
+    observers::ObserversTuple,
+    state::{HasClientPerfMonitor, HasMetadata},
+    Error,
+};
+use serde::{Deserialize, Serialize};
+use std::fmt::{Debug, Formatter};
+
+/// A response invariant: every packet for which `applies` returns true must get a response for which `allowed` returns true
+struct ConformanceRule<P> {
+    name: String,
+    applies: fn(&P) -> bool,
+    allowed: fn(&[u8]) -> bool,
+}
+
+/// Checks the responses of the target against invariants from the protocol specification.
+///
+/// Many spec violations don't crash the target, e.g. a server that answers `PASS` with a `200` instead of
+/// a `230` or `530`. The user declares such invariants as rules per packet type. The [`NetworkExecutor`](crate::NetworkExecutor)
+/// checks every response with [`with_conformance_checker()`](crate::NetworkExecutor::with_conformance_checker)
+/// and stores the violations of an execution in the [`ConformanceMetadata`] of the state,
+/// where the [`ConformanceFeedback`] turns them into objectives.
+///
+/// Responses that the target did not send in time are not checked.
+///
+/// # Example
+/// ```
+/// let checker = ConformanceChecker::new().with_rule(
+///     "PASS must be answered with 230 or 530",
+///     |packet: &FtpCommand| matches!(packet, FtpCommand::Pass(_)),
+///     |response| response.starts_with(b"230") || response.starts_with(b"530"),
+/// );
+/// let executor = NetworkExecutor::new(observers, addr, "state", ftp::status_code).with_conformance_checker(checker);
+/// let mut objective = feedback_or!(CrashFeedback::new(), ConformanceFeedback::new());
+/// ```
+pub struct ConformanceChecker<P> {
+    rules: Vec<ConformanceRule<P>>,
+}
+
+impl<P> ConformanceChecker<P> {
+    /// Create a new ConformanceChecker without any rules
+    pub fn new() -> Self {
+        Self {
+            rules: Vec::new(),
+        }
+    }
+
+    /// Add a rule with the name `name`: the response to every packet for which `applies` returns true
+    /// must be one for which `allowed` returns true
+    pub fn with_rule(mut self, name: &str, applies: fn(&P) -> bool, allowed: fn(&[u8]) -> bool) -> Self {
+        self.rules.push(ConformanceRule {
+            name: name.to_string(),
+            applies,
+            allowed,
+        });
+        self
+    }
+
+    /// Check the `response` of the target to `packet` against all rules and return the names of the violated ones
+    pub fn check<'a>(&'a self, packet: &'a P, response: &'a [u8]) -> impl Iterator<Item = &'a str> + 'a {
+        self.rules.iter().filter(move |rule| (rule.applies)(packet) && !(rule.allowed)(response)).map(|rule| rule.name.as_str())
+    }
+}
+
+impl<P> Debug for ConformanceChecker<P> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
+        f.debug_struct("ConformanceChecker").field("rules", &self.rules.iter().map(|rule| &rule.name).collect::<Vec<_>>()).finish()
+    }
+}
+
+/// A response that violated a rule of a [`ConformanceChecker`], together with the packet it answered
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConformanceViolation {
+    /// Name of the rule
+    pub rule: String,
+    /// Payload of the packet that was sent
+    pub request: Vec<u8>,
+    /// The response of the target
+    pub response: Vec<u8>,
+}
+
+/// The violations of the last execution, stored in the state by the [`NetworkExecutor`](crate::NetworkExecutor)
+/// and attached to solutions by the [`ConformanceFeedback`]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConformanceMetadata {
+    /// All violations in the order they happened
+    pub violations: Vec<ConformanceViolation>,
+}
+
+impl_serdeany!(ConformanceMetadata);
+
+/// An objective feedback that considers an input a solution if the target violated a rule of a
+/// [`ConformanceChecker`] while processing it.
+///
+/// The violations, including the offending exchanges, are attached to the solution as [`ConformanceMetadata`].
+#[derive(Debug, Default)]
+pub struct ConformanceFeedback {
+    metadata: Option<ConformanceMetadata>,
+}
+
+impl ConformanceFeedback {
+    /// Create a new ConformanceFeedback
+    pub fn new() -> Self {
+        Self {
+            metadata: None,
+        }
+    }
+}
+
+impl Named for ConformanceFeedback {
+    fn name(&self) -> &str {
+        "ConformanceFeedback"
+    }
+}
+
+impl<I, S> Feedback<I, S> for ConformanceFeedback
+where
+    I: Input,
+    S: HasClientPerfMonitor + HasMetadata,
+{
+    fn is_interesting<EM, OT>(&mut self, state: &mut S, _mgr: &mut EM, _input: &I, _observers: &OT, _exit_kind: &ExitKind) -> Result<bool, Error>
+    where
+        EM: EventFirer<I>,
+        OT: ObserversTuple<I, S>,
+    {
+        self.metadata = state.metadata().get::<ConformanceMetadata>().filter(|metadata| !metadata.violations.is_empty()).cloned();
+        Ok(self.metadata.is_some())
+    }
+
+    fn append_metadata(&mut self, _state: &mut S, testcase: &mut Testcase<I>) -> Result<(), Error> {
+        if let Some(metadata) = self.metadata.take() {
+            testcase.add_metadata(metadata);
+        }
+
+        Ok(())
+    }
+
+    fn discard_metadata(&mut self, _state: &mut S, _input: &I) -> Result<(), Error> {
+        self.metadata = None;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libafl::{
+        bolts::rands::StdRand,
+        corpus::InMemoryCorpus,
+        events::NopEventManager,
+        inputs::{BytesInput, HasBytesVec},
+        state::StdState,
+    };
+
+    #[test]
+    fn test_conformance() {
+        let checker = ConformanceChecker::new().with_rule("PASS must be answered with 230 or 530", |packet: &BytesInput| packet.bytes().starts_with(b"PASS"), |response| response.starts_with(b"230") || response.starts_with(b"530"));
+        let pass = BytesInput::new(b"PASS secret\r\n".to_vec());
+
+        assert_eq!(checker.check(&pass, b"230 Logged in\r\n").count(), 0);
+        assert_eq!(checker.check(&BytesInput::new(b"USER a\r\n".to_vec()), b"200 OK\r\n").count(), 0);
+        assert_eq!(checker.check(&pass, b"200 OK\r\n").collect::<Vec<_>>(), vec!["PASS must be answered with 230 or 530"]);
+
+        let mut state = StdState::new(StdRand::with_seed(0), InMemoryCorpus::<BytesInput>::new(), InMemoryCorpus::<BytesInput>::new(), &mut (), &mut ()).unwrap();
+        let mut feedback = ConformanceFeedback::new();
+        assert!(!feedback.is_interesting(&mut state, &mut NopEventManager {}, &pass, &(), &ExitKind::Ok).unwrap());
+
+        let violation = ConformanceViolation {
+            rule: "PASS must be answered with 230 or 530".to_string(),
+            request: pass.bytes().to_vec(),
+            response: b"200 OK\r\n".to_vec(),
+        };
+        state.add_metadata(ConformanceMetadata {
+            violations: vec![violation.clone()],
+        });
+        assert!(feedback.is_interesting(&mut state, &mut NopEventManager {}, &pass, &(), &ExitKind::Ok).unwrap());
+
+        let mut testcase = Testcase::<BytesInput>::new(pass);
+        feedback.append_metadata(&mut state, &mut testcase).unwrap();
+        assert_eq!(testcase.metadata().get::<ConformanceMetadata>().unwrap().violations, vec![violation]);
+    }
+}
@@ -0,0 +1,334 @@
+use crate::{checkpoint::Checkpoints, observer::StateObserver, output::client_file, scheduler::MutatorWeights};
+use libafl::{
+    executors::HasObservers,
+    impl_serdeany,
+    inputs::Input,
+    observers::ObserversTuple,
+    stages::Stage,
+    state::{HasCorpus, HasExecutions, HasMetadata, HasSolutions},
+    Error,
+};
+use serde::{Deserialize, Serialize};
+use std::cmp::Eq;
+use std::fmt::Debug;
+use std::fs::File;
+use std::hash::Hash;
+use std::io::{ErrorKind, Read, Seek, SeekFrom};
+use std::marker::PhantomData;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+/// A command that an operator sent to a [`ControlStage`]
+#[derive(Clone, Debug, PartialEq)]
+enum Command {
+    Pause,
+    Resume,
+    ExportGraph(PathBuf),
+    Weights(Vec<f64>),
+    Checkpoint(String),
+}
+
+impl Command {
+    fn parse(line: &str) -> Result<Self, String> {
+        let mut words = line.split_whitespace();
+        let command = words.next().unwrap_or_default();
+        let args: Vec<&str> = words.collect();
+
+        match (command, args.as_slice()) {
+            ("pause", []) => Ok(Command::Pause),
+            ("resume", []) => Ok(Command::Resume),
+            ("export", [path]) => Ok(Command::ExportGraph(PathBuf::from(path))),
+            ("checkpoint", [name]) => Ok(Command::Checkpoint(name.to_string())),
+            ("weights", weights) if !weights.is_empty() => weights.iter().map(|weight| weight.parse::<f64>().map_err(|_| format!("Invalid weight '{}'", weight))).collect::<Result<_, _>>().map(Command::Weights),
+            _ => Err(format!("Invalid command '{}'", line)),
+        }
+    }
+}
+
+/// Reads the commands that were appended to a control file since the last read
+#[derive(Clone, Debug)]
+struct CommandReader {
+    path: PathBuf,
+    offset: u64,
+}
+
+impl CommandReader {
+    fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            offset: 0,
+        }
+    }
+
+    /// Returns all complete lines that are new. A missing file contains no commands.
+    fn read(&mut self) -> Result<Vec<String>, Error> {
+        let mut file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+
+        // The file was truncated or replaced, start from the beginning
+        if file.metadata()?.len() < self.offset {
+            self.offset = 0;
+        }
+
+        let mut content = Vec::new();
+        file.seek(SeekFrom::Start(self.offset))?;
+        file.read_to_end(&mut content)?;
+
+        // Leave incomplete lines for the next read
+        let complete = match content.iter().rposition(|byte| *byte == b'\n') {
+            Some(idx) => idx + 1,
+            None => return Ok(Vec::new()),
+        };
+        self.offset += complete as u64;
+
+        Ok(String::from_utf8_lossy(&content[..complete]).lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')).map(String::from).collect())
+    }
+}
+
+/// How far a [`ControlStage`] has read its control file, stored in the metadata of the state
+/// so that a restarted client doesn't execute the same commands again
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ControlOffset {
+    path: PathBuf,
+    offset: u64,
+}
+
+impl_serdeany!(ControlOffset);
+
+/// A stage that lets an operator control a running campaign through a plain text file,
+/// without restarting any clients.
+///
+/// Commands are appended to the control file one per line, e.g. with `echo pause >> control`:
+///
+/// | Command | Effect |
+/// |---------|--------|
+/// | `pause` | Stop fuzzing until `resume` is received |
+/// | `resume` | Continue fuzzing after a `pause` |
+/// | `export <path>` | Write the state-graph in DOT format to `<path>` |
+/// | `weights <w1> <w2> ...` | Set the [`MutatorWeights`](crate::MutatorWeights) of the [`PacketMutationScheduler`](crate::PacketMutationScheduler) |
+/// | `checkpoint <name>` | Save a checkpoint, requires [`with_checkpoints()`](ControlStage::with_checkpoints) |
+///
+/// Every client that watches the same file receives every command because the stage
+/// remembers how far it has read the file instead of consuming it.
+/// The position is kept in the metadata of the state, so a client that gets restarted
+/// with its state continues after the commands it has already executed.
+/// Commands that fail are reported on stdout and don't stop the campaign.
+/// The file is only checked when the stage runs, so commands take effect
+/// before the next corpus entry gets fuzzed.
+///
+/// # Example
+/// ```
+/// let mut stages = tuple_list!(
+///     ControlStage::<_, _, u32>::new("/tmp/butterfly-control", "state").with_checkpoints(Checkpoints::new("checkpoints")),
+///     StdMutationalStage::new(mutator),
+/// );
+/// ```
+#[derive(Clone, Debug)]
+pub struct ControlStage<I, OT, PS> {
+    reader: CommandReader,
+    observer_name: String,
+    checkpoints: Option<Checkpoints>,
+    client_id: Option<usize>,
+    poll_interval: Duration,
+    last_poll: Option<Instant>,
+    paused: bool,
+    phantom: PhantomData<(I, OT, PS)>,
+}
+
+impl<I, OT, PS> ControlStage<I, OT, PS>
+where
+    I: Input,
+    PS: Clone + Debug + Eq + Hash + Serialize + for<'a> Deserialize<'a>,
+{
+    /// Create a new ControlStage that reads commands from the file at `path` and
+    /// exports the state-graph of the [`StateObserver`] with the name `observer_name`.
+    /// The file is checked once per second.
+    pub fn new<P: Into<PathBuf>>(path: P, observer_name: &str) -> Self {
+        Self {
+            reader: CommandReader::new(path.into()),
+            observer_name: observer_name.to_string(),
+            checkpoints: None,
+            client_id: None,
+            poll_interval: Duration::from_secs(1),
+            last_poll: None,
+            paused: false,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Save checkpoints requested with the `checkpoint` command into `checkpoints`
+    pub fn with_checkpoints(mut self, checkpoints: Checkpoints) -> Self {
+        self.checkpoints = Some(checkpoints);
+        self
+    }
+
+    /// Tag the files of the `export` command with `client_id`, e.g. `graph-client-3.dot` for `export graph.dot`,
+    /// since every client of a multi-core campaign executes every command, see [`client_file()`](crate::client_file).
+    /// Checkpoints are namespaced with [`Checkpoints::with_client_id()`].
+    pub fn with_client_id(mut self, client_id: usize) -> Self {
+        self.client_id = Some(client_id);
+        self
+    }
+
+    /// Check the control file every `poll_interval` instead of once per second
+    pub fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// Returns whether the campaign is currently paused
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Read the new commands, continuing where the last stage on the same state stopped
+    fn read_commands<S: HasMetadata>(&mut self, state: &mut S) -> Result<Vec<String>, Error> {
+        if let Some(saved) = state.metadata().get::<ControlOffset>() {
+            if saved.path == self.reader.path {
+                self.reader.offset = saved.offset;
+            }
+        }
+
+        let lines = self.reader.read()?;
+
+        // Save the offset before executing anything so that checkpoints contain it
+        state.add_metadata(ControlOffset {
+            path: self.reader.path.clone(),
+            offset: self.reader.offset,
+        });
+
+        Ok(lines)
+    }
+
+    fn execute<S>(&mut self, command: Command, state: &mut S, observer: &StateObserver<PS>) -> Result<(), Error>
+    where
+        S: HasCorpus<I> + HasSolutions<I> + HasExecutions + HasMetadata + Serialize,
+    {
+        match command {
+            Command::Pause => {
+                self.paused = true;
+                println!("[butterfly] Paused, waiting for resume");
+            },
+            Command::Resume => {
+                self.paused = false;
+                println!("[butterfly] Resumed");
+            },
+            Command::ExportGraph(path) => {
+                let path = match self.client_id {
+                    Some(client_id) => client_file(&path, client_id),
+                    None => path,
+                };
+                std::fs::write(&path, observer.get_statemachine())?;
+                println!("[butterfly] Exported state-graph to {}", path.display());
+            },
+            Command::Weights(weights) => {
+                state.add_metadata(MutatorWeights::new(weights));
+                println!("[butterfly] Updated mutator weights");
+            },
+            Command::Checkpoint(name) => match &self.checkpoints {
+                Some(checkpoints) => {
+                    checkpoints.save::<I, S, PS>(&name, state, observer)?;
+                },
+                None => return Err(Error::illegal_state("No checkpoint directory configured")),
+            },
+        }
+
+        Ok(())
+    }
+}
+
+impl<E, EM, I, OT, PS, S, Z> Stage<E, EM, S, Z> for ControlStage<I, OT, PS>
+where
+    E: HasObservers<I, OT, S>,
+    OT: ObserversTuple<I, S>,
+    I: Input,
+    PS: Clone + Debug + Eq + Hash + Serialize + for<'a> Deserialize<'a>,
+    S: HasCorpus<I> + HasSolutions<I> + HasExecutions + HasMetadata + Serialize,
+{
+    fn perform(&mut self, _fuzzer: &mut Z, executor: &mut E, state: &mut S, _manager: &mut EM, _corpus_idx: usize) -> Result<(), Error> {
+        loop {
+            if self.last_poll.map(|last_poll| last_poll.elapsed() >= self.poll_interval).unwrap_or(true) {
+                self.last_poll = Some(Instant::now());
+
+                for line in self.read_commands(state)? {
+                    let observer = executor.observers().match_name::<StateObserver<PS>>(&self.observer_name).ok_or_else(|| Error::key_not_found(format!("No StateObserver named \"{}\" with the given state type", self.observer_name)))?;
+                    let result = Command::parse(&line).map_err(Error::illegal_argument).and_then(|command| self.execute(command, state, observer));
+
+                    if let Err(e) = result {
+                        println!("[butterfly] Control command '{}' failed: {}", line, e);
+                    }
+                }
+            }
+
+            if !self.paused {
+                return Ok(());
+            }
+
+            std::thread::sleep(self.poll_interval);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libafl::{
+        bolts::{rands::StdRand, tuples::tuple_list},
+        corpus::InMemoryCorpus,
+        executors::WithObservers,
+        inputs::BytesInput,
+        state::StdState,
+    };
+    use std::io::Write;
+
+    #[test]
+    fn test_command_reader() {
+        let path = std::env::temp_dir().join(format!("butterfly-control-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mut reader = CommandReader::new(path.clone());
+        assert!(reader.read().unwrap().is_empty());
+
+        std::fs::write(&path, "pause\n# comment\n\nweights 1 0.5\nexport").unwrap();
+        let lines = reader.read().unwrap();
+        assert_eq!(lines, vec!["pause", "weights 1 0.5"]);
+        assert_eq!(Command::parse(&lines[0]), Ok(Command::Pause));
+        assert_eq!(Command::parse(&lines[1]), Ok(Command::Weights(vec![1.0, 0.5])));
+
+        std::fs::OpenOptions::new().append(true).open(&path).unwrap().write_all(b" graph.dot\n").unwrap();
+        assert_eq!(reader.read().unwrap(), vec!["export graph.dot"]);
+        assert!(Command::parse("export").is_err());
+        assert!(Command::parse("weights 1 x").is_err());
+
+        std::fs::write(&path, "resume\n").unwrap();
+        assert_eq!(reader.read().unwrap(), vec!["resume"]);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_restart() {
+        let path = std::env::temp_dir().join(format!("butterfly-control-restart-{}", std::process::id()));
+        std::fs::write(&path, "weights 1 2\n").unwrap();
+        let mut state = StdState::new(StdRand::with_seed(0), InMemoryCorpus::<BytesInput>::new(), InMemoryCorpus::<BytesInput>::new(), &mut (), &mut ()).unwrap();
+        let mut executor = WithObservers::new((), tuple_list!(StateObserver::<u32>::new("state")));
+
+        let mut stage = ControlStage::<BytesInput, _, u32>::new(&path, "state").with_poll_interval(Duration::ZERO);
+        stage.perform(&mut (), &mut executor, &mut state, &mut (), 0).unwrap();
+        assert_eq!(state.metadata().get::<MutatorWeights>().unwrap().weights, [1.0, 2.0]);
+
+        // A restarted client gets a new stage but keeps its state
+        state.add_metadata(MutatorWeights::new(vec![3.0]));
+        let mut stage = ControlStage::<BytesInput, _, u32>::new(&path, "state").with_poll_interval(Duration::ZERO);
+        stage.perform(&mut (), &mut executor, &mut state, &mut (), 0).unwrap();
+        assert_eq!(state.metadata().get::<MutatorWeights>().unwrap().weights, [3.0]);
+
+        std::fs::OpenOptions::new().append(true).open(&path).unwrap().write_all(b"weights 4\n").unwrap();
+        stage.perform(&mut (), &mut executor, &mut state, &mut (), 0).unwrap();
+        assert_eq!(state.metadata().get::<MutatorWeights>().unwrap().weights, [4.0]);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
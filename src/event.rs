@@ -1,3 +1,5 @@
+use std::sync::RwLock;
+
 /// Key for user stats.
 ///
 /// [`StateFeedback`](crate::StateFeedback) writes the number of vertices in
@@ -12,6 +14,40 @@ pub static USER_STAT_NODES: &str = "statemachine_nodes";
 /// of the monitor with this key.
 pub static USER_STAT_EDGES: &str = "statemachine_edges";
 
+/// Key for user stats.
+///
+/// [`StateFeedback`](crate::StateFeedback) writes `1` into the user stats of the monitor
+/// with this key when the state-graph grows too fast and it only considers transitions
+/// between known states interesting, and `0` when it goes back to normal.
+/// See [`StateFeedback::with_growth_limit()`](crate::StateFeedback::with_growth_limit).
+pub static USER_STAT_GROWTH_THROTTLED: &str = "statemachine_growth_throttled";
+
+/// Key for user stats.
+///
+/// [`StateFeedback`](crate::StateFeedback) writes how often a new state did not fit into the node budget
+/// of the [`StateObserver`](crate::StateObserver) into the user stats of the monitor with this key, once the budget was reached.
+/// See [`StateObserver::with_node_budget()`](crate::StateObserver::with_node_budget).
+pub static USER_STAT_BUDGET_OVERFLOWS: &str = "statemachine_budget_overflows";
+
+/// Key for user stats.
+///
+/// [`ValidityFeedback`](crate::ValidityFeedback) writes the ratio of accepted packets
+/// to all judged packets into the user stats of the monitor with this key.
+pub static USER_STAT_PACKET_ACCEPTANCE: &str = "packet_acceptance";
+
+/// Prefix of the keys of user stats.
+///
+/// [`MutatorStatsFeedback`](crate::MutatorStatsFeedback) writes the ratio of interesting
+/// executions to all executions of the outputs of each mutator into the user stats of the monitor
+/// with the key `mutator_<name of the mutator>`.
+pub static USER_STAT_MUTATOR_PREFIX: &str = "mutator_";
+
+/// Key for user stats.
+///
+/// [`PhaseStage`](crate::PhaseStage) writes the name of the current [`Phase`](crate::Phase)
+/// into the user stats of the monitor with this key.
+pub static USER_STAT_PHASE: &str = "phase";
+
 /// Key for user stats.
 ///
 /// [`StateFeedback`](crate::StateFeedback) writes a DOT representation
@@ -19,3 +55,65 @@ pub static USER_STAT_EDGES: &str = "statemachine_edges";
 /// Only available with feature `graphviz`.
 #[cfg(feature = "graphviz")]
 pub static USER_STAT_STATEGRAPH: &str = "stategraph";
+
+/// How the [`StateMonitor`](crate::StateMonitor) combines and displays the values
+/// of a registered user stat from all clients. See [`register_user_stat()`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UserStatFormat {
+    /// Sum of all values
+    Sum,
+    /// Average of all values
+    Average,
+    /// Largest value
+    Max,
+    /// Average of all values as a percentage, meant for ratios
+    Percent,
+    /// The value of the first client that reported it, as text
+    Text,
+    /// Sum of the numerators and the denominators of all ratios, as `a/b (x%)`
+    RatioSum,
+}
+
+/// A user stat that has been registered with [`register_user_stat()`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RegisteredUserStat {
+    /// The key of the user stat
+    pub key: String,
+    /// The name that is displayed
+    pub label: String,
+    /// How the value is displayed
+    pub format: UserStatFormat,
+}
+
+static USER_STAT_REGISTRY: RwLock<Vec<RegisteredUserStat>> = RwLock::new(Vec::new());
+
+/// Declare a custom user stat that monitors should display.
+///
+/// Components that fire their own `UpdateUserStats` events can register their keys here
+/// and the [`StateMonitor`](crate::StateMonitor) renders them automatically, as `label: value`.
+/// Other monitors can do the same with [`HasStateStats::format_registered_stats()`](crate::HasStateStats::format_registered_stats).
+/// Registering a key again replaces its label and format.
+/// Register keys in the process that runs the monitor, before fuzzing starts.
+///
+/// # Example
+/// ```
+/// register_user_stat("rejected_packets", "rejected", UserStatFormat::Percent);
+/// ```
+pub fn register_user_stat(key: &str, label: &str, format: UserStatFormat) {
+    let mut registry = USER_STAT_REGISTRY.write().unwrap();
+    let stat = RegisteredUserStat {
+        key: key.to_string(),
+        label: label.to_string(),
+        format,
+    };
+
+    match registry.iter_mut().find(|registered| registered.key == key) {
+        Some(registered) => *registered = stat,
+        None => registry.push(stat),
+    }
+}
+
+/// Returns all registered user stats in the order they were registered
+pub fn registered_user_stats() -> Vec<RegisteredUserStat> {
+    USER_STAT_REGISTRY.read().unwrap().clone()
+}
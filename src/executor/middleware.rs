@@ -0,0 +1,634 @@
+use crate::{
+    executor::throttle::Throttle,
+    output::{client_dir, create_unique_file},
+};
+use libafl::{
+    bolts::rands::{Rand, StdRand},
+    executors::ExitKind,
+};
+use std::fmt::Debug;
+use std::io::Write;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// What the executor should do after a middleware has seen a packet or a response
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Verdict {
+    /// Continue as usual
+    Continue,
+    /// Don't send this packet or, for responses, don't record its state
+    Drop,
+    /// Abort the execution and report it as [`ExitKind::Ok`](libafl::executors::ExitKind::Ok)
+    /// without the states it recorded so far
+    Abort,
+}
+
+/// A composable layer around the transport loop of an executor.
+///
+/// Middlewares get notified about everything the executor does and can
+/// modify or drop outgoing packets, ignore responses or abort an execution.
+/// This way capabilities like logging, pacing or fault injection can be mixed freely
+/// instead of needing a separate executor type for every combination.
+/// All methods have a default implementation that does nothing.
+///
+/// The [`NetworkExecutor`](crate::NetworkExecutor) accepts middlewares via
+/// [`with_middleware()`](crate::NetworkExecutor::with_middleware). Use a [`MiddlewareChain`]
+/// to do the same in your own executors.
+///
+/// Already implemented for:
+/// - [`Throttle`](crate::Throttle) for pacing
+/// - [`PacketLogger`] for logging
+/// - [`FaultInjector`] for fault injection
+/// - [`TokenSubstitution`] for replacing placeholders with session tokens from responses
+/// - [`PcapRecorder`] for pcap recording
+pub trait ExecutorMiddleware: Debug {
+    /// Called before an execution starts
+    fn pre_exec(&mut self) {}
+
+    /// Called before a connection to `addr` gets established
+    fn pre_connect(&mut self, _addr: &SocketAddr) {}
+
+    /// Called after a connection to `addr` has been established
+    fn post_connect(&mut self, _addr: &SocketAddr) {}
+
+    /// Called after the current connection has been closed
+    fn post_disconnect(&mut self) {}
+
+    /// Called with the payload of every packet before it is sent. The payload may be modified.
+    fn on_send(&mut self, _payload: &mut Vec<u8>) -> Verdict {
+        Verdict::Continue
+    }
+
+    /// Called with every response the executor receives
+    fn on_receive(&mut self, _response: &[u8]) -> Verdict {
+        Verdict::Continue
+    }
+
+    /// Called instead of [`on_send()`](ExecutorMiddleware::on_send) for packets that get replayed on a new connection
+    /// before a packet is retried. Only layers that must keep up with the connection, like session tokens, need this.
+    fn on_replay_send(&mut self, _payload: &mut Vec<u8>) {}
+
+    /// Called instead of [`on_receive()`](ExecutorMiddleware::on_receive) for the responses to replayed packets
+    fn on_replay_receive(&mut self, _response: &[u8]) {}
+
+    /// Called after an execution has finished
+    fn post_exec(&mut self, _exit_kind: &ExitKind) {}
+}
+
+/// An ordered list of [`ExecutorMiddleware`]s that acts like a single middleware.
+///
+/// Every notification is passed to the layers in the order they were added.
+/// For outgoing packets and responses the first layer that does not return
+/// [`Verdict::Continue`] decides, the layers after it are skipped.
+#[derive(Debug, Default)]
+pub struct MiddlewareChain {
+    layers: Vec<Box<dyn ExecutorMiddleware>>,
+}
+
+impl MiddlewareChain {
+    /// Create a new, empty MiddlewareChain
+    pub fn new() -> Self {
+        Self {
+            layers: Vec::new(),
+        }
+    }
+
+    /// Append a layer to the chain
+    pub fn with<M: ExecutorMiddleware + 'static>(mut self, layer: M) -> Self {
+        self.push(layer);
+        self
+    }
+
+    /// Append a layer to the chain
+    pub fn push<M: ExecutorMiddleware + 'static>(&mut self, layer: M) {
+        self.layers.push(Box::new(layer));
+    }
+
+    /// Returns whether the chain has no layers
+    pub fn is_empty(&self) -> bool {
+        self.layers.is_empty()
+    }
+}
+
+impl ExecutorMiddleware for MiddlewareChain {
+    fn pre_exec(&mut self) {
+        self.layers.iter_mut().for_each(|layer| layer.pre_exec());
+    }
+
+    fn pre_connect(&mut self, addr: &SocketAddr) {
+        self.layers.iter_mut().for_each(|layer| layer.pre_connect(addr));
+    }
+
+    fn post_connect(&mut self, addr: &SocketAddr) {
+        self.layers.iter_mut().for_each(|layer| layer.post_connect(addr));
+    }
+
+    fn post_disconnect(&mut self) {
+        self.layers.iter_mut().for_each(|layer| layer.post_disconnect());
+    }
+
+    fn on_send(&mut self, payload: &mut Vec<u8>) -> Verdict {
+        self.layers.iter_mut().map(|layer| layer.on_send(payload)).find(|verdict| *verdict != Verdict::Continue).unwrap_or(Verdict::Continue)
+    }
+
+    fn on_receive(&mut self, response: &[u8]) -> Verdict {
+        self.layers.iter_mut().map(|layer| layer.on_receive(response)).find(|verdict| *verdict != Verdict::Continue).unwrap_or(Verdict::Continue)
+    }
+
+    fn on_replay_send(&mut self, payload: &mut Vec<u8>) {
+        self.layers.iter_mut().for_each(|layer| layer.on_replay_send(payload));
+    }
+
+    fn on_replay_receive(&mut self, response: &[u8]) {
+        self.layers.iter_mut().for_each(|layer| layer.on_replay_receive(response));
+    }
+
+    fn post_exec(&mut self, exit_kind: &ExitKind) {
+        self.layers.iter_mut().for_each(|layer| layer.post_exec(exit_kind));
+    }
+}
+
+impl ExecutorMiddleware for Throttle {
+    fn pre_exec(&mut self) {
+        self.wait_execution();
+    }
+
+    fn pre_connect(&mut self, _addr: &SocketAddr) {
+        self.wait_connection();
+    }
+
+    fn on_receive(&mut self, response: &[u8]) -> Verdict {
+        if self.check_response(response) {
+            Verdict::Abort
+        } else {
+            Verdict::Continue
+        }
+    }
+}
+
+/// Render bytes for logs: printable ASCII stays as is, everything else gets escaped
+fn escape(data: &[u8], max_len: usize) -> String {
+    let mut ret: String = data.iter().take(max_len).flat_map(|byte| std::ascii::escape_default(*byte)).map(char::from).collect();
+
+    if data.len() > max_len {
+        ret.push_str("...");
+    }
+
+    ret
+}
+
+/// A middleware that prints every packet, response and connection event.
+///
+/// # Example
+/// ```
+/// let executor = NetworkExecutor::new(observers, addr, "state", infer_state)
+///     .with_middleware(PacketLogger::new().with_max_len(64));
+/// ```
+#[derive(Clone, Debug)]
+pub struct PacketLogger {
+    max_len: usize,
+}
+
+impl PacketLogger {
+    /// Create a new PacketLogger that prints at most 256 bytes of every packet
+    pub fn new() -> Self {
+        Self {
+            max_len: 256,
+        }
+    }
+
+    /// Print at most `max_len` bytes of every packet and response
+    pub fn with_max_len(mut self, max_len: usize) -> Self {
+        self.max_len = max_len;
+        self
+    }
+}
+
+impl ExecutorMiddleware for PacketLogger {
+    fn post_connect(&mut self, addr: &SocketAddr) {
+        println!("[butterfly] Connected to {}", addr);
+    }
+
+    fn post_disconnect(&mut self) {
+        println!("[butterfly] Disconnected");
+    }
+
+    fn on_send(&mut self, payload: &mut Vec<u8>) -> Verdict {
+        println!("[butterfly] > {}", escape(payload, self.max_len));
+        Verdict::Continue
+    }
+
+    fn on_receive(&mut self, response: &[u8]) -> Verdict {
+        println!("[butterfly] < {}", escape(response, self.max_len));
+        Verdict::Continue
+    }
+
+    fn post_exec(&mut self, exit_kind: &ExitKind) {
+        println!("[butterfly] Execution finished: {:?}", exit_kind);
+    }
+}
+
+/// A middleware that randomly drops, truncates or corrupts outgoing packets
+/// to simulate an unreliable network.
+///
+/// All rates are probabilities between 0.0 and 1.0 and default to 0.
+///
+/// # Example
+/// ```
+/// let faults = FaultInjector::new(1234).with_drop_rate(0.01).with_corrupt_rate(0.01);
+/// ```
+#[derive(Debug)]
+pub struct FaultInjector {
+    rand: StdRand,
+    drop_rate: f64,
+    truncate_rate: f64,
+    corrupt_rate: f64,
+}
+
+impl FaultInjector {
+    /// Create a new FaultInjector with a seed for its random decisions
+    pub fn new(seed: u64) -> Self {
+        Self {
+            rand: StdRand::with_seed(seed),
+            drop_rate: 0.0,
+            truncate_rate: 0.0,
+            corrupt_rate: 0.0,
+        }
+    }
+
+    /// Don't send a packet with probability `rate`
+    pub fn with_drop_rate(mut self, rate: f64) -> Self {
+        self.drop_rate = rate;
+        self
+    }
+
+    /// Send only a random prefix of a packet with probability `rate`
+    pub fn with_truncate_rate(mut self, rate: f64) -> Self {
+        self.truncate_rate = rate;
+        self
+    }
+
+    /// Flip a random bit of a packet with probability `rate`
+    pub fn with_corrupt_rate(mut self, rate: f64) -> Self {
+        self.corrupt_rate = rate;
+        self
+    }
+
+    fn chance(&mut self, rate: f64) -> bool {
+        rate > 0.0 && (self.rand.next() as f64 / u64::MAX as f64) < rate
+    }
+}
+
+impl ExecutorMiddleware for FaultInjector {
+    fn on_send(&mut self, payload: &mut Vec<u8>) -> Verdict {
+        if self.chance(self.drop_rate) {
+            return Verdict::Drop;
+        }
+
+        if !payload.is_empty() && self.chance(self.truncate_rate) {
+            let len = self.rand.below(payload.len() as u64) as usize;
+            payload.truncate(len);
+        }
+
+        if !payload.is_empty() && self.chance(self.corrupt_rate) {
+            let bit = self.rand.below(payload.len() as u64 * 8) as usize;
+            payload[bit / 8] ^= 1 << (bit % 8);
+        }
+
+        Verdict::Continue
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Token {
+    placeholder: Vec<u8>,
+    prefix: Vec<u8>,
+    terminators: Vec<u8>,
+    value: Option<Vec<u8>>,
+}
+
+/// A middleware that captures session tokens from responses and
+/// substitutes them into later packets of the same execution.
+///
+/// A token starts after a prefix in a response and ends before the first terminator byte.
+/// Every occurrence of its placeholder in outgoing packets gets replaced
+/// with the last value that was captured. Placeholders of tokens that have not been seen yet
+/// are sent as they are. Captured values are forgotten when a new execution starts.
+///
+/// # Example
+/// ```
+/// // "Set-Cookie: session=abc123; Path=/" makes "Cookie: session={{session}}" become "Cookie: session=abc123"
+/// let tokens = TokenSubstitution::new().with_token(b"{{session}}", b"session=", b";\r\n");
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct TokenSubstitution {
+    tokens: Vec<Token>,
+}
+
+impl TokenSubstitution {
+    /// Create a new TokenSubstitution without any tokens
+    pub fn new() -> Self {
+        Self {
+            tokens: Vec::new(),
+        }
+    }
+
+    /// Add a token
+    ///
+    /// # Arguments
+    /// - `placeholder`: what gets replaced in outgoing packets
+    /// - `prefix`: the bytes in a response that precede the token
+    /// - `terminators`: the token ends before the first of these bytes or at the end of the response
+    pub fn with_token(mut self, placeholder: &[u8], prefix: &[u8], terminators: &[u8]) -> Self {
+        self.tokens.push(Token {
+            placeholder: placeholder.to_vec(),
+            prefix: prefix.to_vec(),
+            terminators: terminators.to_vec(),
+            value: None,
+        });
+        self
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    if needle.is_empty() {
+        return None;
+    }
+
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
+
+impl ExecutorMiddleware for TokenSubstitution {
+    fn pre_exec(&mut self) {
+        self.tokens.iter_mut().for_each(|token| token.value = None);
+    }
+
+    fn on_send(&mut self, payload: &mut Vec<u8>) -> Verdict {
+        for token in &self.tokens {
+            let value = match &token.value {
+                Some(value) => value,
+                None => continue,
+            };
+            let mut start = 0;
+
+            while let Some(pos) = find(&payload[start..], &token.placeholder) {
+                let pos = start + pos;
+                payload.splice(pos..pos + token.placeholder.len(), value.iter().copied());
+                start = pos + value.len();
+            }
+        }
+
+        Verdict::Continue
+    }
+
+    fn on_receive(&mut self, response: &[u8]) -> Verdict {
+        for token in &mut self.tokens {
+            if let Some(pos) = find(response, &token.prefix) {
+                let rest = &response[pos + token.prefix.len()..];
+                let len = rest.iter().position(|byte| token.terminators.contains(byte)).unwrap_or(rest.len());
+                token.value = Some(rest[..len].to_vec());
+            }
+        }
+
+        Verdict::Continue
+    }
+
+    fn on_replay_send(&mut self, payload: &mut Vec<u8>) {
+        self.on_send(payload);
+    }
+
+    fn on_replay_receive(&mut self, response: &[u8]) {
+        self.on_receive(response);
+    }
+}
+
+/// The address of the client in recorded pcaps
+const PCAP_CLIENT_ADDR: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 1);
+/// The address of the target in recorded pcaps if it isn't an IPv4 address
+const PCAP_TARGET_ADDR: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 2);
+const PCAP_FIRST_PORT: u16 = 40000;
+/// pcap magic, version 2.4, snaplen 65535 and LINKTYPE_RAW
+const PCAP_HEADER: [u8; 24] = [0xd4, 0xc3, 0xb2, 0xa1, 2, 0, 4, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xff, 0xff, 0, 0, 101, 0, 0, 0];
+
+const TCP_FIN: u8 = 0x01;
+const TCP_SYN: u8 = 0x02;
+const TCP_PSH_ACK: u8 = 0x18;
+
+/// The TCP connection that is currently being recorded
+#[derive(Clone, Debug)]
+struct RecordedConnection {
+    target: (Ipv4Addr, u16),
+    client_port: u16,
+    client_seq: u32,
+    target_seq: u32,
+}
+
+/// A middleware that records executions into pcap files that can be loaded
+/// as seeds with [`load_pcaps()`](crate::load_pcaps) again.
+///
+/// Every connection becomes a TCP stream with synthetic IPv4 and TCP headers.
+/// By default only executions that did not end with [`ExitKind::Ok`](libafl::executors::ExitKind::Ok)
+/// are written, as `<dir>/<exit kind>-<n>.pcap`. Existing recordings are never overwritten.
+///
+/// # Example
+/// ```
+/// let recorder = PcapRecorder::new("recordings");
+/// ```
+#[derive(Clone, Debug)]
+pub struct PcapRecorder {
+    dir: PathBuf,
+    all_executions: bool,
+    counter: usize,
+    records: Vec<u8>,
+    connection: Option<RecordedConnection>,
+    next_port: u16,
+}
+
+impl PcapRecorder {
+    /// Create a new PcapRecorder that writes its pcaps into `dir`
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Self {
+        Self {
+            dir: dir.into(),
+            all_executions: false,
+            counter: 0,
+            records: Vec::new(),
+            connection: None,
+            next_port: PCAP_FIRST_PORT,
+        }
+    }
+
+    /// Write every execution, not only those that did not end with [`ExitKind::Ok`](libafl::executors::ExitKind::Ok)
+    pub fn with_all_executions(mut self) -> Self {
+        self.all_executions = true;
+        self
+    }
+
+    /// Write into `<dir>/client-<client_id>` so that the clients of a multi-core campaign don't overwrite
+    /// each other's pcaps, see [`client_dir()`](crate::client_dir)
+    pub fn with_client_id(mut self, client_id: usize) -> Self {
+        self.dir = client_dir(&self.dir, client_id);
+        self
+    }
+
+    /// Append a TCP segment to the current recording
+    fn record(&mut self, from_client: bool, flags: u8, payload: &[u8]) {
+        let conn = match &mut self.connection {
+            Some(conn) => conn,
+            None => return,
+        };
+        let payload = &payload[..payload.len().min(u16::MAX as usize - 40)];
+
+        let ((src, src_port), (dst, dst_port)) = if from_client { ((PCAP_CLIENT_ADDR, conn.client_port), conn.target) } else { (conn.target, (PCAP_CLIENT_ADDR, conn.client_port)) };
+        let (seq, ack) = if from_client { (conn.client_seq, conn.target_seq) } else { (conn.target_seq, conn.client_seq) };
+        let advance = payload.len() as u32 + (flags & (TCP_SYN | TCP_FIN) != 0) as u32;
+
+        if from_client {
+            conn.client_seq = conn.client_seq.wrapping_add(advance);
+        } else {
+            conn.target_seq = conn.target_seq.wrapping_add(advance);
+        }
+
+        let mut frame = Vec::with_capacity(40 + payload.len());
+        frame.extend_from_slice(&[0x45, 0]);
+        frame.extend_from_slice(&(40 + payload.len() as u16).to_be_bytes());
+        frame.extend_from_slice(&[0, 0, 0x40, 0, 64, 6, 0, 0]);
+        frame.extend_from_slice(&src.octets());
+        frame.extend_from_slice(&dst.octets());
+        frame.extend_from_slice(&src_port.to_be_bytes());
+        frame.extend_from_slice(&dst_port.to_be_bytes());
+        frame.extend_from_slice(&seq.to_be_bytes());
+        frame.extend_from_slice(&ack.to_be_bytes());
+        frame.extend_from_slice(&[0x50, flags, 0xff, 0xff, 0, 0, 0, 0]);
+        frame.extend_from_slice(payload);
+
+        let time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        self.records.extend_from_slice(&(time.as_secs() as u32).to_le_bytes());
+        self.records.extend_from_slice(&time.subsec_micros().to_le_bytes());
+        self.records.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+        self.records.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+        self.records.extend_from_slice(&frame);
+    }
+
+    fn write(&mut self, exit_kind: &ExitKind) -> std::io::Result<PathBuf> {
+        let prefix = format!("{:?}", exit_kind).to_lowercase();
+        let (path, mut file) = create_unique_file(&self.dir, &prefix, "pcap", &mut self.counter)?;
+        file.write_all(&PCAP_HEADER)?;
+        file.write_all(&self.records)?;
+        Ok(path)
+    }
+}
+
+impl ExecutorMiddleware for PcapRecorder {
+    fn pre_exec(&mut self) {
+        self.records.clear();
+        self.connection = None;
+    }
+
+    fn post_connect(&mut self, addr: &SocketAddr) {
+        let target = match addr.ip() {
+            IpAddr::V4(ip) => ip,
+            IpAddr::V6(_) => PCAP_TARGET_ADDR,
+        };
+
+        self.connection = Some(RecordedConnection {
+            target: (target, addr.port()),
+            client_port: self.next_port,
+            client_seq: 0,
+            target_seq: 0,
+        });
+        self.next_port = self.next_port.checked_add(1).unwrap_or(PCAP_FIRST_PORT);
+        self.record(true, TCP_SYN, &[]);
+    }
+
+    fn post_disconnect(&mut self) {
+        self.record(true, TCP_FIN, &[]);
+        self.connection = None;
+    }
+
+    fn on_send(&mut self, payload: &mut Vec<u8>) -> Verdict {
+        self.record(true, TCP_PSH_ACK, payload);
+        Verdict::Continue
+    }
+
+    fn on_receive(&mut self, response: &[u8]) -> Verdict {
+        self.record(false, TCP_PSH_ACK, response);
+        Verdict::Continue
+    }
+
+    fn post_exec(&mut self, exit_kind: &ExitKind) {
+        if self.records.is_empty() || (*exit_kind == ExitKind::Ok && !self.all_executions) {
+            return;
+        }
+
+        if let Err(err) = self.write(exit_kind) {
+            println!("[butterfly] Could not write pcap into {}: {}", self.dir.display(), err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::{dissect_frame, first_tcp_connection};
+    use pcap::Linktype;
+
+    #[test]
+    fn test_chain() {
+        let mut chain = MiddlewareChain::new().with(TokenSubstitution::new().with_token(b"$ID", b"id=", b"\r\n")).with(FaultInjector::new(0).with_drop_rate(1.0));
+
+        assert_eq!(chain.on_receive(b"200 id=42\r\n"), Verdict::Continue);
+
+        let mut payload = b"GET $ID $ID".to_vec();
+        assert_eq!(chain.on_send(&mut payload), Verdict::Drop);
+        assert_eq!(payload, b"GET 42 42");
+
+        chain.pre_exec();
+        let mut payload = b"GET $ID".to_vec();
+        chain.on_send(&mut payload);
+        assert_eq!(payload, b"GET $ID");
+    }
+
+    #[test]
+    fn test_pcap_recorder() {
+        let dir = std::env::temp_dir().join(format!("butterfly-pcap-recorder-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let mut recorder = PcapRecorder::new(&dir);
+
+        recorder.pre_exec();
+        recorder.post_connect(&"127.0.0.1:2121".parse().unwrap());
+        recorder.on_receive(b"220 ready\r\n");
+        recorder.on_send(&mut b"USER a\r\n".to_vec());
+        recorder.on_send(&mut b"PASS b\r\n".to_vec());
+        recorder.post_disconnect();
+        recorder.post_exec(&ExitKind::Ok);
+        assert!(!dir.exists());
+
+        recorder.post_exec(&ExitKind::Crash);
+        let pcap = std::fs::read(dir.join("crash-0.pcap")).unwrap();
+        assert_eq!(pcap[..24], PCAP_HEADER);
+
+        let mut segments = Vec::new();
+        let mut rest = &pcap[24..];
+        while rest.len() >= 16 {
+            let len = u32::from_le_bytes(rest[8..12].try_into().unwrap()) as usize;
+            segments.push(dissect_frame(Linktype::RAW, &rest[16..16 + len]).unwrap());
+            rest = &rest[16 + len..];
+        }
+
+        assert_eq!(segments.len(), 5);
+        assert!(segments[0].syn && segments[4].fin);
+        assert_eq!(segments[1].src_port, 2121);
+        assert_eq!(first_tcp_connection(&segments), vec![b"USER a\r\n".to_vec(), b"PASS b\r\n".to_vec()]);
+
+        // A restarted recorder doesn't overwrite the recordings of its previous run
+        let mut recorder = PcapRecorder::new(&dir);
+        recorder.pre_exec();
+        recorder.post_connect(&"127.0.0.1:2121".parse().unwrap());
+        recorder.post_exec(&ExitKind::Crash);
+        assert_eq!(std::fs::read(dir.join("crash-0.pcap")).unwrap(), pcap);
+        assert!(dir.join("crash-1.pcap").exists());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
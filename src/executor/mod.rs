@@ -0,0 +1,13 @@
+mod middleware;
+mod network;
+mod packet;
+mod throttle;
+mod tunnel;
+
+pub use middleware::{ExecutorMiddleware, FaultInjector, MiddlewareChain, PacketLogger, PcapRecorder, TokenSubstitution, Verdict};
+pub use network::{NetworkExecutor, TargetSelection, TransportEvent};
+#[cfg(any(feature = "protocol_ftp", feature = "protocol_smtp", feature = "protocol_http1"))]
+pub(crate) use packet::status_code_validity;
+pub use packet::{ConnectionEvent, HasConnectionEvents, HasPayload, HasValidityOracle, NetworkPacket, Validity, ValidityMetadata};
+pub use throttle::Throttle;
+pub use tunnel::TransportProxy;
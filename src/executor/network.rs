@@ -0,0 +1,911 @@
+use crate::{
+    config::{ComponentConfig, HasConfig},
+    conformance::{ConformanceChecker, ConformanceMetadata, ConformanceViolation},
+    executor::{
+        middleware::{ExecutorMiddleware, MiddlewareChain, Verdict},
+        packet::{ConnectionEvent, HasConnectionEvents, HasPayload, HasValidityOracle, Validity, ValidityMetadata},
+        throttle::Throttle,
+        tunnel::TransportProxy,
+    },
+    input::HasPackets,
+    observer::StateObserver,
+};
+use libafl::{
+    executors::{Executor, ExitKind, HasObservers},
+    inputs::Input,
+    observers::ObserversTuple,
+    state::HasMetadata,
+    Error,
+};
+use serde::{Deserialize, Serialize};
+use std::cmp::Eq;
+use std::fmt::{Debug, Formatter};
+use std::hash::Hash;
+use std::io::{ErrorKind, Read, Write};
+use std::marker::PhantomData;
+use std::net::{SocketAddr, TcpStream};
+use std::time::Duration;
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_millis(500);
+const RESPONSE_BUFFER_SIZE: usize = 4096;
+
+/// Outcome of waiting for a response
+enum Reception {
+    /// A response was received or the target did not respond in time
+    Ok,
+    /// The target closed or reset the connection
+    Closed,
+    /// A packet could not be sent
+    Failed,
+    /// A middleware aborted the execution, e.g. the throttle says that the target is overloaded
+    Aborted,
+}
+
+/// A transport-level event that the [`NetworkExecutor`] can record as a state,
+/// see [`NetworkExecutor::with_transport_states()`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TransportEvent {
+    /// A connection to the target could not be established
+    ConnectRefused,
+    /// The target closed the connection instead of sending a response
+    Closed,
+    /// The target reset or aborted the connection
+    ResetByPeer,
+    /// The target did not respond within the timeout
+    Timeout,
+}
+
+/// How the [`NetworkExecutor`] picks one of multiple target addresses for an execution,
+/// see [`NetworkExecutor::with_targets()`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TargetSelection {
+    /// Cycle through the addresses, one execution per address
+    RoundRobin,
+    /// Always use the same address, e.g. the client id modulo the number of addresses,
+    /// so that the clients of a campaign are spread over all addresses
+    Sticky(usize),
+}
+
+/// An executor that sends packets to a target over TCP and records
+/// the states inferred from the responses in a [`StateObserver`].
+///
+/// For every regular packet the executor sends its [`payload`](crate::HasPayload::payload)
+/// and reads a single response. The response is passed to the state inference function
+/// and if it returns a state, the state gets recorded.
+///
+/// The connection is established implicitly before the first packet is sent.
+/// Inputs can explicitly manage the connection via pseudo-packets (see [`HasConnectionEvents`](crate::HasConnectionEvents)):
+/// - `Connect` closes the current connection, if any, and opens a new one
+/// - `Disconnect` closes the current connection. If the next packet is a regular
+///   packet, a new connection is established implicitly before it is sent.
+///
+/// The execution is reported as a crash when
+/// - the target cannot be connected to
+/// - a packet cannot be sent
+/// - the target closes or resets the connection instead of sending a response
+///
+/// Sending can fail because of transient network glitches, too. With
+/// [`with_retries()`](NetworkExecutor::with_retries) a failed send is retried: the executor reconnects,
+/// replays all packets that were sent over the connection before and then sends the packet again.
+/// The states of replayed packets are not recorded a second time, they don't count towards
+/// [hang detection](NetworkExecutor::with_hang_detection) and they only reach the
+/// [replay hooks](crate::ExecutorMiddleware::on_replay_send) of the middlewares.
+/// Only when all retries failed the execution is reported as a crash.
+///
+/// If no response arrives within the timeout, nothing gets recorded and
+/// the execution continues with the next packet. With [`with_transport_states()`](NetworkExecutor::with_transport_states)
+/// timeouts and other [`TransportEvent`]s are recorded as synthetic states. With [`with_hang_detection()`](NetworkExecutor::with_hang_detection)
+/// the execution is reported as [`ExitKind::Timeout`](libafl::executors::ExitKind::Timeout) instead when the target
+/// stops responding altogether.
+///
+/// With [`with_validity_oracle()`](NetworkExecutor::with_validity_oracle) the executor also judges
+/// whether the target accepted or rejected each packet and keeps count in the [`ValidityMetadata`](crate::ValidityMetadata) of the state.
+/// With [`with_conformance_checker()`](NetworkExecutor::with_conformance_checker) it checks the responses
+/// against the rules of a [`ConformanceChecker`](crate::ConformanceChecker).
+///
+/// Additional capabilities like logging, pacing or fault injection are added as
+/// [`ExecutorMiddleware`](crate::ExecutorMiddleware) layers around the transport loop.
+/// For example, the rate of executions and connections can be limited with a [`Throttle`](crate::Throttle).
+///
+/// Targets that are only reachable via a jump host can be connected to through a
+/// [`TransportProxy`](crate::TransportProxy) with [`with_proxy()`](NetworkExecutor::with_proxy).
+/// If a middleware aborts an execution, e.g. because the throttle detected an overloaded target,
+/// it is reported as [`ExitKind::Ok`](libafl::executors::ExitKind::Ok) and the states it recorded so far
+/// are discarded, so that the partial path cannot make the input interesting.
+///
+/// # Example
+/// ```
+/// // Use the FTP status code as the state
+/// let mut executor = NetworkExecutor::new(
+///     tuple_list!(StateObserver::<u32>::new("state")),
+///     "127.0.0.1:2121".parse().unwrap(),
+///     "state",
+///     |response: &[u8]| std::str::from_utf8(response.get(0..3)?).ok()?.parse::<u32>().ok(),
+/// )
+/// .with_greeting();
+/// ```
+pub struct NetworkExecutor<I, P, OT, S, PS, F>
+where
+    OT: ObserversTuple<I, S>,
+    I: Input,
+{
+    observers: OT,
+    observer_name: String,
+    addr: SocketAddr,
+    targets: Vec<SocketAddr>,
+    selection: TargetSelection,
+    target_idx: usize,
+    next_target: usize,
+    failover: bool,
+    proxy: Option<TransportProxy>,
+    timeout: Duration,
+    greeting: bool,
+    retries: usize,
+    replaying: bool,
+    hang_after: Option<usize>,
+    silent: usize,
+    middleware: MiddlewareChain,
+    input_labels: Option<fn(&P) -> String>,
+    validity_oracle: Option<fn(&P, &[u8]) -> Validity>,
+    validity: ValidityMetadata,
+    transport_states: Option<fn(TransportEvent) -> Option<PS>>,
+    conformance: Option<ConformanceChecker<P>>,
+    violations: Vec<ConformanceViolation>,
+    infer_state: F,
+    buf: Vec<u8>,
+    phantom: PhantomData<(I, P, S, PS)>,
+}
+
+impl<I, P, OT, S, PS, F> NetworkExecutor<I, P, OT, S, PS, F>
+where
+    OT: ObserversTuple<I, S>,
+    I: Input + HasPackets<P>,
+    P: HasConnectionEvents + HasPayload,
+    PS: Clone + Debug + Eq + Hash + Serialize + for<'a> Deserialize<'a>,
+    F: FnMut(&[u8]) -> Option<PS>,
+{
+    /// Create a new NetworkExecutor
+    ///
+    /// # Arguments
+    /// - `observers`: the observers of the executor, must contain a [`StateObserver`]
+    /// - `addr`: address of the target
+    /// - `observer_name`: name of the [`StateObserver`] that receives the states
+    /// - `infer_state`: derives a state from a response, returns `None` if no state should be recorded
+    pub fn new(observers: OT, addr: SocketAddr, observer_name: &str, infer_state: F) -> Self {
+        Self {
+            observers,
+            observer_name: observer_name.to_string(),
+            addr,
+            targets: vec![addr],
+            selection: TargetSelection::RoundRobin,
+            target_idx: 0,
+            next_target: 0,
+            failover: false,
+            proxy: None,
+            timeout: DEFAULT_TIMEOUT,
+            greeting: false,
+            retries: 0,
+            replaying: false,
+            hang_after: None,
+            silent: 0,
+            middleware: MiddlewareChain::new(),
+            input_labels: None,
+            validity_oracle: None,
+            validity: ValidityMetadata::default(),
+            transport_states: None,
+            conformance: None,
+            violations: Vec::new(),
+            infer_state,
+            buf: vec![0; RESPONSE_BUFFER_SIZE],
+            phantom: PhantomData,
+        }
+    }
+
+    /// Set the timeout for connecting and for receiving responses. Default: 500ms
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Spread the executions over multiple instances of the target, e.g. replicas behind
+    /// a load balancer or several ports of the same host, to increase the throughput.
+    ///
+    /// The address of an execution is picked according to `selection` and all connections of that execution
+    /// go to the same address. If the first connection of an execution fails, the other addresses are tried
+    /// in order before the execution is reported as a crash. The address given to [`new()`](NetworkExecutor::new)
+    /// is replaced by `targets`, unless `targets` is empty.
+    pub fn with_targets(mut self, targets: Vec<SocketAddr>, selection: TargetSelection) -> Self {
+        if !targets.is_empty() {
+            self.addr = targets[0];
+            self.targets = targets;
+        }
+
+        self.selection = selection;
+        self
+    }
+
+    /// Connect to the target through a SOCKS5 or HTTP CONNECT proxy
+    pub fn with_proxy(mut self, proxy: TransportProxy) -> Self {
+        self.proxy = Some(proxy);
+        self
+    }
+
+    /// Expect the target to send a response immediately after a connection
+    /// has been established, like the banner of an FTP or SMTP server.
+    pub fn with_greeting(mut self) -> Self {
+        self.greeting = true;
+        self
+    }
+
+    /// Retry a packet up to `retries` times if it cannot be sent. Default: 0
+    pub fn with_retries(mut self, retries: usize) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// Report an execution as [`ExitKind::Timeout`](libafl::executors::ExitKind::Timeout) if the target
+    /// did not respond to `silent_packets` packets in a row.
+    pub fn with_hang_detection(mut self, silent_packets: usize) -> Self {
+        self.hang_after = Some(silent_packets.max(1));
+        self
+    }
+
+    /// Label every packet with `label` before it is sent, see [`StateObserver::record_input()`].
+    /// The labels become the inputs of the [Mealy machine](StateObserver::get_mealy_machine).
+    pub fn with_input_labels(mut self, label: fn(&P) -> String) -> Self {
+        self.input_labels = Some(label);
+        self
+    }
+
+    /// Judge every response with the [`HasValidityOracle`](crate::HasValidityOracle) of the packet
+    /// that was sent and count accepted and rejected packets in the [`ValidityMetadata`](crate::ValidityMetadata) of the state.
+    pub fn with_validity_oracle(mut self) -> Self
+    where
+        P: HasValidityOracle,
+    {
+        self.validity_oracle = Some(|packet, response| packet.validity(response));
+        self
+    }
+
+    /// Record a synthetic state whenever a [`TransportEvent`] happens, so that the state-graph also shows
+    /// how inputs affect the connection, e.g. which packet makes the target drop it.
+    /// `state` maps an event to the state that gets recorded or to `None` if the event should not be recorded.
+    ///
+    /// Events during the replay of a [retry](NetworkExecutor::with_retries) are not recorded.
+    /// Make sure that the synthetic states cannot collide with the states that `infer_state` returns.
+    ///
+    /// # Example
+    /// ```
+    /// let executor = NetworkExecutor::new(observers, addr, "state", ftp::status_code).with_transport_states(|event| match event {
+    ///     TransportEvent::Timeout => None,
+    ///     event => Some(1000 + event as u32),
+    /// });
+    /// ```
+    pub fn with_transport_states(mut self, state: fn(TransportEvent) -> Option<PS>) -> Self {
+        self.transport_states = Some(state);
+        self
+    }
+
+    /// Check every response with `checker` and store the violations of an execution
+    /// in the [`ConformanceMetadata`](crate::ConformanceMetadata) of the state
+    pub fn with_conformance_checker(mut self, checker: ConformanceChecker<P>) -> Self {
+        self.conformance = Some(checker);
+        self
+    }
+
+    /// The observer that receives the states. Its presence is checked before every execution.
+    fn state_observer(&mut self) -> Option<&mut StateObserver<PS>> {
+        self.observers.match_name_mut(&self.observer_name)
+    }
+
+    /// Discard the states of an aborted execution
+    fn abort(&mut self, connection: &mut Option<TcpStream>) -> ExitKind {
+        self.disconnect(connection);
+
+        if let Some(observer) = self.state_observer() {
+            observer.discard_execution();
+        }
+
+        ExitKind::Ok
+    }
+
+    /// Record the synthetic state of `event`, if configured
+    fn record_event(&mut self, event: TransportEvent) {
+        if self.replaying {
+            return;
+        }
+
+        if let (Some(state), Some(observer)) = (self.transport_states.and_then(|state| state(event)), self.state_observer()) {
+            observer.record(&state);
+        }
+    }
+
+    /// Limit the rate of executions and connections.
+    /// This is a shorthand for adding the throttle as a middleware.
+    pub fn with_throttle(self, throttle: Throttle) -> Self {
+        self.with_middleware(throttle)
+    }
+
+    /// Add a middleware layer. Layers get notified in the order they were added.
+    pub fn with_middleware<M: ExecutorMiddleware + 'static>(mut self, middleware: M) -> Self {
+        self.middleware.push(middleware);
+        self
+    }
+
+    /// Receive a single response to `packet`, or the greeting if there is no packet, and record its state.
+    fn receive(&mut self, stream: &mut TcpStream, packet: Option<&P>) -> Reception {
+        match stream.read(&mut self.buf) {
+            Ok(0) => {
+                self.record_event(TransportEvent::Closed);
+                Reception::Closed
+            },
+            Ok(len) if self.replaying => {
+                self.middleware.on_replay_receive(&self.buf[..len]);
+                Reception::Ok
+            },
+            Ok(len) => {
+                self.silent = 0;
+
+                match self.middleware.on_receive(&self.buf[..len]) {
+                    Verdict::Continue => {
+                        if let (Some(state), Some(observer)) = ((self.infer_state)(&self.buf[..len]), self.state_observer()) {
+                            observer.record(&state);
+                        }
+
+                        if let (Some(oracle), Some(packet)) = (self.validity_oracle, packet) {
+                            self.validity.count(oracle(packet, &self.buf[..len]));
+                        }
+
+                        if let (Some(checker), Some(packet)) = (&self.conformance, packet) {
+                            for rule in checker.check(packet, &self.buf[..len]) {
+                                self.violations.push(ConformanceViolation {
+                                    rule: rule.to_string(),
+                                    request: packet.payload(),
+                                    response: self.buf[..len].to_vec(),
+                                });
+                            }
+                        }
+
+                        Reception::Ok
+                    },
+                    Verdict::Drop => Reception::Ok,
+                    Verdict::Abort => Reception::Aborted,
+                }
+            },
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                if !self.replaying {
+                    self.silent += 1;
+                }
+
+                self.record_event(TransportEvent::Timeout);
+                Reception::Ok
+            },
+            Err(_) => {
+                self.record_event(TransportEvent::ResetByPeer);
+                Reception::Closed
+            },
+        }
+    }
+
+    /// Pick the address of the target for the next execution
+    fn select_target(&mut self) {
+        self.target_idx = match self.selection {
+            TargetSelection::RoundRobin => {
+                let idx = self.next_target % self.targets.len();
+                self.next_target = idx + 1;
+                idx
+            },
+            TargetSelection::Sticky(idx) => idx % self.targets.len(),
+        };
+        self.addr = self.targets[self.target_idx];
+        self.failover = self.targets.len() > 1;
+    }
+
+    /// Open a TCP connection to `addr`
+    fn open(&mut self, addr: SocketAddr) -> Option<TcpStream> {
+        self.middleware.pre_connect(&addr);
+
+        let stream = match &self.proxy {
+            Some(proxy) => proxy.connect(&addr, self.timeout),
+            None => TcpStream::connect_timeout(&addr, self.timeout),
+        };
+        let stream = stream.ok()?;
+        stream.set_read_timeout(Some(self.timeout)).ok()?;
+        stream.set_nodelay(true).ok()?;
+        self.middleware.post_connect(&addr);
+        Some(stream)
+    }
+
+    /// Open a new connection and receive the greeting, if configured.
+    fn connect(&mut self) -> Result<TcpStream, Reception> {
+        let mut stream = self.open(self.addr);
+
+        // Fall back to the other addresses if the first connection of an execution fails
+        if self.failover {
+            for offset in 1..self.targets.len() {
+                if stream.is_some() {
+                    break;
+                }
+
+                let idx = (self.target_idx + offset) % self.targets.len();
+                stream = self.open(self.targets[idx]);
+
+                if stream.is_some() {
+                    self.target_idx = idx;
+                    self.addr = self.targets[idx];
+                }
+            }
+
+            self.failover = false;
+        }
+
+        let mut stream = match stream {
+            Some(stream) => stream,
+            None => {
+                self.record_event(TransportEvent::ConnectRefused);
+                return Err(Reception::Closed);
+            },
+        };
+
+        if self.greeting {
+            match self.receive(&mut stream, None) {
+                Reception::Ok => {},
+                reception => return Err(reception),
+            }
+        }
+
+        Ok(stream)
+    }
+
+    /// Close the connection, if any
+    fn disconnect(&mut self, connection: &mut Option<TcpStream>) {
+        if connection.take().is_some() {
+            self.middleware.post_disconnect();
+        }
+    }
+
+    /// Send a single packet and receive the response
+    fn send(&mut self, stream: &mut TcpStream, packet: &P) -> Reception {
+        let mut payload = packet.payload();
+
+        if let (Some(label), Some(observer)) = (self.input_labels, self.state_observer()) {
+            observer.record_input(&label(packet));
+        }
+
+        match self.middleware.on_send(&mut payload) {
+            Verdict::Continue if stream.write_all(&payload).is_err() => Reception::Failed,
+            Verdict::Continue => self.receive(stream, Some(packet)),
+            Verdict::Drop => Reception::Ok,
+            Verdict::Abort => Reception::Aborted,
+        }
+    }
+
+    /// Open a new connection and send all regular packets in `prefix` again without recording their states
+    /// and without passing them through the middlewares
+    fn replay(&mut self, prefix: &[P]) -> Result<TcpStream, Reception> {
+        self.replaying = true;
+        let ret = self.connect().and_then(|mut stream| {
+            for packet in prefix.iter().filter(|packet| packet.connection_event().is_none()) {
+                let mut payload = packet.payload();
+                self.middleware.on_replay_send(&mut payload);
+
+                if stream.write_all(&payload).is_err() {
+                    return Err(Reception::Failed);
+                }
+
+                match self.receive(&mut stream, Some(packet)) {
+                    Reception::Ok => {},
+                    reception => return Err(reception),
+                }
+            }
+
+            Ok(stream)
+        });
+        self.replaying = false;
+        ret
+    }
+
+    /// Send a packet and retry it on a new connection if sending fails
+    fn send_with_retries(&mut self, connection: &mut Option<TcpStream>, prefix: &[P], packet: &P) -> Reception {
+        let mut reception = match connection.as_mut() {
+            Some(stream) => self.send(stream, packet),
+            None => return Reception::Ok,
+        };
+
+        for _ in 0..self.retries {
+            if !matches!(reception, Reception::Failed) {
+                break;
+            }
+
+            self.disconnect(connection);
+
+            reception = match self.replay(prefix) {
+                Ok(mut stream) => {
+                    let reception = self.send(&mut stream, packet);
+                    *connection = Some(stream);
+                    reception
+                },
+                Err(Reception::Aborted) => Reception::Aborted,
+                Err(_) => Reception::Failed,
+            };
+        }
+
+        reception
+    }
+
+    /// The transport loop: send all packets of an input and receive the responses
+    fn send_packets(&mut self, input: &I) -> ExitKind {
+        let packets = input.packets();
+        self.silent = 0;
+        self.select_target();
+        let mut connection: Option<TcpStream> = None;
+        // Whether a connection was opened since the start or the last disconnect
+        let mut connected = false;
+        // Index of the first packet sent over the current connection
+        let mut connection_start = 0;
+
+        for (idx, packet) in packets.iter().enumerate() {
+            let reception = match packet.connection_event() {
+                Some(ConnectionEvent::Connect) => {
+                    // Close the old connection before opening a new one
+                    self.disconnect(&mut connection);
+                    connected = true;
+                    connection_start = idx + 1;
+
+                    match self.connect() {
+                        Ok(stream) => {
+                            connection = Some(stream);
+                            Reception::Ok
+                        },
+                        Err(reception) => reception,
+                    }
+                },
+                Some(ConnectionEvent::Disconnect) => {
+                    self.disconnect(&mut connection);
+                    connected = false;
+                    Reception::Ok
+                },
+                None => {
+                    if !connected {
+                        connected = true;
+                        connection_start = idx;
+
+                        match self.connect() {
+                            Ok(stream) => connection = Some(stream),
+                            Err(Reception::Aborted) => return self.abort(&mut connection),
+                            Err(_) => return ExitKind::Crash,
+                        }
+                    }
+
+                    self.send_with_retries(&mut connection, &packets[connection_start..idx], packet)
+                },
+            };
+
+            match reception {
+                Reception::Ok if self.hang_after.is_some_and(|limit| self.silent >= limit) => {
+                    self.disconnect(&mut connection);
+                    return ExitKind::Timeout;
+                },
+                Reception::Ok => {},
+                Reception::Closed | Reception::Failed => {
+                    self.disconnect(&mut connection);
+                    return ExitKind::Crash;
+                },
+                Reception::Aborted => return self.abort(&mut connection),
+            }
+        }
+
+        self.disconnect(&mut connection);
+        ExitKind::Ok
+    }
+}
+
+impl<I, P, OT, S, PS, F> Debug for NetworkExecutor<I, P, OT, S, PS, F>
+where
+    OT: ObserversTuple<I, S>,
+    I: Input,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
+        f.debug_struct("NetworkExecutor").field("addr", &self.addr).field("timeout", &self.timeout).field("greeting", &self.greeting).finish()
+    }
+}
+
+impl<I, P, OT, S, PS, F> HasConfig for NetworkExecutor<I, P, OT, S, PS, F>
+where
+    OT: ObserversTuple<I, S>,
+    I: Input,
+{
+    fn config(&self) -> ComponentConfig {
+        let proxy = match &self.proxy {
+            Some(TransportProxy::Socks5 {
+                addr,
+                ..
+            }) => format!("socks5://{}", addr),
+            Some(TransportProxy::HttpConnect {
+                addr,
+                ..
+            }) => format!("http://{}", addr),
+            None => "none".to_string(),
+        };
+
+        ComponentConfig::new("NetworkExecutor")
+            .with_setting("targets", self.targets.iter().map(|target| target.to_string()).collect::<Vec<_>>().join(", "))
+            .with_setting("target_selection", format!("{:?}", self.selection))
+            .with_setting("proxy", proxy)
+            .with_setting("timeout", format!("{:?}", self.timeout))
+            .with_setting("greeting", self.greeting)
+            .with_setting("retries", self.retries)
+            .with_setting("hang_after", self.hang_after.map_or_else(|| "never".to_string(), |packets| packets.to_string()))
+            .with_setting("input_labels", self.input_labels.is_some())
+            .with_setting("validity_oracle", self.validity_oracle.is_some())
+            .with_setting("transport_states", self.transport_states.is_some())
+            .with_setting("conformance", self.conformance.as_ref().map_or_else(|| "none".to_string(), |checker| format!("{:?}", checker)))
+            .with_setting("middleware", !self.middleware.is_empty())
+    }
+}
+
+impl<I, P, OT, S, PS, F> HasObservers<I, OT, S> for NetworkExecutor<I, P, OT, S, PS, F>
+where
+    OT: ObserversTuple<I, S>,
+    I: Input,
+{
+    fn observers(&self) -> &OT {
+        &self.observers
+    }
+
+    fn observers_mut(&mut self) -> &mut OT {
+        &mut self.observers
+    }
+}
+
+impl<EM, I, P, OT, S, PS, F, Z> Executor<EM, I, S, Z> for NetworkExecutor<I, P, OT, S, PS, F>
+where
+    OT: ObserversTuple<I, S>,
+    I: Input + HasPackets<P>,
+    P: HasConnectionEvents + HasPayload,
+    S: HasMetadata,
+    PS: Clone + Debug + Eq + Hash + Serialize + for<'a> Deserialize<'a>,
+    F: FnMut(&[u8]) -> Option<PS>,
+{
+    fn run_target(&mut self, _fuzzer: &mut Z, state: &mut S, _mgr: &mut EM, input: &I) -> Result<ExitKind, Error> {
+        if self.state_observer().is_none() {
+            return Err(Error::key_not_found(format!("No StateObserver named \"{}\" with the given state type", self.observer_name)));
+        }
+
+        if self.validity_oracle.is_some() {
+            self.validity = state.metadata().get::<ValidityMetadata>().cloned().unwrap_or_default();
+            self.validity.reset();
+        }
+
+        self.middleware.pre_exec();
+        let exit_kind = self.send_packets(input);
+        self.middleware.post_exec(&exit_kind);
+
+        if self.validity_oracle.is_some() {
+            state.add_metadata(self.validity.clone());
+        }
+
+        if self.conformance.is_some() {
+            state.add_metadata(ConformanceMetadata {
+                violations: std::mem::take(&mut self.violations),
+            });
+        }
+
+        Ok(exit_kind)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{executor::middleware::TokenSubstitution, executor::packet::NetworkPacket, test_utils::TestInput};
+    use libafl::{
+        bolts::{
+            rands::StdRand,
+            tuples::{tuple_list, MatchName},
+        },
+        corpus::InMemoryCorpus,
+        inputs::BytesInput,
+        state::StdState,
+    };
+    use std::cell::RefCell;
+    use std::io::{BufRead, BufReader};
+    use std::net::TcpListener;
+    use std::rc::Rc;
+    use std::thread::JoinHandle;
+
+    /// Logs which hooks of the middleware chain were called
+    #[derive(Debug, Default)]
+    struct Spy {
+        log: Rc<RefCell<Vec<&'static str>>>,
+    }
+
+    impl ExecutorMiddleware for Spy {
+        fn post_connect(&mut self, _addr: &SocketAddr) {
+            self.log.borrow_mut().push("connect");
+        }
+
+        fn on_send(&mut self, _payload: &mut Vec<u8>) -> Verdict {
+            self.log.borrow_mut().push("send");
+            Verdict::Continue
+        }
+
+        fn on_receive(&mut self, _response: &[u8]) -> Verdict {
+            self.log.borrow_mut().push("receive");
+            Verdict::Continue
+        }
+    }
+
+    /// Aborts the execution at the `n`-th response
+    #[derive(Debug)]
+    struct AbortAt {
+        n: usize,
+    }
+
+    impl ExecutorMiddleware for AbortAt {
+        fn on_receive(&mut self, _response: &[u8]) -> Verdict {
+            self.n -= 1;
+
+            if self.n == 0 {
+                Verdict::Abort
+            } else {
+                Verdict::Continue
+            }
+        }
+    }
+
+    fn data(line: &str) -> NetworkPacket<BytesInput> {
+        NetworkPacket::Data(BytesInput::new(line.as_bytes().to_vec()))
+    }
+
+    /// Spawn a line-based target that answers "login" with a session id, ignores "quiet", closes the connection
+    /// on "close" and echoes everything else. Returns the lines it received over `connections` connections.
+    fn fake_target(connections: usize) -> (SocketAddr, JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let thread = std::thread::spawn(move || {
+            let mut received = Vec::new();
+
+            for _ in 0..connections {
+                let (stream, _) = listener.accept().unwrap();
+                let mut writer = stream.try_clone().unwrap();
+
+                for line in BufReader::new(stream).lines() {
+                    let line = line.unwrap();
+
+                    match line.as_str() {
+                        "login" => writer.write_all(b"id=7\n").unwrap(),
+                        "quiet" => {},
+                        "close" => {
+                            received.push(line);
+                            break;
+                        },
+                        _ => writer.write_all(format!("OK {}\n", line).as_bytes()).unwrap(),
+                    }
+
+                    received.push(line);
+                }
+            }
+
+            received
+        });
+
+        (addr, thread)
+    }
+
+    #[test]
+    fn test_replay() {
+        let (addr, target) = fake_target(1);
+        let spy = Spy::default();
+        let log = spy.log.clone();
+        let mut executor = NetworkExecutor::<TestInput<NetworkPacket<BytesInput>>, _, _, (), u32, _>::new(tuple_list!(StateObserver::<u32>::new("state")), addr, "state", |response: &[u8]| Some(response.len() as u32))
+            .with_timeout(Duration::from_millis(100))
+            .with_middleware(TokenSubstitution::new().with_token(b"$ID", b"id=", b"\n"))
+            .with_middleware(spy);
+        executor.silent = 1;
+
+        let prefix = [data("login\n"), NetworkPacket::Disconnect, data("quiet\n"), data("use $ID\n")];
+        let stream = executor.replay(&prefix).ok().unwrap();
+        drop(stream);
+
+        // Only the regular packets reach the target, the session token still gets substituted
+        assert_eq!(target.join().unwrap(), ["login", "quiet", "use 7"]);
+        // Neither the middlewares nor the hang detection nor the observer see the replayed packets
+        assert_eq!(*log.borrow(), ["connect"]);
+        assert_eq!(executor.silent, 1);
+        let observer: &StateObserver<u32> = executor.observers().match_name("state").unwrap();
+        assert_eq!(observer.info(), StateObserver::<u32>::new("state").info());
+    }
+
+    fn response_len(response: &[u8]) -> Option<u32> {
+        Some(response.len() as u32)
+    }
+
+    #[test]
+    fn test_connection_events() {
+        let (addr, target) = fake_target(3);
+        let mut executor = NetworkExecutor::<TestInput<NetworkPacket<BytesInput>>, _, _, (), u32, _>::new(tuple_list!(StateObserver::<u32>::new("state")), addr, "state", response_len)
+            .with_timeout(Duration::from_millis(100))
+            .with_transport_states(|event| Some(1000 + event as u32));
+        let input = TestInput {
+            packets: vec![data("hello\n"), NetworkPacket::Disconnect, data("again\n"), NetworkPacket::Connect, data("quiet\n"), data("bye\n")],
+        };
+
+        assert_eq!(executor.send_packets(&input), ExitKind::Ok);
+        // The packet after the disconnect is sent over an implicit new connection
+        assert_eq!(target.join().unwrap(), ["hello", "again", "quiet", "bye"]);
+        let observer: &StateObserver<u32> = executor.observers().match_name("state").unwrap();
+        assert_eq!(observer.path_states(), [9, 9, 1000 + TransportEvent::Timeout as u32, 7]);
+    }
+
+    #[test]
+    fn test_disconnect_without_connect() {
+        let (addr, target) = fake_target(2);
+        let mut executor = NetworkExecutor::<TestInput<NetworkPacket<BytesInput>>, _, _, (), u32, _>::new(tuple_list!(StateObserver::<u32>::new("state")), addr, "state", response_len).with_timeout(Duration::from_millis(100));
+        let input = TestInput {
+            packets: vec![data("hello\n"), NetworkPacket::Disconnect, NetworkPacket::Disconnect, data("bye\n")],
+        };
+
+        assert_eq!(executor.send_packets(&input), ExitKind::Ok);
+        assert_eq!(target.join().unwrap(), ["hello", "bye"]);
+        let observer: &StateObserver<u32> = executor.observers().match_name("state").unwrap();
+        assert_eq!(observer.path_states(), [9, 7]);
+    }
+
+    #[test]
+    fn test_abort() {
+        let (addr, target) = fake_target(1);
+        let mut executor = NetworkExecutor::<TestInput<NetworkPacket<BytesInput>>, _, _, (), u32, _>::new(tuple_list!(StateObserver::<u32>::new("state")), addr, "state", response_len).with_timeout(Duration::from_millis(100)).with_middleware(AbortAt {
+            n: 2,
+        });
+        let input = TestInput {
+            packets: vec![data("hello\n"), data("bye\n"), data("again\n")],
+        };
+
+        // The partial path of an aborted execution is not reported to the feedbacks
+        assert_eq!(executor.send_packets(&input), ExitKind::Ok);
+        assert_eq!(target.join().unwrap(), ["hello", "bye"]);
+        let observer: &StateObserver<u32> = executor.observers().match_name("state").unwrap();
+        assert!(observer.path().is_empty());
+        assert!(!observer.had_new_transitions());
+    }
+
+    #[test]
+    fn test_connection_loss() {
+        let (addr, target) = fake_target(1);
+        let mut executor = NetworkExecutor::<TestInput<NetworkPacket<BytesInput>>, _, _, (), u32, _>::new(tuple_list!(StateObserver::<u32>::new("state")), addr, "state", response_len).with_timeout(Duration::from_millis(100));
+        let input = TestInput {
+            packets: vec![data("hello\n"), data("close\n"), data("bye\n")],
+        };
+
+        assert_eq!(executor.send_packets(&input), ExitKind::Crash);
+        assert_eq!(target.join().unwrap(), ["hello", "close"]);
+
+        // Nothing listens anymore
+        assert_eq!(executor.send_packets(&input), ExitKind::Crash);
+    }
+
+    #[test]
+    fn test_hang_detection() {
+        let (addr, target) = fake_target(1);
+        let mut executor = NetworkExecutor::<TestInput<NetworkPacket<BytesInput>>, _, _, (), u32, _>::new(tuple_list!(StateObserver::<u32>::new("state")), addr, "state", response_len).with_timeout(Duration::from_millis(100)).with_hang_detection(2);
+        let input = TestInput {
+            packets: vec![data("quiet\n"), data("hello\n"), data("quiet\n"), data("quiet\n"), data("bye\n")],
+        };
+
+        assert_eq!(executor.send_packets(&input), ExitKind::Timeout);
+        assert_eq!(target.join().unwrap(), ["quiet", "hello", "quiet", "quiet"]);
+    }
+
+    #[test]
+    fn test_missing_observer() {
+        let mut state = StdState::new(StdRand::with_seed(0), InMemoryCorpus::<TestInput<NetworkPacket<BytesInput>>>::new(), InMemoryCorpus::new(), &mut (), &mut ()).unwrap();
+        let mut executor = NetworkExecutor::<TestInput<NetworkPacket<BytesInput>>, NetworkPacket<BytesInput>, _, _, u32, _>::new(tuple_list!(StateObserver::<u32>::new("state")), "127.0.0.1:1".parse().unwrap(), "other", response_len);
+        let input = TestInput {
+            packets: vec![data("hello\n")],
+        };
+
+        assert!(executor.run_target(&mut (), &mut state, &mut (), &input).is_err());
+    }
+}
@@ -0,0 +1,427 @@
+use crate::grammar::HasGrammarMutation;
+use crate::mutators::{
+    HasCrossoverInsertMutation, HasCrossoverReplaceMutation, HasDelimitedSpliceMutation, HasHavocMutation, HasKindConversion, HasMerge, HasMutationMask, HasNumericFieldMutation, HasPacketGenerator, HasPostMutationFixup, HasRegions, HasSpliceMutation,
+    HasSplit, HasTerminalPacket, NumericField,
+};
+use libafl::{
+    impl_serdeany,
+    inputs::{BytesInput, HasBytesVec},
+    mutators::{MutationResult, MutatorsTuple},
+    state::{HasMaxSize, HasMetadata, HasRand},
+    Error,
+};
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+
+/// Connection management requested by a pseudo-packet.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ConnectionEvent {
+    /// Open a new connection to the target. If a connection is
+    /// already established it gets closed first.
+    Connect,
+    /// Close the current connection.
+    Disconnect,
+}
+
+/// Signifies that a packet type can express connection management
+/// via `Connect` and `Disconnect` pseudo-packets.
+///
+/// The [`NetworkExecutor`](crate::NetworkExecutor) does not send pseudo-packets
+/// but (re-)connects or disconnects instead. This allows inputs to
+/// test how the target handles state across connection churn.
+/// The [`PacketReconnectMutator`](crate::PacketReconnectMutator) inserts such pseudo-packets into inputs.
+///
+/// Already implemented for:
+/// - [`NetworkPacket`]
+pub trait HasConnectionEvents {
+    /// Create a `Connect` pseudo-packet
+    fn connect() -> Self;
+
+    /// Create a `Disconnect` pseudo-packet
+    fn disconnect() -> Self;
+
+    /// If this is a pseudo-packet return the connection event it stands for,
+    /// `None` if this is a regular packet with data.
+    fn connection_event(&self) -> Option<ConnectionEvent>;
+}
+
+/// Signifies that a packet type can be sent over the network by
+/// the [`NetworkExecutor`](crate::NetworkExecutor).
+///
+/// Already implemented for:
+/// - [`BytesInput`](libafl::inputs::BytesInput)
+/// - [`TextLinePacket`](crate::TextLinePacket)
+/// - [`NetworkPacket`]
+pub trait HasPayload {
+    /// Return the bytes that are sent to the target
+    fn payload(&self) -> Vec<u8>;
+}
+
+impl HasPayload for BytesInput {
+    fn payload(&self) -> Vec<u8> {
+        self.bytes().to_vec()
+    }
+}
+
+/// Whether the target accepted a packet, as judged by a [`HasValidityOracle`]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Validity {
+    /// The target processed the packet
+    Accepted,
+    /// The target refused the packet, e.g. with a syntax error
+    Rejected,
+    /// The response does not say
+    Unknown,
+}
+
+/// Signifies that a packet type can tell from the response of the target whether
+/// the target accepted or rejected the packet.
+///
+/// If enabled with [`NetworkExecutor::with_validity_oracle()`](crate::NetworkExecutor::with_validity_oracle),
+/// the executor judges every response and stores the counts in the [`ValidityMetadata`]
+/// of the state. From there the [`PacketMutationScheduler`](crate::PacketMutationScheduler) learns which
+/// mutators mostly produce inputs that get rejected and the [`ValidityFeedback`](crate::ValidityFeedback)
+/// reports the acceptance rate to the monitor.
+///
+/// Already implemented for:
+/// - [`NetworkPacket`]
+/// - `FtpCommand` and `SmtpCommand` from the [`protocols`](crate::protocols) module: reply codes 4xx and 5xx are rejections
+/// - `Http1Request` from the [`protocols`](crate::protocols) module: status codes 4xx are rejections
+pub trait HasValidityOracle {
+    /// Judge the `response` of the target to this packet
+    fn validity(&self, response: &[u8]) -> Validity;
+}
+
+/// Judges a response that starts with a three-digit status code
+#[cfg(any(feature = "protocol_ftp", feature = "protocol_smtp", feature = "protocol_http1"))]
+pub(crate) fn status_code_validity(code: &[u8], rejected: &[u8]) -> Validity {
+    match code {
+        [first, b'0'..=b'9', b'0'..=b'9'] if rejected.contains(first) => Validity::Rejected,
+        [b'1'..=b'9', b'0'..=b'9', b'0'..=b'9'] => Validity::Accepted,
+        _ => Validity::Unknown,
+    }
+}
+
+/// Metadata that the [`NetworkExecutor`](crate::NetworkExecutor) keeps in the state
+/// when a [`HasValidityOracle`] is enabled.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ValidityMetadata {
+    /// Number of accepted packets in the last execution
+    pub accepted: usize,
+    /// Number of rejected packets in the last execution
+    pub rejected: usize,
+    /// Number of accepted packets in all executions
+    pub total_accepted: u64,
+    /// Number of rejected packets in all executions
+    pub total_rejected: u64,
+}
+
+impl_serdeany!(ValidityMetadata);
+
+impl ValidityMetadata {
+    /// Start counting a new execution
+    pub(crate) fn reset(&mut self) {
+        self.accepted = 0;
+        self.rejected = 0;
+    }
+
+    /// Count the judgement of a single response
+    pub(crate) fn count(&mut self, validity: Validity) {
+        match validity {
+            Validity::Accepted => {
+                self.accepted += 1;
+                self.total_accepted += 1;
+            },
+            Validity::Rejected => {
+                self.rejected += 1;
+                self.total_rejected += 1;
+            },
+            Validity::Unknown => {},
+        }
+    }
+
+    /// Returns the fraction of judged packets that were accepted in all executions,
+    /// `None` if no packet has been judged yet
+    pub fn acceptance_rate(&self) -> Option<f64> {
+        let total = self.total_accepted + self.total_rejected;
+
+        if total == 0 {
+            None
+        } else {
+            Some(self.total_accepted as f64 / total as f64)
+        }
+    }
+}
+
+/// Adds the `Connect` and `Disconnect` pseudo-packets to any packet type.
+///
+/// All mutations are forwarded to the wrapped packets, pseudo-packets themselves
+/// are never mutated.
+///
+/// # Example
+/// ```
+/// struct PacketInput {
+///     packets: Vec<NetworkPacket<TextLinePacket>>,
+/// }
+///
+/// let input = PacketInput {
+///     packets: vec![
+///         NetworkPacket::Data(TextLinePacket::from_line(b"USER anonymous").unwrap()),
+///         NetworkPacket::Disconnect,
+///         NetworkPacket::Connect,
+///         NetworkPacket::Data(TextLinePacket::from_line(b"PASS anonymous").unwrap()),
+///     ],
+/// };
+/// ```
+#[derive(Hash, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum NetworkPacket<P> {
+    /// Open a new connection
+    Connect,
+    /// Close the current connection
+    Disconnect,
+    /// A regular packet
+    Data(P),
+}
+
+impl<P> HasConnectionEvents for NetworkPacket<P> {
+    fn connect() -> Self {
+        NetworkPacket::Connect
+    }
+
+    fn disconnect() -> Self {
+        NetworkPacket::Disconnect
+    }
+
+    fn connection_event(&self) -> Option<ConnectionEvent> {
+        match self {
+            NetworkPacket::Connect => Some(ConnectionEvent::Connect),
+            NetworkPacket::Disconnect => Some(ConnectionEvent::Disconnect),
+            NetworkPacket::Data(_) => None,
+        }
+    }
+}
+
+impl<P> HasPayload for NetworkPacket<P>
+where
+    P: HasPayload,
+{
+    fn payload(&self) -> Vec<u8> {
+        match self {
+            NetworkPacket::Data(data) => data.payload(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+impl<P> HasValidityOracle for NetworkPacket<P>
+where
+    P: HasValidityOracle,
+{
+    fn validity(&self, response: &[u8]) -> Validity {
+        match self {
+            NetworkPacket::Data(data) => data.validity(response),
+            _ => Validity::Unknown,
+        }
+    }
+}
+
+impl<P, S> HasCrossoverInsertMutation<S> for NetworkPacket<P>
+where
+    P: HasCrossoverInsertMutation<S>,
+    S: HasRand + HasMaxSize,
+{
+    fn mutate_crossover_insert(&mut self, state: &mut S, other: &Self, stage_idx: i32) -> Result<MutationResult, Error> {
+        match (self, other) {
+            (NetworkPacket::Data(data), NetworkPacket::Data(other_data)) => data.mutate_crossover_insert(state, other_data, stage_idx),
+            _ => Ok(MutationResult::Skipped),
+        }
+    }
+}
+
+impl<P, S> HasCrossoverReplaceMutation<S> for NetworkPacket<P>
+where
+    P: HasCrossoverReplaceMutation<S>,
+    S: HasRand + HasMaxSize,
+{
+    fn mutate_crossover_replace(&mut self, state: &mut S, other: &Self, stage_idx: i32) -> Result<MutationResult, Error> {
+        match (self, other) {
+            (NetworkPacket::Data(data), NetworkPacket::Data(other_data)) => data.mutate_crossover_replace(state, other_data, stage_idx),
+            _ => Ok(MutationResult::Skipped),
+        }
+    }
+}
+
+impl<P, S> HasSpliceMutation<S> for NetworkPacket<P>
+where
+    P: HasSpliceMutation<S>,
+    S: HasRand + HasMaxSize,
+{
+    fn mutate_splice(&mut self, state: &mut S, other: &Self, stage_idx: i32) -> Result<MutationResult, Error> {
+        match (self, other) {
+            (NetworkPacket::Data(data), NetworkPacket::Data(other_data)) => data.mutate_splice(state, other_data, stage_idx),
+            _ => Ok(MutationResult::Skipped),
+        }
+    }
+}
+
+impl<P, S> HasDelimitedSpliceMutation<S> for NetworkPacket<P>
+where
+    P: HasDelimitedSpliceMutation<S>,
+    S: HasRand + HasMaxSize,
+{
+    fn mutate_delimited_splice(&mut self, state: &mut S, other: &Self, delimiters: &[Vec<u8>], stage_idx: i32) -> Result<MutationResult, Error> {
+        match (self, other) {
+            (NetworkPacket::Data(data), NetworkPacket::Data(other_data)) => data.mutate_delimited_splice(state, other_data, delimiters, stage_idx),
+            _ => Ok(MutationResult::Skipped),
+        }
+    }
+}
+
+impl<P, MT, S> HasHavocMutation<MT, S> for NetworkPacket<P>
+where
+    P: HasHavocMutation<MT, S>,
+    MT: MutatorsTuple<BytesInput, S>,
+    S: HasRand + HasMaxSize,
+{
+    fn mutate_havoc(&mut self, state: &mut S, mutations: &mut MT, mutation: usize, stage_idx: i32) -> Result<MutationResult, Error> {
+        match self {
+            NetworkPacket::Data(data) => data.mutate_havoc(state, mutations, mutation, stage_idx),
+            _ => Ok(MutationResult::Skipped),
+        }
+    }
+}
+
+impl<P, S> HasPacketGenerator<S> for NetworkPacket<P>
+where
+    P: HasPacketGenerator<S>,
+    S: HasRand,
+{
+    fn generate_packet(state: &mut S) -> Option<Self> {
+        P::generate_packet(state).map(NetworkPacket::Data)
+    }
+}
+
+impl<P, S> HasSplit<S> for NetworkPacket<P>
+where
+    P: HasSplit<S>,
+    S: HasRand,
+{
+    fn split(&mut self, state: &mut S) -> Option<Self> {
+        match self {
+            NetworkPacket::Data(data) => data.split(state).map(NetworkPacket::Data),
+            _ => None,
+        }
+    }
+}
+
+impl<P, S> HasMerge<S> for NetworkPacket<P>
+where
+    P: HasMerge<S>,
+    S: HasRand,
+{
+    fn merge(&mut self, state: &mut S, other: &Self) -> bool {
+        match (self, other) {
+            (NetworkPacket::Data(data), NetworkPacket::Data(other_data)) => data.merge(state, other_data),
+            _ => false,
+        }
+    }
+}
+
+impl<P> HasNumericFieldMutation for NetworkPacket<P>
+where
+    P: HasNumericFieldMutation,
+{
+    fn numeric_fields(&self) -> Vec<NumericField> {
+        match self {
+            NetworkPacket::Data(data) => data.numeric_fields(),
+            _ => Vec::new(),
+        }
+    }
+
+    fn field_bytes_mut(&mut self) -> Option<&mut Vec<u8>> {
+        match self {
+            NetworkPacket::Data(data) => data.field_bytes_mut(),
+            _ => None,
+        }
+    }
+}
+
+impl<P> HasRegions for NetworkPacket<P>
+where
+    P: HasRegions,
+{
+    fn regions(&self) -> Vec<Range<usize>> {
+        match self {
+            NetworkPacket::Data(data) => data.regions(),
+            _ => Vec::new(),
+        }
+    }
+
+    fn region_bytes_mut(&mut self) -> Option<&mut Vec<u8>> {
+        match self {
+            NetworkPacket::Data(data) => data.region_bytes_mut(),
+            _ => None,
+        }
+    }
+}
+
+impl<P, S> HasKindConversion<S> for NetworkPacket<P>
+where
+    P: HasKindConversion<S>,
+    S: HasRand,
+{
+    fn convert_kind(&mut self, state: &mut S) -> bool {
+        match self {
+            NetworkPacket::Data(data) => data.convert_kind(state),
+            _ => false,
+        }
+    }
+}
+
+impl<P> HasMutationMask for NetworkPacket<P>
+where
+    P: HasMutationMask,
+{
+    fn protected_ranges(&self) -> Vec<Range<usize>> {
+        match self {
+            NetworkPacket::Data(data) => data.protected_ranges(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+impl<P> HasTerminalPacket for NetworkPacket<P>
+where
+    P: HasTerminalPacket,
+{
+    fn is_terminal(&self) -> bool {
+        match self {
+            NetworkPacket::Data(data) => data.is_terminal(),
+            _ => false,
+        }
+    }
+}
+
+impl<P> HasPostMutationFixup for NetworkPacket<P>
+where
+    P: HasPostMutationFixup,
+{
+    fn fixup(&mut self) {
+        if let NetworkPacket::Data(data) = self {
+            data.fixup();
+        }
+    }
+}
+
+impl<P, S> HasGrammarMutation<S> for NetworkPacket<P>
+where
+    P: HasGrammarMutation<S>,
+    S: HasRand + HasMetadata,
+{
+    fn mutate_grammar(&mut self, state: &mut S, stage_idx: i32) -> Result<MutationResult, Error> {
+        match self {
+            NetworkPacket::Data(data) => data.mutate_grammar(state, stage_idx),
+            _ => Ok(MutationResult::Skipped),
+        }
+    }
+}
@@ -0,0 +1,191 @@
+use std::time::{Duration, Instant};
+
+/// Rate factors never drop below this when the target is overloaded
+const MIN_FACTOR: f64 = 1.0 / 64.0;
+/// How fast the rates recover after every execution without overload
+const RECOVERY: f64 = 1.05;
+
+#[derive(Clone, Debug)]
+struct TokenBucket {
+    rate: f64,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    /// Returns `None` for rates that can't be waited for: zero, negative, infinite or NaN
+    fn new(rate: f64) -> Option<Self> {
+        if !(rate > 0.0 && rate.is_finite()) {
+            return None;
+        }
+
+        Some(Self {
+            rate,
+            tokens: rate.max(1.0),
+            last_refill: Instant::now(),
+        })
+    }
+
+    /// Take a token, sleep until one is available if necessary
+    fn acquire(&mut self, factor: f64) {
+        let rate = self.rate * factor;
+        let capacity = rate.max(1.0);
+        let now = Instant::now();
+
+        self.tokens = (self.tokens + now.duration_since(self.last_refill).as_secs_f64() * rate).min(capacity);
+        self.last_refill = now;
+
+        if self.tokens < 1.0 {
+            std::thread::sleep(Duration::try_from_secs_f64((1.0 - self.tokens) / rate).unwrap_or(Duration::MAX));
+            self.tokens = 1.0;
+            self.last_refill = Instant::now();
+        }
+
+        self.tokens -= 1.0;
+    }
+}
+
+/// A token-bucket throttle that limits how many executions and connections
+/// per second an executor makes.
+///
+/// Some targets stop working properly when they get too many connections
+/// in a short amount of time. Instead of sleeping for a fixed amount of time
+/// in every execution, configure the rates the target can handle and
+/// the responses that signal an overloaded target. When such a response is seen
+/// the rates are halved and then recover slowly with every execution.
+///
+/// Use it with [`NetworkExecutor::with_throttle()`](crate::NetworkExecutor::with_throttle) or call
+/// [`wait_execution()`](Throttle::wait_execution), [`wait_connection()`](Throttle::wait_connection) and
+/// [`check_response()`](Throttle::check_response) from your own executor.
+///
+/// # Example
+/// ```
+/// let throttle = Throttle::new()
+///     .with_max_connections(20.0)
+///     .with_overload_response(b"MAXIMUM ALLOWED USERS CONNECTED");
+/// ```
+#[derive(Clone, Debug)]
+pub struct Throttle {
+    executions: Option<TokenBucket>,
+    connections: Option<TokenBucket>,
+    overload_responses: Vec<Vec<u8>>,
+    factor: f64,
+    overloaded: bool,
+}
+
+impl Throttle {
+    /// Create a new Throttle that does not limit anything
+    pub fn new() -> Self {
+        Self {
+            executions: None,
+            connections: None,
+            overload_responses: Vec::new(),
+            factor: 1.0,
+            overloaded: false,
+        }
+    }
+
+    /// Allow at most `per_sec` executions per second.
+    /// Rates that are not positive and finite don't limit anything.
+    pub fn with_max_executions(mut self, per_sec: f64) -> Self {
+        self.executions = TokenBucket::new(per_sec);
+        self
+    }
+
+    /// Allow at most `per_sec` new connections per second.
+    /// Rates that are not positive and finite don't limit anything.
+    pub fn with_max_connections(mut self, per_sec: f64) -> Self {
+        self.connections = TokenBucket::new(per_sec);
+        self
+    }
+
+    /// Consider the target overloaded if a response contains `pattern`
+    pub fn with_overload_response(mut self, pattern: &[u8]) -> Self {
+        self.overload_responses.push(pattern.to_vec());
+        self
+    }
+
+    /// Call this before an execution starts. Blocks until the execution may proceed.
+    ///
+    /// If the previous execution did not report an overload the rates recover a bit.
+    pub fn wait_execution(&mut self) {
+        if !self.overloaded {
+            self.factor = (self.factor * RECOVERY).min(1.0);
+        }
+        self.overloaded = false;
+
+        if let Some(bucket) = &mut self.executions {
+            bucket.acquire(self.factor);
+        }
+    }
+
+    /// Call this before a connection gets established. Blocks until the connection may be made.
+    pub fn wait_connection(&mut self) {
+        if let Some(bucket) = &mut self.connections {
+            bucket.acquire(self.factor);
+        }
+    }
+
+    /// Check whether a response signals an overloaded target and if so,
+    /// slow down. Returns whether the target is overloaded.
+    pub fn check_response(&mut self, response: &[u8]) -> bool {
+        let overloaded = self.overload_responses.iter().any(|pattern| !pattern.is_empty() && response.windows(pattern.len()).any(|w| w == &pattern[..]));
+
+        if overloaded {
+            self.overload();
+        }
+
+        overloaded
+    }
+
+    /// Tell the throttle that the target is overloaded. This halves the rates.
+    pub fn overload(&mut self) {
+        if !self.overloaded {
+            self.factor = (self.factor / 2.0).max(MIN_FACTOR);
+            self.overloaded = true;
+        }
+    }
+
+    /// Returns the fraction of the configured rates that is currently allowed
+    pub fn factor(&self) -> f64 {
+        self.factor
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_adaptive_factor() {
+        let mut throttle = Throttle::new().with_overload_response(b"421 ");
+
+        assert!(!throttle.check_response(b"220 ready\r\n"));
+        assert!(throttle.check_response(b"421 too many connections\r\n"));
+        assert!(throttle.check_response(b"421 too many connections\r\n"));
+        assert_eq!(throttle.factor(), 0.5);
+
+        throttle.wait_execution();
+        assert_eq!(throttle.factor(), 0.5);
+        throttle.wait_execution();
+        assert!(throttle.factor() > 0.5);
+
+        for _ in 0..64 {
+            throttle.wait_execution();
+        }
+        assert_eq!(throttle.factor(), 1.0);
+    }
+
+    #[test]
+    fn test_invalid_rates() {
+        for rate in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            let mut throttle = Throttle::new().with_max_executions(rate).with_max_connections(rate);
+            assert!(throttle.executions.is_none() && throttle.connections.is_none());
+
+            // Doesn't panic or block
+            throttle.overload();
+            throttle.wait_execution();
+            throttle.wait_connection();
+        }
+    }
+}
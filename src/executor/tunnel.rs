@@ -0,0 +1,295 @@
+use std::io::{Error, ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::time::Duration;
+
+const SOCKS_VERSION: u8 = 5;
+const SOCKS_NO_AUTH: u8 = 0;
+const SOCKS_USER_PASS: u8 = 2;
+const SOCKS_CONNECT: u8 = 1;
+const SOCKS_IPV4: u8 = 1;
+const SOCKS_DOMAIN: u8 = 3;
+const SOCKS_IPV6: u8 = 4;
+/// Upper bound on the size of the response header of an HTTP proxy
+const MAX_HTTP_HEADER: usize = 8192;
+
+/// A proxy that the [`NetworkExecutor`](crate::NetworkExecutor) tunnels its connections through,
+/// e.g. when the target sits in a lab network that is only reachable via a jump host.
+///
+/// Every connection to the target gets its own tunnel. Since every client of a multi-core campaign
+/// creates its own executor, the proxy can be configured per client.
+///
+/// # Example
+/// ```
+/// // On the jump host: ssh -D 1080 ...
+/// let proxy = TransportProxy::socks5("127.0.0.1:1080".parse().unwrap());
+/// let executor = NetworkExecutor::new(observers, "10.0.0.5:21".parse().unwrap(), "state", ftp::status_code).with_proxy(proxy);
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TransportProxy {
+    /// A SOCKS5 proxy, see RFC 1928
+    Socks5 {
+        /// Address of the proxy
+        addr: SocketAddr,
+        /// Username and password for RFC 1929 authentication
+        credentials: Option<(String, String)>,
+    },
+    /// An HTTP proxy that supports the `CONNECT` method
+    HttpConnect {
+        /// Address of the proxy
+        addr: SocketAddr,
+        /// Username and password for basic authentication
+        credentials: Option<(String, String)>,
+    },
+}
+
+impl TransportProxy {
+    /// Create a new SOCKS5 proxy without authentication
+    pub fn socks5(addr: SocketAddr) -> Self {
+        TransportProxy::Socks5 {
+            addr,
+            credentials: None,
+        }
+    }
+
+    /// Create a new HTTP CONNECT proxy without authentication
+    pub fn http_connect(addr: SocketAddr) -> Self {
+        TransportProxy::HttpConnect {
+            addr,
+            credentials: None,
+        }
+    }
+
+    /// Authenticate with a username and a password
+    pub fn with_credentials(mut self, username: &str, password: &str) -> Self {
+        match &mut self {
+            TransportProxy::Socks5 {
+                credentials,
+                ..
+            }
+            | TransportProxy::HttpConnect {
+                credentials,
+                ..
+            } => *credentials = Some((username.to_string(), password.to_string())),
+        }
+        self
+    }
+
+    /// Open a tunnel to `target` through the proxy. `timeout` applies to connecting
+    /// and to every read and write of the handshake.
+    pub(crate) fn connect(&self, target: &SocketAddr, timeout: Duration) -> Result<TcpStream, Error> {
+        let addr = match self {
+            TransportProxy::Socks5 {
+                addr,
+                ..
+            }
+            | TransportProxy::HttpConnect {
+                addr,
+                ..
+            } => addr,
+        };
+
+        let mut stream = TcpStream::connect_timeout(addr, timeout)?;
+        stream.set_read_timeout(Some(timeout))?;
+        stream.set_write_timeout(Some(timeout))?;
+
+        match self {
+            TransportProxy::Socks5 {
+                credentials,
+                ..
+            } => socks5_handshake(&mut stream, target, credentials.as_ref())?,
+            TransportProxy::HttpConnect {
+                credentials,
+                ..
+            } => http_handshake(&mut stream, target, credentials.as_ref())?,
+        }
+
+        stream.set_write_timeout(None)?;
+        Ok(stream)
+    }
+}
+
+fn proxy_error(msg: &str) -> Error {
+    Error::new(ErrorKind::ConnectionRefused, msg.to_string())
+}
+
+fn socks5_handshake(stream: &mut TcpStream, target: &SocketAddr, credentials: Option<&(String, String)>) -> Result<(), Error> {
+    let method = if credentials.is_some() { SOCKS_USER_PASS } else { SOCKS_NO_AUTH };
+    stream.write_all(&[SOCKS_VERSION, 1, method])?;
+
+    let mut reply = [0; 2];
+    stream.read_exact(&mut reply)?;
+
+    if reply != [SOCKS_VERSION, method] {
+        return Err(proxy_error("SOCKS5 proxy rejected the authentication method"));
+    }
+
+    if let Some((username, password)) = credentials {
+        if username.len() > 255 || password.len() > 255 {
+            return Err(Error::new(ErrorKind::InvalidInput, "SOCKS5 credentials are too long"));
+        }
+
+        let mut request = vec![1, username.len() as u8];
+        request.extend_from_slice(username.as_bytes());
+        request.push(password.len() as u8);
+        request.extend_from_slice(password.as_bytes());
+        stream.write_all(&request)?;
+
+        stream.read_exact(&mut reply)?;
+
+        if reply[1] != 0 {
+            return Err(proxy_error("SOCKS5 proxy rejected the credentials"));
+        }
+    }
+
+    let mut request = vec![SOCKS_VERSION, SOCKS_CONNECT, 0];
+
+    match target {
+        SocketAddr::V4(addr) => {
+            request.push(SOCKS_IPV4);
+            request.extend_from_slice(&addr.ip().octets());
+        },
+        SocketAddr::V6(addr) => {
+            request.push(SOCKS_IPV6);
+            request.extend_from_slice(&addr.ip().octets());
+        },
+    }
+
+    request.extend_from_slice(&target.port().to_be_bytes());
+    stream.write_all(&request)?;
+
+    let mut header = [0; 4];
+    stream.read_exact(&mut header)?;
+
+    if header[0] != SOCKS_VERSION || header[1] != 0 {
+        return Err(proxy_error("SOCKS5 proxy could not connect to the target"));
+    }
+
+    // Skip the address the proxy bound to
+    let len = match header[3] {
+        SOCKS_IPV4 => 4,
+        SOCKS_IPV6 => 16,
+        SOCKS_DOMAIN => {
+            let mut len = [0; 1];
+            stream.read_exact(&mut len)?;
+            len[0] as usize
+        },
+        _ => return Err(proxy_error("SOCKS5 proxy sent an invalid address type")),
+    };
+    let mut bound = vec![0; len + 2];
+    stream.read_exact(&mut bound)?;
+
+    Ok(())
+}
+
+fn http_handshake(stream: &mut TcpStream, target: &SocketAddr, credentials: Option<&(String, String)>) -> Result<(), Error> {
+    let mut request = format!("CONNECT {0} HTTP/1.1\r\nHost: {0}\r\n", target);
+
+    if let Some((username, password)) = credentials {
+        request.push_str(&format!("Proxy-Authorization: Basic {}\r\n", base64(format!("{}:{}", username, password).as_bytes())));
+    }
+
+    request.push_str("\r\n");
+    stream.write_all(request.as_bytes())?;
+
+    // Read byte by byte so that nothing the target sends afterwards gets lost
+    let mut response = Vec::new();
+    let mut byte = [0; 1];
+
+    while !response.ends_with(b"\r\n\r\n") {
+        if response.len() >= MAX_HTTP_HEADER {
+            return Err(proxy_error("HTTP proxy sent an oversized response"));
+        }
+
+        stream.read_exact(&mut byte)?;
+        response.push(byte[0]);
+    }
+
+    let status = response.split(|c| *c == b' ').nth(1).unwrap_or_default();
+
+    if response.starts_with(b"HTTP/1.") && status == b"200" {
+        Ok(())
+    } else {
+        Err(proxy_error(&format!("HTTP proxy refused the tunnel: {}", String::from_utf8_lossy(response.split(|c| *c == b'\r').next().unwrap_or_default()))))
+    }
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut ret = String::with_capacity(data.len() / 3 * 4 + 4);
+
+    for chunk in data.chunks(3) {
+        let bits = (chunk[0] as u32) << 16 | (*chunk.get(1).unwrap_or(&0) as u32) << 8 | *chunk.get(2).unwrap_or(&0) as u32;
+
+        for i in 0..4 {
+            if i <= chunk.len() {
+                ret.push(ALPHABET[(bits >> (18 - 6 * i) & 0x3F) as usize] as char);
+            } else {
+                ret.push('=');
+            }
+        }
+    }
+
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::thread::JoinHandle;
+
+    /// Spawn a fake proxy that checks every request of the handshake, sends the replies and then echoes everything
+    fn fake_proxy(handshake: Vec<(Vec<u8>, Vec<u8>)>) -> (SocketAddr, JoinHandle<()>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let thread = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+
+            for (request, reply) in handshake {
+                let mut received = vec![0; request.len()];
+                stream.read_exact(&mut received).unwrap();
+                assert_eq!(received, request);
+                stream.write_all(&reply).unwrap();
+            }
+
+            let mut writer = stream.try_clone().unwrap();
+            let _ = std::io::copy(&mut stream, &mut writer);
+        });
+
+        (addr, thread)
+    }
+
+    fn echo(mut stream: TcpStream) {
+        stream.write_all(b"PING").unwrap();
+        let mut buf = [0; 4];
+        stream.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"PING");
+    }
+
+    #[test]
+    fn test_socks5() {
+        let target: SocketAddr = "10.0.0.5:21".parse().unwrap();
+        let handshake = vec![(vec![5, 1, 2], vec![5, 2]), (b"\x01\x04user\x04pass".to_vec(), vec![1, 0]), (vec![5, 1, 0, 1, 10, 0, 0, 5, 0, 21], vec![5, 0, 0, 1, 127, 0, 0, 1, 0x12, 0x34])];
+
+        let (addr, thread) = fake_proxy(handshake);
+        let stream = TransportProxy::socks5(addr).with_credentials("user", "pass").connect(&target, Duration::from_secs(5)).unwrap();
+        echo(stream);
+        thread.join().unwrap();
+    }
+
+    #[test]
+    fn test_http_connect() {
+        let target: SocketAddr = "10.0.0.5:21".parse().unwrap();
+        let request = b"CONNECT 10.0.0.5:21 HTTP/1.1\r\nHost: 10.0.0.5:21\r\nProxy-Authorization: Basic dXNlcjpwYXNz\r\n\r\n".to_vec();
+        let reply = b"HTTP/1.1 200 Connection established\r\n\r\n".to_vec();
+
+        let (addr, thread) = fake_proxy(vec![(request, reply)]);
+        let stream = TransportProxy::http_connect(addr).with_credentials("user", "pass").connect(&target, Duration::from_secs(5)).unwrap();
+        echo(stream);
+        thread.join().unwrap();
+
+        assert_eq!(base64(b"a"), "YQ==");
+        assert_eq!(base64(b"ab"), "YWI=");
+    }
+}
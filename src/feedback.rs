@@ -1,20 +1,26 @@
 use crate::{
-    event::{USER_STAT_EDGES, USER_STAT_NODES},
+    event::{register_user_stat, UserStatFormat, USER_STAT_BUDGET_OVERFLOWS, USER_STAT_EDGES, USER_STAT_GROWTH_THROTTLED, USER_STAT_MUTATOR_PREFIX, USER_STAT_NODES, USER_STAT_PACKET_ACCEPTANCE},
+    executor::ValidityMetadata,
+    mutators::TransitionNoveltyMetadata,
     observer::StateObserver,
+    scheduler::MutatorStatsMetadata,
+    validate::execute_once,
 };
 
 #[cfg(feature = "graphviz")]
 use crate::event::USER_STAT_STATEGRAPH;
 
+use libafl::impl_serdeany;
 use libafl::{
     bolts::tuples::Named,
-    events::{Event, EventFirer},
-    executors::ExitKind,
+    corpus::Testcase,
+    events::{Event, EventFirer, NopEventManager},
+    executors::{Executor, ExitKind, HasObservers},
     feedbacks::{Feedback, HasObserverName},
     inputs::Input,
     monitors::UserStats,
     observers::ObserversTuple,
-    state::HasClientPerfMonitor,
+    state::{HasClientPerfMonitor, HasMetadata},
     Error,
 };
 use serde::{Deserialize, Serialize};
@@ -23,13 +29,33 @@ use std::fmt::Debug;
 use std::hash::Hash;
 use std::marker::PhantomData;
 
+#[derive(Debug, Clone, Copy)]
+struct GrowthLimit {
+    max_new_nodes: usize,
+    window_execs: usize,
+}
+
 /// Determines that an input is interesting if it led to new states or transitions in the previous run.
+///
+/// If the state inference is too fine-grained every other input creates a new state
+/// and the corpus explodes. To guard against this, limit the growth of the state-graph with
+/// [`with_growth_limit()`](StateFeedback::with_growth_limit).
+///
+/// With [`with_hit_buckets()`](StateFeedback::with_hit_buckets) an input is also interesting if it took a known
+/// transition often enough for its hit count to reach a new logarithmic bucket.
+/// If the observer tracks [n-grams](StateObserver::with_ngrams), new sequences of states are interesting, too.
 #[derive(Debug)]
 pub struct StateFeedback<PS>
 where
     PS: Debug + Clone + Eq + Hash + Serialize + for<'a> Deserialize<'a>,
 {
     observer_name: String,
+    growth_limit: Option<GrowthLimit>,
+    window_execs: usize,
+    window_nodes: usize,
+    throttled: bool,
+    hit_buckets: bool,
+    max_loop_ratio: Option<f64>,
     phantom: PhantomData<PS>,
 }
 
@@ -41,9 +67,74 @@ where
     pub fn new(observer: &StateObserver<PS>) -> Self {
         Self {
             observer_name: observer.name().to_string(),
+            growth_limit: None,
+            window_execs: 0,
+            window_nodes: 0,
+            throttled: false,
+            hit_buckets: false,
+            max_loop_ratio: None,
             phantom: PhantomData,
         }
     }
+
+    /// Also consider inputs interesting if the hit count of a transition reached a new bucket,
+    /// see [`StateObserver::had_new_hit_buckets()`]. Rarely taken transitions that get taken again
+    /// are rewarded this way, transitions that are taken in every run almost never.
+    pub fn with_hit_buckets(mut self) -> Self {
+        self.hit_buckets = true;
+        self
+    }
+
+    /// Dampen inputs that mostly drive the target around the same loop: if the [loop ratio](StateObserver::loop_ratio)
+    /// of a run exceeds `max_loop_ratio`, only new transitions make the input interesting, not new
+    /// [hit buckets](StateFeedback::with_hit_buckets) or [n-grams](StateObserver::with_ngrams).
+    /// Loops produce plenty of both, but the long inputs behind them are slow to execute and rarely add anything else.
+    pub fn with_loop_limit(mut self, max_loop_ratio: f64) -> Self {
+        self.max_loop_ratio = Some(max_loop_ratio);
+        self
+    }
+
+    /// Monitor how fast the state-graph grows. If more than `max_new_nodes` states
+    /// get created within `window_execs` executions, only inputs that create new transitions
+    /// between already known states are interesting during the next window.
+    /// Whether this is active is reported with the user stat [`USER_STAT_GROWTH_THROTTLED`](crate::USER_STAT_GROWTH_THROTTLED).
+    pub fn with_growth_limit(mut self, max_new_nodes: usize, window_execs: usize) -> Self {
+        self.growth_limit = Some(GrowthLimit {
+            max_new_nodes,
+            window_execs,
+        });
+        self
+    }
+
+    /// Returns whether the growth limit is currently in effect
+    pub fn is_throttled(&self) -> bool {
+        self.throttled
+    }
+
+    /// Count an execution towards the current window. Returns whether the throttling changed.
+    fn update_growth(&mut self, nodes: usize) -> bool {
+        let limit = match self.growth_limit {
+            Some(limit) => limit,
+            None => return false,
+        };
+
+        self.window_execs += 1;
+
+        if self.window_execs < limit.window_execs {
+            return false;
+        }
+
+        let throttled = nodes.saturating_sub(self.window_nodes) > limit.max_new_nodes;
+        self.window_execs = 0;
+        self.window_nodes = nodes;
+
+        if throttled == self.throttled {
+            false
+        } else {
+            self.throttled = throttled;
+            true
+        }
+    }
 }
 
 impl<PS> Named for StateFeedback<PS>
@@ -77,10 +168,45 @@ where
     {
         let state_observer = observers.match_name::<StateObserver<PS>>(&self.observer_name).unwrap();
 
-        let ret = state_observer.had_new_transitions();
+        if self.update_growth(state_observer.info().0) {
+            if self.throttled {
+                println!("[butterfly] The state-graph grows too fast, only transitions between known states are interesting now");
+            } else {
+                println!("[butterfly] The growth of the state-graph is back to normal");
+            }
+
+            mgr.fire(
+                state,
+                Event::UpdateUserStats {
+                    name: USER_STAT_GROWTH_THROTTLED.to_string(),
+                    value: UserStats::Number(self.throttled as u64),
+                    phantom: PhantomData,
+                },
+            )?;
+        }
+
+        let mut ret = if self.throttled { state_observer.had_new_known_transitions() } else { state_observer.had_new_transitions() };
+        let loop_heavy = self.max_loop_ratio.is_some_and(|max_loop_ratio| state_observer.loop_ratio() > max_loop_ratio);
+
+        if !loop_heavy {
+            ret |= self.hit_buckets && state_observer.had_new_hit_buckets();
+            ret |= !self.throttled && state_observer.had_new_ngrams();
+        }
 
         if ret {
             let (nodes, edges) = state_observer.info();
+            let overflows = state_observer.budget_overflows();
+
+            if overflows > 0 {
+                mgr.fire(
+                    state,
+                    Event::UpdateUserStats {
+                        name: USER_STAT_BUDGET_OVERFLOWS.to_string(),
+                        value: UserStats::Number(overflows),
+                        phantom: PhantomData,
+                    },
+                )?;
+            }
 
             mgr.fire(
                 state,
@@ -115,3 +241,630 @@ where
         Ok(ret)
     }
 }
+
+/// Reports how many packets the target accepted to the monitor.
+///
+/// Every `interval` executions this feedback takes the [`ValidityMetadata`](crate::ValidityMetadata)
+/// that the [`NetworkExecutor`](crate::NetworkExecutor) collects with a [`HasValidityOracle`](crate::HasValidityOracle)
+/// and fires the user stat [`USER_STAT_PACKET_ACCEPTANCE`](crate::USER_STAT_PACKET_ACCEPTANCE).
+/// The stat is registered with [`register_user_stat()`](crate::register_user_stat) such that the
+/// [`StateMonitor`](crate::StateMonitor) displays it.
+/// It never considers an input interesting, so combine it with other feedbacks via `feedback_or!`.
+#[derive(Debug)]
+pub struct ValidityFeedback {
+    interval: usize,
+    execs: usize,
+}
+
+impl ValidityFeedback {
+    /// Create a new ValidityFeedback that reports every `interval` executions
+    pub fn new(interval: usize) -> Self {
+        register_user_stat(USER_STAT_PACKET_ACCEPTANCE, "accepted", UserStatFormat::Percent);
+
+        Self {
+            interval: interval.max(1),
+            execs: 0,
+        }
+    }
+}
+
+impl Named for ValidityFeedback {
+    fn name(&self) -> &str {
+        "ValidityFeedback"
+    }
+}
+
+impl<I, S> Feedback<I, S> for ValidityFeedback
+where
+    I: Input,
+    S: HasClientPerfMonitor + HasMetadata,
+{
+    fn is_interesting<EM, OT>(&mut self, state: &mut S, mgr: &mut EM, _input: &I, _observers: &OT, _exit_kind: &ExitKind) -> Result<bool, Error>
+    where
+        EM: EventFirer<I>,
+        OT: ObserversTuple<I, S>,
+    {
+        self.execs += 1;
+
+        if self.execs.checked_rem(self.interval) != Some(0) {
+            return Ok(false);
+        }
+
+        if let Some(validity) = state.metadata().get::<ValidityMetadata>() {
+            let value = UserStats::Ratio(validity.total_accepted, validity.total_accepted + validity.total_rejected);
+
+            mgr.fire(
+                state,
+                Event::UpdateUserStats {
+                    name: USER_STAT_PACKET_ACCEPTANCE.to_string(),
+                    value,
+                    phantom: PhantomData,
+                },
+            )?;
+        }
+
+        Ok(false)
+    }
+}
+
+/// Reports the effectiveness of every mutator to the monitor.
+///
+/// Every `interval` executions this feedback takes the [`MutatorStatsMetadata`](crate::MutatorStatsMetadata)
+/// that a [`PacketMutationScheduler`](crate::PacketMutationScheduler) collects with
+/// [`with_mutator_stats()`](crate::PacketMutationScheduler::with_mutator_stats) and fires one user stat per mutator
+/// with the key [`USER_STAT_MUTATOR_PREFIX`](crate::USER_STAT_MUTATOR_PREFIX) followed by the name of the mutator.
+/// Its value is the ratio of interesting executions to all executions.
+///
+/// The stats are registered with [`register_user_stat()`](crate::register_user_stat) when they are reported
+/// for the first time. If the monitor runs in a different process, register them there yourself
+/// with [`UserStatFormat::RatioSum`](crate::UserStatFormat::RatioSum).
+/// It never considers an input interesting, so combine it with other feedbacks via `feedback_or!`.
+#[derive(Debug)]
+pub struct MutatorStatsFeedback {
+    interval: usize,
+    execs: usize,
+    registered: usize,
+}
+
+impl MutatorStatsFeedback {
+    /// Create a new MutatorStatsFeedback that reports every `interval` executions
+    pub fn new(interval: usize) -> Self {
+        Self {
+            interval: interval.max(1),
+            execs: 0,
+            registered: 0,
+        }
+    }
+}
+
+impl Named for MutatorStatsFeedback {
+    fn name(&self) -> &str {
+        "MutatorStatsFeedback"
+    }
+}
+
+impl<I, S> Feedback<I, S> for MutatorStatsFeedback
+where
+    I: Input,
+    S: HasClientPerfMonitor + HasMetadata,
+{
+    fn is_interesting<EM, OT>(&mut self, state: &mut S, mgr: &mut EM, _input: &I, _observers: &OT, _exit_kind: &ExitKind) -> Result<bool, Error>
+    where
+        EM: EventFirer<I>,
+        OT: ObserversTuple<I, S>,
+    {
+        self.execs += 1;
+
+        if self.execs.checked_rem(self.interval) != Some(0) {
+            return Ok(false);
+        }
+
+        let stats = match state.metadata().get::<MutatorStatsMetadata>() {
+            Some(metadata) => metadata.stats.clone(),
+            None => return Ok(false),
+        };
+
+        // Mutators are only ever appended to the metadata
+        for stat in &stats[self.registered.min(stats.len())..] {
+            register_user_stat(&format!("{}{}", USER_STAT_MUTATOR_PREFIX, stat.name), &stat.name, UserStatFormat::RatioSum);
+        }
+        self.registered = stats.len();
+
+        for stat in stats {
+            mgr.fire(
+                state,
+                Event::UpdateUserStats {
+                    name: format!("{}{}", USER_STAT_MUTATOR_PREFIX, stat.name),
+                    value: UserStats::Ratio(stat.interesting, stat.executions),
+                    phantom: PhantomData,
+                },
+            )?;
+        }
+
+        Ok(false)
+    }
+}
+
+/// Records at which positions of a path new transitions were discovered in the
+/// [`TransitionNoveltyMetadata`] of the state, for the [`NoveltySelector`](crate::NoveltySelector).
+///
+/// Never considers an input interesting, so combine it with the other feedbacks.
+///
+/// # Example
+/// ```
+/// let mut feedback = feedback_or!(StateFeedback::new(&state_observer), TransitionNoveltyFeedback::new(&state_observer));
+/// let mutator = PacketHavocMutator::new(supported_havoc_mutations()).with_selection(NoveltySelector::new());
+/// ```
+#[derive(Debug)]
+pub struct TransitionNoveltyFeedback<PS>
+where
+    PS: Debug + Clone + Eq + Hash + Serialize + for<'a> Deserialize<'a>,
+{
+    observer_name: String,
+    phantom: PhantomData<PS>,
+}
+
+impl<PS> TransitionNoveltyFeedback<PS>
+where
+    PS: Debug + Clone + Eq + Hash + Serialize + for<'a> Deserialize<'a>,
+{
+    /// Create a new TransitionNoveltyFeedback that reads the new transitions from `observer`
+    pub fn new(observer: &StateObserver<PS>) -> Self {
+        Self {
+            observer_name: observer.name().to_string(),
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<PS> Named for TransitionNoveltyFeedback<PS>
+where
+    PS: Debug + Clone + Eq + Hash + Serialize + for<'a> Deserialize<'a>,
+{
+    fn name(&self) -> &str {
+        "TransitionNoveltyFeedback"
+    }
+}
+
+impl<PS> HasObserverName for TransitionNoveltyFeedback<PS>
+where
+    PS: Debug + Clone + Eq + Hash + Serialize + for<'a> Deserialize<'a>,
+{
+    fn observer_name(&self) -> &str {
+        &self.observer_name
+    }
+}
+
+impl<I, S, PS> Feedback<I, S> for TransitionNoveltyFeedback<PS>
+where
+    I: Input,
+    S: HasClientPerfMonitor + HasMetadata,
+    PS: Debug + Clone + Eq + Hash + Serialize + for<'a> Deserialize<'a>,
+{
+    fn is_interesting<EM, OT>(&mut self, state: &mut S, _mgr: &mut EM, _input: &I, observers: &OT, _exit_kind: &ExitKind) -> Result<bool, Error>
+    where
+        EM: EventFirer<I>,
+        OT: ObserversTuple<I, S>,
+    {
+        let state_observer = observers.match_name::<StateObserver<PS>>(&self.observer_name).unwrap();
+        let positions = state_observer.new_transition_positions();
+
+        if !positions.is_empty() {
+            if !state.has_metadata::<TransitionNoveltyMetadata>() {
+                state.add_metadata(TransitionNoveltyMetadata::default());
+            }
+
+            state.metadata_mut().get_mut::<TransitionNoveltyMetadata>().unwrap().record(positions);
+        }
+
+        Ok(false)
+    }
+}
+
+/// The ids of the states that the target went through while processing an input,
+/// attached to testcases by the [`StatePathFeedback`]
+///
+/// The ids refer to the exact state-graph of the [`StateObserver`], see [`StateObserver::exact_path()`],
+/// so two testcases with the same path exercised the same states in the same order.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StatePathMetadata {
+    /// The state ids in the order they were recorded
+    pub path: Vec<u32>,
+}
+
+impl_serdeany!(StatePathMetadata);
+
+impl StatePathMetadata {
+    /// Returns the states of the path, looked up in the exact state-graph of `observer`,
+    /// e.g. to group crashes by the state in which the target crashed.
+    /// Ids that the observer doesn't know, e.g. after the state-graph was reset, are skipped.
+    pub fn states<PS>(&self, observer: &StateObserver<PS>) -> Vec<PS>
+    where
+        PS: Debug + Clone + Eq + Hash + Serialize + for<'a> Deserialize<'a>,
+    {
+        self.path.iter().filter_map(|id| observer.exact_state(*id)).cloned().collect()
+    }
+
+    /// Returns the id of the last state of the path, i.e. the state the target ended up in
+    pub fn last_state(&self) -> Option<u32> {
+        self.path.last().copied()
+    }
+}
+
+/// Attaches the [exact path](StateObserver::exact_path) of the last run to every new testcase as [`StatePathMetadata`],
+/// which the [`PacketSuffixSpliceMutator`](crate::PacketSuffixSpliceMutator) needs to find matching suffixes.
+/// As part of the objectives it records the states that led to each solution, which helps to deduplicate crashes.
+///
+/// Never considers an input interesting, so combine it with the other feedbacks.
+///
+/// # Example
+/// ```
+/// let mut feedback = feedback_or!(StateFeedback::new(&state_observer), StatePathFeedback::new(&state_observer));
+/// let mut objective = feedback_or!(CrashFeedback::new(), StatePathFeedback::new(&state_observer));
+/// ```
+#[derive(Debug)]
+pub struct StatePathFeedback<PS>
+where
+    PS: Debug + Clone + Eq + Hash + Serialize + for<'a> Deserialize<'a>,
+{
+    observer_name: String,
+    path: Option<Vec<u32>>,
+    phantom: PhantomData<PS>,
+}
+
+impl<PS> StatePathFeedback<PS>
+where
+    PS: Debug + Clone + Eq + Hash + Serialize + for<'a> Deserialize<'a>,
+{
+    /// Create a new StatePathFeedback that reads the path from `observer`
+    pub fn new(observer: &StateObserver<PS>) -> Self {
+        Self {
+            observer_name: observer.name().to_string(),
+            path: None,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<PS> Named for StatePathFeedback<PS>
+where
+    PS: Debug + Clone + Eq + Hash + Serialize + for<'a> Deserialize<'a>,
+{
+    fn name(&self) -> &str {
+        "StatePathFeedback"
+    }
+}
+
+impl<PS> HasObserverName for StatePathFeedback<PS>
+where
+    PS: Debug + Clone + Eq + Hash + Serialize + for<'a> Deserialize<'a>,
+{
+    fn observer_name(&self) -> &str {
+        &self.observer_name
+    }
+}
+
+impl<I, S, PS> Feedback<I, S> for StatePathFeedback<PS>
+where
+    I: Input,
+    S: HasClientPerfMonitor,
+    PS: Debug + Clone + Eq + Hash + Serialize + for<'a> Deserialize<'a>,
+{
+    fn is_interesting<EM, OT>(&mut self, _state: &mut S, _mgr: &mut EM, _input: &I, observers: &OT, _exit_kind: &ExitKind) -> Result<bool, Error>
+    where
+        EM: EventFirer<I>,
+        OT: ObserversTuple<I, S>,
+    {
+        let state_observer = observers.match_name::<StateObserver<PS>>(&self.observer_name).unwrap();
+        self.path = Some(state_observer.exact_path().to_vec());
+        Ok(false)
+    }
+
+    fn append_metadata(&mut self, _state: &mut S, testcase: &mut Testcase<I>) -> Result<(), Error> {
+        if let Some(path) = self.path.take() {
+            testcase.add_metadata(StatePathMetadata {
+                path,
+            });
+        }
+
+        Ok(())
+    }
+
+    fn discard_metadata(&mut self, _state: &mut S, _input: &I) -> Result<(), Error> {
+        self.path = None;
+        Ok(())
+    }
+}
+
+/// Metadata that [`HangFeedback`] attaches to every hang
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HangMetadata {
+    /// The last state the target reported before it stopped responding, formatted with `Debug`
+    pub last_state: Option<String>,
+    /// All states the target went through, formatted with `Debug`
+    pub states: Vec<String>,
+    /// How often the hang was reproduced
+    pub reproductions: usize,
+}
+
+impl_serdeany!(HangMetadata);
+
+/// An objective feedback for hangs of network targets.
+///
+/// Over the network a timeout can just as well be caused by jitter or a slow connection.
+/// Thus, an [`ExitKind::Timeout`](libafl::executors::ExitKind::Timeout) only counts as a hang
+/// if the input times out again in every one of `retries` re-executions and
+/// the target got stuck in the same state every time.
+/// The re-executions happen on a separate executor that talks to the same target and
+/// has its own [`StateObserver`] with the same name.
+///
+/// The last state the target responded with is stored in the [`HangMetadata`] of the solution.
+///
+/// # Example
+/// ```
+/// let retry_executor = NetworkExecutor::new(tuple_list!(StateObserver::<u32>::new("state")), addr, "state", ftp::status_code).with_hang_detection(3);
+/// let mut objective = feedback_or!(CrashFeedback::new(), HangFeedback::new(&state_observer, retry_executor, 2));
+/// ```
+pub struct HangFeedback<E, OT, PS>
+where
+    PS: Debug + Clone + Eq + Hash + Serialize + for<'a> Deserialize<'a>,
+{
+    observer_name: String,
+    executor: E,
+    retries: usize,
+    metadata: Option<HangMetadata>,
+    phantom: PhantomData<(OT, PS)>,
+}
+
+impl<E, OT, PS> HangFeedback<E, OT, PS>
+where
+    PS: Debug + Clone + Eq + Hash + Serialize + for<'a> Deserialize<'a>,
+{
+    /// Create a new HangFeedback
+    ///
+    /// # Arguments
+    /// - `observer`: the [`StateObserver`] of the main executor
+    /// - `executor`: the executor for re-executions
+    /// - `retries`: how often a timeout must be reproduced
+    pub fn new(observer: &StateObserver<PS>, executor: E, retries: usize) -> Self {
+        Self {
+            observer_name: observer.name().to_string(),
+            executor,
+            retries,
+            metadata: None,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<E, OT, PS> Debug for HangFeedback<E, OT, PS>
+where
+    PS: Debug + Clone + Eq + Hash + Serialize + for<'a> Deserialize<'a>,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        f.debug_struct("HangFeedback").field("observer_name", &self.observer_name).field("retries", &self.retries).finish()
+    }
+}
+
+impl<E, OT, PS> Named for HangFeedback<E, OT, PS>
+where
+    PS: Debug + Clone + Eq + Hash + Serialize + for<'a> Deserialize<'a>,
+{
+    fn name(&self) -> &str {
+        "HangFeedback"
+    }
+}
+
+impl<E, OT, PS> HasObserverName for HangFeedback<E, OT, PS>
+where
+    PS: Debug + Clone + Eq + Hash + Serialize + for<'a> Deserialize<'a>,
+{
+    fn observer_name(&self) -> &str {
+        &self.observer_name
+    }
+}
+
+impl<I, S, E, OT, PS> Feedback<I, S> for HangFeedback<E, OT, PS>
+where
+    I: Input,
+    S: HasClientPerfMonitor,
+    E: Executor<NopEventManager, I, S, ()> + HasObservers<I, OT, S>,
+    OT: ObserversTuple<I, S>,
+    PS: Debug + Clone + Eq + Hash + Serialize + for<'a> Deserialize<'a>,
+{
+    fn is_interesting<EM, OT2>(&mut self, state: &mut S, _mgr: &mut EM, input: &I, observers: &OT2, exit_kind: &ExitKind) -> Result<bool, Error>
+    where
+        EM: EventFirer<I>,
+        OT2: ObserversTuple<I, S>,
+    {
+        self.metadata = None;
+
+        if *exit_kind != ExitKind::Timeout {
+            return Ok(false);
+        }
+
+        let missing = || Error::key_not_found(format!("No StateObserver named \"{}\" with the given state type", self.observer_name));
+        let states = observers.match_name::<StateObserver<PS>>(&self.observer_name).ok_or_else(missing)?.path_states();
+
+        for _ in 0..self.retries {
+            let exit_kind = execute_once(&mut (), state, &mut self.executor, &mut NopEventManager {}, input)?;
+            let observer = self.executor.observers().match_name::<StateObserver<PS>>(&self.observer_name).ok_or_else(missing)?;
+
+            if exit_kind != ExitKind::Timeout || observer.path_states().last() != states.last() {
+                println!("[butterfly] Timeout did not reproduce, ignoring it");
+                return Ok(false);
+            }
+        }
+
+        self.metadata = Some(HangMetadata {
+            last_state: states.last().map(|state| format!("{:?}", state)),
+            states: states.iter().map(|state| format!("{:?}", state)).collect(),
+            reproductions: self.retries,
+        });
+        Ok(true)
+    }
+
+    fn append_metadata(&mut self, _state: &mut S, testcase: &mut Testcase<I>) -> Result<(), Error> {
+        if let Some(metadata) = self.metadata.take() {
+            testcase.add_metadata(metadata);
+        }
+
+        Ok(())
+    }
+
+    fn discard_metadata(&mut self, _state: &mut S, _input: &I) -> Result<(), Error> {
+        self.metadata = None;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libafl::{
+        bolts::{rands::StdRand, tuples::tuple_list},
+        corpus::InMemoryCorpus,
+        inputs::{BytesInput, HasBytesVec},
+        observers::Observer,
+        state::StdState,
+    };
+
+    /// Times out on inputs that start with `hang`, but only on every `flakiness`-th execution
+    #[derive(Debug)]
+    struct HangingExecutor {
+        observers: (StateObserver<u32>, ()),
+        flakiness: usize,
+        executions: usize,
+    }
+
+    impl<EM, S, Z> Executor<EM, BytesInput, S, Z> for HangingExecutor {
+        fn run_target(&mut self, _fuzzer: &mut Z, _state: &mut S, _mgr: &mut EM, input: &BytesInput) -> Result<ExitKind, Error> {
+            self.executions += 1;
+            self.observers.0.record(&1);
+
+            if input.bytes().starts_with(b"hang") && self.executions.checked_rem(self.flakiness) == Some(0) {
+                Ok(ExitKind::Timeout)
+            } else {
+                self.observers.0.record(&2);
+                Ok(ExitKind::Ok)
+            }
+        }
+    }
+
+    impl<S> HasObservers<BytesInput, (StateObserver<u32>, ()), S> for HangingExecutor {
+        fn observers(&self) -> &(StateObserver<u32>, ()) {
+            &self.observers
+        }
+
+        fn observers_mut(&mut self) -> &mut (StateObserver<u32>, ()) {
+            &mut self.observers
+        }
+    }
+
+    #[test]
+    fn test_growth_limit() {
+        let observer = StateObserver::<u32>::new("state");
+        let mut feedback = StateFeedback::new(&observer).with_growth_limit(2, 3);
+
+        assert!(!feedback.update_growth(1));
+        assert!(!feedback.update_growth(2));
+        assert!(feedback.update_growth(5));
+        assert!(feedback.is_throttled());
+
+        assert!(!feedback.update_growth(6));
+        assert!(!feedback.update_growth(6));
+        assert!(feedback.update_growth(6));
+        assert!(!feedback.is_throttled());
+    }
+
+    #[test]
+    fn test_loop_limit() {
+        let mut state = StdState::new(StdRand::with_seed(0), InMemoryCorpus::<BytesInput>::new(), InMemoryCorpus::<BytesInput>::new(), &mut (), &mut ()).unwrap();
+        let mut mgr = NopEventManager {};
+        let input = BytesInput::new(b"NOOP".to_vec());
+        let mut observers = tuple_list!(StateObserver::<u32>::new("state").with_ngrams(4));
+        let mut feedback = StateFeedback::new(&observers.0);
+        let mut limited = StateFeedback::new(&observers.0).with_loop_limit(0.5);
+
+        let mut run = |observers: &mut (StateObserver<u32>, ()), states: &[u32]| {
+            Observer::<BytesInput, _>::pre_exec(&mut observers.0, &mut state, &input).unwrap();
+            states.iter().for_each(|s| observers.0.record(s));
+            let interesting = feedback.is_interesting(&mut state, &mut mgr, &input, observers, &ExitKind::Ok).unwrap();
+            (interesting, limited.is_interesting(&mut state, &mut mgr, &input, observers, &ExitKind::Ok).unwrap())
+        };
+
+        assert_eq!(run(&mut observers, &[1, 2, 3]), (true, true));
+        assert_eq!(run(&mut observers, &[1, 2, 1, 2, 1, 2, 1, 2]), (true, true));
+
+        // Only new n-grams from a loop
+        assert_eq!(run(&mut observers, &[2, 1, 2, 1, 2, 1, 2, 1, 2, 3]), (true, false));
+    }
+
+    #[test]
+    fn test_hang_feedback() {
+        let mut state = StdState::new(StdRand::with_seed(0), InMemoryCorpus::<BytesInput>::new(), InMemoryCorpus::<BytesInput>::new(), &mut (), &mut ()).unwrap();
+        let mut mgr = NopEventManager {};
+        let mut observer = StateObserver::<u32>::new("state");
+        observer.record(&1);
+        let observers = tuple_list!(observer);
+        let input = BytesInput::new(b"hang".to_vec());
+
+        let executor = HangingExecutor {
+            observers: tuple_list!(StateObserver::<u32>::new("state")),
+            flakiness: 1,
+            executions: 0,
+        };
+        let mut feedback = HangFeedback::new(&observers.0, executor, 2);
+        assert!(feedback.is_interesting(&mut state, &mut mgr, &input, &observers, &ExitKind::Timeout).unwrap());
+        assert!(!feedback.is_interesting(&mut state, &mut mgr, &input, &observers, &ExitKind::Ok).unwrap());
+
+        let flaky_executor = HangingExecutor {
+            observers: tuple_list!(StateObserver::<u32>::new("state")),
+            flakiness: 2,
+            executions: 0,
+        };
+        let mut feedback = HangFeedback::new(&observers.0, flaky_executor, 2);
+        assert!(!feedback.is_interesting(&mut state, &mut mgr, &input, &observers, &ExitKind::Timeout).unwrap());
+
+        // The executor of the reproduction must have the observer, too
+        let other_executor = HangingExecutor {
+            observers: tuple_list!(StateObserver::<u32>::new("other")),
+            flakiness: 1,
+            executions: 0,
+        };
+        let mut feedback = HangFeedback::new(&observers.0, other_executor, 2);
+        assert!(feedback.is_interesting(&mut state, &mut mgr, &input, &observers, &ExitKind::Timeout).is_err());
+    }
+
+    #[test]
+    fn test_state_path_feedback() {
+        let mut state = StdState::new(StdRand::with_seed(0), InMemoryCorpus::<BytesInput>::new(), InMemoryCorpus::<BytesInput>::new(), &mut (), &mut ()).unwrap();
+        let mut mgr = NopEventManager {};
+        let mut observer = StateObserver::<u32>::new("state").with_abstraction_level(|code| code / 100);
+        observer.set_abstraction_level(1);
+        let mut feedback = StatePathFeedback::new(&observer);
+        let input = BytesInput::new(b"USER a".to_vec());
+
+        for code in [220, 230, 250, 530] {
+            observer.record(&code);
+        }
+
+        let observers = tuple_list!(observer);
+        assert!(!feedback.is_interesting(&mut state, &mut mgr, &input, &observers, &ExitKind::Ok).unwrap());
+        let mut testcase = Testcase::<BytesInput>::new(input.clone());
+        feedback.append_metadata(&mut state, &mut testcase).unwrap();
+
+        // The exact states, not the abstract ones
+        let metadata = testcase.metadata().get::<StatePathMetadata>().unwrap();
+        assert_eq!(metadata.path, vec![0, 1, 2, 3]);
+        assert_eq!(metadata.states(&observers.0), vec![220, 230, 250, 530]);
+        assert_eq!(metadata.last_state(), Some(3));
+
+        // Inputs that are not added don't get the path of an earlier run
+        feedback.is_interesting(&mut state, &mut mgr, &input, &observers, &ExitKind::Ok).unwrap();
+        feedback.discard_metadata(&mut state, &input).unwrap();
+        let mut testcase = Testcase::<BytesInput>::new(input);
+        feedback.append_metadata(&mut state, &mut testcase).unwrap();
+        assert!(!testcase.has_metadata::<StatePathMetadata>());
+    }
+}
@@ -0,0 +1,375 @@
+//! A C API for harnesses that are written in C or C++.
+//!
+//! Existing harnesses and simulators often already know how to talk to the target
+//! and what state it is in. Instead of rewriting their I/O logic in Rust, they can keep it
+//! and let a butterfly fuzzer drive them via callbacks:
+//! - The fuzzer uses an [`FfiExecutor`] that hands every packet of an input to a C callback.
+//! - The callback sends the packet with its own I/O and tells the [`StateObserver`] which state
+//!   the target is in with [`butterfly_observer_record()`].
+//! - If the harness restarts the target in the middle of an input, it reports that with
+//!   [`butterfly_observer_target_restart()`] so that no transition across the restart ends up in the state-graph.
+//!
+//! States are 64-bit integers on the C side, so the observer must be a `StateObserver<u64>`.
+//! Observers can also be created and inspected from C, e.g. to test a harness on its own.
+//! The declarations for C are in `include/butterfly.h`.
+//!
+//! To link a harness against butterfly, build a static library from a crate that
+//! depends on butterfly with the feature `ffi` and contains the fuzzer.
+//!
+//! # Example
+//! ```
+//! // C side
+//! int send_packet(void* user_data, ButterflyObserver* observer, const uint8_t* data, size_t len) {
+//!     struct session* session = user_data;
+//!     uint64_t state;
+//!
+//!     if (session_send(session, data, len, &state) < 0) {
+//!         return BUTTERFLY_CRASH;
+//!     }
+//!
+//!     butterfly_observer_record(observer, state);
+//!     return BUTTERFLY_OK;
+//! }
+//! ```
+//! ```
+//! // Rust side
+//! extern "C" {
+//!     fn send_packet(user_data: *mut c_void, observer: *mut StateObserver<u64>, data: *const u8, len: usize) -> c_int;
+//! }
+//!
+//! let mut executor = FfiExecutor::new(tuple_list!(StateObserver::<u64>::new("state")), "state", send_packet, session);
+//! ```
+
+use crate::{
+    executor::HasPayload,
+    input::HasPackets,
+    observer::{HasTargetRestart, StateObserver},
+};
+use libafl::{
+    executors::{Executor, ExitKind, HasObservers},
+    inputs::Input,
+    observers::ObserversTuple,
+    Error,
+};
+use std::ffi::{c_char, c_int, c_void, CStr, CString};
+use std::fmt::{Debug, Formatter};
+use std::marker::PhantomData;
+
+/// Return value of a callback: the packet was processed normally
+pub const BUTTERFLY_OK: c_int = 0;
+/// Return value of a callback: the target crashed, the execution is reported as [`ExitKind::Crash`]
+pub const BUTTERFLY_CRASH: c_int = 1;
+/// Return value of a callback: the target hangs, the execution is reported as [`ExitKind::Timeout`]
+pub const BUTTERFLY_TIMEOUT: c_int = 2;
+/// Return value of a callback: stop the execution early without reporting anything
+pub const BUTTERFLY_ABORT: c_int = 3;
+
+/// Called before every execution, e.g. to reset the target or to open a new session
+pub type ResetCallback = unsafe extern "C" fn(user_data: *mut c_void) -> c_int;
+
+/// Called for every packet of an input with its payload.
+/// The state the target is in afterwards should be recorded in `observer`.
+pub type PacketCallback = unsafe extern "C" fn(user_data: *mut c_void, observer: *mut StateObserver<u64>, data: *const u8, len: usize) -> c_int;
+
+/// Translate the return value of a callback. `None` means continue.
+fn exit_kind(ret: c_int) -> Option<ExitKind> {
+    match ret {
+        BUTTERFLY_OK => None,
+        BUTTERFLY_TIMEOUT => Some(ExitKind::Timeout),
+        BUTTERFLY_ABORT => Some(ExitKind::Ok),
+        _ => Some(ExitKind::Crash),
+    }
+}
+
+/// An executor that hands the packets of an input to C callbacks.
+///
+/// Before every execution the optional [`ResetCallback`] is called, then the [`PacketCallback`] for
+/// every packet in order, together with the `StateObserver<u64>` with the name `observer_name`.
+/// The callbacks return one of [`BUTTERFLY_OK`], [`BUTTERFLY_CRASH`], [`BUTTERFLY_TIMEOUT`] or [`BUTTERFLY_ABORT`].
+/// Anything else counts as a crash.
+///
+/// `user_data` is passed to the callbacks unchanged. It must stay valid as long as the executor exists.
+///
+/// __Only available with feature__: `ffi`
+pub struct FfiExecutor<I, P, OT, S>
+where
+    OT: ObserversTuple<I, S>,
+    I: Input,
+{
+    observers: OT,
+    observer_name: String,
+    packet: PacketCallback,
+    reset: Option<ResetCallback>,
+    user_data: *mut c_void,
+    phantom: PhantomData<(I, P, S)>,
+}
+
+impl<I, P, OT, S> FfiExecutor<I, P, OT, S>
+where
+    OT: ObserversTuple<I, S>,
+    I: Input,
+{
+    /// Create a new FfiExecutor that calls `packet` for every packet
+    pub fn new(observers: OT, observer_name: &str, packet: PacketCallback, user_data: *mut c_void) -> Self {
+        Self {
+            observers,
+            observer_name: observer_name.to_string(),
+            packet,
+            reset: None,
+            user_data,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Call `reset` before every execution
+    pub fn with_reset(mut self, reset: ResetCallback) -> Self {
+        self.reset = Some(reset);
+        self
+    }
+}
+
+impl<I, P, OT, S> Debug for FfiExecutor<I, P, OT, S>
+where
+    OT: ObserversTuple<I, S>,
+    I: Input,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
+        f.debug_struct("FfiExecutor").field("observer_name", &self.observer_name).field("user_data", &self.user_data).finish()
+    }
+}
+
+impl<I, P, OT, S> HasObservers<I, OT, S> for FfiExecutor<I, P, OT, S>
+where
+    OT: ObserversTuple<I, S>,
+    I: Input,
+{
+    fn observers(&self) -> &OT {
+        &self.observers
+    }
+
+    fn observers_mut(&mut self) -> &mut OT {
+        &mut self.observers
+    }
+}
+
+impl<EM, I, P, OT, S, Z> Executor<EM, I, S, Z> for FfiExecutor<I, P, OT, S>
+where
+    OT: ObserversTuple<I, S>,
+    I: Input + HasPackets<P>,
+    P: HasPayload,
+{
+    fn run_target(&mut self, _fuzzer: &mut Z, _state: &mut S, _mgr: &mut EM, input: &I) -> Result<ExitKind, Error> {
+        if let Some(reset) = self.reset {
+            // SAFETY: the caller of new() guarantees that the callbacks can handle user_data
+            if let Some(exit_kind) = exit_kind(unsafe { reset(self.user_data) }) {
+                return Ok(exit_kind);
+            }
+        }
+
+        let observer_name = &self.observer_name;
+        let observer: *mut StateObserver<u64> = self.observers.match_name_mut::<StateObserver<u64>>(observer_name).ok_or_else(|| Error::key_not_found(format!("No StateObserver<u64> named \"{}\"", observer_name)))?;
+
+        for packet in input.packets() {
+            let payload = packet.payload();
+
+            // SAFETY: the observer outlives the call and the payload is valid for `len` bytes
+            if let Some(exit_kind) = exit_kind(unsafe { (self.packet)(self.user_data, observer, payload.as_ptr(), payload.len()) }) {
+                return Ok(exit_kind);
+            }
+        }
+
+        Ok(ExitKind::Ok)
+    }
+}
+
+/// Create a new `StateObserver<u64>` with the given name.
+/// Returns NULL if `name` is NULL or not valid UTF-8.
+///
+/// # Safety
+/// `name` must be NULL or a valid NUL-terminated string.
+/// The observer must be released with [`butterfly_observer_free()`].
+#[no_mangle]
+pub unsafe extern "C" fn butterfly_observer_new(name: *const c_char) -> *mut StateObserver<u64> {
+    if name.is_null() {
+        return std::ptr::null_mut();
+    }
+
+    match CStr::from_ptr(name).to_str() {
+        Ok(name) => Box::into_raw(Box::new(StateObserver::new(name))),
+        Err(_) => std::ptr::null_mut(),
+    }
+}
+
+/// Release an observer that was created with [`butterfly_observer_new()`]. NULL is ignored.
+///
+/// # Safety
+/// `observer` must be NULL or have been returned by [`butterfly_observer_new()`] and must not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn butterfly_observer_free(observer: *mut StateObserver<u64>) {
+    if !observer.is_null() {
+        drop(Box::from_raw(observer));
+    }
+}
+
+/// Tell the observer that the target has entered `state`, see [`StateObserver::record()`]
+///
+/// # Safety
+/// `observer` must be NULL or a valid observer.
+#[no_mangle]
+pub unsafe extern "C" fn butterfly_observer_record(observer: *mut StateObserver<u64>, state: u64) {
+    if let Some(observer) = observer.as_mut() {
+        observer.record(&state);
+    }
+}
+
+/// Tell the observer that the target has entered `state` after it processed the packet with the index `packet_idx`,
+/// see [`StateObserver::record_with_context()`]
+///
+/// # Safety
+/// `observer` must be NULL or a valid observer.
+#[no_mangle]
+pub unsafe extern "C" fn butterfly_observer_record_with_context(observer: *mut StateObserver<u64>, state: u64, packet_idx: usize) {
+    if let Some(observer) = observer.as_mut() {
+        observer.record_with_context(&state, packet_idx);
+    }
+}
+
+/// Label the next transition with the input that the target is about to process,
+/// see [`StateObserver::record_input()`]
+///
+/// # Safety
+/// `observer` must be NULL or a valid observer and `label` must be NULL or a valid NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn butterfly_observer_record_input(observer: *mut StateObserver<u64>, label: *const c_char) {
+    if let (Some(observer), false) = (observer.as_mut(), label.is_null()) {
+        observer.record_input(&CStr::from_ptr(label).to_string_lossy());
+    }
+}
+
+/// Tell the observer that the target process has been restarted, see [`HasTargetRestart`]
+///
+/// # Safety
+/// `observer` must be NULL or a valid observer.
+#[no_mangle]
+pub unsafe extern "C" fn butterfly_observer_target_restart(observer: *mut StateObserver<u64>) {
+    if let Some(observer) = observer.as_mut() {
+        observer.on_target_restart();
+    }
+}
+
+/// Store the number of vertices and edges of the state-graph in `nodes` and `edges`
+///
+/// # Safety
+/// `observer` must be NULL or a valid observer, `nodes` and `edges` must be NULL or valid pointers.
+#[no_mangle]
+pub unsafe extern "C" fn butterfly_observer_info(observer: *const StateObserver<u64>, nodes: *mut usize, edges: *mut usize) {
+    let (num_nodes, num_edges) = observer.as_ref().map(|observer| observer.info()).unwrap_or_default();
+
+    if let Some(nodes) = nodes.as_mut() {
+        *nodes = num_nodes;
+    }
+
+    if let Some(edges) = edges.as_mut() {
+        *edges = num_edges;
+    }
+}
+
+/// Returns the name of the observer and the state-graph in DOT format, see [`StateObserver::get_statemachine()`].
+/// Returns NULL if `observer` is NULL.
+///
+/// # Safety
+/// `observer` must be NULL or a valid observer.
+/// The string must be released with [`butterfly_string_free()`].
+#[no_mangle]
+pub unsafe extern "C" fn butterfly_observer_statemachine(observer: *const StateObserver<u64>) -> *mut c_char {
+    match observer.as_ref() {
+        Some(observer) => CString::new(observer.get_statemachine()).map(CString::into_raw).unwrap_or(std::ptr::null_mut()),
+        None => std::ptr::null_mut(),
+    }
+}
+
+/// Release a string that butterfly returned. NULL is ignored.
+///
+/// # Safety
+/// `string` must be NULL or have been returned by butterfly and must not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn butterfly_string_free(string: *mut c_char) {
+    if !string.is_null() {
+        drop(CString::from_raw(string));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::TestInput;
+    use libafl::{
+        bolts::tuples::{tuple_list, MatchName, Named},
+        inputs::BytesInput,
+    };
+    use serde::{Deserialize, Serialize};
+
+    /// Records the first byte of every packet as the state, crashes on 0xFF
+    unsafe extern "C" fn send_packet(user_data: *mut c_void, observer: *mut StateObserver<u64>, data: *const u8, len: usize) -> c_int {
+        *(user_data as *mut usize) += 1;
+        let data = std::slice::from_raw_parts(data, len);
+
+        match data.first() {
+            Some(0xFF) => BUTTERFLY_CRASH,
+            Some(byte) => {
+                butterfly_observer_record(observer, *byte as u64);
+                BUTTERFLY_OK
+            },
+            None => BUTTERFLY_OK,
+        }
+    }
+
+    unsafe extern "C" fn reset(user_data: *mut c_void) -> c_int {
+        *(user_data as *mut usize) = 0;
+        BUTTERFLY_OK
+    }
+
+    #[test]
+    fn test_ffi_executor() {
+        let mut packets_seen = 0usize;
+        let mut executor = FfiExecutor::<TestInput, BytesInput, _, ()>::new(tuple_list!(StateObserver::<u64>::new("state")), "state", send_packet, &mut packets_seen as *mut usize as *mut c_void).with_reset(reset);
+        let input = TestInput {
+            packets: vec![BytesInput::new(vec![1]), BytesInput::new(vec![2]), BytesInput::new(vec![0xFF]), BytesInput::new(vec![3])],
+        };
+
+        let exit_kind = executor.run_target(&mut (), &mut (), &mut (), &input).unwrap();
+        assert_eq!(exit_kind, ExitKind::Crash);
+        assert_eq!(packets_seen, 3);
+
+        let observer: &StateObserver<u64> = executor.observers().match_name("state").unwrap();
+        assert_eq!(observer.info(), (2, 1));
+    }
+
+    #[test]
+    fn test_ffi_observer() {
+        let name = CString::new("state").unwrap();
+        let label = CString::new("USER").unwrap();
+
+        unsafe {
+            let observer = butterfly_observer_new(name.as_ptr());
+            assert_eq!((*observer).name(), "state");
+
+            butterfly_observer_record(observer, 220);
+            butterfly_observer_record_input(observer, label.as_ptr());
+            butterfly_observer_record(observer, 331);
+            butterfly_observer_target_restart(observer);
+            butterfly_observer_record(observer, 220);
+
+            let (mut nodes, mut edges) = (0, 0);
+            butterfly_observer_info(observer, &mut nodes, &mut edges);
+            assert_eq!((nodes, edges), (2, 1));
+
+            let dot = butterfly_observer_statemachine(observer);
+            assert_eq!(CStr::from_ptr(dot).to_str().unwrap(), "digraph IMPLEMENTED_STATE_MACHINE {\"0\"[label=\"220\"];\"1\"[label=\"331\"];\"0\"->\"1\";}");
+            butterfly_string_free(dot);
+            butterfly_observer_free(observer);
+
+            assert!(butterfly_observer_new(std::ptr::null()).is_null());
+        }
+    }
+}
@@ -0,0 +1,164 @@
+//! Building blocks for [`HasPostMutationFixup`](crate::HasPostMutationFixup) implementations.
+//!
+//! Most binary protocols protect their messages with length fields and checksums
+//! that a mutation invalidates immediately. The helpers in this module
+//! compute the common checksums and patch integers into packets at fixed offsets,
+//! so that a fixup usually boils down to a few lines:
+//!
+//! ```
+//! // | length (2 bytes, big-endian) | body ... | CRC-16/MODBUS (2 bytes, little-endian) |
+//! impl HasPostMutationFixup for RtuPacket {
+//!     fn fixup(&mut self) {
+//!         let bytes = self.data.bytes_mut();
+//!
+//!         if bytes.len() < 4 {
+//!             bytes.resize(4, 0);
+//!         }
+//!
+//!         let end = bytes.len() - 2;
+//!         fixups::set_length(&mut bytes[..end], 0, 2, Endianness::Big, 2);
+//!         let crc = fixups::crc16_modbus(&bytes[..end]);
+//!         fixups::write_integer(bytes, end, 2, Endianness::Little, crc as u64);
+//!     }
+//! }
+//! ```
+
+use crate::Endianness;
+
+/// CRC-16/CCITT-FALSE: polynomial `0x1021`, initial value `0xFFFF`, no reflection.
+/// Used by e.g. XMODEM-like framings and many embedded protocols.
+pub fn crc16_ccitt(data: &[u8]) -> u16 {
+    let mut crc = 0xFFFFu16;
+
+    for byte in data {
+        crc ^= (*byte as u16) << 8;
+
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 { (crc << 1) ^ 0x1021 } else { crc << 1 };
+        }
+    }
+
+    crc
+}
+
+/// CRC-16/MODBUS: reflected polynomial `0xA001`, initial value `0xFFFF`.
+/// Used by Modbus RTU, where it is transmitted in little-endian byte order.
+pub fn crc16_modbus(data: &[u8]) -> u16 {
+    let mut crc = 0xFFFFu16;
+
+    for byte in data {
+        crc ^= *byte as u16;
+
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xA001 } else { crc >> 1 };
+        }
+    }
+
+    crc
+}
+
+/// CRC-32 as used by Ethernet, zlib and PNG: reflected polynomial `0xEDB88320`,
+/// initial value and final XOR `0xFFFFFFFF`.
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFFFFFFu32;
+
+    for byte in data {
+        crc ^= *byte as u32;
+
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB88320 } else { crc >> 1 };
+        }
+    }
+
+    !crc
+}
+
+/// The Internet checksum of RFC 1071 that IPv4, ICMP, TCP and UDP use:
+/// the ones' complement of the ones' complement sum of all 16-bit big-endian words.
+/// An odd number of bytes is padded with a zero byte.
+///
+/// Make sure that the checksum field itself is zero when computing the checksum.
+pub fn internet_checksum(data: &[u8]) -> u16 {
+    let mut sum = 0u32;
+
+    for word in data.chunks(2) {
+        let word = match word {
+            [high, low] => u16::from_be_bytes([*high, *low]),
+            [high] => u16::from_be_bytes([*high, 0]),
+            _ => unreachable!(),
+        };
+        sum += word as u32;
+        sum = (sum & 0xFFFF) + (sum >> 16);
+    }
+
+    !(sum as u16)
+}
+
+/// Write the lowest `width` bytes of `value` at `offset` into `bytes`.
+///
+/// Returns `false` and leaves `bytes` untouched if the field doesn't fit into `bytes`
+/// or is wider than 8 bytes.
+pub fn write_integer(bytes: &mut [u8], offset: usize, width: usize, endianness: Endianness, value: u64) -> bool {
+    if width > 8 || offset.saturating_add(width) > bytes.len() {
+        return false;
+    }
+
+    bytes[offset..offset + width].copy_from_slice(&endianness.encode(value, width));
+    true
+}
+
+/// Set the length field of `width` bytes at `offset` to the number of bytes
+/// that follow position `from`, which is usually the end of the length field or the end of a fixed-size header.
+/// Lengths that don't fit into the field get truncated.
+///
+/// Returns `false` and leaves `bytes` untouched if the field doesn't fit into `bytes`.
+///
+/// # Example
+/// ```
+/// // | type (1 byte) | length (4 bytes, little-endian) | value ... |
+/// fixups::set_length(bytes, 1, 4, Endianness::Little, 5);
+/// ```
+pub fn set_length(bytes: &mut [u8], offset: usize, width: usize, endianness: Endianness, from: usize) -> bool {
+    let len = bytes.len().saturating_sub(from) as u64;
+    write_integer(bytes, offset, width, endianness, len)
+}
+
+/// Make sure that `bytes` ends with `terminator`, e.g. `\r\n` for line-based text protocols.
+/// Returns whether the terminator had to be appended.
+pub fn ensure_terminator(bytes: &mut Vec<u8>, terminator: &[u8]) -> bool {
+    if bytes.ends_with(terminator) {
+        false
+    } else {
+        bytes.extend_from_slice(terminator);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_checksums() {
+        assert_eq!(crc16_ccitt(b"123456789"), 0x29B1);
+        assert_eq!(crc16_modbus(b"123456789"), 0x4B37);
+        assert_eq!(crc32(b"123456789"), 0xCBF43926);
+        assert_eq!(internet_checksum(&[0x00, 0x01, 0xF2, 0x03, 0xF4, 0xF5, 0xF6, 0xF7]), !0xDDF2);
+        assert_eq!(internet_checksum(&[0x45, 0x00, 0x00, 0x73, 0x00, 0x00, 0x40, 0x00, 0x40, 0x11, 0x00, 0x00, 0xC0, 0xA8, 0x00, 0x01, 0xC0, 0xA8, 0x00, 0xC7]), 0xB861);
+    }
+
+    #[test]
+    fn test_length_fields() {
+        let mut bytes = vec![0x01, 0, 0, 0, 0, b'a', b'b', b'c'];
+        assert!(set_length(&mut bytes, 1, 4, Endianness::Little, 5));
+        assert_eq!(&bytes[..5], &[0x01, 3, 0, 0, 0]);
+        assert!(set_length(&mut bytes, 1, 2, Endianness::Big, 0));
+        assert_eq!(&bytes[..5], &[0x01, 0, 8, 0, 0]);
+        assert!(!write_integer(&mut bytes, 7, 2, Endianness::Big, 0));
+
+        let mut line = b"NOOP".to_vec();
+        assert!(ensure_terminator(&mut line, b"\r\n"));
+        assert!(!ensure_terminator(&mut line, b"\r\n"));
+        assert_eq!(line, b"NOOP\r\n");
+    }
+}
@@ -0,0 +1,942 @@
+use crate::{
+    config::{ComponentConfig, HasConfig},
+    executor::HasPayload,
+    input::HasPackets,
+    mutators::{HasPacketGenerator, HasPostMutationFixup, PacketSelection, PacketSelector},
+};
+use libafl::{
+    bolts::{
+        rands::{Rand, StdRand},
+        tuples::Named,
+    },
+    impl_serdeany,
+    inputs::Input,
+    mutators::{MutationResult, Mutator},
+    state::{HasMetadata, HasRand},
+    Error,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+use std::marker::PhantomData;
+
+/// Rules nested deeper than this only expand to their simplest alternative
+const MAX_DEPTH: usize = 16;
+/// The largest `Bytes` field that gets generated, unless the minimum is larger
+const MAX_GENERATED_LEN: usize = 32;
+/// Upper bound on the number of ambiguous parses that are considered per rule
+const MAX_PARSES: usize = 16;
+
+/// Byte order of integer and length fields
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Endianness {
+    /// Most significant byte first
+    Big,
+    /// Least significant byte first
+    Little,
+}
+
+impl Endianness {
+    pub(crate) fn encode(self, value: u64, width: usize) -> Vec<u8> {
+        let bytes = value.to_be_bytes();
+        let mut ret = bytes[8 - width.min(8)..].to_vec();
+
+        if self == Endianness::Little {
+            ret.reverse();
+        }
+
+        ret
+    }
+
+    pub(crate) fn decode(self, bytes: &[u8]) -> u64 {
+        let mut ret = 0;
+
+        for i in 0..bytes.len() {
+            let byte = match self {
+                Endianness::Big => bytes[i],
+                Endianness::Little => bytes[bytes.len() - 1 - i],
+            };
+            ret = (ret << 8) | byte as u64;
+        }
+
+        ret
+    }
+}
+
+/// A field in an alternative of a [`Grammar`] rule
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Field {
+    /// Fixed bytes that never change
+    Literal(Vec<u8>),
+    /// Arbitrary bytes with a length between `min` and `max`
+    Bytes {
+        /// Minimum length
+        min: usize,
+        /// Maximum length
+        max: usize,
+    },
+    /// One of several byte strings
+    Enum(Vec<Vec<u8>>),
+    /// An unsigned integer of `width` bytes with a value between `min` and `max`
+    Integer {
+        /// Number of bytes, at most 8
+        width: usize,
+        /// Byte order
+        endianness: Endianness,
+        /// Smallest value
+        min: u64,
+        /// Largest value
+        max: u64,
+    },
+    /// The length in bytes of another field of the same alternative, given by its index.
+    /// It is computed when the packet gets serialized and never mutated.
+    Length {
+        /// Number of bytes, at most 8
+        width: usize,
+        /// Byte order
+        endianness: Endianness,
+        /// Index of the field whose length this is
+        of: usize,
+    },
+    /// Another rule of the grammar
+    Rule(String),
+    /// Between `min` and `max` expansions of another rule
+    Repeat {
+        /// Name of the rule
+        rule: String,
+        /// Minimum number of expansions
+        min: usize,
+        /// Maximum number of expansions
+        max: usize,
+    },
+}
+
+impl Field {
+    /// Shorthand for a [`Field::Literal`]
+    pub fn literal(bytes: &[u8]) -> Self {
+        Field::Literal(bytes.to_vec())
+    }
+
+    /// Shorthand for a [`Field::Rule`]
+    pub fn rule(name: &str) -> Self {
+        Field::Rule(name.to_string())
+    }
+
+    /// Whether the field expands to other rules
+    fn is_recursive(&self) -> bool {
+        matches!(self, Field::Rule(_) | Field::Repeat { .. })
+    }
+}
+
+/// A node in the parse tree of a [`GrammarPacket`]
+#[derive(Clone, Debug, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GrammarNode {
+    /// The bytes of a `Literal`, `Bytes`, `Enum` or `Integer` field
+    Terminal(Vec<u8>),
+    /// A `Length` field. Its value gets filled in during serialization.
+    Length {
+        /// Number of bytes
+        width: usize,
+        /// Byte order
+        endianness: Endianness,
+        /// Index of the sibling whose length this is
+        of: usize,
+    },
+    /// An expansion of a rule with one child per field of the chosen alternative
+    Rule {
+        /// Name of the rule
+        name: String,
+        /// Index of the alternative
+        alternative: usize,
+        /// One node per field
+        children: Vec<GrammarNode>,
+    },
+    /// The expansions of a `Repeat` field
+    Repeat(Vec<GrammarNode>),
+}
+
+impl GrammarNode {
+    /// Serialize the tree into bytes, computing all `Length` fields
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut ret = Vec::new();
+        self.write(&mut ret);
+        ret
+    }
+
+    fn write(&self, out: &mut Vec<u8>) {
+        match self {
+            GrammarNode::Terminal(bytes) => out.extend_from_slice(bytes),
+            GrammarNode::Length {
+                width,
+                ..
+            } => out.resize(out.len() + width, 0),
+            GrammarNode::Rule {
+                children,
+                ..
+            } => {
+                let mut parts: Vec<Vec<u8>> = children.iter().map(GrammarNode::to_bytes).collect();
+
+                for (idx, child) in children.iter().enumerate() {
+                    if let GrammarNode::Length {
+                        width,
+                        endianness,
+                        of,
+                    } = child
+                    {
+                        let len = parts.get(*of).map_or(0, Vec::len);
+                        parts[idx] = endianness.encode(len as u64, *width);
+                    }
+                }
+
+                for part in parts {
+                    out.extend(part);
+                }
+            },
+            GrammarNode::Repeat(items) => {
+                for item in items {
+                    item.write(out);
+                }
+            },
+        }
+    }
+
+    /// Get the children of a `Rule` or the expansions of a `Repeat` node
+    pub fn children(&self) -> &[GrammarNode] {
+        match self {
+            GrammarNode::Rule {
+                children,
+                ..
+            } => children,
+            GrammarNode::Repeat(items) => items,
+            _ => &[],
+        }
+    }
+
+    fn get_mut(&mut self, path: &[usize]) -> Option<&mut GrammarNode> {
+        match path.split_first() {
+            None => Some(self),
+            Some((idx, rest)) => match self {
+                GrammarNode::Rule {
+                    children,
+                    ..
+                } => children.get_mut(*idx)?.get_mut(rest),
+                GrammarNode::Repeat(items) => items.get_mut(*idx)?.get_mut(rest),
+                _ => None,
+            },
+        }
+    }
+}
+
+/// A runtime description of the messages of a protocol.
+///
+/// A grammar consists of named rules. Every rule has one or more alternatives
+/// and every alternative is a sequence of [`Field`]s.
+/// Fields can be literals, free bytes, enumerations, integers, length fields
+/// that refer to other fields, references to other rules and repetitions.
+///
+/// The grammar must be stored as metadata in the state, so that
+/// [`GrammarPacket`]s can be generated and mutated:
+/// ```
+/// // A TLV message: type, length and value
+/// let grammar = Grammar::new("message")
+///     .with_rule("message", vec![
+///         vec![Field::Enum(vec![b"\x01".to_vec(), b"\x02".to_vec()]), Field::Length { width: 2, endianness: Endianness::Big, of: 2 }, Field::rule("value")],
+///     ])
+///     .with_rule("value", vec![
+///         vec![Field::literal(b"id="), Field::Integer { width: 1, endianness: Endianness::Big, min: 0, max: 9 }],
+///         vec![Field::Bytes { min: 1, max: 64 }],
+///     ]);
+/// grammar.validate().unwrap();
+/// state.add_metadata(grammar);
+/// ```
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Grammar {
+    start: String,
+    rules: BTreeMap<String, Vec<Vec<Field>>>,
+}
+
+impl_serdeany!(Grammar);
+
+impl Grammar {
+    /// Create a new Grammar whose packets are expansions of the rule `start`
+    pub fn new(start: &str) -> Self {
+        Self {
+            start: start.to_string(),
+            rules: BTreeMap::new(),
+        }
+    }
+
+    /// Add a rule with a list of alternatives. An existing rule with the same name gets replaced.
+    pub fn with_rule(mut self, name: &str, alternatives: Vec<Vec<Field>>) -> Self {
+        self.rules.insert(name.to_string(), alternatives);
+        self
+    }
+
+    /// Get the name of the start rule
+    pub fn start(&self) -> &str {
+        &self.start
+    }
+
+    /// Get the alternatives of a rule
+    pub fn rule(&self, name: &str) -> Option<&[Vec<Field>]> {
+        self.rules.get(name).map(Vec::as_slice)
+    }
+
+    /// Check that all referenced rules exist and that all fields are well-formed
+    pub fn validate(&self) -> Result<(), Error> {
+        if !self.rules.contains_key(&self.start) {
+            return Err(Error::key_not_found(format!("Start rule '{}' does not exist", self.start)));
+        }
+
+        for (name, alternatives) in &self.rules {
+            if alternatives.is_empty() {
+                return Err(Error::illegal_argument(format!("Rule '{}' has no alternatives", name)));
+            }
+
+            for fields in alternatives {
+                for field in fields {
+                    let valid = match field {
+                        Field::Literal(_) => true,
+                        Field::Bytes {
+                            min,
+                            max,
+                        } => min <= max,
+                        Field::Enum(values) => !values.is_empty(),
+                        Field::Integer {
+                            width,
+                            min,
+                            max,
+                            ..
+                        } => (1..=8).contains(width) && min <= max,
+                        Field::Length {
+                            width,
+                            of,
+                            ..
+                        } => (1..=8).contains(width) && *of < fields.len() && !matches!(fields[*of], Field::Length { .. }),
+                        Field::Rule(rule) => self.rules.contains_key(rule),
+                        Field::Repeat {
+                            rule,
+                            min,
+                            max,
+                        } => self.rules.contains_key(rule) && min <= max,
+                    };
+
+                    if !valid {
+                        return Err(Error::illegal_argument(format!("Invalid field {:?} in rule '{}'", field, name)));
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Generate a random expansion of the start rule
+    pub fn generate<R: Rand>(&self, rand: &mut R) -> GrammarNode {
+        self.generate_rule(rand, &self.start, 0)
+    }
+
+    /// Generate a random expansion of a rule
+    fn generate_rule<R: Rand>(&self, rand: &mut R, name: &str, depth: usize) -> GrammarNode {
+        let alternatives = self.rules.get(name).map(Vec::as_slice).unwrap_or_default();
+
+        if alternatives.is_empty() {
+            return GrammarNode::Terminal(Vec::new());
+        }
+
+        let alternative = if depth < MAX_DEPTH {
+            rand.below(alternatives.len() as u64) as usize
+        } else {
+            // Stop the recursion as soon as possible
+            alternatives.iter().position(|fields| !fields.iter().any(Field::is_recursive)).unwrap_or(0)
+        };
+
+        GrammarNode::Rule {
+            name: name.to_string(),
+            alternative,
+            children: alternatives[alternative].iter().map(|field| self.generate_field(rand, field, depth)).collect(),
+        }
+    }
+
+    fn generate_field<R: Rand>(&self, rand: &mut R, field: &Field, depth: usize) -> GrammarNode {
+        match field {
+            Field::Literal(bytes) => GrammarNode::Terminal(bytes.clone()),
+            Field::Bytes {
+                min,
+                max,
+            } => {
+                let max = (*max).min((*min).max(MAX_GENERATED_LEN));
+                let len = rand.between(*min as u64, max as u64) as usize;
+                GrammarNode::Terminal((0..len).map(|_| rand.next() as u8).collect())
+            },
+            Field::Enum(values) => GrammarNode::Terminal(rand.choose(values).clone()),
+            Field::Integer {
+                width,
+                endianness,
+                min,
+                max,
+            } => GrammarNode::Terminal(endianness.encode(random_integer(rand, *min, *max), *width)),
+            Field::Length {
+                width,
+                endianness,
+                of,
+            } => GrammarNode::Length {
+                width: *width,
+                endianness: *endianness,
+                of: *of,
+            },
+            Field::Rule(rule) => self.generate_rule(rand, rule, depth + 1),
+            Field::Repeat {
+                rule,
+                min,
+                max,
+            } => {
+                let count = if depth < MAX_DEPTH { rand.between(*min as u64, *max as u64) as usize } else { *min };
+                GrammarNode::Repeat((0..count).map(|_| self.generate_rule(rand, rule, depth + 1)).collect())
+            },
+        }
+    }
+
+    /// Parse bytes into a tree of the start rule. Returns `None` if the bytes don't match the grammar.
+    pub fn parse(&self, bytes: &[u8]) -> Option<GrammarNode> {
+        self.parse_rule(&self.start, bytes, 0, 0).into_iter().find(|(_, end)| *end == bytes.len()).map(|(node, _)| node)
+    }
+
+    /// Returns all ways to parse a rule starting at `pos`, as the tree and the end position
+    fn parse_rule(&self, name: &str, data: &[u8], pos: usize, depth: usize) -> Vec<(GrammarNode, usize)> {
+        let mut ret = Vec::new();
+
+        if depth > MAX_DEPTH {
+            return ret;
+        }
+
+        for (alternative, fields) in self.rules.get(name).into_iter().flatten().enumerate() {
+            let mut parses = Vec::new();
+            let mut lengths = vec![None; fields.len()];
+            self.parse_fields(fields, data, pos, depth, &mut lengths, &mut Vec::new(), &mut Vec::new(), &mut parses);
+
+            for (children, end) in parses {
+                ret.push((
+                    GrammarNode::Rule {
+                        name: name.to_string(),
+                        alternative,
+                        children,
+                    },
+                    end,
+                ));
+            }
+
+            if ret.len() >= MAX_PARSES {
+                break;
+            }
+        }
+
+        ret
+    }
+
+    /// Parse the remaining fields of an alternative with backtracking
+    #[allow(clippy::too_many_arguments)]
+    fn parse_fields(&self, fields: &[Field], data: &[u8], pos: usize, depth: usize, lengths: &mut [Option<usize>], spans: &mut Vec<usize>, children: &mut Vec<GrammarNode>, out: &mut Vec<(Vec<GrammarNode>, usize)>) {
+        let idx = children.len();
+
+        if out.len() >= MAX_PARSES {
+            return;
+        }
+
+        if idx == fields.len() {
+            out.push((children.clone(), pos));
+            return;
+        }
+
+        for (node, end) in self.parse_field(&fields[idx], data, pos, depth, lengths[idx]) {
+            let mut constrained = None;
+
+            if let Field::Length {
+                endianness,
+                of,
+                ..
+            } = &fields[idx]
+            {
+                let value = endianness.decode(&data[pos..end]) as usize;
+
+                if *of < idx {
+                    if spans[*of] != value {
+                        continue;
+                    }
+                } else {
+                    constrained = Some((*of, lengths[*of]));
+                    lengths[*of] = Some(value);
+                }
+            }
+
+            spans.push(end - pos);
+            children.push(node);
+            self.parse_fields(fields, data, end, depth, lengths, spans, children, out);
+            children.pop();
+            spans.pop();
+
+            if let Some((of, old)) = constrained {
+                lengths[of] = old;
+            }
+        }
+    }
+
+    /// Returns all ways to parse a single field at `pos`, optionally with a known length
+    fn parse_field(&self, field: &Field, data: &[u8], pos: usize, depth: usize, len: Option<usize>) -> Vec<(GrammarNode, usize)> {
+        let rest = &data[pos..];
+        let fits = |end: usize| len.map(|len| end == pos + len).unwrap_or(true);
+
+        let mut ret = match field {
+            Field::Literal(bytes) if rest.starts_with(bytes) => vec![(GrammarNode::Terminal(bytes.clone()), pos + bytes.len())],
+            Field::Literal(_) => Vec::new(),
+            Field::Bytes {
+                min,
+                max,
+            } => {
+                let longest = (*max).min(rest.len());
+                // Greedy: try the longest match first
+                (*min..=longest).rev().map(|len| (GrammarNode::Terminal(rest[..len].to_vec()), pos + len)).collect()
+            },
+            Field::Enum(values) => values.iter().filter(|value| rest.starts_with(value)).map(|value| (GrammarNode::Terminal(value.clone()), pos + value.len())).collect(),
+            Field::Integer {
+                width,
+                endianness,
+                min,
+                max,
+            } => match rest.get(..*width) {
+                Some(bytes) if (*min..=*max).contains(&endianness.decode(bytes)) => vec![(GrammarNode::Terminal(bytes.to_vec()), pos + width)],
+                _ => Vec::new(),
+            },
+            Field::Length {
+                width,
+                endianness,
+                of,
+            } if rest.len() >= *width => vec![(
+                GrammarNode::Length {
+                    width: *width,
+                    endianness: *endianness,
+                    of: *of,
+                },
+                pos + width,
+            )],
+            Field::Length {
+                ..
+            } => Vec::new(),
+            Field::Rule(rule) => self.parse_rule(rule, data, pos, depth + 1),
+            Field::Repeat {
+                rule,
+                min,
+                max,
+            } => {
+                let mut ret = Vec::new();
+                self.parse_repeat(rule, *min, *max, data, pos, depth, &mut Vec::new(), &mut ret);
+                ret
+            },
+        };
+
+        ret.retain(|(_, end)| fits(*end));
+        ret.truncate(MAX_PARSES);
+        ret
+    }
+
+    /// Parse as many expansions of a rule as possible, longest match first
+    #[allow(clippy::too_many_arguments)]
+    fn parse_repeat(&self, rule: &str, min: usize, max: usize, data: &[u8], pos: usize, depth: usize, items: &mut Vec<GrammarNode>, out: &mut Vec<(GrammarNode, usize)>) {
+        if out.len() >= MAX_PARSES {
+            return;
+        }
+
+        if items.len() < max {
+            for (item, end) in self.parse_rule(rule, data, pos, depth + 1) {
+                // Expansions that consume nothing would repeat forever
+                if end > pos {
+                    items.push(item);
+                    self.parse_repeat(rule, min, max, data, end, depth, items, out);
+                    items.pop();
+                }
+            }
+        }
+
+        if items.len() >= min {
+            out.push((GrammarNode::Repeat(items.clone()), pos));
+        }
+    }
+
+    /// Collect the paths of all nodes that can be mutated, together with the field they are an expansion of.
+    /// Rule nodes don't need a field because they carry the name of their rule.
+    fn mutable_nodes<'a>(&'a self, node: &GrammarNode, field: Option<&'a Field>, path: &mut Vec<usize>, out: &mut Vec<(Vec<usize>, Option<&'a Field>)>) {
+        match (node, field) {
+            (
+                GrammarNode::Terminal(_),
+                Some(
+                    Field::Bytes {
+                        ..
+                    }
+                    | Field::Enum(_)
+                    | Field::Integer {
+                        ..
+                    },
+                ),
+            ) => out.push((path.clone(), field)),
+            (
+                GrammarNode::Rule {
+                    name,
+                    alternative,
+                    children,
+                },
+                _,
+            ) => {
+                out.push((path.clone(), None));
+
+                if let Some(fields) = self.rules.get(name).and_then(|alternatives| alternatives.get(*alternative)) {
+                    for (idx, (child, field)) in children.iter().zip(fields).enumerate() {
+                        path.push(idx);
+                        self.mutable_nodes(child, Some(field), path, out);
+                        path.pop();
+                    }
+                }
+            },
+            (
+                GrammarNode::Repeat(items),
+                Some(Field::Repeat {
+                    ..
+                }),
+            ) => {
+                out.push((path.clone(), field));
+
+                for (idx, item) in items.iter().enumerate() {
+                    path.push(idx);
+                    self.mutable_nodes(item, None, path, out);
+                    path.pop();
+                }
+            },
+            _ => {},
+        }
+    }
+
+    /// Mutate a random node of a tree within the bounds of the grammar
+    fn mutate<R: Rand>(&self, rand: &mut R, tree: &mut GrammarNode) -> MutationResult {
+        let mut nodes = Vec::new();
+        self.mutable_nodes(tree, None, &mut Vec::new(), &mut nodes);
+
+        if nodes.is_empty() {
+            return MutationResult::Skipped;
+        }
+
+        let (path, field) = nodes[rand.below(nodes.len() as u64) as usize].clone();
+        let node = match tree.get_mut(&path) {
+            Some(node) => node,
+            None => return MutationResult::Skipped,
+        };
+        let depth = path.len();
+
+        if let GrammarNode::Rule {
+            name,
+            ..
+        } = node
+        {
+            let name = name.clone();
+            *node = self.generate_rule(rand, &name, depth);
+            return MutationResult::Mutated;
+        }
+
+        match (node, field) {
+            (
+                GrammarNode::Terminal(bytes),
+                Some(
+                    field @ Field::Bytes {
+                        ..
+                    },
+                ),
+            ) => {
+                if !bytes.is_empty() && rand.below(2) == 0 {
+                    let idx = rand.below(bytes.len() as u64) as usize;
+                    bytes[idx] ^= 1 + rand.below(255) as u8;
+                } else if let GrammarNode::Terminal(new_bytes) = self.generate_field(rand, field, depth) {
+                    *bytes = new_bytes;
+                }
+            },
+            (GrammarNode::Terminal(bytes), Some(Field::Enum(values))) => {
+                let others: Vec<&Vec<u8>> = values.iter().filter(|value| *value != bytes).collect();
+
+                if others.is_empty() {
+                    return MutationResult::Skipped;
+                }
+
+                *bytes = rand.choose(others).clone();
+            },
+            (
+                GrammarNode::Terminal(bytes),
+                Some(Field::Integer {
+                    width,
+                    endianness,
+                    min,
+                    max,
+                }),
+            ) => {
+                let value = match rand.below(3) {
+                    0 => *min,
+                    1 => *max,
+                    _ => random_integer(rand, *min, *max),
+                };
+                *bytes = endianness.encode(value, *width);
+            },
+            (
+                GrammarNode::Repeat(items),
+                Some(Field::Repeat {
+                    rule,
+                    min,
+                    max,
+                }),
+            ) => match rand.below(3) {
+                0 if items.len() > *min => {
+                    items.remove(rand.below(items.len() as u64) as usize);
+                },
+                1 if items.len() < *max && !items.is_empty() => {
+                    let idx = rand.below(items.len() as u64) as usize;
+                    items.insert(idx, items[idx].clone());
+                },
+                _ if items.len() < *max => {
+                    let idx = rand.below(items.len() as u64 + 1) as usize;
+                    items.insert(idx, self.generate_rule(rand, rule, depth));
+                },
+                _ => return MutationResult::Skipped,
+            },
+            _ => return MutationResult::Skipped,
+        }
+
+        MutationResult::Mutated
+    }
+}
+
+/// A packet that is an expansion of a [`Grammar`].
+///
+/// It keeps the parse tree of the packet, so that mutations
+/// happen on the level of fields instead of raw bytes and `Length` fields are always correct.
+///
+/// # Example
+/// ```
+/// let grammar = state.metadata().get::<Grammar>().unwrap();
+/// let packet = GrammarPacket::parse(grammar, b"\x01\x00\x04id=7").unwrap();
+/// ```
+#[derive(Hash, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GrammarPacket {
+    tree: GrammarNode,
+}
+
+impl GrammarPacket {
+    /// Create a new GrammarPacket from a parse tree
+    pub fn new(tree: GrammarNode) -> Self {
+        Self {
+            tree,
+        }
+    }
+
+    /// Parse bytes with a grammar. Returns `None` if the bytes don't match the grammar.
+    pub fn parse(grammar: &Grammar, bytes: &[u8]) -> Option<Self> {
+        grammar.parse(bytes).map(Self::new)
+    }
+
+    /// Get the parse tree
+    pub fn tree(&self) -> &GrammarNode {
+        &self.tree
+    }
+}
+
+impl HasPayload for GrammarPacket {
+    fn payload(&self) -> Vec<u8> {
+        self.tree.to_bytes()
+    }
+}
+
+impl<S> HasPacketGenerator<S> for GrammarPacket
+where
+    S: HasRand + HasMetadata,
+{
+    fn generate_packet(state: &mut S) -> Option<Self> {
+        let mut rand = StdRand::with_seed(state.rand_mut().next());
+        let grammar = state.metadata().get::<Grammar>()?;
+        Some(Self::new(grammar.generate(&mut rand)))
+    }
+}
+
+/// Signifies that a packet type can be mutated within the bounds of a [`Grammar`].
+/// Used by the [`GrammarPacketMutator`].
+///
+/// Already implemented for:
+/// - [`GrammarPacket`]
+/// - [`NetworkPacket`](crate::NetworkPacket)
+pub trait HasGrammarMutation<S>
+where
+    S: HasRand + HasMetadata,
+{
+    /// Perform a single grammar mutation
+    fn mutate_grammar(&mut self, state: &mut S, stage_idx: i32) -> Result<MutationResult, Error>;
+}
+
+impl HasPostMutationFixup for GrammarPacket {}
+
+impl<S> HasGrammarMutation<S> for GrammarPacket
+where
+    S: HasRand + HasMetadata,
+{
+    fn mutate_grammar(&mut self, state: &mut S, _stage_idx: i32) -> Result<MutationResult, Error> {
+        let mut rand = StdRand::with_seed(state.rand_mut().next());
+
+        match state.metadata().get::<Grammar>() {
+            Some(grammar) => Ok(grammar.mutate(&mut rand, &mut self.tree)),
+            None => Ok(MutationResult::Skipped),
+        }
+    }
+}
+
+/// A mutator that mutates the parse tree of a random packet.
+///
+/// It picks a random node of the tree and
+/// - regenerates it if it is the expansion of a rule
+/// - flips a byte or generates new bytes for `Bytes` fields
+/// - picks another value of `Enum` fields
+/// - sets `Integer` fields to their minimum, maximum or a random value in between
+/// - removes, duplicates or adds an expansion of a `Repeat` field
+///
+/// The [`Grammar`] must be stored as metadata in the state, see [`HasGrammarMutation`].
+pub struct GrammarPacketMutator<P, SEL = PacketSelection> {
+    selection: SEL,
+    phantom: PhantomData<P>,
+}
+
+impl<P> GrammarPacketMutator<P> {
+    /// Create a new GrammarPacketMutator
+    pub fn new() -> Self {
+        Self {
+            selection: PacketSelection::Uniform,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<P, SEL> GrammarPacketMutator<P, SEL> {
+    /// Choose the packet with the given [`PacketSelector`] instead of uniformly
+    pub fn with_selection<SEL2>(self, selection: SEL2) -> GrammarPacketMutator<P, SEL2> {
+        GrammarPacketMutator {
+            selection,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<I, S, P, SEL> Mutator<I, S> for GrammarPacketMutator<P, SEL>
+where
+    P: HasGrammarMutation<S> + HasPostMutationFixup,
+    I: Input + HasPackets<P>,
+    S: HasRand + HasMetadata,
+    SEL: PacketSelector<P, S>,
+{
+    fn mutate(&mut self, state: &mut S, input: &mut I, stage_idx: i32) -> Result<MutationResult, Error> {
+        if input.packets().is_empty() {
+            return Ok(MutationResult::Skipped);
+        }
+
+        let idx = self.selection.select_packet(state, input.packets());
+        let result = input.packets_mut()[idx].mutate_grammar(state, stage_idx)?;
+
+        if result == MutationResult::Mutated {
+            input.packets_mut()[idx].fixup();
+        }
+
+        Ok(result)
+    }
+}
+
+impl<P, SEL> Named for GrammarPacketMutator<P, SEL> {
+    fn name(&self) -> &str {
+        "GrammarPacketMutator"
+    }
+}
+
+impl<P, SEL> HasConfig for GrammarPacketMutator<P, SEL>
+where
+    SEL: Debug,
+{
+    fn config(&self) -> ComponentConfig {
+        ComponentConfig::new(self.name()).with_setting("selection", format!("{:?}", self.selection))
+    }
+}
+
+fn random_integer<R: Rand>(rand: &mut R, min: u64, max: u64) -> u64 {
+    if min == 0 && max == u64::MAX {
+        rand.next()
+    } else {
+        rand.between(min, max)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tlv_grammar() -> Grammar {
+        Grammar::new("message")
+            .with_rule(
+                "message",
+                vec![vec![
+                    Field::Enum(vec![b"\x01".to_vec(), b"\x02".to_vec()]),
+                    Field::Length {
+                        width: 2,
+                        endianness: Endianness::Big,
+                        of: 2,
+                    },
+                    Field::Repeat {
+                        rule: "option".to_string(),
+                        min: 0,
+                        max: 4,
+                    },
+                ]],
+            )
+            .with_rule(
+                "option",
+                vec![
+                    vec![
+                        Field::literal(b"id="),
+                        Field::Integer {
+                            width: 1,
+                            endianness: Endianness::Big,
+                            min: b'0' as u64,
+                            max: b'9' as u64,
+                        },
+                    ],
+                    vec![
+                        Field::literal(b"x"),
+                        Field::Bytes {
+                            min: 1,
+                            max: 8,
+                        },
+                    ],
+                ],
+            )
+    }
+
+    #[test]
+    fn test_grammar() {
+        let grammar = tlv_grammar();
+        grammar.validate().unwrap();
+        assert!(Grammar::new("message").validate().is_err());
+
+        let packet = GrammarPacket::parse(&grammar, b"\x01\x00\x08id=7id=3").unwrap();
+        assert_eq!(packet.payload(), b"\x01\x00\x08id=7id=3");
+        assert_eq!(packet.tree().children()[2].children().len(), 2);
+        assert!(grammar.parse(b"\x01\x00\x09id=7id=3").is_none());
+        assert!(grammar.parse(b"\x03\x00\x00").is_none());
+
+        let mut rand = StdRand::with_seed(0);
+        let mut tree = grammar.generate(&mut rand);
+
+        for _ in 0..100 {
+            grammar.mutate(&mut rand, &mut tree);
+
+            // Mutations stay within the grammar and keep the length correct
+            let bytes = tree.to_bytes();
+            assert_eq!(grammar.parse(&bytes).map(|tree| tree.to_bytes()), Some(bytes));
+        }
+    }
+}
@@ -0,0 +1,102 @@
+use ahash::AHasher;
+use std::fmt::Debug;
+use std::hash::Hasher;
+
+type ResponseParser<PS> = dyn Fn(&[u8]) -> Option<PS>;
+
+/// Derives the state of the target from a response, for [`StateObserver::record_response()`](crate::StateObserver::record_response).
+///
+/// Most executors infer the state from a response buffer in a few lines of parsing.
+/// This bundles the parsing with the observer, so that executors only have to pass on what they receive.
+/// Responses that the parser returns `None` for are not recorded.
+///
+/// # Example
+/// ```
+/// let observer = StateObserver::<u32>::new("state").with_response_inference(ResponseStateInference::status_code());
+/// let observer = StateObserver::<u32>::new("state").with_response_inference(ResponseStateInference::new(ftp::status_code));
+/// let observer = StateObserver::<u8>::new("state").with_response_inference(ResponseStateInference::new(|response: &[u8]| response.first().copied()));
+/// ```
+pub struct ResponseStateInference<PS> {
+    parser: Box<ResponseParser<PS>>,
+}
+
+impl<PS> ResponseStateInference<PS> {
+    /// Create a new ResponseStateInference that derives the state with `parser`
+    pub fn new<F>(parser: F) -> Self
+    where
+        F: Fn(&[u8]) -> Option<PS> + 'static,
+    {
+        Self {
+            parser: Box::new(parser),
+        }
+    }
+
+    /// Returns the state of `response`, if any
+    pub fn infer(&self, response: &[u8]) -> Option<PS> {
+        (self.parser)(response)
+    }
+}
+
+impl ResponseStateInference<u32> {
+    /// Use the numeric status code in the first line of a response as the state, like the reply codes of FTP
+    /// and SMTP (`220 ready`, `250-PIPELINING`) or the status of HTTP (`HTTP/1.1 404 Not Found`).
+    ///
+    /// The code is the first word of the first line that starts with a digit, up to the first character that is not a digit.
+    pub fn status_code() -> Self {
+        Self::new(parse_status_code)
+    }
+}
+
+impl ResponseStateInference<u64> {
+    /// Use a hash of the first `n` bytes of a response as the state, for binary protocols whose
+    /// message type and status are in a fixed-size header. Empty responses are not recorded.
+    pub fn prefix_hash(n: usize) -> Self {
+        Self::new(move |response: &[u8]| {
+            if response.is_empty() {
+                return None;
+            }
+
+            let mut hasher = AHasher::default();
+            hasher.write(&response[..n.min(response.len())]);
+            Some(hasher.finish())
+        })
+    }
+}
+
+impl<PS> Debug for ResponseStateInference<PS> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "ResponseStateInference")
+    }
+}
+
+fn parse_status_code(response: &[u8]) -> Option<u32> {
+    let line = response.split(|c| *c == b'\n').next()?;
+    let word = line.split(|c| c.is_ascii_whitespace()).find(|word| word.first().is_some_and(u8::is_ascii_digit))?;
+    let digits = word.iter().take_while(|c| c.is_ascii_digit()).count();
+    std::str::from_utf8(&word[..digits]).ok()?.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_code() {
+        let inference = ResponseStateInference::status_code();
+        assert_eq!(inference.infer(b"220 ProFTPD ready\r\n"), Some(220));
+        assert_eq!(inference.infer(b"250-PIPELINING\r\n250 OK\r\n"), Some(250));
+        assert_eq!(inference.infer(b"HTTP/1.1 404 Not Found\r\n\r\n"), Some(404));
+        assert_eq!(inference.infer(b"hello\r\n200 OK\r\n"), None);
+        assert_eq!(inference.infer(b"99999999999 overflow"), None);
+        assert_eq!(inference.infer(b""), None);
+    }
+
+    #[test]
+    fn test_prefix_hash() {
+        let inference = ResponseStateInference::prefix_hash(2);
+        assert_eq!(inference.infer(b"\x01\x00abc"), inference.infer(b"\x01\x00xyz"));
+        assert_ne!(inference.infer(b"\x01\x00abc"), inference.infer(b"\x01\x01abc"));
+        assert_eq!(inference.infer(b"\x01"), inference.infer(b"\x01"));
+        assert_eq!(inference.infer(b""), None);
+    }
+}
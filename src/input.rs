@@ -1,7 +1,20 @@
-use libafl::{Error, Evaluator};
-use pcap::{Capture, Offline};
+use crate::{observer::StateObserver, validate::execute_once};
+use libafl::{
+    events::EventFirer,
+    executors::{Executor, HasObservers},
+    inputs::Input,
+    observers::ObserversTuple,
+    Error, Evaluator, ExecutionProcessor,
+};
+use pcap::{Capture, Linktype, Offline};
+use serde::{Deserialize, Serialize};
+use std::cmp::Eq;
+use std::collections::{HashMap, HashSet};
 use std::ffi::OsStr;
-use std::path::PathBuf;
+use std::fmt::Debug;
+use std::hash::Hash;
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
 
 /// Signifies that an input consists of packets.
 ///
@@ -56,6 +69,33 @@ pub trait HasPackets<I> {
 
     /// Get the inputs packets
     fn packets_mut(&mut self) -> &mut Vec<I>;
+
+    /// Get mutable references to two different packets at once, e.g. to
+    /// mutate packet `first` with the contents of packet `second` without cloning it.
+    ///
+    /// Returns `None` if `first` and `second` are the same packet or if one of them is out of bounds.
+    ///
+    /// # Example
+    /// ```
+    /// if let Some((packet, other)) = input.packets_pair_mut(0, 1) {
+    ///     packet.bytes_mut().extend_from_slice(other.bytes());
+    /// }
+    /// ```
+    fn packets_pair_mut(&mut self, first: usize, second: usize) -> Option<(&mut I, &mut I)> {
+        let packets = self.packets_mut();
+
+        if first == second || first >= packets.len() || second >= packets.len() {
+            return None;
+        }
+
+        if first < second {
+            let (head, tail) = packets.split_at_mut(second);
+            Some((&mut head[first], &mut tail[0]))
+        } else {
+            let (head, tail) = packets.split_at_mut(first);
+            Some((&mut tail[0], &mut head[second]))
+        }
+    }
 }
 
 /// Signifies that an input can be constructed from a packet capture.
@@ -68,10 +108,20 @@ pub trait HasPcapRepresentation<I> {
     //TODO: maybe to_pcap() ?
 }
 
+/// Signifies that an input can be constructed from raw packets.
+///
+/// Use it in conjunction with [`load_raw_seeds`].
+pub trait HasRawRepresentation<I> {
+    /// Given the packets of a seed file, as produced by a splitter, construct an input
+    fn from_raw_packets(packets: Vec<Vec<u8>>) -> Result<I, Error>;
+}
+
 /// Helper function that loads pcap files from a given directory into the corpus.
 ///
 /// It scans the directory for files ending with `.pcap` or `.pcapng` and loads them
 /// via [`HasPcapRepresentation::from_pcap()`](crate::HasPcapRepresentation::from_pcap).
+/// This uses the default settings of [`PcapLoader`], so subdirectories are only searched
+/// up to 16 levels deep. Use the loader directly for more control, e.g. [`PcapLoader::with_max_depth()`].
 ///
 /// This is an equivalent to [`load_initial_inputs()`](libafl::state::StdState::load_initial_inputs) from LibAFL.
 ///
@@ -87,28 +137,827 @@ where
     I: HasPcapRepresentation<I>,
     P: Into<PathBuf>,
 {
-    for entry in std::fs::read_dir(&in_dir.into())? {
-        let entry = entry?;
-        let path = entry.path();
+    PcapLoader::new().load(state, fuzzer, executor, mgr, in_dir)
+}
+
+/// How many directories deep a [`PcapLoader`] recurses by default
+const DEFAULT_MAX_DEPTH: usize = 16;
 
-        let attributes = std::fs::metadata(&path);
+/// Restrictions for walking seed directories
+#[derive(Clone, Debug)]
+struct WalkOptions {
+    max_depth: Option<usize>,
+    follow_symlinks: bool,
+    max_file_size: Option<u64>,
+}
 
-        if attributes.is_err() {
+impl WalkOptions {
+    fn unrestricted() -> Self {
+        Self {
+            max_depth: None,
+            follow_symlinks: true,
+            max_file_size: None,
+        }
+    }
+}
+
+/// Recursively collects all non-empty files in a given directory that are accepted by `accept`.
+fn find_files<P>(in_dir: P, accept: &dyn Fn(&Path) -> bool) -> Result<Vec<PathBuf>, Error>
+where
+    P: Into<PathBuf>,
+{
+    let mut files = Vec::new();
+    walk_dir(&in_dir.into(), 0, &WalkOptions::unrestricted(), &mut HashSet::new(), accept, &mut files)?;
+    Ok(files)
+}
+
+fn walk_dir(dir: &Path, depth: usize, options: &WalkOptions, visited: &mut HashSet<PathBuf>, accept: &dyn Fn(&Path) -> bool, files: &mut Vec<PathBuf>) -> Result<(), Error> {
+    // Directories reachable over multiple paths, e.g. via symlink loops, are only visited once
+    if let Ok(canonical) = dir.canonicalize() {
+        if !visited.insert(canonical) {
+            return Ok(());
+        }
+    }
+
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+
+        if !options.follow_symlinks && std::fs::symlink_metadata(&path).map(|attr| attr.file_type().is_symlink()).unwrap_or(true) {
             continue;
         }
 
-        let attr = attributes?;
+        let attr = match std::fs::metadata(&path) {
+            Ok(attr) => attr,
+            Err(_) => continue,
+        };
 
         if attr.is_file() && attr.len() > 0 {
-            if path.extension() == Some(OsStr::new("pcapng")) || path.extension() == Some(OsStr::new("pcap")) {
-                println!("[butterfly] Loading pcap {}...", path.display());
-                let input = I::from_pcap(Capture::from_file(path).expect("invalid pcap format"))?;
+            if !accept(&path) {
+                continue;
+            }
+
+            match options.max_file_size {
+                Some(max_size) if attr.len() > max_size => println!("[butterfly] Skipping {}, it is larger than {} bytes", path.display(), max_size),
+                _ => files.push(path),
+            }
+        } else if attr.is_dir() && options.max_depth.unwrap_or(usize::MAX) > depth {
+            if let Err(err) = walk_dir(&path, depth + 1, options, visited, accept, files) {
+                println!("[butterfly] Skipping directory {}: {}", path.display(), err);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Finds and loads pcap files from a seed directory.
+///
+/// By default it accepts files ending with `.pcap` or `.pcapng` (ignoring case),
+/// recurses at most 16 directories deep, follows symlinks but visits every
+/// directory only once and loads files of any size.
+/// Files that are not valid captures are skipped.
+///
+/// # Example
+/// ```
+/// PcapLoader::new()
+///     .with_extensions(&["pcap", "cap"])
+///     .with_max_depth(2)
+///     .with_symlinks(false)
+///     .with_max_file_size(16 * 1024 * 1024)
+///     .load(&mut state, &mut fuzzer, &mut executor, &mut mgr, "pcaps")
+///     .unwrap();
+/// ```
+#[derive(Clone, Debug)]
+pub struct PcapLoader {
+    extensions: Vec<String>,
+    options: WalkOptions,
+}
+
+impl PcapLoader {
+    /// Create a new PcapLoader with the default settings
+    pub fn new() -> Self {
+        Self {
+            extensions: vec!["pcap".to_string(), "pcapng".to_string()],
+            options: WalkOptions {
+                max_depth: Some(DEFAULT_MAX_DEPTH),
+                follow_symlinks: true,
+                max_file_size: None,
+            },
+        }
+    }
+
+    /// Only accept files with these extensions (without the dot)
+    pub fn with_extensions(mut self, extensions: &[&str]) -> Self {
+        self.extensions = extensions.iter().map(|extension| extension.to_ascii_lowercase()).collect();
+        self
+    }
+
+    /// Recurse at most `max_depth` directories deep. 0 only loads the files directly in the directory. Default: 16
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.options.max_depth = Some(max_depth);
+        self
+    }
+
+    /// Set whether symlinks to files and directories are followed
+    pub fn with_symlinks(mut self, follow: bool) -> Self {
+        self.options.follow_symlinks = follow;
+        self
+    }
+
+    /// Skip files that are larger than `max_size` bytes
+    pub fn with_max_file_size(mut self, max_size: u64) -> Self {
+        self.options.max_file_size = Some(max_size);
+        self
+    }
+
+    fn accepts(&self, path: &Path) -> bool {
+        match path.extension().and_then(OsStr::to_str) {
+            Some(extension) => self.extensions.iter().any(|accepted| accepted.eq_ignore_ascii_case(extension)),
+            None => false,
+        }
+    }
+
+    /// Collect the paths of all pcap files in `in_dir` without loading them
+    pub fn find<P>(&self, in_dir: P) -> Result<Vec<PathBuf>, Error>
+    where
+        P: Into<PathBuf>,
+    {
+        let mut files = Vec::new();
+        walk_dir(&in_dir.into(), 0, &self.options, &mut HashSet::new(), &|path| self.accepts(path), &mut files)?;
+        Ok(files)
+    }
+
+    /// Parse a single pcap file into an input. Returns `None` if the file is not a valid capture.
+    pub(crate) fn parse<I>(&self, path: &Path) -> Result<Option<I>, Error>
+    where
+        I: HasPcapRepresentation<I>,
+    {
+        println!("[butterfly] Loading pcap {}...", path.display());
+
+        match Capture::from_file(path) {
+            Ok(capture) => Ok(Some(I::from_pcap(capture)?)),
+            Err(err) => {
+                println!("[butterfly] Skipping {}: {}", path.display(), err);
+                Ok(None)
+            },
+        }
+    }
+
+    /// Load all pcap files in `in_dir` into the corpus like [`load_pcaps`]
+    pub fn load<S, Z, E, EM, I, P>(&self, state: &mut S, fuzzer: &mut Z, executor: &mut E, mgr: &mut EM, in_dir: P) -> Result<(), Error>
+    where
+        Z: Evaluator<E, EM, I, S>,
+        I: HasPcapRepresentation<I>,
+        P: Into<PathBuf>,
+    {
+        for path in self.find(in_dir)? {
+            if let Some(input) = self.parse(&path)? {
                 let _ = fuzzer.evaluate_input(state, executor, mgr, input)?;
             }
-        } else if attr.is_dir() {
-            load_pcaps(state, fuzzer, executor, mgr, path)?;
         }
+
+        Ok(())
+    }
+
+    /// Load all pcap files in `in_dir` into the corpus like [`load_pcaps_deduplicated`]
+    pub fn load_deduplicated<S, Z, E, EM, I, OT, P, PS>(&self, state: &mut S, fuzzer: &mut Z, executor: &mut E, mgr: &mut EM, in_dir: P, dedup: &mut SeedDeduplicator<PS>) -> Result<(), Error>
+    where
+        Z: ExecutionProcessor<I, OT, S>,
+        EM: EventFirer<I>,
+        E: Executor<EM, I, S, Z> + HasObservers<I, OT, S>,
+        OT: ObserversTuple<I, S>,
+        I: Input + HasPcapRepresentation<I>,
+        P: Into<PathBuf>,
+        PS: Clone + Debug + Eq + Hash + Serialize + for<'a> Deserialize<'a>,
+    {
+        for path in self.find(in_dir)? {
+            if let Some(input) = self.parse(&path)? {
+                dedup.load(state, fuzzer, executor, mgr, input)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Helper function that loads plain seed files from a given directory into the corpus.
+///
+/// Every non-empty file in the directory is read and split into packets by `splitter`.
+/// The packets are then turned into an input via
+/// [`HasRawRepresentation::from_raw_packets()`](crate::HasRawRepresentation::from_raw_packets).
+/// butterfly provides the splitters [`delimiter_splitter`] and [`length_prefix_splitter`]
+/// but any closure works.
+///
+/// This is the equivalent of [`load_pcaps`] for seeds that are not packet captures.
+///
+/// # Arguments
+/// - `state`: libafls state
+/// - `fuzzer`: libafls fuzzer
+/// - `executor`: libafls executor
+/// - `mgr`: libafls event manager
+/// - `in_dir`: path to directory with seed files
+/// - `splitter`: splits the content of a file into packets
+///
+/// # Example
+/// ```
+/// // Every line of a seed file is a packet
+/// load_raw_seeds(&mut state, &mut fuzzer, &mut executor, &mut mgr, "seeds", &mut delimiter_splitter(b"\r\n")).unwrap();
+/// ```
+pub fn load_raw_seeds<S, Z, E, EM, I, P, F>(state: &mut S, fuzzer: &mut Z, executor: &mut E, mgr: &mut EM, in_dir: P, splitter: &mut F) -> Result<(), Error>
+where
+    Z: Evaluator<E, EM, I, S>,
+    I: HasRawRepresentation<I>,
+    P: Into<PathBuf>,
+    F: FnMut(&[u8]) -> Vec<Vec<u8>>,
+{
+    for path in find_files(in_dir, &|_| true)? {
+        println!("[butterfly] Loading seed {}...", path.display());
+        let input = I::from_raw_packets(splitter(&std::fs::read(&path)?))?;
+        let _ = fuzzer.evaluate_input(state, executor, mgr, input)?;
+    }
+
+    Ok(())
+}
+
+/// Prefix tree over the state paths of loaded seeds
+#[derive(Debug, Default)]
+struct PathTrie {
+    children: HashMap<u32, PathTrie>,
+    end: bool,
+}
+
+impl PathTrie {
+    fn insert(&mut self, path: &[u32]) {
+        let mut node = self;
+
+        for state in path {
+            node = node.children.entry(*state).or_default();
+        }
+
+        node.end = true;
+    }
+
+    /// Returns whether `path` is a prefix of (or equal to) an inserted path
+    fn covers(&self, path: &[u32]) -> bool {
+        let mut node = self;
+
+        for state in path {
+            match node.children.get(state) {
+                Some(child) => node = child,
+                None => return false,
+            }
+        }
+
+        node.end || !node.children.is_empty()
+    }
+}
+
+/// Keeps track of the state paths of loaded seeds for
+/// [`load_pcaps_deduplicated`] and [`load_raw_seeds_deduplicated`].
+///
+/// A seed is considered a duplicate if its state path is a prefix of
+/// (or equal to) the state path of a seed that was loaded before it.
+/// The same deduplicator can be passed to multiple loaders to deduplicate across them.
+///
+/// When you create a SeedDeduplicator always specify `PS` manually, it must be the
+/// same type as the one of the [`StateObserver`]:
+/// ```
+/// let mut dedup = SeedDeduplicator::<u32>::new("state");
+/// ```
+#[derive(Debug)]
+pub struct SeedDeduplicator<PS> {
+    observer_name: String,
+    paths: PathTrie,
+    skipped: usize,
+    phantom: PhantomData<PS>,
+}
+
+impl<PS> SeedDeduplicator<PS>
+where
+    PS: Clone + Debug + Eq + Hash + Serialize + for<'a> Deserialize<'a>,
+{
+    /// Create a new SeedDeduplicator that takes the state paths
+    /// from the StateObserver with name `observer_name`.
+    pub fn new(observer_name: &str) -> Self {
+        Self {
+            observer_name: observer_name.to_string(),
+            paths: PathTrie::default(),
+            skipped: 0,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Returns the number of seeds that were skipped so far.
+    pub fn skipped(&self) -> usize {
+        self.skipped
+    }
+
+    /// Executes the input and hands the execution to the feedbacks if its state path is not covered yet.
+    fn load<S, Z, E, EM, I, OT>(&mut self, state: &mut S, fuzzer: &mut Z, executor: &mut E, mgr: &mut EM, input: I) -> Result<(), Error>
+    where
+        Z: ExecutionProcessor<I, OT, S>,
+        EM: EventFirer<I>,
+        E: Executor<EM, I, S, Z> + HasObservers<I, OT, S>,
+        OT: ObserversTuple<I, S>,
+        I: Input,
+    {
+        let exit_kind = execute_once(fuzzer, state, executor, mgr, &input)?;
+
+        let observer = match executor.observers().match_name::<StateObserver<PS>>(&self.observer_name) {
+            Some(observer) => observer,
+            None => return Err(Error::key_not_found(format!("No StateObserver named \"{}\" with the given state type", self.observer_name))),
+        };
+
+        if self.paths.covers(observer.path()) {
+            self.skipped += 1;
+            println!("[butterfly] Skipping seed, state path is already covered");
+            return Ok(());
+        }
+
+        self.paths.insert(observer.path());
+
+        let _ = fuzzer.process_execution(state, mgr, input, executor.observers(), &exit_kind, true)?;
+
+        Ok(())
+    }
+}
+
+/// Like [`load_pcaps`] but skips seeds whose state path is already covered by a previously loaded seed.
+///
+/// Every seed gets executed exactly once to obtain its state path. Seeds that are not
+/// covered get judged by the feedbacks of that execution, like in [`load_pcaps`].
+///
+/// # Arguments
+/// - `state`: libafls state
+/// - `fuzzer`: libafls fuzzer
+/// - `executor`: libafls executor
+/// - `mgr`: libafls event manager
+/// - `in_dir`: path to directory with pcap files
+/// - `dedup`: keeps track of the state paths of loaded seeds
+///
+/// # Example
+/// ```
+/// let mut dedup = SeedDeduplicator::<u32>::new("state");
+/// load_pcaps_deduplicated(&mut state, &mut fuzzer, &mut executor, &mut mgr, "pcaps", &mut dedup).unwrap();
+/// println!("Skipped {} seeds", dedup.skipped());
+/// ```
+pub fn load_pcaps_deduplicated<S, Z, E, EM, I, OT, P, PS>(state: &mut S, fuzzer: &mut Z, executor: &mut E, mgr: &mut EM, in_dir: P, dedup: &mut SeedDeduplicator<PS>) -> Result<(), Error>
+where
+    Z: ExecutionProcessor<I, OT, S>,
+    EM: EventFirer<I>,
+    E: Executor<EM, I, S, Z> + HasObservers<I, OT, S>,
+    OT: ObserversTuple<I, S>,
+    I: Input + HasPcapRepresentation<I>,
+    P: Into<PathBuf>,
+    PS: Clone + Debug + Eq + Hash + Serialize + for<'a> Deserialize<'a>,
+{
+    PcapLoader::new().load_deduplicated(state, fuzzer, executor, mgr, in_dir, dedup)
+}
+
+/// Like [`load_raw_seeds`] but skips seeds whose state path is already covered by a previously loaded seed.
+///
+/// See [`load_pcaps_deduplicated`] for details.
+pub fn load_raw_seeds_deduplicated<S, Z, E, EM, I, OT, P, F, PS>(state: &mut S, fuzzer: &mut Z, executor: &mut E, mgr: &mut EM, in_dir: P, splitter: &mut F, dedup: &mut SeedDeduplicator<PS>) -> Result<(), Error>
+where
+    Z: ExecutionProcessor<I, OT, S>,
+    EM: EventFirer<I>,
+    E: Executor<EM, I, S, Z> + HasObservers<I, OT, S>,
+    OT: ObserversTuple<I, S>,
+    I: Input + HasRawRepresentation<I>,
+    P: Into<PathBuf>,
+    F: FnMut(&[u8]) -> Vec<Vec<u8>>,
+    PS: Clone + Debug + Eq + Hash + Serialize + for<'a> Deserialize<'a>,
+{
+    for path in find_files(in_dir, &|_| true)? {
+        println!("[butterfly] Loading seed {}...", path.display());
+        let input = I::from_raw_packets(splitter(&std::fs::read(&path)?))?;
+        dedup.load(state, fuzzer, executor, mgr, input)?;
     }
 
     Ok(())
 }
+
+/// Returns a splitter for [`load_raw_seeds`] that splits data after every occurence of `delimiter`.
+///
+/// The delimiter stays part of the packet it terminates. Trailing bytes without
+/// a delimiter become the last packet.
+pub fn delimiter_splitter(delimiter: &[u8]) -> impl FnMut(&[u8]) -> Vec<Vec<u8>> {
+    let delimiter = delimiter.to_vec();
+
+    move |data: &[u8]| {
+        let mut packets = Vec::new();
+        let mut start = 0;
+        let mut i = 0;
+
+        if delimiter.is_empty() {
+            return vec![data.to_vec()];
+        }
+
+        while i + delimiter.len() <= data.len() {
+            if data[i..].starts_with(&delimiter) {
+                i += delimiter.len();
+                packets.push(data[start..i].to_vec());
+                start = i;
+            } else {
+                i += 1;
+            }
+        }
+
+        if start < data.len() {
+            packets.push(data[start..].to_vec());
+        }
+
+        packets
+    }
+}
+
+/// Returns a splitter for [`load_raw_seeds`] for protocols where every packet starts with its length.
+///
+/// The length field is `width` bytes wide (1, 2, 4 or 8), stored in big- or little-endian
+/// and does not include itself. The length field stays part of the packet.
+/// If the last packet is truncated it gets returned as is.
+pub fn length_prefix_splitter(width: usize, big_endian: bool) -> impl FnMut(&[u8]) -> Vec<Vec<u8>> {
+    assert!(matches!(width, 1 | 2 | 4 | 8), "invalid width of length field: {}", width);
+
+    move |data: &[u8]| {
+        let mut packets = Vec::new();
+        let mut i = 0;
+
+        while i < data.len() {
+            if i + width > data.len() {
+                packets.push(data[i..].to_vec());
+                break;
+            }
+
+            let mut buf = [0u8; 8];
+            let len = if big_endian {
+                buf[8 - width..].copy_from_slice(&data[i..i + width]);
+                u64::from_be_bytes(buf)
+            } else {
+                buf[..width].copy_from_slice(&data[i..i + width]);
+                u64::from_le_bytes(buf)
+            } as usize;
+
+            let end = std::cmp::min(data.len(), (i + width).saturating_add(len));
+            packets.push(data[i..end].to_vec());
+            i = end;
+        }
+
+        packets
+    }
+}
+
+/// Transport layer protocol of a [`TransportSegment`]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TransportProtocol {
+    /// TCP
+    Tcp,
+    /// UDP
+    Udp,
+}
+
+/// A TCP or UDP segment dissected from a captured frame by [`capture_segments()`].
+#[derive(Clone, Debug)]
+pub struct TransportSegment {
+    /// Whether this is TCP or UDP
+    pub protocol: TransportProtocol,
+    /// Source port
+    pub src_port: u16,
+    /// Destination port
+    pub dst_port: u16,
+    /// Whether this is a SYN without ACK, i.e. the client initiates a connection
+    pub syn: bool,
+    /// Whether FIN or RST is set, i.e. one side closes the connection
+    pub fin: bool,
+    /// The payload of the segment
+    pub payload: Vec<u8>,
+}
+
+#[inline]
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_be_bytes([*data.get(offset)?, *data.get(offset + 1)?]))
+}
+
+/// Dissect an IPv4 or IPv6 packet down to its TCP or UDP segment.
+fn dissect_ip(packet: &[u8]) -> Option<TransportSegment> {
+    let (protocol, payload) = match packet.first()? >> 4 {
+        4 => {
+            let header_len = (*packet.first()? as usize & 0xF) * 4;
+            let total_len = (read_u16(packet, 2)? as usize).min(packet.len());
+            (*packet.get(9)?, packet.get(header_len..total_len)?)
+        },
+        6 => {
+            let total_len = (40 + read_u16(packet, 4)? as usize).min(packet.len());
+            (*packet.get(6)?, packet.get(40..total_len)?)
+        },
+        _ => return None,
+    };
+
+    match protocol {
+        6 => {
+            let header_len = (*payload.get(12)? as usize >> 4) * 4;
+            let flags = *payload.get(13)?;
+
+            Some(TransportSegment {
+                protocol: TransportProtocol::Tcp,
+                src_port: read_u16(payload, 0)?,
+                dst_port: read_u16(payload, 2)?,
+                syn: flags & 0x12 == 0x02,
+                fin: flags & 0x05 != 0,
+                payload: payload.get(header_len..)?.to_vec(),
+            })
+        },
+        17 => Some(TransportSegment {
+            protocol: TransportProtocol::Udp,
+            src_port: read_u16(payload, 0)?,
+            dst_port: read_u16(payload, 2)?,
+            syn: false,
+            fin: false,
+            payload: payload.get(8..)?.to_vec(),
+        }),
+        _ => None,
+    }
+}
+
+/// Dissect an ethernet frame down to its TCP or UDP segment.
+/// Returns `None` if the frame does not contain either.
+fn dissect_ethernet(frame: &[u8]) -> Option<TransportSegment> {
+    let mut offset = 12;
+    let mut ethertype = read_u16(frame, offset)?;
+
+    // Skip VLAN tags
+    while ethertype == 0x8100 || ethertype == 0x88A8 {
+        offset += 4;
+        ethertype = read_u16(frame, offset)?;
+    }
+
+    dissect_ethertype(ethertype, frame.get(offset + 2..)?)
+}
+
+fn dissect_ethertype(ethertype: u16, packet: &[u8]) -> Option<TransportSegment> {
+    match ethertype {
+        0x0800 | 0x86DD => dissect_ip(packet),
+        _ => None,
+    }
+}
+
+/// Dissect an unencrypted 802.11 data frame with an LLC/SNAP header
+fn dissect_ieee802_11(frame: &[u8]) -> Option<TransportSegment> {
+    let frame_control = read_u16(frame, 0)?;
+    let (kind, subtype, flags) = ((frame_control >> 10) & 0x3, (frame_control >> 12) & 0xF, frame_control & 0xFF);
+
+    // Only data frames that are not protected
+    if kind != 2 || flags & 0x40 != 0 {
+        return None;
+    }
+
+    let mut offset = 24;
+
+    // Four addresses if ToDS and FromDS are set
+    if flags & 0x3 == 0x3 {
+        offset += 6;
+    }
+
+    // QoS control
+    if subtype & 0x8 != 0 {
+        offset += 2;
+    }
+
+    if frame.get(offset..offset + 6)? != [0xAA, 0xAA, 0x03, 0, 0, 0] {
+        return None;
+    }
+
+    dissect_ethertype(read_u16(frame, offset + 6)?, frame.get(offset + 8..)?)
+}
+
+/// Dissect a captured frame of any common link type down to its TCP or UDP segment.
+/// Returns `None` if the frame does not contain either or the link type is not supported.
+///
+/// Supported link types are Ethernet, raw IP, BSD loopback, Linux cooked captures (v1 and v2),
+/// which is what capturing on the `any` interface produces, and unencrypted 802.11 with or without radiotap headers.
+pub fn dissect_frame(linktype: Linktype, frame: &[u8]) -> Option<TransportSegment> {
+    match linktype {
+        Linktype::ETHERNET => dissect_ethernet(frame),
+        Linktype::RAW | Linktype::IPV4 | Linktype::IPV6 => dissect_ip(frame),
+        Linktype::NULL | Linktype::LOOP => dissect_ip(frame.get(4..)?),
+        Linktype::LINUX_SLL => dissect_ethertype(read_u16(frame, 14)?, frame.get(16..)?),
+        Linktype::LINUX_SLL2 => dissect_ethertype(read_u16(frame, 0)?, frame.get(20..)?),
+        Linktype::IEEE802_11 => dissect_ieee802_11(frame),
+        Linktype::IEEE802_11_RADIOTAP => {
+            let header_len = u16::from_le_bytes([*frame.get(2)?, *frame.get(3)?]) as usize;
+            dissect_ieee802_11(frame.get(header_len..)?)
+        },
+        _ => None,
+    }
+}
+
+/// Returns whether [`dissect_frame()`] can handle frames of the given link type
+fn is_supported_linktype(linktype: Linktype) -> bool {
+    matches!(linktype, Linktype::ETHERNET | Linktype::RAW | Linktype::IPV4 | Linktype::IPV6 | Linktype::NULL | Linktype::LOOP | Linktype::LINUX_SLL | Linktype::LINUX_SLL2 | Linktype::IEEE802_11 | Linktype::IEEE802_11_RADIOTAP)
+}
+
+/// Dissect all frames of a capture and return the TCP and UDP segments in order.
+///
+/// The link type of the capture is detected automatically, see [`dissect_frame()`].
+/// Use this to implement [`HasPcapRepresentation`] without parsing the frames yourself.
+pub fn capture_segments(mut capture: Capture<Offline>) -> Vec<TransportSegment> {
+    let linktype = capture.get_datalink();
+    let mut segments = Vec::new();
+
+    if !is_supported_linktype(linktype) {
+        println!("[butterfly] Unsupported link type {:?} in pcap, skipping", linktype);
+        return segments;
+    }
+
+    while let Ok(packet) = capture.next() {
+        if let Some(segment) = dissect_frame(linktype, packet.data) {
+            segments.push(segment);
+        }
+    }
+
+    segments
+}
+
+/// Returns the payloads the client sent to the server over the first TCP connection in a capture.
+///
+/// The first connection is the one opened by the first SYN. If the handshake was not captured,
+/// the first TCP segment with a payload determines the connection.
+///
+/// # Example
+/// ```
+/// impl HasPcapRepresentation<FtpInput> for FtpInput {
+///     fn from_pcap(capture: Capture<Offline>) -> Result<Self, Error> {
+///         let payloads = first_tcp_connection(&capture_segments(capture));
+///         // parse the payloads...
+///     }
+/// }
+/// ```
+pub fn first_tcp_connection(segments: &[TransportSegment]) -> Vec<Vec<u8>> {
+    let tcp = || segments.iter().filter(|s| s.protocol == TransportProtocol::Tcp);
+    let ports = match tcp().find(|s| s.syn).or_else(|| tcp().find(|s| !s.payload.is_empty())) {
+        Some(first) => (first.src_port, first.dst_port),
+        None => return Vec::new(),
+    };
+    let mut payloads = Vec::new();
+
+    for segment in tcp().filter(|s| (s.src_port, s.dst_port) == ports) {
+        if !segment.payload.is_empty() {
+            payloads.push(segment.payload.clone());
+        }
+
+        if segment.fin {
+            break;
+        }
+    }
+
+    payloads
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct PairInput {
+        packets: Vec<u8>,
+    }
+
+    impl HasPackets<u8> for PairInput {
+        fn packets(&self) -> &[u8] {
+            &self.packets
+        }
+
+        fn packets_mut(&mut self) -> &mut Vec<u8> {
+            &mut self.packets
+        }
+    }
+
+    #[test]
+    fn test_packets_pair_mut() {
+        let mut input = PairInput {
+            packets: vec![0, 1, 2],
+        };
+
+        let (a, b) = input.packets_pair_mut(2, 0).unwrap();
+        std::mem::swap(a, b);
+        assert_eq!(input.packets, vec![2, 1, 0]);
+
+        let (a, b) = input.packets_pair_mut(1, 0).unwrap();
+        *a += *b;
+        assert_eq!(input.packets, vec![2, 3, 0]);
+
+        assert!(input.packets_pair_mut(1, 1).is_none());
+        assert!(input.packets_pair_mut(0, 3).is_none());
+        assert!(input.packets_pair_mut(3, 0).is_none());
+    }
+
+    #[test]
+    fn test_delimiter_splitter() {
+        let mut splitter = delimiter_splitter(b"\r\n");
+        assert_eq!(splitter(b"USER a\r\nPASS b\r\n"), vec![b"USER a\r\n".to_vec(), b"PASS b\r\n".to_vec()]);
+        assert_eq!(splitter(b"USER a\r\nQUIT"), vec![b"USER a\r\n".to_vec(), b"QUIT".to_vec()]);
+        assert!(splitter(b"").is_empty());
+    }
+
+    #[test]
+    fn test_length_prefix_splitter() {
+        let mut splitter = length_prefix_splitter(2, true);
+        assert_eq!(splitter(b"\x00\x01A\x00\x02BC"), vec![b"\x00\x01A".to_vec(), b"\x00\x02BC".to_vec()]);
+        assert_eq!(splitter(b"\x00\x05AB"), vec![b"\x00\x05AB".to_vec()]);
+
+        let mut splitter = length_prefix_splitter(4, false);
+        assert_eq!(splitter(b"\x01\x00\x00\x00A\x00"), vec![b"\x01\x00\x00\x00A".to_vec(), b"\x00".to_vec()]);
+    }
+
+    #[test]
+    fn test_pcap_loader() {
+        let root = std::env::temp_dir().join(format!("butterfly-pcap-loader-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(root.join("a/b")).unwrap();
+
+        for (name, size) in [("x.pcap", 10), ("y.PCAPNG", 10), ("z.txt", 10), ("empty.pcap", 0), ("big.pcap", 100), ("a/x.pcap", 10), ("a/b/x.pcap", 10)] {
+            std::fs::write(root.join(name), vec![0; size]).unwrap();
+        }
+
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(&root, root.join("a/b/loop")).unwrap();
+
+        let find = |loader: PcapLoader| {
+            let mut files: Vec<String> = loader.find(&root).unwrap().iter().map(|path| path.strip_prefix(&root).unwrap().display().to_string()).collect();
+            files.sort();
+            files
+        };
+
+        assert_eq!(find(PcapLoader::new()), ["a/b/x.pcap", "a/x.pcap", "big.pcap", "x.pcap", "y.PCAPNG"]);
+        assert_eq!(find(PcapLoader::new().with_max_depth(1).with_max_file_size(50)), ["a/x.pcap", "x.pcap", "y.PCAPNG"]);
+        assert_eq!(find(PcapLoader::new().with_max_depth(0).with_extensions(&["TXT"])), ["z.txt"]);
+
+        let _ = std::fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn test_dissect_ethernet() {
+        let mut frame = vec![0; 12];
+        // ethertype, IPv4 header with protocol TCP
+        frame.extend_from_slice(&[0x08, 0x00, 0x45, 0, 0, 46, 0, 0, 0, 0, 64, 6, 0, 0, 127, 0, 0, 1, 127, 0, 0, 1]);
+        // TCP header from port 1234 to 21 with PSH|ACK
+        frame.extend_from_slice(&[0x04, 0xD2, 0x00, 0x15, 0, 0, 0, 0, 0, 0, 0, 0, 0x50, 0x18, 0, 0, 0, 0, 0, 0]);
+        frame.extend_from_slice(b"QUIT\r\n");
+        // ethernet padding
+        frame.extend_from_slice(&[0; 4]);
+
+        let segment = dissect_ethernet(&frame).unwrap();
+        assert_eq!(segment.protocol, TransportProtocol::Tcp);
+        assert_eq!((segment.src_port, segment.dst_port), (1234, 21));
+        assert!(!segment.syn && !segment.fin);
+        assert_eq!(segment.payload, b"QUIT\r\n");
+    }
+
+    #[test]
+    fn test_dissect_frame() {
+        // IPv6 header with protocol UDP
+        let mut packet = vec![0x60, 0, 0, 0, 0, 10, 17, 64];
+        packet.extend_from_slice(&[0; 32]);
+        // UDP header from port 5353 to 53
+        packet.extend_from_slice(&[0x14, 0xE9, 0x00, 0x35, 0, 10, 0, 0]);
+        packet.extend_from_slice(b"hi");
+
+        let mut sll = vec![0; 14];
+        sll.extend_from_slice(&[0x86, 0xDD]);
+        sll.extend_from_slice(&packet);
+
+        let mut wifi = vec![0x88, 0x01];
+        wifi.extend_from_slice(&[0; 24]);
+        wifi.extend_from_slice(&[0xAA, 0xAA, 0x03, 0, 0, 0, 0x86, 0xDD]);
+        wifi.extend_from_slice(&packet);
+
+        let mut radiotap = vec![0, 0, 8, 0, 0, 0, 0, 0];
+        radiotap.extend_from_slice(&wifi);
+
+        for (linktype, frame) in [(Linktype::RAW, &packet), (Linktype::LINUX_SLL, &sll), (Linktype::IEEE802_11, &wifi), (Linktype::IEEE802_11_RADIOTAP, &radiotap)] {
+            let segment = dissect_frame(linktype, frame).unwrap();
+            assert_eq!(segment.protocol, TransportProtocol::Udp);
+            assert_eq!((segment.src_port, segment.dst_port), (5353, 53));
+            assert_eq!(segment.payload, b"hi");
+        }
+
+        assert!(dissect_frame(Linktype::PPP, &packet).is_none());
+    }
+
+    #[test]
+    fn test_path_trie() {
+        let mut trie = PathTrie::default();
+        assert!(!trie.covers(&[1]));
+
+        trie.insert(&[1, 2, 3]);
+        trie.insert(&[1, 4]);
+
+        assert!(trie.covers(&[]));
+        assert!(trie.covers(&[1]));
+        assert!(trie.covers(&[1, 2]));
+        assert!(trie.covers(&[1, 2, 3]));
+        assert!(trie.covers(&[1, 4]));
+        assert!(!trie.covers(&[1, 2, 3, 4]));
+        assert!(!trie.covers(&[1, 3]));
+        assert!(!trie.covers(&[2]));
+    }
+}
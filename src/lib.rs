@@ -17,28 +17,181 @@
 //!   - In order to create a new, working input type you MUST implement the following traits:       
 //!   [`Hash`](core::hash::Hash), [`Debug`](core::fmt::Debug), [`Clone`](core::clone::Clone), [`Serialize`](serde::Serialize), [`Deserialize`](serde::Deserialize), [`Input`](libafl::inputs::Input)     
 //!   - To make it usable by other butterfly components, implement [`HasPackets`], [`HasLen`](libafl::bolts::HasLen)
-//!   - If you want to load it from a PCAP file, implement [`HasPcapRepresentation`]
+//!   - If you want to load it from a PCAP file, implement [`HasPcapRepresentation`].
+//!     [`capture_segments`] and [`first_tcp_connection`] help with extracting the payloads
+//!   - If you want to load it from plain files, implement [`HasRawRepresentation`] and use [`load_raw_seeds`].
+//!     The same trait lets a [`RecordingProxy`] record the sessions of a real client with the target into a corpus directory
+//!   - To keep the initial corpus small, [`load_pcaps_deduplicated`] and [`load_raw_seeds_deduplicated`]
+//!     skip seeds whose state path is already covered by a [`SeedDeduplicator`]
+//!   - [`TraceSynthesizer`] generates new seeds by walking the state-graph and concatenating the packets
+//!     that took the target along each transition in existing inputs
+//!   - [`StatisticalGenerator`] generates new seeds from the packet types per position and payload lengths
+//!     that it learned from existing inputs, for targets without a grammar
+//!   - For line-based text protocols like FTP or SMTP, [`TextLinePacket`] is a ready-made packet type
+//!     that implements all mutation traits. Its keywords are taken from a [`KeywordDictionary`]
+//!   - For strictly structured protocols, describe the messages with a [`Grammar`] and use [`GrammarPacket`]s
 //! - **Mutators**
 //!   - havoc: [`PacketHavocMutator`] gets a list of havoc mutators and uses [`HasHavocMutation`] to mutate a selected packet.      
 //!     Not all of libafls havoc mutators work with packet-based inputs, though. [`supported_havoc_mutations`] gives you all havoc
-//!     mutators that work
+//!     mutators that work. Packets that implement [`HasRegions`] can have the mutations confined to one region at a time.
+//!     With [`PacketHavocMutator::with_max_packets()`] it mutates multiple packets per run.
+//!     For text protocols, [`supported_text_havoc_mutations`] only writes printable characters, keeps CRLF terminators
+//!     intact and mutates digits as numbers
+//!   - kind conversion: [`PacketKindConversionMutator`] turns a packet into another kind, e.g. a `CWD` into a `LIST`
+//!     with the same argument, see [`HasKindConversion`]
+//!   - numeric fields: [`PacketFieldMutator`] applies arithmetic, interesting-value and boundary mutations
+//!     to the typed integer fields that binary packets expose via [`HasNumericFieldMutation`]
+//!   - byte-backed packets: every packet type that implements LibAFLs [`HasBytesVec`](libafl::inputs::HasBytesVec),
+//!     e.g. a newtype around a byte vector, supports havoc, crossover and splice mutations out of the box.
+//!     Byte ranges like magic numbers or session cookies that a packet declares via [`HasMutationMask`] are never changed
+//!   - packet selection: mutators that target a packet choose it with a [`PacketSelector`], uniformly by default.
+//!     [`PacketSelection`] biases the choice towards the end of an input, [`LengthSelector`] towards large packets and
+//!     [`NoveltySelector`] towards positions where the [`TransitionNoveltyFeedback`] saw new transitions
 //!   - packet-mutators:
 //!     - [`PacketDeleteMutator`], [`PacketDuplicateMutator`], [`PacketReorderMutator`]
+//!     - [`PacketRotateMutator`] rotates or shifts a whole window of packets
+//!     - [`PacketShuffleMutator`] shuffles a random window of packets
+//!     - [`PacketDuplicateHavocMutator`] duplicates a packet and mutates the copy with havoc mutations
+//!     - [`PacketTruncateMutator`] cuts off a random suffix of packets
+//!     - [`PacketFragmentMutator`] splits a packet into two, see [`HasSplit`]
+//!     - [`PacketMergeMutator`] merges two adjacent packets into one, see [`HasMerge`]
+//!     - [`PacketReconnectMutator`] inserts `Disconnect` and `Connect` pseudo-packets
+//!     - [`PacketTeardownMutator`] disconnects abruptly, reorders or extends the final packets of a session
+//!     - [`PacketInsertMutator`] inserts new packets that the packet type creates via [`HasPacketGenerator`]
+//!   - packet bounds: the structural mutators keep the number of packets within the [`PacketBounds`] of the state,
+//!     if there are any. [`PacketMutationScheduler::with_bounds()`] declares them once for all mutators. The [`PacketBoundsStage`] adapts them with a [`PacketBoundsPolicy`], e.g. the [`StallGrowthPolicy`]
+//!     allows longer inputs once the state graph stops growing
+//!   - terminal packets: the duplicate, insert and reorder mutators can keep packets from ending up after a packet
+//!     that ends the session like a `QUIT`, see [`HasTerminalPacket`] and [`TerminalHandling`]
 //!   - crossover mutators:
 //!     - [`PacketCrossoverInsertMutator`] and [`PacketCrossoverReplaceMutator`]
 //!   - splicing mutators:
-//!     - [`PacketSpliceMutator`]
+//!     - [`PacketSpliceMutator`], optionally only at delimiters like `\r\n`, see [`HasDelimitedSpliceMutation`]
+//!     - [`PacketSuffixSpliceMutator`] keeps a prefix of the input and appends the suffix of another corpus entry
+//!       that went through the same state, like AFLNet. It needs the state paths that the [`StatePathFeedback`] records
+//!   - grammar mutators:
+//!     - [`GrammarPacketMutator`] mutates the parse trees of packets within a [`Grammar`], see [`HasGrammarMutation`]
+//!   - tokens: [`TokenExtractor`] fills LibAFLs token dictionary with frequent words and magic bytes
+//!     from the corpus, [`TokenExtractionStage`] repeats that periodically during fuzzing.
+//!     [`load_tokens()`] adds the tokens of an AFL-style dictionary file.
+//!     [`supported_havoc_mutations_with_tokens()`] adds the token mutators to the havoc mutations
+//!   - fixups: packets that implement [`HasPostMutationFixup`] repair length fields, terminators etc.
+//!     after they have been mutated. The [`PacketMutationScheduler`] can additionally apply fixups to whole inputs.
+//!     The [`fixups`] module has checksums and length-field helpers for writing them
+//!   - scheduling: the [`PacketMutationScheduler`] picks one mutator per run, uniformly or
+//!     biased by weights given with [`PacketMutationScheduler::with_weights()`] or by the success rates it
+//!     learns with [`PacketMutationScheduler::with_adaptive_selection()`]
+//!   - power schedule: the [`CostAwareMutationalStage`] gives testcases that are more expensive to execute
+//!     than the average fewer mutations, so that long sessions don't monopolize the campaign
+//!   - phases: the [`PhaseStage`] alternates between exploration and exploitation in time slices.
+//!     The [`PhaseScheduler`] picks corpus entries and the [`PacketMutationScheduler`] picks mutators that fit the current [`Phase`]
+//! - **Executor**
+//!   - [`NetworkExecutor`] sends packets to a target over TCP and records the states
+//!     it infers from the responses. Packets must implement [`HasPayload`]
+//!   - Connection management can be part of an input by implementing [`HasConnectionEvents`]
+//!     or wrapping the packet type in a [`NetworkPacket`]
+//!   - Capabilities are added as [`ExecutorMiddleware`] layers around the transport loop:
+//!     - [`Throttle`] limits the rate of executions and connections and slows down
+//!       when the target reports that it is overloaded
+//!     - [`PacketLogger`] prints all traffic, [`PcapRecorder`] records it into pcap files
+//!     - [`FaultInjector`] drops, truncates or corrupts packets
+//!     - [`TokenSubstitution`] inserts session tokens from responses into later packets
+//!   - Targets behind a jump host are reached through a SOCKS5 or HTTP CONNECT [`TransportProxy`]
+//!   - Executions can be spread over multiple instances of a target with [`NetworkExecutor::with_targets()`]
+//!   - Refused connections, resets and timeouts can be recorded as synthetic states with
+//!     [`NetworkExecutor::with_transport_states()`], see [`TransportEvent`]
+//!   - Packets that implement [`HasValidityOracle`] can tell whether the target accepted them.
+//!     The executor counts them in the [`ValidityMetadata`] of the state, from where
+//!     the [`PacketMutationScheduler`] learns to penalize mutators whose outputs get rejected
 //! - **Observer**
 //!   - [`StateObserver`] builds a state-graph
+//!   - The state-graph can be inspected with [`StateObserver::nodes()`], [`StateObserver::edges()`],
+//!     [`StateObserver::successors()`] and [`StateObserver::predecessors()`]
+//!   - Structural [`GraphMetrics`] like the depth, the strongly connected components and the dead ends of the state-graph
+//!     are computed by [`StateObserver::metrics()`]
+//!   - It counts how often every state and transition was hit, see [`StateObserver::node_hits()`] and [`StateObserver::edge_hits()`].
+//!     [`StateFeedback::with_hit_buckets()`] rewards rarely taken transitions that get taken again
+//!   - With [`StateObserver::with_ngrams()`] sequences of the last N states count as coverage, so that known transitions
+//!     taken in a new order are rewarded, too
+//!   - [`StateObserver::loop_ratio()`] tells how much of a run went around the same cycles again,
+//!     [`StateFeedback::with_loop_limit()`] uses it to dampen loop-heavy inputs
+//!   - The state-graph can be exported in the DOT format with [`StateObserver::get_statemachine()`], labeled with the
+//!     states or custom labels from [`StateObserver::with_node_labels()`], or as a Mermaid diagram for Markdown
+//!     with [`StateObserver::to_mermaid()`] or in the GraphML format with [`StateObserver::to_graphml()`]
+//!   - [`StateObserver::record_with_context()`] annotates transitions with the packets that caused them,
+//!     which shows up as edge labels in the DOT and GraphML exports
+//!   - The state-graph can be exported as a Mealy machine for automata-learning tools
+//!     with [`StateObserver::get_mealy_machine()`]
+//!   - [`StateObserver::with_log_capture()`] attaches the tail of a log file of the target to every new state
+//!   - Observers that implement [`HasTargetRestart`] are told when the target process is restarted
+//!     mid-campaign, e.g. via [`notify_target_restart()`]. [`StateObserver::with_restart_marker()`]
+//!     records restarts as a marker state, otherwise the state after a restart counts as an entry state
+//!   - [`StateObserver::with_node_budget()`] caps the size of the state-graph for noisy states and ignores, merges or
+//!     evicts states beyond the budget according to a [`NodeBudgetPolicy`]
+//!   - For large states [`StateObserver::with_node_storage()`] with [`NodeStorage::Hashed`] finds the nodes
+//!     by a 64-bit hash of the states instead of comparing them
+//!   - Harnesses that keep the connection to the target across executions connect the runs in the state-graph
+//!     with [`StateObserver::with_reset_mode()`] and [`ResetMode::KeepLastState`]
+//!   - [`StateMaskLearner`] learns which bits of byte-array states are volatile and creates a [`StateMask`]
+//!     that removes them before they reach the state-graph
 //!   - The executor is responsible for calling [`StateObserver::record()`] with state information inferred from
 //!     the fuzz target
+//!   - Executors that get responses from the target can pass them to [`StateObserver::record_response()`]
+//!     and let a [`ResponseStateInference`] derive the states, e.g. from numeric status codes or a hash of a header
+//!   - With [`StateObserver::with_latency_buckets()`] the response time of the target becomes part of the state,
+//!     so that timing anomalies like retry loops or backoffs show up as new states
+//!   - States in which the conversation is over, like error replies or a closed connection, can be marked with a [`StateMark`]
+//!     via [`StateObserver::mark_state()`] or [`StateObserver::with_state_marks()`]. They stand out in the exports and
+//!     [`StateObserver::first_mark()`] tells how quickly an input dead-ended
+//!   - If the target runs in a forked child process, [`StateObserver::with_shared_memory()`] passes the states
+//!     that are recorded there to the parent
+//!   - With [`StateObserver::with_raw_states()`] the executor records raw states via [`StateObserver::record_raw()`]
+//!     and an abstraction decides which parts of them end up in the state-graph
 //! - **Feedback**
 //!   - [`StateFeedback`] determines if a [`StateObserver`] has seen new states in the last run
+//!   - [`MultiStateFeedback`] does the same for several independent state signals of a target, each recorded
+//!     by its own [`StateObserver`] and declared as a [`StateChannel`], so that new transitions in any of them count
+//!   - [`StatePathFeedback`] attaches the exact sequence of states that an input went through as [`StatePathMetadata`]
+//!     to corpus entries and solutions
+//!   - [`ValidityFeedback`] reports the rate of accepted packets to the monitor
+//!   - [`MutatorStatsFeedback`] reports how many outputs of each mutator were interesting to the monitor
+//!   - [`ConformanceFeedback`] is an objective for responses that violate the rules of a [`ConformanceChecker`],
+//!     e.g. "PASS must be answered with 230 or 530", which catches spec violations that don't crash the target
+//!   - [`ProvenanceFeedback`] attaches the chain of mutations that produced a testcase as [`ProvenanceMetadata`],
+//!     which [`dump_provenance()`] prints for any entry of the corpus or the solutions
+//!   - [`HangFeedback`] is an objective for hangs that only reports timeouts that reproduce in the same state
 //! - **Monitor**
 //!   - butterfly provides a [`StateMonitor`] that prints information about the state-graph in addition to
 //!     all the other info
 //!   - if you want to use a different monitor but still want to get state-graph information you can
 //!     implement [`HasStateStats`]
+//!   - custom user stats registered with [`register_user_stat()`] are displayed automatically
+//! - **Validation**
+//!   - [`validate_harness()`] does a dry-run of a seed and reports common misconfigurations
+//!     of the harness in a [`HarnessReport`]
+//!   - [`validate_seeds()`] loads a corpus like [`load_pcaps`] and reports what each seed
+//!     contributed to the state-graph in a [`SeedReport`]
+//!   - [`compare_targets()`] replays a corpus against two versions of a target and reports
+//!     every input whose behavior changed in a [`RegressionReport`]
+//! - **Triage**
+//!   - [`TriageFeedback`] wraps an objective feedback and invokes a [`CrashTriageHook`] for every saved objective
+//!   - [`BundleTriageHook`] and [`ScriptTriageHook`] are ready-made hooks
+//!   - [`MultiObjectiveFeedback`] evaluates several prioritized [`Objective`]s and tags every solution
+//!     with the ones that matched
+//! - **Checkpoints**
+//!   - [`Checkpoints`] saves the fuzzer state and the state-graph under a name and restores them later,
+//!     so that long campaigns can be branched
+//!   - [`ControlStage`] lets an operator pause and resume a running campaign, export the state-graph,
+//!     change the [`MutatorWeights`] or save checkpoints by appending commands to a file
+//!   - [`CampaignConfig`] records the effective configuration of the scheduler, the mutators and the executor
+//!     via [`HasConfig`] in the state, so that it is saved with every checkpoint and results can be attributed to it
+//! - **Multi-core campaigns**
+//!   - The [`PcapRecorder`], the triage hooks, [`Checkpoints`] and the [`ControlStage`] write into separate
+//!     directories or files per client when given the core id of a LibAFL `Launcher` client via `with_client_id()`,
+//!     see [`client_dir()`] and [`client_file()`]
+//!   - The [`GraphSyncStage`] shares the state-graph between the clients via the broker, so that a state that one client
+//!     discovered is not new to the others anymore. Graphs can also be merged by hand with [`StateObserver::export_graph()`]
+//!     and [`StateObserver::merge_graph()`]
 //!
 //! # Features
 //! - `graphviz`
@@ -46,6 +199,18 @@
 //! - `safe_only`
 //!   - By default butterfly uses some unsafe code for performance reasons
 //!     but this can be disabled with this feature
+//! - `protocol_ftp`, `protocol_dns`, `protocol_mqtt`, ...
+//!   - Add ready-made packet and input types for these protocols in the [`protocols`] module.
+//!     See its documentation for a list of all protocols. `protocols` enables all of them
+//! - `radamsa`
+//!   - Adds the [`PacketRadamsaMutator`], a heavy, generic mutator with pure-Rust versions of Radamsas mutations
+//!     for targets where the structured havoc mutations plateau
+//! - `toy_target`
+//!   - Adds [`ToyFtpServer`], a tiny FTP-like server running in a background thread
+//!     that can be used to test harnesses without an external target
+//! - `ffi`
+//!   - Adds the `ffi` module with a C API and the `FfiExecutor` so that harnesses written in C or C++
+//!     can feed states into a butterfly fuzzer. Has no effect together with `safe_only`
 //!
 //! # Tutorials, examples and more...
 //! ... can be found in our [repository](https://github.com/fkie-cad/butterfly) and [wiki](https://github.com/fkie-cad/butterfly/wiki).
@@ -56,28 +221,98 @@
 #![feature(test)]
 #![cfg_attr(feature = "safe_only", forbid(unsafe_code))]
 
+mod autodict;
+mod channel;
+mod checkpoint;
+mod config;
+mod conformance;
+mod control;
 mod event;
+mod executor;
 mod feedback;
+#[cfg(all(feature = "ffi", not(feature = "safe_only")))]
+pub mod ffi;
+pub mod fixups;
+mod grammar;
+mod inference;
 mod input;
+mod mask;
 mod monitor;
 mod mutators;
+mod objective;
 mod observer;
+mod output;
+mod phase;
+mod power;
+pub mod protocols;
+mod provenance;
+mod proxy;
+mod regression;
 mod scheduler;
-
-pub use event::{USER_STAT_EDGES, USER_STAT_NODES};
-pub use feedback::StateFeedback;
-pub use input::{load_pcaps, HasPackets, HasPcapRepresentation};
+mod sync;
+mod synthesis;
+#[cfg(test)]
+mod test_utils;
+mod text;
+#[cfg(feature = "toy_target")]
+mod toy;
+mod triage;
+mod validate;
+
+pub use autodict::{load_tokens, TokenExtractionStage, TokenExtractor};
+pub use channel::{MultiStateFeedback, StateChannel, StateChannelsTuple};
+pub use checkpoint::Checkpoints;
+pub use config::{CampaignConfig, ComponentConfig, ConfigTuple, HasConfig};
+pub use conformance::{ConformanceChecker, ConformanceFeedback, ConformanceMetadata, ConformanceViolation};
+pub use control::ControlStage;
+pub use event::{
+    register_user_stat, registered_user_stats, RegisteredUserStat, UserStatFormat, USER_STAT_BUDGET_OVERFLOWS, USER_STAT_EDGES, USER_STAT_GROWTH_THROTTLED, USER_STAT_MUTATOR_PREFIX, USER_STAT_NODES, USER_STAT_PACKET_ACCEPTANCE, USER_STAT_PHASE,
+};
+pub use executor::{
+    ConnectionEvent, ExecutorMiddleware, FaultInjector, HasConnectionEvents, HasPayload, HasValidityOracle, MiddlewareChain, NetworkExecutor, NetworkPacket, PacketLogger, PcapRecorder, TargetSelection, Throttle, TokenSubstitution, TransportEvent,
+    TransportProxy, Validity, ValidityMetadata, Verdict,
+};
+pub use feedback::{HangFeedback, HangMetadata, MutatorStatsFeedback, StateFeedback, StatePathFeedback, StatePathMetadata, TransitionNoveltyFeedback, ValidityFeedback};
+pub use grammar::{Endianness, Field, Grammar, GrammarNode, GrammarPacket, GrammarPacketMutator, HasGrammarMutation};
+pub use inference::ResponseStateInference;
+pub use input::{
+    capture_segments, delimiter_splitter, dissect_frame, first_tcp_connection, length_prefix_splitter, load_pcaps, load_pcaps_deduplicated, load_raw_seeds, load_raw_seeds_deduplicated, HasPackets, HasPcapRepresentation, HasRawRepresentation, PcapLoader,
+    SeedDeduplicator, TransportProtocol, TransportSegment,
+};
+pub use mask::{StateMask, StateMaskLearner};
 pub use monitor::{HasStateStats, StateMonitor};
 pub use mutators::{
-    supported_havoc_mutations, HasCrossoverInsertMutation, HasCrossoverReplaceMutation, HasHavocMutation, HasSpliceMutation, PacketCrossoverInsertMutator, PacketCrossoverReplaceMutator, PacketDeleteMutator, PacketDuplicateMutator, PacketHavocMutator,
-    PacketReorderMutator, PacketSpliceMutator, SupportedHavocMutationsType,
+    supported_havoc_mutations, supported_havoc_mutations_with_tokens, supported_text_havoc_mutations, CaseFlipMutator, FieldWidth, HasCrossoverInsertMutation, HasCrossoverReplaceMutation, HasDelimitedSpliceMutation, HasHavocMutation, HasKindConversion,
+    HasMerge, HasMutationMask, HasNumericFieldMutation, HasPacketGenerator, HasPostMutationFixup, HasRegions, HasSpliceMutation, HasSplit, HasTerminalPacket, LengthSelector, NoveltySelector, NumericField, PacketBounds, PacketBoundsPolicy,
+    PacketBoundsStage, PacketCrossoverInsertMutator, PacketCrossoverReplaceMutator, PacketDeleteMutator, PacketDuplicateHavocMutator, PacketDuplicateMutator, PacketFieldMutator, PacketFragmentMutator, PacketHavocMutator, PacketInsertMutator,
+    PacketKindConversionMutator, PacketMergeMutator, PacketReconnectMutator, PacketReorderMutator, PacketRotateMutator, PacketSelection, PacketSelector, PacketShuffleMutator, PacketSpliceMutator, PacketSuffixSpliceMutator, PacketTeardownMutator,
+    PacketTruncateMutator, PrintableByteMutator, PrintableInsertMutator, StallGrowthPolicy, SupportedHavocMutationsType, SupportedHavocMutationsWithTokensType, SupportedTextHavocMutationsType, TerminalHandling, TextCopyMutator, TextDeleteMutator,
+    TextNumberMutator, TransitionNoveltyMetadata,
 };
-pub use observer::StateObserver;
-pub use scheduler::PacketMutationScheduler;
+pub use objective::{MultiObjectiveFeedback, Objective, ObjectiveMetadata, ObjectivesTuple};
+pub use observer::{notify_target_restart, GraphMetrics, HasTargetRestart, NodeBudgetPolicy, NodeStorage, ResetMode, StateMark, StateObserver, StateShMem};
+pub use output::{client_dir, client_file};
+pub use phase::{Phase, PhaseMetadata, PhaseScheduler, PhaseStage};
+pub use power::{CostAwareMutationalStage, ExecCostMetadata};
+pub use provenance::{dump_provenance, ProvenanceFeedback, ProvenanceMetadata, ProvenanceStep};
+pub use proxy::RecordingProxy;
+pub use regression::{compare_targets, BehaviorChange, InputTrace, RegressionReport};
+pub use scheduler::{MutatorStat, MutatorStatsMetadata, MutatorWeights, PacketMutationScheduler};
+pub use sync::{register_graph_sync, GraphSyncStage, GRAPH_SYNC_TAG};
+pub use synthesis::{StatisticalGenerator, TraceSynthesizer};
+pub use text::{KeywordDictionary, TextLinePacket};
+pub use triage::{BundleTriageHook, CrashTriageHook, ScriptTriageHook, TriageFeedback};
+pub use validate::{validate_harness, validate_seeds, HarnessReport, SeedCoverage, SeedReport};
 
 #[cfg(feature = "graphviz")]
 pub use {event::USER_STAT_STATEGRAPH, monitor::GraphvizMonitor};
 
+#[cfg(feature = "radamsa")]
+pub use mutators::{radamsa_mutations, BadStringMutator, FunnyUnicodeMutator, FuseMutator, LineDeleteMutator, LineRepeatMutator, LineSwapMutator, PacketRadamsaMutator, RadamsaMutationsType, SequenceRepeatMutator};
+
+#[cfg(feature = "toy_target")]
+pub use toy::ToyFtpServer;
+
 /// The tests below are just for checking that harnesses compile
 /// with the butterfly components. We don't actually want to execute
 /// any harness.
@@ -116,6 +351,8 @@ mod tests {
         B(BytesInput),
     }
 
+    impl HasPostMutationFixup for PacketType {}
+
     impl<S> HasCrossoverInsertMutation<S> for PacketType
     where
         S: HasRand + HasMaxSize,
@@ -325,8 +562,14 @@ mod tests {
             PacketDeleteMutator::new(4),
             PacketDuplicateMutator::new(16)
         ));
-        let mut stages = tuple_list!(StdMutationalStage::new(mutator));
+        let mut stages = tuple_list!(ControlStage::<_, _, TargetState>::new("control", "state"), StdMutationalStage::new(mutator));
         let mut executor = ExampleExecutor::new(tuple_list!(state_observer));
+        let seed = PacketInput {
+            packets: vec![PacketType::A(BytesInput::new(b"A".to_vec()))],
+        };
+        let _report: HarnessReport<TargetState> = validate_harness(&mut fuzzer, &mut state, &mut executor, &mut mgr, &seed, "state").unwrap();
+        let mut candidate = ExampleExecutor::new(tuple_list!(StateObserver::<TargetState>::new("state")));
+        let _regressions: RegressionReport<_, TargetState> = compare_targets(&mut fuzzer, &mut state, &mut executor, &mut candidate, &mut mgr, std::slice::from_ref(&seed), "state").unwrap();
         fuzzer.fuzz_loop(&mut stages, &mut executor, &mut state, &mut mgr).unwrap();
     }
 
@@ -439,6 +682,8 @@ mod tests {
         ));
         let mut stages = tuple_list!(StdMutationalStage::new(mutator));
         let mut executor = RawExecutor::new(tuple_list!(state_observer));
+        let mut dedup = SeedDeduplicator::<TargetState>::new("state");
+        load_pcaps_deduplicated(&mut state, &mut fuzzer, &mut executor, &mut mgr, "pcaps", &mut dedup).unwrap();
         fuzzer.fuzz_loop(&mut stages, &mut executor, &mut state, &mut mgr).unwrap();
     }
 }
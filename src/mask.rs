@@ -0,0 +1,187 @@
+use crate::{observer::StateObserver, validate::execute_once};
+use libafl::{
+    executors::{Executor, HasObservers},
+    inputs::Input,
+    observers::ObserversTuple,
+    Error,
+};
+use serde::{Deserialize, Serialize};
+
+/// A bit mask that normalizes byte-array states by clearing their volatile bits,
+/// e.g. counters, timestamps or session ids that the target embeds in its state.
+///
+/// Volatile bits are the most common reason why the state-graph explodes: every execution
+/// creates new states although the target went through the same logical states.
+/// A mask is usually learned with a [`StateMaskLearner`] and applied to the states
+/// that the state inference function of the executor returns with [`wrap()`](StateMask::wrap).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct StateMask<const N: usize> {
+    mask: [u8; N],
+}
+
+impl<const N: usize> StateMask<N> {
+    /// Create a new StateMask. Bits that are set in `mask` are kept, all others are cleared.
+    pub fn new(mask: [u8; N]) -> Self {
+        Self {
+            mask,
+        }
+    }
+
+    /// Returns the bits that are kept
+    pub fn mask(&self) -> &[u8; N] {
+        &self.mask
+    }
+
+    /// Returns the number of bits that are cleared
+    pub fn volatile_bits(&self) -> usize {
+        self.mask.iter().map(|byte| byte.count_zeros() as usize).sum()
+    }
+
+    /// Clear the volatile bits of `state`
+    pub fn apply(&self, state: &[u8; N]) -> [u8; N] {
+        let mut ret = *state;
+
+        for (byte, mask) in ret.iter_mut().zip(&self.mask) {
+            *byte &= mask;
+        }
+
+        ret
+    }
+
+    /// Apply the mask to every state that `inference` returns.
+    ///
+    /// # Example
+    /// ```
+    /// let executor = NetworkExecutor::new(observers, addr, "state", mask.wrap(infer_state));
+    /// ```
+    pub fn wrap<F>(self, mut inference: F) -> impl FnMut(&[u8]) -> Option<[u8; N]>
+    where
+        F: FnMut(&[u8]) -> Option<[u8; N]>,
+    {
+        move |response| inference(response).map(|state| self.apply(&state))
+    }
+}
+
+/// Learns which bits of byte-array states are stable and which are volatile across
+/// executions of the same input and generates a [`StateMask`] from that.
+///
+/// Every input is executed multiple times and the states that were recorded at the same
+/// position of the path are compared. A bit that differs in any of the runs is volatile.
+/// Since the state-graph of the learning runs gets polluted by the volatile states,
+/// learn with a separate executor and [`StateObserver`] before fuzzing.
+///
+/// # Example
+/// ```
+/// let mut learner = StateMaskLearner::<16>::new();
+///
+/// for seed in &seeds {
+///     learner.learn(&mut fuzzer, &mut state, &mut learning_executor, &mut mgr, seed, "state", 5)?;
+/// }
+///
+/// let mask = learner.mask();
+/// let executor = NetworkExecutor::new(observers, addr, "state", mask.wrap(infer_state));
+/// ```
+#[derive(Clone, Debug)]
+pub struct StateMaskLearner<const N: usize> {
+    volatile: [u8; N],
+    samples: usize,
+}
+
+impl<const N: usize> StateMaskLearner<N> {
+    /// Create a new StateMaskLearner that considers all bits stable
+    pub fn new() -> Self {
+        Self {
+            volatile: [0; N],
+            samples: 0,
+        }
+    }
+
+    /// Compare the paths of multiple executions of the same input.
+    /// Paths of different lengths are compared up to the length of the shorter one.
+    pub fn observe(&mut self, paths: &[Vec<[u8; N]>]) {
+        let (first, others) = match paths.split_first() {
+            Some(split) => split,
+            None => return,
+        };
+
+        for other in others {
+            for (a, b) in first.iter().zip(other) {
+                for (volatile, (a, b)) in self.volatile.iter_mut().zip(a.iter().zip(b)) {
+                    *volatile |= a ^ b;
+                }
+
+                self.samples += 1;
+            }
+        }
+    }
+
+    /// Execute `input` `runs` times and [`observe()`](StateMaskLearner::observe) the paths that the
+    /// [`StateObserver`] with the name `observer_name` recorded
+    #[allow(clippy::too_many_arguments)]
+    pub fn learn<E, EM, I, S, Z, OT>(&mut self, fuzzer: &mut Z, state: &mut S, executor: &mut E, mgr: &mut EM, input: &I, observer_name: &str, runs: usize) -> Result<(), Error>
+    where
+        E: Executor<EM, I, S, Z> + HasObservers<I, OT, S>,
+        OT: ObserversTuple<I, S>,
+        I: Input,
+        [u8; N]: Serialize + for<'a> Deserialize<'a>,
+    {
+        let mut paths = Vec::with_capacity(runs);
+
+        for _ in 0..runs {
+            execute_once(fuzzer, state, executor, mgr, input)?;
+
+            let observer = executor.observers().match_name::<StateObserver<[u8; N]>>(observer_name).ok_or_else(|| Error::key_not_found(format!("No StateObserver named \"{}\" with the given state type", observer_name)))?;
+            paths.push(observer.path_states());
+        }
+
+        self.observe(&paths);
+        Ok(())
+    }
+
+    /// Returns the number of state pairs that have been compared so far
+    pub fn samples(&self) -> usize {
+        self.samples
+    }
+
+    /// Generate a mask that clears all bits that were volatile so far
+    pub fn mask(&self) -> StateMask<N> {
+        let mut mask = [0; N];
+
+        for (mask, volatile) in mask.iter_mut().zip(&self.volatile) {
+            *mask = !volatile;
+        }
+
+        let mask = StateMask::new(mask);
+        println!("[butterfly] Learned a state mask from {} samples: {} of {} bits are volatile", self.samples, mask.volatile_bits(), N * 8);
+        mask
+    }
+}
+
+impl<const N: usize> Default for StateMaskLearner<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_learn_mask() {
+        let mut learner = StateMaskLearner::<4>::new();
+
+        // The second byte is a counter, the last byte a flag in the lowest bit
+        learner.observe(&[vec![[1, 7, 0xAA, 0x10], [2, 8, 0xAA, 0x10]], vec![[1, 9, 0xAA, 0x11], [2, 10, 0xAA, 0x10]], vec![[1, 11, 0xAA, 0x10]]]);
+        assert_eq!(learner.samples(), 3);
+
+        let mask = learner.mask();
+        assert_eq!(mask.mask(), &[0xFF, !(7 ^ 9 | 8 ^ 10 | 7 ^ 11), 0xFF, 0xFE]);
+        assert_eq!(mask.apply(&[1, 7, 0xAA, 0x11]), mask.apply(&[1, 9, 0xAA, 0x10]));
+        assert_ne!(mask.apply(&[1, 7, 0xAA, 0x10]), mask.apply(&[2, 7, 0xAA, 0x10]));
+
+        let mut inference = mask.wrap(|response: &[u8]| response.try_into().ok());
+        assert_eq!(inference(&[3, 0xFF, 3, 3]), Some([3, mask.mask()[1], 3, 2]));
+        assert_eq!(inference(b"too long"), None);
+    }
+}
@@ -1,4 +1,4 @@
-use crate::event::{USER_STAT_EDGES, USER_STAT_NODES};
+use crate::event::{registered_user_stats, UserStatFormat, USER_STAT_EDGES, USER_STAT_NODES};
 use libafl::{
     bolts::{current_time, format_duration_hms},
     monitors::{ClientStats, Monitor, UserStats},
@@ -40,6 +40,81 @@ pub trait HasStateStats: Monitor {
     fn avg_statemachine_edges(&mut self) -> u64 {
         self.calculate_average(USER_STAT_EDGES)
     }
+
+    /// Render all user stats registered with [`register_user_stat()`](crate::register_user_stat)
+    /// that at least one client reported, as ` | label: value` for every stat.
+    fn format_registered_stats(&mut self) -> String {
+        let mut ret = String::new();
+
+        for stat in registered_user_stats() {
+            let values: Vec<UserStats> = self.client_stats_mut().iter_mut().filter_map(|client_stat| client_stat.get_user_stats(&stat.key).cloned()).collect();
+
+            if let Some(value) = format_user_stat(&values, stat.format) {
+                ret.push_str(&format!(" | {}: {}", stat.label, value));
+            }
+        }
+
+        ret
+    }
+}
+
+fn user_stat_number(value: &UserStats) -> Option<f64> {
+    match value {
+        UserStats::Number(n) => Some(*n as f64),
+        UserStats::Float(f) => Some(*f),
+        UserStats::Ratio(_, 0) => Some(0.0),
+        UserStats::Ratio(a, b) => Some(*a as f64 / *b as f64),
+        UserStats::String(_) => None,
+    }
+}
+
+fn format_number(value: f64) -> String {
+    if value.fract() == 0.0 {
+        format!("{}", value)
+    } else {
+        format!("{:.2}", value)
+    }
+}
+
+/// Combine the values of a user stat from all clients
+fn format_user_stat(values: &[UserStats], format: UserStatFormat) -> Option<String> {
+    if format == UserStatFormat::Text {
+        return values.first().map(|value| value.to_string());
+    }
+
+    if format == UserStatFormat::RatioSum {
+        let ratios: Vec<(u64, u64)> = values
+            .iter()
+            .filter_map(|value| match value {
+                UserStats::Ratio(a, b) => Some((*a, *b)),
+                _ => None,
+            })
+            .collect();
+
+        if ratios.is_empty() {
+            return None;
+        }
+
+        let (a, b) = ratios.iter().fold((0, 0), |(a, b), ratio| (a + ratio.0, b + ratio.1));
+        return Some(UserStats::Ratio(a, b).to_string());
+    }
+
+    let numbers: Vec<f64> = values.iter().filter_map(user_stat_number).collect();
+
+    if numbers.is_empty() {
+        return None;
+    }
+
+    let sum: f64 = numbers.iter().sum();
+    let average = sum / numbers.len() as f64;
+
+    Some(match format {
+        UserStatFormat::Sum => format_number(sum),
+        UserStatFormat::Average => format_number(average),
+        UserStatFormat::Max => format_number(numbers.iter().cloned().fold(f64::MIN, f64::max)),
+        UserStatFormat::Percent => format!("{:.1}%", average * 100.0),
+        UserStatFormat::Text | UserStatFormat::RatioSum => unreachable!(),
+    })
 }
 
 /// A monitor that prints information about the state-graph in addition to all other info.
@@ -93,9 +168,10 @@ impl Monitor for StateMonitor {
         let execs = self.total_execs();
         let execs_per_sec = self.execs_per_sec();
         let cores = std::cmp::max(1, self.client_stats.len().saturating_sub(1));
+        let registered_stats = self.format_registered_stats();
 
         println!(
-            "[butterfly::{}] uptime: {} | cores: {} | corpus: {} | objectives: {} | total execs: {} | exec/s: {} | nodes: {} | edges: {}",
+            "[butterfly::{}] uptime: {} | cores: {} | corpus: {} | objectives: {} | total execs: {} | exec/s: {} | nodes: {} | edges: {}{}",
             msg,
             format_duration_hms(&(current_time() - self.start_time)),
             cores,
@@ -105,6 +181,7 @@ impl Monitor for StateMonitor {
             execs_per_sec,
             num_nodes,
             num_edges,
+            registered_stats,
         );
     }
 }
@@ -196,3 +273,29 @@ where
         self.base.display(event_msg, sender_id);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::register_user_stat;
+
+    #[test]
+    fn test_registered_stats() {
+        let mut monitor = StateMonitor::new();
+        assert_eq!(monitor.format_registered_stats(), "");
+
+        register_user_stat("test_rejected", "rejected", UserStatFormat::Percent);
+        register_user_stat("test_sessions", "sessions", UserStatFormat::Average);
+        register_user_stat("test_sessions", "sessions", UserStatFormat::Sum);
+        register_user_stat("test_havoc", "havoc", UserStatFormat::RatioSum);
+
+        monitor.client_stats_mut_for(1).update_user_stats("test_rejected".to_string(), UserStats::Ratio(1, 4));
+        monitor.client_stats_mut_for(2).update_user_stats("test_rejected".to_string(), UserStats::Ratio(3, 4));
+        monitor.client_stats_mut_for(1).update_user_stats("test_sessions".to_string(), UserStats::Number(3));
+        monitor.client_stats_mut_for(2).update_user_stats("test_sessions".to_string(), UserStats::Number(4));
+        monitor.client_stats_mut_for(1).update_user_stats("test_havoc".to_string(), UserStats::Ratio(1, 10));
+        monitor.client_stats_mut_for(2).update_user_stats("test_havoc".to_string(), UserStats::Ratio(3, 30));
+
+        assert_eq!(monitor.format_registered_stats(), " | rejected: 50.0% | sessions: 7 | havoc: 4/40 (10%)");
+    }
+}
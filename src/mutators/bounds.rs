@@ -0,0 +1,195 @@
+use crate::observer::StateObserver;
+use libafl::{bolts::tuples::Named, executors::HasObservers, impl_serdeany, inputs::Input, observers::ObserversTuple, stages::Stage, state::HasMetadata, Error};
+use serde::{Deserialize, Serialize};
+use std::cmp::Eq;
+use std::fmt::Debug;
+use std::hash::Hash;
+use std::marker::PhantomData;
+
+/// The minimum and maximum number of packets that the structural mutators keep an input within.
+///
+/// If the state has this as metadata, it overrides the bounds that were given to the constructors
+/// of the [`PacketDeleteMutator`](crate::PacketDeleteMutator), [`PacketDuplicateMutator`](crate::PacketDuplicateMutator),
+/// [`PacketSpliceMutator`](crate::PacketSpliceMutator) etc., so that all of them apply the same bounds.
+/// Declare them with [`PacketMutationScheduler::with_bounds()`](crate::PacketMutationScheduler::with_bounds)
+/// or let the [`PacketBoundsStage`] keep them up to date with a [`PacketBoundsPolicy`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PacketBounds {
+    /// Inputs never get shorter than this
+    pub min_packets: usize,
+    /// Inputs never get longer than this
+    pub max_packets: usize,
+}
+
+impl_serdeany!(PacketBounds);
+
+impl PacketBounds {
+    /// Create new PacketBounds. `max_packets` is raised to `min_packets` if it is smaller.
+    pub fn new(min_packets: usize, max_packets: usize) -> Self {
+        Self {
+            min_packets,
+            max_packets: max_packets.max(min_packets),
+        }
+    }
+
+    /// Returns the minimum number of packets from the state or `fallback` if the state has no bounds.
+    /// Inputs are never shrunk to zero packets.
+    pub(crate) fn min_packets<S: HasMetadata>(state: &S, fallback: usize) -> usize {
+        state.metadata().get::<Self>().map_or(fallback, |bounds| bounds.min_packets).max(1)
+    }
+
+    /// Returns the maximum number of packets from the state or `fallback` if the state has no bounds
+    pub(crate) fn max_packets<S: HasMetadata>(state: &S, fallback: usize) -> usize {
+        state.metadata().get::<Self>().map_or(fallback, |bounds| bounds.max_packets)
+    }
+}
+
+/// Decides how the [`PacketBounds`] change over the course of a campaign.
+///
+/// Already implemented for:
+/// - [`PacketBounds`]: static bounds that never change
+/// - [`StallGrowthPolicy`]
+pub trait PacketBoundsPolicy {
+    /// Get the new bounds, given the `current` ones and the number of nodes and edges in the state graph
+    fn update(&mut self, current: PacketBounds, nodes: usize, edges: usize) -> PacketBounds;
+}
+
+impl PacketBoundsPolicy for PacketBounds {
+    fn update(&mut self, _current: PacketBounds, _nodes: usize, _edges: usize) -> PacketBounds {
+        *self
+    }
+}
+
+/// A [`PacketBoundsPolicy`] that allows longer inputs once the state graph stops growing.
+///
+/// Short inputs are cheap and good for exploring the first states of a protocol.
+/// When no new edges were found in `patience` consecutive updates, deeper states are probably
+/// only reachable with longer sessions, so `max_packets` is raised by `step`, up to `limit`.
+#[derive(Debug, Clone)]
+pub struct StallGrowthPolicy {
+    patience: usize,
+    step: usize,
+    limit: usize,
+    edges: usize,
+    stalled: usize,
+}
+
+impl StallGrowthPolicy {
+    /// Create a new StallGrowthPolicy that raises `max_packets` by `step` after `patience` updates
+    /// without new edges, but never beyond `limit`
+    pub fn new(patience: usize, step: usize, limit: usize) -> Self {
+        Self {
+            patience: patience.max(1),
+            step: step.max(1),
+            limit,
+            edges: 0,
+            stalled: 0,
+        }
+    }
+}
+
+impl PacketBoundsPolicy for StallGrowthPolicy {
+    fn update(&mut self, current: PacketBounds, _nodes: usize, edges: usize) -> PacketBounds {
+        if edges > self.edges {
+            self.edges = edges;
+            self.stalled = 0;
+            return current;
+        }
+
+        self.stalled += 1;
+
+        if self.stalled < self.patience || current.max_packets >= self.limit {
+            return current;
+        }
+
+        self.stalled = 0;
+        PacketBounds::new(current.min_packets, (current.max_packets + self.step).min(self.limit))
+    }
+}
+
+/// A stage that applies a [`PacketBoundsPolicy`] and stores the resulting [`PacketBounds`] in the state,
+/// where all structural mutators consult them.
+///
+/// Put it before the mutational stage.
+///
+/// # Example
+/// ```
+/// let bounds = PacketBoundsStage::new(PacketBounds::new(1, 16), StallGrowthPolicy::new(1000, 4, 64), &state_observer);
+/// let mut stages = tuple_list!(bounds, StdMutationalStage::new(mutator));
+/// ```
+#[derive(Debug)]
+pub struct PacketBoundsStage<BP, I, OT, PS> {
+    initial: PacketBounds,
+    policy: BP,
+    observer_name: String,
+    phantom: PhantomData<(I, OT, PS)>,
+}
+
+impl<BP, I, OT, PS> PacketBoundsStage<BP, I, OT, PS>
+where
+    BP: PacketBoundsPolicy,
+    PS: Clone + Debug + Eq + Hash + Serialize + for<'a> Deserialize<'a>,
+{
+    /// Create a new PacketBoundsStage that starts with the `initial` bounds and lets `policy`
+    /// adapt them based on the state graph of `state_observer`
+    pub fn new(initial: PacketBounds, policy: BP, state_observer: &StateObserver<PS>) -> Self {
+        Self {
+            initial,
+            policy,
+            observer_name: state_observer.name().to_string(),
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<BP, E, EM, I, OT, PS, S, Z> Stage<E, EM, S, Z> for PacketBoundsStage<BP, I, OT, PS>
+where
+    BP: PacketBoundsPolicy,
+    E: HasObservers<I, OT, S>,
+    OT: ObserversTuple<I, S>,
+    I: Input,
+    PS: Clone + Debug + Eq + Hash + Serialize + for<'a> Deserialize<'a>,
+    S: HasMetadata,
+{
+    fn perform(&mut self, _fuzzer: &mut Z, executor: &mut E, state: &mut S, _manager: &mut EM, _corpus_idx: usize) -> Result<(), Error> {
+        let observer = executor.observers().match_name::<StateObserver<PS>>(&self.observer_name).ok_or_else(|| Error::key_not_found(format!("No StateObserver named \"{}\" with the given state type", self.observer_name)))?;
+        let (nodes, edges) = observer.info();
+        let current = state.metadata().get::<PacketBounds>().copied().unwrap_or(self.initial);
+        let bounds = self.policy.update(current, nodes, edges);
+
+        if bounds != current {
+            println!("[butterfly] Packet bounds changed to {}..={}", bounds.min_packets, bounds.max_packets);
+        }
+
+        state.add_metadata(bounds);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stall_growth() {
+        let mut policy = StallGrowthPolicy::new(3, 4, 10);
+        let mut bounds = PacketBounds::new(1, 4);
+
+        // Growing graph, no change
+        for edges in 1..10 {
+            bounds = policy.update(bounds, edges, edges);
+        }
+        assert_eq!(bounds, PacketBounds::new(1, 4));
+
+        for _ in 0..3 {
+            bounds = policy.update(bounds, 9, 9);
+        }
+        assert_eq!(bounds, PacketBounds::new(1, 8));
+
+        // Never beyond the limit
+        for _ in 0..100 {
+            bounds = policy.update(bounds, 9, 9);
+        }
+        assert_eq!(bounds, PacketBounds::new(1, 10));
+    }
+}
@@ -0,0 +1,141 @@
+use crate::{
+    config::{ComponentConfig, HasConfig},
+    input::HasPackets,
+    mutators::{HasPostMutationFixup, PacketSelection, PacketSelector},
+};
+use libafl::{
+    bolts::{tuples::Named, HasLen},
+    inputs::Input,
+    mutators::{MutationResult, Mutator},
+    state::HasRand,
+    Error,
+};
+use std::fmt::Debug;
+use std::marker::PhantomData;
+
+/// Signifies that a packet type has different kinds, like the commands of a protocol,
+/// and that a packet can be turned into another kind. Used by the [`PacketKindConversionMutator`].
+///
+/// Byte-level mutations never change the kind of enum-based packets, so a target never sees e.g. a
+/// `LIST` with the path argument of a `CWD`. Converting packets explores such command substitutions.
+///
+/// Already implemented for:
+/// - [`TextLinePacket`](crate::TextLinePacket): a random keyword from the [`KeywordDictionary`](crate::KeywordDictionary), the arguments are kept
+/// - [`NetworkPacket`](crate::NetworkPacket): forwards to the inner packet of `Data` packets
+/// - the FTP and SMTP commands of the [`protocols`](crate::protocols) module: the argument is kept if the new command has one
+///
+/// # Example
+/// ```
+/// impl<S: HasRand> HasKindConversion<S> for PacketType {
+///     fn convert_kind(&mut self, state: &mut S) -> bool {
+///         *self = match self {
+///             PacketType::A(data) => PacketType::B(data.clone()),
+///             PacketType::B(data) => PacketType::A(data.clone()),
+///         };
+///         true
+///     }
+/// }
+/// ```
+pub trait HasKindConversion<S>
+where
+    S: HasRand,
+{
+    /// Turn the packet into a random, different kind and reuse its contents where the new kind has room for them.
+    /// Returns `false` if the packet was not converted.
+    fn convert_kind(&mut self, state: &mut S) -> bool;
+}
+
+/// A mutator that changes the kind of a single packet with [`HasKindConversion`],
+/// e.g. turns a `CWD` into a `LIST` with the same argument.
+///
+/// Afterwards the packet gets fixed up with [`HasPostMutationFixup`].
+pub struct PacketKindConversionMutator<P, SEL = PacketSelection> {
+    selection: SEL,
+    phantom: PhantomData<P>,
+}
+
+impl<P> PacketKindConversionMutator<P> {
+    /// Create a new PacketKindConversionMutator
+    pub fn new() -> Self {
+        Self {
+            selection: PacketSelection::Uniform,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<P, SEL> PacketKindConversionMutator<P, SEL> {
+    /// Choose the packet with the given [`PacketSelector`] instead of uniformly
+    pub fn with_selection<SEL2>(self, selection: SEL2) -> PacketKindConversionMutator<P, SEL2> {
+        PacketKindConversionMutator {
+            selection,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<I, S, P, SEL> Mutator<I, S> for PacketKindConversionMutator<P, SEL>
+where
+    P: HasKindConversion<S> + HasPostMutationFixup,
+    I: Input + HasLen + HasPackets<P>,
+    S: HasRand,
+    SEL: PacketSelector<P, S>,
+{
+    fn mutate(&mut self, state: &mut S, input: &mut I, _stage_idx: i32) -> Result<MutationResult, Error> {
+        if input.len() == 0 {
+            return Ok(MutationResult::Skipped);
+        }
+
+        let idx = self.selection.select_packet(state, input.packets());
+        let packet = &mut input.packets_mut()[idx];
+
+        if !packet.convert_kind(state) {
+            return Ok(MutationResult::Skipped);
+        }
+
+        packet.fixup();
+        Ok(MutationResult::Mutated)
+    }
+}
+
+impl<P, SEL> Named for PacketKindConversionMutator<P, SEL> {
+    fn name(&self) -> &str {
+        "PacketKindConversionMutator"
+    }
+}
+
+impl<P, SEL> HasConfig for PacketKindConversionMutator<P, SEL>
+where
+    SEL: Debug,
+{
+    fn config(&self) -> ComponentConfig {
+        ComponentConfig::new(self.name()).with_setting("selection", format!("{:?}", self.selection))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{test_utils::TestInput, KeywordDictionary, TextLinePacket};
+    use libafl::{
+        bolts::rands::StdRand,
+        corpus::InMemoryCorpus,
+        state::{HasMetadata, StdState},
+    };
+
+    #[test]
+    fn test_kind_conversion() {
+        let mut state = StdState::new(StdRand::with_seed(0), InMemoryCorpus::<TestInput<TextLinePacket>>::new(), InMemoryCorpus::<TestInput<TextLinePacket>>::new(), &mut (), &mut ()).unwrap();
+        let mut mutator = PacketKindConversionMutator::new();
+        let mut input = TestInput {
+            packets: TextLinePacket::parse(b"CWD /tmp\r\n"),
+        };
+
+        // Without a dictionary there is nothing to convert to
+        assert_eq!(mutator.mutate(&mut state, &mut input, 0).unwrap(), MutationResult::Skipped);
+
+        state.add_metadata(KeywordDictionary::new(&["CWD", "LIST"]));
+        while mutator.mutate(&mut state, &mut input, 0).unwrap() == MutationResult::Skipped {}
+        assert_eq!(input.packets[0].to_bytes(), b"LIST /tmp\r\n");
+    }
+}
@@ -1,11 +1,16 @@
-use crate::input::HasPackets;
+use crate::{
+    config::{ComponentConfig, HasConfig},
+    input::HasPackets,
+    mutators::{mutate_masked, HasMutationMask, HasPostMutationFixup, PacketSelection, PacketSelector},
+};
 use libafl::{
     bolts::{rands::Rand, tuples::Named, HasLen},
-    inputs::{BytesInput, HasBytesVec, Input},
+    inputs::{HasBytesVec, Input},
     mutators::{MutationResult, Mutator},
     state::{HasMaxSize, HasRand},
     Error,
 };
+use std::fmt::Debug;
 use std::marker::PhantomData;
 
 /// Signifies that a packet type supports the [`PacketCrossoverInsertMutator`] mutator.    
@@ -15,7 +20,8 @@ use std::marker::PhantomData;
 /// IMPORTANT: This must be implemented on the packet type, not the input type.
 ///
 /// Already implemented for
-/// - [`BytesInput`](libafl::inputs::BytesInput)
+/// - every type that implements [`HasBytesVec`](libafl::inputs::HasBytesVec), like [`BytesInput`](libafl::inputs::BytesInput)
+///   or a newtype around a byte vector, and [`HasMutationMask`](crate::HasMutationMask)
 ///
 /// # Example
 /// Suppose we have the following packet type
@@ -60,32 +66,35 @@ where
     fn mutate_crossover_insert(&mut self, state: &mut S, other: &Self, stage_idx: i32) -> Result<MutationResult, Error>;
 }
 
-impl<S> HasCrossoverInsertMutation<S> for BytesInput
+impl<T, S> HasCrossoverInsertMutation<S> for T
 where
+    T: HasBytesVec + HasMutationMask,
     S: HasRand + HasMaxSize,
 {
     fn mutate_crossover_insert(&mut self, state: &mut S, other: &Self, _stage_idx: i32) -> Result<MutationResult, Error> {
-        let self_len = self.len();
-        let other_len = other.len();
+        mutate_masked(self, |packet| {
+            let self_len = packet.bytes().len();
+            let other_len = other.bytes().len();
 
-        if self_len == 0 || other_len == 0 {
-            return Ok(MutationResult::Skipped);
-        }
+            if self_len == 0 || other_len == 0 {
+                return Ok(MutationResult::Skipped);
+            }
 
-        let from = state.rand_mut().below(other_len as u64) as usize;
-        let to = state.rand_mut().below(self_len as u64) as usize;
-        let len = state.rand_mut().below((other_len - from) as u64) as usize + 1;
+            let from = state.rand_mut().below(other_len as u64) as usize;
+            let to = state.rand_mut().below(self_len as u64) as usize;
+            let len = state.rand_mut().below((other_len - from) as u64) as usize + 1;
 
-        // Make room for `len` additional bytes
-        self.bytes_mut().resize(self_len + len, 0);
+            // Make room for `len` additional bytes
+            packet.bytes_mut().resize(self_len + len, 0);
 
-        // Move bytes at `to` `len` places to the right
-        self.bytes_mut().copy_within(to..self_len, to + len);
+            // Move bytes at `to` `len` places to the right
+            packet.bytes_mut().copy_within(to..self_len, to + len);
 
-        // Insert `from` bytes from `other` into self at index `to`
-        self.bytes_mut()[to..to + len].copy_from_slice(&other.bytes()[from..from + len]);
+            // Insert `from` bytes from `other` into self at index `to`
+            packet.bytes_mut()[to..to + len].copy_from_slice(&other.bytes()[from..from + len]);
 
-        Ok(MutationResult::Mutated)
+            Ok(MutationResult::Mutated)
+        })
     }
 }
 
@@ -93,62 +102,81 @@ where
 /// but for two packets in one seed.
 ///
 /// `P` denotes the type of an individual packet that MUST implement [`HasCrossoverInsertMutation`].
-pub struct PacketCrossoverInsertMutator<P, S>
+pub struct PacketCrossoverInsertMutator<P, S, SEL = PacketSelection>
 where
-    P: HasCrossoverInsertMutation<S> + Clone,
+    P: HasCrossoverInsertMutation<S>,
     S: HasRand + HasMaxSize,
 {
+    selection: SEL,
     phantom: PhantomData<(P, S)>,
 }
 
 impl<P, S> PacketCrossoverInsertMutator<P, S>
 where
-    P: HasCrossoverInsertMutation<S> + Clone,
+    P: HasCrossoverInsertMutation<S>,
     S: HasRand + HasMaxSize,
 {
     /// Create a new PacketCrossoverInsertMutator
     pub fn new() -> Self {
         Self {
+            selection: PacketSelection::Uniform,
             phantom: PhantomData,
         }
     }
 }
 
-impl<I, S, P> Mutator<I, S> for PacketCrossoverInsertMutator<P, S>
+impl<P, S, SEL> PacketCrossoverInsertMutator<P, S, SEL>
 where
-    P: HasCrossoverInsertMutation<S> + Clone,
+    P: HasCrossoverInsertMutation<S>,
+    S: HasRand + HasMaxSize,
+{
+    /// Choose the packet that gets mutated with the given [`PacketSelector`] instead of uniformly.
+    /// The packet that the data comes from is always chosen uniformly.
+    pub fn with_selection<SEL2>(self, selection: SEL2) -> PacketCrossoverInsertMutator<P, S, SEL2>
+    where
+        SEL2: PacketSelector<P, S>,
+    {
+        PacketCrossoverInsertMutator {
+            selection,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<I, S, P, SEL> Mutator<I, S> for PacketCrossoverInsertMutator<P, S, SEL>
+where
+    P: HasCrossoverInsertMutation<S> + HasPostMutationFixup,
     I: Input + HasLen + HasPackets<P>,
     S: HasRand + HasMaxSize,
+    SEL: PacketSelector<P, S>,
 {
     fn mutate(&mut self, state: &mut S, input: &mut I, stage_idx: i32) -> Result<MutationResult, Error> {
         if input.len() <= 1 {
             return Ok(MutationResult::Skipped);
         }
 
-        let packet = state.rand_mut().below(input.len() as u64) as usize;
+        let packet = self.selection.select_packet(state, input.packets());
         let other = state.rand_mut().below(input.len() as u64) as usize;
 
-        if packet == other {
-            return Ok(MutationResult::Skipped);
-        }
+        // Borrow both packets at once instead of cloning `other`
+        let (packet, other) = match input.packets_pair_mut(packet, other) {
+            Some(pair) => pair,
+            None => return Ok(MutationResult::Skipped),
+        };
 
-        #[cfg(feature = "safe_only")]
-        {
-            let other = input.packets()[other].clone();
-            input.packets_mut()[packet].mutate_crossover_insert(state, &other, stage_idx)
-        }
-        #[cfg(not(feature = "safe_only"))]
-        {
-            let dst = std::ptr::addr_of_mut!(input.packets_mut()[packet]);
-            let src = std::ptr::addr_of!(input.packets()[other]);
-            unsafe { dst.as_mut().unwrap().mutate_crossover_insert(state, src.as_ref().unwrap(), stage_idx) }
+        let result = packet.mutate_crossover_insert(state, other, stage_idx)?;
+
+        if result == MutationResult::Mutated {
+            packet.fixup();
         }
+
+        Ok(result)
     }
 }
 
-impl<P, S> Named for PacketCrossoverInsertMutator<P, S>
+impl<P, S, SEL> Named for PacketCrossoverInsertMutator<P, S, SEL>
 where
-    P: HasCrossoverInsertMutation<S> + Clone,
+    P: HasCrossoverInsertMutation<S>,
     S: HasRand + HasMaxSize,
 {
     fn name(&self) -> &str {
@@ -156,6 +184,17 @@ where
     }
 }
 
+impl<P, S, SEL> HasConfig for PacketCrossoverInsertMutator<P, S, SEL>
+where
+    P: HasCrossoverInsertMutation<S>,
+    S: HasRand + HasMaxSize,
+    SEL: Debug,
+{
+    fn config(&self) -> ComponentConfig {
+        ComponentConfig::new(self.name()).with_setting("selection", format!("{:?}", self.selection))
+    }
+}
+
 /// Signifies that a packet type supports the [`PacketCrossoverReplaceMutator`] mutator.    
 ///
 /// If you want to use the [`PacketCrossoverReplaceMutator`] your Input type must have
@@ -163,7 +202,8 @@ where
 /// IMPORTANT: This must be implemented on the packet type, not the input type.
 ///
 /// Already implemented for
-/// - [`BytesInput`](libafl::inputs::BytesInput)
+/// - every type that implements [`HasBytesVec`](libafl::inputs::HasBytesVec), like [`BytesInput`](libafl::inputs::BytesInput)
+///   or a newtype around a byte vector, and [`HasMutationMask`](crate::HasMutationMask)
 ///
 /// # Example
 /// Suppose we have the following packet type
@@ -208,25 +248,28 @@ where
     fn mutate_crossover_replace(&mut self, state: &mut S, other: &Self, stage_idx: i32) -> Result<MutationResult, Error>;
 }
 
-impl<S> HasCrossoverReplaceMutation<S> for BytesInput
+impl<T, S> HasCrossoverReplaceMutation<S> for T
 where
+    T: HasBytesVec + HasMutationMask,
     S: HasRand + HasMaxSize,
 {
     fn mutate_crossover_replace(&mut self, state: &mut S, other: &Self, _stage_idx: i32) -> Result<MutationResult, Error> {
-        let self_len = self.len();
-        let other_len = other.len();
+        mutate_masked(self, |packet| {
+            let self_len = packet.bytes().len();
+            let other_len = other.bytes().len();
 
-        if self_len == 0 || other_len == 0 {
-            return Ok(MutationResult::Skipped);
-        }
+            if self_len == 0 || other_len == 0 {
+                return Ok(MutationResult::Skipped);
+            }
 
-        let from = state.rand_mut().below(other_len as u64) as usize;
-        let to = state.rand_mut().below(self_len as u64) as usize;
-        let len = 1 + state.rand_mut().below(std::cmp::min(other_len - from, self_len - to) as u64) as usize;
+            let from = state.rand_mut().below(other_len as u64) as usize;
+            let to = state.rand_mut().below(self_len as u64) as usize;
+            let len = 1 + state.rand_mut().below(std::cmp::min(other_len - from, self_len - to) as u64) as usize;
 
-        self.bytes_mut()[to..to + len].copy_from_slice(&other.bytes()[from..from + len]);
+            packet.bytes_mut()[to..to + len].copy_from_slice(&other.bytes()[from..from + len]);
 
-        Ok(MutationResult::Mutated)
+            Ok(MutationResult::Mutated)
+        })
     }
 }
 
@@ -234,62 +277,81 @@ where
 /// but for two packets in one seed.
 ///
 /// `P` denotes the type of an individual packet that MUST implement [`HasCrossoverReplaceMutation`].
-pub struct PacketCrossoverReplaceMutator<P, S>
+pub struct PacketCrossoverReplaceMutator<P, S, SEL = PacketSelection>
 where
-    P: HasCrossoverReplaceMutation<S> + Clone,
+    P: HasCrossoverReplaceMutation<S>,
     S: HasRand + HasMaxSize,
 {
+    selection: SEL,
     phantom: PhantomData<(P, S)>,
 }
 
 impl<P, S> PacketCrossoverReplaceMutator<P, S>
 where
-    P: HasCrossoverReplaceMutation<S> + Clone,
+    P: HasCrossoverReplaceMutation<S>,
     S: HasRand + HasMaxSize,
 {
     /// Create a new PacketCrossoverReplaceMutator
     pub fn new() -> Self {
         Self {
+            selection: PacketSelection::Uniform,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<P, S, SEL> PacketCrossoverReplaceMutator<P, S, SEL>
+where
+    P: HasCrossoverReplaceMutation<S>,
+    S: HasRand + HasMaxSize,
+{
+    /// Choose the packet that gets mutated with the given [`PacketSelector`] instead of uniformly.
+    /// The packet that the data comes from is always chosen uniformly.
+    pub fn with_selection<SEL2>(self, selection: SEL2) -> PacketCrossoverReplaceMutator<P, S, SEL2>
+    where
+        SEL2: PacketSelector<P, S>,
+    {
+        PacketCrossoverReplaceMutator {
+            selection,
             phantom: PhantomData,
         }
     }
 }
 
-impl<I, S, P> Mutator<I, S> for PacketCrossoverReplaceMutator<P, S>
+impl<I, S, P, SEL> Mutator<I, S> for PacketCrossoverReplaceMutator<P, S, SEL>
 where
-    P: HasCrossoverReplaceMutation<S> + Clone,
+    P: HasCrossoverReplaceMutation<S> + HasPostMutationFixup,
     I: Input + HasLen + HasPackets<P>,
     S: HasRand + HasMaxSize,
+    SEL: PacketSelector<P, S>,
 {
     fn mutate(&mut self, state: &mut S, input: &mut I, stage_idx: i32) -> Result<MutationResult, Error> {
         if input.len() <= 1 {
             return Ok(MutationResult::Skipped);
         }
 
-        let packet = state.rand_mut().below(input.len() as u64) as usize;
+        let packet = self.selection.select_packet(state, input.packets());
         let other = state.rand_mut().below(input.len() as u64) as usize;
 
-        if packet == other {
-            return Ok(MutationResult::Skipped);
-        }
+        // Borrow both packets at once instead of cloning `other`
+        let (packet, other) = match input.packets_pair_mut(packet, other) {
+            Some(pair) => pair,
+            None => return Ok(MutationResult::Skipped),
+        };
 
-        #[cfg(feature = "safe_only")]
-        {
-            let other = input.packets()[other].clone();
-            input.packets_mut()[packet].mutate_crossover_replace(state, &other, stage_idx)
-        }
-        #[cfg(not(feature = "safe_only"))]
-        {
-            let dst = std::ptr::addr_of_mut!(input.packets_mut()[packet]);
-            let src = std::ptr::addr_of!(input.packets()[other]);
-            unsafe { dst.as_mut().unwrap().mutate_crossover_replace(state, src.as_ref().unwrap(), stage_idx) }
+        let result = packet.mutate_crossover_replace(state, other, stage_idx)?;
+
+        if result == MutationResult::Mutated {
+            packet.fixup();
         }
+
+        Ok(result)
     }
 }
 
-impl<P, S> Named for PacketCrossoverReplaceMutator<P, S>
+impl<P, S, SEL> Named for PacketCrossoverReplaceMutator<P, S, SEL>
 where
-    P: HasCrossoverReplaceMutation<S> + Clone,
+    P: HasCrossoverReplaceMutation<S>,
     S: HasRand + HasMaxSize,
 {
     fn name(&self) -> &str {
@@ -297,9 +359,21 @@ where
     }
 }
 
+impl<P, S, SEL> HasConfig for PacketCrossoverReplaceMutator<P, S, SEL>
+where
+    P: HasCrossoverReplaceMutation<S>,
+    S: HasRand + HasMaxSize,
+    SEL: Debug,
+{
+    fn config(&self) -> ComponentConfig {
+        ComponentConfig::new(self.name()).with_setting("selection", format!("{:?}", self.selection))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_utils::TestInput;
     use libafl::{
         bolts::rands::StdRand,
         inputs::BytesInput,
@@ -307,7 +381,7 @@ mod tests {
         state::{HasMaxSize, HasRand},
     };
     extern crate test;
-    use serde::{Deserialize, Serialize};
+
     use test::Bencher;
 
     struct TestState {
@@ -343,30 +417,6 @@ mod tests {
         }
     }
 
-    #[derive(Hash, Debug, Clone, Serialize, Deserialize)]
-    struct TestInput {
-        packets: Vec<BytesInput>,
-    }
-    impl Input for TestInput {
-        fn generate_name(&self, _idx: usize) -> String {
-            todo!();
-        }
-    }
-    impl HasPackets<BytesInput> for TestInput {
-        fn packets(&self) -> &[BytesInput] {
-            &self.packets
-        }
-
-        fn packets_mut(&mut self) -> &mut Vec<BytesInput> {
-            &mut self.packets
-        }
-    }
-    impl HasLen for TestInput {
-        fn len(&self) -> usize {
-            self.packets.len()
-        }
-    }
-
     #[test]
     fn test_insert_empty() {
         let mut state = TestState::new();
@@ -1,11 +1,16 @@
-use crate::input::HasPackets;
+use crate::{
+    config::{ComponentConfig, HasConfig},
+    input::HasPackets,
+    mutators::{PacketBounds, PacketSelection, PacketSelector},
+};
 use libafl::{
-    bolts::{rands::Rand, tuples::Named, HasLen},
+    bolts::{tuples::Named, HasLen},
     inputs::Input,
     mutators::{MutationResult, Mutator},
-    state::{HasMaxSize, HasRand},
+    state::{HasMaxSize, HasMetadata, HasRand},
     Error,
 };
+use std::fmt::Debug;
 use std::marker::PhantomData;
 
 /// A mutator that deletes a single, random packet.
@@ -18,7 +23,8 @@ use std::marker::PhantomData;
 /// // Make sure that we always have at least 4 packets in an input
 /// let mutator = PacketDeleteMutator::new(4);
 /// ```
-pub struct PacketDeleteMutator<P> {
+pub struct PacketDeleteMutator<P, SEL = PacketSelection> {
+    selection: SEL,
     phantom: PhantomData<P>,
     min_packets: usize,
 }
@@ -27,31 +33,53 @@ impl<P> PacketDeleteMutator<P> {
     /// Create a new PacketDeleteMutator with a lower bound on the number of packets
     pub fn new(min_packets: usize) -> Self {
         Self {
+            selection: PacketSelection::Uniform,
             phantom: PhantomData,
             min_packets: std::cmp::max(1, min_packets),
         }
     }
 }
 
-impl<I, S, P> Mutator<I, S> for PacketDeleteMutator<P>
+impl<P, SEL> PacketDeleteMutator<P, SEL> {
+    /// Choose the packet with the given [`PacketSelector`] instead of uniformly
+    pub fn with_selection<SEL2>(self, selection: SEL2) -> PacketDeleteMutator<P, SEL2> {
+        PacketDeleteMutator {
+            min_packets: self.min_packets,
+            selection,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<I, S, P, SEL> Mutator<I, S> for PacketDeleteMutator<P, SEL>
 where
     I: Input + HasLen + HasPackets<P>,
-    S: HasRand + HasMaxSize,
+    S: HasRand + HasMaxSize + HasMetadata,
+    SEL: PacketSelector<P, S>,
 {
     fn mutate(&mut self, state: &mut S, input: &mut I, _stage_idx: i32) -> Result<MutationResult, Error> {
-        if input.len() <= self.min_packets {
+        if input.len() <= PacketBounds::min_packets(state, self.min_packets) {
             return Ok(MutationResult::Skipped);
         }
 
-        let idx = state.rand_mut().below(input.len() as u64) as usize;
+        let idx = self.selection.select_packet(state, input.packets());
         input.packets_mut().remove(idx);
 
         Ok(MutationResult::Mutated)
     }
 }
 
-impl<P> Named for PacketDeleteMutator<P> {
+impl<P, SEL> Named for PacketDeleteMutator<P, SEL> {
     fn name(&self) -> &str {
         "PacketDeleteMutator"
     }
 }
+
+impl<P, SEL> HasConfig for PacketDeleteMutator<P, SEL>
+where
+    SEL: Debug,
+{
+    fn config(&self) -> ComponentConfig {
+        ComponentConfig::new(self.name()).with_setting("min_packets", self.min_packets).with_setting("selection", format!("{:?}", self.selection))
+    }
+}
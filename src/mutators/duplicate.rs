@@ -1,11 +1,20 @@
-use crate::input::HasPackets;
+use crate::{
+    config::{ComponentConfig, HasConfig},
+    input::HasPackets,
+    mutators::{terminal_setting, HasHavocMutation, HasPostMutationFixup, HasTerminalPacket, PacketBounds, PacketSelection, PacketSelector, TerminalGuard, TerminalHandling},
+};
 use libafl::{
-    bolts::{rands::Rand, tuples::Named, HasLen},
-    inputs::Input,
-    mutators::{MutationResult, Mutator},
-    state::HasRand,
+    bolts::{
+        rands::Rand,
+        tuples::{Named, NamedTuple},
+        HasLen,
+    },
+    inputs::{BytesInput, Input},
+    mutators::{MutationResult, Mutator, MutatorsTuple},
+    state::{HasMaxSize, HasMetadata, HasRand},
     Error,
 };
+use std::fmt::Debug;
 use std::marker::PhantomData;
 
 /// A mutator that duplicates a single, random packet.
@@ -18,11 +27,13 @@ use std::marker::PhantomData;
 /// // Make sure that we never exceed 16 packets in an input
 /// let mutator = PacketDuplicateMutator::new(16);
 /// ```
-pub struct PacketDuplicateMutator<P>
+pub struct PacketDuplicateMutator<P, SEL = PacketSelection>
 where
     P: Clone,
 {
     max_packets: usize,
+    selection: SEL,
+    terminal: Option<TerminalGuard<P>>,
     phantom: PhantomData<P>,
 }
 
@@ -34,37 +45,72 @@ where
     pub fn new(max_packets: usize) -> Self {
         Self {
             max_packets,
+            selection: PacketSelection::Uniform,
+            terminal: None,
             phantom: PhantomData,
         }
     }
 }
 
-impl<I, S, P> Mutator<I, S> for PacketDuplicateMutator<P>
+impl<P, SEL> PacketDuplicateMutator<P, SEL>
+where
+    P: Clone,
+{
+    /// Choose the packet with the given [`PacketSelector`] instead of uniformly
+    pub fn with_selection<SEL2>(self, selection: SEL2) -> PacketDuplicateMutator<P, SEL2> {
+        PacketDuplicateMutator {
+            max_packets: self.max_packets,
+            selection,
+            terminal: self.terminal,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Take packets that end a session into account, see [`HasTerminalPacket`]
+    pub fn with_terminal_handling(mut self, handling: TerminalHandling) -> Self
+    where
+        P: HasTerminalPacket,
+    {
+        self.terminal = Some(TerminalGuard::new(handling));
+        self
+    }
+}
+
+impl<I, S, P, SEL> Mutator<I, S> for PacketDuplicateMutator<P, SEL>
 where
     P: Clone,
     I: Input + HasLen + HasPackets<P>,
-    S: HasRand,
+    S: HasRand + HasMetadata,
+    SEL: PacketSelector<P, S>,
 {
     fn mutate(&mut self, state: &mut S, input: &mut I, _stage_idx: i32) -> Result<MutationResult, Error> {
-        if input.len() >= self.max_packets {
+        if input.len() >= PacketBounds::max_packets(state, self.max_packets) {
             return Ok(MutationResult::Skipped);
         }
 
-        let from = state.rand_mut().below(input.len() as u64) as usize;
+        let from = self.selection.select_packet(state, input.packets());
         let to = state.rand_mut().below(input.len() as u64 + 1) as usize;
 
         if from == to {
             return Ok(MutationResult::Skipped);
         }
 
+        let trailing = self.terminal.as_ref().map_or(0, |guard| guard.trailing(input.packets()));
         let copy = input.packets()[from].clone();
         input.packets_mut().insert(to, copy);
 
+        if let Some(guard) = &self.terminal {
+            if !guard.accept(trailing, input.packets_mut()) {
+                input.packets_mut().remove(to);
+                return Ok(MutationResult::Skipped);
+            }
+        }
+
         Ok(MutationResult::Mutated)
     }
 }
 
-impl<P> Named for PacketDuplicateMutator<P>
+impl<P, SEL> Named for PacketDuplicateMutator<P, SEL>
 where
     P: Clone,
 {
@@ -72,3 +118,187 @@ where
         "PacketDuplicateMutator"
     }
 }
+
+impl<P, SEL> HasConfig for PacketDuplicateMutator<P, SEL>
+where
+    P: Clone,
+    SEL: Debug,
+{
+    fn config(&self) -> ComponentConfig {
+        ComponentConfig::new(self.name()).with_setting("max_packets", self.max_packets).with_setting("selection", format!("{:?}", self.selection)).with_setting("terminal", terminal_setting(&self.terminal))
+    }
+}
+
+/// A mutator that duplicates a single, random packet and applies a stack of
+/// havoc mutations to the copy.
+///
+/// A verbatim copy of a packet rarely changes the behavior of the target,
+/// but a near-identical one often does, e.g. a second `USER` command with a slightly
+/// different name. The copy is mutated like by the [`PacketHavocMutator`](crate::PacketHavocMutator),
+/// so `P` MUST implement [`HasHavocMutation`].
+/// It respects an upper bound on the number of packets passed as an argument to the constructor.
+///
+/// # Example
+/// ```
+/// // Make sure that we never exceed 16 packets in an input
+/// let mutator = PacketDuplicateHavocMutator::new(supported_havoc_mutations(), 16);
+/// ```
+pub struct PacketDuplicateHavocMutator<MT, S, P, SEL = PacketSelection>
+where
+    P: HasHavocMutation<MT, S> + Clone,
+    MT: MutatorsTuple<BytesInput, S>,
+    S: HasRand + HasMaxSize,
+{
+    /// These mutation operators must exclusively be for BytesInputs
+    mutations: MT,
+    max_packets: usize,
+    selection: SEL,
+    terminal: Option<TerminalGuard<P>>,
+    phantom: PhantomData<(S, P)>,
+}
+
+impl<MT, S, P> PacketDuplicateHavocMutator<MT, S, P>
+where
+    P: HasHavocMutation<MT, S> + Clone,
+    MT: MutatorsTuple<BytesInput, S>,
+    S: HasRand + HasMaxSize,
+{
+    /// Create a new PacketDuplicateHavocMutator with mutators that can be applied to
+    /// [`BytesInputs`](libafl::inputs::BytesInput) and an upper bound on the number of packets
+    pub fn new(mutations: MT, max_packets: usize) -> Self {
+        Self {
+            mutations,
+            max_packets,
+            selection: PacketSelection::Uniform,
+            terminal: None,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<MT, S, P, SEL> PacketDuplicateHavocMutator<MT, S, P, SEL>
+where
+    P: HasHavocMutation<MT, S> + Clone,
+    MT: MutatorsTuple<BytesInput, S>,
+    S: HasRand + HasMaxSize,
+{
+    /// Choose the packet with the given [`PacketSelector`] instead of uniformly
+    pub fn with_selection<SEL2>(self, selection: SEL2) -> PacketDuplicateHavocMutator<MT, S, P, SEL2> {
+        PacketDuplicateHavocMutator {
+            mutations: self.mutations,
+            max_packets: self.max_packets,
+            selection,
+            terminal: self.terminal,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Take packets that end a session into account, see [`HasTerminalPacket`]
+    pub fn with_terminal_handling(mut self, handling: TerminalHandling) -> Self
+    where
+        P: HasTerminalPacket,
+    {
+        self.terminal = Some(TerminalGuard::new(handling));
+        self
+    }
+}
+
+impl<I, MT, S, P, SEL> Mutator<I, S> for PacketDuplicateHavocMutator<MT, S, P, SEL>
+where
+    P: HasHavocMutation<MT, S> + HasPostMutationFixup + Clone,
+    I: Input + HasLen + HasPackets<P>,
+    MT: MutatorsTuple<BytesInput, S>,
+    S: HasRand + HasMaxSize + HasMetadata,
+    SEL: PacketSelector<P, S>,
+{
+    fn mutate(&mut self, state: &mut S, input: &mut I, stage_idx: i32) -> Result<MutationResult, Error> {
+        if input.len() == 0 || input.len() >= PacketBounds::max_packets(state, self.max_packets) {
+            return Ok(MutationResult::Skipped);
+        }
+
+        let from = self.selection.select_packet(state, input.packets());
+        let to = state.rand_mut().below(input.len() as u64 + 1) as usize;
+        let iters = 1 + state.rand_mut().below(16);
+        let mut copy = input.packets()[from].clone();
+        let mut result = MutationResult::Skipped;
+
+        for _ in 0..iters {
+            let mutation = state.rand_mut().below(self.mutations.len() as u64) as usize;
+
+            if copy.mutate_havoc(state, &mut self.mutations, mutation, stage_idx)? == MutationResult::Mutated {
+                result = MutationResult::Mutated;
+            }
+        }
+
+        // Without a single successful mutation this would be a plain duplicate
+        if result == MutationResult::Mutated {
+            let trailing = self.terminal.as_ref().map_or(0, |guard| guard.trailing(input.packets()));
+            copy.fixup();
+            input.packets_mut().insert(to, copy);
+
+            if let Some(guard) = &self.terminal {
+                if !guard.accept(trailing, input.packets_mut()) {
+                    input.packets_mut().remove(to);
+                    return Ok(MutationResult::Skipped);
+                }
+            }
+        }
+
+        Ok(result)
+    }
+}
+
+impl<MT, S, P, SEL> Named for PacketDuplicateHavocMutator<MT, S, P, SEL>
+where
+    P: HasHavocMutation<MT, S> + Clone,
+    MT: MutatorsTuple<BytesInput, S>,
+    S: HasRand + HasMaxSize,
+{
+    fn name(&self) -> &str {
+        "PacketDuplicateHavocMutator"
+    }
+}
+
+impl<MT, S, P, SEL> HasConfig for PacketDuplicateHavocMutator<MT, S, P, SEL>
+where
+    P: HasHavocMutation<MT, S> + Clone,
+    MT: MutatorsTuple<BytesInput, S> + NamedTuple,
+    S: HasRand + HasMaxSize,
+    SEL: Debug,
+{
+    fn config(&self) -> ComponentConfig {
+        ComponentConfig::new(self.name())
+            .with_setting("mutations", (0..self.mutations.len()).filter_map(|mutation| self.mutations.name(mutation)).collect::<Vec<_>>().join(", "))
+            .with_setting("max_packets", self.max_packets)
+            .with_setting("selection", format!("{:?}", self.selection))
+            .with_setting("terminal", terminal_setting(&self.terminal))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mutators::supported_havoc_mutations;
+    use crate::test_utils::TestInput;
+    use libafl::{bolts::rands::StdRand, corpus::InMemoryCorpus, inputs::HasBytesVec, state::StdState};
+
+    #[test]
+    fn test_duplicate_havoc() {
+        let mut state = StdState::new(StdRand::with_seed(0), InMemoryCorpus::<TestInput>::new(), InMemoryCorpus::<TestInput>::new(), &mut (), &mut ()).unwrap();
+        let mut mutator = PacketDuplicateHavocMutator::new(supported_havoc_mutations(), 2);
+
+        for _ in 0..100 {
+            let mut input = TestInput {
+                packets: vec![BytesInput::new(b"USER anonymous".to_vec())],
+            };
+
+            if mutator.mutate(&mut state, &mut input, 0).unwrap() == MutationResult::Mutated {
+                assert_eq!(input.len(), 2);
+                assert!(input.packets.iter().any(|packet| packet.bytes() == b"USER anonymous"));
+            }
+
+            // The upper bound is respected
+            assert_eq!(mutator.mutate(&mut state, &mut input, 0).unwrap(), MutationResult::Skipped);
+        }
+    }
+}
@@ -0,0 +1,296 @@
+use crate::{
+    config::{ComponentConfig, HasConfig},
+    grammar::Endianness,
+    input::HasPackets,
+    mutators::{HasPostMutationFixup, PacketSelection, PacketSelector},
+};
+use libafl::{
+    bolts::{rands::Rand, tuples::Named, HasLen},
+    inputs::Input,
+    mutators::{MutationResult, Mutator},
+    state::HasRand,
+    Error,
+};
+use serde::{Deserialize, Serialize};
+use std::fmt::Debug;
+use std::marker::PhantomData;
+
+/// Largest value that gets added to or subtracted from a field, same as in AFL
+const ARITH_MAX: u64 = 35;
+
+/// Interesting values from AFL, they get truncated to the width of the field
+const INTERESTING_VALUES: [u64; 26] = [
+    0x80,
+    0xFF,
+    0,
+    1,
+    16,
+    32,
+    64,
+    100,
+    127, // 8 bit
+    0x8000,
+    0xFF7F,
+    128,
+    255,
+    256,
+    512,
+    1000,
+    1024,
+    4096,
+    32767, // 16 bit
+    0x8000_0000,
+    0xFA00_00FA,
+    0xFFFF_7FFF,
+    0xFFFF,
+    65536,
+    100_663_045,
+    0x7FFF_FFFF, // 32 bit
+];
+
+/// Width of a [`NumericField`]
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FieldWidth {
+    /// A single byte
+    U8,
+    /// Two bytes
+    U16,
+    /// Four bytes
+    U32,
+}
+
+impl FieldWidth {
+    /// The number of bytes of the field
+    pub fn bytes(self) -> usize {
+        match self {
+            FieldWidth::U8 => 1,
+            FieldWidth::U16 => 2,
+            FieldWidth::U32 => 4,
+        }
+    }
+
+    /// The largest value that fits into the field
+    pub fn max_value(self) -> u64 {
+        (1u64 << (8 * self.bytes())) - 1
+    }
+}
+
+/// An unsigned integer field inside the bytes of a packet
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NumericField {
+    /// Offset of the first byte of the field
+    pub offset: usize,
+    /// Width of the field
+    pub width: FieldWidth,
+    /// Byte order of the field
+    pub endianness: Endianness,
+}
+
+impl NumericField {
+    /// Create a new NumericField
+    pub fn new(offset: usize, width: FieldWidth, endianness: Endianness) -> Self {
+        Self {
+            offset,
+            width,
+            endianness,
+        }
+    }
+}
+
+/// Signifies that a packet has typed integer fields, like the type, length and id fields of a binary protocol.
+/// Used by the [`PacketFieldMutator`].
+///
+/// Blind byte havoc on such packets mostly produces values that the target rejects during parsing.
+/// Mutating the fields as numbers reaches off-by-one lengths, sign flips and boundary values instead.
+///
+/// Already implemented for:
+/// - [`NetworkPacket`](crate::NetworkPacket): forwards to the inner packet of `Data` packets
+///
+/// # Example
+/// ```
+/// struct TlvPacket {
+///     // 1 byte type, 2 bytes big-endian length, value
+///     data: Vec<u8>,
+/// }
+///
+/// impl HasNumericFieldMutation for TlvPacket {
+///     fn numeric_fields(&self) -> Vec<NumericField> {
+///         vec![NumericField::new(0, FieldWidth::U8, Endianness::Big), NumericField::new(1, FieldWidth::U16, Endianness::Big)]
+///     }
+///
+///     fn field_bytes_mut(&mut self) -> Option<&mut Vec<u8>> {
+///         Some(&mut self.data)
+///     }
+/// }
+/// ```
+pub trait HasNumericFieldMutation {
+    /// The numeric fields of the packet, given as offsets into [`field_bytes_mut()`](HasNumericFieldMutation::field_bytes_mut).
+    /// Fields that don't fit into the bytes are ignored.
+    fn numeric_fields(&self) -> Vec<NumericField>;
+
+    /// The bytes the fields refer to or `None` if the packet has no fields
+    fn field_bytes_mut(&mut self) -> Option<&mut Vec<u8>>;
+}
+
+/// A mutator that changes a single numeric field of a packet that implements [`HasNumericFieldMutation`].
+///
+/// It either
+/// - adds or subtracts a small value,
+/// - replaces the value with one of AFLs interesting values or
+/// - replaces the value with a boundary of the field: zero, the largest value or the signed minimum and maximum.
+///
+/// Afterwards the packet gets fixed up with [`HasPostMutationFixup`].
+pub struct PacketFieldMutator<P, SEL = PacketSelection> {
+    selection: SEL,
+    phantom: PhantomData<P>,
+}
+
+impl<P> PacketFieldMutator<P> {
+    /// Create a new PacketFieldMutator
+    pub fn new() -> Self {
+        Self {
+            selection: PacketSelection::Uniform,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<P, SEL> PacketFieldMutator<P, SEL> {
+    /// Choose the packet with the given [`PacketSelector`] instead of uniformly
+    pub fn with_selection<SEL2>(self, selection: SEL2) -> PacketFieldMutator<P, SEL2> {
+        PacketFieldMutator {
+            selection,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<P, SEL> PacketFieldMutator<P, SEL> {
+    fn mutate_value<S: HasRand>(state: &mut S, value: u64, width: FieldWidth) -> u64 {
+        let max = width.max_value();
+
+        match state.rand_mut().below(3) {
+            0 => {
+                let delta = 1 + state.rand_mut().below(ARITH_MAX);
+
+                if state.rand_mut().below(2) == 0 {
+                    value.wrapping_add(delta) & max
+                } else {
+                    value.wrapping_sub(delta) & max
+                }
+            },
+            1 => *state.rand_mut().choose(&INTERESTING_VALUES) & max,
+            _ => *state.rand_mut().choose(&[0, max, max >> 1, (max >> 1) + 1]),
+        }
+    }
+}
+
+impl<I, S, P, SEL> Mutator<I, S> for PacketFieldMutator<P, SEL>
+where
+    P: HasNumericFieldMutation + HasPostMutationFixup,
+    I: Input + HasLen + HasPackets<P>,
+    S: HasRand,
+    SEL: PacketSelector<P, S>,
+{
+    fn mutate(&mut self, state: &mut S, input: &mut I, _stage_idx: i32) -> Result<MutationResult, Error> {
+        if input.len() == 0 {
+            return Ok(MutationResult::Skipped);
+        }
+
+        let idx = self.selection.select_packet(state, input.packets());
+        let packet = &mut input.packets_mut()[idx];
+        let fields = packet.numeric_fields();
+
+        let bytes = match packet.field_bytes_mut() {
+            Some(bytes) => bytes,
+            None => return Ok(MutationResult::Skipped),
+        };
+
+        let fields: Vec<_> = fields.into_iter().filter(|field| field.offset + field.width.bytes() <= bytes.len()).collect();
+
+        if fields.is_empty() {
+            return Ok(MutationResult::Skipped);
+        }
+
+        let field = *state.rand_mut().choose(&fields);
+        let range = field.offset..field.offset + field.width.bytes();
+        let old = field.endianness.decode(&bytes[range.clone()]);
+        let new = Self::mutate_value(state, old, field.width);
+
+        if new == old {
+            return Ok(MutationResult::Skipped);
+        }
+
+        bytes[range].copy_from_slice(&field.endianness.encode(new, field.width.bytes()));
+        packet.fixup();
+
+        Ok(MutationResult::Mutated)
+    }
+}
+
+impl<P, SEL> Named for PacketFieldMutator<P, SEL> {
+    fn name(&self) -> &str {
+        "PacketFieldMutator"
+    }
+}
+
+impl<P, SEL> HasConfig for PacketFieldMutator<P, SEL>
+where
+    SEL: Debug,
+{
+    fn config(&self) -> ComponentConfig {
+        ComponentConfig::new(self.name()).with_setting("selection", format!("{:?}", self.selection))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::TestInput;
+    use libafl::{bolts::rands::StdRand, corpus::InMemoryCorpus, inputs::BytesInput, state::StdState};
+
+    #[derive(Hash, Debug, Clone, Serialize, Deserialize)]
+    struct TlvPacket {
+        data: Vec<u8>,
+    }
+
+    impl HasNumericFieldMutation for TlvPacket {
+        fn numeric_fields(&self) -> Vec<NumericField> {
+            vec![NumericField::new(1, FieldWidth::U16, Endianness::Little), NumericField::new(8, FieldWidth::U32, Endianness::Big)]
+        }
+
+        fn field_bytes_mut(&mut self) -> Option<&mut Vec<u8>> {
+            Some(&mut self.data)
+        }
+    }
+
+    impl HasPostMutationFixup for TlvPacket {}
+
+    #[test]
+    fn test_field_mutation() {
+        let mut state = StdState::new(StdRand::with_seed(0), InMemoryCorpus::<BytesInput>::new(), InMemoryCorpus::<BytesInput>::new(), &mut (), &mut ()).unwrap();
+        let mut mutator = PacketFieldMutator::new();
+        let mut boundary = false;
+
+        for _ in 0..1000 {
+            // The second field does not fit and must never be touched
+            let mut input = TestInput {
+                packets: vec![TlvPacket {
+                    data: vec![7, 4, 0, b'a', b'b', b'c', b'd'],
+                }],
+            };
+
+            if mutator.mutate(&mut state, &mut input, 0).unwrap() == MutationResult::Mutated {
+                let data = &input.packets[0].data;
+                assert_eq!(data.len(), 7);
+                assert_eq!(data[0], 7);
+                assert_eq!(&data[3..], b"abcd");
+                assert_ne!(&data[1..3], &[4, 0]);
+
+                boundary |= data[1..3] == [0xFF, 0x7F];
+            }
+        }
+
+        assert!(boundary);
+    }
+}
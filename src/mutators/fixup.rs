@@ -0,0 +1,50 @@
+use libafl::inputs::BytesInput;
+
+/// Signifies that a packet can repair itself after it has been mutated.
+///
+/// Mutations often turn a packet into something the target rejects right away,
+/// because a length field, a checksum or a terminator like `\r\n` no longer
+/// matches the payload. All butterfly mutators that change the contents of packets
+/// call [`fixup()`](HasPostMutationFixup::fixup) on the packets they modified
+/// after a successful mutation, so that such packets make it past the parser of the target.
+///
+/// The default implementation does nothing, so packet types that don't need
+/// any repairs simply opt in with an empty impl block.
+///
+/// The [`PacketFragmentMutator`](crate::PacketFragmentMutator) is the only mutator that doesn't call it,
+/// since it deliberately creates packets that are incomplete on their own.
+///
+/// Checksums and length fields can be computed with the helpers in the [`fixups`](crate::fixups) module.
+///
+/// Already implemented for:
+/// - [`BytesInput`](libafl::inputs::BytesInput): does nothing
+/// - [`TextLinePacket`](crate::TextLinePacket): does nothing
+/// - [`GrammarPacket`](crate::GrammarPacket): does nothing, length fields are computed during serialization
+/// - [`NetworkPacket`](crate::NetworkPacket): forwards to the inner packet of `Data` packets
+///
+/// # Example
+/// Suppose a packet consists of a 16-bit length prefix and a body
+/// ```
+/// struct PrefixedPacket {
+///     data: BytesInput,
+/// }
+///
+/// impl HasPostMutationFixup for PrefixedPacket {
+///     fn fixup(&mut self) {
+///         let bytes = self.data.bytes_mut();
+///
+///         if bytes.len() < 2 {
+///             bytes.resize(2, 0);
+///         }
+///
+///         let len = (bytes.len() - 2).min(u16::MAX as usize) as u16;
+///         bytes[..2].copy_from_slice(&len.to_be_bytes());
+///     }
+/// }
+/// ```
+pub trait HasPostMutationFixup {
+    /// Repair the packet after it has been mutated
+    fn fixup(&mut self) {}
+}
+
+impl HasPostMutationFixup for BytesInput {}
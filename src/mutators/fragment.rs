@@ -0,0 +1,121 @@
+use crate::{
+    config::{ComponentConfig, HasConfig},
+    input::HasPackets,
+    mutators::{PacketBounds, PacketSelection, PacketSelector},
+};
+use libafl::{
+    bolts::{rands::Rand, tuples::Named, HasLen},
+    inputs::{BytesInput, HasBytesVec, Input},
+    mutators::{MutationResult, Mutator},
+    state::{HasMetadata, HasRand},
+    Error,
+};
+use std::fmt::Debug;
+use std::marker::PhantomData;
+
+/// Signifies that a packet can be split into two packets that
+/// are sent one after the other. Used by the [`PacketFragmentMutator`].
+///
+/// Already implemented for:
+/// - [`BytesInput`](libafl::inputs::BytesInput)
+/// - [`NetworkPacket`](crate::NetworkPacket): only `Data` packets are split
+pub trait HasSplit<S>: Sized
+where
+    S: HasRand,
+{
+    /// Split the packet at a random position. `self` keeps the first part,
+    /// the second part gets returned. Returns `None` if the packet cannot be split.
+    fn split(&mut self, state: &mut S) -> Option<Self>;
+}
+
+impl<S> HasSplit<S> for BytesInput
+where
+    S: HasRand,
+{
+    fn split(&mut self, state: &mut S) -> Option<Self> {
+        if self.bytes().len() < 2 {
+            return None;
+        }
+
+        let offset = 1 + state.rand_mut().below(self.bytes().len() as u64 - 1) as usize;
+        Some(BytesInput::new(self.bytes_mut().split_off(offset)))
+    }
+}
+
+/// A mutator that splits a single, random packet into two consecutive packets.
+///
+/// With stream protocols, framing bugs often only show when a logical message
+/// arrives in multiple writes. It respects an upper bound on the number of packets
+/// passed as an argument to the constructor.
+///
+/// # Example
+/// ```
+/// // Make sure that we never exceed 16 packets in an input
+/// let mutator = PacketFragmentMutator::new(16);
+/// ```
+pub struct PacketFragmentMutator<P, SEL = PacketSelection> {
+    max_packets: usize,
+    selection: SEL,
+    phantom: PhantomData<P>,
+}
+
+impl<P> PacketFragmentMutator<P> {
+    /// Create a new PacketFragmentMutator with an upper bound on the number of packets
+    pub fn new(max_packets: usize) -> Self {
+        Self {
+            max_packets,
+            selection: PacketSelection::Uniform,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<P, SEL> PacketFragmentMutator<P, SEL> {
+    /// Choose the packet with the given [`PacketSelector`] instead of uniformly
+    pub fn with_selection<SEL2>(self, selection: SEL2) -> PacketFragmentMutator<P, SEL2> {
+        PacketFragmentMutator {
+            max_packets: self.max_packets,
+            selection,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<I, S, P, SEL> Mutator<I, S> for PacketFragmentMutator<P, SEL>
+where
+    P: HasSplit<S>,
+    I: Input + HasLen + HasPackets<P>,
+    S: HasRand + HasMetadata,
+    SEL: PacketSelector<P, S>,
+{
+    fn mutate(&mut self, state: &mut S, input: &mut I, _stage_idx: i32) -> Result<MutationResult, Error> {
+        if input.len() == 0 || input.len() >= PacketBounds::max_packets(state, self.max_packets) {
+            return Ok(MutationResult::Skipped);
+        }
+
+        let idx = self.selection.select_packet(state, input.packets());
+
+        match input.packets_mut()[idx].split(state) {
+            Some(tail) => {
+                input.packets_mut().insert(idx + 1, tail);
+                Ok(MutationResult::Mutated)
+            },
+            None => Ok(MutationResult::Skipped),
+        }
+    }
+}
+
+impl<P, SEL> Named for PacketFragmentMutator<P, SEL> {
+    fn name(&self) -> &str {
+        "PacketFragmentMutator"
+    }
+}
+
+impl<P, SEL> HasConfig for PacketFragmentMutator<P, SEL>
+where
+    SEL: Debug,
+{
+    fn config(&self) -> ComponentConfig {
+        ComponentConfig::new(self.name()).with_setting("max_packets", self.max_packets).with_setting("selection", format!("{:?}", self.selection))
+    }
+}
@@ -1,16 +1,22 @@
-use crate::input::HasPackets;
+use crate::{
+    config::{ComponentConfig, HasConfig},
+    input::HasPackets,
+    mutators::{is_intact, mutate_masked, HasMutationMask, HasPostMutationFixup, PacketSelection, PacketSelector},
+};
 use libafl::{
     bolts::{
         rands::Rand,
-        tuples::{tuple_list, Named},
+        tuples::{tuple_list, Merge, Named, NamedTuple},
         HasLen,
     },
-    inputs::{bytes::BytesInput, Input},
-    mutators::{mutations::*, MutationResult, Mutator, MutatorsTuple},
+    inputs::{bytes::BytesInput, HasBytesVec, Input},
+    mutators::{mutations::*, MutationResult, Mutator, MutatorsTuple, TokenInsert, TokenReplace},
     state::{HasMaxSize, HasRand},
     Error,
 };
+use std::fmt::Debug;
 use std::marker::PhantomData;
+use std::ops::Range;
 
 /// Tuple of all havoc mutators in libafl that get exactly one input.
 ///
@@ -86,6 +92,27 @@ pub fn supported_havoc_mutations() -> SupportedHavocMutationsType {
     )
 }
 
+/// Tuple of all havoc mutators in [`SupportedHavocMutationsType`] plus LibAFLs token mutators
+/// `TokenInsert` and `TokenReplace`.
+pub type SupportedHavocMutationsWithTokensType = <SupportedHavocMutationsType as Merge<(TokenInsert, (TokenReplace, ()))>>::MergeResult;
+
+/// Returns a tuple with all the mutations of [`supported_havoc_mutations()`] plus
+/// `TokenInsert` and `TokenReplace`, which insert tokens from the [`Tokens`](libafl::mutators::Tokens)
+/// metadata of the state into packets.
+///
+/// The token mutators skip when the state has no tokens, so fill the dictionary first,
+/// e.g. with [`load_tokens()`](crate::load_tokens) or a [`TokenExtractor`](crate::TokenExtractor).
+/// The state must implement `HasMetadata`.
+///
+/// # Example
+/// ```
+/// load_tokens(&mut state, "ftp.dict")?;
+/// let mutator = PacketHavocMutator::new(supported_havoc_mutations_with_tokens());
+/// ```
+pub fn supported_havoc_mutations_with_tokens() -> SupportedHavocMutationsWithTokensType {
+    supported_havoc_mutations().merge(tuple_list!(TokenInsert::new(), TokenReplace::new()))
+}
+
 /// Signifies that a packet type supports the [`PacketHavocMutator`].
 ///
 /// If you want to use the [`PacketHavocMutator`] your Input type must have
@@ -93,7 +120,9 @@ pub fn supported_havoc_mutations() -> SupportedHavocMutationsType {
 /// IMPORTANT: This must be implemented by the packet type, not the input type.
 ///
 /// Already implemented for:
-/// - [`BytesInput`](libafl::inputs::BytesInput)
+/// - every type that implements [`HasBytesVec`](libafl::inputs::HasBytesVec), like [`BytesInput`](libafl::inputs::BytesInput)
+///   or a newtype around a byte vector, and [`HasMutationMask`](crate::HasMutationMask).
+///   The bytes are moved into a temporary [`BytesInput`](libafl::inputs::BytesInput) for the mutation.
 ///
 /// # Example
 /// Suppose we have the following packet type
@@ -134,20 +163,86 @@ where
     fn mutate_havoc(&mut self, state: &mut S, mutations: &mut MT, mutation: usize, stage_idx: i32) -> Result<MutationResult, Error>;
 }
 
-impl<MT, S> HasHavocMutation<MT, S> for BytesInput
+impl<T, MT, S> HasHavocMutation<MT, S> for T
 where
+    T: HasBytesVec + HasMutationMask,
     MT: MutatorsTuple<BytesInput, S>,
     S: HasRand + HasMaxSize,
 {
     fn mutate_havoc(&mut self, state: &mut S, mutations: &mut MT, mutation: usize, stage_idx: i32) -> Result<MutationResult, Error> {
-        mutations.get_and_mutate(mutation, state, self, stage_idx)
+        mutate_masked(self, |packet| {
+            let mut data = BytesInput::new(std::mem::take(packet.bytes_mut()));
+            let result = mutations.get_and_mutate(mutation, state, &mut data, stage_idx);
+            *packet.bytes_mut() = std::mem::take(data.bytes_mut());
+            result
+        })
+    }
+}
+
+/// Signifies that a packet consists of regions like a header, individual fields or a body,
+/// similar to the regions of AFLNet.
+///
+/// With [`PacketHavocMutator::with_regions()`] all stacked havoc mutations of one round
+/// stay within a single, randomly chosen region. This keeps sensitive headers intact
+/// while the body gets mutated and vice versa.
+///
+/// Already implemented for:
+/// - [`NetworkPacket`](crate::NetworkPacket): forwards to the inner packet of `Data` packets
+///
+/// # Example
+/// ```
+/// struct TlvPacket {
+///     // 1 byte type, 2 bytes length, value
+///     data: Vec<u8>,
+/// }
+///
+/// impl HasRegions for TlvPacket {
+///     fn regions(&self) -> Vec<Range<usize>> {
+///         vec![0..1, 1..3, 3..self.data.len()]
+///     }
+///
+///     fn region_bytes_mut(&mut self) -> Option<&mut Vec<u8>> {
+///         Some(&mut self.data)
+///     }
+/// }
+/// ```
+pub trait HasRegions {
+    /// The regions of the packet as ranges into [`region_bytes_mut()`](HasRegions::region_bytes_mut).
+    /// Regions may overlap and don't have to cover all bytes.
+    fn regions(&self) -> Vec<Range<usize>>;
+
+    /// The bytes the regions refer to or `None` if the packet has no regions
+    fn region_bytes_mut(&mut self) -> Option<&mut Vec<u8>>;
+}
+
+/// The regions of a packet and the bytes they refer to
+type Regions<'a> = (Vec<Range<usize>>, &'a mut Vec<u8>);
+
+/// Returns the byte ranges of a packet that must not be mutated
+type MaskFn<P> = fn(&P) -> Vec<Range<usize>>;
+
+/// Returns the regions of a packet and the bytes they refer to, if it has any valid ones
+fn packet_regions<P: HasRegions>(packet: &mut P) -> Option<Regions<'_>> {
+    let mut regions = packet.regions();
+    let bytes = packet.region_bytes_mut()?;
+    regions.retain(|region| region.start < region.end && region.end <= bytes.len());
+
+    if regions.is_empty() {
+        None
+    } else {
+        Some((regions, bytes))
     }
 }
 
 /// A mutator that applies a set of havoc mutations to a single packet.
 ///
+/// Some transitions of the target require coordinated changes across multiple packets.
+/// With [`with_max_packets()`](PacketHavocMutator::with_max_packets) it mutates multiple
+/// different packets in one run instead.
+///
 /// `P` denotes the packet type that MUST implement [`HasHavocMutation`].
-pub struct PacketHavocMutator<I, MT, S, P>
+/// The packets are chosen by a [`PacketSelector`], uniformly by default.
+pub struct PacketHavocMutator<I, MT, S, P, SEL = PacketSelection>
 where
     P: HasHavocMutation<MT, S>,
     I: Input + HasLen + HasPackets<P>,
@@ -156,6 +251,10 @@ where
 {
     /// These mutation operators must exclusively be for BytesInputs
     mutations: MT,
+    regions: Option<fn(&mut P) -> Option<Regions<'_>>>,
+    mask: Option<MaskFn<P>>,
+    max_packets: usize,
+    selection: SEL,
     phantom: PhantomData<(I, S, P)>,
 }
 
@@ -171,6 +270,75 @@ where
     pub fn new(mutations: MT) -> Self {
         Self {
             mutations,
+            regions: None,
+            mask: None,
+            max_packets: 1,
+            selection: PacketSelection::Uniform,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<I, MT, S, P, SEL> PacketHavocMutator<I, MT, S, P, SEL>
+where
+    P: HasHavocMutation<MT, S>,
+    I: Input + HasLen + HasPackets<P>,
+    MT: MutatorsTuple<BytesInput, S>,
+    S: HasRand + HasMaxSize,
+{
+    /// Confine the stacked mutations of one round to a single, randomly chosen region
+    /// of a packet. Packets without regions are mutated as a whole.
+    pub fn with_regions(mut self) -> Self
+    where
+        P: HasRegions,
+    {
+        self.regions = Some(packet_regions::<P>);
+        self
+    }
+
+    /// Never change the bytes that a packet protects with [`HasMutationMask`](crate::HasMutationMask)
+    /// when mutating a region. The protected ranges must refer to the same bytes as the regions.
+    /// Regions that are protected entirely are never chosen and mutations that change a protected byte
+    /// are undone.
+    ///
+    /// Mutations of whole packets with [`HasHavocMutation`] already honor the mask of byte-backed packets.
+    ///
+    /// # Example
+    /// ```
+    /// let mutator = PacketHavocMutator::new(supported_havoc_mutations()).with_regions().with_mutation_mask();
+    /// ```
+    pub fn with_mutation_mask(mut self) -> Self
+    where
+        P: HasMutationMask,
+    {
+        self.mask = Some(P::protected_ranges);
+        self
+    }
+
+    /// Mutate between 1 and `max_packets` different packets per run.
+    /// Each packet gets its own stack of havoc mutations.
+    /// If the selector picks a packet twice, it is only mutated once.
+    ///
+    /// # Example
+    /// ```
+    /// let mutator = PacketHavocMutator::new(supported_havoc_mutations()).with_max_packets(3);
+    /// ```
+    pub fn with_max_packets(mut self, max_packets: usize) -> Self {
+        self.max_packets = max_packets.max(1);
+        self
+    }
+
+    /// Choose the packets to mutate with the given [`PacketSelector`] instead of uniformly
+    pub fn with_selection<SEL2>(self, selection: SEL2) -> PacketHavocMutator<I, MT, S, P, SEL2>
+    where
+        SEL2: PacketSelector<P, S>,
+    {
+        PacketHavocMutator {
+            mutations: self.mutations,
+            regions: self.regions,
+            mask: self.mask,
+            max_packets: self.max_packets,
+            selection,
             phantom: PhantomData,
         }
     }
@@ -186,21 +354,86 @@ where
     }
 }
 
-impl<I, MT, S, P> Mutator<I, S> for PacketHavocMutator<I, MT, S, P>
+impl<I, MT, S, P, SEL> Mutator<I, S> for PacketHavocMutator<I, MT, S, P, SEL>
 where
-    P: HasHavocMutation<MT, S>,
+    P: HasHavocMutation<MT, S> + HasPostMutationFixup,
     I: Input + HasLen + HasPackets<P>,
     MT: MutatorsTuple<BytesInput, S>,
     S: HasRand + HasMaxSize,
+    SEL: PacketSelector<P, S>,
 {
     fn mutate(&mut self, state: &mut S, input: &mut I, stage_idx: i32) -> Result<MutationResult, Error> {
         if input.len() == 0 {
             return Ok(MutationResult::Skipped);
         }
 
+        let mut result = MutationResult::Skipped;
+        let count = 1 + state.rand_mut().below(self.max_packets.min(input.len()) as u64) as usize;
+        let mut mutated = Vec::with_capacity(count);
+
+        for _ in 0..count {
+            let packet = self.selection.select_packet(state, input.packets());
+
+            if mutated.contains(&packet) {
+                continue;
+            }
+            mutated.push(packet);
+
+            if self.mutate_packet(state, input, packet, stage_idx)? == MutationResult::Mutated {
+                result = MutationResult::Mutated;
+            }
+        }
+
+        Ok(result)
+    }
+}
+
+impl<I, MT, S, P, SEL> PacketHavocMutator<I, MT, S, P, SEL>
+where
+    P: HasHavocMutation<MT, S> + HasPostMutationFixup,
+    I: Input + HasLen + HasPackets<P>,
+    MT: MutatorsTuple<BytesInput, S>,
+    S: HasRand + HasMaxSize,
+{
+    /// Apply a stack of havoc mutations to the packet at index `packet`
+    fn mutate_packet(&mut self, state: &mut S, input: &mut I, packet: usize, stage_idx: i32) -> Result<MutationResult, Error> {
         let mut result = MutationResult::Skipped;
         let iters = self.iterations(state);
-        let packet = state.rand_mut().below(input.len() as u64) as usize;
+
+        let protected = self.mask.map_or_else(Vec::new, |mask| mask(&input.packets()[packet]));
+
+        if let Some((mut regions, bytes)) = self.regions.and_then(|regions| regions(&mut input.packets_mut()[packet])) {
+            regions.retain(|region| !protected.iter().any(|range| range.start <= region.start && region.end <= range.end));
+
+            if regions.is_empty() {
+                return Ok(MutationResult::Skipped);
+            }
+
+            let region = regions[state.rand_mut().below(regions.len() as u64) as usize].clone();
+            let mut data = BytesInput::new(bytes[region.clone()].to_vec());
+
+            for _ in 0..iters {
+                let mutation = self.schedule(state);
+
+                if self.mutations.get_and_mutate(mutation, state, &mut data, stage_idx)? == MutationResult::Mutated {
+                    result = MutationResult::Mutated;
+                }
+            }
+
+            if result == MutationResult::Mutated {
+                let original = bytes.clone();
+                bytes.splice(region, data.bytes().iter().copied());
+
+                if !is_intact(&original, bytes, &protected) {
+                    *bytes = original;
+                    return Ok(MutationResult::Skipped);
+                }
+
+                input.packets_mut()[packet].fixup();
+            }
+
+            return Ok(result);
+        }
 
         for _ in 0..iters {
             let mutation = self.schedule(state);
@@ -212,11 +445,15 @@ where
             }
         }
 
+        if result == MutationResult::Mutated {
+            input.packets_mut()[packet].fixup();
+        }
+
         Ok(result)
     }
 }
 
-impl<I, MT, S, P> Named for PacketHavocMutator<I, MT, S, P>
+impl<I, MT, S, P, SEL> Named for PacketHavocMutator<I, MT, S, P, SEL>
 where
     P: HasHavocMutation<MT, S>,
     I: Input + HasLen + HasPackets<P>,
@@ -227,3 +464,193 @@ where
         "PacketHavocMutator"
     }
 }
+
+impl<I, MT, S, P, SEL> HasConfig for PacketHavocMutator<I, MT, S, P, SEL>
+where
+    P: HasHavocMutation<MT, S>,
+    I: Input + HasLen + HasPackets<P>,
+    MT: MutatorsTuple<BytesInput, S> + NamedTuple,
+    S: HasRand + HasMaxSize,
+    SEL: Debug,
+{
+    fn config(&self) -> ComponentConfig {
+        ComponentConfig::new(self.name())
+            .with_setting("mutations", (0..self.mutations.len()).filter_map(|mutation| self.mutations.name(mutation)).collect::<Vec<_>>().join(", "))
+            .with_setting("max_packets", self.max_packets)
+            .with_setting("regions", self.regions.is_some())
+            .with_setting("mutation_mask", self.mask.is_some())
+            .with_setting("selection", format!("{:?}", self.selection))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::TestInput;
+    use libafl::{
+        bolts::rands::StdRand,
+        corpus::InMemoryCorpus,
+        mutators::Tokens,
+        state::{HasMetadata, StdState},
+    };
+    use serde::{Deserialize, Serialize};
+
+    /// 2 bytes of header that must never change, followed by two fields
+    #[derive(Hash, Debug, Clone, Serialize, Deserialize)]
+    struct RegionPacket {
+        data: BytesInput,
+    }
+
+    impl<MT, S> HasHavocMutation<MT, S> for RegionPacket
+    where
+        MT: MutatorsTuple<BytesInput, S>,
+        S: HasRand + HasMaxSize,
+    {
+        fn mutate_havoc(&mut self, state: &mut S, mutations: &mut MT, mutation: usize, stage_idx: i32) -> Result<MutationResult, Error> {
+            self.data.mutate_havoc(state, mutations, mutation, stage_idx)
+        }
+    }
+
+    impl HasRegions for RegionPacket {
+        fn regions(&self) -> Vec<Range<usize>> {
+            vec![2..4, 4..self.data.bytes().len()]
+        }
+
+        fn region_bytes_mut(&mut self) -> Option<&mut Vec<u8>> {
+            Some(self.data.bytes_mut())
+        }
+    }
+
+    impl HasMutationMask for RegionPacket {
+        fn protected_ranges(&self) -> Vec<Range<usize>> {
+            // The header and the first byte of the first field
+            vec![0..2, 2..3]
+        }
+    }
+
+    impl HasPostMutationFixup for RegionPacket {}
+
+    #[test]
+    fn test_regions() {
+        let mut state = StdState::new(StdRand::with_seed(0), InMemoryCorpus::<TestInput<RegionPacket>>::new(), InMemoryCorpus::<TestInput<RegionPacket>>::new(), &mut (), &mut ()).unwrap();
+        let mut mutator = PacketHavocMutator::new(supported_havoc_mutations()).with_regions();
+        let mut input = TestInput {
+            packets: vec![RegionPacket {
+                data: BytesInput::new(b"\xAA\xBBbody".to_vec()),
+            }],
+        };
+
+        for _ in 0..1000 {
+            mutator.mutate(&mut state, &mut input, 0).unwrap();
+            let bytes = input.packets[0].data.bytes();
+            assert!(bytes.starts_with(b"\xAA\xBB"));
+
+            // Make sure that both regions stay valid
+            if bytes.len() < 5 {
+                input.packets[0].data.bytes_mut().resize(5, 0);
+            }
+        }
+    }
+
+    #[test]
+    fn test_mutation_mask() {
+        let mut state = StdState::new(StdRand::with_seed(0), InMemoryCorpus::<TestInput<RegionPacket>>::new(), InMemoryCorpus::<TestInput<RegionPacket>>::new(), &mut (), &mut ()).unwrap();
+        let mut mutator = PacketHavocMutator::new(supported_havoc_mutations()).with_regions().with_mutation_mask();
+        let mut mutated = false;
+
+        for _ in 0..1000 {
+            let mut input = TestInput {
+                packets: vec![RegionPacket {
+                    data: BytesInput::new(b"\xAA\xBBbody".to_vec()),
+                }],
+            };
+
+            mutator.mutate(&mut state, &mut input, 0).unwrap();
+            let bytes = input.packets[0].data.bytes();
+            assert!(bytes.starts_with(b"\xAA\xBBb"));
+            mutated |= bytes != b"\xAA\xBBbody";
+        }
+
+        assert!(mutated);
+    }
+
+    #[test]
+    fn test_max_packets() {
+        let mut state = StdState::new(StdRand::with_seed(0), InMemoryCorpus::<TestInput<RegionPacket>>::new(), InMemoryCorpus::<TestInput<RegionPacket>>::new(), &mut (), &mut ()).unwrap();
+        let mut mutator = PacketHavocMutator::new(supported_havoc_mutations()).with_max_packets(3);
+        let original = TestInput {
+            packets: (0..4)
+                .map(|_| RegionPacket {
+                    data: BytesInput::new(b"USER anonymous".to_vec()),
+                })
+                .collect(),
+        };
+        let mut most_mutated = 0;
+
+        for _ in 0..1000 {
+            let mut input = original.clone();
+            mutator.mutate(&mut state, &mut input, 0).unwrap();
+            let mutated = input.packets.iter().filter(|packet| packet.data.bytes() != b"USER anonymous").count();
+            assert!(mutated <= 3);
+            most_mutated = most_mutated.max(mutated);
+        }
+
+        assert_eq!(most_mutated, 3);
+    }
+
+    /// A user-defined packet that only implements HasBytesVec
+    #[derive(Hash, Debug, Clone, Serialize, Deserialize)]
+    struct Payload(Vec<u8>);
+
+    impl HasMutationMask for Payload {}
+
+    impl HasBytesVec for Payload {
+        fn bytes(&self) -> &[u8] {
+            &self.0
+        }
+
+        fn bytes_mut(&mut self) -> &mut Vec<u8> {
+            &mut self.0
+        }
+    }
+
+    #[test]
+    fn test_bytes_vec_newtype() {
+        use crate::mutators::{HasCrossoverInsertMutation, HasCrossoverReplaceMutation, HasSpliceMutation};
+
+        let mut state = StdState::new(StdRand::with_seed(0), InMemoryCorpus::<TestInput<RegionPacket>>::new(), InMemoryCorpus::<TestInput<RegionPacket>>::new(), &mut (), &mut ()).unwrap();
+        let mut mutations = supported_havoc_mutations();
+        let mut packet = Payload(b"USER anonymous".to_vec());
+        let other = Payload(b"PASS".to_vec());
+
+        assert!((0..100).any(|mutation| packet.mutate_havoc(&mut state, &mut mutations, mutation % 22, 0).unwrap() == MutationResult::Mutated));
+        assert_ne!(packet.0, b"USER anonymous");
+
+        assert_eq!(packet.mutate_crossover_insert(&mut state, &other, 0).unwrap(), MutationResult::Mutated);
+        assert_eq!(packet.mutate_crossover_replace(&mut state, &other, 0).unwrap(), MutationResult::Mutated);
+        assert_eq!(packet.mutate_splice(&mut state, &other, 0).unwrap(), MutationResult::Mutated);
+        assert_eq!(Payload(Vec::new()).mutate_splice(&mut state, &other, 0).unwrap(), MutationResult::Skipped);
+    }
+
+    #[test]
+    fn test_tokens() {
+        let mut state = StdState::new(StdRand::with_seed(0), InMemoryCorpus::<TestInput<RegionPacket>>::new(), InMemoryCorpus::<TestInput<RegionPacket>>::new(), &mut (), &mut ()).unwrap();
+        let mut mutator = PacketHavocMutator::new(supported_havoc_mutations_with_tokens());
+        let original = TestInput {
+            packets: vec![RegionPacket {
+                data: BytesInput::new(b"USER anonymous".to_vec()),
+            }],
+        };
+
+        let mut tokens = Tokens::new();
+        tokens.add_token(&b"SITE EXEC".to_vec());
+        state.add_metadata(tokens);
+
+        let found = (0..1000).any(|_| {
+            let mut input = original.clone();
+            mutator.mutate(&mut state, &mut input, 0).unwrap();
+            input.packets[0].data.bytes().windows(9).any(|window| window == b"SITE EXEC")
+        });
+        assert!(found);
+    }
+}
@@ -0,0 +1,132 @@
+use crate::{
+    config::{ComponentConfig, HasConfig},
+    input::HasPackets,
+    mutators::{terminal_setting, HasPostMutationFixup, HasTerminalPacket, PacketBounds, TerminalGuard, TerminalHandling},
+};
+use libafl::{
+    bolts::{rands::Rand, tuples::Named, HasLen},
+    inputs::{BytesInput, Input},
+    mutators::{MutationResult, Mutator},
+    state::{HasMaxSize, HasMetadata, HasRand},
+    Error,
+};
+use std::marker::PhantomData;
+
+/// The largest packet [`BytesInput`] generates on its own
+const MAX_GENERATED_LEN: u64 = 32;
+
+/// Signifies that a packet type can create new packets from scratch.
+/// Used by the [`PacketInsertMutator`].
+///
+/// The packets can be completely random or be built from templates,
+/// e.g. from a dictionary that is stored as metadata in the state.
+///
+/// Already implemented for:
+/// - [`BytesInput`](libafl::inputs::BytesInput): random bytes
+/// - [`TextLinePacket`](crate::TextLinePacket): a random keyword from the [`KeywordDictionary`](crate::KeywordDictionary) with random arguments
+/// - [`NetworkPacket`](crate::NetworkPacket): generates `Data` packets
+pub trait HasPacketGenerator<S>: Sized
+where
+    S: HasRand,
+{
+    /// Create a new packet or return `None` if no packet can be generated
+    fn generate_packet(state: &mut S) -> Option<Self>;
+}
+
+impl<S> HasPacketGenerator<S> for BytesInput
+where
+    S: HasRand + HasMaxSize,
+{
+    fn generate_packet(state: &mut S) -> Option<Self> {
+        let max_len = MAX_GENERATED_LEN.min(state.max_size() as u64);
+
+        if max_len == 0 {
+            return None;
+        }
+
+        let len = 1 + state.rand_mut().below(max_len);
+        let bytes = (0..len).map(|_| state.rand_mut().next() as u8).collect();
+        Some(BytesInput::new(bytes))
+    }
+}
+
+/// A mutator that inserts a newly generated packet at a random position.
+///
+/// The packets come from the [`HasPacketGenerator`] implementation of the packet type.
+/// It respects an upper bound on the number of packets
+/// passed as an argument to the constructor.
+///
+/// # Example
+/// ```
+/// // Make sure that we never exceed 16 packets in an input
+/// let mutator = PacketInsertMutator::new(16);
+/// ```
+pub struct PacketInsertMutator<P> {
+    max_packets: usize,
+    terminal: Option<TerminalGuard<P>>,
+    phantom: PhantomData<P>,
+}
+
+impl<P> PacketInsertMutator<P> {
+    /// Create a new PacketInsertMutator with an upper bound on the number of packets
+    pub fn new(max_packets: usize) -> Self {
+        Self {
+            max_packets,
+            terminal: None,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Take packets that end a session into account, see [`HasTerminalPacket`]
+    pub fn with_terminal_handling(mut self, handling: TerminalHandling) -> Self
+    where
+        P: HasTerminalPacket,
+    {
+        self.terminal = Some(TerminalGuard::new(handling));
+        self
+    }
+}
+
+impl<I, S, P> Mutator<I, S> for PacketInsertMutator<P>
+where
+    P: HasPacketGenerator<S> + HasPostMutationFixup,
+    I: Input + HasLen + HasPackets<P>,
+    S: HasRand + HasMetadata,
+{
+    fn mutate(&mut self, state: &mut S, input: &mut I, _stage_idx: i32) -> Result<MutationResult, Error> {
+        if input.len() >= PacketBounds::max_packets(state, self.max_packets) {
+            return Ok(MutationResult::Skipped);
+        }
+
+        let mut packet = match P::generate_packet(state) {
+            Some(packet) => packet,
+            None => return Ok(MutationResult::Skipped),
+        };
+        packet.fixup();
+        let to = state.rand_mut().below(input.len() as u64 + 1) as usize;
+        let trailing = self.terminal.as_ref().map_or(0, |guard| guard.trailing(input.packets()));
+
+        input.packets_mut().insert(to, packet);
+
+        if let Some(guard) = &self.terminal {
+            if !guard.accept(trailing, input.packets_mut()) {
+                input.packets_mut().remove(to);
+                return Ok(MutationResult::Skipped);
+            }
+        }
+
+        Ok(MutationResult::Mutated)
+    }
+}
+
+impl<P> Named for PacketInsertMutator<P> {
+    fn name(&self) -> &str {
+        "PacketInsertMutator"
+    }
+}
+
+impl<P> HasConfig for PacketInsertMutator<P> {
+    fn config(&self) -> ComponentConfig {
+        ComponentConfig::new(self.name()).with_setting("max_packets", self.max_packets).with_setting("terminal", terminal_setting(&self.terminal))
+    }
+}
@@ -0,0 +1,116 @@
+use crate::{
+    config::{ComponentConfig, HasConfig},
+    input::HasPackets,
+    mutators::{HasPostMutationFixup, PacketBounds, PacketSelection, PacketSelector},
+};
+use libafl::{
+    bolts::{tuples::Named, HasLen},
+    inputs::{BytesInput, HasBytesVec, Input},
+    mutators::{MutationResult, Mutator},
+    state::{HasMaxSize, HasMetadata, HasRand},
+    Error,
+};
+use std::fmt::Debug;
+use std::marker::PhantomData;
+
+/// Signifies that two packets can be combined into a single packet.
+/// Used by the [`PacketMergeMutator`].
+///
+/// Already implemented for:
+/// - [`BytesInput`](libafl::inputs::BytesInput)
+/// - [`NetworkPacket`](crate::NetworkPacket): only two `Data` packets are merged
+pub trait HasMerge<S>
+where
+    S: HasRand,
+{
+    /// Append `other` to this packet. Returns whether the packets were merged.
+    fn merge(&mut self, state: &mut S, other: &Self) -> bool;
+}
+
+impl<S> HasMerge<S> for BytesInput
+where
+    S: HasRand + HasMaxSize,
+{
+    fn merge(&mut self, state: &mut S, other: &Self) -> bool {
+        if self.bytes().len() + other.bytes().len() > state.max_size() {
+            return false;
+        }
+
+        self.bytes_mut().extend_from_slice(other.bytes());
+        true
+    }
+}
+
+/// A mutator that merges two adjacent packets into one.
+///
+/// This is the opposite of the [`PacketFragmentMutator`](crate::PacketFragmentMutator)
+/// and exercises parsers that expect exactly one message per read.
+///
+/// # Example
+/// ```
+/// let mutator = PacketMergeMutator::new();
+/// ```
+pub struct PacketMergeMutator<P, SEL = PacketSelection> {
+    selection: SEL,
+    phantom: PhantomData<P>,
+}
+
+impl<P> PacketMergeMutator<P> {
+    /// Create a new PacketMergeMutator
+    pub fn new() -> Self {
+        Self {
+            selection: PacketSelection::Uniform,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<P, SEL> PacketMergeMutator<P, SEL> {
+    /// Choose the packet with the given [`PacketSelector`] instead of uniformly
+    pub fn with_selection<SEL2>(self, selection: SEL2) -> PacketMergeMutator<P, SEL2> {
+        PacketMergeMutator {
+            selection,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<I, S, P, SEL> Mutator<I, S> for PacketMergeMutator<P, SEL>
+where
+    P: HasMerge<S> + HasPostMutationFixup,
+    I: Input + HasLen + HasPackets<P>,
+    S: HasRand + HasMetadata,
+    SEL: PacketSelector<P, S>,
+{
+    fn mutate(&mut self, state: &mut S, input: &mut I, _stage_idx: i32) -> Result<MutationResult, Error> {
+        if input.len() <= PacketBounds::min_packets(state, 1) {
+            return Ok(MutationResult::Skipped);
+        }
+
+        let idx = self.selection.select_packet(state, &input.packets()[..input.len() - 1]);
+        let (first, second) = input.packets_mut().split_at_mut(idx + 1);
+
+        if first[idx].merge(state, &second[0]) {
+            input.packets_mut().remove(idx + 1);
+            input.packets_mut()[idx].fixup();
+            Ok(MutationResult::Mutated)
+        } else {
+            Ok(MutationResult::Skipped)
+        }
+    }
+}
+
+impl<P, SEL> Named for PacketMergeMutator<P, SEL> {
+    fn name(&self) -> &str {
+        "PacketMergeMutator"
+    }
+}
+
+impl<P, SEL> HasConfig for PacketMergeMutator<P, SEL>
+where
+    SEL: Debug,
+{
+    fn config(&self) -> ComponentConfig {
+        ComponentConfig::new(self.name()).with_setting("selection", format!("{:?}", self.selection))
+    }
+}
@@ -1,13 +1,54 @@
+mod bounds;
+mod convert;
 mod crossover;
 mod delete;
 mod duplicate;
+mod field;
+mod fixup;
+mod fragment;
 mod havoc;
+mod insert;
+mod merge;
+mod printable;
+mod protect;
+#[cfg(feature = "radamsa")]
+mod radamsa;
+mod reconnect;
 mod reorder;
+mod rotate;
+mod selection;
+mod shuffle;
 mod splice;
+mod suffix;
+mod teardown;
+mod terminal;
+mod truncate;
 
+pub use bounds::{PacketBounds, PacketBoundsPolicy, PacketBoundsStage, StallGrowthPolicy};
+pub use convert::{HasKindConversion, PacketKindConversionMutator};
 pub use crossover::{HasCrossoverInsertMutation, HasCrossoverReplaceMutation, PacketCrossoverInsertMutator, PacketCrossoverReplaceMutator};
 pub use delete::PacketDeleteMutator;
-pub use duplicate::PacketDuplicateMutator;
-pub use havoc::{supported_havoc_mutations, HasHavocMutation, PacketHavocMutator, SupportedHavocMutationsType};
+pub use duplicate::{PacketDuplicateHavocMutator, PacketDuplicateMutator};
+pub use field::{FieldWidth, HasNumericFieldMutation, NumericField, PacketFieldMutator};
+pub use fixup::HasPostMutationFixup;
+pub use fragment::{HasSplit, PacketFragmentMutator};
+pub use havoc::{supported_havoc_mutations, supported_havoc_mutations_with_tokens, HasHavocMutation, HasRegions, PacketHavocMutator, SupportedHavocMutationsType, SupportedHavocMutationsWithTokensType};
+pub use insert::{HasPacketGenerator, PacketInsertMutator};
+pub use merge::{HasMerge, PacketMergeMutator};
+pub use printable::{supported_text_havoc_mutations, CaseFlipMutator, PrintableByteMutator, PrintableInsertMutator, SupportedTextHavocMutationsType, TextCopyMutator, TextDeleteMutator, TextNumberMutator};
+pub use protect::HasMutationMask;
+pub(crate) use protect::{is_intact, mutate_masked};
+#[cfg(feature = "radamsa")]
+pub use radamsa::{radamsa_mutations, BadStringMutator, FunnyUnicodeMutator, FuseMutator, LineDeleteMutator, LineRepeatMutator, LineSwapMutator, PacketRadamsaMutator, RadamsaMutationsType, SequenceRepeatMutator};
+pub use reconnect::PacketReconnectMutator;
 pub use reorder::PacketReorderMutator;
-pub use splice::{HasSpliceMutation, PacketSpliceMutator};
+pub use rotate::PacketRotateMutator;
+pub(crate) use selection::choose_weighted;
+pub use selection::{LengthSelector, NoveltySelector, PacketSelection, PacketSelector, TransitionNoveltyMetadata};
+pub use shuffle::PacketShuffleMutator;
+pub use splice::{HasDelimitedSpliceMutation, HasSpliceMutation, PacketSpliceMutator};
+pub use suffix::PacketSuffixSpliceMutator;
+pub use teardown::PacketTeardownMutator;
+pub(crate) use terminal::{terminal_setting, TerminalGuard};
+pub use terminal::{HasTerminalPacket, TerminalHandling};
+pub use truncate::PacketTruncateMutator;
@@ -0,0 +1,364 @@
+use libafl::{
+    bolts::{rands::Rand, tuples::Named},
+    inputs::{HasBytesVec, Input},
+    mutators::{MutationResult, Mutator},
+    state::{HasMaxSize, HasRand},
+    Error,
+};
+use std::ops::Range;
+
+/// Upper bound on the number of bytes that get inserted, copied or deleted at once
+const MAX_TEXT_CHUNK: u64 = 16;
+/// Largest value that gets added to or subtracted from a number, same as in AFL
+const ARITH_MAX: u64 = 35;
+/// Numbers that commonly hit boundaries in parsers of text protocols
+const INTERESTING_NUMBERS: [u64; 12] = [0, 1, 127, 128, 255, 256, 65535, 65536, 2147483647, 2147483648, 4294967295, 4294967296];
+
+/// Line terminators are never modified, split or deleted by the text mutators
+fn is_terminator(byte: u8) -> bool {
+    byte == b'\r' || byte == b'\n'
+}
+
+/// A random printable ASCII character
+fn random_printable<S: HasRand>(state: &mut S) -> u8 {
+    0x20 + state.rand_mut().below(95) as u8
+}
+
+/// A random position to insert at that neither splits a CRLF nor comes after the final line terminator
+fn insert_position<S: HasRand>(state: &mut S, bytes: &[u8]) -> usize {
+    let end = bytes.len() - bytes.iter().rev().take_while(|&&byte| is_terminator(byte)).count();
+    let pos = state.rand_mut().below(end as u64 + 1) as usize;
+
+    if pos > 0 && pos < bytes.len() && bytes[pos - 1] == b'\r' && bytes[pos] == b'\n' {
+        pos - 1
+    } else {
+        pos
+    }
+}
+
+/// A random, non-empty range of bytes that contains no line terminators
+fn text_range<S: HasRand>(state: &mut S, bytes: &[u8]) -> Option<Range<usize>> {
+    let candidates: Vec<usize> = (0..bytes.len()).filter(|&i| !is_terminator(bytes[i])).collect();
+
+    if candidates.is_empty() {
+        return None;
+    }
+
+    let start = *state.rand_mut().choose(&candidates);
+    let len = 1 + state.rand_mut().below(MAX_TEXT_CHUNK) as usize;
+    let end = bytes[start..].iter().take(len).position(|&byte| is_terminator(byte)).map_or_else(|| (start + len).min(bytes.len()), |offset| start + offset);
+
+    Some(start..end)
+}
+
+/// Replaces a single byte that is not a line terminator with a random printable character
+#[derive(Debug, Default)]
+pub struct PrintableByteMutator;
+
+impl PrintableByteMutator {
+    /// Create a new PrintableByteMutator
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl<I, S> Mutator<I, S> for PrintableByteMutator
+where
+    I: Input + HasBytesVec,
+    S: HasRand,
+{
+    fn mutate(&mut self, state: &mut S, input: &mut I, _stage_idx: i32) -> Result<MutationResult, Error> {
+        let range = match text_range(state, input.bytes()) {
+            Some(range) => range,
+            None => return Ok(MutationResult::Skipped),
+        };
+
+        let new = random_printable(state);
+        let byte = &mut input.bytes_mut()[range.start];
+
+        if *byte == new {
+            return Ok(MutationResult::Skipped);
+        }
+
+        *byte = new;
+        Ok(MutationResult::Mutated)
+    }
+}
+
+impl Named for PrintableByteMutator {
+    fn name(&self) -> &str {
+        "PrintableByteMutator"
+    }
+}
+
+/// Toggles the case of a letter, for case-insensitive keyword handling
+#[derive(Debug, Default)]
+pub struct CaseFlipMutator;
+
+impl CaseFlipMutator {
+    /// Create a new CaseFlipMutator
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl<I, S> Mutator<I, S> for CaseFlipMutator
+where
+    I: Input + HasBytesVec,
+    S: HasRand,
+{
+    fn mutate(&mut self, state: &mut S, input: &mut I, _stage_idx: i32) -> Result<MutationResult, Error> {
+        let letters: Vec<usize> = (0..input.bytes().len()).filter(|&i| input.bytes()[i].is_ascii_alphabetic()).collect();
+
+        if letters.is_empty() {
+            return Ok(MutationResult::Skipped);
+        }
+
+        let idx = *state.rand_mut().choose(&letters);
+        input.bytes_mut()[idx] ^= 0x20;
+
+        Ok(MutationResult::Mutated)
+    }
+}
+
+impl Named for CaseFlipMutator {
+    fn name(&self) -> &str {
+        "CaseFlipMutator"
+    }
+}
+
+/// Inserts a run of random printable characters or of a single repeated one
+#[derive(Debug, Default)]
+pub struct PrintableInsertMutator;
+
+impl PrintableInsertMutator {
+    /// Create a new PrintableInsertMutator
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl<I, S> Mutator<I, S> for PrintableInsertMutator
+where
+    I: Input + HasBytesVec,
+    S: HasRand + HasMaxSize,
+{
+    fn mutate(&mut self, state: &mut S, input: &mut I, _stage_idx: i32) -> Result<MutationResult, Error> {
+        let len = 1 + state.rand_mut().below(MAX_TEXT_CHUNK) as usize;
+
+        if input.bytes().len() + len > state.max_size() {
+            return Ok(MutationResult::Skipped);
+        }
+
+        let repeat = state.rand_mut().below(2) == 0;
+        let first = random_printable(state);
+        let chunk: Vec<u8> = (0..len).map(|i| if repeat || i == 0 { first } else { random_printable(state) }).collect();
+        let pos = insert_position(state, input.bytes());
+
+        input.bytes_mut().splice(pos..pos, chunk);
+        Ok(MutationResult::Mutated)
+    }
+}
+
+impl Named for PrintableInsertMutator {
+    fn name(&self) -> &str {
+        "PrintableInsertMutator"
+    }
+}
+
+/// Deletes a few bytes that don't contain a line terminator
+#[derive(Debug, Default)]
+pub struct TextDeleteMutator;
+
+impl TextDeleteMutator {
+    /// Create a new TextDeleteMutator
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl<I, S> Mutator<I, S> for TextDeleteMutator
+where
+    I: Input + HasBytesVec,
+    S: HasRand,
+{
+    fn mutate(&mut self, state: &mut S, input: &mut I, _stage_idx: i32) -> Result<MutationResult, Error> {
+        match text_range(state, input.bytes()) {
+            Some(range) => {
+                input.bytes_mut().drain(range);
+                Ok(MutationResult::Mutated)
+            },
+            None => Ok(MutationResult::Skipped),
+        }
+    }
+}
+
+impl Named for TextDeleteMutator {
+    fn name(&self) -> &str {
+        "TextDeleteMutator"
+    }
+}
+
+/// Copies a few bytes that don't contain a line terminator to another position
+#[derive(Debug, Default)]
+pub struct TextCopyMutator;
+
+impl TextCopyMutator {
+    /// Create a new TextCopyMutator
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl<I, S> Mutator<I, S> for TextCopyMutator
+where
+    I: Input + HasBytesVec,
+    S: HasRand + HasMaxSize,
+{
+    fn mutate(&mut self, state: &mut S, input: &mut I, _stage_idx: i32) -> Result<MutationResult, Error> {
+        let range = match text_range(state, input.bytes()) {
+            Some(range) => range,
+            None => return Ok(MutationResult::Skipped),
+        };
+
+        if input.bytes().len() + range.len() > state.max_size() {
+            return Ok(MutationResult::Skipped);
+        }
+
+        let chunk = input.bytes()[range].to_vec();
+        let pos = insert_position(state, input.bytes());
+
+        input.bytes_mut().splice(pos..pos, chunk);
+        Ok(MutationResult::Mutated)
+    }
+}
+
+impl Named for TextCopyMutator {
+    fn name(&self) -> &str {
+        "TextCopyMutator"
+    }
+}
+
+/// Treats a run of decimal digits as a number and replaces it with a nearby, interesting or negative number
+#[derive(Debug, Default)]
+pub struct TextNumberMutator;
+
+impl TextNumberMutator {
+    /// Create a new TextNumberMutator
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl<I, S> Mutator<I, S> for TextNumberMutator
+where
+    I: Input + HasBytesVec,
+    S: HasRand + HasMaxSize,
+{
+    fn mutate(&mut self, state: &mut S, input: &mut I, _stage_idx: i32) -> Result<MutationResult, Error> {
+        let bytes = input.bytes();
+        let mut numbers = Vec::new();
+        let mut i = 0;
+
+        while i < bytes.len() {
+            if bytes[i].is_ascii_digit() {
+                let start = i;
+
+                while i < bytes.len() && bytes[i].is_ascii_digit() {
+                    i += 1;
+                }
+
+                numbers.push(start..i);
+            } else {
+                i += 1;
+            }
+        }
+
+        if numbers.is_empty() {
+            return Ok(MutationResult::Skipped);
+        }
+
+        let range = state.rand_mut().choose(&numbers).clone();
+        let old = std::str::from_utf8(&bytes[range.clone()]).unwrap().parse::<u64>().unwrap_or(u64::MAX);
+
+        let new = match state.rand_mut().below(4) {
+            0 => old.saturating_add(1 + state.rand_mut().below(ARITH_MAX)).to_string(),
+            1 => old.saturating_sub(1 + state.rand_mut().below(ARITH_MAX)).to_string(),
+            2 => state.rand_mut().choose(&INTERESTING_NUMBERS).to_string(),
+            _ => format!("-{}", old),
+        };
+
+        if new.as_bytes() == &bytes[range.clone()] || bytes.len() - range.len() + new.len() > state.max_size() {
+            return Ok(MutationResult::Skipped);
+        }
+
+        input.bytes_mut().splice(range, new.into_bytes());
+        Ok(MutationResult::Mutated)
+    }
+}
+
+impl Named for TextNumberMutator {
+    fn name(&self) -> &str {
+        "TextNumberMutator"
+    }
+}
+
+/// Tuple of the havoc mutators for text protocols, see [`supported_text_havoc_mutations()`]
+pub type SupportedTextHavocMutationsType = (PrintableByteMutator, (CaseFlipMutator, (PrintableInsertMutator, (TextDeleteMutator, (TextCopyMutator, (TextNumberMutator, ()))))));
+
+/// Returns a tuple of havoc mutations for text protocols like FTP or SMTP that can be used by a [`PacketHavocMutator`](crate::PacketHavocMutator).
+///
+/// Raw bitflips on text commands mostly produce garbage that the target rejects right away.
+/// These mutators instead
+/// - only ever write printable ASCII characters,
+/// - never modify, split or delete the `\r` and `\n` of line terminators and
+/// - mutate runs of digits as numbers.
+///
+/// # Example
+/// ```
+/// let mutator = PacketHavocMutator::new(supported_text_havoc_mutations());
+/// ```
+pub fn supported_text_havoc_mutations() -> SupportedTextHavocMutationsType {
+    (PrintableByteMutator::new(), (CaseFlipMutator::new(), (PrintableInsertMutator::new(), (TextDeleteMutator::new(), (TextCopyMutator::new(), (TextNumberMutator::new(), ()))))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libafl::{
+        bolts::{rands::StdRand, tuples::HasConstLen},
+        corpus::InMemoryCorpus,
+        inputs::BytesInput,
+        mutators::MutatorsTuple,
+        state::StdState,
+    };
+
+    #[test]
+    fn test_text_havoc() {
+        let mut state = StdState::new(StdRand::with_seed(0), InMemoryCorpus::<BytesInput>::new(), InMemoryCorpus::<BytesInput>::new(), &mut (), &mut ()).unwrap();
+        let mut mutations = supported_text_havoc_mutations();
+        let mut numbers = TextNumberMutator::new();
+        let mut number_changed = false;
+
+        for _ in 0..1000 {
+            let mut input = BytesInput::new(b"REST 1024\r\nRETR file.txt\r\n".to_vec());
+
+            for _ in 0..8 {
+                let mutation = state.rand_mut().below(mutations.len() as u64) as usize;
+                mutations.get_and_mutate(mutation, &mut state, &mut input, 0).unwrap();
+            }
+
+            let bytes = input.bytes();
+            assert!(bytes.iter().all(|&byte| (0x20..0x7F).contains(&byte) || is_terminator(byte)));
+            assert!(bytes.ends_with(b"\r\n"));
+            assert_eq!(bytes.windows(2).filter(|window| window == b"\r\n").count(), 2);
+            assert_eq!(bytes.iter().filter(|&&byte| is_terminator(byte)).count(), 4);
+
+            let mut input = BytesInput::new(b"REST 1024\r\n".to_vec());
+            numbers.mutate(&mut state, &mut input, 0).unwrap();
+            number_changed |= input.bytes() == b"REST 1025\r\n";
+        }
+
+        assert!(number_changed);
+    }
+}
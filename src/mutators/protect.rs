@@ -0,0 +1,126 @@
+use libafl::{
+    inputs::{BytesInput, HasBytesVec},
+    mutators::MutationResult,
+    Error,
+};
+use std::ops::Range;
+
+/// Signifies that a packet has byte ranges that must never be mutated, like magic numbers
+/// or session cookies that the target checks before anything else.
+///
+/// Havoc, crossover and splice mutations of byte-backed packets, i.e. packets that implement
+/// [`HasBytesVec`](libafl::inputs::HasBytesVec), are undone if they change a protected byte.
+/// Protected ranges are given as offsets into [`bytes()`](libafl::inputs::HasBytesVec::bytes), so a mutation that
+/// inserts or deletes bytes before a protected range counts as a change of that range.
+/// The [`PacketHavocMutator`](crate::PacketHavocMutator) additionally honors the mask within regions,
+/// see [`with_mutation_mask()`](crate::PacketHavocMutator::with_mutation_mask).
+///
+/// IMPORTANT: byte-backed packets must implement this trait to get the havoc, crossover and splice mutations.
+/// An empty implementation protects nothing.
+///
+/// Already implemented for:
+/// - [`BytesInput`](libafl::inputs::BytesInput): nothing is protected
+/// - [`NetworkPacket`](crate::NetworkPacket): forwards to the inner packet of `Data` packets
+///
+/// # Example
+/// ```
+/// struct SessionPacket {
+///     // 4 bytes magic, 16 bytes session cookie, body
+///     data: Vec<u8>,
+/// }
+///
+/// impl HasMutationMask for SessionPacket {
+///     fn protected_ranges(&self) -> Vec<Range<usize>> {
+///         vec![0..20]
+///     }
+/// }
+/// ```
+pub trait HasMutationMask {
+    /// The byte ranges that must not be changed by mutations
+    fn protected_ranges(&self) -> Vec<Range<usize>> {
+        Vec::new()
+    }
+}
+
+impl HasMutationMask for BytesInput {}
+
+/// Returns whether all `protected` ranges of `original` are unchanged in `bytes`.
+/// Ranges that don't fit into `original` are ignored.
+pub(crate) fn is_intact(original: &[u8], bytes: &[u8], protected: &[Range<usize>]) -> bool {
+    protected.iter().filter(|range| range.start < range.end && range.end <= original.len()).all(|range| bytes.get(range.clone()) == Some(&original[range.clone()]))
+}
+
+/// Applies `mutate` to a byte-backed packet and undoes it if it changed a protected byte
+pub(crate) fn mutate_masked<T, F>(packet: &mut T, mutate: F) -> Result<MutationResult, Error>
+where
+    T: HasBytesVec + HasMutationMask,
+    F: FnOnce(&mut T) -> Result<MutationResult, Error>,
+{
+    let protected = packet.protected_ranges();
+
+    if protected.is_empty() {
+        return mutate(packet);
+    }
+
+    let original = packet.bytes().to_vec();
+    let result = mutate(packet)?;
+
+    if result == MutationResult::Mutated && !is_intact(&original, packet.bytes(), &protected) {
+        *packet.bytes_mut() = original;
+        return Ok(MutationResult::Skipped);
+    }
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mutators::{supported_havoc_mutations, HasCrossoverInsertMutation, HasHavocMutation, HasSpliceMutation};
+    use libafl::{
+        bolts::rands::{Rand, StdRand},
+        corpus::InMemoryCorpus,
+        state::{HasRand, StdState},
+    };
+
+    /// 2 bytes of magic and 2 bytes of session cookie, followed by the body
+    struct CookiePacket(Vec<u8>);
+
+    impl HasBytesVec for CookiePacket {
+        fn bytes(&self) -> &[u8] {
+            &self.0
+        }
+
+        fn bytes_mut(&mut self) -> &mut Vec<u8> {
+            &mut self.0
+        }
+    }
+
+    impl HasMutationMask for CookiePacket {
+        fn protected_ranges(&self) -> Vec<Range<usize>> {
+            vec![0..2, 2..4]
+        }
+    }
+
+    #[test]
+    fn test_mutation_mask() {
+        let mut state = StdState::new(StdRand::with_seed(0), InMemoryCorpus::<BytesInput>::new(), InMemoryCorpus::<BytesInput>::new(), &mut (), &mut ()).unwrap();
+        let mut mutations = supported_havoc_mutations();
+        let other = CookiePacket(b"XXXXother".to_vec());
+        let mut mutated = false;
+
+        for _ in 0..1000 {
+            let mut packet = CookiePacket(b"\xDE\xAD\xBE\xEFbody".to_vec());
+            let mutation = state.rand_mut().below(22) as usize;
+
+            let results = [packet.mutate_havoc(&mut state, &mut mutations, mutation, 0).unwrap(), packet.mutate_crossover_insert(&mut state, &other, 0).unwrap(), packet.mutate_splice(&mut state, &other, 0).unwrap()];
+
+            assert!(packet.0.starts_with(b"\xDE\xAD\xBE\xEF"));
+            mutated |= results.contains(&MutationResult::Mutated);
+        }
+
+        assert!(mutated);
+        assert!(!is_intact(b"abcd", b"abXd", &[0..1, 1..3]));
+        assert!(is_intact(b"abcd", b"abXd", &[0..2, 3..4, 2..9]));
+    }
+}
@@ -0,0 +1,550 @@
+use crate::{
+    config::{ComponentConfig, HasConfig},
+    input::HasPackets,
+    mutators::{choose_weighted, HasHavocMutation, HasPostMutationFixup, PacketSelection, PacketSelector, TextNumberMutator},
+};
+use libafl::{
+    bolts::{
+        rands::Rand,
+        tuples::{tuple_list, tuple_list_type, HasConstLen, Named, NamedTuple},
+        HasLen,
+    },
+    inputs::{BytesInput, HasBytesVec, Input},
+    mutators::{
+        mutations::{BitFlipMutator, ByteRandMutator, BytesDeleteMutator, BytesRandInsertMutator},
+        MutationResult, Mutator, MutatorsTuple,
+    },
+    state::{HasMaxSize, HasRand},
+    Error,
+};
+use std::marker::PhantomData;
+use std::ops::Range;
+
+/// Upper bound on the length of a sequence that gets repeated
+const MAX_SEQUENCE_LEN: u64 = 32;
+/// Upper bound on the exponent of the number of repetitions, i.e. at most 2^10 repetitions
+const MAX_REPEAT_EXP: u64 = 10;
+/// Upper bound on the priority of a mutation
+const MAX_SCORE: u64 = 10;
+/// On average, the priorities of all mutations get re-rolled once in this many runs
+const RESCORE_INTERVAL: u64 = 1000;
+/// Upper bound on the number of mutations in a burst
+const MAX_BURST: usize = 16;
+
+/// Strings that commonly trip up parsers, string formatting and shell escaping
+const BAD_STRINGS: [&[u8]; 14] = [b"%n", b"%s%s%s%s%s%s", b"%99999999999s", b"\0", b"\r\n", b"../../../../../../etc/passwd", b"$(reboot)", b"`reboot`", b";reboot;", b"'", b"\"", b"\\", b"{{}}", b"%0a%0d"];
+
+/// Byte sequences that commonly trip up UTF-8 handling
+const FUNNY_UNICODE: [&[u8]; 9] = [
+    &[0xC0, 0xAF],             // overlong '/'
+    &[0xEF, 0xBB, 0xBF],       // byte order mark
+    &[0xE2, 0x80, 0xAE],       // right-to-left override
+    &[0xE2, 0x80, 0x8D],       // zero width joiner
+    &[0xEF, 0xBF, 0xBF],       // noncharacter U+FFFF
+    &[0xED, 0xA0, 0x80],       // lone surrogate
+    &[0xF4, 0x8F, 0xBF, 0xBF], // largest code point
+    &[0xFE],
+    &[0xFF],
+];
+
+/// The ranges of all lines in `bytes`, each including its `\n`
+fn lines(bytes: &[u8]) -> Vec<Range<usize>> {
+    let mut lines = Vec::new();
+    let mut start = 0;
+
+    for (i, byte) in bytes.iter().enumerate() {
+        if *byte == b'\n' {
+            lines.push(start..i + 1);
+            start = i + 1;
+        }
+    }
+
+    if start < bytes.len() {
+        lines.push(start..bytes.len());
+    }
+
+    lines
+}
+
+/// A random number of repetitions between 2 and 2^[`MAX_REPEAT_EXP`], so that most repetitions
+/// are short but some are long enough to hit buffer limits
+fn repetitions<S: HasRand>(state: &mut S) -> usize {
+    1 << (1 + state.rand_mut().below(MAX_REPEAT_EXP))
+}
+
+/// Inserts `chunk` repeated `count` times at `pos`, as long as the result fits into the max size
+fn insert_repeated<I, S>(state: &S, input: &mut I, pos: usize, chunk: &[u8], count: usize) -> MutationResult
+where
+    I: HasBytesVec,
+    S: HasMaxSize,
+{
+    let count = count.min(state.max_size().saturating_sub(input.bytes().len()) / chunk.len().max(1));
+
+    if count == 0 || chunk.is_empty() {
+        return MutationResult::Skipped;
+    }
+
+    input.bytes_mut().splice(pos..pos, chunk.iter().copied().cycle().take(chunk.len() * count));
+    MutationResult::Mutated
+}
+
+/// Deletes a line, like the `ld` mutation of Radamsa
+#[derive(Debug, Default)]
+pub struct LineDeleteMutator;
+
+impl LineDeleteMutator {
+    /// Create a new LineDeleteMutator
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl<I, S> Mutator<I, S> for LineDeleteMutator
+where
+    I: Input + HasBytesVec,
+    S: HasRand,
+{
+    fn mutate(&mut self, state: &mut S, input: &mut I, _stage_idx: i32) -> Result<MutationResult, Error> {
+        let lines = lines(input.bytes());
+
+        // Never delete the only line
+        if lines.len() <= 1 {
+            return Ok(MutationResult::Skipped);
+        }
+
+        let line = state.rand_mut().choose(&lines).clone();
+        input.bytes_mut().drain(line);
+        Ok(MutationResult::Mutated)
+    }
+}
+
+impl Named for LineDeleteMutator {
+    fn name(&self) -> &str {
+        "LineDeleteMutator"
+    }
+}
+
+/// Repeats a line between 2 and 1024 times, like the `lr` mutation of Radamsa
+#[derive(Debug, Default)]
+pub struct LineRepeatMutator;
+
+impl LineRepeatMutator {
+    /// Create a new LineRepeatMutator
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl<I, S> Mutator<I, S> for LineRepeatMutator
+where
+    I: Input + HasBytesVec,
+    S: HasRand + HasMaxSize,
+{
+    fn mutate(&mut self, state: &mut S, input: &mut I, _stage_idx: i32) -> Result<MutationResult, Error> {
+        let lines = lines(input.bytes());
+
+        if lines.is_empty() {
+            return Ok(MutationResult::Skipped);
+        }
+
+        let line = state.rand_mut().choose(&lines).clone();
+        let chunk = input.bytes()[line.clone()].to_vec();
+        let count = repetitions(state) - 1;
+
+        Ok(insert_repeated(state, input, line.end, &chunk, count))
+    }
+}
+
+impl Named for LineRepeatMutator {
+    fn name(&self) -> &str {
+        "LineRepeatMutator"
+    }
+}
+
+/// Swaps two lines, like the `ls` mutation of Radamsa
+#[derive(Debug, Default)]
+pub struct LineSwapMutator;
+
+impl LineSwapMutator {
+    /// Create a new LineSwapMutator
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl<I, S> Mutator<I, S> for LineSwapMutator
+where
+    I: Input + HasBytesVec,
+    S: HasRand,
+{
+    fn mutate(&mut self, state: &mut S, input: &mut I, _stage_idx: i32) -> Result<MutationResult, Error> {
+        let mut lines = lines(input.bytes());
+
+        if lines.len() <= 1 {
+            return Ok(MutationResult::Skipped);
+        }
+
+        let first = state.rand_mut().below(lines.len() as u64) as usize;
+        let second = state.rand_mut().below(lines.len() as u64) as usize;
+
+        if input.bytes()[lines[first].clone()] == input.bytes()[lines[second].clone()] {
+            return Ok(MutationResult::Skipped);
+        }
+
+        lines.swap(first, second);
+        let bytes: Vec<u8> = lines.into_iter().flat_map(|line| input.bytes()[line].to_vec()).collect();
+        *input.bytes_mut() = bytes;
+
+        Ok(MutationResult::Mutated)
+    }
+}
+
+impl Named for LineSwapMutator {
+    fn name(&self) -> &str {
+        "LineSwapMutator"
+    }
+}
+
+/// Repeats a short sequence of bytes between 2 and 1024 times, like the `sr` mutation of Radamsa
+#[derive(Debug, Default)]
+pub struct SequenceRepeatMutator;
+
+impl SequenceRepeatMutator {
+    /// Create a new SequenceRepeatMutator
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl<I, S> Mutator<I, S> for SequenceRepeatMutator
+where
+    I: Input + HasBytesVec,
+    S: HasRand + HasMaxSize,
+{
+    fn mutate(&mut self, state: &mut S, input: &mut I, _stage_idx: i32) -> Result<MutationResult, Error> {
+        let len = input.bytes().len();
+
+        if len == 0 {
+            return Ok(MutationResult::Skipped);
+        }
+
+        let start = state.rand_mut().below(len as u64) as usize;
+        let end = (start + 1 + state.rand_mut().below(MAX_SEQUENCE_LEN) as usize).min(len);
+        let chunk = input.bytes()[start..end].to_vec();
+        let count = repetitions(state) - 1;
+
+        Ok(insert_repeated(state, input, end, &chunk, count))
+    }
+}
+
+impl Named for SequenceRepeatMutator {
+    fn name(&self) -> &str {
+        "SequenceRepeatMutator"
+    }
+}
+
+/// Jumps from one position to another one with the same byte, like the `ft` mutation of Radamsa.
+///
+/// Jumping forward drops the bytes in between, jumping backward repeats them.
+#[derive(Debug, Default)]
+pub struct FuseMutator;
+
+impl FuseMutator {
+    /// Create a new FuseMutator
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl<I, S> Mutator<I, S> for FuseMutator
+where
+    I: Input + HasBytesVec,
+    S: HasRand + HasMaxSize,
+{
+    fn mutate(&mut self, state: &mut S, input: &mut I, _stage_idx: i32) -> Result<MutationResult, Error> {
+        let bytes = input.bytes();
+
+        if bytes.is_empty() {
+            return Ok(MutationResult::Skipped);
+        }
+
+        let from = state.rand_mut().below(bytes.len() as u64) as usize;
+        let targets: Vec<usize> = (0..bytes.len()).filter(|&to| to != from && bytes[to] == bytes[from]).collect();
+
+        if targets.is_empty() {
+            return Ok(MutationResult::Skipped);
+        }
+
+        let to = *state.rand_mut().choose(&targets);
+
+        if to < from && bytes.len() + from - to > state.max_size() {
+            return Ok(MutationResult::Skipped);
+        }
+
+        let fused = [&bytes[..from], &bytes[to..]].concat();
+        *input.bytes_mut() = fused;
+
+        Ok(MutationResult::Mutated)
+    }
+}
+
+impl Named for FuseMutator {
+    fn name(&self) -> &str {
+        "FuseMutator"
+    }
+}
+
+/// Inserts a string that commonly trips up parsers, like format specifiers or shell injections,
+/// similar to the `ab` mutation of Radamsa
+#[derive(Debug, Default)]
+pub struct BadStringMutator;
+
+impl BadStringMutator {
+    /// Create a new BadStringMutator
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl<I, S> Mutator<I, S> for BadStringMutator
+where
+    I: Input + HasBytesVec,
+    S: HasRand + HasMaxSize,
+{
+    fn mutate(&mut self, state: &mut S, input: &mut I, _stage_idx: i32) -> Result<MutationResult, Error> {
+        let chunk = *state.rand_mut().choose(&BAD_STRINGS);
+        let pos = state.rand_mut().below(input.bytes().len() as u64 + 1) as usize;
+        Ok(insert_repeated(state, input, pos, chunk, 1))
+    }
+}
+
+impl Named for BadStringMutator {
+    fn name(&self) -> &str {
+        "BadStringMutator"
+    }
+}
+
+/// Inserts a byte sequence that commonly trips up UTF-8 handling, like overlong encodings
+/// or a byte order mark, similar to the `ui` mutation of Radamsa
+#[derive(Debug, Default)]
+pub struct FunnyUnicodeMutator;
+
+impl FunnyUnicodeMutator {
+    /// Create a new FunnyUnicodeMutator
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl<I, S> Mutator<I, S> for FunnyUnicodeMutator
+where
+    I: Input + HasBytesVec,
+    S: HasRand + HasMaxSize,
+{
+    fn mutate(&mut self, state: &mut S, input: &mut I, _stage_idx: i32) -> Result<MutationResult, Error> {
+        let chunk = *state.rand_mut().choose(&FUNNY_UNICODE);
+        let pos = state.rand_mut().below(input.bytes().len() as u64 + 1) as usize;
+        Ok(insert_repeated(state, input, pos, chunk, 1))
+    }
+}
+
+impl Named for FunnyUnicodeMutator {
+    fn name(&self) -> &str {
+        "FunnyUnicodeMutator"
+    }
+}
+
+/// Tuple of the mutations of the [`PacketRadamsaMutator`], see [`radamsa_mutations()`]
+pub type RadamsaMutationsType =
+    tuple_list_type!(LineDeleteMutator, LineRepeatMutator, LineSwapMutator, SequenceRepeatMutator, FuseMutator, BadStringMutator, FunnyUnicodeMutator, TextNumberMutator, BitFlipMutator, ByteRandMutator, BytesDeleteMutator, BytesRandInsertMutator);
+
+/// Returns a tuple with the mutations of the [`PacketRadamsaMutator`].
+///
+/// Besides a few of LibAFLs byte-level mutations, these are pure-Rust versions of
+/// Radamsas line, sequence, fuse, number and string mutations.
+pub fn radamsa_mutations() -> RadamsaMutationsType {
+    tuple_list!(
+        LineDeleteMutator::new(),
+        LineRepeatMutator::new(),
+        LineSwapMutator::new(),
+        SequenceRepeatMutator::new(),
+        FuseMutator::new(),
+        BadStringMutator::new(),
+        FunnyUnicodeMutator::new(),
+        TextNumberMutator::new(),
+        BitFlipMutator::new(),
+        ByteRandMutator::new(),
+        BytesDeleteMutator::new(),
+        BytesRandInsertMutator::new(),
+    )
+}
+
+/// A heavy, generic mutator in the spirit of [Radamsa](https://gitlab.com/akihe/radamsa)
+/// for targets where the structured havoc mutations plateau.
+///
+/// It mutates a single packet with one mutation or a burst of several from [`radamsa_mutations()`],
+/// which repeat, swap and fuse lines and sequences and insert bad strings and funny unicode.
+/// Like Radamsa it keeps a priority for every mutation that rises when the mutation succeeds,
+/// drops when it doesn't and gets re-rolled from time to time, so that a campaign goes through
+/// phases dominated by different mutations.
+///
+/// `P` denotes the packet type that MUST implement [`HasHavocMutation`] for the [`RadamsaMutationsType`],
+/// which every packet type with a generic implementation of [`HasHavocMutation`] does.
+///
+/// __Only available with feature__: `radamsa`
+///
+/// # Example
+/// ```
+/// let mutator = PacketMutationScheduler::new(tuple_list!(
+///     PacketHavocMutator::new(supported_havoc_mutations()),
+///     PacketRadamsaMutator::new(),
+/// ));
+/// ```
+pub struct PacketRadamsaMutator<P, SEL = PacketSelection> {
+    mutations: RadamsaMutationsType,
+    scores: Vec<u64>,
+    selection: SEL,
+    phantom: PhantomData<P>,
+}
+
+impl<P> PacketRadamsaMutator<P> {
+    /// Create a new PacketRadamsaMutator
+    pub fn new() -> Self {
+        let mutations = radamsa_mutations();
+
+        Self {
+            scores: vec![MAX_SCORE / 2; mutations.len()],
+            mutations,
+            selection: PacketSelection::Uniform,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<P, SEL> PacketRadamsaMutator<P, SEL> {
+    /// Choose the packet with the given [`PacketSelector`] instead of uniformly
+    pub fn with_selection<SEL2>(self, selection: SEL2) -> PacketRadamsaMutator<P, SEL2> {
+        PacketRadamsaMutator {
+            mutations: self.mutations,
+            scores: self.scores,
+            selection,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Returns the current priorities of the mutations, in the order of [`radamsa_mutations()`]
+    pub fn scores(&self) -> &[u64] {
+        &self.scores
+    }
+}
+
+impl<I, S, P, SEL> Mutator<I, S> for PacketRadamsaMutator<P, SEL>
+where
+    P: HasHavocMutation<RadamsaMutationsType, S> + HasPostMutationFixup,
+    I: Input + HasLen + HasPackets<P>,
+    S: HasRand + HasMaxSize,
+    SEL: PacketSelector<P, S>,
+    RadamsaMutationsType: MutatorsTuple<BytesInput, S>,
+{
+    fn mutate(&mut self, state: &mut S, input: &mut I, stage_idx: i32) -> Result<MutationResult, Error> {
+        if input.len() == 0 {
+            return Ok(MutationResult::Skipped);
+        }
+
+        if state.rand_mut().below(RESCORE_INTERVAL) == 0 {
+            for score in &mut self.scores {
+                *score = 1 + state.rand_mut().below(MAX_SCORE);
+            }
+        }
+
+        let packet = self.selection.select_packet(state, input.packets());
+        let burst = if state.rand_mut().below(2) == 0 { 1 } else { 1 + state.rand_mut().below(MAX_BURST as u64) as usize };
+        let mut result = MutationResult::Skipped;
+
+        for _ in 0..burst {
+            let mutation = choose_weighted(state.rand_mut(), &self.scores);
+            let outcome = input.packets_mut()[packet].mutate_havoc(state, &mut self.mutations, mutation, stage_idx)?;
+
+            if outcome == MutationResult::Mutated {
+                self.scores[mutation] = (self.scores[mutation] + 1).min(MAX_SCORE);
+                result = MutationResult::Mutated;
+            } else {
+                self.scores[mutation] = (self.scores[mutation] - 1).max(1);
+            }
+        }
+
+        if result == MutationResult::Mutated {
+            input.packets_mut()[packet].fixup();
+        }
+
+        Ok(result)
+    }
+}
+
+impl<P, SEL> Named for PacketRadamsaMutator<P, SEL> {
+    fn name(&self) -> &str {
+        "PacketRadamsaMutator"
+    }
+}
+
+impl<P, SEL> HasConfig for PacketRadamsaMutator<P, SEL>
+where
+    SEL: std::fmt::Debug,
+{
+    fn config(&self) -> ComponentConfig {
+        ComponentConfig::new(self.name()).with_setting("mutations", (0..self.mutations.len()).filter_map(|mutation| self.mutations.name(mutation)).collect::<Vec<_>>().join(", ")).with_setting("selection", format!("{:?}", self.selection))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::TestInput;
+    use libafl::{bolts::rands::StdRand, corpus::InMemoryCorpus, state::StdState};
+
+    #[test]
+    fn test_line_mutations() {
+        let mut state = StdState::new(StdRand::with_seed(0), InMemoryCorpus::<TestInput>::new(), InMemoryCorpus::<TestInput>::new(), &mut (), &mut ()).unwrap();
+        let original = b"GET / HTTP/1.1\r\nHost: a\r\n\r\n".to_vec();
+
+        let mut input = BytesInput::new(original.clone());
+        while LineSwapMutator::new().mutate(&mut state, &mut input, 0).unwrap() == MutationResult::Skipped {}
+        assert_eq!(input.bytes().len(), original.len());
+        assert_eq!(lines(input.bytes()).len(), 3);
+
+        let mut input = BytesInput::new(original.clone());
+        assert_eq!(LineDeleteMutator::new().mutate(&mut state, &mut input, 0).unwrap(), MutationResult::Mutated);
+        assert_eq!(lines(input.bytes()).len(), 2);
+
+        // Repetitions are capped by the max size
+        state.set_max_size(64);
+        let mut input = BytesInput::new(original);
+        for _ in 0..100 {
+            LineRepeatMutator::new().mutate(&mut state, &mut input, 0).unwrap();
+            SequenceRepeatMutator::new().mutate(&mut state, &mut input, 0).unwrap();
+            assert!(input.bytes().len() <= 64);
+        }
+    }
+
+    #[test]
+    fn test_radamsa() {
+        let mut state = StdState::new(StdRand::with_seed(0), InMemoryCorpus::<TestInput>::new(), InMemoryCorpus::<TestInput>::new(), &mut (), &mut ()).unwrap();
+        let mut mutator = PacketRadamsaMutator::new();
+        let original = TestInput {
+            packets: vec![BytesInput::new(b"USER anonymous\r\n".to_vec()), BytesInput::new(b"PASS secret\r\n".to_vec())],
+        };
+        let mut mutated = 0;
+
+        for _ in 0..1000 {
+            let mut input = original.clone();
+
+            if mutator.mutate(&mut state, &mut input, 0).unwrap() == MutationResult::Mutated {
+                assert_eq!(input.len(), 2);
+                mutated += 1;
+            }
+        }
+
+        assert!(mutated > 500);
+        assert!(mutator.scores().iter().all(|score| (1..=MAX_SCORE).contains(score)));
+    }
+}
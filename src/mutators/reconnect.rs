@@ -0,0 +1,90 @@
+use crate::{
+    config::{ComponentConfig, HasConfig},
+    executor::HasConnectionEvents,
+    input::HasPackets,
+    mutators::PacketBounds,
+};
+use libafl::{
+    bolts::{rands::Rand, tuples::Named, HasLen},
+    inputs::Input,
+    mutators::{MutationResult, Mutator},
+    state::{HasMetadata, HasRand},
+    Error,
+};
+use std::marker::PhantomData;
+
+/// A mutator that inserts a `Disconnect` pseudo-packet immediately followed by
+/// a `Connect` pseudo-packet at a random position, such that the target
+/// sees a reconnect in the middle of a session.
+///
+/// The packet type must implement [`HasConnectionEvents`](crate::HasConnectionEvents).
+///
+/// It respects an upper bound on the number of packets
+/// passed as an argument to the constructor.
+///
+/// # Example
+/// ```
+/// // Make sure that we never exceed 16 packets in an input
+/// let mutator = PacketReconnectMutator::new(16);
+/// ```
+pub struct PacketReconnectMutator<P>
+where
+    P: HasConnectionEvents,
+{
+    max_packets: usize,
+    phantom: PhantomData<P>,
+}
+
+impl<P> PacketReconnectMutator<P>
+where
+    P: HasConnectionEvents,
+{
+    /// Create a new PacketReconnectMutator with an upper bound on the number of packets
+    pub fn new(max_packets: usize) -> Self {
+        Self {
+            max_packets,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<I, S, P> Mutator<I, S> for PacketReconnectMutator<P>
+where
+    P: HasConnectionEvents,
+    I: Input + HasLen + HasPackets<P>,
+    S: HasRand + HasMetadata,
+{
+    fn mutate(&mut self, state: &mut S, input: &mut I, _stage_idx: i32) -> Result<MutationResult, Error> {
+        if input.len() < 2 || input.len() + 2 > PacketBounds::max_packets(state, self.max_packets) {
+            return Ok(MutationResult::Skipped);
+        }
+
+        // Only reconnect between two packets, reconnecting at the
+        // very beginning or end is pointless
+        let idx = 1 + state.rand_mut().below(input.len() as u64 - 1) as usize;
+
+        let packets = input.packets_mut();
+        packets.insert(idx, P::connect());
+        packets.insert(idx, P::disconnect());
+
+        Ok(MutationResult::Mutated)
+    }
+}
+
+impl<P> Named for PacketReconnectMutator<P>
+where
+    P: HasConnectionEvents,
+{
+    fn name(&self) -> &str {
+        "PacketReconnectMutator"
+    }
+}
+
+impl<P> HasConfig for PacketReconnectMutator<P>
+where
+    P: HasConnectionEvents,
+{
+    fn config(&self) -> ComponentConfig {
+        ComponentConfig::new(self.name()).with_setting("max_packets", self.max_packets)
+    }
+}
@@ -1,4 +1,8 @@
-use crate::input::HasPackets;
+use crate::{
+    config::{ComponentConfig, HasConfig},
+    input::HasPackets,
+    mutators::{terminal_setting, HasTerminalPacket, TerminalGuard, TerminalHandling},
+};
 use libafl::{
     bolts::{rands::Rand, tuples::Named, HasLen},
     inputs::Input,
@@ -10,6 +14,7 @@ use std::marker::PhantomData;
 
 /// A mutator that swaps two random packets.
 pub struct PacketReorderMutator<P> {
+    terminal: Option<TerminalGuard<P>>,
     phantom: PhantomData<P>,
 }
 
@@ -17,9 +22,19 @@ impl<P> PacketReorderMutator<P> {
     /// Create a new PacketReorderMutator
     pub fn new() -> Self {
         Self {
+            terminal: None,
             phantom: PhantomData,
         }
     }
+
+    /// Take packets that end a session into account, see [`HasTerminalPacket`]
+    pub fn with_terminal_handling(mut self, handling: TerminalHandling) -> Self
+    where
+        P: HasTerminalPacket,
+    {
+        self.terminal = Some(TerminalGuard::new(handling));
+        self
+    }
 }
 
 impl<I, S, P> Mutator<I, S> for PacketReorderMutator<P>
@@ -39,8 +54,16 @@ where
             return Ok(MutationResult::Skipped);
         }
 
+        let trailing = self.terminal.as_ref().map_or(0, |guard| guard.trailing(input.packets()));
         input.packets_mut().swap(from, to);
 
+        if let Some(guard) = &self.terminal {
+            if !guard.accept(trailing, input.packets_mut()) {
+                input.packets_mut().swap(from, to);
+                return Ok(MutationResult::Skipped);
+            }
+        }
+
         Ok(MutationResult::Mutated)
     }
 }
@@ -50,3 +73,9 @@ impl<P> Named for PacketReorderMutator<P> {
         "PacketReorderMutator"
     }
 }
+
+impl<P> HasConfig for PacketReorderMutator<P> {
+    fn config(&self) -> ComponentConfig {
+        ComponentConfig::new(self.name()).with_setting("terminal", terminal_setting(&self.terminal))
+    }
+}
@@ -0,0 +1,115 @@
+use crate::{
+    config::{ComponentConfig, HasConfig},
+    input::HasPackets,
+};
+use libafl::{
+    bolts::{rands::Rand, tuples::Named, HasLen},
+    inputs::Input,
+    mutators::{MutationResult, Mutator},
+    state::HasRand,
+    Error,
+};
+use std::marker::PhantomData;
+
+/// A mutator that moves a whole group of packets at once.
+///
+/// It picks a random contiguous window of packets and either
+/// - rotates the packets inside the window by a random amount or
+/// - shifts the window as a whole a random number of positions to the left or right,
+///   e.g. to move an authentication exchange later into the session.
+///
+/// These reorderings cannot be reached with the single swap of the [`PacketReorderMutator`](crate::PacketReorderMutator).
+pub struct PacketRotateMutator<P> {
+    phantom: PhantomData<P>,
+}
+
+impl<P> PacketRotateMutator<P> {
+    /// Create a new PacketRotateMutator
+    pub fn new() -> Self {
+        Self {
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<I, S, P> Mutator<I, S> for PacketRotateMutator<P>
+where
+    I: Input + HasLen + HasPackets<P>,
+    S: HasRand,
+{
+    fn mutate(&mut self, state: &mut S, input: &mut I, _stage_idx: i32) -> Result<MutationResult, Error> {
+        let len = input.len();
+
+        if len <= 1 {
+            return Ok(MutationResult::Skipped);
+        }
+
+        let start = state.rand_mut().below(len as u64 - 1) as usize;
+        let end = start + 2 + state.rand_mut().below((len - start - 1) as u64) as usize;
+        let window = end - start;
+        let packets = input.packets_mut();
+
+        // Rotate within the window or, if the window does not span the whole input, shift it
+        if window == len || state.rand_mut().below(2) == 0 {
+            let amount = 1 + state.rand_mut().below(window as u64 - 1) as usize;
+            packets[start..end].rotate_left(amount);
+        } else {
+            // Shifting is a rotation of the window and the packets it moves past
+            let left = start;
+            let amount = 1 + state.rand_mut().below((len - window) as u64) as usize;
+
+            if amount <= left {
+                packets[start - amount..end].rotate_right(window);
+            } else {
+                let amount = amount - left;
+                packets[start..end + amount].rotate_left(window);
+            }
+        }
+
+        Ok(MutationResult::Mutated)
+    }
+}
+
+impl<P> Named for PacketRotateMutator<P> {
+    fn name(&self) -> &str {
+        "PacketRotateMutator"
+    }
+}
+
+impl<P> HasConfig for PacketRotateMutator<P> {
+    fn config(&self) -> ComponentConfig {
+        ComponentConfig::new(self.name())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::TestInput;
+    use libafl::{bolts::rands::StdRand, corpus::InMemoryCorpus, inputs::BytesInput, state::StdState};
+
+    #[test]
+    fn test_rotate() {
+        let mut state = StdState::new(StdRand::with_seed(0), InMemoryCorpus::<BytesInput>::new(), InMemoryCorpus::<BytesInput>::new(), &mut (), &mut ()).unwrap();
+        let mut mutator = PacketRotateMutator::new();
+        let mut moved_later = false;
+
+        for _ in 0..1000 {
+            let mut input = TestInput {
+                packets: vec![0, 1, 2, 3, 4, 5],
+            };
+
+            assert_eq!(mutator.mutate(&mut state, &mut input, 0).unwrap(), MutationResult::Mutated);
+            assert_ne!(input.packets, vec![0, 1, 2, 3, 4, 5]);
+
+            let mut sorted = input.packets.clone();
+            sorted.sort();
+            assert_eq!(sorted, vec![0, 1, 2, 3, 4, 5]);
+
+            // The "authentication" 0, 1 was shifted to the end as a whole
+            moved_later |= input.packets == vec![2, 3, 4, 5, 0, 1];
+        }
+
+        assert!(moved_later);
+    }
+}
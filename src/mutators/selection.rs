@@ -0,0 +1,249 @@
+use crate::executor::HasPayload;
+use libafl::{
+    bolts::rands::Rand,
+    impl_serdeany,
+    state::{HasMetadata, HasRand},
+};
+use serde::{Deserialize, Serialize};
+
+/// A strategy that decides which packet of an input a mutator works on.
+///
+/// Every packet mutator that targets an existing packet accepts a selector via its `with_selection()` method:
+/// [`PacketHavocMutator`](crate::PacketHavocMutator), [`PacketCrossoverInsertMutator`](crate::PacketCrossoverInsertMutator),
+/// [`PacketCrossoverReplaceMutator`](crate::PacketCrossoverReplaceMutator), [`PacketSpliceMutator`](crate::PacketSpliceMutator),
+/// [`PacketDeleteMutator`](crate::PacketDeleteMutator), [`PacketDuplicateMutator`](crate::PacketDuplicateMutator),
+/// [`PacketDuplicateHavocMutator`](crate::PacketDuplicateHavocMutator), [`PacketFragmentMutator`](crate::PacketFragmentMutator),
+/// [`PacketMergeMutator`](crate::PacketMergeMutator) and [`GrammarPacketMutator`](crate::GrammarPacketMutator).
+/// Mutators that work on positions rather than packets, like the [`PacketReorderMutator`](crate::PacketReorderMutator)
+/// or the [`PacketTruncateMutator`](crate::PacketTruncateMutator), don't.
+///
+/// Already implemented for:
+/// - [`PacketSelection`]
+/// - [`LengthSelector`]
+/// - [`NoveltySelector`]
+///
+/// # Example
+/// Always pick the first packet, e.g. to fuzz the handshake of a protocol:
+/// ```
+/// struct FirstPacket;
+///
+/// impl<P, S> PacketSelector<P, S> for FirstPacket {
+///     fn select_packet(&mut self, _state: &mut S, _packets: &[P]) -> usize {
+///         0
+///     }
+/// }
+///
+/// let mutator = PacketHavocMutator::new(supported_havoc_mutations()).with_selection(FirstPacket);
+/// ```
+pub trait PacketSelector<P, S> {
+    /// Return the index of one of `packets`. `packets` is never empty.
+    fn select_packet(&mut self, state: &mut S, packets: &[P]) -> usize;
+}
+
+/// Choose an index with a probability proportional to its weight
+pub(crate) fn choose_weighted<R: Rand>(rand: &mut R, weights: &[u64]) -> usize {
+    let total: u64 = weights.iter().sum();
+
+    if total == 0 {
+        return rand.below(weights.len() as u64) as usize;
+    }
+
+    let mut r = rand.below(total);
+
+    for (idx, weight) in weights.iter().enumerate() {
+        if r < *weight {
+            return idx;
+        }
+
+        r -= weight;
+    }
+
+    weights.len() - 1
+}
+
+/// How packet mutators choose the packet of an input they mutate.
+///
+/// Later packets tend to reach deeper states of the target because all packets before them
+/// have to be processed first. A tail bias concentrates the mutations on the end of an input.
+///
+/// This is the default [`PacketSelector`] of all packet mutators.
+///
+/// # Example
+/// ```
+/// let mutator = PacketHavocMutator::new(supported_havoc_mutations()).with_selection(PacketSelection::ExponentialTail);
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PacketSelection {
+    /// Every packet is equally likely
+    #[default]
+    Uniform,
+    /// The probability of a packet grows linearly with its position,
+    /// i.e. the last packet is `n` times as likely as the first one
+    LinearTail,
+    /// The last packet is chosen with probability 1/2, the one before it with 1/4 and so on
+    ExponentialTail,
+}
+
+impl PacketSelection {
+    /// Choose an index in `0..len`. `len` must not be 0.
+    pub fn select<R: Rand>(&self, rand: &mut R, len: usize) -> usize {
+        debug_assert!(len > 0);
+
+        match self {
+            PacketSelection::Uniform => rand.below(len as u64) as usize,
+            PacketSelection::LinearTail => {
+                // Index i has weight i + 1, so invert the triangular numbers
+                let total = (len as u64) * (len as u64 + 1) / 2;
+                let r = rand.below(total) as f64;
+                let idx = (((8.0 * r + 1.0).sqrt() - 1.0) / 2.0) as usize;
+                idx.min(len - 1)
+            },
+            PacketSelection::ExponentialTail => {
+                let mut distance = 0;
+
+                while distance + 1 < len && rand.below(2) == 1 {
+                    distance += 1;
+                }
+
+                len - 1 - distance
+            },
+        }
+    }
+}
+
+impl<P, S> PacketSelector<P, S> for PacketSelection
+where
+    S: HasRand,
+{
+    fn select_packet(&mut self, state: &mut S, packets: &[P]) -> usize {
+        self.select(state.rand_mut(), packets.len())
+    }
+}
+
+/// A [`PacketSelector`] that picks packets with a probability proportional to the length of their payload,
+/// such that large packets with many fields get more attention than short keep-alives.
+///
+/// The payload of every packet is rendered for each selection, so this is slower than a [`PacketSelection`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LengthSelector;
+
+impl LengthSelector {
+    /// Create a new LengthSelector
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl<P, S> PacketSelector<P, S> for LengthSelector
+where
+    P: HasPayload,
+    S: HasRand,
+{
+    fn select_packet(&mut self, state: &mut S, packets: &[P]) -> usize {
+        let weights: Vec<u64> = packets.iter().map(|packet| packet.payload().len() as u64 + 1).collect();
+        choose_weighted(state.rand_mut(), &weights)
+    }
+}
+
+/// How many new transitions the [`StateObserver`](crate::StateObserver) discovered at each position of a path,
+/// stored in the metadata of the state by the [`TransitionNoveltyFeedback`](crate::TransitionNoveltyFeedback)
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TransitionNoveltyMetadata {
+    /// Number of new transitions per position
+    pub novelty: Vec<u64>,
+}
+
+impl_serdeany!(TransitionNoveltyMetadata);
+
+impl TransitionNoveltyMetadata {
+    /// Count a new transition at every position in `positions`
+    pub fn record(&mut self, positions: &[usize]) {
+        for position in positions {
+            if *position >= self.novelty.len() {
+                self.novelty.resize(*position + 1, 0);
+            }
+
+            self.novelty[*position] += 1;
+        }
+    }
+}
+
+/// A [`PacketSelector`] that prefers the positions in an input at which new transitions have been discovered
+/// so far, using the [`TransitionNoveltyMetadata`] that the [`TransitionNoveltyFeedback`](crate::TransitionNoveltyFeedback) collects.
+///
+/// The `n`-th packet is assumed to cause the `n`-th transition, which holds if the executor records one state per packet.
+/// Every position has a base weight of 1, so it degrades to uniform selection without metadata.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoveltySelector;
+
+impl NoveltySelector {
+    /// Create a new NoveltySelector
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl<P, S> PacketSelector<P, S> for NoveltySelector
+where
+    S: HasRand + HasMetadata,
+{
+    fn select_packet(&mut self, state: &mut S, packets: &[P]) -> usize {
+        let novelty = state.metadata().get::<TransitionNoveltyMetadata>().map(|metadata| metadata.novelty.as_slice()).unwrap_or_default();
+        let weights: Vec<u64> = (0..packets.len()).map(|idx| 1 + novelty.get(idx).copied().unwrap_or(0)).collect();
+        choose_weighted(state.rand_mut(), &weights)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libafl::{bolts::rands::StdRand, corpus::InMemoryCorpus, inputs::BytesInput, state::StdState};
+
+    fn histogram(selection: PacketSelection, len: usize) -> Vec<usize> {
+        let mut rand = StdRand::with_seed(0);
+        let mut histogram = vec![0; len];
+
+        for _ in 0..10000 {
+            histogram[selection.select(&mut rand, len)] += 1;
+        }
+
+        histogram
+    }
+
+    #[test]
+    fn test_selection() {
+        let uniform = histogram(PacketSelection::Uniform, 4);
+        assert!(uniform.iter().all(|count| *count > 2000 && *count < 3000));
+
+        let linear = histogram(PacketSelection::LinearTail, 4);
+        assert!(linear.windows(2).all(|pair| pair[0] < pair[1]));
+        assert!(linear[0] > 700 && linear[0] < 1300);
+        assert!(linear[3] > 3500 && linear[3] < 4500);
+
+        let exponential = histogram(PacketSelection::ExponentialTail, 4);
+        assert!(exponential[3] > 4500 && exponential[3] < 5500);
+        assert!(exponential[1] < exponential[2]);
+
+        for selection in [PacketSelection::Uniform, PacketSelection::LinearTail, PacketSelection::ExponentialTail] {
+            assert_eq!(histogram(selection, 1), vec![10000]);
+        }
+    }
+
+    #[test]
+    fn test_selectors() {
+        let mut state = StdState::new(StdRand::with_seed(0), InMemoryCorpus::<BytesInput>::new(), InMemoryCorpus::<BytesInput>::new(), &mut (), &mut ()).unwrap();
+        let packets = vec![BytesInput::new(vec![0; 99]), BytesInput::new(Vec::new()), BytesInput::new(Vec::new())];
+
+        let mut selector = LengthSelector::new();
+        assert!((0..1000).filter(|_| selector.select_packet(&mut state, &packets) == 0).count() > 900);
+
+        let mut selector = NoveltySelector::new();
+        assert!((0..1000).filter(|_| selector.select_packet(&mut state, &packets) == 2).count() < 500);
+
+        let mut metadata = TransitionNoveltyMetadata::default();
+        metadata.record(&[2, 2, 4]);
+        assert_eq!(metadata.novelty, vec![0, 0, 2, 0, 1]);
+        state.add_metadata(metadata);
+        assert!((0..1000).filter(|_| selector.select_packet(&mut state, &packets) == 2).count() > 500);
+    }
+}
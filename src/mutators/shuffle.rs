@@ -0,0 +1,122 @@
+use crate::{
+    config::{ComponentConfig, HasConfig},
+    input::HasPackets,
+};
+use libafl::{
+    bolts::{rands::Rand, tuples::Named, HasLen},
+    inputs::Input,
+    mutators::{MutationResult, Mutator},
+    state::HasRand,
+    Error,
+};
+use std::marker::PhantomData;
+
+/// Default upper bound on the number of packets that are shuffled at once
+const DEFAULT_MAX_WINDOW: usize = 8;
+
+/// A mutator that shuffles a random contiguous window of packets.
+///
+/// The packets inside the window are brought into a uniformly random order with a Fisher–Yates shuffle,
+/// which simulates a burst of commands that arrive out of order. The packets outside of the window stay in place.
+///
+/// # Example
+/// ```
+/// let mutator = PacketShuffleMutator::new().with_max_window(4);
+/// ```
+pub struct PacketShuffleMutator<P> {
+    max_window: usize,
+    phantom: PhantomData<P>,
+}
+
+impl<P> PacketShuffleMutator<P> {
+    /// Create a new PacketShuffleMutator that shuffles up to 8 packets at once
+    pub fn new() -> Self {
+        Self {
+            max_window: DEFAULT_MAX_WINDOW,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Shuffle up to `max_window` packets at once. Values below 2 are raised to 2.
+    pub fn with_max_window(mut self, max_window: usize) -> Self {
+        self.max_window = max_window.max(2);
+        self
+    }
+}
+
+impl<I, S, P> Mutator<I, S> for PacketShuffleMutator<P>
+where
+    I: Input + HasLen + HasPackets<P>,
+    S: HasRand,
+{
+    fn mutate(&mut self, state: &mut S, input: &mut I, _stage_idx: i32) -> Result<MutationResult, Error> {
+        let len = input.len();
+
+        if len <= 1 {
+            return Ok(MutationResult::Skipped);
+        }
+
+        let max_window = self.max_window.min(len);
+        let window = 2 + state.rand_mut().below(max_window as u64 - 1) as usize;
+        let start = state.rand_mut().below((len - window + 1) as u64) as usize;
+        let packets = &mut input.packets_mut()[start..start + window];
+
+        for i in (1..window).rev() {
+            let j = state.rand_mut().below(i as u64 + 1) as usize;
+            packets.swap(i, j);
+        }
+
+        Ok(MutationResult::Mutated)
+    }
+}
+
+impl<P> Named for PacketShuffleMutator<P> {
+    fn name(&self) -> &str {
+        "PacketShuffleMutator"
+    }
+}
+
+impl<P> HasConfig for PacketShuffleMutator<P> {
+    fn config(&self) -> ComponentConfig {
+        ComponentConfig::new(self.name()).with_setting("max_window", self.max_window)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::TestInput;
+    use libafl::{bolts::rands::StdRand, corpus::InMemoryCorpus, inputs::BytesInput, state::StdState};
+
+    #[test]
+    fn test_shuffle() {
+        let mut state = StdState::new(StdRand::with_seed(0), InMemoryCorpus::<BytesInput>::new(), InMemoryCorpus::<BytesInput>::new(), &mut (), &mut ()).unwrap();
+        let mut mutator = PacketShuffleMutator::new().with_max_window(3);
+        let mut reversed = false;
+
+        for _ in 0..1000 {
+            let mut input = TestInput {
+                packets: vec![0, 1, 2, 3, 4, 5, 6, 7],
+            };
+
+            assert_eq!(mutator.mutate(&mut state, &mut input, 0).unwrap(), MutationResult::Mutated);
+
+            let mut sorted = input.packets.clone();
+            sorted.sort();
+            assert_eq!(sorted, vec![0, 1, 2, 3, 4, 5, 6, 7]);
+
+            // Only packets within a window of 3 were moved
+            let moved: Vec<usize> = (0..8).filter(|idx| input.packets[*idx] != *idx as u8).collect();
+            assert!(moved.is_empty() || moved[moved.len() - 1] - moved[0] < 3);
+
+            reversed |= input.packets == vec![0, 1, 2, 3, 4, 7, 6, 5];
+        }
+
+        assert!(reversed);
+
+        let mut input = TestInput {
+            packets: vec![0],
+        };
+        assert_eq!(mutator.mutate(&mut state, &mut input, 0).unwrap(), MutationResult::Skipped);
+    }
+}
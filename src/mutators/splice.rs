@@ -1,11 +1,16 @@
-use crate::input::HasPackets;
+use crate::{
+    config::{ComponentConfig, HasConfig},
+    input::HasPackets,
+    mutators::{mutate_masked, HasMutationMask, HasPostMutationFixup, PacketBounds, PacketSelection, PacketSelector},
+};
 use libafl::{
     bolts::{rands::Rand, tuples::Named, HasLen},
-    inputs::{BytesInput, HasBytesVec, Input},
+    inputs::{HasBytesVec, Input},
     mutators::{MutationResult, Mutator},
-    state::{HasMaxSize, HasRand},
+    state::{HasMaxSize, HasMetadata, HasRand},
     Error,
 };
+use std::fmt::Debug;
 use std::marker::PhantomData;
 
 /// Signifies that a packet type supports the [`PacketSpliceMutator`] mutator.
@@ -15,7 +20,8 @@ use std::marker::PhantomData;
 /// IMPORTANT: This must be implemented on the packet type, NOT the Input type.
 ///
 /// Already implemented for:
-/// - [`BytesInput`](libafl::inputs::BytesInput)
+/// - every type that implements [`HasBytesVec`](libafl::inputs::HasBytesVec), like [`BytesInput`](libafl::inputs::BytesInput)
+///   or a newtype around a byte vector, and [`HasMutationMask`](crate::HasMutationMask)
 ///
 /// # Example
 /// Suppose we have the following packet type
@@ -60,33 +66,100 @@ where
     fn mutate_splice(&mut self, state: &mut S, other: &Self, stage_idx: i32) -> Result<MutationResult, Error>;
 }
 
-impl<S> HasSpliceMutation<S> for BytesInput
+impl<T, S> HasSpliceMutation<S> for T
 where
+    T: HasBytesVec + HasMutationMask,
     S: HasRand + HasMaxSize,
 {
     fn mutate_splice(&mut self, state: &mut S, other: &Self, _stage_idx: i32) -> Result<MutationResult, Error> {
-        let self_len = self.len();
-        let other_len = other.len();
+        mutate_masked(self, |packet| {
+            let self_len = packet.bytes().len();
+            let other_len = other.bytes().len();
 
-        if self_len == 0 || other_len == 0 {
-            return Ok(MutationResult::Skipped);
-        }
+            if self_len == 0 || other_len == 0 {
+                return Ok(MutationResult::Skipped);
+            }
 
-        let to = state.rand_mut().below(self_len as u64) as usize;
-        let from = state.rand_mut().below(other_len as u64) as usize;
-        let len = other_len - from;
+            let to = state.rand_mut().below(self_len as u64) as usize;
+            let from = state.rand_mut().below(other_len as u64) as usize;
+            let len = other_len - from;
 
-        // Make sure we have enough space for all the bytes from `other`
-        if to + len > self_len {
-            self.bytes_mut().resize(to + len, 0);
-        }
+            // Make sure we have enough space for all the bytes from `other`
+            if to + len > self_len {
+                packet.bytes_mut().resize(to + len, 0);
+            }
+
+            packet.bytes_mut()[to..to + len].copy_from_slice(&other.bytes()[from..from + len]);
+
+            Ok(MutationResult::Mutated)
+        })
+    }
+}
+
+/// Signifies that a packet type supports splicing at protocol delimiters like `\r\n`, spaces or NUL bytes,
+/// see [`PacketSpliceMutator::with_delimiters()`].
+///
+/// Splicing at arbitrary midpoints mostly cuts keywords and arguments of text commands in half.
+/// Splicing right after delimiters combines whole tokens instead, e.g. `USER anonymous\r\n` and `PASS secret\r\n`
+/// become `USER secret\r\n`, which the target still parses.
+///
+/// Already implemented for:
+/// - every type that implements [`HasBytesVec`](libafl::inputs::HasBytesVec), like [`BytesInput`](libafl::inputs::BytesInput)
+///   or a newtype around a byte vector, and [`HasMutationMask`](crate::HasMutationMask)
+/// - [`NetworkPacket`](crate::NetworkPacket): forwards to the inner packets if both are `Data` packets
+pub trait HasDelimitedSpliceMutation<S>
+where
+    S: HasRand + HasMaxSize,
+{
+    /// Perform one splicing mutation where `self` is cut right after a delimiter and continued
+    /// with the bytes of `other` from the start or right after a delimiter.
+    ///
+    /// The arguments to this function are similar to [`Mutator::mutate()`](libafl::mutators::Mutator::mutate).
+    fn mutate_delimited_splice(&mut self, state: &mut S, other: &Self, delimiters: &[Vec<u8>], stage_idx: i32) -> Result<MutationResult, Error>;
+}
+
+/// Returns the positions in `bytes` that directly follow a delimiter, in ascending order
+fn delimiter_boundaries(bytes: &[u8], delimiters: &[Vec<u8>]) -> Vec<usize> {
+    let mut boundaries: Vec<usize> = (0..bytes.len()).flat_map(|pos| delimiters.iter().filter(move |delimiter| !delimiter.is_empty() && bytes[pos..].starts_with(delimiter)).map(move |delimiter| pos + delimiter.len())).collect();
+    boundaries.sort_unstable();
+    boundaries.dedup();
+    boundaries
+}
+
+impl<T, S> HasDelimitedSpliceMutation<S> for T
+where
+    T: HasBytesVec + HasMutationMask,
+    S: HasRand + HasMaxSize,
+{
+    fn mutate_delimited_splice(&mut self, state: &mut S, other: &Self, delimiters: &[Vec<u8>], _stage_idx: i32) -> Result<MutationResult, Error> {
+        mutate_masked(self, |packet| {
+            let tos = delimiter_boundaries(packet.bytes(), delimiters);
+            let mut froms = delimiter_boundaries(other.bytes(), delimiters);
+            froms.retain(|&from| from < other.bytes().len());
+            froms.insert(0, 0);
+
+            if tos.is_empty() || other.bytes().is_empty() {
+                return Ok(MutationResult::Skipped);
+            }
+
+            let to = *state.rand_mut().choose(&tos);
+            let from = *state.rand_mut().choose(&froms);
+
+            if packet.bytes()[to..] == other.bytes()[from..] {
+                return Ok(MutationResult::Skipped);
+            }
 
-        self.bytes_mut()[to..to + len].copy_from_slice(&other.bytes()[from..from + len]);
+            packet.bytes_mut().truncate(to);
+            packet.bytes_mut().extend_from_slice(&other.bytes()[from..]);
 
-        Ok(MutationResult::Mutated)
+            Ok(MutationResult::Mutated)
+        })
     }
 }
 
+/// Splices two packets with [`HasDelimitedSpliceMutation`]
+type DelimitedSpliceFn<P, S> = fn(&mut P, &mut S, &P, &[Vec<u8>], i32) -> Result<MutationResult, Error>;
+
 /// A mutator that splices two random packets together.
 ///
 /// `P` denotes the type of an individual packet that MUST implement [`HasSpliceMutation`].
@@ -98,13 +171,16 @@ where
 /// // Make sure that we always have at least 4 packets
 /// let mutator = PacketSpliceMutator::new(4);
 /// ```
-pub struct PacketSpliceMutator<P, S>
+pub struct PacketSpliceMutator<P, S, SEL = PacketSelection>
 where
     P: HasSpliceMutation<S>,
     S: HasRand + HasMaxSize,
 {
     phantom: PhantomData<(P, S)>,
     min_packets: usize,
+    selection: SEL,
+    delimiters: Vec<Vec<u8>>,
+    delimited_splice: Option<DelimitedSpliceFn<P, S>>,
 }
 
 impl<P, S> PacketSpliceMutator<P, S>
@@ -117,35 +193,80 @@ where
         Self {
             phantom: PhantomData,
             min_packets: std::cmp::max(1, min_packets),
+            selection: PacketSelection::Uniform,
+            delimiters: Vec::new(),
+            delimited_splice: None,
         }
     }
 }
 
-impl<I, P, S> Mutator<I, S> for PacketSpliceMutator<P, S>
+impl<P, S, SEL> PacketSpliceMutator<P, S, SEL>
 where
     P: HasSpliceMutation<S>,
     S: HasRand + HasMaxSize,
+{
+    /// Choose the packet that the next packet gets spliced into with the given [`PacketSelector`] instead of uniformly.
+    /// The selector never sees the last packet since it has no successor.
+    pub fn with_selection<SEL2>(self, selection: SEL2) -> PacketSpliceMutator<P, S, SEL2>
+    where
+        SEL2: PacketSelector<P, S>,
+    {
+        PacketSpliceMutator {
+            phantom: PhantomData,
+            min_packets: self.min_packets,
+            selection,
+            delimiters: self.delimiters,
+            delimited_splice: self.delimited_splice,
+        }
+    }
+
+    /// Only splice right after one of the given `delimiters`, see [`HasDelimitedSpliceMutation`]
+    ///
+    /// # Example
+    /// ```
+    /// let mutator = PacketSpliceMutator::new(4).with_delimiters(&[b"\r\n", b" ", b"\0"]);
+    /// ```
+    pub fn with_delimiters(mut self, delimiters: &[&[u8]]) -> Self
+    where
+        P: HasDelimitedSpliceMutation<S>,
+    {
+        self.delimiters = delimiters.iter().map(|delimiter| delimiter.to_vec()).collect();
+        self.delimited_splice = Some(P::mutate_delimited_splice);
+        self
+    }
+}
+
+impl<I, P, S, SEL> Mutator<I, S> for PacketSpliceMutator<P, S, SEL>
+where
+    P: HasSpliceMutation<S> + HasPostMutationFixup,
+    S: HasRand + HasMaxSize + HasMetadata,
     I: Input + HasLen + HasPackets<P>,
+    SEL: PacketSelector<P, S>,
 {
     fn mutate(&mut self, state: &mut S, input: &mut I, stage_idx: i32) -> Result<MutationResult, Error> {
-        if input.len() <= self.min_packets {
+        if input.len() <= PacketBounds::min_packets(state, self.min_packets) {
             return Ok(MutationResult::Skipped);
         }
 
-        let packet = state.rand_mut().below(input.len() as u64 - 1) as usize;
+        let packet = self.selection.select_packet(state, &input.packets()[..input.len() - 1]);
         let other = input.packets_mut().remove(packet + 1);
 
-        let ret = input.packets_mut()[packet].mutate_splice(state, &other, stage_idx)?;
+        let ret = match self.delimited_splice {
+            Some(splice) => splice(&mut input.packets_mut()[packet], state, &other, &self.delimiters, stage_idx)?,
+            None => input.packets_mut()[packet].mutate_splice(state, &other, stage_idx)?,
+        };
 
         if ret == MutationResult::Skipped {
             input.packets_mut().insert(packet + 1, other);
+        } else {
+            input.packets_mut()[packet].fixup();
         }
 
         Ok(ret)
     }
 }
 
-impl<P, S> Named for PacketSpliceMutator<P, S>
+impl<P, S, SEL> Named for PacketSpliceMutator<P, S, SEL>
 where
     P: HasSpliceMutation<S>,
     S: HasRand + HasMaxSize,
@@ -155,6 +276,20 @@ where
     }
 }
 
+impl<P, S, SEL> HasConfig for PacketSpliceMutator<P, S, SEL>
+where
+    P: HasSpliceMutation<S>,
+    S: HasRand + HasMaxSize,
+    SEL: Debug,
+{
+    fn config(&self) -> ComponentConfig {
+        ComponentConfig::new(self.name())
+            .with_setting("min_packets", self.min_packets)
+            .with_setting("selection", format!("{:?}", self.selection))
+            .with_setting("delimiters", self.delimiters.iter().map(|delimiter| delimiter.escape_ascii().to_string()).collect::<Vec<_>>().join(", "))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -221,6 +356,30 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_delimited_splice() {
+        let mut state = TestState::new();
+        let delimiters = [b"\r\n".to_vec(), b" ".to_vec()];
+        let other = BytesInput::new(b"PASS secret\r\n".to_vec());
+        let mut spliced = Vec::new();
+
+        for _ in 0..100 {
+            let mut a = BytesInput::new(b"USER anonymous\r\n".to_vec());
+
+            if a.mutate_delimited_splice(&mut state, &other, &delimiters, 0).unwrap() == MutationResult::Mutated {
+                spliced.push(a.bytes().to_vec());
+            }
+        }
+
+        let expected: [&[u8]; 4] = [b"USER secret\r\n", b"USER PASS secret\r\n", b"USER anonymous\r\nPASS secret\r\n", b"USER anonymous\r\nsecret\r\n"];
+        assert!(spliced.iter().all(|bytes| expected.contains(&bytes.as_slice())));
+        assert!(spliced.iter().any(|bytes| bytes == b"USER secret\r\n"));
+
+        // Nothing to splice at
+        let mut a = BytesInput::new(b"USER".to_vec());
+        assert_eq!(a.mutate_delimited_splice(&mut state, &other, &delimiters, 0).unwrap(), MutationResult::Skipped);
+    }
+
     #[test]
     fn test_splice_resize() {
         let mut state = TestState::new();
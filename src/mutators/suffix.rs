@@ -0,0 +1,181 @@
+use crate::{
+    config::{ComponentConfig, HasConfig},
+    feedback::StatePathMetadata,
+    input::HasPackets,
+    mutators::PacketBounds,
+};
+use libafl::{
+    bolts::{rands::Rand, tuples::Named, HasLen},
+    corpus::Corpus,
+    inputs::Input,
+    mutators::{MutationResult, Mutator},
+    state::{HasCorpus, HasMetadata, HasRand},
+    Error,
+};
+use std::marker::PhantomData;
+
+/// A mutator that splices inputs at a common state, like AFLNet does.
+///
+/// It keeps the packets of the current input up to a random packet and replaces the rest
+/// with the packets of another corpus entry that follow the same state in its state path.
+/// Since the target is in the same state at the splice point either way, the suffix is
+/// much more likely to be processed meaningfully than after an arbitrary splice.
+///
+/// The state paths are taken from the [`StatePathMetadata`](crate::StatePathMetadata) of the testcases,
+/// so the [`StatePathFeedback`](crate::StatePathFeedback) MUST be part of the feedbacks. Testcases without
+/// the metadata are ignored. The `n`-th packet is assumed to cause the `n`-th state, which holds if the
+/// executor records one state per packet.
+///
+/// It respects an upper bound on the number of packets passed as an argument to the constructor.
+///
+/// # Example
+/// ```
+/// let mut feedback = feedback_or!(StateFeedback::new(&state_observer), StatePathFeedback::new(&state_observer));
+/// let mutator = PacketSuffixSpliceMutator::new(16);
+/// ```
+pub struct PacketSuffixSpliceMutator<P> {
+    max_packets: usize,
+    phantom: PhantomData<P>,
+}
+
+impl<P> PacketSuffixSpliceMutator<P> {
+    /// Create a new PacketSuffixSpliceMutator with an upper bound on the number of packets
+    pub fn new(max_packets: usize) -> Self {
+        Self {
+            max_packets,
+            phantom: PhantomData,
+        }
+    }
+}
+
+/// Returns the state path of the corpus entry `idx`
+fn state_path<I, S>(state: &S, idx: usize) -> Result<Option<Vec<u32>>, Error>
+where
+    I: Input,
+    S: HasCorpus<I>,
+{
+    Ok(state.corpus().get(idx)?.borrow().metadata().get::<StatePathMetadata>().map(|metadata| metadata.path.clone()))
+}
+
+impl<I, S, P> Mutator<I, S> for PacketSuffixSpliceMutator<P>
+where
+    P: Clone,
+    I: Input + HasLen + HasPackets<P>,
+    S: HasRand + HasCorpus<I> + HasMetadata,
+{
+    fn mutate(&mut self, state: &mut S, input: &mut I, _stage_idx: i32) -> Result<MutationResult, Error> {
+        let current = match *state.corpus().current() {
+            Some(idx) => idx,
+            None => return Ok(MutationResult::Skipped),
+        };
+
+        let path = match state_path(state, current)? {
+            Some(path) => path,
+            None => return Ok(MutationResult::Skipped),
+        };
+
+        let len = std::cmp::min(input.len(), path.len());
+
+        if len == 0 {
+            return Ok(MutationResult::Skipped);
+        }
+
+        let prefix = 1 + state.rand_mut().below(len as u64) as usize;
+        let prefix_state = path[prefix - 1];
+
+        // All (corpus entry, start of suffix) pairs that continue from the prefix state
+        let mut candidates = Vec::new();
+
+        for idx in 0..state.corpus().count() {
+            if idx == current {
+                continue;
+            }
+
+            if let Some(other_path) = state_path(state, idx)? {
+                candidates.extend(other_path.iter().enumerate().filter(|(_, id)| **id == prefix_state).map(|(pos, _)| (idx, pos + 1)));
+            }
+        }
+
+        if candidates.is_empty() {
+            return Ok(MutationResult::Skipped);
+        }
+
+        let (idx, start) = candidates[state.rand_mut().below(candidates.len() as u64) as usize];
+        let room = PacketBounds::max_packets(state, self.max_packets).saturating_sub(prefix);
+        let mut other = state.corpus().get(idx)?.borrow_mut();
+        let other = other.load_input()?;
+
+        if start >= other.len() || room == 0 {
+            return Ok(MutationResult::Skipped);
+        }
+
+        input.packets_mut().truncate(prefix);
+        input.packets_mut().extend(other.packets()[start..].iter().take(room).cloned());
+        Ok(MutationResult::Mutated)
+    }
+}
+
+impl<P> Named for PacketSuffixSpliceMutator<P> {
+    fn name(&self) -> &str {
+        "PacketSuffixSpliceMutator"
+    }
+}
+
+impl<P> HasConfig for PacketSuffixSpliceMutator<P> {
+    fn config(&self) -> ComponentConfig {
+        ComponentConfig::new(self.name()).with_setting("max_packets", self.max_packets)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{test_utils::TestInput, TextLinePacket};
+    use libafl::{
+        bolts::rands::StdRand,
+        corpus::{InMemoryCorpus, Testcase},
+        state::StdState,
+    };
+
+    fn testcase(data: &[u8], path: Vec<u32>) -> Testcase<TestInput<TextLinePacket>> {
+        let mut testcase = Testcase::new(TestInput {
+            packets: TextLinePacket::parse(data),
+        });
+        testcase.add_metadata(StatePathMetadata {
+            path,
+        });
+        testcase
+    }
+
+    #[test]
+    fn test_suffix_splice() {
+        let mut state = StdState::new(StdRand::with_seed(0), InMemoryCorpus::<TestInput<TextLinePacket>>::new(), InMemoryCorpus::<TestInput<TextLinePacket>>::new(), &mut (), &mut ()).unwrap();
+        let mut mutator = PacketSuffixSpliceMutator::new(16);
+        let original = TestInput {
+            packets: TextLinePacket::parse(b"USER a\r\nPASS b\r\n"),
+        };
+
+        // Without a current testcase there is no state path
+        assert_eq!(mutator.mutate(&mut state, &mut original.clone(), 0).unwrap(), MutationResult::Skipped);
+
+        state.corpus_mut().add(testcase(b"USER a\r\nPASS b\r\n", vec![1, 2])).unwrap();
+        state.corpus_mut().add(testcase(b"USER c\r\nLIST\r\n", vec![1, 3])).unwrap();
+        state.corpus_mut().add(testcase(b"NOOP\r\nUSER d\r\nPASS e\r\nCWD /\r\n", vec![0, 1, 2, 4])).unwrap();
+        *state.corpus_mut().current_mut() = Some(0);
+
+        let mut outputs = Vec::new();
+
+        for _ in 0..1000 {
+            let mut input = original.clone();
+
+            if mutator.mutate(&mut state, &mut input, 0).unwrap() == MutationResult::Mutated {
+                outputs.push(input.packets.iter().flat_map(|packet| packet.to_bytes()).collect::<Vec<u8>>());
+            }
+        }
+
+        // The suffixes after state 1 and after state 2
+        assert!(outputs.iter().all(|output| output == b"USER a\r\nLIST\r\n" || output == b"USER a\r\nPASS e\r\nCWD /\r\n" || output == b"USER a\r\nPASS b\r\nCWD /\r\n"));
+        assert!(outputs.contains(&b"USER a\r\nLIST\r\n".to_vec()));
+        assert!(outputs.contains(&b"USER a\r\nPASS b\r\nCWD /\r\n".to_vec()));
+    }
+}
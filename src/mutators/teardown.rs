@@ -0,0 +1,189 @@
+use crate::{
+    config::{ComponentConfig, HasConfig},
+    executor::HasConnectionEvents,
+    input::HasPackets,
+    mutators::PacketBounds,
+};
+use libafl::{
+    bolts::{rands::Rand, tuples::Named, HasLen},
+    inputs::Input,
+    mutators::{MutationResult, Mutator},
+    state::{HasMetadata, HasRand},
+    Error,
+};
+use std::marker::PhantomData;
+
+/// A mutator that focuses on the teardown of a session, i.e. the last few packets of an input.
+///
+/// Cleanup paths of a target are rarely exercised because most inputs end with the same
+/// orderly `QUIT` or simply run out of packets, yet they often contain use-after-free style bugs.
+/// This mutator only touches the last `tail` packets of an input and does one of the following:
+/// - insert a `Disconnect` pseudo-packet, such that the connection is closed abruptly before the session ended
+/// - swap two packets, e.g. send the `QUIT` before the last command
+/// - delete a packet, e.g. the `QUIT`
+/// - append a copy of a packet to the end, such that something gets sent after the teardown command
+///
+/// The packet type must implement [`HasConnectionEvents`](crate::HasConnectionEvents).
+/// It respects an upper bound on the number of packets passed as an argument to the constructor.
+///
+/// # Example
+/// ```
+/// // Mutate the last 3 packets and never exceed 16 packets in an input
+/// let mutator = PacketTeardownMutator::new(3, 16);
+/// ```
+pub struct PacketTeardownMutator<P>
+where
+    P: HasConnectionEvents + Clone,
+{
+    tail: usize,
+    max_packets: usize,
+    phantom: PhantomData<P>,
+}
+
+impl<P> PacketTeardownMutator<P>
+where
+    P: HasConnectionEvents + Clone,
+{
+    /// Create a new PacketTeardownMutator that mutates the last `tail` packets of an input
+    /// with an upper bound on the number of packets
+    pub fn new(tail: usize, max_packets: usize) -> Self {
+        Self {
+            tail: std::cmp::max(1, tail),
+            max_packets,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<I, S, P> Mutator<I, S> for PacketTeardownMutator<P>
+where
+    P: HasConnectionEvents + Clone,
+    I: Input + HasLen + HasPackets<P>,
+    S: HasRand + HasMetadata,
+{
+    fn mutate(&mut self, state: &mut S, input: &mut I, _stage_idx: i32) -> Result<MutationResult, Error> {
+        let len = input.len();
+        let max_packets = PacketBounds::max_packets(state, self.max_packets);
+
+        if len == 0 {
+            return Ok(MutationResult::Skipped);
+        }
+
+        let start = len.saturating_sub(self.tail);
+        let tail = (len - start) as u64;
+
+        match state.rand_mut().below(4) {
+            // Close the connection before one of the final packets
+            0 => {
+                if len >= max_packets {
+                    return Ok(MutationResult::Skipped);
+                }
+
+                let idx = start + state.rand_mut().below(tail) as usize;
+                input.packets_mut().insert(idx, P::disconnect());
+            },
+            // Change the order of the final packets
+            1 => {
+                if tail < 2 {
+                    return Ok(MutationResult::Skipped);
+                }
+
+                let from = start + state.rand_mut().below(tail) as usize;
+                let to = start + state.rand_mut().below(tail) as usize;
+
+                if from == to {
+                    return Ok(MutationResult::Skipped);
+                }
+
+                input.packets_mut().swap(from, to);
+            },
+            // Leave out one of the final packets
+            2 => {
+                if len < 2 {
+                    return Ok(MutationResult::Skipped);
+                }
+
+                let idx = start + state.rand_mut().below(tail) as usize;
+                input.packets_mut().remove(idx);
+            },
+            // Send something after the session has been torn down
+            3 => {
+                if len >= max_packets {
+                    return Ok(MutationResult::Skipped);
+                }
+
+                let idx = state.rand_mut().below(len as u64) as usize;
+                let packet = &input.packets()[idx];
+
+                if packet.connection_event().is_some() {
+                    return Ok(MutationResult::Skipped);
+                }
+
+                let packet = packet.clone();
+                input.packets_mut().push(packet);
+            },
+            _ => unreachable!(),
+        }
+
+        Ok(MutationResult::Mutated)
+    }
+}
+
+impl<P> Named for PacketTeardownMutator<P>
+where
+    P: HasConnectionEvents + Clone,
+{
+    fn name(&self) -> &str {
+        "PacketTeardownMutator"
+    }
+}
+
+impl<P> HasConfig for PacketTeardownMutator<P>
+where
+    P: HasConnectionEvents + Clone,
+{
+    fn config(&self) -> ComponentConfig {
+        ComponentConfig::new(self.name()).with_setting("tail", self.tail).with_setting("max_packets", self.max_packets)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::executor::NetworkPacket;
+    use crate::test_utils::TestInput;
+    use libafl::{
+        bolts::rands::StdRand,
+        corpus::InMemoryCorpus,
+        inputs::{BytesInput, HasBytesVec},
+        state::StdState,
+    };
+
+    fn data(packet: &NetworkPacket<BytesInput>) -> Option<&[u8]> {
+        match packet {
+            NetworkPacket::Data(data) => Some(data.bytes()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_teardown() {
+        let mut state = StdState::new(StdRand::with_seed(0), InMemoryCorpus::<TestInput<NetworkPacket<BytesInput>>>::new(), InMemoryCorpus::<TestInput<NetworkPacket<BytesInput>>>::new(), &mut (), &mut ()).unwrap();
+        let mut mutator = PacketTeardownMutator::new(2, 6);
+        let packets: Vec<NetworkPacket<BytesInput>> = [b"USER a", b"PASS b", b"LIST /", b"QUIT  "].iter().map(|line| NetworkPacket::Data(BytesInput::new(line.to_vec()))).collect();
+
+        for _ in 0..100 {
+            let mut input = TestInput {
+                packets: packets.clone(),
+            };
+
+            while mutator.mutate(&mut state, &mut input, 0).unwrap() == MutationResult::Skipped {}
+
+            // The beginning of the session is left alone
+            assert!(input.len() >= 3 && input.len() <= 5);
+            assert_eq!(data(&input.packets[0]), Some(&b"USER a"[..]));
+            assert_eq!(data(&input.packets[1]), Some(&b"PASS b"[..]));
+            assert_ne!(input.packets, packets);
+        }
+    }
+}
@@ -0,0 +1,120 @@
+/// Signifies that a packet can end a session, like a `QUIT` or `LOGOUT` command,
+/// after which the target doesn't read any further packets.
+///
+/// Packets after a terminal packet are never processed by the target, so mutators that put packets there
+/// only waste executions. With `with_terminal_handling()` the [`PacketDuplicateMutator`](crate::PacketDuplicateMutator),
+/// [`PacketInsertMutator`](crate::PacketInsertMutator) and [`PacketReorderMutator`](crate::PacketReorderMutator)
+/// take terminal packets into account, see [`TerminalHandling`].
+///
+/// Already implemented for:
+/// - [`TextLinePacket`](crate::TextLinePacket): `QUIT` and `LOGOUT`, case-insensitive
+/// - [`NetworkPacket`](crate::NetworkPacket): forwards to the inner packet of `Data` packets
+/// - the FTP and SMTP commands of the [`protocols`](crate::protocols) module: `QUIT`
+///
+/// # Example
+/// ```
+/// impl HasTerminalPacket for ImapCommand {
+///     fn is_terminal(&self) -> bool {
+///         matches!(self, ImapCommand::Logout)
+///     }
+/// }
+///
+/// let mutator = PacketInsertMutator::new(16).with_terminal_handling(TerminalHandling::Avoid);
+/// ```
+pub trait HasTerminalPacket {
+    /// Returns whether the target stops reading after this packet
+    fn is_terminal(&self) -> bool {
+        false
+    }
+}
+
+/// How the sequence mutators treat packets after the first terminal packet of an input
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TerminalHandling {
+    /// Never place packets after a terminal packet. Mutations that would do so are skipped.
+    Avoid,
+    /// Place packets anywhere, but remove all packets after the first terminal packet afterwards
+    Strip,
+}
+
+/// Applies a [`TerminalHandling`] for packets of type `P`
+#[derive(Debug)]
+pub(crate) struct TerminalGuard<P> {
+    is_terminal: fn(&P) -> bool,
+    handling: TerminalHandling,
+}
+
+impl<P> TerminalGuard<P>
+where
+    P: HasTerminalPacket,
+{
+    /// Create a new TerminalGuard for packets that implement [`HasTerminalPacket`]
+    pub(crate) fn new(handling: TerminalHandling) -> Self {
+        Self {
+            is_terminal: P::is_terminal,
+            handling,
+        }
+    }
+}
+
+impl<P> TerminalGuard<P> {
+    /// Returns the number of packets after the first terminal packet
+    pub(crate) fn trailing(&self, packets: &[P]) -> usize {
+        packets.iter().position(self.is_terminal).map_or(0, |idx| packets.len() - idx - 1)
+    }
+
+    /// Check `packets` after a mutation, given the number of [`trailing()`](TerminalGuard::trailing) packets before it.
+    /// Returns `false` if the mutation placed packets after a terminal packet and must be undone.
+    /// With [`TerminalHandling::Strip`] such packets are removed instead.
+    pub(crate) fn accept(&self, trailing: usize, packets: &mut Vec<P>) -> bool {
+        match self.handling {
+            TerminalHandling::Avoid => self.trailing(packets) <= trailing,
+            TerminalHandling::Strip => {
+                let trailing = self.trailing(packets);
+                packets.truncate(packets.len() - trailing);
+                true
+            },
+        }
+    }
+}
+
+/// Describes the terminal handling of a mutator for its [`ComponentConfig`](crate::ComponentConfig)
+pub(crate) fn terminal_setting<P>(guard: &Option<TerminalGuard<P>>) -> String {
+    guard.as_ref().map_or_else(|| "none".to_string(), |guard| format!("{:?}", guard.handling))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{test_utils::TestInput, PacketDuplicateMutator, PacketReorderMutator, TextLinePacket};
+    use libafl::{
+        bolts::{rands::StdRand, HasLen},
+        corpus::InMemoryCorpus,
+        mutators::{MutationResult, Mutator},
+        state::StdState,
+    };
+
+    #[test]
+    fn test_terminal_handling() {
+        let mut state = StdState::new(StdRand::with_seed(0), InMemoryCorpus::<TestInput<TextLinePacket>>::new(), InMemoryCorpus::<TestInput<TextLinePacket>>::new(), &mut (), &mut ()).unwrap();
+        let mut duplicate = PacketDuplicateMutator::new(16).with_terminal_handling(TerminalHandling::Avoid);
+        let mut reorder = PacketReorderMutator::new().with_terminal_handling(TerminalHandling::Strip);
+        let original = TestInput {
+            packets: TextLinePacket::parse(b"USER a\r\nPASS b\r\nquit\r\n"),
+        };
+
+        for _ in 0..1000 {
+            let mut input = original.clone();
+
+            if duplicate.mutate(&mut state, &mut input, 0).unwrap() == MutationResult::Mutated {
+                assert_eq!(input.len(), 4);
+                assert_eq!(input.packets[3].keyword(), b"quit");
+            }
+
+            let mut input = original.clone();
+            reorder.mutate(&mut state, &mut input, 0).unwrap();
+            let quit = input.packets.iter().position(|packet| packet.is_terminal()).unwrap();
+            assert_eq!(quit, input.len() - 1);
+        }
+    }
+}
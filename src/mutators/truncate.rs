@@ -0,0 +1,71 @@
+use crate::{
+    config::{ComponentConfig, HasConfig},
+    input::HasPackets,
+    mutators::PacketBounds,
+};
+use libafl::{
+    bolts::{rands::Rand, tuples::Named, HasLen},
+    inputs::Input,
+    mutators::{MutationResult, Mutator},
+    state::{HasMetadata, HasRand},
+    Error,
+};
+use std::marker::PhantomData;
+
+/// A mutator that removes a random number of packets from the end of an input.
+///
+/// Unlike the [`PacketDeleteMutator`](crate::PacketDeleteMutator), which only removes
+/// a single packet, this cuts off a whole suffix and produces shorter, faster inputs.
+/// It respects a lower bound on the number of packets
+/// passed as an argument to the constructor.
+///
+/// # Example
+/// ```
+/// // Make sure that we always have at least 4 packets in an input
+/// let mutator = PacketTruncateMutator::new(4);
+/// ```
+pub struct PacketTruncateMutator<P> {
+    phantom: PhantomData<P>,
+    min_packets: usize,
+}
+
+impl<P> PacketTruncateMutator<P> {
+    /// Create a new PacketTruncateMutator with a lower bound on the number of packets
+    pub fn new(min_packets: usize) -> Self {
+        Self {
+            phantom: PhantomData,
+            min_packets: std::cmp::max(1, min_packets),
+        }
+    }
+}
+
+impl<I, S, P> Mutator<I, S> for PacketTruncateMutator<P>
+where
+    I: Input + HasLen + HasPackets<P>,
+    S: HasRand + HasMetadata,
+{
+    fn mutate(&mut self, state: &mut S, input: &mut I, _stage_idx: i32) -> Result<MutationResult, Error> {
+        let min_packets = PacketBounds::min_packets(state, self.min_packets);
+
+        if input.len() <= min_packets {
+            return Ok(MutationResult::Skipped);
+        }
+
+        let len = min_packets + state.rand_mut().below((input.len() - min_packets) as u64) as usize;
+        input.packets_mut().truncate(len);
+
+        Ok(MutationResult::Mutated)
+    }
+}
+
+impl<P> Named for PacketTruncateMutator<P> {
+    fn name(&self) -> &str {
+        "PacketTruncateMutator"
+    }
+}
+
+impl<P> HasConfig for PacketTruncateMutator<P> {
+    fn config(&self) -> ComponentConfig {
+        ComponentConfig::new(self.name()).with_setting("min_packets", self.min_packets)
+    }
+}
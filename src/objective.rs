@@ -0,0 +1,292 @@
+use libafl::{
+    bolts::tuples::Named,
+    corpus::Testcase,
+    events::EventFirer,
+    executors::ExitKind,
+    feedbacks::Feedback,
+    impl_serdeany,
+    inputs::Input,
+    observers::ObserversTuple,
+    state::{HasClientPerfMonitor, HasMetadata},
+    Error,
+};
+use serde::{Deserialize, Serialize};
+use std::fmt::Debug;
+use std::path::PathBuf;
+
+/// An objective feedback together with a name and a priority,
+/// the building block of a [`MultiObjectiveFeedback`].
+#[derive(Debug)]
+pub struct Objective<F> {
+    name: String,
+    priority: u32,
+    feedback: F,
+}
+
+impl<F> Objective<F> {
+    /// Create a new Objective
+    ///
+    /// # Arguments
+    /// - `name`: the name solutions are tagged with when this objective matches
+    /// - `priority`: how important a match is. Higher is more important
+    /// - `feedback`: the feedback that decides whether the objective matches
+    pub fn new(name: &str, priority: u32, feedback: F) -> Self {
+        Self {
+            name: name.to_string(),
+            priority,
+            feedback,
+        }
+    }
+}
+
+/// A tuple of [`Objective`]s, created with LibAFLs `tuple_list!`.
+pub trait ObjectivesTuple<I, S>: Debug
+where
+    I: Input,
+    S: HasClientPerfMonitor,
+{
+    /// Initialize all feedbacks
+    fn init_all(&mut self, state: &mut S) -> Result<(), Error>;
+
+    /// Evaluate all feedbacks and append whether they matched to `matches`
+    #[allow(clippy::too_many_arguments)]
+    fn evaluate_all<EM, OT>(&mut self, state: &mut S, mgr: &mut EM, input: &I, observers: &OT, exit_kind: &ExitKind, matches: &mut Vec<bool>) -> Result<(), Error>
+    where
+        EM: EventFirer<I>,
+        OT: ObserversTuple<I, S>;
+
+    /// Let all feedbacks that matched append their metadata and discard the metadata of all others
+    fn finish_all(&mut self, state: &mut S, testcase: &mut Testcase<I>, matches: &[bool]) -> Result<(), Error>;
+
+    /// Discard the metadata of all feedbacks
+    fn discard_all(&mut self, state: &mut S, input: &I) -> Result<(), Error>;
+
+    /// Append the names and priorities of all objectives to `out`
+    fn describe_all(&self, out: &mut Vec<(String, u32)>);
+}
+
+impl<I, S> ObjectivesTuple<I, S> for ()
+where
+    I: Input,
+    S: HasClientPerfMonitor,
+{
+    fn init_all(&mut self, _state: &mut S) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn evaluate_all<EM, OT>(&mut self, _state: &mut S, _mgr: &mut EM, _input: &I, _observers: &OT, _exit_kind: &ExitKind, _matches: &mut Vec<bool>) -> Result<(), Error>
+    where
+        EM: EventFirer<I>,
+        OT: ObserversTuple<I, S>,
+    {
+        Ok(())
+    }
+
+    fn finish_all(&mut self, _state: &mut S, _testcase: &mut Testcase<I>, _matches: &[bool]) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn discard_all(&mut self, _state: &mut S, _input: &I) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn describe_all(&self, _out: &mut Vec<(String, u32)>) {}
+}
+
+impl<I, S, F, Tail> ObjectivesTuple<I, S> for (Objective<F>, Tail)
+where
+    I: Input,
+    S: HasClientPerfMonitor,
+    F: Feedback<I, S>,
+    Tail: ObjectivesTuple<I, S>,
+{
+    fn init_all(&mut self, state: &mut S) -> Result<(), Error> {
+        self.0.feedback.init_state(state)?;
+        self.1.init_all(state)
+    }
+
+    fn evaluate_all<EM, OT>(&mut self, state: &mut S, mgr: &mut EM, input: &I, observers: &OT, exit_kind: &ExitKind, matches: &mut Vec<bool>) -> Result<(), Error>
+    where
+        EM: EventFirer<I>,
+        OT: ObserversTuple<I, S>,
+    {
+        matches.push(self.0.feedback.is_interesting(state, mgr, input, observers, exit_kind)?);
+        self.1.evaluate_all(state, mgr, input, observers, exit_kind, matches)
+    }
+
+    fn finish_all(&mut self, state: &mut S, testcase: &mut Testcase<I>, matches: &[bool]) -> Result<(), Error> {
+        if matches[0] {
+            self.0.feedback.append_metadata(state, testcase)?;
+        } else if let Some(input) = testcase.input() {
+            self.0.feedback.discard_metadata(state, input)?;
+        }
+
+        self.1.finish_all(state, testcase, &matches[1..])
+    }
+
+    fn discard_all(&mut self, state: &mut S, input: &I) -> Result<(), Error> {
+        self.0.feedback.discard_metadata(state, input)?;
+        self.1.discard_all(state, input)
+    }
+
+    fn describe_all(&self, out: &mut Vec<(String, u32)>) {
+        out.push((self.0.name.clone(), self.0.priority));
+        self.1.describe_all(out);
+    }
+}
+
+/// Metadata that [`MultiObjectiveFeedback`] attaches to every solution
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ObjectiveMetadata {
+    /// Names of all objectives that matched, most important first
+    pub objectives: Vec<String>,
+    /// The highest priority of all objectives that matched
+    pub priority: u32,
+}
+
+impl_serdeany!(ObjectiveMetadata);
+
+/// Evaluates several objective feedbacks at once and tags every solution with
+/// the objectives that matched and a priority.
+///
+/// Unlike `feedback_or!` all objectives are evaluated, even if one already matched,
+/// so that every solution carries the complete list of objectives it fulfills in its [`ObjectiveMetadata`].
+/// Optionally the solutions can be sorted into subdirectories `p<priority>-<objective>+<objective>...`
+/// of a directory with [`with_classification_dir()`](MultiObjectiveFeedback::with_classification_dir).
+///
+/// # Example
+/// ```
+/// let mut objective = MultiObjectiveFeedback::new(tuple_list!(
+///     Objective::new("crash", 100, CrashFeedback::new()),
+///     Objective::new("hang", 50, TimeoutFeedback::new()),
+/// ));
+/// ```
+#[derive(Debug)]
+pub struct MultiObjectiveFeedback<OT> {
+    objectives: OT,
+    matches: Vec<bool>,
+    classification_dir: Option<PathBuf>,
+    counter: usize,
+}
+
+impl<OT> MultiObjectiveFeedback<OT> {
+    /// Create a new MultiObjectiveFeedback from a tuple of [`Objective`]s
+    pub fn new(objectives: OT) -> Self {
+        Self {
+            objectives,
+            matches: Vec::new(),
+            classification_dir: None,
+            counter: 0,
+        }
+    }
+
+    /// Store solutions of an on-disk solutions corpus in subdirectories of `dir` named after
+    /// the priority and the objectives that matched instead of the directory of the corpus.
+    pub fn with_classification_dir<P: Into<PathBuf>>(mut self, dir: P) -> Self {
+        self.classification_dir = Some(dir.into());
+        self
+    }
+
+    /// Build the metadata for the objectives in the last evaluation
+    fn classify(&self, descriptions: &[(String, u32)]) -> ObjectiveMetadata {
+        let mut matched: Vec<&(String, u32)> = descriptions.iter().zip(&self.matches).filter(|(_, matched)| **matched).map(|(description, _)| description).collect();
+        matched.sort_by_key(|(_, priority)| std::cmp::Reverse(*priority));
+
+        ObjectiveMetadata {
+            objectives: matched.iter().map(|(name, _)| name.clone()).collect(),
+            priority: matched.first().map_or(0, |(_, priority)| *priority),
+        }
+    }
+}
+
+impl<OT> Named for MultiObjectiveFeedback<OT> {
+    fn name(&self) -> &str {
+        "MultiObjectiveFeedback"
+    }
+}
+
+impl<I, S, OT> Feedback<I, S> for MultiObjectiveFeedback<OT>
+where
+    I: Input,
+    S: HasClientPerfMonitor,
+    OT: ObjectivesTuple<I, S>,
+{
+    fn init_state(&mut self, state: &mut S) -> Result<(), Error> {
+        self.objectives.init_all(state)
+    }
+
+    fn is_interesting<EM, OT2>(&mut self, state: &mut S, mgr: &mut EM, input: &I, observers: &OT2, exit_kind: &ExitKind) -> Result<bool, Error>
+    where
+        EM: EventFirer<I>,
+        OT2: ObserversTuple<I, S>,
+    {
+        self.matches.clear();
+        self.objectives.evaluate_all(state, mgr, input, observers, exit_kind, &mut self.matches)?;
+        Ok(self.matches.contains(&true))
+    }
+
+    fn append_metadata(&mut self, state: &mut S, testcase: &mut Testcase<I>) -> Result<(), Error> {
+        self.objectives.finish_all(state, testcase, &self.matches)?;
+
+        let mut descriptions = Vec::new();
+        self.objectives.describe_all(&mut descriptions);
+        let metadata = self.classify(&descriptions);
+
+        if let (Some(dir), Some(input)) = (&self.classification_dir, testcase.input()) {
+            let dir = dir.join(format!("p{}-{}", metadata.priority, metadata.objectives.join("+")));
+            std::fs::create_dir_all(&dir)?;
+
+            let filename = dir.join(input.generate_name(self.counter));
+            self.counter += 1;
+            testcase.set_filename(filename.to_string_lossy().into_owned());
+        }
+
+        println!("[butterfly] Found a solution for {} with priority {}", metadata.objectives.join(", "), metadata.priority);
+        testcase.add_metadata(metadata);
+        self.matches.clear();
+        Ok(())
+    }
+
+    fn discard_metadata(&mut self, state: &mut S, input: &I) -> Result<(), Error> {
+        self.matches.clear();
+        self.objectives.discard_all(state, input)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libafl::{
+        bolts::{rands::StdRand, tuples::tuple_list},
+        corpus::InMemoryCorpus,
+        events::NopEventManager,
+        feedbacks::{CrashFeedback, TimeoutFeedback},
+        inputs::BytesInput,
+        state::StdState,
+    };
+
+    #[test]
+    fn test_classification() {
+        let mut state = StdState::new(StdRand::with_seed(0), InMemoryCorpus::<BytesInput>::new(), InMemoryCorpus::<BytesInput>::new(), &mut (), &mut ()).unwrap();
+        let mut mgr = NopEventManager {};
+        let input = BytesInput::new(b"input".to_vec());
+
+        let mut feedback = MultiObjectiveFeedback::new(tuple_list!(Objective::new("hang", 50, TimeoutFeedback::new()), Objective::new("crash", 100, CrashFeedback::new())));
+        Feedback::<BytesInput, _>::init_state(&mut feedback, &mut state).unwrap();
+
+        assert!(!feedback.is_interesting(&mut state, &mut mgr, &input, &(), &ExitKind::Ok).unwrap());
+        feedback.discard_metadata(&mut state, &input).unwrap();
+
+        assert!(feedback.is_interesting(&mut state, &mut mgr, &input, &(), &ExitKind::Crash).unwrap());
+        let mut testcase = Testcase::<BytesInput>::new(input);
+        feedback.append_metadata(&mut state, &mut testcase).unwrap();
+
+        assert_eq!(
+            testcase.metadata().get::<ObjectiveMetadata>(),
+            Some(&ObjectiveMetadata {
+                objectives: vec!["crash".to_string()],
+                priority: 100,
+            })
+        );
+    }
+}
@@ -1,10 +1,51 @@
-use ahash::RandomState;
-use libafl::{bolts::tuples::Named, executors::ExitKind, observers::Observer, Error};
+use crate::inference::ResponseStateInference;
+use ahash::{AHasher, RandomState};
+use libafl::{
+    bolts::{
+        shmem::{ShMem, ShMemProvider, StdShMemProvider},
+        tuples::{MatchName, Named},
+        AsMutSlice, AsSlice,
+    },
+    executors::ExitKind,
+    observers::Observer,
+    Error,
+};
 use serde::{Deserialize, Serialize};
+use std::any::Any;
 use std::cmp::Eq;
-use std::collections::{HashMap, HashSet};
+use std::collections::{hash_map::Entry, BTreeSet, HashMap, HashSet, VecDeque};
 use std::fmt::{Debug, Write};
-use std::hash::Hash;
+use std::fs::{File, OpenOptions};
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// Source of the transitions into the first state of a run
+const ENTRY_NODE: u32 = u32::MAX;
+/// Input label of transitions that were recorded without a label
+const NO_LABEL: &str = "epsilon";
+/// Maximum number of distinct contexts that are kept per transition
+const MAX_EDGE_CONTEXTS: usize = 16;
+
+/// Escape `s` for a quoted string in the DOT format
+fn escape_dot(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n").replace('\r', "")
+}
+
+/// The label of an edge with the given contexts
+fn join_contexts(contexts: &BTreeSet<String>) -> String {
+    contexts.iter().map(String::as_str).collect::<Vec<&str>>().join(", ")
+}
+
+/// Escape `s` for text and attribute values in XML
+fn escape_xml(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;").replace('\'', "&apos;")
+}
+
+/// Escape `s` for a quoted label in a Mermaid diagram
+fn escape_mermaid(s: &str) -> String {
+    s.replace('#', "#35;").replace('"', "#quot;").replace('\n', "<br>").replace('\r', "")
+}
 
 #[inline]
 fn pack_transition(from: u32, to: u32) -> u64 {
@@ -22,10 +63,51 @@ struct StateGraph<PS>
 where
     PS: Clone + Debug + Eq + Hash,
 {
+    /// Node ids by state, empty with [`NodeStorage::Hashed`]
     nodes: HashMap<PS, u32, RandomState>,
-    edges: HashSet<u64, RandomState>,
+    /// Node ids by the hash of their state, see [`StateObserver::with_node_storage()`]
+    #[serde(default)]
+    hashed: Option<HashedNodes<PS>>,
+    /// How often each node was entered, by id
+    node_hits: Vec<u64>,
+    /// How often each edge was taken
+    edges: HashMap<u64, u64, RandomState>,
     last_node: Option<u32>,
+    /// Whether a new node was created in the last run
+    #[serde(default)]
+    new_nodes: bool,
     new_transitions: bool,
+    /// Whether the hit count of an edge reached a new bucket in the last run
+    new_hit_buckets: bool,
+    new_known_transitions: bool,
+    /// Nodes that were created in the current run. Evicted ids are reused, so the ids alone don't tell.
+    #[serde(skip)]
+    fresh_nodes: HashSet<u32>,
+    path: Vec<u32>,
+    /// Positions in `path` at which new edges were created
+    #[serde(skip)]
+    new_positions: Vec<usize>,
+    /// Labels of the inputs that caused a transition, including self-loops
+    /// and transitions from [`ENTRY_NODE`]
+    labels: HashMap<u64, BTreeSet<String>, RandomState>,
+    /// The packets that caused a transition, see [`StateObserver::record_with_context()`]
+    #[serde(default)]
+    contexts: HashMap<u64, BTreeSet<String>, RandomState>,
+    /// Sequences of consecutive states, see [`StateObserver::with_ngrams()`]
+    #[serde(default)]
+    ngrams: HashSet<Vec<u32>, RandomState>,
+    /// Whether a new n-gram was seen in the last run
+    #[serde(default)]
+    new_ngrams: bool,
+    /// Position in `path` after which the states form n-grams, moved by restarts
+    #[serde(default)]
+    window_start: usize,
+    /// Ids of evicted nodes that get handed out again, see [`NodeBudgetPolicy::EvictColdest`]
+    #[serde(default)]
+    free_ids: Vec<u32>,
+    /// How often a state didn't fit into the node budget
+    #[serde(default)]
+    overflows: u64,
 }
 impl<PS> StateGraph<PS>
 where
@@ -34,143 +116,2712 @@ where
     fn new() -> Self {
         Self {
             nodes: HashMap::<PS, u32, RandomState>::default(),
-            edges: HashSet::<u64, RandomState>::default(),
+            hashed: None,
+            node_hits: Vec::new(),
+            edges: HashMap::<u64, u64, RandomState>::default(),
             last_node: None,
+            new_nodes: false,
             new_transitions: false,
+            new_hit_buckets: false,
+            new_known_transitions: false,
+            fresh_nodes: HashSet::new(),
+            path: Vec::new(),
+            new_positions: Vec::new(),
+            labels: HashMap::<u64, BTreeSet<String>, RandomState>::default(),
+            contexts: HashMap::<u64, BTreeSet<String>, RandomState>::default(),
+            ngrams: HashSet::<Vec<u32>, RandomState>::default(),
+            new_ngrams: false,
+            window_start: 0,
+            free_ids: Vec::new(),
+            overflows: 0,
         }
     }
 
-    fn reset(&mut self) {
-        self.last_node = None;
+    fn reset(&mut self, mode: ResetMode) {
+        if mode == ResetMode::Full {
+            self.last_node = None;
+        }
+
+        self.new_nodes = false;
         self.new_transitions = false;
+        self.new_hit_buckets = false;
+        self.new_known_transitions = false;
+        self.fresh_nodes.clear();
+        self.path.clear();
+        self.new_positions.clear();
+        self.new_ngrams = false;
+        self.window_start = 0;
     }
 
-    fn add_node(&mut self, state: &PS) -> u32 {
-        match self.nodes.get(state) {
-            Some(id) => *id,
+    /// Switch to another way of storing the nodes, keeping the ids of the existing ones
+    fn set_storage(&mut self, storage: NodeStorage) {
+        let entries: Vec<(PS, u32)> = self.node_entries().map(|(state, id)| (state.clone(), id)).collect();
+        self.nodes.clear();
+        self.hashed = match storage {
+            NodeStorage::Exact => None,
+            NodeStorage::Hashed {
+                check_collisions,
+            } => Some(HashedNodes::new(check_collisions)),
+        };
+
+        for (state, id) in &entries {
+            self.store_node(state, *id);
+        }
+    }
+
+    fn num_nodes(&self) -> usize {
+        match &self.hashed {
+            Some(hashed) => hashed.len,
+            None => self.nodes.len(),
+        }
+    }
+
+    /// Returns the id of the node of `state`
+    fn node_id(&self, state: &PS) -> Option<u32> {
+        match &self.hashed {
+            Some(hashed) => hashed.get(state),
+            None => self.nodes.get(state).copied(),
+        }
+    }
+
+    /// Returns all states with the ids of their nodes, in no particular order
+    fn node_entries(&self) -> Box<dyn Iterator<Item = (&PS, u32)> + '_> {
+        match &self.hashed {
+            Some(hashed) => Box::new(hashed.states.iter().enumerate().filter_map(|(id, state)| state.as_ref().map(|state| (state, id as u32)))),
+            None => Box::new(self.nodes.iter().map(|(state, id)| (state, *id))),
+        }
+    }
+
+    fn store_node(&mut self, state: &PS, id: u32) {
+        match &mut self.hashed {
+            Some(hashed) => hashed.insert(state, id),
+            None => assert!(self.nodes.insert(state.clone(), id).is_none()),
+        }
+    }
+
+    /// Add a node for a state that is not in the graph yet, reusing the id of an evicted node if there is one
+    fn insert_node(&mut self, state: &PS) -> u32 {
+        match self.free_ids.pop() {
+            Some(id) => {
+                self.node_hits[id as usize] = 0;
+                self.store_node(state, id);
+                id
+            },
             None => {
-                let next_id = self.nodes.len() as u32;
-                assert!(self.nodes.insert(state.clone(), next_id).is_none());
+                let next_id = self.node_hits.len() as u32;
+                self.store_node(state, next_id);
+                self.node_hits.push(0);
                 next_id
             },
         }
     }
 
-    fn add_edge(&mut self, id: u32) {
-        self.new_transitions |= match self.last_node.take() {
-            Some(old_id) => {
-                if old_id != id {
-                    self.edges.insert(pack_transition(old_id, id))
-                } else {
-                    false
-                }
+    fn add_node(&mut self, state: &PS) -> u32 {
+        let id = match self.node_id(state) {
+            Some(id) => id,
+            None => {
+                self.new_nodes = true;
+                let id = self.insert_node(state);
+                self.fresh_nodes.insert(id);
+                id
             },
-            None => false,
         };
 
+        self.node_hits[id as usize] += 1;
+        id
+    }
+
+    /// Returns the state that should be recorded instead of `state` to stay within `budget`,
+    /// `None` if nothing should be recorded. Evicts a node if the policy says so.
+    fn budgeted<'a>(&mut self, state: &'a PS, budget: &'a Option<NodeBudget<PS>>) -> Option<&'a PS> {
+        let budget = match budget {
+            Some(budget) if self.num_nodes() >= budget.max_nodes && self.node_id(state).is_none() => budget,
+            _ => return Some(state),
+        };
+
+        self.overflows += 1;
+
+        match &budget.policy {
+            NodeBudgetPolicy::Ignore => None,
+            NodeBudgetPolicy::Merge(overflow) => Some(overflow),
+            NodeBudgetPolicy::EvictColdest => {
+                self.evict_coldest();
+                Some(state)
+            },
+        }
+    }
+
+    /// Remove the node that was entered least often, except for the nodes of the current run
+    /// and the node the next transition starts from, together with all its edges and n-grams
+    fn evict_coldest(&mut self) {
+        let on_path: HashSet<u32> = self.path.iter().copied().chain(self.last_node).collect();
+        let coldest = self.node_entries().filter(|(_, id)| !on_path.contains(id)).min_by_key(|(_, id)| self.node_hits[*id as usize]).map(|(state, id)| (state.clone(), id));
+
+        if let Some((state, id)) = coldest {
+            let touches = |transition: &u64| {
+                let (from, to) = unpack_transition(*transition);
+                from == id || to == id
+            };
+
+            match &mut self.hashed {
+                Some(hashed) => hashed.remove(&state, id),
+                None => {
+                    self.nodes.remove(&state);
+                },
+            }
+
+            self.edges.retain(|transition, _| !touches(transition));
+            self.labels.retain(|transition, _| !touches(transition));
+            self.contexts.retain(|transition, _| !touches(transition));
+            self.ngrams.retain(|ngram| !ngram.contains(&id));
+            self.free_ids.push(id);
+        }
+    }
+
+    fn add_label(&mut self, from: u32, to: u32, label: &str) {
+        let labels = self.labels.entry(pack_transition(from, to)).or_default();
+
+        if !labels.contains(label) {
+            labels.insert(label.to_string());
+        }
+    }
+
+    /// Returns the source of the edge if a new edge was created
+    fn add_edge(&mut self, id: u32, label: Option<&str>, context: Option<&str>) -> Option<u32> {
+        let mut new_edge = None;
+
+        match (self.last_node, label) {
+            (None, label) => self.add_label(ENTRY_NODE, id, label.unwrap_or(NO_LABEL)),
+            (Some(old_id), Some(label)) => self.add_label(old_id, id, label),
+            (Some(_), None) => {},
+        }
+
+        if let Some(old_id) = self.last_node.take() {
+            if old_id != id {
+                if let Some(context) = context {
+                    let contexts = self.contexts.entry(pack_transition(old_id, id)).or_default();
+
+                    if contexts.len() < MAX_EDGE_CONTEXTS && !contexts.contains(context) {
+                        contexts.insert(context.to_string());
+                    }
+                }
+
+                let hits = self.edges.entry(pack_transition(old_id, id)).or_insert(0);
+                *hits += 1;
+
+                if *hits == 1 {
+                    self.new_transitions = true;
+                    self.new_known_transitions |= !self.fresh_nodes.contains(&old_id) && !self.fresh_nodes.contains(&id);
+                    self.new_positions.push(self.path.len());
+                    new_edge = Some(old_id);
+                } else if hits.is_power_of_two() {
+                    self.new_hit_buckets = true;
+                }
+            }
+        }
+
         self.last_node = Some(id);
+        self.path.push(id);
+        new_edge
+    }
+
+    /// Forget the current state so that the next state is treated like the first one of a run
+    fn detach(&mut self) {
+        self.last_node = None;
+        self.window_start = self.path.len();
+    }
+
+    /// Returns the successors of every node, indexed by id
+    fn adjacency(&self) -> Vec<Vec<u32>> {
+        let mut adjacency = vec![Vec::new(); self.node_hits.len()];
+
+        for transition in self.edges.keys() {
+            let (from, to) = unpack_transition(*transition);
+            adjacency[from as usize].push(to);
+        }
+
+        adjacency
+    }
+
+    /// Returns the ids of the nodes that runs started in, in ascending order.
+    /// Graphs that were only merged from other clients have no record of that, so node 0 is used.
+    fn entry_nodes(&self) -> Vec<u32> {
+        let mut entries: Vec<u32> = self.labels.keys().map(|transition| unpack_transition(*transition)).filter(|(from, _)| *from == ENTRY_NODE).map(|(_, to)| to).collect();
+        entries.sort_unstable();
+        entries.dedup();
+
+        if entries.is_empty() && self.num_nodes() > 0 && !self.free_ids.contains(&0) {
+            entries.push(0);
+        }
+
+        entries
+    }
+
+    /// Returns the largest number of transitions that are needed to reach a node from an entry node
+    fn depth(&self) -> usize {
+        let adjacency = self.adjacency();
+        let mut distances = vec![usize::MAX; adjacency.len()];
+        let mut queue = VecDeque::new();
+
+        for entry in self.entry_nodes() {
+            distances[entry as usize] = 0;
+            queue.push_back(entry);
+        }
+
+        let mut depth = 0;
+
+        while let Some(id) = queue.pop_front() {
+            let distance = distances[id as usize];
+            depth = std::cmp::max(depth, distance);
+
+            for next in &adjacency[id as usize] {
+                if distances[*next as usize] == usize::MAX {
+                    distances[*next as usize] = distance + 1;
+                    queue.push_back(*next);
+                }
+            }
+        }
+
+        depth
+    }
+
+    /// Returns the strongly connected components with Kosarajus algorithm.
+    /// The ids in a component and the components by their first id are sorted in ascending order.
+    fn strongly_connected_components(&self) -> Vec<Vec<u32>> {
+        let adjacency = self.adjacency();
+        let mut reverse = vec![Vec::new(); adjacency.len()];
+
+        for (from, successors) in adjacency.iter().enumerate() {
+            for to in successors {
+                reverse[*to as usize].push(from as u32);
+            }
+        }
+
+        let mut ids: Vec<u32> = self.node_entries().map(|(_, id)| id).collect();
+        ids.sort_unstable();
+
+        // Order the nodes by the time their depth-first search finished
+        let mut visited = vec![false; adjacency.len()];
+        let mut order = Vec::with_capacity(ids.len());
+
+        for id in &ids {
+            if visited[*id as usize] {
+                continue;
+            }
+
+            visited[*id as usize] = true;
+            let mut stack = vec![(*id, 0)];
+
+            while let Some((node, child)) = stack.last_mut() {
+                match adjacency[*node as usize].get(*child) {
+                    Some(next) => {
+                        *child += 1;
+
+                        if !visited[*next as usize] {
+                            visited[*next as usize] = true;
+                            stack.push((*next, 0));
+                        }
+                    },
+                    None => {
+                        order.push(*node);
+                        stack.pop();
+                    },
+                }
+            }
+        }
+
+        // Collect the components on the reverse graph in reverse finishing order
+        let mut assigned = vec![false; adjacency.len()];
+        let mut components = Vec::new();
+
+        for id in order.into_iter().rev() {
+            if assigned[id as usize] {
+                continue;
+            }
+
+            assigned[id as usize] = true;
+            let mut component = Vec::new();
+            let mut stack = vec![id];
+
+            while let Some(node) = stack.pop() {
+                component.push(node);
+
+                for prev in &reverse[node as usize] {
+                    if !assigned[*prev as usize] {
+                        assigned[*prev as usize] = true;
+                        stack.push(*prev);
+                    }
+                }
+            }
+
+            component.sort_unstable();
+            components.push(component);
+        }
+
+        components.sort_unstable_by_key(|component| component[0]);
+        components
+    }
+
+    /// Returns the ids of all nodes without outgoing edges in ascending order
+    fn dead_ends(&self) -> Vec<u32> {
+        let adjacency = self.adjacency();
+        let mut dead_ends: Vec<u32> = self.node_entries().map(|(_, id)| id).filter(|id| adjacency[*id as usize].is_empty()).collect();
+        dead_ends.sort_unstable();
+        dead_ends
+    }
+
+    /// Record the last `n` states of the path as an n-gram, if there are that many since the start of the run
+    fn add_ngram(&mut self, n: usize) {
+        if self.path.len() - self.window_start < n {
+            return;
+        }
+
+        let ngram = &self.path[self.path.len() - n..];
+
+        if !self.ngrams.contains(ngram) {
+            self.ngrams.insert(ngram.to_vec());
+            self.new_ngrams = true;
+        }
+    }
+
+    /// Add the states and transitions of another state-graph without counting them as hits
+    /// or as new in the current run. `edges` refer to the indices in `nodes`.
+    /// Returns the number of nodes and edges that were added.
+    fn merge(&mut self, nodes: &[PS], edges: &[(u32, u32, u64)]) -> (usize, usize) {
+        let num_nodes = self.num_nodes();
+        let mut new_edges = 0;
+
+        let ids: Vec<u32> = nodes
+            .iter()
+            .map(|state| match self.node_id(state) {
+                Some(id) => id,
+                None => self.insert_node(state),
+            })
+            .collect();
+
+        for (from, to, hits) in edges {
+            let (from, to) = match (ids.get(*from as usize), ids.get(*to as usize)) {
+                (Some(from), Some(to)) if from != to => (*from, *to),
+                _ => continue,
+            };
+
+            if let Entry::Vacant(entry) = self.edges.entry(pack_transition(from, to)) {
+                entry.insert(std::cmp::max(*hits, 1));
+                new_edges += 1;
+            }
+        }
+
+        (self.num_nodes() - num_nodes, new_edges)
+    }
+
+    fn get_state(&self, id: u32) -> Option<&PS> {
+        match &self.hashed {
+            Some(hashed) => hashed.states.get(id as usize).and_then(Option::as_ref),
+            None => self.nodes.iter().find(|(_, node)| **node == id).map(|(state, _)| state),
+        }
     }
 
-    fn write_dot<S>(&self, stream: &mut S)
+    fn write_dot<S>(&self, stream: &mut S, snapshots: Option<&HashMap<u32, String>>, label: fn(&PS) -> String, mark: &dyn Fn(&PS) -> Option<StateMark>)
     where
         S: Write,
     {
         let _ = write!(stream, "digraph IMPLEMENTED_STATE_MACHINE {{");
 
-        for value in &self.edges {
+        let mut nodes: Vec<(u32, &PS)> = self.node_entries().map(|(state, id)| (id, state)).collect();
+        nodes.sort_unstable_by_key(|(id, _)| *id);
+
+        for (id, state) in nodes {
+            let _ = write!(stream, "\"{}\"[label=\"{}\"", id, escape_dot(&label(state)));
+
+            if let Some(snapshot) = snapshots.and_then(|snapshots| snapshots.get(&id)) {
+                let _ = write!(stream, ",tooltip=\"{}\"", escape_dot(snapshot));
+            }
+
+            match mark(state) {
+                Some(StateMark::Error) => {
+                    let _ = write!(stream, ",shape=octagon,color=red");
+                },
+                Some(StateMark::Terminal) => {
+                    let _ = write!(stream, ",shape=doublecircle");
+                },
+                None => {},
+            }
+
+            let _ = write!(stream, "];");
+        }
+
+        for value in self.edges.keys() {
             let (from, to) = unpack_transition(*value);
-            let _ = write!(stream, "\"{}\"->\"{}\";", from, to);
+            let _ = write!(stream, "\"{}\"->\"{}\"", from, to);
+
+            if let Some(contexts) = self.contexts.get(value) {
+                let _ = write!(stream, "[label=\"{}\"]", escape_dot(&join_contexts(contexts)));
+            }
+
+            let _ = write!(stream, ";");
         }
 
         let _ = write!(stream, "}}");
     }
+
+    fn write_graphml<S>(&self, stream: &mut S, label: fn(&PS) -> String, mark: &dyn Fn(&PS) -> Option<StateMark>)
+    where
+        S: Write,
+    {
+        let _ = writeln!(stream, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>");
+        let _ = writeln!(stream, "<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">");
+        let _ = writeln!(stream, "  <key id=\"label\" for=\"node\" attr.name=\"label\" attr.type=\"string\"/>");
+        let _ = writeln!(stream, "  <key id=\"mark\" for=\"node\" attr.name=\"mark\" attr.type=\"string\"/>");
+        let _ = writeln!(stream, "  <key id=\"hits\" for=\"edge\" attr.name=\"hits\" attr.type=\"long\"/>");
+        let _ = writeln!(stream, "  <key id=\"context\" for=\"edge\" attr.name=\"context\" attr.type=\"string\"/>");
+        let _ = writeln!(stream, "  <graph id=\"stategraph\" edgedefault=\"directed\">");
+
+        let mut nodes: Vec<(u32, &PS)> = self.node_entries().map(|(state, id)| (id, state)).collect();
+        nodes.sort_unstable_by_key(|(id, _)| *id);
+
+        for (id, state) in nodes {
+            let _ = write!(stream, "    <node id=\"n{}\"><data key=\"label\">{}</data>", id, escape_xml(&label(state)));
+
+            if let Some(mark) = mark(state) {
+                let _ = write!(stream, "<data key=\"mark\">{}</data>", mark.name());
+            }
+
+            let _ = writeln!(stream, "</node>");
+        }
+
+        let mut edges: Vec<(&u64, &u64)> = self.edges.iter().collect();
+        edges.sort_unstable();
+
+        for (transition, hits) in edges {
+            let (from, to) = unpack_transition(*transition);
+            let _ = write!(stream, "    <edge source=\"n{}\" target=\"n{}\"><data key=\"hits\">{}</data>", from, to, hits);
+
+            if let Some(contexts) = self.contexts.get(transition) {
+                let _ = write!(stream, "<data key=\"context\">{}</data>", escape_xml(&join_contexts(contexts)));
+            }
+
+            let _ = writeln!(stream, "</edge>");
+        }
+
+        let _ = writeln!(stream, "  </graph>");
+        let _ = writeln!(stream, "</graphml>");
+    }
+
+    fn write_mermaid<S>(&self, stream: &mut S, label: fn(&PS) -> String, mark: &dyn Fn(&PS) -> Option<StateMark>)
+    where
+        S: Write,
+    {
+        let _ = writeln!(stream, "flowchart LR");
+
+        let mut nodes: Vec<(u32, &PS, Option<StateMark>)> = self.node_entries().map(|(state, id)| (id, state, mark(state))).collect();
+        nodes.sort_unstable_by_key(|(id, _, _)| *id);
+
+        if nodes.iter().any(|(_, _, mark)| mark.is_some()) {
+            let _ = writeln!(stream, "    classDef error stroke:#d00,stroke-width:2px");
+            let _ = writeln!(stream, "    classDef terminal stroke-width:4px");
+        }
+
+        for (id, state, mark) in nodes {
+            match mark {
+                Some(mark) => {
+                    let _ = writeln!(stream, "    s{}[\"{}\"]:::{}", id, escape_mermaid(&label(state)), mark.name());
+                },
+                None => {
+                    let _ = writeln!(stream, "    s{}[\"{}\"]", id, escape_mermaid(&label(state)));
+                },
+            }
+        }
+
+        let mut edges: Vec<(u32, u32)> = self.edges.keys().map(|transition| unpack_transition(*transition)).collect();
+        edges.sort_unstable();
+
+        for (from, to) in edges {
+            let _ = writeln!(stream, "    s{} --> s{}", from, to);
+        }
+    }
+
+    fn write_mealy<S>(&self, stream: &mut S)
+    where
+        S: Write,
+    {
+        let mut states = vec![None; self.node_hits.len()];
+        for (state, id) in self.node_entries() {
+            states[id as usize] = Some(state);
+        }
+
+        let mut transitions: Vec<(u32, u32, &str)> = self.labels.iter().flat_map(|(transition, labels)| labels.iter().map(move |label| (unpack_transition(*transition), label.as_str()))).map(|((from, to), label)| (from, to, label)).collect();
+        transitions.extend(self.edges.keys().map(|transition| unpack_transition(*transition)).filter(|transition| !self.labels.contains_key(&pack_transition(transition.0, transition.1))).map(|(from, to)| (from, to, NO_LABEL)));
+        transitions.sort_unstable();
+
+        let _ = writeln!(stream, "digraph mealy {{");
+        let _ = writeln!(stream, "__start0 [label=\"\", shape=none];");
+        let _ = writeln!(stream, "init [label=\"init\"];");
+
+        for id in 0..states.len() {
+            let _ = writeln!(stream, "s{} [label=\"s{}\"];", id, id);
+        }
+
+        let _ = writeln!(stream, "__start0 -> init [label=\"\"];");
+
+        for (from, to, label) in transitions {
+            let output = escape_dot(&format!("{:?}", states[to as usize].unwrap()));
+            let label = escape_dot(label);
+
+            if from == ENTRY_NODE {
+                let _ = writeln!(stream, "init -> s{} [label=\"{}/{}\"];", to, label, output);
+            } else {
+                let _ = writeln!(stream, "s{} -> s{} [label=\"{}/{}\"];", from, to, label, output);
+            }
+        }
+
+        let _ = writeln!(stream, "}}");
+    }
 }
 
-/// An observer that builds a state-graph.
-///
-/// The states that this observer stores must implement
-/// the following traits: [`Eq`](core::cmp::Eq), [`Hash`](std::hash::Hash), [`Debug`](core::fmt::Debug), [`Clone`](core::clone::Clone), [`Serialize`](serde::Serialize), [`Deserialize`](serde::Deserialize).
-/// Most commonly used state types are u64, u32 or [u8; N] with N <= 32.
-///
-/// When you create a StateObserver always specify `PS` manually:
-/// ```
-/// type State = u64;
-/// let observer = StateObserver::<State>::new("state observer");
-/// ```
-///
-/// The executor is responsible for calling [`StateObserver::record()`](crate::StateObserver::record)
-/// with states inferred from the fuzz target.
+/// Logs when states and transitions were discovered into a CSV file
 #[derive(Debug, Serialize, Deserialize)]
-#[serde(bound = "PS: serde::Serialize + for<'a> serde::Deserialize<'a>")]
-pub struct StateObserver<PS>
-where
-    PS: Clone + Debug + Eq + Hash,
-{
-    name: String,
-    graph: StateGraph<PS>,
+struct StateTiming {
+    path: PathBuf,
+    #[serde(skip)]
+    file: Option<File>,
+    #[serde(skip)]
+    start: Option<Instant>,
+    /// Seconds of the last entry of a file from a previous process, so that the time keeps counting up
+    #[serde(skip)]
+    offset: f64,
+    #[serde(skip)]
+    failed: bool,
 }
 
-impl<PS> StateObserver<PS>
-where
-    PS: Clone + Debug + Eq + Hash + Serialize + for<'a> Deserialize<'a>,
-{
-    /// Create a new StateObserver with a given name.
-    pub fn new(name: &str) -> Self {
+impl StateTiming {
+    fn new(path: &Path) -> Self {
         Self {
-            name: name.to_string(),
-            graph: StateGraph::<PS>::new(),
+            path: path.to_path_buf(),
+            file: None,
+            start: Some(Instant::now()),
+            offset: 0.0,
+            failed: false,
         }
     }
 
-    /// Tell the observer that the target has entered state `state`.
-    pub fn record(&mut self, state: &PS) {
-        let node = self.graph.add_node(state);
-        self.graph.add_edge(node);
-    }
+    /// Open the file for appending. A new file gets a header, the entries of an existing one
+    /// determine where the time continues.
+    fn open(&mut self) -> std::io::Result<File> {
+        use std::io::Write;
 
-    /// Returns whether any new edges were created in the state-graph during the last run.
-    /// Used by [`StateFeedback`](crate::StateFeedback).
-    pub fn had_new_transitions(&self) -> bool {
-        self.graph.new_transitions
-    }
+        let existing = std::fs::read_to_string(&self.path).unwrap_or_default();
+        let mut file = OpenOptions::new().append(true).create(true).open(&self.path)?;
 
-    /// Returns the number of vertices and edges in the state-graph.
-    /// Used by [`StateFeedback`](crate::StateFeedback).
-    pub fn info(&self) -> (usize, usize) {
-        (self.graph.nodes.len(), self.graph.edges.len())
+        if existing.is_empty() {
+            writeln!(file, "kind,from,to,executions,seconds,state")?;
+        } else {
+            self.offset = existing.lines().last().and_then(|line| line.split(',').nth(4)?.parse().ok()).unwrap_or(0.0);
+        }
+
+        Ok(file)
     }
 
-    /// Returns a DOT representation of the statemachine.
-    pub fn get_statemachine(&self) -> String {
-        let mut s = String::with_capacity(1024);
-        self.graph.write_dot(&mut s);
-        s
+    fn log(&mut self, kind: &str, from: u32, to: Option<u32>, executions: u64, state: &str) {
+        use std::io::Write;
+
+        if self.failed {
+            return;
+        }
+
+        if self.file.is_none() {
+            match self.open() {
+                Ok(file) => {
+                    self.file = Some(file);
+                },
+                Err(err) => {
+                    println!("[butterfly] Could not open {}: {}", self.path.display(), err);
+                    self.failed = true;
+                    return;
+                },
+            }
+        }
+
+        let seconds = self.offset + self.start.get_or_insert_with(Instant::now).elapsed().as_secs_f64();
+        let to = to.map(|to| to.to_string()).unwrap_or_default();
+        let _ = writeln!(self.file.as_mut().unwrap(), "{},{},{},{},{:.3},\"{}\"", kind, from, to, executions, seconds, state.replace('"', "\"\""));
     }
 }
 
-impl<PS> Named for StateObserver<PS>
-where
-    PS: Clone + Debug + Hash + Eq + Serialize + for<'a> Deserialize<'a>,
-{
-    fn name(&self) -> &str {
-        &self.name
-    }
+/// Captures the tail of a log file of the target whenever a new state is discovered
+#[derive(Debug, Serialize, Deserialize)]
+struct LogCapture {
+    path: PathBuf,
+    max_bytes: usize,
+    /// Captured log tails by the id of the state in the exact state-graph
+    snapshots: HashMap<u32, String>,
 }
 
-impl<PS, I, S> Observer<I, S> for StateObserver<PS>
-where
-    PS: Clone + Debug + Hash + Eq + Serialize + for<'a> Deserialize<'a>,
-{
-    fn pre_exec(&mut self, _state: &mut S, _input: &I) -> Result<(), Error> {
-        self.graph.reset();
-        Ok(())
+impl LogCapture {
+    fn new(path: &Path, max_bytes: usize) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            max_bytes,
+            snapshots: HashMap::new(),
+        }
+    }
+
+    /// Returns the last `max_bytes` of the log, starting at a line boundary.
+    /// A log that doesn't exist (yet) is empty.
+    fn tail(&self) -> Option<String> {
+        use std::io::{Read, Seek, SeekFrom};
+
+        let mut file = File::open(&self.path).ok()?;
+        let len = file.metadata().ok()?.len();
+        let start = len.saturating_sub(self.max_bytes as u64);
+        let mut content = Vec::with_capacity(self.max_bytes);
+        file.seek(SeekFrom::Start(start)).ok()?;
+        file.take(self.max_bytes as u64).read_to_end(&mut content).ok()?;
+
+        // Don't start in the middle of a line
+        if start > 0 {
+            let newline = content.iter().position(|byte| *byte == b'\n').map(|idx| idx + 1).unwrap_or(0);
+            content.drain(..newline);
+        }
+
+        Some(String::from_utf8_lossy(&content).into_owned())
+    }
+
+    fn capture(&mut self, id: u32) {
+        // Ids of evicted states are reused, so never keep the snapshot of a previous state
+        match self.tail() {
+            Some(tail) => self.snapshots.insert(id, tail),
+            None => self.snapshots.remove(&id),
+        };
+    }
+}
+
+/// Observers that need to know when the target process is restarted in the middle of a campaign.
+///
+/// Persistent targets are usually recycled by a process manager every few thousand executions or after
+/// they crashed. If this happens during an execution, the state the target was in before the restart has
+/// nothing to do with the state it is in afterwards. Whoever restarts the target, e.g. the harness
+/// behind the `FfiExecutor` of the `ffi` module or a custom executor, should call [`on_target_restart()`](HasTargetRestart::on_target_restart)
+/// on the affected observers, for example with [`notify_target_restart()`].
+///
+/// Already implemented for:
+/// - [`StateObserver`]
+pub trait HasTargetRestart {
+    /// The target process has been restarted
+    fn on_target_restart(&mut self);
+}
+
+/// Call [`on_target_restart()`](HasTargetRestart::on_target_restart) on the observer of type `T` with the name `name` in `observers`
+pub fn notify_target_restart<T, OT>(observers: &mut OT, name: &str) -> Result<(), Error>
+where
+    T: HasTargetRestart + 'static,
+    OT: MatchName,
+{
+    let observer = observers.match_name_mut::<T>(name).ok_or_else(|| Error::key_not_found(format!("No observer named \"{}\" with the given type", name)))?;
+    observer.on_target_restart();
+    Ok(())
+}
+
+/// Maps the raw states given to [`StateObserver::record_raw()`] to states of the state-graph.
+/// The raw type is checked at runtime, so that it doesn't show up in the type of the observer.
+struct RawAbstraction<PS>(Box<RawAbstractionFn<PS>>);
+
+type RawAbstractionFn<PS> = dyn Fn(&dyn Any) -> Option<PS>;
+
+impl<PS> Debug for RawAbstraction<PS> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "RawAbstraction")
+    }
+}
+
+/// The shared memory type of [`StateObserver::with_shared_memory()`]
+pub type StateShMem = <StdShMemProvider as ShMemProvider>::ShMem;
+
+/// Kinds of entries in a [`SharedLog`]
+const SHARED_STATE: u8 = 0;
+const SHARED_INPUT_LABEL: u8 = 1;
+const SHARED_TARGET_RESTART: u8 = 2;
+const SHARED_STATE_WITH_CONTEXT: u8 = 3;
+/// Size of the header of a [`SharedLog`]: bytes used and number of dropped entries
+const SHARED_HEADER: usize = 8;
+
+/// Everything a [`StateObserver`] records in a child process, in a shared memory mapping so that
+/// the parent can replay it. See [`StateObserver::with_shared_memory()`] for the layout.
+#[derive(Debug)]
+struct SharedLog {
+    shmem: StateShMem,
+}
+
+impl SharedLog {
+    fn read_u32(&self, offset: usize) -> u32 {
+        let bytes = &self.shmem.as_slice()[offset..offset + 4];
+        u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+    }
+
+    fn write_u32(&mut self, offset: usize, value: u32) {
+        self.shmem.as_mut_slice()[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+    }
+
+    fn clear(&mut self) {
+        if self.shmem.len() >= SHARED_HEADER {
+            self.write_u32(0, 0);
+            self.write_u32(4, 0);
+        }
+    }
+
+    /// Append an entry, returns `false` if it was dropped because the mapping is full
+    fn push(&mut self, kind: u8, payload: &[u8]) -> bool {
+        if self.shmem.len() < SHARED_HEADER {
+            return false;
+        }
+
+        let used = self.read_u32(0) as usize;
+        let start = SHARED_HEADER + used;
+
+        if start + 5 + payload.len() > self.shmem.len() {
+            let dropped = self.read_u32(4);
+            self.write_u32(4, dropped.saturating_add(1));
+            return false;
+        }
+
+        let slice = self.shmem.as_mut_slice();
+        slice[start] = kind;
+        slice[start + 1..start + 5].copy_from_slice(&(payload.len() as u32).to_le_bytes());
+        slice[start + 5..start + 5 + payload.len()].copy_from_slice(payload);
+        self.write_u32(0, (used + 5 + payload.len()) as u32);
+        true
+    }
+
+    /// Returns all entries and the number of dropped entries
+    fn entries(&self) -> (Vec<(u8, Vec<u8>)>, u32) {
+        if self.shmem.len() < SHARED_HEADER {
+            return (Vec::new(), 0);
+        }
+
+        let slice = self.shmem.as_slice();
+        let end = (SHARED_HEADER + self.read_u32(0) as usize).min(slice.len());
+        let mut entries = Vec::new();
+        let mut offset = SHARED_HEADER;
+
+        while offset + 5 <= end {
+            let len = self.read_u32(offset + 1) as usize;
+
+            if offset + 5 + len > end {
+                break;
+            }
+
+            entries.push((slice[offset], slice[offset + 5..offset + 5 + len].to_vec()));
+            offset += 5 + len;
+        }
+
+        (entries, self.read_u32(4))
+    }
+}
+
+/// The exact state-graph of a [`StateObserver`] in the format of [`StateObserver::export_graph()`]
+#[derive(Serialize, Deserialize)]
+#[serde(bound = "PS: serde::Serialize + for<'a> serde::Deserialize<'a>")]
+struct GraphExport<PS> {
+    /// The states by id
+    nodes: Vec<PS>,
+    /// The transitions with their hit counts
+    edges: Vec<(u32, u32, u64)>,
+}
+
+/// What a [`StateObserver`] does with a new state when its state-graph is full,
+/// see [`StateObserver::with_node_budget()`]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum NodeBudgetPolicy<PS> {
+    /// Don't record new states at all. The state-graph stops growing, transitions between known states are still recorded.
+    Ignore,
+    /// Record this catch-all state instead of every new state. It gets a node even if the state-graph is full.
+    Merge(PS),
+    /// Make room by removing the state that was entered least often, together with its transitions.
+    /// States of the current run are never removed. Takes time linear in the size of the state-graph for every new state.
+    EvictColdest,
+}
+
+/// What a [`StateObserver`] forgets before every execution, see [`StateObserver::with_reset_mode()`]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ResetMode {
+    /// Every execution starts from scratch, its first state is an entry state
+    #[default]
+    Full,
+    /// Keep the last state of the previous execution, so that the transition from it to the first state
+    /// of the next execution is recorded. For harnesses that keep the connection to the target across executions.
+    KeepLastState,
+}
+
+/// How the state-graphs of a [`StateObserver`] find the node of a state, see [`StateObserver::with_node_storage()`]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum NodeStorage {
+    /// Look the nodes up by their states
+    #[default]
+    Exact,
+    /// Look the nodes up by a 64-bit hash of their states. Without `check_collisions` two states with
+    /// the same hash share a node, with it every lookup additionally compares the state of the node.
+    Hashed {
+        /// Give states with the same hash separate nodes
+        check_collisions: bool,
+    },
+}
+
+/// The nodes of a state-graph with [`NodeStorage::Hashed`]
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(bound = "PS: Eq + Hash + serde::Serialize + for<'a> serde::Deserialize<'a>")]
+struct HashedNodes<PS> {
+    /// Node ids by the hash of their state
+    ids: HashMap<u64, u32, RandomState>,
+    /// States by node id, `None` for evicted nodes
+    states: Vec<Option<PS>>,
+    /// Node ids of states whose hash belongs to another state, if collisions are checked
+    collisions: HashMap<PS, u32, RandomState>,
+    check_collisions: bool,
+    len: usize,
+}
+
+impl<PS> HashedNodes<PS>
+where
+    PS: Clone + Eq + Hash,
+{
+    fn new(check_collisions: bool) -> Self {
+        Self {
+            ids: HashMap::<u64, u32, RandomState>::default(),
+            states: Vec::new(),
+            collisions: HashMap::<PS, u32, RandomState>::default(),
+            check_collisions,
+            len: 0,
+        }
+    }
+
+    /// The hash must be the same in every process, so that saved and merged state-graphs stay valid
+    fn hash(state: &PS) -> u64 {
+        let mut hasher = AHasher::default();
+        state.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    fn get(&self, state: &PS) -> Option<u32> {
+        let id = self.ids.get(&Self::hash(state)).copied();
+
+        if !self.check_collisions {
+            return id;
+        }
+
+        match id {
+            Some(id) if self.states[id as usize].as_ref() == Some(state) => Some(id),
+            _ if self.collisions.is_empty() => None,
+            _ => self.collisions.get(state).copied(),
+        }
+    }
+
+    fn insert(&mut self, state: &PS, id: u32) {
+        match self.ids.entry(Self::hash(state)) {
+            Entry::Vacant(entry) => {
+                entry.insert(id);
+            },
+            Entry::Occupied(_) => {
+                self.collisions.insert(state.clone(), id);
+            },
+        }
+
+        if self.states.len() <= id as usize {
+            self.states.resize(id as usize + 1, None);
+        }
+
+        self.states[id as usize] = Some(state.clone());
+        self.len += 1;
+    }
+
+    fn remove(&mut self, state: &PS, id: u32) {
+        let hash = Self::hash(state);
+
+        if self.ids.get(&hash) == Some(&id) {
+            self.ids.remove(&hash);
+        } else {
+            self.collisions.remove(state);
+        }
+
+        self.states[id as usize] = None;
+        self.len -= 1;
+    }
+}
+
+/// Marks states of a [`StateObserver`] in which the conversation with the target is effectively over,
+/// see [`StateObserver::mark_state()`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum StateMark {
+    /// The target rejected the input, e.g. with an FTP reply code 5xx
+    Error,
+    /// The target can't continue from this state, e.g. because it closed the connection
+    Terminal,
+}
+
+impl StateMark {
+    fn name(&self) -> &'static str {
+        match self {
+            StateMark::Error => "error",
+            StateMark::Terminal => "terminal",
+        }
+    }
+}
+
+/// Structural metrics of a state-graph, see [`StateObserver::metrics()`]
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GraphMetrics {
+    /// Number of states
+    pub nodes: usize,
+    /// Number of transitions
+    pub edges: usize,
+    /// See [`StateObserver::depth()`]
+    pub depth: usize,
+    /// Number of strongly connected components, see [`StateObserver::strongly_connected_components()`]
+    pub components: usize,
+    /// Number of states without outgoing transitions, see [`StateObserver::dead_ends()`]
+    pub dead_ends: usize,
+}
+
+/// The maximum number of nodes of the state-graphs of a [`StateObserver`] and what happens when it is reached
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct NodeBudget<PS> {
+    max_nodes: usize,
+    policy: NodeBudgetPolicy<PS>,
+}
+
+/// Buckets the response times given to [`StateObserver::record_with_latency()`] and folds the bucket into the state
+#[derive(Clone, Debug)]
+struct LatencyBuckets<PS> {
+    /// Upper bounds of all buckets but the last one, in ascending order
+    bounds: Vec<Duration>,
+    combine: fn(&PS, usize) -> PS,
+}
+
+impl<PS> LatencyBuckets<PS> {
+    fn bucket(&self, latency: Duration) -> usize {
+        self.bounds.partition_point(|bound| *bound <= latency)
+    }
+}
+
+/// Decides when a [`StateObserver`] switches between its abstraction levels
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+struct AbstractionSwitching {
+    stall_execs: usize,
+    max_nodes: usize,
+}
+
+/// An observer that builds a state-graph.
+///
+/// The states that this observer stores must implement
+/// the following traits: [`Eq`](core::cmp::Eq), [`Hash`](std::hash::Hash), [`Debug`](core::fmt::Debug), [`Clone`](core::clone::Clone), [`Serialize`](serde::Serialize), [`Deserialize`](serde::Deserialize).
+/// Most commonly used state types are u64, u32 or [u8; N] with N <= 32.
+///
+/// When you create a StateObserver always specify `PS` manually:
+/// ```
+/// type State = u64;
+/// let observer = StateObserver::<State>::new("state observer");
+/// ```
+///
+/// The executor is responsible for calling [`StateObserver::record()`](crate::StateObserver::record)
+/// with states inferred from the fuzz target.
+///
+/// # Abstraction levels
+/// Besides the exact states the observer can build state-graphs of coarser abstractions
+/// of the states, for example the class of a status code (2xx, 4xx, 5xx) instead of the code itself.
+/// All levels are recorded in every run but only the active level
+/// is reported to the [`StateFeedback`](crate::StateFeedback).
+/// Level 0 is always the exact state, every call to [`with_abstraction_level()`](StateObserver::with_abstraction_level)
+/// adds a coarser level on top.
+/// ```
+/// let observer = StateObserver::<u32>::new("state")
+///     .with_abstraction_level(|code| code / 100 * 100)
+///     .with_abstraction_switching(10000, 256);
+/// ```
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(bound = "PS: serde::Serialize + for<'a> serde::Deserialize<'a>")]
+pub struct StateObserver<PS>
+where
+    PS: Clone + Debug + Eq + Hash,
+{
+    name: String,
+    graphs: Vec<StateGraph<PS>>,
+    #[serde(skip)]
+    abstractions: Vec<fn(&PS) -> PS>,
+    level: usize,
+    switching: Option<AbstractionSwitching>,
+    stalled_execs: usize,
+    timing: Option<StateTiming>,
+    log_capture: Option<LogCapture>,
+    executions: u64,
+    #[serde(skip)]
+    input_label: Option<String>,
+    restart_marker: Option<PS>,
+    #[serde(skip)]
+    node_label: Option<fn(&PS) -> String>,
+    #[serde(skip)]
+    raw_abstraction: Option<RawAbstraction<PS>>,
+    #[serde(skip)]
+    shared: Option<SharedLog>,
+    /// Whether this is the copy of the observer in a child process
+    #[serde(skip)]
+    in_child: bool,
+    #[serde(skip)]
+    warned_dropped: bool,
+    #[serde(default)]
+    ngram_size: Option<usize>,
+    #[serde(default)]
+    budget: Option<NodeBudget<PS>>,
+    #[serde(skip)]
+    warned_budget: bool,
+    #[serde(default)]
+    reset_mode: ResetMode,
+    #[serde(skip)]
+    response_inference: Option<ResponseStateInference<PS>>,
+    #[serde(skip)]
+    latency_buckets: Option<LatencyBuckets<PS>>,
+    #[serde(default)]
+    marks: HashMap<PS, StateMark>,
+    #[serde(skip)]
+    mark_classifier: Option<fn(&PS) -> Option<StateMark>>,
+    #[serde(default)]
+    node_storage: NodeStorage,
+}
+
+impl<PS> StateObserver<PS>
+where
+    PS: Clone + Debug + Eq + Hash + Serialize + for<'a> Deserialize<'a>,
+{
+    /// Create a new StateObserver with a given name.
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            graphs: vec![StateGraph::<PS>::new()],
+            abstractions: Vec::new(),
+            level: 0,
+            switching: None,
+            stalled_execs: 0,
+            timing: None,
+            log_capture: None,
+            executions: 0,
+            input_label: None,
+            restart_marker: None,
+            node_label: None,
+            raw_abstraction: None,
+            shared: None,
+            in_child: false,
+            warned_dropped: false,
+            ngram_size: None,
+            budget: None,
+            warned_budget: false,
+            reset_mode: ResetMode::Full,
+            response_inference: None,
+            latency_buckets: None,
+            marks: HashMap::new(),
+            mark_classifier: None,
+            node_storage: NodeStorage::Exact,
+        }
+    }
+
+    /// Log the wall-clock time and the number of executions at which each state
+    /// and transition of the exact state-graph was discovered into the CSV file `path`.
+    ///
+    /// The file has the columns `kind,from,to,executions,seconds,state`.
+    /// `kind` is either `node` or `edge`. Nodes only have `from`, their id, and `state`.
+    /// Time is counted from the creation of the observer. If the file already exists, e.g. because a restarting
+    /// event manager restarted the client, the entries are appended and the time continues from the last entry.
+    /// When fuzzing with multiple clients give every client its own file.
+    pub fn with_state_timing<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.timing = Some(StateTiming::new(path.as_ref()));
+        self
+    }
+
+    /// Capture the last `max_bytes` of the log file of the target at `path` whenever a new state
+    /// is discovered. This helps to label opaque states, e.g. hashes, with what the target reported at that moment.
+    ///
+    /// The captured logs can be queried with [`log_snapshot()`](StateObserver::log_snapshot) and are attached
+    /// as tooltips to the nodes of the exact state-graph in [`get_statemachine()`](StateObserver::get_statemachine).
+    /// Since the log is read right after the state was recorded, lines that the target writes
+    /// with a delay may be missing.
+    ///
+    /// # Example
+    /// ```
+    /// let observer = StateObserver::<u64>::new("state").with_log_capture("/var/log/target.log", 1024);
+    /// ```
+    pub fn with_log_capture<P: AsRef<Path>>(mut self, path: P, max_bytes: usize) -> Self {
+        self.log_capture = Some(LogCapture::new(path.as_ref(), max_bytes));
+        self
+    }
+
+    /// Returns the log that was captured when the state with the id `id` in the
+    /// exact state-graph was discovered, see [`with_log_capture()`](StateObserver::with_log_capture)
+    pub fn log_snapshot(&self, id: u32) -> Option<&str> {
+        self.log_capture.as_ref()?.snapshots.get(&id).map(String::as_str)
+    }
+
+    /// Record `marker` as the state of the target whenever it is restarted, see [`HasTargetRestart`].
+    ///
+    /// This makes restarts visible in the state-graph as transitions into and out of the marker node.
+    /// Without a marker the state after a restart is treated like the first state of a run.
+    /// The marker is part of the [path](StateObserver::path), so it shifts the positions of all later states.
+    ///
+    /// # Example
+    /// ```
+    /// let observer = StateObserver::<u64>::new("state").with_restart_marker(u64::MAX);
+    /// ```
+    pub fn with_restart_marker(mut self, marker: PS) -> Self {
+        self.restart_marker = Some(marker);
+        self
+    }
+
+    /// Label the nodes in [`get_statemachine()`](StateObserver::get_statemachine) with `label`
+    /// instead of the `Debug` representation of their states, e.g. to decode opaque state values.
+    ///
+    /// # Example
+    /// ```
+    /// let observer = StateObserver::<u32>::new("state").with_node_labels(|code| format!("FTP {}", code));
+    /// ```
+    pub fn with_node_labels(mut self, label: fn(&PS) -> String) -> Self {
+        self.node_label = Some(label);
+        self
+    }
+
+    /// Let the executor record raw states of type `RS` with [`record_raw()`](StateObserver::record_raw)
+    /// that `abstraction` turns into the states of the state-graph.
+    ///
+    /// Raw states are often too fine-grained, e.g. they contain counters or timestamps, and every one of them
+    /// would become a new node. With an abstraction the executor can pass on whatever it gets from the target
+    /// and the observer decides what is relevant.
+    ///
+    /// # Example
+    /// ```
+    /// struct Session {
+    ///     logged_in: bool,
+    ///     messages: u64,
+    /// }
+    ///
+    /// let observer = StateObserver::<bool>::new("state").with_raw_states(|session: &Session| session.logged_in);
+    /// ```
+    pub fn with_raw_states<RS, F>(mut self, abstraction: F) -> Self
+    where
+        RS: 'static,
+        F: Fn(&RS) -> PS + 'static,
+    {
+        self.raw_abstraction = Some(RawAbstraction(Box::new(move |raw: &dyn Any| raw.downcast_ref::<RS>().map(&abstraction))));
+        self
+    }
+
+    /// Let the executor pass on the responses of the target with [`record_response()`](StateObserver::record_response)
+    /// and derive the states from them with `inference`.
+    ///
+    /// # Example
+    /// ```
+    /// let observer = StateObserver::<u32>::new("state").with_response_inference(ResponseStateInference::status_code());
+    /// ```
+    pub fn with_response_inference(mut self, inference: ResponseStateInference<PS>) -> Self {
+        self.response_inference = Some(inference);
+        self
+    }
+
+    /// Collect the states that are recorded in a child process in the shared memory mapping `shmem`,
+    /// so that they reach the state-graph in the parent, e.g. with LibAFLs `InProcessForkExecutor`.
+    ///
+    /// In the child, the observer only appends the states, input labels and restarts to the mapping.
+    /// After the execution the parent replays them into its state-graph. The mapping has a fixed capacity,
+    /// entries that don't fit are dropped and reported once.
+    ///
+    /// Targets behind a forkserver can write the mapping directly. It starts with two little-endian `u32`s,
+    /// the number of bytes of entries that follow and the number of dropped entries. Every entry consists of a kind byte,
+    /// the length of the payload as a little-endian `u32` and the payload. Kind 0 is a state serialized with
+    /// [postcard](https://docs.rs/postcard), kind 1 an input label as UTF-8 (see [`record_input()`](StateObserver::record_input)),
+    /// kind 2 a restart of the target without payload (see [`HasTargetRestart`]) and kind 3 a state like kind 0
+    /// preceded by the index of the packet as a little-endian `u32` (see [`record_with_context()`](StateObserver::record_with_context)).
+    ///
+    /// # Example
+    /// ```
+    /// let shmem = StdShMemProvider::new()?.new_shmem(64 * 1024)?;
+    /// let observer = StateObserver::<u64>::new("state").with_shared_memory(shmem);
+    /// ```
+    pub fn with_shared_memory(mut self, shmem: StateShMem) -> Self {
+        let mut shared = SharedLog {
+            shmem,
+        };
+        shared.clear();
+        self.shared = Some(shared);
+        self
+    }
+
+    /// Append an entry to the shared memory if this is the copy of the observer in a child process.
+    /// Returns whether the entry was handled this way.
+    fn push_shared(&mut self, kind: u8, payload: &[u8]) -> bool {
+        match (&mut self.shared, self.in_child) {
+            (Some(shared), true) => {
+                shared.push(kind, payload);
+                true
+            },
+            _ => false,
+        }
+    }
+
+    /// Replay what the child process recorded in the shared memory
+    fn replay_shared(&mut self) {
+        let (entries, dropped) = match &self.shared {
+            Some(shared) => shared.entries(),
+            None => return,
+        };
+
+        for (kind, payload) in entries {
+            match kind {
+                SHARED_STATE => match postcard::from_bytes::<PS>(&payload) {
+                    Ok(state) => self.record(&state),
+                    Err(err) => println!("[butterfly] Could not decode a state from shared memory: {}", err),
+                },
+                SHARED_INPUT_LABEL => self.record_input(&String::from_utf8_lossy(&payload)),
+                SHARED_TARGET_RESTART => self.on_target_restart(),
+                SHARED_STATE_WITH_CONTEXT if payload.len() >= 4 => match postcard::from_bytes::<PS>(&payload[4..]) {
+                    Ok(state) => self.record_with_context(&state, u32::from_le_bytes(payload[..4].try_into().unwrap()) as usize),
+                    Err(err) => println!("[butterfly] Could not decode a state from shared memory: {}", err),
+                },
+                _ => {},
+            }
+        }
+
+        if dropped > 0 && !self.warned_dropped {
+            println!("[butterfly] The shared memory of StateObserver \"{}\" is too small, {} entries were dropped", self.name, dropped);
+            self.warned_dropped = true;
+        }
+
+        if let Some(shared) = &mut self.shared {
+            shared.clear();
+        }
+    }
+
+    /// Decide what the observer forgets before every execution, [`ResetMode::Full`] by default.
+    ///
+    /// With [`ResetMode::KeepLastState`] the first state of an execution is connected to the last state of the previous one,
+    /// which reflects the target if the harness keeps a persistent connection across executions.
+    /// A [restart](HasTargetRestart) of the target still starts over.
+    ///
+    /// # Example
+    /// ```
+    /// let observer = StateObserver::<u32>::new("state").with_reset_mode(ResetMode::KeepLastState);
+    /// ```
+    pub fn with_reset_mode(mut self, mode: ResetMode) -> Self {
+        self.reset_mode = mode;
+        self
+    }
+
+    /// Additionally track the sequences of the last `n` states that the target went through, with `n` at least 2.
+    ///
+    /// The state-graph only stores which transitions exist, so it cannot tell `A -> B -> A -> C` from `A -> C`
+    /// once all transitions are known. With n-grams every window of `n` consecutive states counts as coverage,
+    /// so the [`StateFeedback`](crate::StateFeedback) also rewards inputs that take known transitions in a new order.
+    /// Larger values of `n` distinguish longer histories but let the number of n-grams grow quickly.
+    /// Self-loops are part of the windows and a [restart](HasTargetRestart) starts a new window.
+    ///
+    /// # Example
+    /// ```
+    /// let observer = StateObserver::<u32>::new("state").with_ngrams(3);
+    /// ```
+    pub fn with_ngrams(mut self, n: usize) -> Self {
+        self.ngram_size = Some(n.max(2));
+        self
+    }
+
+    /// Limit every state-graph of the observer to `max_nodes` states and decide with `policy` what happens to new states
+    /// once the limit is reached, instead of letting the state-graph grow without bound if the states are derived from noisy data.
+    ///
+    /// A warning is printed the first time the limit is reached. [`budget_overflows()`](StateObserver::budget_overflows)
+    /// counts how often it happened and the [`StateFeedback`](crate::StateFeedback) reports it with the user stat
+    /// [`USER_STAT_BUDGET_OVERFLOWS`](crate::USER_STAT_BUDGET_OVERFLOWS). State-graphs of other clients that are
+    /// [merged](StateObserver::merge_graph) are not limited.
+    ///
+    /// # Example
+    /// ```
+    /// let observer = StateObserver::<u64>::new("state").with_node_budget(4096, NodeBudgetPolicy::EvictColdest);
+    /// ```
+    pub fn with_node_budget(mut self, max_nodes: usize, policy: NodeBudgetPolicy<PS>) -> Self {
+        self.budget = Some(NodeBudget {
+            max_nodes,
+            policy,
+        });
+        self
+    }
+
+    /// Decide how the state-graphs find the node of a state, [`NodeStorage::Exact`] by default.
+    ///
+    /// For large states like `[u8; 64]` or long strings [`NodeStorage::Hashed`] is cheaper: every
+    /// [`record()`](StateObserver::record) looks up a 64-bit hash instead of comparing whole states and
+    /// the states are stored densely by id. Unless collisions are checked, states with the same hash are merged,
+    /// which is unlikely to matter for a few million states.
+    ///
+    /// # Example
+    /// ```
+    /// let observer = StateObserver::<[u8; 64]>::new("state").with_node_storage(NodeStorage::Hashed { check_collisions: false });
+    /// ```
+    pub fn with_node_storage(mut self, storage: NodeStorage) -> Self {
+        self.node_storage = storage;
+
+        for graph in &mut self.graphs {
+            graph.set_storage(storage);
+        }
+
+        self
+    }
+
+    /// Returns how often a new state did not fit into the state-graph of the active abstraction level,
+    /// see [`with_node_budget()`](StateObserver::with_node_budget)
+    pub fn budget_overflows(&self) -> u64 {
+        self.graph().overflows
+    }
+
+    /// Make the response time of the target part of the state, so that a known state that suddenly takes much
+    /// longer to reach, e.g. because the target entered a retry loop or a backoff, counts as a new state.
+    ///
+    /// The latencies given to [`record_with_latency()`](StateObserver::record_with_latency) are sorted into buckets
+    /// separated by `bounds`: bucket 0 holds everything below the first bound and bucket `bounds.len()` everything
+    /// from the last bound on. `combine` folds the bucket into the state before it is recorded.
+    /// Keep the buckets coarse, every bucket can multiply the number of states.
+    ///
+    /// # Example
+    /// ```
+    /// let observer = StateObserver::<u32>::new("state").with_latency_buckets(&[Duration::from_millis(10), Duration::from_millis(500)], |code, bucket| code * 10 + bucket as u32);
+    ///
+    /// // in the executor
+    /// let start = Instant::now();
+    /// let response = send(packet)?;
+    /// observer.record_with_latency(&infer_state(&response), start.elapsed());
+    /// ```
+    pub fn with_latency_buckets(mut self, bounds: &[Duration], combine: fn(&PS, usize) -> PS) -> Self {
+        let mut bounds = bounds.to_vec();
+        bounds.sort_unstable();
+        bounds.dedup();
+        self.latency_buckets = Some(LatencyBuckets {
+            bounds,
+            combine,
+        });
+        self
+    }
+
+    /// Returns the bucket of `latency`, see [`with_latency_buckets()`](StateObserver::with_latency_buckets),
+    /// or `None` if the observer has no latency buckets
+    pub fn latency_bucket(&self, latency: Duration) -> Option<usize> {
+        self.latency_buckets.as_ref().map(|buckets| buckets.bucket(latency))
+    }
+
+    /// Mark every state for which `classify` returns a [`StateMark`] as an error or a terminal state,
+    /// e.g. all FTP reply codes from 500 on. See [`mark_state()`](StateObserver::mark_state).
+    ///
+    /// # Example
+    /// ```
+    /// let observer = StateObserver::<u32>::new("state").with_state_marks(|code| (*code >= 500).then_some(StateMark::Error));
+    /// ```
+    pub fn with_state_marks(mut self, classify: fn(&PS) -> Option<StateMark>) -> Self {
+        self.mark_classifier = Some(classify);
+        self
+    }
+
+    /// Mark `state` as an error or a terminal state, e.g. a dedicated state that the executor
+    /// records when the target closes the connection. Overrides the marks of [`with_state_marks()`](StateObserver::with_state_marks).
+    ///
+    /// Marked states are rendered differently in [`get_statemachine()`](StateObserver::get_statemachine),
+    /// [`to_mermaid()`](StateObserver::to_mermaid) and [`to_graphml()`](StateObserver::to_graphml), and
+    /// [`first_mark()`](StateObserver::first_mark) tells how quickly a run ended up in one of them,
+    /// so that inputs which immediately dead-end can be deprioritized.
+    /// With abstraction levels the marks apply to the states of the active level.
+    pub fn mark_state(&mut self, state: PS, mark: StateMark) {
+        self.marks.insert(state, mark);
+    }
+
+    /// Returns the mark of `state`, if any
+    pub fn state_mark(&self, state: &PS) -> Option<StateMark> {
+        self.marks.get(state).copied().or_else(|| self.mark_classifier.and_then(|classify| classify(state)))
+    }
+
+    /// Add an abstraction level that is coarser than all previous levels.
+    /// `abstraction` maps an exact state to its abstract state.
+    pub fn with_abstraction_level(mut self, abstraction: fn(&PS) -> PS) -> Self {
+        let mut graph = StateGraph::<PS>::new();
+        graph.set_storage(self.node_storage);
+        self.abstractions.push(abstraction);
+        self.graphs.push(graph);
+        self
+    }
+
+    /// Switch the abstraction level automatically:
+    /// If the active level had no new transitions for `stall_execs` executions
+    /// the next finer level becomes active. If the state-graph of the active level
+    /// has more than `max_nodes` vertices the next coarser level becomes active.
+    pub fn with_abstraction_switching(mut self, stall_execs: usize, max_nodes: usize) -> Self {
+        self.switching = Some(AbstractionSwitching {
+            stall_execs,
+            max_nodes,
+        });
+        self
+    }
+
+    /// Returns the active abstraction level. 0 means exact states.
+    pub fn abstraction_level(&self) -> usize {
+        self.level
+    }
+
+    /// Returns the number of abstraction levels including the exact states
+    pub fn num_abstraction_levels(&self) -> usize {
+        self.graphs.len()
+    }
+
+    /// Change the active abstraction level. Levels that don't exist are ignored.
+    pub fn set_abstraction_level(&mut self, level: usize) {
+        if level < self.graphs.len() {
+            self.level = level;
+            self.stalled_execs = 0;
+        }
+    }
+
+    /// Take over the state-graphs of a deserialized observer, e.g. after the fuzzer restarted.
+    ///
+    /// The abstractions of [`with_abstraction_level()`](StateObserver::with_abstraction_level) are functions
+    /// that can't be serialized, so a deserialized observer with abstraction levels can't be used directly.
+    /// Create the observer as usual and restore the saved one into it. Fails if the number of abstraction levels differs.
+    pub fn restore(&mut self, saved: Self) -> Result<(), Error> {
+        if saved.graphs.len() != self.graphs.len() {
+            return Err(Error::illegal_argument(format!("Expected {} abstraction levels but the saved observer has {}", self.graphs.len(), saved.graphs.len())));
+        }
+
+        self.graphs = saved.graphs;
+        self.level = saved.level;
+        self.stalled_execs = saved.stalled_execs;
+        self.executions = saved.executions;
+
+        if let (Some(log_capture), Some(saved)) = (&mut self.log_capture, saved.log_capture) {
+            log_capture.snapshots = saved.snapshots;
+        }
+
+        Ok(())
+    }
+
+    /// Serialize everything that executions change, so that a dry-run can be [rolled back](StateObserver::rollback)
+    pub(crate) fn snapshot(&self) -> Result<Vec<u8>, Error> {
+        postcard::to_allocvec(self).map_err(|err| Error::serialize(format!("Could not encode the observer: {}", err)))
+    }
+
+    /// Return to the state of a [snapshot](StateObserver::snapshot)
+    pub(crate) fn rollback(&mut self, snapshot: &[u8]) -> Result<(), Error> {
+        let mut saved: Self = postcard::from_bytes(snapshot).map_err(|err| Error::serialize(format!("Could not decode the observer: {}", err)))?;
+        self.budget = saved.budget.take();
+        self.marks = std::mem::take(&mut saved.marks);
+        self.restore(saved)
+    }
+
+    /// Forget the path and the novelty flags of the current execution, so that no feedback considers it interesting.
+    /// The states and transitions it added to the state-graph stay known.
+    pub(crate) fn discard_execution(&mut self) {
+        for graph in &mut self.graphs {
+            graph.reset(self.reset_mode);
+        }
+
+        if let Some(shared) = &mut self.shared {
+            shared.clear();
+        }
+    }
+
+    #[inline]
+    fn graph(&self) -> &StateGraph<PS> {
+        &self.graphs[self.level]
+    }
+
+    /// Apply the switching policy after a run
+    fn switch_abstraction_level(&mut self) {
+        let switching = match self.switching {
+            Some(switching) => switching,
+            None => return,
+        };
+
+        if self.graph().new_transitions {
+            self.stalled_execs = 0;
+        } else {
+            self.stalled_execs += 1;
+        }
+
+        let level = if self.graph().num_nodes() > switching.max_nodes && self.level + 1 < self.graphs.len() {
+            self.level + 1
+        } else if self.stalled_execs >= switching.stall_execs && self.level > 0 && self.graphs[self.level - 1].num_nodes() <= switching.max_nodes {
+            self.level - 1
+        } else {
+            return;
+        };
+
+        println!("[butterfly] Switching from abstraction level {} to {}", self.level, level);
+        self.set_abstraction_level(level);
+    }
+
+    /// Tell the observer which input the target is about to process, e.g. the type of the packet that
+    /// gets sent next. The next transition is labeled with it in the
+    /// [Mealy machine](StateObserver::get_mealy_machine). Labels must not contain `/`.
+    pub fn record_input(&mut self, label: &str) {
+        if self.push_shared(SHARED_INPUT_LABEL, label.as_bytes()) {
+            return;
+        }
+
+        self.input_label = Some(label.to_string());
+    }
+
+    /// Tell the observer that the target has entered the raw state `raw`, which gets turned into a state
+    /// by the abstraction of [`with_raw_states()`](StateObserver::with_raw_states).
+    ///
+    /// Fails if there is no abstraction or `RS` is not the raw type of the abstraction.
+    pub fn record_raw<RS: 'static>(&mut self, raw: &RS) -> Result<(), Error> {
+        let abstraction = self.raw_abstraction.as_ref().ok_or_else(|| Error::illegal_state(format!("StateObserver \"{}\" has no abstraction for raw states", self.name)))?;
+        let state = (abstraction.0)(raw).ok_or_else(|| Error::illegal_argument(format!("StateObserver \"{}\" expects raw states of a different type than {}", self.name, std::any::type_name::<RS>())))?;
+        self.record(&state);
+        Ok(())
+    }
+
+    /// Tell the observer that the target sent `response` and record the state that the
+    /// [`ResponseStateInference`] of [`with_response_inference()`](StateObserver::with_response_inference) derives from it.
+    /// Responses without a state are ignored.
+    ///
+    /// Fails if there is no response inference.
+    pub fn record_response(&mut self, response: &[u8]) -> Result<(), Error> {
+        let inference = self.response_inference.as_ref().ok_or_else(|| Error::illegal_state(format!("StateObserver \"{}\" has no response inference", self.name)))?;
+
+        if let Some(state) = inference.infer(response) {
+            self.record(&state);
+        }
+
+        Ok(())
+    }
+
+    /// Tell the observer that the target has entered state `state` and took `latency` to respond.
+    /// The bucket of the latency is combined into the state as configured with
+    /// [`with_latency_buckets()`](StateObserver::with_latency_buckets). Without latency buckets
+    /// this is the same as [`record()`](StateObserver::record).
+    pub fn record_with_latency(&mut self, state: &PS, latency: Duration) {
+        match &self.latency_buckets {
+            Some(buckets) => {
+                let state = (buckets.combine)(state, buckets.bucket(latency));
+                self.record(&state);
+            },
+            None => self.record(state),
+        }
+    }
+
+    /// Tell the observer that the target has entered state `state`.
+    pub fn record(&mut self, state: &PS) {
+        if self.in_child && self.shared.is_some() {
+            match postcard::to_allocvec(state) {
+                Ok(payload) => {
+                    self.push_shared(SHARED_STATE, &payload);
+                },
+                Err(err) => println!("[butterfly] Could not encode a state for shared memory: {}", err),
+            }
+            return;
+        }
+
+        self.record_transition(state, None);
+    }
+
+    /// Tell the observer that the target has entered state `state` after it processed the packet
+    /// with the index `packet_idx` of the current input.
+    ///
+    /// The transition into `state` gets annotated with the index and, if the packet was labeled with
+    /// [`record_input()`](StateObserver::record_input), with its label, e.g. `#2 PASS`. The annotations
+    /// show up as edge labels in [`get_statemachine()`](StateObserver::get_statemachine) and
+    /// [`to_graphml()`](StateObserver::to_graphml), which tells which packets lead from one state to another
+    /// in a protocol without documentation. At most 16 distinct annotations are kept per transition.
+    ///
+    /// # Example
+    /// ```
+    /// for (idx, packet) in input.packets().iter().enumerate() {
+    ///     observer.record_input(packet.kind());
+    ///     let response = send(packet)?;
+    ///     observer.record_with_context(&infer_state(&response), idx);
+    /// }
+    /// ```
+    pub fn record_with_context(&mut self, state: &PS, packet_idx: usize) {
+        if self.in_child && self.shared.is_some() {
+            match postcard::to_allocvec(state) {
+                Ok(state) => {
+                    let mut payload = (packet_idx as u32).to_le_bytes().to_vec();
+                    payload.extend_from_slice(&state);
+                    self.push_shared(SHARED_STATE_WITH_CONTEXT, &payload);
+                },
+                Err(err) => println!("[butterfly] Could not encode a state for shared memory: {}", err),
+            }
+            return;
+        }
+
+        let context = match &self.input_label {
+            Some(label) => format!("#{} {}", packet_idx, label),
+            None => format!("#{}", packet_idx),
+        };
+        self.record_transition(state, Some(&context));
+    }
+
+    fn record_transition(&mut self, state: &PS, context: Option<&str>) {
+        let label = self.input_label.take();
+        let label = label.as_deref();
+
+        if let Some(exact) = self.graphs[0].budgeted(state, &self.budget) {
+            let new_node = self.graphs[0].node_id(exact).is_none();
+            let node = self.graphs[0].add_node(exact);
+            let new_edge = self.graphs[0].add_edge(node, label, context);
+
+            if let Some(n) = self.ngram_size {
+                self.graphs[0].add_ngram(n);
+            }
+
+            if let (Some(log_capture), true) = (&mut self.log_capture, new_node) {
+                log_capture.capture(node);
+            }
+
+            if let Some(timing) = &mut self.timing {
+                if new_node {
+                    timing.log("node", node, None, self.executions, &format!("{:?}", exact));
+                }
+
+                if let Some(from) = new_edge {
+                    timing.log("edge", from, Some(node), self.executions, "");
+                }
+            }
+        }
+
+        for (abstraction, graph) in self.abstractions.iter().zip(&mut self.graphs[1..]) {
+            let state = abstraction(state);
+
+            if let Some(state) = graph.budgeted(&state, &self.budget) {
+                let node = graph.add_node(state);
+                graph.add_edge(node, label, context);
+
+                if let Some(n) = self.ngram_size {
+                    graph.add_ngram(n);
+                }
+            }
+        }
+
+        if let (Some(budget), false) = (&self.budget, self.warned_budget) {
+            if self.graphs.iter().any(|graph| graph.overflows > 0) {
+                let consequence = match budget.policy {
+                    NodeBudgetPolicy::Ignore => "new states are ignored",
+                    NodeBudgetPolicy::Merge(_) => "new states are merged into one",
+                    NodeBudgetPolicy::EvictColdest => "the least visited states are evicted",
+                };
+                println!("[butterfly] StateObserver \"{}\" reached its budget of {} nodes, {}", self.name, budget.max_nodes, consequence);
+                self.warned_budget = true;
+            }
+        }
+    }
+
+    /// Returns whether any new edges were created in the state-graph during the last run.
+    /// Used by [`StateFeedback`](crate::StateFeedback).
+    ///
+    /// This doesn't tell whether the target reached a new state or took a new path between known states,
+    /// use [`had_new_nodes()`](StateObserver::had_new_nodes) and [`had_new_edges()`](StateObserver::had_new_edges) for that.
+    pub fn had_new_transitions(&self) -> bool {
+        self.graph().new_transitions
+    }
+
+    /// Returns whether the target entered a state during the last run that wasn't in the state-graph before,
+    /// including the first state of a run, which has no incoming edge.
+    pub fn had_new_nodes(&self) -> bool {
+        self.graph().new_nodes
+    }
+
+    /// Returns whether any new edges were created in the state-graph during the last run, regardless of whether
+    /// they lead to new states. Together with [`had_new_nodes()`](StateObserver::had_new_nodes) a feedback can tell
+    /// a new transition between known states, where this is `true` but `had_new_nodes()` is not, from a brand-new state.
+    pub fn had_new_edges(&self) -> bool {
+        self.graph().new_transitions
+    }
+
+    /// Returns whether any new edges were created during the last run
+    /// that connect states which already existed before the run.
+    /// Used by [`StateFeedback`](crate::StateFeedback) when the growth of the state-graph is limited.
+    pub fn had_new_known_transitions(&self) -> bool {
+        self.graph().new_known_transitions
+    }
+
+    /// Returns the number of vertices and edges in the state-graph.
+    /// Used by [`StateFeedback`](crate::StateFeedback).
+    pub fn info(&self) -> (usize, usize) {
+        (self.graph().num_nodes(), self.graph().edges.len())
+    }
+
+    /// Returns the number of vertices and edges in the exact state-graph, regardless of the active abstraction level
+    pub(crate) fn exact_info(&self) -> (usize, usize) {
+        (self.graphs[0].num_nodes(), self.graphs[0].edges.len())
+    }
+
+    /// Returns all states of the active abstraction level with their ids, in no particular order.
+    /// Ids start at 0 and are handed out sequentially, except that the ids of
+    /// [evicted](NodeBudgetPolicy::EvictColdest) states are reused.
+    pub fn nodes(&self) -> impl Iterator<Item = (u32, &PS)> {
+        self.graph().node_entries().map(|(state, id)| (id, state))
+    }
+
+    /// Returns all transitions of the active abstraction level as `(from, to)` pairs of state ids, in no particular order
+    pub fn edges(&self) -> impl Iterator<Item = (u32, u32)> + '_ {
+        self.graph().edges.keys().map(|transition| unpack_transition(*transition))
+    }
+
+    /// Returns the id of `state` in the state-graph of the active abstraction level,
+    /// `None` if the target never entered it
+    pub fn node_id(&self, state: &PS) -> Option<u32> {
+        self.graph().node_id(state)
+    }
+
+    /// Returns the state with the id `id`
+    pub fn node_state(&self, id: u32) -> Option<&PS> {
+        self.graph().get_state(id)
+    }
+
+    /// Returns the state with the id `id` in the exact state-graph
+    pub(crate) fn exact_state(&self, id: u32) -> Option<&PS> {
+        self.graphs[0].get_state(id)
+    }
+
+    /// Returns the ids of all states that the target went to directly from the state with the id `id`, in ascending order
+    pub fn successors(&self, id: u32) -> Vec<u32> {
+        let mut successors: Vec<u32> = self.edges().filter(|(from, _)| *from == id).map(|(_, to)| to).collect();
+        successors.sort_unstable();
+        successors
+    }
+
+    /// Returns the ids of all states from which the target went directly to the state with the id `id`, in ascending order
+    pub fn predecessors(&self, id: u32) -> Vec<u32> {
+        let mut predecessors: Vec<u32> = self.edges().filter(|(_, to)| *to == id).map(|(from, _)| from).collect();
+        predecessors.sort_unstable();
+        predecessors
+    }
+
+    /// Returns the ids of the states that runs started in, in ascending order.
+    /// For state-graphs that were only [merged](StateObserver::merge_graph) from other clients this is the state with id 0.
+    pub fn entry_states(&self) -> Vec<u32> {
+        self.graph().entry_nodes()
+    }
+
+    /// Returns how deep the target has been driven into its state-graph: the largest number of transitions
+    /// that are needed to reach any state from one of the [entry states](StateObserver::entry_states).
+    ///
+    /// This is the longest of the shortest paths, since the longest path through a graph with cycles is unbounded.
+    pub fn depth(&self) -> usize {
+        self.graph().depth()
+    }
+
+    /// Returns the strongly connected components of the state-graph, i.e. the largest groups of states
+    /// in which every state can be reached from every other one. States that are not part of a cycle
+    /// form a component by themselves. Many small components indicate a protocol that mostly moves forward,
+    /// one large component a target that can get back to most states.
+    ///
+    /// The ids of a component are sorted, the components are sorted by their first id.
+    pub fn strongly_connected_components(&self) -> Vec<Vec<u32>> {
+        self.graph().strongly_connected_components()
+    }
+
+    /// Returns the ids of all states without outgoing transitions in ascending order, e.g. states after
+    /// which the target closes the connection or states from which the fuzzer hasn't found a way out yet.
+    pub fn dead_ends(&self) -> Vec<u32> {
+        self.graph().dead_ends()
+    }
+
+    /// Returns all structural metrics of the state-graph at once.
+    /// They are computed from scratch, which takes time linear in the size of the state-graph.
+    ///
+    /// # Example
+    /// ```
+    /// let metrics = observer.metrics();
+    /// println!("depth {}, {} components, {} dead ends", metrics.depth, metrics.components, metrics.dead_ends);
+    /// ```
+    pub fn metrics(&self) -> GraphMetrics {
+        let (nodes, edges) = self.info();
+
+        GraphMetrics {
+            nodes,
+            edges,
+            depth: self.depth(),
+            components: self.strongly_connected_components().len(),
+            dead_ends: self.dead_ends().len(),
+        }
+    }
+
+    /// Returns the number of states in the state-graph that are marked with `mark`, see [`mark_state()`](StateObserver::mark_state)
+    pub fn num_marked(&self, mark: StateMark) -> usize {
+        self.graph().node_entries().filter(|(state, _)| self.state_mark(state) == Some(mark)).count()
+    }
+
+    /// Returns how often the target entered a state marked with `mark` during the last run
+    pub fn path_marks(&self, mark: StateMark) -> usize {
+        self.graph().path.iter().filter_map(|id| self.graph().get_state(*id)).filter(|state| self.state_mark(state) == Some(mark)).count()
+    }
+
+    /// Returns the position in the [path](StateObserver::path) of the last run at which the target
+    /// first entered a marked state, together with its mark. A small position means the input dead-ended early.
+    pub fn first_mark(&self) -> Option<(usize, StateMark)> {
+        self.graph().path.iter().enumerate().find_map(|(pos, id)| self.graph().get_state(*id).and_then(|state| self.state_mark(state)).map(|mark| (pos, mark)))
+    }
+
+    /// Returns how often the target entered the state with the id `id` since the observer was created
+    pub fn node_hits(&self, id: u32) -> u64 {
+        self.graph().node_hits.get(id as usize).copied().unwrap_or(0)
+    }
+
+    /// Returns how often the target went from the state with the id `from` to the state with the id `to`
+    /// since the observer was created. Self-loops are not counted.
+    pub fn edge_hits(&self, from: u32, to: u32) -> u64 {
+        self.graph().edges.get(&pack_transition(from, to)).copied().unwrap_or(0)
+    }
+
+    /// Returns whether the hit count of an existing edge reached a new bucket during the last run.
+    /// The buckets are logarithmic, i.e. a new bucket starts at every power of two, so this
+    /// happens often for rarely taken transitions and almost never for frequent ones.
+    /// Used by [`StateFeedback::with_hit_buckets()`](crate::StateFeedback::with_hit_buckets).
+    pub fn had_new_hit_buckets(&self) -> bool {
+        self.graph().new_hit_buckets
+    }
+
+    /// Returns whether a new sequence of states was seen during the last run, see [`with_ngrams()`](StateObserver::with_ngrams).
+    /// Always `false` without n-grams.
+    pub fn had_new_ngrams(&self) -> bool {
+        self.graph().new_ngrams
+    }
+
+    /// Returns the number of distinct sequences of states that were seen, see [`with_ngrams()`](StateObserver::with_ngrams)
+    pub fn num_ngrams(&self) -> usize {
+        self.graph().ngrams.len()
+    }
+
+    /// Returns the share of steps in the [path](StateObserver::path) of the last run that repeated a transition
+    /// which was already taken earlier in the same run, including self-loops. This is 0 for a run without cycles
+    /// and approaches 1 for inputs that drive the target around the same loop over and over.
+    /// Used by [`StateFeedback::with_loop_limit()`](crate::StateFeedback::with_loop_limit).
+    pub fn loop_ratio(&self) -> f64 {
+        let path = self.path();
+
+        if path.len() < 2 {
+            return 0.0;
+        }
+
+        let mut taken = HashSet::<u64, RandomState>::default();
+        let repeated = path.windows(2).filter(|step| !taken.insert(pack_transition(step[0], step[1]))).count();
+        repeated as f64 / (path.len() - 1) as f64
+    }
+
+    /// Returns the ids of the states that the target went through during the last run
+    /// in the order they were recorded.
+    pub fn path(&self) -> &[u32] {
+        &self.graph().path
+    }
+
+    /// Returns the ids of the states in the exact state-graph that the target went through during the last run,
+    /// regardless of the active abstraction level. Unlike the ids of the abstract levels, they never change meaning
+    /// when the observer switches levels.
+    pub fn exact_path(&self) -> &[u32] {
+        &self.graphs[0].path
+    }
+
+    /// Returns the positions in the [path](StateObserver::path) of the last run
+    /// at which new edges were created in the state-graph.
+    pub fn new_transition_positions(&self) -> &[usize] {
+        &self.graph().new_positions
+    }
+
+    /// Returns the states that the target went through during the last run
+    /// in the order they were recorded.
+    ///
+    /// In contrast to [`path()`](crate::StateObserver::path) this looks up the
+    /// actual state values, which is considerably slower.
+    pub fn path_states(&self) -> Vec<PS> {
+        self.graph().path.iter().filter_map(|id| self.graph().get_state(*id)).cloned().collect()
+    }
+
+    /// Returns the exact state-graph in a compact binary format that another observer
+    /// with the same state type can [merge](StateObserver::merge_graph).
+    /// Used by the [`GraphSyncStage`](crate::GraphSyncStage).
+    pub fn export_graph(&self) -> Result<Vec<u8>, Error> {
+        let graph = &self.graphs[0];
+        let mut entries: Vec<(u32, &PS)> = graph.node_entries().map(|(state, id)| (id, state)).collect();
+        entries.sort_unstable_by_key(|(id, _)| *id);
+
+        // Evicted states leave gaps in the ids, the export numbers the states densely
+        let mut index = vec![0; graph.node_hits.len()];
+
+        for (idx, (id, _)) in entries.iter().enumerate() {
+            index[*id as usize] = idx as u32;
+        }
+
+        let export = GraphExport {
+            nodes: entries.into_iter().map(|(_, state)| state.clone()).collect(),
+            edges: graph
+                .edges
+                .iter()
+                .map(|(transition, hits)| {
+                    let (from, to) = unpack_transition(*transition);
+                    (index[from as usize], index[to as usize], *hits)
+                })
+                .collect(),
+        };
+
+        postcard::to_allocvec(&export).map_err(|err| Error::serialize(format!("Could not encode the state-graph: {}", err)))
+    }
+
+    /// Add the states and transitions of a state-graph from [`export_graph()`](StateObserver::export_graph),
+    /// e.g. of another client of a multi-core campaign, to all abstraction levels of this observer.
+    ///
+    /// Merged states and transitions are known from then on, so runs that reach them
+    /// don't count as new anymore. They don't count as hits and the last run is not affected.
+    /// Returns the number of states and transitions that were added to the exact state-graph.
+    ///
+    /// # Example
+    /// ```
+    /// let (nodes, edges) = observer.merge_graph(&other_observer.export_graph()?)?;
+    /// ```
+    pub fn merge_graph(&mut self, data: &[u8]) -> Result<(usize, usize), Error> {
+        let export: GraphExport<PS> = postcard::from_bytes(data).map_err(|err| Error::serialize(format!("Could not decode the state-graph: {}", err)))?;
+        let added = self.graphs[0].merge(&export.nodes, &export.edges);
+
+        for (abstraction, graph) in self.abstractions.iter().zip(&mut self.graphs[1..]) {
+            let nodes: Vec<PS> = export.nodes.iter().map(abstraction).collect();
+            graph.merge(&nodes, &export.edges);
+        }
+
+        Ok(added)
+    }
+
+    /// Returns a DOT representation of the statemachine.
+    ///
+    /// The nodes are labeled with the `Debug` representation of their states
+    /// or the labels of [`with_node_labels()`](StateObserver::with_node_labels).
+    /// The edges are labeled with the packets given to [`record_with_context()`](StateObserver::record_with_context), if any.
+    /// [Error states](StateMark::Error) are red octagons and [terminal states](StateMark::Terminal) double circles.
+    pub fn get_statemachine(&self) -> String {
+        let mut s = String::with_capacity(1024);
+        // Snapshots refer to the ids of the exact state-graph
+        let snapshots = self.log_capture.as_ref().filter(|_| self.level == 0).map(|log_capture| &log_capture.snapshots);
+        self.graph().write_dot(&mut s, snapshots, self.node_label(), &|state| self.state_mark(state));
+        s
+    }
+
+    /// Returns the state-graph as a [Mermaid](https://mermaid.js.org) flowchart that GitHub and
+    /// most wikis render directly in Markdown inside a ```` ```mermaid ```` block.
+    ///
+    /// The nodes are labeled like in [`get_statemachine()`](StateObserver::get_statemachine),
+    /// marked states get the class `error` or `terminal`.
+    pub fn to_mermaid(&self) -> String {
+        let mut s = String::with_capacity(1024);
+        self.graph().write_mermaid(&mut s, self.node_label(), &|state| self.state_mark(state));
+        s
+    }
+
+    /// Returns the state-graph in the [GraphML](http://graphml.graphdrawing.org) format that
+    /// tools like Gephi, yEd or NetworkX can load.
+    ///
+    /// The nodes have the attribute `label`, labeled like in [`get_statemachine()`](StateObserver::get_statemachine),
+    /// and marked states the attribute `mark` with the value `error` or `terminal`.
+    /// The edges have the attribute `hits`, see [`edge_hits()`](StateObserver::edge_hits), and the attribute `context`
+    /// if they were annotated with [`record_with_context()`](StateObserver::record_with_context).
+    pub fn to_graphml(&self) -> String {
+        let mut s = String::with_capacity(1024);
+        self.graph().write_graphml(&mut s, self.node_label(), &|state| self.state_mark(state));
+        s
+    }
+
+    /// The labels of the nodes in exports
+    fn node_label(&self) -> fn(&PS) -> String {
+        self.node_label.unwrap_or(|state| format!("{:?}", state))
+    }
+
+    /// Returns the state-graph as a Mealy machine in the DOT format that
+    /// [AALpy](https://github.com/DES-Lab/AALpy) and [LearnLib](https://learnlib.de) can load.
+    ///
+    /// The inputs of the transitions are the labels given to [`record_input()`](StateObserver::record_input),
+    /// e.g. via [`NetworkExecutor::with_input_labels()`](crate::NetworkExecutor::with_input_labels),
+    /// or `epsilon` if there was none. The output of a transition is the state it leads to.
+    /// The initial state `init` is the state of the target before it processes an input.
+    ///
+    /// Note that the result is not necessarily deterministic: If the target reacted differently
+    /// to the same input in the same state, both transitions are included.
+    pub fn get_mealy_machine(&self) -> String {
+        let mut s = String::with_capacity(1024);
+        self.graph().write_mealy(&mut s);
+        s
+    }
+}
+
+impl<PS> HasTargetRestart for StateObserver<PS>
+where
+    PS: Clone + Debug + Eq + Hash + Serialize + for<'a> Deserialize<'a>,
+{
+    fn on_target_restart(&mut self) {
+        if self.push_shared(SHARED_TARGET_RESTART, &[]) {
+            return;
+        }
+
+        // The restart was not caused by the input the target was about to process
+        self.input_label = None;
+
+        match self.restart_marker.clone() {
+            Some(marker) => self.record(&marker),
+            None => {
+                for graph in &mut self.graphs {
+                    graph.detach();
+                }
+            },
+        }
+    }
+}
+
+impl<PS> Named for StateObserver<PS>
+where
+    PS: Clone + Debug + Hash + Eq + Serialize + for<'a> Deserialize<'a>,
+{
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl<PS, I, S> Observer<I, S> for StateObserver<PS>
+where
+    PS: Clone + Debug + Hash + Eq + Serialize + for<'a> Deserialize<'a>,
+{
+    fn pre_exec(&mut self, _state: &mut S, _input: &I) -> Result<(), Error> {
+        if self.abstractions.len() + 1 != self.graphs.len() {
+            return Err(Error::illegal_state(format!("StateObserver \"{}\" was deserialized without its abstraction levels, restore() it into a new observer", self.name)));
+        }
+
+        self.executions += 1;
+        self.input_label = None;
+        self.switch_abstraction_level();
+
+        for graph in &mut self.graphs {
+            graph.reset(self.reset_mode);
+        }
+
+        if let Some(shared) = &mut self.shared {
+            shared.clear();
+        }
+
+        Ok(())
     }
 
     fn post_exec(&mut self, _state: &mut S, _input: &I, _exit_kind: &ExitKind) -> Result<(), Error> {
+        self.replay_shared();
+        Ok(())
+    }
+
+    fn pre_exec_child(&mut self, _state: &mut S, _input: &I) -> Result<(), Error> {
+        self.in_child = true;
         Ok(())
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libafl::bolts::tuples::tuple_list;
+
+    fn run(observer: &mut StateObserver<u32>, states: &[u32]) {
+        Observer::<(), ()>::pre_exec(observer, &mut (), &()).unwrap();
+
+        for state in states {
+            observer.record(state);
+        }
+    }
+
+    fn run_strings(observer: &mut StateObserver<String>, states: &[&str]) {
+        Observer::<(), ()>::pre_exec(observer, &mut (), &()).unwrap();
+
+        for state in states {
+            observer.record(&state.to_string());
+        }
+    }
+
+    #[test]
+    fn test_abstraction_switching() {
+        let mut observer = StateObserver::<u32>::new("state").with_abstraction_level(|code| code / 100 * 100).with_abstraction_switching(2, 3);
+
+        run(&mut observer, &[200, 404]);
+        assert!(observer.had_new_transitions());
+        assert!(!observer.had_new_known_transitions());
+
+        run(&mut observer, &[404, 200]);
+        assert!(observer.had_new_known_transitions());
+        assert_eq!(observer.info(), (2, 2));
+
+        run(&mut observer, &[200, 403, 500, 501]);
+        assert!(observer.had_new_transitions());
+        assert!(!observer.had_new_known_transitions());
+        assert_eq!(observer.info(), (5, 5));
+
+        // Too many nodes: switch to status classes
+        run(&mut observer, &[200, 401]);
+        assert_eq!(observer.abstraction_level(), 1);
+        assert_eq!(observer.info(), (3, 3));
+        assert!(!observer.had_new_transitions());
+        assert_eq!(observer.path_states(), vec![200, 400]);
+
+        // The exact graph is still too large to switch back
+        for _ in 0..4 {
+            run(&mut observer, &[200]);
+        }
+        assert_eq!(observer.abstraction_level(), 1);
+    }
+
+    #[test]
+    fn test_ngrams() {
+        let mut observer = StateObserver::<u32>::new("state").with_ngrams(3);
+        run(&mut observer, &[1, 2, 1, 3]);
+        assert!(observer.had_new_ngrams());
+        assert_eq!(observer.num_ngrams(), 2);
+
+        // Too short for a window
+        run(&mut observer, &[1, 3]);
+        assert!(!observer.had_new_ngrams());
+
+        // Known transitions in a new order
+        run(&mut observer, &[1, 2, 1, 2]);
+        assert!(!observer.had_new_transitions());
+        assert!(observer.had_new_ngrams());
+        assert_eq!(observer.num_ngrams(), 3);
+
+        // A restart starts a new window
+        run(&mut observer, &[2, 1]);
+        observer.on_target_restart();
+        observer.record(&3);
+        assert!(!observer.had_new_ngrams());
+
+        run(&mut observer, &[1, 2, 1, 2, 1, 2]);
+        assert_eq!(observer.loop_ratio(), 0.6);
+        run(&mut observer, &[1, 2, 3]);
+        assert_eq!(observer.loop_ratio(), 0.0);
+
+        let mut observer = StateObserver::<u32>::new("state");
+        run(&mut observer, &[1, 2, 1, 3]);
+        assert!(!observer.had_new_ngrams());
+        assert_eq!(observer.num_ngrams(), 0);
+    }
+
+    #[test]
+    fn test_node_budget() {
+        let mut observer = StateObserver::<u32>::new("state").with_node_budget(3, NodeBudgetPolicy::Ignore);
+        run(&mut observer, &[1, 2, 3, 4, 3, 1]);
+        assert_eq!(observer.path_states(), vec![1, 2, 3, 3, 1]);
+        assert_eq!(observer.info(), (3, 3));
+        assert_eq!(observer.budget_overflows(), 1);
+
+        let mut observer = StateObserver::<u32>::new("state").with_node_budget(3, NodeBudgetPolicy::Merge(0));
+        run(&mut observer, &[1, 2, 3, 4, 5]);
+        assert_eq!(observer.path_states(), vec![1, 2, 3, 0, 0]);
+        assert_eq!(observer.budget_overflows(), 2);
+
+        let mut observer = StateObserver::<u32>::new("state").with_node_budget(3, NodeBudgetPolicy::EvictColdest);
+        run(&mut observer, &[1, 2, 1, 2]);
+        run(&mut observer, &[3]);
+        run(&mut observer, &[2, 4]);
+        assert_eq!(observer.info(), (3, 3));
+        assert_eq!(observer.node_id(&3), None);
+        assert_eq!(observer.node_id(&4), Some(2));
+        assert_eq!(observer.node_hits(2), 1);
+        assert_eq!(observer.successors(observer.node_id(&2).unwrap()), vec![0, 2]);
+        assert!(observer.get_mealy_machine().contains("s1 -> s2"));
+
+        // A new state on the reused id of an evicted state is not known
+        let mut observer = StateObserver::<u32>::new("state").with_node_budget(2, NodeBudgetPolicy::EvictColdest);
+        run(&mut observer, &[1, 2, 2]);
+        run(&mut observer, &[2, 3]);
+        assert_eq!(observer.node_id(&3), Some(0));
+        assert!(observer.had_new_transitions());
+        assert!(!observer.had_new_known_transitions());
+
+        // The state the next run continues from is not evicted
+        let mut observer = StateObserver::<u32>::new("state").with_node_budget(2, NodeBudgetPolicy::EvictColdest).with_reset_mode(ResetMode::KeepLastState);
+        run(&mut observer, &[1, 1, 1, 2]);
+        run(&mut observer, &[3]);
+        assert_eq!(observer.node_id(&2), Some(1));
+        assert_eq!(observer.node_id(&3), Some(0));
+        assert_eq!(observer.successors(1), vec![0]);
+
+        // Gaps in the ids survive exports
+        let mut observer = StateObserver::<u32>::new("state").with_node_budget(3, NodeBudgetPolicy::EvictColdest);
+        run(&mut observer, &[1, 2, 2, 3, 3]);
+        run(&mut observer, &[3, 2, 4]);
+        run(&mut observer, &[]);
+        observer.graphs[0].evict_coldest();
+        assert_eq!(observer.node_id(&4), None);
+        assert_eq!(observer.info(), (2, 2));
+        assert!(observer.get_mealy_machine().contains("-> s"));
+
+        let mut other = StateObserver::<u32>::new("state");
+        assert_eq!(other.merge_graph(&observer.export_graph().unwrap()).unwrap(), (2, 2));
+        assert_eq!(other.successors(other.node_id(&3).unwrap()), vec![other.node_id(&2).unwrap()]);
+    }
+
+    #[test]
+    fn test_reset_mode() {
+        let mut observer = StateObserver::<u32>::new("state");
+        run(&mut observer, &[1, 2]);
+        run(&mut observer, &[3]);
+        assert_eq!(observer.edges().count(), 1);
+
+        let mut observer = StateObserver::<u32>::new("state").with_reset_mode(ResetMode::KeepLastState);
+        run(&mut observer, &[1, 2]);
+        run(&mut observer, &[3]);
+        assert!(observer.had_new_transitions());
+        assert_eq!(observer.successors(1), vec![2]);
+        assert_eq!(observer.path(), &[2]);
+
+        observer.on_target_restart();
+        run(&mut observer, &[1]);
+        assert_eq!(observer.predecessors(0), Vec::<u32>::new());
+    }
+
+    #[test]
+    fn test_hit_counts() {
+        let mut observer = StateObserver::<u32>::new("state");
+
+        run(&mut observer, &[1, 2, 2]);
+        assert!(!observer.had_new_hit_buckets());
+        run(&mut observer, &[1, 2]);
+        assert!(observer.had_new_hit_buckets());
+        run(&mut observer, &[1, 2]);
+        assert!(!observer.had_new_hit_buckets());
+
+        assert_eq!(observer.node_hits(0), 3);
+        assert_eq!(observer.node_hits(1), 4);
+        assert_eq!(observer.node_hits(2), 0);
+        assert_eq!(observer.edge_hits(0, 1), 3);
+        assert_eq!(observer.edge_hits(1, 1), 0);
+
+        // Bucket 4..8
+        run(&mut observer, &[1, 2]);
+        assert!(observer.had_new_hit_buckets());
+        assert!(!observer.had_new_transitions());
+    }
+
+    #[test]
+    fn test_introspection() {
+        let mut observer = StateObserver::<u32>::new("state");
+        run(&mut observer, &[220, 331, 230]);
+        run(&mut observer, &[220, 530, 331]);
+
+        let mut nodes: Vec<(u32, u32)> = observer.nodes().map(|(id, state)| (id, *state)).collect();
+        nodes.sort_unstable();
+        assert_eq!(nodes, vec![(0, 220), (1, 331), (2, 230), (3, 530)]);
+        assert_eq!(observer.edges().count(), 4);
+
+        let id = observer.node_id(&331).unwrap();
+        assert_eq!(observer.node_state(id), Some(&331));
+        assert_eq!(observer.successors(id), vec![2]);
+        assert_eq!(observer.predecessors(id), vec![0, 3]);
+        assert_eq!(observer.node_id(&500), None);
+    }
+
+    #[test]
+    fn test_merge_graph() {
+        let mut observer = StateObserver::<u32>::new("state").with_abstraction_level(|code| code / 100 * 100);
+        let mut other = StateObserver::<u32>::new("state");
+        run(&mut observer, &[220, 331]);
+        run(&mut other, &[220, 530, 331, 230]);
+        run(&mut other, &[220, 530, 331, 230]);
+
+        assert_eq!(observer.merge_graph(&other.export_graph().unwrap()).unwrap(), (2, 3));
+        assert_eq!(observer.info(), (4, 4));
+        assert_eq!(observer.edge_hits(observer.node_id(&530).unwrap(), observer.node_id(&331).unwrap()), 2);
+        assert_eq!(observer.node_hits(observer.node_id(&530).unwrap()), 0);
+
+        // Merging again adds nothing
+        assert_eq!(observer.merge_graph(&other.export_graph().unwrap()).unwrap(), (0, 0));
+
+        // Merged transitions are not new
+        run(&mut observer, &[220, 530, 331]);
+        assert!(!observer.had_new_transitions());
+
+        observer.set_abstraction_level(1);
+        assert_eq!(observer.info(), (3, 4));
+
+        assert!(observer.merge_graph(b"garbage").is_err());
+    }
+
+    #[test]
+    fn test_graph_metrics() {
+        let mut observer = StateObserver::<u32>::new("state");
+        assert_eq!(observer.metrics(), GraphMetrics::default());
+
+        // 0 -> 1 <-> 2 -> 3, 0 -> 4
+        run(&mut observer, &[220, 331, 230, 331, 230, 221]);
+        run(&mut observer, &[220, 530]);
+
+        assert_eq!(observer.entry_states(), vec![0]);
+        assert_eq!(observer.depth(), 3);
+        assert_eq!(observer.strongly_connected_components(), vec![vec![0], vec![1, 2], vec![3], vec![4]]);
+        assert_eq!(observer.dead_ends(), vec![3, 4]);
+        assert_eq!(
+            observer.metrics(),
+            GraphMetrics {
+                nodes: 5,
+                edges: 5,
+                depth: 3,
+                components: 4,
+                dead_ends: 2,
+            }
+        );
+
+        // Depths are counted from the closest entry state
+        run(&mut observer, &[421, 220]);
+        assert_eq!(observer.entry_states(), vec![0, 5]);
+        assert_eq!(observer.depth(), 3);
+        assert_eq!(observer.strongly_connected_components().len(), 5);
+    }
+
+    #[test]
+    fn test_node_labels() {
+        let mut observer = StateObserver::<String>::new("state");
+        Observer::<(), ()>::pre_exec(&mut observer, &mut (), &()).unwrap();
+        observer.record(&"LOGIN".to_string());
+        observer.record(&"say \"hi\"".to_string());
+
+        let dot = observer.get_statemachine();
+        assert!(dot.contains("\"0\"[label=\"\\\"LOGIN\\\"\"];"));
+        assert!(dot.contains("\"1\"[label=\"\\\"say \\\\\\\"hi\\\\\\\"\\\"\"];"));
+        assert!(dot.contains("\"0\"->\"1\";"));
+
+        let mut observer = StateObserver::<String>::new("state").with_node_labels(|state| state.to_lowercase());
+        Observer::<(), ()>::pre_exec(&mut observer, &mut (), &()).unwrap();
+        observer.record(&"LOGIN".to_string());
+        assert!(observer.get_statemachine().contains("\"0\"[label=\"login\"];"));
+    }
+
+    #[test]
+    fn test_edge_contexts() {
+        let mut observer = StateObserver::<u32>::new("state");
+        run(&mut observer, &[220]);
+        observer.record_input("USER");
+        observer.record_with_context(&331, 0);
+        observer.record_input("<PASS>");
+        observer.record_with_context(&230, 1);
+        observer.record_with_context(&230, 2);
+
+        let dot = observer.get_statemachine();
+        assert!(dot.contains("\"0\"->\"1\"[label=\"#0 USER\"];"));
+        assert!(dot.contains("\"1\"->\"2\"[label=\"#1 <PASS>\"];"));
+
+        let graphml = observer.to_graphml();
+        assert!(graphml.contains("<node id=\"n0\"><data key=\"label\">220</data></node>"));
+        assert!(graphml.contains("<edge source=\"n1\" target=\"n2\"><data key=\"hits\">1</data><data key=\"context\">#1 &lt;PASS&gt;</data></edge>"));
+
+        // Transitions without context are not labeled
+        run(&mut observer, &[220, 230]);
+        assert!(observer.get_statemachine().contains("\"0\"->\"2\";"));
+        assert!(observer.get_mealy_machine().contains("s0 -> s1 [label=\"USER/331\"];"));
+    }
+
+    #[test]
+    fn test_mermaid() {
+        let mut observer = StateObserver::<String>::new("state");
+        run_strings(&mut observer, &["220", "331 \"#1\""]);
+        run_strings(&mut observer, &["220", "530"]);
+
+        assert_eq!(observer.to_mermaid(), "flowchart LR\n    s0[\"#quot;220#quot;\"]\n    s1[\"#quot;331 \\#quot;#35;1\\#quot;#quot;\"]\n    s2[\"#quot;530#quot;\"]\n    s0 --> s1\n    s0 --> s2\n");
+    }
+
+    #[test]
+    fn test_record_response() {
+        let mut observer = StateObserver::<u32>::new("state");
+        assert!(observer.record_response(b"220 ready\r\n").is_err());
+
+        let mut observer = observer.with_response_inference(ResponseStateInference::status_code());
+        Observer::<(), ()>::pre_exec(&mut observer, &mut (), &()).unwrap();
+        observer.record_response(b"220 ready\r\n").unwrap();
+        observer.record_response(b"garbage").unwrap();
+        observer.record_response(b"331 password required\r\n").unwrap();
+        assert_eq!(observer.path_states(), vec![220, 331]);
+    }
+
+    #[test]
+    fn test_latency_buckets() {
+        let mut observer = StateObserver::<u32>::new("state");
+        assert_eq!(observer.latency_bucket(Duration::from_millis(1)), None);
+        Observer::<(), ()>::pre_exec(&mut observer, &mut (), &()).unwrap();
+        observer.record_with_latency(&220, Duration::from_secs(1));
+        assert_eq!(observer.path_states(), vec![220]);
+
+        let mut observer = StateObserver::<u32>::new("state").with_latency_buckets(&[Duration::from_millis(500), Duration::from_millis(10)], |code, bucket| code * 10 + bucket as u32);
+        assert_eq!(observer.latency_bucket(Duration::from_millis(1)), Some(0));
+        assert_eq!(observer.latency_bucket(Duration::from_millis(10)), Some(1));
+        assert_eq!(observer.latency_bucket(Duration::from_secs(3)), Some(2));
+
+        Observer::<(), ()>::pre_exec(&mut observer, &mut (), &()).unwrap();
+        observer.record_with_latency(&220, Duration::from_millis(2));
+        observer.record_with_latency(&331, Duration::from_millis(3));
+        assert!(observer.had_new_transitions());
+
+        // Same states, but the second one took longer
+        Observer::<(), ()>::pre_exec(&mut observer, &mut (), &()).unwrap();
+        observer.record_with_latency(&220, Duration::from_millis(2));
+        observer.record_with_latency(&331, Duration::from_millis(800));
+        assert!(observer.had_new_transitions());
+        assert_eq!(observer.path_states(), vec![2200, 3312]);
+        assert_eq!(observer.info(), (3, 2));
+    }
+
+    #[test]
+    fn test_state_marks() {
+        let mut observer = StateObserver::<u32>::new("state").with_state_marks(|code| (*code >= 500).then_some(StateMark::Error));
+        observer.mark_state(0, StateMark::Terminal);
+        observer.mark_state(502, StateMark::Terminal);
+
+        run(&mut observer, &[220, 530, 502, 0]);
+        assert_eq!(observer.first_mark(), Some((1, StateMark::Error)));
+        assert_eq!(observer.path_marks(StateMark::Error), 1);
+        assert_eq!(observer.path_marks(StateMark::Terminal), 2);
+        assert_eq!(observer.num_marked(StateMark::Terminal), 2);
+
+        run(&mut observer, &[220, 331]);
+        assert_eq!(observer.first_mark(), None);
+        assert_eq!(observer.num_marked(StateMark::Error), 1);
+
+        let dot = observer.get_statemachine();
+        assert!(dot.contains("\"0\"[label=\"220\"];"));
+        assert!(dot.contains("\"1\"[label=\"530\",shape=octagon,color=red];"));
+        assert!(dot.contains("\"3\"[label=\"0\",shape=doublecircle];"));
+        assert!(observer.to_mermaid().contains("    s1[\"530\"]:::error\n"));
+        assert!(observer.to_graphml().contains("<node id=\"n2\"><data key=\"label\">502</data><data key=\"mark\">terminal</data></node>"));
+    }
+
+    #[test]
+    fn test_node_and_edge_novelty() {
+        let mut observer = StateObserver::<u32>::new("state");
+
+        // A new first state has no edge
+        run(&mut observer, &[220]);
+        assert!(observer.had_new_nodes());
+        assert!(!observer.had_new_edges());
+
+        run(&mut observer, &[220, 331]);
+        assert!(observer.had_new_nodes());
+        assert!(observer.had_new_edges());
+
+        run(&mut observer, &[331, 220]);
+        assert!(!observer.had_new_nodes());
+        assert!(observer.had_new_edges());
+
+        run(&mut observer, &[220, 331, 220]);
+        assert!(!observer.had_new_nodes());
+        assert!(!observer.had_new_edges());
+    }
+
+    #[test]
+    fn test_node_storage() {
+        let mut exact = StateObserver::<String>::new("state").with_abstraction_level(|state| state[..1].to_string());
+        let mut hashed = StateObserver::<String>::new("state").with_abstraction_level(|state| state[..1].to_string()).with_node_storage(NodeStorage::Hashed {
+            check_collisions: true,
+        });
+
+        for observer in [&mut exact, &mut hashed] {
+            run_strings(observer, &["220 ready", "331 password", "230 logged in"]);
+            run_strings(observer, &["220 ready", "530 denied"]);
+        }
+
+        assert_eq!(hashed.info(), exact.info());
+        assert_eq!(hashed.path_states(), exact.path_states());
+        assert_eq!(hashed.to_mermaid(), exact.to_mermaid());
+        assert_eq!(hashed.node_id(&"331 password".to_string()), Some(1));
+        assert_eq!(hashed.node_state(3), Some(&"530 denied".to_string()));
+
+        hashed.set_abstraction_level(1);
+        assert_eq!(hashed.info(), (3, 3));
+    }
+
+    #[test]
+    fn test_hash_collisions() {
+        let a = "a".to_string();
+        let b = "b".to_string();
+
+        for check_collisions in [false, true] {
+            let mut nodes = HashedNodes::new(check_collisions);
+            nodes.insert(&a, 0);
+            // Pretend that b has the same hash as a
+            nodes.ids.insert(HashedNodes::hash(&b), 0);
+
+            if !check_collisions {
+                assert_eq!(nodes.get(&b), Some(0));
+                continue;
+            }
+
+            assert_eq!(nodes.get(&b), None);
+            nodes.insert(&b, 1);
+            assert_eq!(nodes.get(&a), Some(0));
+            assert_eq!(nodes.get(&b), Some(1));
+
+            nodes.remove(&b, 1);
+            assert_eq!(nodes.get(&b), None);
+            assert_eq!(nodes.len, 1);
+        }
+    }
+
+    #[test]
+    fn test_deserialized_abstractions() {
+        let mut observer = StateObserver::<u32>::new("state").with_abstraction_level(|code| code / 100 * 100);
+        run(&mut observer, &[200, 201]);
+        observer.set_abstraction_level(1);
+
+        let saved: StateObserver<u32> = postcard::from_bytes(&postcard::to_allocvec(&observer).unwrap()).unwrap();
+        let mut deserialized: StateObserver<u32> = postcard::from_bytes(&postcard::to_allocvec(&observer).unwrap()).unwrap();
+        assert!(Observer::<(), ()>::pre_exec(&mut deserialized, &mut (), &()).is_err());
+
+        let mut restored = StateObserver::<u32>::new("state").with_abstraction_level(|code| code / 100 * 100);
+        restored.restore(saved).unwrap();
+        run(&mut restored, &[200, 300]);
+        assert_eq!(restored.abstraction_level(), 1);
+        assert_eq!(restored.info(), (2, 1));
+    }
+
+    #[test]
+    fn test_raw_states() {
+        // Status code and message
+        let mut observer = StateObserver::<u32>::new("state").with_raw_states(|response: &(u32, String)| response.0);
+        Observer::<(), ()>::pre_exec(&mut observer, &mut (), &()).unwrap();
+
+        for (code, message) in [(220u32, "ready"), (331, "send password"), (331, "again")] {
+            observer.record_raw(&(code, message.to_string())).unwrap();
+        }
+
+        assert_eq!(observer.path_states(), vec![220, 331, 331]);
+        assert!(observer.record_raw(&220u32).is_err());
+        assert!(StateObserver::<u32>::new("state").record_raw(&220u32).is_err());
+    }
+
+    #[test]
+    fn test_shared_memory() {
+        let mut provider = StdShMemProvider::new().unwrap();
+        let shmem = provider.new_shmem(1024).unwrap();
+        let child_shmem = provider.shmem_from_id_and_size(shmem.id(), shmem.len()).unwrap();
+        let mut parent = StateObserver::<u32>::new("state").with_shared_memory(shmem);
+        let mut child = StateObserver::<u32>::new("state").with_shared_memory(child_shmem);
+
+        Observer::<(), ()>::pre_exec(&mut parent, &mut (), &()).unwrap();
+        Observer::<(), ()>::pre_exec_child(&mut child, &mut (), &()).unwrap();
+        child.record(&220);
+        child.record_input("USER");
+        child.record(&331);
+        child.record_with_context(&230, 1);
+        assert_eq!(child.info(), (0, 0));
+
+        Observer::<(), ()>::post_exec(&mut parent, &mut (), &(), &ExitKind::Ok).unwrap();
+        assert_eq!(parent.path_states(), vec![220, 331, 230]);
+        assert!(parent.get_mealy_machine().contains("s0 -> s1 [label=\"USER/331\"];"));
+        assert!(parent.get_statemachine().contains("\"1\"->\"2\"[label=\"#1\"];"));
+
+        // Entries that don't fit are dropped
+        let shmem = provider.new_shmem(20).unwrap();
+        let child_shmem = provider.shmem_from_id_and_size(shmem.id(), shmem.len()).unwrap();
+        let mut parent = StateObserver::<u32>::new("state").with_shared_memory(shmem);
+        let mut child = StateObserver::<u32>::new("state").with_shared_memory(child_shmem);
+
+        Observer::<(), ()>::pre_exec(&mut parent, &mut (), &()).unwrap();
+        Observer::<(), ()>::pre_exec_child(&mut child, &mut (), &()).unwrap();
+        child.record(&220);
+        child.record(&331);
+        Observer::<(), ()>::post_exec(&mut parent, &mut (), &(), &ExitKind::Ok).unwrap();
+        assert_eq!(parent.path_states(), vec![220]);
+    }
+
+    #[test]
+    fn test_state_timing() {
+        let path = std::env::temp_dir().join(format!("butterfly-state-timing-{}.csv", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mut observer = StateObserver::<u32>::new("state").with_state_timing(&path);
+
+        run(&mut observer, &[1, 2]);
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        run(&mut observer, &[1, 2, 1]);
+        drop(observer);
+
+        // A restarted client appends to the file
+        let mut observer = StateObserver::<u32>::new("state").with_state_timing(&path);
+        run(&mut observer, &[3]);
+        drop(observer);
+
+        let csv = std::fs::read_to_string(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        let rows: Vec<Vec<&str>> = csv.lines().map(|line| line.split(',').collect()).collect();
+
+        assert_eq!(rows.len(), 6);
+        assert_eq!(rows[0], ["kind", "from", "to", "executions", "seconds", "state"]);
+        assert_eq!((rows[5][0], rows[5][5]), ("node", "\"3\""));
+        assert!(rows[5][4].parse::<f64>().unwrap() >= rows[4][4].parse::<f64>().unwrap());
+        assert!(rows[4][4].parse::<f64>().unwrap() >= 0.01);
+        assert_eq!((rows[1][0], rows[1][1], rows[1][3], rows[1][5]), ("node", "0", "1", "\"1\""));
+        assert_eq!((rows[3][0], rows[3][1], rows[3][2], rows[3][3]), ("edge", "0", "1", "1"));
+        assert_eq!((rows[4][0], rows[4][1], rows[4][2], rows[4][3]), ("edge", "1", "0", "2"));
+    }
+
+    #[test]
+    fn test_log_capture() {
+        let path = std::env::temp_dir().join(format!("butterfly-log-capture-{}.log", std::process::id()));
+        std::fs::write(&path, "startup\n").unwrap();
+        let mut observer = StateObserver::<u32>::new("state").with_log_capture(&path, 24);
+
+        run(&mut observer, &[1]);
+        std::fs::write(&path, "startup\nuser \"a\" logged in\n").unwrap();
+        run(&mut observer, &[1, 2, 1]);
+        let _ = std::fs::remove_file(&path);
+
+        assert_eq!(observer.log_snapshot(0), Some("startup\n"));
+        // Only complete lines of the tail are kept
+        assert_eq!(observer.log_snapshot(1), Some("user \"a\" logged in\n"));
+        assert_eq!(observer.log_snapshot(2), None);
+        assert!(observer.get_statemachine().contains("\"1\"[label=\"2\",tooltip=\"user \\\"a\\\" logged in\\n\"];"));
+    }
+
+    #[test]
+    fn test_mealy_machine() {
+        let mut observer = StateObserver::<u32>::new("state");
+
+        for _ in 0..2 {
+            Observer::<(), ()>::pre_exec(&mut observer, &mut (), &()).unwrap();
+            observer.record(&220);
+            observer.record_input("USER");
+            observer.record(&331);
+            observer.record_input("NOOP");
+            observer.record(&331);
+        }
+        run(&mut observer, &[220, 530]);
+
+        let mealy = observer.get_mealy_machine();
+        assert!(mealy.contains("__start0 -> init"));
+        assert!(mealy.contains("init -> s0 [label=\"epsilon/220\"];"));
+        assert!(mealy.contains("s0 -> s1 [label=\"USER/331\"];"));
+        assert!(mealy.contains("s1 -> s1 [label=\"NOOP/331\"];"));
+        assert!(mealy.contains("s0 -> s2 [label=\"epsilon/530\"];"));
+        assert_eq!(mealy.matches("->").count(), 5);
+    }
+
+    #[test]
+    fn test_target_restart() {
+        let mut observer = StateObserver::<u32>::new("state");
+        Observer::<(), ()>::pre_exec(&mut observer, &mut (), &()).unwrap();
+        observer.record(&220);
+        observer.record_input("USER");
+        observer.record(&331);
+        observer.on_target_restart();
+        observer.record(&220);
+
+        // The greeting after the restart is an entry, not a transition from 331
+        assert_eq!(observer.info(), (2, 1));
+        assert_eq!(observer.path(), &[0, 1, 0]);
+        assert!(observer.get_mealy_machine().contains("init -> s0 [label=\"epsilon/220\"];"));
+
+        let mut observers = tuple_list!(StateObserver::<u32>::new("state").with_restart_marker(u32::MAX));
+        let observer = &mut observers.0;
+        Observer::<(), ()>::pre_exec(observer, &mut (), &()).unwrap();
+        observer.record(&220);
+        observer.record(&331);
+        notify_target_restart::<StateObserver<u32>, _>(&mut observers, "state").unwrap();
+        observers.0.record(&220);
+
+        assert_eq!(observers.0.info(), (3, 3));
+        assert_eq!(observers.0.path_states(), vec![220, 331, u32::MAX, 220]);
+        assert!(notify_target_restart::<StateObserver<u64>, _>(&mut observers, "state").is_err());
+    }
+}
+
 #[cfg(test)]
 mod benchmarks {
     extern crate test;
@@ -190,7 +2841,7 @@ mod benchmarks {
         let mut graph = StateGraph::<State>::new();
         b.iter(|| {
             let node = graph.add_node(&State::default());
-            graph.add_edge(node);
+            graph.add_edge(node, None, None);
         });
     }
 
@@ -200,7 +2851,7 @@ mod benchmarks {
         let mut i: usize = 0;
         b.iter(|| {
             let node = graph.add_node(&state(i));
-            graph.add_edge(node);
+            graph.add_edge(node, None, None);
             i += 1;
         });
     }
@@ -216,13 +2867,13 @@ mod benchmarks {
 
             for j in 0..limit {
                 let j_node = graph.add_node(&state(j));
-                graph.add_edge(i_node);
-                graph.add_edge(j_node);
-                graph.reset();
+                graph.add_edge(i_node, None, None);
+                graph.add_edge(j_node, None, None);
+                graph.reset(ResetMode::Full);
             }
         }
 
-        println!("nodes = {}", graph.nodes.len());
+        println!("nodes = {}", graph.num_nodes());
         println!("edges = {}", graph.edges.len());
 
         loop {}
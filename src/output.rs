@@ -0,0 +1,114 @@
+use std::fs::{File, OpenOptions};
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+/// Returns the output directory of a single client of a multi-core campaign: `<base>/client-<client_id>`.
+///
+/// Every client of a LibAFL `Launcher` runs its own executor, stages and hooks, so components that
+/// write files must not share a directory. The [`PcapRecorder`](crate::PcapRecorder), the triage hooks,
+/// [`Checkpoints`](crate::Checkpoints) and the [`ControlStage`](crate::ControlStage) namespace their output
+/// with this when they are given a client id via `with_client_id()`.
+///
+/// # Example
+/// ```
+/// let mut run_client = |state: Option<_>, mut mgr, core_id| {
+///     let hook = BundleTriageHook::new("crashes").with_client_id(core_id);
+///     // writes into crashes/client-<core_id>/crash-<n>
+///     ...
+/// };
+/// ```
+pub fn client_dir<P: AsRef<Path>>(base: P, client_id: usize) -> PathBuf {
+    base.as_ref().join(format!("client-{}", client_id))
+}
+
+/// Returns the path of a file that a single client of a multi-core campaign writes:
+/// `<stem>-client-<client_id>.<extension>`, e.g. `graph-client-3.dot` for `graph.dot`.
+pub fn client_file<P: AsRef<Path>>(path: P, client_id: usize) -> PathBuf {
+    let path = path.as_ref();
+    let mut name = path.file_stem().unwrap_or_default().to_os_string();
+    name.push(format!("-client-{}", client_id));
+
+    if let Some(extension) = path.extension() {
+        name.push(".");
+        name.push(extension);
+    }
+
+    path.with_file_name(name)
+}
+
+/// Creates the first directory `<dir>/<prefix>-<n>` that doesn't exist yet, starting with `n = *counter`,
+/// and advances `counter` past it. This way a restarted client continues after the entries
+/// of its previous run instead of overwriting them.
+pub(crate) fn create_unique_dir(dir: &Path, prefix: &str, counter: &mut usize) -> std::io::Result<PathBuf> {
+    std::fs::create_dir_all(dir)?;
+
+    loop {
+        let path = dir.join(format!("{}-{}", prefix, counter));
+        *counter += 1;
+
+        match std::fs::create_dir(&path) {
+            Ok(()) => return Ok(path),
+            Err(err) if err.kind() == ErrorKind::AlreadyExists => continue,
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+/// Like [`create_unique_dir`] but creates the first file `<dir>/<prefix>-<n>.<extension>` that doesn't exist yet.
+pub(crate) fn create_unique_file(dir: &Path, prefix: &str, extension: &str, counter: &mut usize) -> std::io::Result<(PathBuf, File)> {
+    std::fs::create_dir_all(dir)?;
+
+    loop {
+        let path = dir.join(format!("{}-{}.{}", prefix, counter, extension));
+        *counter += 1;
+
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(file) => return Ok((path, file)),
+            Err(err) if err.kind() == ErrorKind::AlreadyExists => continue,
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_client_paths() {
+        assert_eq!(client_dir("out/crashes", 3), PathBuf::from("out/crashes/client-3"));
+        assert_eq!(client_file("/tmp/graph.dot", 0), PathBuf::from("/tmp/graph-client-0.dot"));
+        assert_eq!(client_file("graph", 12), PathBuf::from("graph-client-12"));
+    }
+
+    #[test]
+    fn test_create_unique_dir() {
+        let dir = std::env::temp_dir().join(format!("butterfly-unique-dir-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("crash-1")).unwrap();
+
+        // A restarted client starts counting at 0 again
+        let mut counter = 0;
+        assert_eq!(create_unique_dir(&dir, "crash", &mut counter).unwrap(), dir.join("crash-0"));
+        assert_eq!(create_unique_dir(&dir, "crash", &mut counter).unwrap(), dir.join("crash-2"));
+        assert_eq!(counter, 3);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_create_unique_file() {
+        let dir = std::env::temp_dir().join(format!("butterfly-unique-file-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("crash-0.pcap"), b"old").unwrap();
+
+        let mut counter = 0;
+        let (path, _) = create_unique_file(&dir, "crash", "pcap", &mut counter).unwrap();
+        assert_eq!(path, dir.join("crash-1.pcap"));
+        assert_eq!(counter, 2);
+        assert_eq!(std::fs::read(dir.join("crash-0.pcap")).unwrap(), b"old");
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,246 @@
+use crate::event::{register_user_stat, UserStatFormat, USER_STAT_PHASE};
+use libafl::{
+    bolts::{current_time, rands::Rand},
+    corpus::Corpus,
+    events::{Event, EventFirer},
+    impl_serdeany,
+    inputs::Input,
+    monitors::UserStats,
+    schedulers::Scheduler,
+    stages::Stage,
+    state::{HasCorpus, HasMetadata, HasRand, HasSolutions},
+    Error,
+};
+use serde::{Deserialize, Serialize};
+use std::marker::PhantomData;
+use std::time::Duration;
+
+/// A preferred input is picked in 3 out of this many draws, the rest is uniform
+const PREFERENCE_ODDS: u64 = 4;
+/// The newest 1 / FRONTIER_SHARE of the corpus counts as the frontier
+const FRONTIER_SHARE: usize = 4;
+
+/// The phase of a campaign that alternates between exploration and exploitation, see [`PhaseStage`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Phase {
+    /// Find new states: prefer inputs that reached the newest states and structural mutators
+    #[default]
+    Exploration,
+    /// Find crashes: prefer inputs whose mutations crashed the target before and havoc mutators
+    Exploitation,
+}
+
+impl Phase {
+    /// The name of the phase as it is reported to the monitor
+    pub fn name(&self) -> &'static str {
+        match self {
+            Phase::Exploration => "exploration",
+            Phase::Exploitation => "exploitation",
+        }
+    }
+}
+
+/// The current [`Phase`] of the campaign and what the [`PhaseStage`] learned about the corpus,
+/// stored in the metadata of the state
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PhaseMetadata {
+    /// The current phase
+    pub phase: Phase,
+    /// Indices of the corpus entries whose mutations produced a solution
+    pub crash_adjacent: Vec<usize>,
+    slice_start: Option<Duration>,
+    solutions: usize,
+}
+
+impl_serdeany!(PhaseMetadata);
+
+/// A stage that alternates the campaign between an exploration and an exploitation [`Phase`] in fixed time slices.
+///
+/// The phase is stored in the [`PhaseMetadata`] of the state, from where
+/// - the [`PhaseScheduler`] picks corpus entries that fit the phase and
+/// - the [`PacketMutationScheduler`](crate::PacketMutationScheduler) picks mutators with the weights of the phase,
+///   see [`with_phase_weights()`](crate::PacketMutationScheduler::with_phase_weights).
+///
+/// The stage also remembers the corpus entries whose mutations produced a solution,
+/// so it must come after the mutational stages. Every phase change is reported to the monitor
+/// with the user stat [`USER_STAT_PHASE`](crate::USER_STAT_PHASE).
+///
+/// # Example
+/// ```
+/// let mutator = PacketMutationScheduler::new(tuple_list!(
+///     PacketReorderMutator::new(),
+///     PacketDuplicateMutator::new(16),
+///     PacketHavocMutator::new(supported_havoc_mutations()),
+/// ))
+/// .with_phase_weights(vec![4.0, 4.0, 1.0], vec![1.0, 1.0, 8.0]);
+///
+/// // 10 minutes of exploration, then 5 minutes of exploitation
+/// let mut stages = tuple_list!(
+///     StdMutationalStage::new(mutator),
+///     PhaseStage::new(Duration::from_secs(600), Duration::from_secs(300)),
+/// );
+/// let mut fuzzer = StdFuzzer::new(PhaseScheduler::new(), feedback, objective);
+/// ```
+#[derive(Clone, Debug)]
+pub struct PhaseStage<I> {
+    exploration: Duration,
+    exploitation: Duration,
+    phantom: PhantomData<I>,
+}
+
+impl<I> PhaseStage<I> {
+    /// Create a new PhaseStage that explores for `exploration` and exploits for `exploitation`
+    pub fn new(exploration: Duration, exploitation: Duration) -> Self {
+        register_user_stat(USER_STAT_PHASE, "phase", UserStatFormat::Text);
+
+        Self {
+            exploration,
+            exploitation,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Returns the length of the time slice of `phase`
+    fn slice(&self, phase: Phase) -> Duration {
+        match phase {
+            Phase::Exploration => self.exploration,
+            Phase::Exploitation => self.exploitation,
+        }
+    }
+}
+
+impl<E, EM, I, S, Z> Stage<E, EM, S, Z> for PhaseStage<I>
+where
+    I: Input,
+    EM: EventFirer<I>,
+    S: HasCorpus<I> + HasSolutions<I> + HasMetadata,
+{
+    fn perform(&mut self, _fuzzer: &mut Z, _executor: &mut E, state: &mut S, manager: &mut EM, corpus_idx: usize) -> Result<(), Error> {
+        let solutions = state.solutions().count();
+        let now = current_time();
+
+        if !state.has_metadata::<PhaseMetadata>() {
+            state.add_metadata(PhaseMetadata {
+                solutions,
+                ..PhaseMetadata::default()
+            });
+        }
+
+        let metadata = state.metadata_mut().get_mut::<PhaseMetadata>().unwrap();
+
+        if solutions > metadata.solutions && !metadata.crash_adjacent.contains(&corpus_idx) {
+            metadata.crash_adjacent.push(corpus_idx);
+        }
+        metadata.solutions = solutions;
+
+        let started = metadata.slice_start.is_some();
+        let slice_start = *metadata.slice_start.get_or_insert(now);
+
+        if started && now.saturating_sub(slice_start) < self.slice(metadata.phase) {
+            return Ok(());
+        }
+
+        if started {
+            metadata.phase = match metadata.phase {
+                Phase::Exploration => Phase::Exploitation,
+                Phase::Exploitation => Phase::Exploration,
+            };
+            metadata.slice_start = Some(now);
+        }
+
+        let phase = metadata.phase;
+
+        manager.fire(
+            state,
+            Event::UpdateUserStats {
+                name: USER_STAT_PHASE.to_string(),
+                value: UserStats::String(phase.name().to_string()),
+                phantom: PhantomData,
+            },
+        )
+    }
+}
+
+/// A corpus scheduler that picks entries according to the [`Phase`] of the campaign.
+///
+/// - During exploration it prefers the newest quarter of the corpus. These are the inputs that
+///   discovered the most recent states, i.e. they reach the frontier of the state-graph.
+/// - During exploitation it prefers the entries whose mutations already produced a solution.
+///
+/// A quarter of all picks are uniform so that no entry starves.
+/// Without a [`PhaseStage`] or outside of any phase it picks uniformly at random.
+#[derive(Clone, Debug, Default)]
+pub struct PhaseScheduler;
+
+impl PhaseScheduler {
+    /// Create a new PhaseScheduler
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl<I, S> Scheduler<I, S> for PhaseScheduler
+where
+    I: Input,
+    S: HasCorpus<I> + HasMetadata + HasRand,
+{
+    fn next(&self, state: &mut S) -> Result<usize, Error> {
+        let count = state.corpus().count();
+
+        if count == 0 {
+            return Err(Error::empty("No entries in corpus".to_owned()));
+        }
+
+        let preferred: Vec<usize> = match state.metadata().get::<PhaseMetadata>() {
+            Some(metadata) if metadata.phase == Phase::Exploration => (count - (count / FRONTIER_SHARE).max(1)..count).collect(),
+            Some(metadata) => metadata.crash_adjacent.iter().copied().filter(|idx| *idx < count).collect(),
+            None => Vec::new(),
+        };
+
+        let idx = if !preferred.is_empty() && state.rand_mut().below(PREFERENCE_ODDS) != 0 { state.rand_mut().choose(preferred) } else { state.rand_mut().below(count as u64) as usize };
+
+        *state.corpus_mut().current_mut() = Some(idx);
+        Ok(idx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libafl::{
+        bolts::rands::StdRand,
+        corpus::{InMemoryCorpus, Testcase},
+        events::NopEventManager,
+        inputs::BytesInput,
+        state::StdState,
+    };
+
+    #[test]
+    fn test_phases() {
+        let mut state = StdState::new(StdRand::with_seed(0), InMemoryCorpus::<BytesInput>::new(), InMemoryCorpus::<BytesInput>::new(), &mut (), &mut ()).unwrap();
+        let mut mgr = NopEventManager {};
+        let mut stage = PhaseStage::new(Duration::ZERO, Duration::from_secs(3600));
+        let scheduler = PhaseScheduler::new();
+
+        for i in 0..8 {
+            state.corpus_mut().add(Testcase::new(BytesInput::new(vec![i]))).unwrap();
+        }
+
+        // The first run only starts the exploration
+        stage.perform(&mut (), &mut (), &mut state, &mut mgr, 0).unwrap();
+        assert_eq!(state.metadata().get::<PhaseMetadata>().unwrap().phase, Phase::Exploration);
+        assert!((0..100).filter(|_| scheduler.next(&mut state).unwrap() >= 6).count() > 50);
+
+        // Entry 3 produced a solution and exploration is over
+        state.solutions_mut().add(Testcase::new(BytesInput::new(vec![0]))).unwrap();
+        stage.perform(&mut (), &mut (), &mut state, &mut mgr, 3).unwrap();
+        let metadata = state.metadata().get::<PhaseMetadata>().unwrap();
+        assert_eq!(metadata.phase, Phase::Exploitation);
+        assert_eq!(metadata.crash_adjacent, vec![3]);
+        assert!((0..100).filter(|_| scheduler.next(&mut state).unwrap() == 3).count() > 50);
+
+        // Exploitation lasts an hour
+        stage.perform(&mut (), &mut (), &mut state, &mut mgr, 5).unwrap();
+        assert_eq!(state.metadata().get::<PhaseMetadata>().unwrap().phase, Phase::Exploitation);
+    }
+}
@@ -0,0 +1,196 @@
+use libafl::{
+    bolts::{current_time, rands::Rand},
+    corpus::Corpus,
+    fuzzer::Evaluator,
+    impl_serdeany,
+    inputs::Input,
+    mutators::Mutator,
+    stages::Stage,
+    state::{HasClientPerfMonitor, HasCorpus, HasMetadata, HasRand},
+    Error,
+};
+use serde::{Deserialize, Serialize};
+use std::marker::PhantomData;
+use std::time::Duration;
+
+/// Upper bound on the number of mutations per testcase, same as in LibAFLs `StdMutationalStage`
+const DEFAULT_MAX_ITERATIONS: u64 = 128;
+
+/// The execution times that the [`CostAwareMutationalStage`] measured over all testcases,
+/// stored in the metadata of the state
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExecCostMetadata {
+    /// Sum of all measured execution times
+    pub total: Duration,
+    /// Number of measured executions
+    pub executions: u64,
+}
+
+impl_serdeany!(ExecCostMetadata);
+
+impl ExecCostMetadata {
+    /// Returns the average execution time or `None` if nothing was measured yet
+    pub fn average(&self) -> Option<Duration> {
+        if self.executions == 0 {
+            None
+        } else {
+            Some(self.total.div_f64(self.executions as f64))
+        }
+    }
+}
+
+/// A mutational stage that gives expensive testcases fewer mutations.
+///
+/// Long sessions with many packets cost a lot more per execution than short ones, yet LibAFLs
+/// `StdMutationalStage` gives all testcases the same number of mutations, so a handful of slow seeds
+/// can monopolize the campaign. This stage measures how long the executions of every testcase take
+/// and stores the average as the exec time of the testcase. The number of mutations is then
+/// drawn like in the `StdMutationalStage` but scaled by `average cost / cost of the testcase`.
+///
+/// Testcases that are cheaper than the average are not boosted, this only caps expensive ones.
+/// At least `min_iterations` mutations are always performed.
+///
+/// # Example
+/// ```
+/// let mut stages = tuple_list!(CostAwareMutationalStage::new(mutator).with_min_iterations(4));
+/// ```
+#[derive(Clone, Debug)]
+pub struct CostAwareMutationalStage<I, M> {
+    mutator: M,
+    max_iterations: u64,
+    min_iterations: usize,
+    phantom: PhantomData<I>,
+}
+
+impl<I, M> CostAwareMutationalStage<I, M> {
+    /// Create a new CostAwareMutationalStage that performs up to 128 mutations per testcase
+    pub fn new(mutator: M) -> Self {
+        Self {
+            mutator,
+            max_iterations: DEFAULT_MAX_ITERATIONS,
+            min_iterations: 1,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Perform up to `max_iterations` mutations per testcase
+    pub fn with_max_iterations(mut self, max_iterations: u64) -> Self {
+        self.max_iterations = max_iterations.max(1);
+        self
+    }
+
+    /// Perform at least `min_iterations` mutations per testcase, no matter how expensive it is
+    pub fn with_min_iterations(mut self, min_iterations: usize) -> Self {
+        self.min_iterations = min_iterations.max(1);
+        self
+    }
+
+    /// Returns the mutator of this stage
+    pub fn mutator(&self) -> &M {
+        &self.mutator
+    }
+
+    /// Returns the mutator of this stage
+    pub fn mutator_mut(&mut self) -> &mut M {
+        &mut self.mutator
+    }
+
+    /// Get the number of mutations for the testcase at `corpus_idx`
+    pub fn iterations<S>(&self, state: &mut S, corpus_idx: usize) -> Result<usize, Error>
+    where
+        I: Input,
+        S: HasCorpus<I> + HasMetadata + HasRand,
+    {
+        let iterations = 1 + state.rand_mut().below(self.max_iterations) as usize;
+        let cost = *state.corpus().get(corpus_idx)?.borrow().exec_time();
+        let average = state.metadata().get::<ExecCostMetadata>().and_then(ExecCostMetadata::average);
+
+        let factor = match (cost, average) {
+            (Some(cost), Some(average)) if cost > average => average.as_secs_f64() / cost.as_secs_f64(),
+            _ => 1.0,
+        };
+
+        Ok(((iterations as f64 * factor).round() as usize).max(self.min_iterations))
+    }
+}
+
+impl<E, EM, I, M, S, Z> Stage<E, EM, S, Z> for CostAwareMutationalStage<I, M>
+where
+    M: Mutator<I, S>,
+    I: Input,
+    S: HasClientPerfMonitor + HasCorpus<I> + HasMetadata + HasRand,
+    Z: Evaluator<E, EM, I, S>,
+{
+    fn perform(&mut self, fuzzer: &mut Z, executor: &mut E, state: &mut S, manager: &mut EM, corpus_idx: usize) -> Result<(), Error> {
+        let num = self.iterations(state, corpus_idx)?;
+        let mut cost = Duration::ZERO;
+
+        for i in 0..num {
+            let mut input = state.corpus().get(corpus_idx)?.borrow_mut().load_input()?.clone();
+            self.mutator.mutate(state, &mut input, i as i32)?;
+
+            let start = current_time();
+            let (_, new_idx) = fuzzer.evaluate_input(state, executor, manager, input)?;
+            cost += current_time().saturating_sub(start);
+
+            self.mutator.post_exec(state, i as i32, new_idx)?;
+        }
+
+        state.corpus().get(corpus_idx)?.borrow_mut().set_exec_time(cost / num as u32);
+
+        if !state.has_metadata::<ExecCostMetadata>() {
+            state.add_metadata(ExecCostMetadata::default());
+        }
+
+        let metadata = state.metadata_mut().get_mut::<ExecCostMetadata>().unwrap();
+        metadata.total += cost;
+        metadata.executions += num as u64;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libafl::{
+        bolts::rands::StdRand,
+        corpus::{InMemoryCorpus, Testcase},
+        inputs::BytesInput,
+        mutators::MutationResult,
+        state::StdState,
+    };
+
+    struct NopMutator;
+
+    impl<I: Input, S> Mutator<I, S> for NopMutator {
+        fn mutate(&mut self, _state: &mut S, _input: &mut I, _stage_idx: i32) -> Result<MutationResult, Error> {
+            Ok(MutationResult::Skipped)
+        }
+    }
+
+    #[test]
+    fn test_cost_cap() {
+        let mut state = StdState::new(StdRand::with_seed(0), InMemoryCorpus::<BytesInput>::new(), InMemoryCorpus::<BytesInput>::new(), &mut (), &mut ()).unwrap();
+        let stage = CostAwareMutationalStage::new(NopMutator).with_max_iterations(100).with_min_iterations(2);
+
+        for millis in [1, 1, 20] {
+            let mut testcase = Testcase::new(BytesInput::new(vec![0; millis]));
+            testcase.set_exec_time(Duration::from_millis(millis as u64));
+            state.corpus_mut().add(testcase).unwrap();
+        }
+
+        // Without measurements everything gets the full range
+        assert!((0..100).any(|_| stage.iterations(&mut state, 2).unwrap() > 50));
+
+        state.add_metadata(ExecCostMetadata {
+            total: Duration::from_millis(200),
+            executions: 100,
+        });
+        assert_eq!(state.metadata().get::<ExecCostMetadata>().unwrap().average(), Some(Duration::from_millis(2)));
+
+        // The slow testcase costs 10x the average
+        assert!((0..100).all(|_| (2..=10).contains(&stage.iterations(&mut state, 2).unwrap())));
+        assert!((0..100).any(|_| stage.iterations(&mut state, 0).unwrap() > 50));
+    }
+}
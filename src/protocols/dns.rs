@@ -0,0 +1,592 @@
+//! Packet and input types for DNS.
+//!
+//! __Only available with feature__: `protocol_dns`
+//!
+//! # Example
+//! ```
+//! load_pcaps::<_, _, _, _, DnsInput, _>(&mut state, &mut fuzzer, &mut executor, &mut mgr, "pcaps").unwrap();
+//! ```
+
+use crate::{
+    executor::HasPayload,
+    input::{capture_segments, HasPackets, HasPcapRepresentation, TransportProtocol},
+    mutators::{HasCrossoverInsertMutation, HasCrossoverReplaceMutation, HasHavocMutation, HasPostMutationFixup, HasSpliceMutation},
+};
+use libafl::{
+    bolts::{rands::Rand, HasLen},
+    inputs::{BytesInput, HasBytesVec, Input},
+    mutators::{MutationResult, MutatorsTuple},
+    state::{HasMaxSize, HasRand},
+    Error,
+};
+use pcap::{Capture, Offline};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+const DNS_PORT: u16 = 53;
+const MAX_LABEL_LEN: usize = 63;
+const MAX_POINTER: usize = 0x3FFF;
+/// Maximum number of compression pointers followed while reading a single name
+const MAX_POINTER_HOPS: usize = 32;
+
+const TYPE_NS: u16 = 2;
+const TYPE_CNAME: u16 = 5;
+const TYPE_SOA: u16 = 6;
+const TYPE_PTR: u16 = 12;
+const TYPE_MX: u16 = 15;
+
+/// Types that a havoc mutation chooses from when it changes the type of a question or record
+const INTERESTING_TYPES: [u16; 14] = [1, TYPE_NS, TYPE_CNAME, TYPE_SOA, TYPE_PTR, TYPE_MX, 16, 28, 33, 41, 43, 46, 252, 255];
+
+/// A domain name, stored as a list of uncompressed labels.
+///
+/// Compression gets applied when the packet is serialized.
+#[derive(Hash, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DnsName {
+    labels: Vec<BytesInput>,
+}
+
+impl DnsName {
+    /// Create a name from a dotted string like `www.example.com`
+    pub fn from_dotted(name: &str) -> Self {
+        Self {
+            labels: name.split('.').filter(|label| !label.is_empty()).map(|label| BytesInput::new(label.as_bytes().to_vec())).collect(),
+        }
+    }
+
+    /// Get the labels of the name
+    pub fn labels(&self) -> &[BytesInput] {
+        &self.labels
+    }
+
+    /// Labels as they are written on the wire: non-empty and at most 63 bytes long
+    fn wire_labels(&self) -> Vec<&[u8]> {
+        self.labels.iter().map(|label| &label.bytes()[..label.bytes().len().min(MAX_LABEL_LEN)]).filter(|label| !label.is_empty()).collect()
+    }
+}
+
+/// The RDATA of a resource record.
+///
+/// Records that contain names get parsed such that compression pointers
+/// stay valid after mutations.
+#[derive(Hash, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DnsRecordData {
+    /// NS, CNAME and PTR records
+    Name(DnsName),
+    /// MX records
+    Mx(u16, DnsName),
+    /// Everything else, uninterpreted
+    Raw(BytesInput),
+}
+
+/// An entry of the question section
+#[derive(Hash, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DnsQuestion {
+    /// The name that is queried
+    pub name: DnsName,
+    /// QTYPE
+    pub qtype: u16,
+    /// QCLASS
+    pub qclass: u16,
+}
+
+/// A resource record of the answer, authority or additional section
+#[derive(Hash, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DnsRecord {
+    /// The owner of the record
+    pub name: DnsName,
+    /// TYPE
+    pub rtype: u16,
+    /// CLASS
+    pub class: u16,
+    /// TTL
+    pub ttl: u32,
+    /// RDATA
+    pub data: DnsRecordData,
+}
+
+/// A DNS message.
+///
+/// The section counts in the header are computed from the sections when the
+/// packet is serialized and names are compressed, so mutations can never
+/// produce inconsistent counts or dangling compression pointers.
+///
+/// The havoc mutation either mutates a label of a name, RDATA or replaces the
+/// type of a question or record. Crossover and splice mutations combine
+/// labels and RDATA of two packets.
+#[derive(Hash, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DnsPacket {
+    /// Transaction ID
+    pub id: u16,
+    /// Flags, including opcode and rcode
+    pub flags: u16,
+    /// Question section
+    pub questions: Vec<DnsQuestion>,
+    /// Answer section
+    pub answers: Vec<DnsRecord>,
+    /// Authority section
+    pub authorities: Vec<DnsRecord>,
+    /// Additional section
+    pub additionals: Vec<DnsRecord>,
+}
+
+#[inline]
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_be_bytes([*data.get(offset)?, *data.get(offset + 1)?]))
+}
+
+/// Read a possibly compressed name at `offset`. Returns the name and the offset after it.
+fn read_name(data: &[u8], offset: usize) -> Option<(DnsName, usize)> {
+    let mut labels = Vec::new();
+    let mut pos = offset;
+    let mut end = None;
+    let mut hops = 0;
+
+    loop {
+        let len = *data.get(pos)? as usize;
+
+        if len & 0xC0 == 0xC0 {
+            hops += 1;
+
+            if hops > MAX_POINTER_HOPS {
+                return None;
+            }
+
+            end.get_or_insert(pos + 2);
+            pos = read_u16(data, pos)? as usize & MAX_POINTER;
+        } else if len == 0 {
+            end.get_or_insert(pos + 1);
+            break;
+        } else {
+            labels.push(BytesInput::new(data.get(pos + 1..pos + 1 + len)?.to_vec()));
+            pos += 1 + len;
+        }
+    }
+
+    Some((
+        DnsName {
+            labels,
+        },
+        end?,
+    ))
+}
+
+/// Write a name without compression
+fn write_uncompressed_name(name: &DnsName, out: &mut Vec<u8>) {
+    for label in name.wire_labels() {
+        out.push(label.len() as u8);
+        out.extend_from_slice(label);
+    }
+    out.push(0);
+}
+
+fn read_record(data: &[u8], offset: usize) -> Option<(DnsRecord, usize)> {
+    let (name, pos) = read_name(data, offset)?;
+    let rtype = read_u16(data, pos)?;
+    let class = read_u16(data, pos + 2)?;
+    let ttl = u32::from_be_bytes(data.get(pos + 4..pos + 8)?.try_into().ok()?);
+    let rdlen = read_u16(data, pos + 8)? as usize;
+    let start = pos + 10;
+    let rdata = data.get(start..start + rdlen)?;
+
+    let data = match rtype {
+        TYPE_NS | TYPE_CNAME | TYPE_PTR => DnsRecordData::Name(read_name(data, start)?.0),
+        TYPE_MX => DnsRecordData::Mx(read_u16(data, start)?, read_name(data, start + 2)?.0),
+        TYPE_SOA => {
+            // Expand the two names so that the record does not depend on compression
+            let (mname, pos) = read_name(data, start)?;
+            let (rname, pos) = read_name(data, pos)?;
+            let mut raw = Vec::new();
+            write_uncompressed_name(&mname, &mut raw);
+            write_uncompressed_name(&rname, &mut raw);
+            raw.extend_from_slice(data.get(pos..start + rdlen)?);
+            DnsRecordData::Raw(BytesInput::new(raw))
+        },
+        _ => DnsRecordData::Raw(BytesInput::new(rdata.to_vec())),
+    };
+
+    Some((
+        DnsRecord {
+            name,
+            rtype,
+            class,
+            ttl,
+            data,
+        },
+        start + rdlen,
+    ))
+}
+
+/// Serializes names with compression
+struct NameWriter {
+    suffixes: HashMap<Vec<Vec<u8>>, usize>,
+}
+
+impl NameWriter {
+    fn new() -> Self {
+        Self {
+            suffixes: HashMap::new(),
+        }
+    }
+
+    fn write(&mut self, name: &DnsName, out: &mut Vec<u8>) {
+        let labels = name.wire_labels();
+
+        for i in 0..labels.len() {
+            let suffix: Vec<Vec<u8>> = labels[i..].iter().map(|label| label.to_vec()).collect();
+
+            if let Some(offset) = self.suffixes.get(&suffix) {
+                out.extend_from_slice(&(0xC000 | *offset as u16).to_be_bytes());
+                return;
+            }
+
+            if out.len() <= MAX_POINTER {
+                self.suffixes.insert(suffix, out.len());
+            }
+
+            out.push(labels[i].len() as u8);
+            out.extend_from_slice(labels[i]);
+        }
+
+        out.push(0);
+    }
+}
+
+impl DnsPacket {
+    /// Parse a DNS message as it is sent over UDP
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        let counts = [read_u16(data, 4)?, read_u16(data, 6)?, read_u16(data, 8)?, read_u16(data, 10)?];
+        let mut packet = Self {
+            id: read_u16(data, 0)?,
+            flags: read_u16(data, 2)?,
+            questions: Vec::new(),
+            answers: Vec::new(),
+            authorities: Vec::new(),
+            additionals: Vec::new(),
+        };
+        let mut pos = 12;
+
+        for _ in 0..counts[0] {
+            let (name, next) = read_name(data, pos)?;
+            packet.questions.push(DnsQuestion {
+                name,
+                qtype: read_u16(data, next)?,
+                qclass: read_u16(data, next + 2)?,
+            });
+            pos = next + 4;
+        }
+
+        for (count, section) in counts[1..].iter().zip([&mut packet.answers, &mut packet.authorities, &mut packet.additionals]) {
+            for _ in 0..*count {
+                let (record, next) = read_record(data, pos)?;
+                section.push(record);
+                pos = next;
+            }
+        }
+
+        Some(packet)
+    }
+
+    /// Serialize the message as it is sent over UDP
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(512);
+        let mut names = NameWriter::new();
+
+        out.extend_from_slice(&self.id.to_be_bytes());
+        out.extend_from_slice(&self.flags.to_be_bytes());
+
+        for len in [self.questions.len(), self.answers.len(), self.authorities.len(), self.additionals.len()] {
+            out.extend_from_slice(&(len.min(u16::MAX as usize) as u16).to_be_bytes());
+        }
+
+        for question in &self.questions {
+            names.write(&question.name, &mut out);
+            out.extend_from_slice(&question.qtype.to_be_bytes());
+            out.extend_from_slice(&question.qclass.to_be_bytes());
+        }
+
+        for record in self.answers.iter().chain(&self.authorities).chain(&self.additionals) {
+            names.write(&record.name, &mut out);
+            out.extend_from_slice(&record.rtype.to_be_bytes());
+            out.extend_from_slice(&record.class.to_be_bytes());
+            out.extend_from_slice(&record.ttl.to_be_bytes());
+
+            let len_offset = out.len();
+            out.extend_from_slice(&[0, 0]);
+
+            match &record.data {
+                DnsRecordData::Name(name) => names.write(name, &mut out),
+                DnsRecordData::Mx(preference, name) => {
+                    out.extend_from_slice(&preference.to_be_bytes());
+                    names.write(name, &mut out);
+                },
+                DnsRecordData::Raw(data) => out.extend_from_slice(&data.bytes()[..data.bytes().len().min(u16::MAX as usize)]),
+            }
+
+            let rdlen = (out.len() - len_offset - 2) as u16;
+            out[len_offset..len_offset + 2].copy_from_slice(&rdlen.to_be_bytes());
+        }
+
+        out
+    }
+
+    /// Serialize the message as it is sent over TCP, with a length prefix
+    pub fn to_tcp_bytes(&self) -> Vec<u8> {
+        let data = self.to_bytes();
+        [&(data.len() as u16).to_be_bytes()[..], &data].concat()
+    }
+
+    fn records(&self) -> impl Iterator<Item = &DnsRecord> {
+        self.answers.iter().chain(&self.authorities).chain(&self.additionals)
+    }
+
+    /// All labels and RDATA that can be mutated
+    fn fields(&self) -> Vec<&BytesInput> {
+        let mut fields: Vec<&BytesInput> = self.questions.iter().flat_map(|q| q.name.labels.iter()).collect();
+
+        for record in self.records() {
+            fields.extend(record.name.labels.iter());
+
+            match &record.data {
+                DnsRecordData::Name(name) | DnsRecordData::Mx(_, name) => fields.extend(name.labels.iter()),
+                DnsRecordData::Raw(data) => fields.push(data),
+            }
+        }
+
+        fields
+    }
+
+    /// All labels and RDATA that can be mutated (mutable)
+    fn fields_mut(&mut self) -> Vec<&mut BytesInput> {
+        let mut fields: Vec<&mut BytesInput> = self.questions.iter_mut().flat_map(|q| q.name.labels.iter_mut()).collect();
+
+        for record in self.answers.iter_mut().chain(&mut self.authorities).chain(&mut self.additionals) {
+            fields.extend(record.name.labels.iter_mut());
+
+            match &mut record.data {
+                DnsRecordData::Name(name) | DnsRecordData::Mx(_, name) => fields.extend(name.labels.iter_mut()),
+                DnsRecordData::Raw(data) => fields.push(data),
+            }
+        }
+
+        fields
+    }
+
+    /// Pick a random field of self and other
+    fn pick_fields<'a, S>(&'a mut self, state: &mut S, other: &'a Self) -> Option<(&'a mut BytesInput, &'a BytesInput)>
+    where
+        S: HasRand,
+    {
+        let mut fields = self.fields_mut();
+        let other_fields = other.fields();
+
+        if fields.is_empty() || other_fields.is_empty() {
+            return None;
+        }
+
+        let idx = state.rand_mut().below(fields.len() as u64) as usize;
+        let other_idx = state.rand_mut().below(other_fields.len() as u64) as usize;
+        Some((fields.swap_remove(idx), other_fields[other_idx]))
+    }
+
+    /// Replace the type of a random question or record
+    fn mutate_type<S>(&mut self, state: &mut S) -> MutationResult
+    where
+        S: HasRand,
+    {
+        let num_records = self.records().count();
+        let total = self.questions.len() + num_records;
+
+        if total == 0 {
+            return MutationResult::Skipped;
+        }
+
+        let idx = state.rand_mut().below(total as u64) as usize;
+        let new_type = if state.rand_mut().below(8) == 0 { state.rand_mut().below(u16::MAX as u64 + 1) as u16 } else { *state.rand_mut().choose(&INTERESTING_TYPES) };
+
+        let field = if idx < self.questions.len() {
+            &mut self.questions[idx].qtype
+        } else {
+            let idx = idx - self.questions.len();
+            &mut self.answers.iter_mut().chain(&mut self.authorities).chain(&mut self.additionals).nth(idx).unwrap().rtype
+        };
+
+        if *field == new_type {
+            MutationResult::Skipped
+        } else {
+            *field = new_type;
+            MutationResult::Mutated
+        }
+    }
+}
+
+impl HasPayload for DnsPacket {
+    fn payload(&self) -> Vec<u8> {
+        self.to_bytes()
+    }
+}
+
+impl HasPostMutationFixup for DnsPacket {}
+
+impl<S> HasCrossoverInsertMutation<S> for DnsPacket
+where
+    S: HasRand + HasMaxSize,
+{
+    fn mutate_crossover_insert(&mut self, state: &mut S, other: &Self, stage_idx: i32) -> Result<MutationResult, Error> {
+        match self.pick_fields(state, other) {
+            Some((field, other_field)) => field.mutate_crossover_insert(state, other_field, stage_idx),
+            None => Ok(MutationResult::Skipped),
+        }
+    }
+}
+
+impl<S> HasCrossoverReplaceMutation<S> for DnsPacket
+where
+    S: HasRand + HasMaxSize,
+{
+    fn mutate_crossover_replace(&mut self, state: &mut S, other: &Self, stage_idx: i32) -> Result<MutationResult, Error> {
+        match self.pick_fields(state, other) {
+            Some((field, other_field)) => field.mutate_crossover_replace(state, other_field, stage_idx),
+            None => Ok(MutationResult::Skipped),
+        }
+    }
+}
+
+impl<S> HasSpliceMutation<S> for DnsPacket
+where
+    S: HasRand + HasMaxSize,
+{
+    fn mutate_splice(&mut self, state: &mut S, other: &Self, stage_idx: i32) -> Result<MutationResult, Error> {
+        match self.pick_fields(state, other) {
+            Some((field, other_field)) => field.mutate_splice(state, other_field, stage_idx),
+            None => Ok(MutationResult::Skipped),
+        }
+    }
+}
+
+impl<MT, S> HasHavocMutation<MT, S> for DnsPacket
+where
+    MT: MutatorsTuple<BytesInput, S>,
+    S: HasRand + HasMaxSize,
+{
+    fn mutate_havoc(&mut self, state: &mut S, mutations: &mut MT, mutation: usize, stage_idx: i32) -> Result<MutationResult, Error> {
+        if state.rand_mut().below(4) == 0 {
+            return Ok(self.mutate_type(state));
+        }
+
+        let mut fields = self.fields_mut();
+
+        if fields.is_empty() {
+            return Ok(MutationResult::Skipped);
+        }
+
+        let idx = state.rand_mut().below(fields.len() as u64) as usize;
+        mutations.get_and_mutate(mutation, state, fields[idx], stage_idx)
+    }
+}
+
+/// A sequence of DNS messages sent by a client.
+///
+/// It can be loaded from pcap files, where all UDP datagrams sent to port 53
+/// make up the input.
+#[derive(Hash, Debug, Clone, Serialize, Deserialize)]
+pub struct DnsInput {
+    packets: Vec<DnsPacket>,
+}
+
+impl DnsInput {
+    /// Create a new DnsInput from a list of messages
+    pub fn new(packets: Vec<DnsPacket>) -> Self {
+        Self {
+            packets,
+        }
+    }
+}
+
+impl Input for DnsInput {
+    fn generate_name(&self, idx: usize) -> String {
+        format!("dns-{}", idx)
+    }
+}
+
+impl HasPackets<DnsPacket> for DnsInput {
+    fn packets(&self) -> &[DnsPacket] {
+        &self.packets
+    }
+
+    fn packets_mut(&mut self) -> &mut Vec<DnsPacket> {
+        &mut self.packets
+    }
+}
+
+impl HasLen for DnsInput {
+    fn len(&self) -> usize {
+        self.packets.len()
+    }
+}
+
+impl HasPcapRepresentation<DnsInput> for DnsInput {
+    fn from_pcap(capture: Capture<Offline>) -> Result<Self, Error> {
+        let packets = capture_segments(capture).into_iter().filter(|s| s.protocol == TransportProtocol::Udp && s.dst_port == DNS_PORT).filter_map(|s| DnsPacket::parse(&s.payload)).collect();
+        Ok(Self::new(packets))
+    }
+}
+
+/// State inference function that uses the opcode and the rcode of a response as the state.
+///
+/// Handles responses with and without the TCP length prefix.
+pub fn response_code(response: &[u8]) -> Option<u16> {
+    // A response sent over UDP has an ID, the length prefix over TCP equals the rest of the response
+    let offset = if read_u16(response, 0)? as usize == response.len() - 2 { 2 } else { 0 };
+    let flags = read_u16(response, offset + 2)?;
+    Some(flags & 0x780F)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roundtrip() {
+        let packet = DnsPacket {
+            id: 0x1234,
+            flags: 0x8180,
+            questions: vec![DnsQuestion {
+                name: DnsName::from_dotted("www.example.com"),
+                qtype: 1,
+                qclass: 1,
+            }],
+            answers: vec![
+                DnsRecord {
+                    name: DnsName::from_dotted("www.example.com"),
+                    rtype: TYPE_CNAME,
+                    class: 1,
+                    ttl: 60,
+                    data: DnsRecordData::Name(DnsName::from_dotted("web.example.com")),
+                },
+                DnsRecord {
+                    name: DnsName::from_dotted("web.example.com"),
+                    rtype: 1,
+                    class: 1,
+                    ttl: 60,
+                    data: DnsRecordData::Raw(BytesInput::new(vec![127, 0, 0, 1])),
+                },
+            ],
+            authorities: Vec::new(),
+            additionals: Vec::new(),
+        };
+
+        let bytes = packet.to_bytes();
+        // The second occurence of www.example.com must be a pointer to the first
+        assert_eq!(&bytes[12 + 17 + 4..12 + 17 + 6], &[0xC0, 12]);
+        assert_eq!(DnsPacket::parse(&bytes).unwrap(), packet);
+
+        // Mutating a label must not break the pointers
+        let mut mutated = packet.clone();
+        mutated.questions[0].name.labels[1] = BytesInput::new(b"changed".to_vec());
+        assert_eq!(DnsPacket::parse(&mutated.to_bytes()).unwrap(), mutated);
+
+        assert_eq!(response_code(&bytes), Some(0));
+        assert_eq!(response_code(&packet.to_tcp_bytes()), Some(0));
+    }
+}
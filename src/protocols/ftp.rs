@@ -0,0 +1,414 @@
+//! Packet and input types for FTP.
+//!
+//! __Only available with feature__: `protocol_ftp`
+//!
+//! # Example
+//! ```
+//! let mut executor = NetworkExecutor::new(tuple_list!(state_observer), "127.0.0.1:2121".parse().unwrap(), "state", ftp::status_code).with_greeting();
+//! load_pcaps::<_, _, _, _, FtpInput, _>(&mut state, &mut fuzzer, &mut executor, &mut mgr, "pcaps").unwrap();
+//! ```
+
+use crate::{
+    executor::{status_code_validity, HasPayload, HasValidityOracle, NetworkPacket, Validity},
+    input::{capture_segments, first_tcp_connection, HasPackets, HasPcapRepresentation},
+    mutators::{HasCrossoverInsertMutation, HasCrossoverReplaceMutation, HasHavocMutation, HasKindConversion, HasPostMutationFixup, HasSpliceMutation, HasTerminalPacket},
+    protocols::{lines, parse_reply_code},
+};
+use libafl::{
+    bolts::{rands::Rand, HasLen},
+    inputs::{BytesInput, HasBytesVec, Input},
+    mutators::{MutationResult, MutatorsTuple},
+    state::{HasMaxSize, HasRand},
+    Error,
+};
+use pcap::{Capture, Offline};
+use serde::{Deserialize, Serialize};
+
+/// The verbs of all known commands, that commands get converted to
+const VERBS: [&[u8]; 26] =
+    [b"USER", b"PASS", b"ACCT", b"CWD", b"CDUP", b"PWD", b"MKD", b"RMD", b"DELE", b"RNFR", b"RNTO", b"RETR", b"STOR", b"SIZE", b"LIST", b"NLST", b"TYPE", b"PORT", b"PASV", b"REST", b"SITE", b"SYST", b"FEAT", b"NOOP", b"ABOR", b"QUIT"];
+
+/// A command sent by an FTP client.
+///
+/// Commands that are not known are stored as a whole line in [`FtpCommand::Other`].
+/// Only the arguments of commands are mutated.
+#[derive(Hash, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[allow(missing_docs)]
+pub enum FtpCommand {
+    User(BytesInput),
+    Pass(BytesInput),
+    Acct(BytesInput),
+    Cwd(BytesInput),
+    Cdup,
+    Pwd,
+    Mkd(BytesInput),
+    Rmd(BytesInput),
+    Dele(BytesInput),
+    Rnfr(BytesInput),
+    Rnto(BytesInput),
+    Retr(BytesInput),
+    Stor(BytesInput),
+    Size(BytesInput),
+    List(Option<BytesInput>),
+    Nlst(Option<BytesInput>),
+    Type(BytesInput),
+    Port(BytesInput),
+    Pasv,
+    Rest(BytesInput),
+    Site(BytesInput),
+    Syst,
+    Feat,
+    Noop,
+    Abor,
+    Quit,
+    /// Any other command line, without the terminating `\r\n`
+    Other(BytesInput),
+}
+
+impl FtpCommand {
+    /// Parse a single command line. Returns `None` for empty lines.
+    pub fn from_line(line: &[u8]) -> Option<Self> {
+        let line = line.strip_suffix(b"\n").unwrap_or(line);
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+
+        if line.is_empty() {
+            return None;
+        }
+
+        let (verb, arg) = match line.iter().position(|c| *c == b' ') {
+            Some(idx) => (&line[..idx], Some(BytesInput::new(line[idx + 1..].to_vec()))),
+            None => (line, None),
+        };
+        let verb = verb.to_ascii_uppercase();
+
+        let command = match (&verb[..], arg) {
+            (b"USER", Some(arg)) => FtpCommand::User(arg),
+            (b"PASS", Some(arg)) => FtpCommand::Pass(arg),
+            (b"ACCT", Some(arg)) => FtpCommand::Acct(arg),
+            (b"CWD", Some(arg)) => FtpCommand::Cwd(arg),
+            (b"CDUP", None) => FtpCommand::Cdup,
+            (b"PWD", None) => FtpCommand::Pwd,
+            (b"MKD", Some(arg)) => FtpCommand::Mkd(arg),
+            (b"RMD", Some(arg)) => FtpCommand::Rmd(arg),
+            (b"DELE", Some(arg)) => FtpCommand::Dele(arg),
+            (b"RNFR", Some(arg)) => FtpCommand::Rnfr(arg),
+            (b"RNTO", Some(arg)) => FtpCommand::Rnto(arg),
+            (b"RETR", Some(arg)) => FtpCommand::Retr(arg),
+            (b"STOR", Some(arg)) => FtpCommand::Stor(arg),
+            (b"SIZE", Some(arg)) => FtpCommand::Size(arg),
+            (b"LIST", arg) => FtpCommand::List(arg),
+            (b"NLST", arg) => FtpCommand::Nlst(arg),
+            (b"TYPE", Some(arg)) => FtpCommand::Type(arg),
+            (b"PORT", Some(arg)) => FtpCommand::Port(arg),
+            (b"PASV", None) => FtpCommand::Pasv,
+            (b"REST", Some(arg)) => FtpCommand::Rest(arg),
+            (b"SITE", Some(arg)) => FtpCommand::Site(arg),
+            (b"SYST", None) => FtpCommand::Syst,
+            (b"FEAT", None) => FtpCommand::Feat,
+            (b"NOOP", None) => FtpCommand::Noop,
+            (b"ABOR", None) => FtpCommand::Abor,
+            (b"QUIT", None) => FtpCommand::Quit,
+            _ => FtpCommand::Other(BytesInput::new(line.to_vec())),
+        };
+
+        Some(command)
+    }
+
+    /// Get the verb of the command, empty for [`FtpCommand::Other`]
+    pub fn verb(&self) -> &'static [u8] {
+        match self {
+            FtpCommand::User(_) => b"USER",
+            FtpCommand::Pass(_) => b"PASS",
+            FtpCommand::Acct(_) => b"ACCT",
+            FtpCommand::Cwd(_) => b"CWD",
+            FtpCommand::Cdup => b"CDUP",
+            FtpCommand::Pwd => b"PWD",
+            FtpCommand::Mkd(_) => b"MKD",
+            FtpCommand::Rmd(_) => b"RMD",
+            FtpCommand::Dele(_) => b"DELE",
+            FtpCommand::Rnfr(_) => b"RNFR",
+            FtpCommand::Rnto(_) => b"RNTO",
+            FtpCommand::Retr(_) => b"RETR",
+            FtpCommand::Stor(_) => b"STOR",
+            FtpCommand::Size(_) => b"SIZE",
+            FtpCommand::List(_) => b"LIST",
+            FtpCommand::Nlst(_) => b"NLST",
+            FtpCommand::Type(_) => b"TYPE",
+            FtpCommand::Port(_) => b"PORT",
+            FtpCommand::Pasv => b"PASV",
+            FtpCommand::Rest(_) => b"REST",
+            FtpCommand::Site(_) => b"SITE",
+            FtpCommand::Syst => b"SYST",
+            FtpCommand::Feat => b"FEAT",
+            FtpCommand::Noop => b"NOOP",
+            FtpCommand::Abor => b"ABOR",
+            FtpCommand::Quit => b"QUIT",
+            FtpCommand::Other(_) => b"",
+        }
+    }
+
+    /// Get the argument of the command, if it has one
+    pub fn argument(&self) -> Option<&BytesInput> {
+        match self {
+            FtpCommand::User(arg)
+            | FtpCommand::Pass(arg)
+            | FtpCommand::Acct(arg)
+            | FtpCommand::Cwd(arg)
+            | FtpCommand::Mkd(arg)
+            | FtpCommand::Rmd(arg)
+            | FtpCommand::Dele(arg)
+            | FtpCommand::Rnfr(arg)
+            | FtpCommand::Rnto(arg)
+            | FtpCommand::Retr(arg)
+            | FtpCommand::Stor(arg)
+            | FtpCommand::Size(arg)
+            | FtpCommand::List(Some(arg))
+            | FtpCommand::Nlst(Some(arg))
+            | FtpCommand::Type(arg)
+            | FtpCommand::Port(arg)
+            | FtpCommand::Rest(arg)
+            | FtpCommand::Site(arg)
+            | FtpCommand::Other(arg) => Some(arg),
+            _ => None,
+        }
+    }
+
+    /// Get the argument of the command, if it has one (mutable)
+    pub fn argument_mut(&mut self) -> Option<&mut BytesInput> {
+        match self {
+            FtpCommand::User(arg)
+            | FtpCommand::Pass(arg)
+            | FtpCommand::Acct(arg)
+            | FtpCommand::Cwd(arg)
+            | FtpCommand::Mkd(arg)
+            | FtpCommand::Rmd(arg)
+            | FtpCommand::Dele(arg)
+            | FtpCommand::Rnfr(arg)
+            | FtpCommand::Rnto(arg)
+            | FtpCommand::Retr(arg)
+            | FtpCommand::Stor(arg)
+            | FtpCommand::Size(arg)
+            | FtpCommand::List(Some(arg))
+            | FtpCommand::Nlst(Some(arg))
+            | FtpCommand::Type(arg)
+            | FtpCommand::Port(arg)
+            | FtpCommand::Rest(arg)
+            | FtpCommand::Site(arg)
+            | FtpCommand::Other(arg) => Some(arg),
+            _ => None,
+        }
+    }
+}
+
+impl HasPayload for FtpCommand {
+    fn payload(&self) -> Vec<u8> {
+        let mut ret = self.verb().to_vec();
+
+        match (self, self.argument()) {
+            (FtpCommand::Other(line), _) => ret.extend_from_slice(line.bytes()),
+            (_, Some(arg)) => {
+                ret.push(b' ');
+                ret.extend_from_slice(arg.bytes());
+            },
+            (_, None) => {},
+        }
+
+        ret.extend_from_slice(b"\r\n");
+        ret
+    }
+}
+
+impl HasValidityOracle for FtpCommand {
+    fn validity(&self, response: &[u8]) -> Validity {
+        status_code_validity(response.get(..3).unwrap_or_default(), b"45")
+    }
+}
+
+impl HasPostMutationFixup for FtpCommand {}
+
+impl<S> HasKindConversion<S> for FtpCommand
+where
+    S: HasRand,
+{
+    fn convert_kind(&mut self, state: &mut S) -> bool {
+        let verb = *state.rand_mut().choose(&VERBS);
+
+        if matches!(self, FtpCommand::Other(_)) || verb == self.verb() {
+            return false;
+        }
+
+        // Keep the argument if the new command takes one, otherwise drop it or use an empty one
+        let with_arg = self.argument().map(|arg| [verb, b" ", arg.bytes()].concat());
+        let candidates = [with_arg, Some(verb.to_vec()), Some([verb, b" "].concat())];
+
+        match candidates.iter().flatten().filter_map(|line| FtpCommand::from_line(&[line, &b"\r\n"[..]].concat())).find(|command| !matches!(command, FtpCommand::Other(_))) {
+            Some(command) => {
+                *self = command;
+                true
+            },
+            None => false,
+        }
+    }
+}
+
+impl HasTerminalPacket for FtpCommand {
+    fn is_terminal(&self) -> bool {
+        matches!(self, FtpCommand::Quit)
+    }
+}
+
+impl<S> HasCrossoverInsertMutation<S> for FtpCommand
+where
+    S: HasRand + HasMaxSize,
+{
+    fn mutate_crossover_insert(&mut self, state: &mut S, other: &Self, stage_idx: i32) -> Result<MutationResult, Error> {
+        match (self.argument_mut(), other.argument()) {
+            (Some(arg), Some(other_arg)) => arg.mutate_crossover_insert(state, other_arg, stage_idx),
+            _ => Ok(MutationResult::Skipped),
+        }
+    }
+}
+
+impl<S> HasCrossoverReplaceMutation<S> for FtpCommand
+where
+    S: HasRand + HasMaxSize,
+{
+    fn mutate_crossover_replace(&mut self, state: &mut S, other: &Self, stage_idx: i32) -> Result<MutationResult, Error> {
+        match (self.argument_mut(), other.argument()) {
+            (Some(arg), Some(other_arg)) => arg.mutate_crossover_replace(state, other_arg, stage_idx),
+            _ => Ok(MutationResult::Skipped),
+        }
+    }
+}
+
+impl<S> HasSpliceMutation<S> for FtpCommand
+where
+    S: HasRand + HasMaxSize,
+{
+    fn mutate_splice(&mut self, state: &mut S, other: &Self, stage_idx: i32) -> Result<MutationResult, Error> {
+        match (self.argument_mut(), other.argument()) {
+            (Some(arg), Some(other_arg)) => arg.mutate_splice(state, other_arg, stage_idx),
+            _ => Ok(MutationResult::Skipped),
+        }
+    }
+}
+
+impl<MT, S> HasHavocMutation<MT, S> for FtpCommand
+where
+    MT: MutatorsTuple<BytesInput, S>,
+    S: HasRand + HasMaxSize,
+{
+    fn mutate_havoc(&mut self, state: &mut S, mutations: &mut MT, mutation: usize, stage_idx: i32) -> Result<MutationResult, Error> {
+        match self.argument_mut() {
+            Some(arg) => arg.mutate_havoc(state, mutations, mutation, stage_idx),
+            None => Ok(MutationResult::Skipped),
+        }
+    }
+}
+
+/// An FTP session as seen on the control connection.
+///
+/// It can be loaded from pcap files, where the commands the client sent over
+/// the first TCP connection make up the input. Data connections are ignored.
+#[derive(Hash, Debug, Clone, Serialize, Deserialize)]
+pub struct FtpInput {
+    packets: Vec<NetworkPacket<FtpCommand>>,
+}
+
+impl FtpInput {
+    /// Create a new FtpInput from a list of commands
+    pub fn new(commands: Vec<FtpCommand>) -> Self {
+        Self {
+            packets: commands.into_iter().map(NetworkPacket::Data).collect(),
+        }
+    }
+
+    /// Parse all command lines in a byte stream
+    pub fn parse(stream: &[u8]) -> Self {
+        Self::new(lines(stream).filter_map(FtpCommand::from_line).collect())
+    }
+}
+
+impl Input for FtpInput {
+    fn generate_name(&self, idx: usize) -> String {
+        format!("ftp-{}", idx)
+    }
+}
+
+impl HasPackets<NetworkPacket<FtpCommand>> for FtpInput {
+    fn packets(&self) -> &[NetworkPacket<FtpCommand>] {
+        &self.packets
+    }
+
+    fn packets_mut(&mut self) -> &mut Vec<NetworkPacket<FtpCommand>> {
+        &mut self.packets
+    }
+}
+
+impl HasLen for FtpInput {
+    fn len(&self) -> usize {
+        self.packets.len()
+    }
+}
+
+impl HasPcapRepresentation<FtpInput> for FtpInput {
+    fn from_pcap(capture: Capture<Offline>) -> Result<Self, Error> {
+        let stream = first_tcp_connection(&capture_segments(capture)).concat();
+        Ok(Self::parse(&stream))
+    }
+}
+
+/// State inference function for the [`NetworkExecutor`](crate::NetworkExecutor)
+/// that uses the reply code of the server as the state.
+pub fn status_code(response: &[u8]) -> Option<u32> {
+    parse_reply_code(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libafl::{bolts::rands::StdRand, corpus::InMemoryCorpus, state::StdState};
+
+    #[test]
+    fn test_parse() {
+        let input = FtpInput::parse(b"USER anonymous\r\npass secret\r\nLIST\r\nLIST /tmp\r\nXCWD a\r\nQUIT\r\n");
+        let commands: Vec<&FtpCommand> = input
+            .packets()
+            .iter()
+            .map(|packet| match packet {
+                NetworkPacket::Data(command) => command,
+                _ => unreachable!(),
+            })
+            .collect();
+
+        assert_eq!(commands[0], &FtpCommand::User(BytesInput::new(b"anonymous".to_vec())));
+        assert_eq!(commands[1].payload(), b"PASS secret\r\n");
+        assert_eq!(commands[2], &FtpCommand::List(None));
+        assert_eq!(commands[3].argument().unwrap().bytes(), b"/tmp");
+        assert_eq!(commands[4].payload(), b"XCWD a\r\n");
+        assert_eq!(commands[5], &FtpCommand::Quit);
+        assert_eq!(status_code(b"230 logged in\r\n"), Some(230));
+        assert_eq!(status_code(b"garbage"), None);
+        assert_eq!(commands[0].validity(b"530 Login incorrect\r\n"), Validity::Rejected);
+        assert_eq!(commands[0].validity(b"331 Password required\r\n"), Validity::Accepted);
+    }
+
+    #[test]
+    fn test_convert_kind() {
+        let mut state = StdState::new(StdRand::with_seed(0), InMemoryCorpus::<FtpInput>::new(), InMemoryCorpus::<FtpInput>::new(), &mut (), &mut ()).unwrap();
+
+        for _ in 0..100 {
+            let mut command = FtpCommand::Cwd(BytesInput::new(b"/tmp".to_vec()));
+
+            if command.convert_kind(&mut state) {
+                assert_ne!(command.verb(), b"CWD");
+                assert!(command.argument().map_or(true, |arg| arg.bytes() == b"/tmp"));
+                assert!(!matches!(command, FtpCommand::Other(_)));
+            }
+        }
+
+        let mut command = FtpCommand::Cdup;
+        while !command.convert_kind(&mut state) {}
+        assert!(command.argument().map_or(true, |arg| arg.bytes().is_empty()));
+    }
+}
@@ -0,0 +1,741 @@
+//! Packet and input types for gRPC services.
+//!
+//! __Only available with feature__: `protocol_grpc`
+//!
+//! Messages are kept in a schema-less representation of the protobuf wire format
+//! such that the mutators can drop fields, duplicate (repeated) fields and
+//! confuse the types of scalars without knowing the `.proto` definitions.
+//! Seeds are created from [prost](https://docs.rs/prost) messages and every call is sent
+//! as an HTTP/2 HEADERS + DATA frame pair on its own stream.
+//!
+//! # Example
+//! ```
+//! let call = GrpcCall::new(1, b"/helloworld.Greeter/SayHello", ProtobufMessage::from_message(&request).unwrap());
+//! let input = GrpcInput::new(vec![call]);
+//!
+//! let mut executor = NetworkExecutor::new(tuple_list!(state_observer), "127.0.0.1:50051".parse().unwrap(), "state", grpc::frame_state);
+//! ```
+
+use crate::{
+    executor::{HasPayload, NetworkPacket},
+    input::HasPackets,
+    mutators::{HasCrossoverInsertMutation, HasCrossoverReplaceMutation, HasHavocMutation, HasPostMutationFixup, HasSpliceMutation},
+};
+use libafl::{
+    bolts::{rands::Rand, HasLen},
+    inputs::{BytesInput, HasBytesVec, Input},
+    mutators::{MutationResult, MutatorsTuple},
+    state::{HasMaxSize, HasRand},
+    Error,
+};
+use prost::encoding::{decode_key, decode_varint, encode_key, encode_varint, WireType};
+use serde::{Deserialize, Serialize};
+
+/// Values that a havoc mutation chooses from when it replaces a scalar
+const INTERESTING_VALUES: [u64; 10] = [0, 1, 0x7F, 0x80, 0x7FFF_FFFF, 0x8000_0000, 0xFFFF_FFFF, 0x7FFF_FFFF_FFFF_FFFF, 0x8000_0000_0000_0000, u64::MAX];
+
+const HTTP2_PREFACE: &[u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+/// Default SETTINGS_MAX_FRAME_SIZE of HTTP/2
+const HTTP2_MAX_FRAME_SIZE: usize = 16384;
+
+const FRAME_DATA: u8 = 0x0;
+const FRAME_HEADERS: u8 = 0x1;
+const FRAME_RST_STREAM: u8 = 0x3;
+const FRAME_SETTINGS: u8 = 0x4;
+const FRAME_GOAWAY: u8 = 0x7;
+
+const FLAG_END_STREAM: u8 = 0x1;
+const FLAG_END_HEADERS: u8 = 0x4;
+
+/// The value of a single protobuf field, one variant per wire type.
+///
+/// Groups are not supported since they are deprecated.
+#[derive(Hash, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ProtobufValue {
+    /// int32, int64, uint32, uint64, sint32, sint64, bool, enum
+    Varint(u64),
+    /// fixed64, sfixed64, double
+    Fixed64(u64),
+    /// string, bytes, embedded messages, packed repeated fields
+    LengthDelimited(BytesInput),
+    /// fixed32, sfixed32, float
+    Fixed32(u32),
+}
+
+impl ProtobufValue {
+    fn wire_type(&self) -> WireType {
+        match self {
+            ProtobufValue::Varint(_) => WireType::Varint,
+            ProtobufValue::Fixed64(_) => WireType::SixtyFourBit,
+            ProtobufValue::LengthDelimited(_) => WireType::LengthDelimited,
+            ProtobufValue::Fixed32(_) => WireType::ThirtyTwoBit,
+        }
+    }
+
+    fn is_scalar(&self) -> bool {
+        !matches!(self, ProtobufValue::LengthDelimited(_))
+    }
+
+    /// Interpret the value as a number. Bytes are read as little endian.
+    fn as_u64(&self) -> u64 {
+        match self {
+            ProtobufValue::Varint(value) | ProtobufValue::Fixed64(value) => *value,
+            ProtobufValue::Fixed32(value) => *value as u64,
+            ProtobufValue::LengthDelimited(bytes) => {
+                let mut buf = [0u8; 8];
+                let len = bytes.bytes().len().min(8);
+                buf[..len].copy_from_slice(&bytes.bytes()[..len]);
+                u64::from_le_bytes(buf)
+            },
+        }
+    }
+
+    /// Reinterpret the value with another wire type
+    fn confuse<R: Rand>(&self, rand: &mut R) -> Self {
+        let current = self.wire_type();
+        let value = self.as_u64();
+        let others: Vec<WireType> = [WireType::Varint, WireType::SixtyFourBit, WireType::LengthDelimited, WireType::ThirtyTwoBit].into_iter().filter(|wire_type| *wire_type != current).collect();
+
+        match others[rand.below(others.len() as u64) as usize] {
+            WireType::Varint => ProtobufValue::Varint(value),
+            WireType::SixtyFourBit => ProtobufValue::Fixed64(value),
+            WireType::ThirtyTwoBit => ProtobufValue::Fixed32(value as u32),
+            _ => ProtobufValue::LengthDelimited(BytesInput::new(value.to_le_bytes().to_vec())),
+        }
+    }
+}
+
+/// A single field of a protobuf message
+#[derive(Hash, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProtobufField {
+    /// The field number from the `.proto` definition
+    pub number: u32,
+    /// The value of the field
+    pub value: ProtobufValue,
+}
+
+/// A protobuf message in its wire representation.
+///
+/// Fields are kept in the order they appear on the wire, repeated fields
+/// appear multiple times and embedded messages are plain
+/// [`LengthDelimited`](ProtobufValue::LengthDelimited) values.
+/// This makes it possible to produce messages that no `.proto` definition
+/// would allow.
+#[derive(Hash, Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProtobufMessage {
+    fields: Vec<ProtobufField>,
+}
+
+impl ProtobufMessage {
+    /// Create an empty message
+    pub fn new() -> Self {
+        Self {
+            fields: Vec::new(),
+        }
+    }
+
+    /// Parse a message from its encoded form.
+    /// Returns `None` if the buffer is malformed or contains groups.
+    pub fn parse(mut buf: &[u8]) -> Option<Self> {
+        let mut fields = Vec::new();
+
+        while !buf.is_empty() {
+            let (number, wire_type) = decode_key(&mut buf).ok()?;
+
+            let value = match wire_type {
+                WireType::Varint => ProtobufValue::Varint(decode_varint(&mut buf).ok()?),
+                WireType::SixtyFourBit => {
+                    let value = buf.get(..8)?;
+                    buf = &buf[8..];
+                    ProtobufValue::Fixed64(u64::from_le_bytes(value.try_into().unwrap()))
+                },
+                WireType::ThirtyTwoBit => {
+                    let value = buf.get(..4)?;
+                    buf = &buf[4..];
+                    ProtobufValue::Fixed32(u32::from_le_bytes(value.try_into().unwrap()))
+                },
+                WireType::LengthDelimited => {
+                    let len = decode_varint(&mut buf).ok()? as usize;
+                    let value = buf.get(..len)?;
+                    buf = &buf[len..];
+                    ProtobufValue::LengthDelimited(BytesInput::new(value.to_vec()))
+                },
+                WireType::StartGroup | WireType::EndGroup => return None,
+            };
+
+            fields.push(ProtobufField {
+                number,
+                value,
+            });
+        }
+
+        Some(Self {
+            fields,
+        })
+    }
+
+    /// Convert a prost message into its wire representation
+    pub fn from_message<M: prost::Message>(message: &M) -> Option<Self> {
+        Self::parse(&message.encode_to_vec())
+    }
+
+    /// Decode the message into a prost message
+    pub fn decode<M: prost::Message + Default>(&self) -> Result<M, prost::DecodeError> {
+        M::decode(&self.to_bytes()[..])
+    }
+
+    /// Encode the message
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut ret = Vec::new();
+
+        for field in &self.fields {
+            encode_key(field.number, field.value.wire_type(), &mut ret);
+
+            match &field.value {
+                ProtobufValue::Varint(value) => encode_varint(*value, &mut ret),
+                ProtobufValue::Fixed64(value) => ret.extend_from_slice(&value.to_le_bytes()),
+                ProtobufValue::Fixed32(value) => ret.extend_from_slice(&value.to_le_bytes()),
+                ProtobufValue::LengthDelimited(bytes) => {
+                    encode_varint(bytes.bytes().len() as u64, &mut ret);
+                    ret.extend_from_slice(bytes.bytes());
+                },
+            }
+        }
+
+        ret
+    }
+
+    /// Get all fields in wire order
+    pub fn fields(&self) -> &[ProtobufField] {
+        &self.fields
+    }
+
+    /// Get all fields in wire order
+    pub fn fields_mut(&mut self) -> &mut Vec<ProtobufField> {
+        &mut self.fields
+    }
+
+    /// Pick a random field that satisfies `filter`
+    fn pick_field<S, F>(&self, state: &mut S, filter: F) -> Option<usize>
+    where
+        S: HasRand,
+        F: Fn(&ProtobufValue) -> bool,
+    {
+        let candidates: Vec<usize> = (0..self.fields.len()).filter(|idx| filter(&self.fields[*idx].value)).collect();
+
+        if candidates.is_empty() {
+            None
+        } else {
+            Some(*state.rand_mut().choose(&candidates))
+        }
+    }
+
+    fn mutate_drop<S: HasRand>(&mut self, state: &mut S) -> MutationResult {
+        if self.fields.is_empty() {
+            return MutationResult::Skipped;
+        }
+
+        let idx = state.rand_mut().below(self.fields.len() as u64) as usize;
+        self.fields.remove(idx);
+        MutationResult::Mutated
+    }
+
+    fn mutate_duplicate<S: HasRand + HasMaxSize>(&mut self, state: &mut S) -> MutationResult {
+        if self.fields.is_empty() || self.to_bytes().len() >= state.max_size() {
+            return MutationResult::Skipped;
+        }
+
+        let idx = state.rand_mut().below(self.fields.len() as u64) as usize;
+        let field = self.fields[idx].clone();
+        self.fields.insert(idx + 1, field);
+        MutationResult::Mutated
+    }
+
+    fn mutate_confuse<S: HasRand>(&mut self, state: &mut S) -> MutationResult {
+        if self.fields.is_empty() {
+            return MutationResult::Skipped;
+        }
+
+        let idx = state.rand_mut().below(self.fields.len() as u64) as usize;
+        self.fields[idx].value = self.fields[idx].value.confuse(state.rand_mut());
+        MutationResult::Mutated
+    }
+
+    fn mutate_scalar<S: HasRand>(&mut self, state: &mut S) -> MutationResult {
+        let idx = match self.pick_field(state, ProtobufValue::is_scalar) {
+            Some(idx) => idx,
+            None => return MutationResult::Skipped,
+        };
+
+        let old = self.fields[idx].value.as_u64();
+        let new = if state.rand_mut().below(2) == 0 { *state.rand_mut().choose(&INTERESTING_VALUES) } else { old ^ (1 << state.rand_mut().below(64)) };
+
+        self.fields[idx].value = match self.fields[idx].value {
+            ProtobufValue::Varint(_) => ProtobufValue::Varint(new),
+            ProtobufValue::Fixed64(_) => ProtobufValue::Fixed64(new),
+            _ => ProtobufValue::Fixed32(new as u32),
+        };
+
+        if self.fields[idx].value.as_u64() == old {
+            MutationResult::Skipped
+        } else {
+            MutationResult::Mutated
+        }
+    }
+
+    /// Pick a pair of length-delimited values from `self` and `other`
+    fn pick_bytes<'a, S>(&'a mut self, state: &mut S, other: &'a Self) -> Option<(&'a mut BytesInput, &'a BytesInput)>
+    where
+        S: HasRand,
+    {
+        let idx = self.pick_field(state, |value| !value.is_scalar())?;
+        let other_idx = other.pick_field(state, |value| !value.is_scalar())?;
+
+        match (&mut self.fields[idx].value, &other.fields[other_idx].value) {
+            (ProtobufValue::LengthDelimited(bytes), ProtobufValue::LengthDelimited(other_bytes)) => Some((bytes, other_bytes)),
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl<S> HasCrossoverInsertMutation<S> for ProtobufMessage
+where
+    S: HasRand + HasMaxSize,
+{
+    fn mutate_crossover_insert(&mut self, state: &mut S, other: &Self, _stage_idx: i32) -> Result<MutationResult, Error> {
+        if other.fields.is_empty() || self.to_bytes().len() >= state.max_size() {
+            return Ok(MutationResult::Skipped);
+        }
+
+        let field = state.rand_mut().choose(&other.fields).clone();
+        let idx = state.rand_mut().below(self.fields.len() as u64 + 1) as usize;
+        self.fields.insert(idx, field);
+        Ok(MutationResult::Mutated)
+    }
+}
+
+impl<S> HasCrossoverReplaceMutation<S> for ProtobufMessage
+where
+    S: HasRand + HasMaxSize,
+{
+    fn mutate_crossover_replace(&mut self, state: &mut S, other: &Self, _stage_idx: i32) -> Result<MutationResult, Error> {
+        if self.fields.is_empty() || other.fields.is_empty() {
+            return Ok(MutationResult::Skipped);
+        }
+
+        let field = state.rand_mut().choose(&other.fields).clone();
+        let idx = state.rand_mut().below(self.fields.len() as u64) as usize;
+
+        if self.fields[idx] == field {
+            Ok(MutationResult::Skipped)
+        } else {
+            self.fields[idx] = field;
+            Ok(MutationResult::Mutated)
+        }
+    }
+}
+
+impl<S> HasSpliceMutation<S> for ProtobufMessage
+where
+    S: HasRand + HasMaxSize,
+{
+    fn mutate_splice(&mut self, state: &mut S, other: &Self, stage_idx: i32) -> Result<MutationResult, Error> {
+        match self.pick_bytes(state, other) {
+            Some((bytes, other_bytes)) => bytes.mutate_splice(state, other_bytes, stage_idx),
+            None => Ok(MutationResult::Skipped),
+        }
+    }
+}
+
+impl<MT, S> HasHavocMutation<MT, S> for ProtobufMessage
+where
+    MT: MutatorsTuple<BytesInput, S>,
+    S: HasRand + HasMaxSize,
+{
+    fn mutate_havoc(&mut self, state: &mut S, mutations: &mut MT, mutation: usize, stage_idx: i32) -> Result<MutationResult, Error> {
+        match state.rand_mut().below(8) {
+            0 => Ok(self.mutate_drop(state)),
+            1 => Ok(self.mutate_duplicate(state)),
+            2 => Ok(self.mutate_confuse(state)),
+            3 | 4 => Ok(self.mutate_scalar(state)),
+            _ => match self.pick_field(state, |value| !value.is_scalar()) {
+                Some(idx) => match &mut self.fields[idx].value {
+                    ProtobufValue::LengthDelimited(bytes) => mutations.get_and_mutate(mutation, state, bytes, stage_idx),
+                    _ => unreachable!(),
+                },
+                None => Ok(MutationResult::Skipped),
+            },
+        }
+    }
+}
+
+/// Encode an HTTP/2 frame
+fn http2_frame(kind: u8, flags: u8, stream_id: u32, payload: &[u8]) -> Vec<u8> {
+    let mut ret = Vec::with_capacity(9 + payload.len());
+    ret.extend_from_slice(&(payload.len() as u32).to_be_bytes()[1..]);
+    ret.push(kind);
+    ret.push(flags);
+    ret.extend_from_slice(&(stream_id & 0x7FFF_FFFF).to_be_bytes());
+    ret.extend_from_slice(payload);
+    ret
+}
+
+/// Encode an HPACK string literal without huffman coding
+fn hpack_string(buf: &mut Vec<u8>, value: &[u8]) {
+    let mut len = value.len();
+
+    if len < 0x7F {
+        buf.push(len as u8);
+    } else {
+        buf.push(0x7F);
+        len -= 0x7F;
+
+        while len >= 0x80 {
+            buf.push((len & 0x7F) as u8 | 0x80);
+            len >>= 7;
+        }
+
+        buf.push(len as u8);
+    }
+
+    buf.extend_from_slice(value);
+}
+
+/// Encode an HPACK "literal header field without indexing" with a new name
+fn hpack_literal(buf: &mut Vec<u8>, name: &[u8], value: &[u8]) {
+    buf.push(0x00);
+    hpack_string(buf, name);
+    hpack_string(buf, value);
+}
+
+/// A single unary gRPC call.
+///
+/// Its payload is a HEADERS frame followed by DATA frames that carry
+/// the length-prefixed message on the HTTP/2 stream `stream_id`.
+/// A connection has to start with a [`GrpcPacket::Preface`].
+#[derive(Hash, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GrpcCall {
+    stream_id: u32,
+    path: BytesInput,
+    metadata: Vec<(Vec<u8>, BytesInput)>,
+    message: ProtobufMessage,
+}
+
+impl GrpcCall {
+    /// Create a new call of the method `path` (e.g. `/package.Service/Method`).
+    ///
+    /// Clients must use odd, increasing stream ids.
+    pub fn new(stream_id: u32, path: &[u8], message: ProtobufMessage) -> Self {
+        Self {
+            stream_id,
+            path: BytesInput::new(path.to_vec()),
+            metadata: Vec::new(),
+            message,
+        }
+    }
+
+    /// Send an additional header with the call, e.g. `:authority` or `authorization`
+    pub fn with_metadata(mut self, name: &[u8], value: &[u8]) -> Self {
+        self.metadata.push((name.to_vec(), BytesInput::new(value.to_vec())));
+        self
+    }
+
+    /// Get the HTTP/2 stream id
+    pub fn stream_id(&self) -> u32 {
+        self.stream_id
+    }
+
+    /// Get the method path
+    pub fn path(&self) -> &BytesInput {
+        &self.path
+    }
+
+    /// Get the request message
+    pub fn message(&self) -> &ProtobufMessage {
+        &self.message
+    }
+
+    /// Get the request message
+    pub fn message_mut(&mut self) -> &mut ProtobufMessage {
+        &mut self.message
+    }
+}
+
+impl HasPayload for GrpcCall {
+    fn payload(&self) -> Vec<u8> {
+        // :method POST and :scheme http from the static table
+        let mut headers = vec![0x83, 0x86];
+        hpack_literal(&mut headers, b":path", self.path.bytes());
+
+        for (name, value) in &self.metadata {
+            hpack_literal(&mut headers, name, value.bytes());
+        }
+
+        hpack_literal(&mut headers, b"content-type", b"application/grpc");
+        hpack_literal(&mut headers, b"te", b"trailers");
+
+        let message = self.message.to_bytes();
+        let mut data = Vec::with_capacity(5 + message.len());
+        data.push(0);
+        data.extend_from_slice(&(message.len() as u32).to_be_bytes());
+        data.extend_from_slice(&message);
+
+        let mut ret = http2_frame(FRAME_HEADERS, FLAG_END_HEADERS, self.stream_id, &headers);
+        let mut chunks = data.chunks(HTTP2_MAX_FRAME_SIZE).peekable();
+
+        while let Some(chunk) = chunks.next() {
+            let flags = if chunks.peek().is_none() { FLAG_END_STREAM } else { 0 };
+            ret.extend(http2_frame(FRAME_DATA, flags, self.stream_id, chunk));
+        }
+
+        ret
+    }
+}
+
+impl<S> HasCrossoverInsertMutation<S> for GrpcCall
+where
+    S: HasRand + HasMaxSize,
+{
+    fn mutate_crossover_insert(&mut self, state: &mut S, other: &Self, stage_idx: i32) -> Result<MutationResult, Error> {
+        self.message.mutate_crossover_insert(state, &other.message, stage_idx)
+    }
+}
+
+impl<S> HasCrossoverReplaceMutation<S> for GrpcCall
+where
+    S: HasRand + HasMaxSize,
+{
+    fn mutate_crossover_replace(&mut self, state: &mut S, other: &Self, stage_idx: i32) -> Result<MutationResult, Error> {
+        self.message.mutate_crossover_replace(state, &other.message, stage_idx)
+    }
+}
+
+impl<S> HasSpliceMutation<S> for GrpcCall
+where
+    S: HasRand + HasMaxSize,
+{
+    fn mutate_splice(&mut self, state: &mut S, other: &Self, stage_idx: i32) -> Result<MutationResult, Error> {
+        self.message.mutate_splice(state, &other.message, stage_idx)
+    }
+}
+
+impl<MT, S> HasHavocMutation<MT, S> for GrpcCall
+where
+    MT: MutatorsTuple<BytesInput, S>,
+    S: HasRand + HasMaxSize,
+{
+    fn mutate_havoc(&mut self, state: &mut S, mutations: &mut MT, mutation: usize, stage_idx: i32) -> Result<MutationResult, Error> {
+        // Most mutations of the path only yield UNIMPLEMENTED, so focus on the message
+        match state.rand_mut().below(16) {
+            0 => mutations.get_and_mutate(mutation, state, &mut self.path, stage_idx),
+            1 if !self.metadata.is_empty() => {
+                let idx = state.rand_mut().below(self.metadata.len() as u64) as usize;
+                mutations.get_and_mutate(mutation, state, &mut self.metadata[idx].1, stage_idx)
+            },
+            _ => self.message.mutate_havoc(state, mutations, mutation, stage_idx),
+        }
+    }
+}
+
+/// A packet on an HTTP/2 connection to a gRPC service
+#[derive(Hash, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GrpcPacket {
+    /// The HTTP/2 connection preface followed by an empty SETTINGS frame
+    Preface,
+    /// A gRPC call
+    Call(GrpcCall),
+}
+
+impl HasPayload for GrpcPacket {
+    fn payload(&self) -> Vec<u8> {
+        match self {
+            GrpcPacket::Preface => {
+                let mut ret = HTTP2_PREFACE.to_vec();
+                ret.extend(http2_frame(FRAME_SETTINGS, 0, 0, &[]));
+                ret
+            },
+            GrpcPacket::Call(call) => call.payload(),
+        }
+    }
+}
+
+impl HasPostMutationFixup for GrpcPacket {}
+
+impl<S> HasCrossoverInsertMutation<S> for GrpcPacket
+where
+    S: HasRand + HasMaxSize,
+{
+    fn mutate_crossover_insert(&mut self, state: &mut S, other: &Self, stage_idx: i32) -> Result<MutationResult, Error> {
+        match (self, other) {
+            (GrpcPacket::Call(call), GrpcPacket::Call(other)) => call.mutate_crossover_insert(state, other, stage_idx),
+            _ => Ok(MutationResult::Skipped),
+        }
+    }
+}
+
+impl<S> HasCrossoverReplaceMutation<S> for GrpcPacket
+where
+    S: HasRand + HasMaxSize,
+{
+    fn mutate_crossover_replace(&mut self, state: &mut S, other: &Self, stage_idx: i32) -> Result<MutationResult, Error> {
+        match (self, other) {
+            (GrpcPacket::Call(call), GrpcPacket::Call(other)) => call.mutate_crossover_replace(state, other, stage_idx),
+            _ => Ok(MutationResult::Skipped),
+        }
+    }
+}
+
+impl<S> HasSpliceMutation<S> for GrpcPacket
+where
+    S: HasRand + HasMaxSize,
+{
+    fn mutate_splice(&mut self, state: &mut S, other: &Self, stage_idx: i32) -> Result<MutationResult, Error> {
+        match (self, other) {
+            (GrpcPacket::Call(call), GrpcPacket::Call(other)) => call.mutate_splice(state, other, stage_idx),
+            _ => Ok(MutationResult::Skipped),
+        }
+    }
+}
+
+impl<MT, S> HasHavocMutation<MT, S> for GrpcPacket
+where
+    MT: MutatorsTuple<BytesInput, S>,
+    S: HasRand + HasMaxSize,
+{
+    fn mutate_havoc(&mut self, state: &mut S, mutations: &mut MT, mutation: usize, stage_idx: i32) -> Result<MutationResult, Error> {
+        match self {
+            GrpcPacket::Preface => Ok(MutationResult::Skipped),
+            GrpcPacket::Call(call) => call.mutate_havoc(state, mutations, mutation, stage_idx),
+        }
+    }
+}
+
+/// A sequence of gRPC calls over one HTTP/2 connection.
+///
+/// gRPC traffic is usually encrypted and HPACK-compressed, so instead of
+/// loading pcaps, create the seeds from prost messages with
+/// [`ProtobufMessage::from_message()`].
+#[derive(Hash, Debug, Clone, Serialize, Deserialize)]
+pub struct GrpcInput {
+    packets: Vec<NetworkPacket<GrpcPacket>>,
+}
+
+impl GrpcInput {
+    /// Create a new GrpcInput that sends the connection preface and then all `calls`
+    pub fn new(calls: Vec<GrpcCall>) -> Self {
+        let mut packets = vec![NetworkPacket::Data(GrpcPacket::Preface)];
+        packets.extend(calls.into_iter().map(|call| NetworkPacket::Data(GrpcPacket::Call(call))));
+
+        Self {
+            packets,
+        }
+    }
+}
+
+impl Input for GrpcInput {
+    fn generate_name(&self, idx: usize) -> String {
+        format!("grpc-{}", idx)
+    }
+}
+
+impl HasPackets<NetworkPacket<GrpcPacket>> for GrpcInput {
+    fn packets(&self) -> &[NetworkPacket<GrpcPacket>] {
+        &self.packets
+    }
+
+    fn packets_mut(&mut self) -> &mut Vec<NetworkPacket<GrpcPacket>> {
+        &mut self.packets
+    }
+}
+
+impl HasLen for GrpcInput {
+    fn len(&self) -> usize {
+        self.packets.len()
+    }
+}
+
+/// State inference function for the [`NetworkExecutor`](crate::NetworkExecutor).
+///
+/// The grpc-status lives in HPACK-compressed trailers, so this uses
+/// the error codes of RST_STREAM and GOAWAY frames if the server sent any
+/// and otherwise the set of frame types in the response.
+pub fn frame_state(response: &[u8]) -> Option<u32> {
+    let mut types = 0u32;
+    let mut rest = response;
+
+    while rest.len() >= 9 {
+        let len = u32::from_be_bytes([0, rest[0], rest[1], rest[2]]) as usize;
+        let kind = rest[3];
+        let payload = &rest[9..(9 + len).min(rest.len())];
+
+        match kind {
+            FRAME_RST_STREAM if payload.len() >= 4 => return Some(0x300 | u32::from_be_bytes(payload[0..4].try_into().unwrap())),
+            FRAME_GOAWAY if payload.len() >= 8 => return Some(0x700 | u32::from_be_bytes(payload[4..8].try_into().unwrap())),
+            _ => types |= 1 << (kind & 0xF),
+        }
+
+        rest = &rest[9 + payload.len()..];
+    }
+
+    if types == 0 {
+        None
+    } else {
+        Some(types)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libafl::bolts::rands::StdRand;
+
+    #[test]
+    fn test_roundtrip() {
+        // field 1: varint 150, field 2: "testing", field 3: fixed32
+        let encoded = b"\x08\x96\x01\x12\x07testing\x1d\x01\x00\x00\x00";
+        let message = ProtobufMessage::parse(encoded).unwrap();
+
+        assert_eq!(message.fields().len(), 3);
+        assert_eq!(message.fields()[0].value, ProtobufValue::Varint(150));
+        assert_eq!(message.fields()[1].value, ProtobufValue::LengthDelimited(BytesInput::new(b"testing".to_vec())));
+        assert_eq!(message.fields()[2].value, ProtobufValue::Fixed32(1));
+        assert_eq!(message.to_bytes(), encoded);
+        assert!(ProtobufMessage::parse(b"\x12\x07test").is_none());
+    }
+
+    #[test]
+    fn test_confuse() {
+        let mut rand = StdRand::with_seed(0);
+        let all = [WireType::Varint, WireType::SixtyFourBit, WireType::LengthDelimited, WireType::ThirtyTwoBit];
+        let values = [ProtobufValue::Varint(1), ProtobufValue::Fixed64(1), ProtobufValue::LengthDelimited(BytesInput::new(vec![1])), ProtobufValue::Fixed32(1)];
+
+        for value in values {
+            let mut seen = Vec::new();
+
+            for _ in 0..100 {
+                let wire_type = value.confuse(&mut rand).wire_type();
+
+                if !seen.contains(&wire_type) {
+                    seen.push(wire_type);
+                }
+            }
+
+            // Every other wire type, but never the current one
+            assert_eq!(seen.len(), 3);
+            assert!(all.iter().all(|wire_type| seen.contains(wire_type) != (*wire_type == value.wire_type())));
+        }
+    }
+
+    #[test]
+    fn test_framing() {
+        let call = GrpcCall::new(3, b"/a.B/C", ProtobufMessage::parse(b"\x08\x01").unwrap());
+        let payload = call.payload();
+
+        assert_eq!(&payload[3..9], &[FRAME_HEADERS, FLAG_END_HEADERS, 0, 0, 0, 3]);
+        assert!(payload.ends_with(&[0, 0, 7, FRAME_DATA, FLAG_END_STREAM, 0, 0, 0, 3, 0, 0, 0, 0, 2, 0x08, 0x01]));
+        assert!(GrpcPacket::Preface.payload().starts_with(HTTP2_PREFACE));
+
+        assert_eq!(frame_state(&http2_frame(FRAME_SETTINGS, 0, 0, &[])), Some(1 << FRAME_SETTINGS));
+        assert_eq!(frame_state(&http2_frame(FRAME_RST_STREAM, 0, 3, &[0, 0, 0, 2])), Some(0x302));
+    }
+}
@@ -0,0 +1,399 @@
+//! Packet and input types for HTTP/1.x.
+//!
+//! __Only available with feature__: `protocol_http1`
+//!
+//! # Example
+//! ```
+//! let mut executor = NetworkExecutor::new(tuple_list!(state_observer), "127.0.0.1:8080".parse().unwrap(), "state", http1::status_code);
+//! load_pcaps::<_, _, _, _, Http1Input, _>(&mut state, &mut fuzzer, &mut executor, &mut mgr, "pcaps").unwrap();
+//! ```
+
+use crate::{
+    executor::{status_code_validity, HasPayload, HasValidityOracle, NetworkPacket, Validity},
+    input::{capture_segments, first_tcp_connection, HasPackets, HasPcapRepresentation},
+    mutators::{HasCrossoverInsertMutation, HasCrossoverReplaceMutation, HasHavocMutation, HasPostMutationFixup, HasSpliceMutation},
+};
+use libafl::{
+    bolts::{rands::Rand, HasLen},
+    inputs::{BytesInput, HasBytesVec, Input},
+    mutators::{MutationResult, MutatorsTuple},
+    state::{HasMaxSize, HasRand},
+    Error,
+};
+use pcap::{Capture, Offline};
+use serde::{Deserialize, Serialize};
+
+/// The method of an HTTP request
+#[derive(Hash, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[allow(missing_docs)]
+pub enum HttpMethod {
+    Get,
+    Head,
+    Post,
+    Put,
+    Delete,
+    Connect,
+    Options,
+    Trace,
+    Patch,
+    /// Any other method
+    Other(Vec<u8>),
+}
+
+impl HttpMethod {
+    const KNOWN: [HttpMethod; 9] = [HttpMethod::Get, HttpMethod::Head, HttpMethod::Post, HttpMethod::Put, HttpMethod::Delete, HttpMethod::Connect, HttpMethod::Options, HttpMethod::Trace, HttpMethod::Patch];
+
+    /// Parse a method
+    pub fn from_bytes(method: &[u8]) -> Self {
+        match method {
+            b"GET" => HttpMethod::Get,
+            b"HEAD" => HttpMethod::Head,
+            b"POST" => HttpMethod::Post,
+            b"PUT" => HttpMethod::Put,
+            b"DELETE" => HttpMethod::Delete,
+            b"CONNECT" => HttpMethod::Connect,
+            b"OPTIONS" => HttpMethod::Options,
+            b"TRACE" => HttpMethod::Trace,
+            b"PATCH" => HttpMethod::Patch,
+            _ => HttpMethod::Other(method.to_vec()),
+        }
+    }
+
+    /// Get the method as it is sent on the wire
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            HttpMethod::Get => b"GET",
+            HttpMethod::Head => b"HEAD",
+            HttpMethod::Post => b"POST",
+            HttpMethod::Put => b"PUT",
+            HttpMethod::Delete => b"DELETE",
+            HttpMethod::Connect => b"CONNECT",
+            HttpMethod::Options => b"OPTIONS",
+            HttpMethod::Trace => b"TRACE",
+            HttpMethod::Patch => b"PATCH",
+            HttpMethod::Other(method) => method,
+        }
+    }
+}
+
+/// An HTTP/1.x request.
+///
+/// The `Content-Length` header is computed when the request is serialized, so
+/// mutations of the body always result in a well-framed request.
+/// Chunked bodies are de-chunked while parsing.
+///
+/// The havoc mutation either mutates the request target, the value of a header,
+/// the body or replaces the method. Crossover and splice mutations combine the
+/// bodies of two requests.
+#[derive(Hash, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Http1Request {
+    method: HttpMethod,
+    target: BytesInput,
+    version: Vec<u8>,
+    headers: Vec<(Vec<u8>, BytesInput)>,
+    body: BytesInput,
+}
+
+impl Http1Request {
+    /// Create a new request without headers and body
+    pub fn new(method: HttpMethod, target: &[u8]) -> Self {
+        Self {
+            method,
+            target: BytesInput::new(target.to_vec()),
+            version: b"HTTP/1.1".to_vec(),
+            headers: Vec::new(),
+            body: BytesInput::new(Vec::new()),
+        }
+    }
+
+    /// Parse the first request in a byte stream.
+    /// Returns the request and the number of bytes it occupied in the stream.
+    pub fn parse(stream: &[u8]) -> Option<(Self, usize)> {
+        let header_len = stream.windows(4).position(|w| w == b"\r\n\r\n")?;
+        let mut lines = stream[..header_len].split(|c| *c == b'\n').map(|line| line.strip_suffix(b"\r").unwrap_or(line));
+
+        let mut request_line = lines.next()?.splitn(3, |c| *c == b' ');
+        let mut request = Self::new(HttpMethod::from_bytes(request_line.next()?), request_line.next()?);
+        request.version = request_line.next().unwrap_or(b"HTTP/1.1").to_vec();
+
+        let mut content_length = 0;
+        let mut chunked = false;
+
+        for line in lines {
+            let idx = match line.iter().position(|c| *c == b':') {
+                Some(idx) => idx,
+                None => continue,
+            };
+            let name = &line[..idx];
+            let value = line[idx + 1..].strip_prefix(b" ").unwrap_or(&line[idx + 1..]);
+
+            if name.eq_ignore_ascii_case(b"content-length") {
+                content_length = std::str::from_utf8(value).ok().and_then(|v| v.trim().parse().ok()).unwrap_or(0);
+            } else if name.eq_ignore_ascii_case(b"transfer-encoding") && value.eq_ignore_ascii_case(b"chunked") {
+                chunked = true;
+            } else {
+                request.headers.push((name.to_vec(), BytesInput::new(value.to_vec())));
+            }
+        }
+
+        let mut len = header_len + 4;
+
+        if chunked {
+            let (body, body_len) = dechunk(&stream[len..]);
+            request.body = BytesInput::new(body);
+            len += body_len;
+        } else {
+            let body_len = content_length.min(stream.len() - len);
+            request.body = BytesInput::new(stream[len..len + body_len].to_vec());
+            len += body_len;
+        }
+
+        Some((request, len))
+    }
+
+    /// Get the method
+    pub fn method(&self) -> &HttpMethod {
+        &self.method
+    }
+
+    /// Get the request target
+    pub fn target(&self) -> &BytesInput {
+        &self.target
+    }
+
+    /// Get the headers without `Content-Length` and `Transfer-Encoding`
+    pub fn headers(&self) -> &[(Vec<u8>, BytesInput)] {
+        &self.headers
+    }
+
+    /// Add a header. `Content-Length` is always computed and must not be added.
+    pub fn add_header(&mut self, name: &[u8], value: &[u8]) {
+        self.headers.push((name.to_vec(), BytesInput::new(value.to_vec())));
+    }
+
+    /// Get the body
+    pub fn body(&self) -> &BytesInput {
+        &self.body
+    }
+
+    /// Set the body
+    pub fn set_body(&mut self, body: &[u8]) {
+        self.body = BytesInput::new(body.to_vec());
+    }
+}
+
+/// Decode a chunked body. Returns the body and the number of bytes it occupied in the stream.
+fn dechunk(stream: &[u8]) -> (Vec<u8>, usize) {
+    let mut body = Vec::new();
+    let mut pos = 0;
+
+    while let Some(line_len) = stream[pos..].windows(2).position(|w| w == b"\r\n") {
+        let size = std::str::from_utf8(&stream[pos..pos + line_len]).ok().and_then(|size| usize::from_str_radix(size.split(';').next().unwrap_or("").trim(), 16).ok());
+        pos += line_len + 2;
+
+        match size {
+            Some(0) | None => {
+                // Skip the trailer
+                if let Some(end) = stream[pos..].windows(2).position(|w| w == b"\r\n") {
+                    pos += end + 2;
+                }
+                break;
+            },
+            Some(size) => {
+                let end = (pos + size).min(stream.len());
+                body.extend_from_slice(&stream[pos..end]);
+                pos = (end + 2).min(stream.len());
+            },
+        }
+    }
+
+    (body, pos)
+}
+
+impl HasPayload for Http1Request {
+    fn payload(&self) -> Vec<u8> {
+        let mut ret = Vec::new();
+
+        ret.extend_from_slice(self.method.as_bytes());
+        ret.push(b' ');
+        ret.extend_from_slice(self.target.bytes());
+        ret.push(b' ');
+        ret.extend_from_slice(&self.version);
+        ret.extend_from_slice(b"\r\n");
+
+        for (name, value) in &self.headers {
+            ret.extend_from_slice(name);
+            ret.extend_from_slice(b": ");
+            ret.extend_from_slice(value.bytes());
+            ret.extend_from_slice(b"\r\n");
+        }
+
+        if !self.body.bytes().is_empty() {
+            ret.extend_from_slice(format!("Content-Length: {}\r\n", self.body.bytes().len()).as_bytes());
+        }
+
+        ret.extend_from_slice(b"\r\n");
+        ret.extend_from_slice(self.body.bytes());
+        ret
+    }
+}
+
+impl HasValidityOracle for Http1Request {
+    fn validity(&self, response: &[u8]) -> Validity {
+        match response.strip_prefix(b"HTTP/").and_then(|rest| rest.splitn(3, |c| *c == b' ').nth(1)) {
+            Some(code) => status_code_validity(code, b"4"),
+            None => Validity::Unknown,
+        }
+    }
+}
+
+impl HasPostMutationFixup for Http1Request {}
+
+impl<S> HasCrossoverInsertMutation<S> for Http1Request
+where
+    S: HasRand + HasMaxSize,
+{
+    fn mutate_crossover_insert(&mut self, state: &mut S, other: &Self, stage_idx: i32) -> Result<MutationResult, Error> {
+        self.body.mutate_crossover_insert(state, &other.body, stage_idx)
+    }
+}
+
+impl<S> HasCrossoverReplaceMutation<S> for Http1Request
+where
+    S: HasRand + HasMaxSize,
+{
+    fn mutate_crossover_replace(&mut self, state: &mut S, other: &Self, stage_idx: i32) -> Result<MutationResult, Error> {
+        self.body.mutate_crossover_replace(state, &other.body, stage_idx)
+    }
+}
+
+impl<S> HasSpliceMutation<S> for Http1Request
+where
+    S: HasRand + HasMaxSize,
+{
+    fn mutate_splice(&mut self, state: &mut S, other: &Self, stage_idx: i32) -> Result<MutationResult, Error> {
+        self.body.mutate_splice(state, &other.body, stage_idx)
+    }
+}
+
+impl<MT, S> HasHavocMutation<MT, S> for Http1Request
+where
+    MT: MutatorsTuple<BytesInput, S>,
+    S: HasRand + HasMaxSize,
+{
+    fn mutate_havoc(&mut self, state: &mut S, mutations: &mut MT, mutation: usize, stage_idx: i32) -> Result<MutationResult, Error> {
+        // 0: method, 1: target, 2: body, 3..: headers
+        let target = state.rand_mut().below(3 + self.headers.len() as u64) as usize;
+
+        match target {
+            0 => {
+                let method = state.rand_mut().choose(&HttpMethod::KNOWN).clone();
+
+                if method == self.method {
+                    Ok(MutationResult::Skipped)
+                } else {
+                    self.method = method;
+                    Ok(MutationResult::Mutated)
+                }
+            },
+            1 => mutations.get_and_mutate(mutation, state, &mut self.target, stage_idx),
+            2 => mutations.get_and_mutate(mutation, state, &mut self.body, stage_idx),
+            _ => mutations.get_and_mutate(mutation, state, &mut self.headers[target - 3].1, stage_idx),
+        }
+    }
+}
+
+/// A sequence of HTTP/1.x requests sent over one (keep-alive) connection.
+///
+/// It can be loaded from pcap files, where all requests the client sent over
+/// the first TCP connection make up the input.
+#[derive(Hash, Debug, Clone, Serialize, Deserialize)]
+pub struct Http1Input {
+    packets: Vec<NetworkPacket<Http1Request>>,
+}
+
+impl Http1Input {
+    /// Create a new Http1Input from a list of requests
+    pub fn new(requests: Vec<Http1Request>) -> Self {
+        Self {
+            packets: requests.into_iter().map(NetworkPacket::Data).collect(),
+        }
+    }
+
+    /// Parse all requests in a byte stream
+    pub fn parse(stream: &[u8]) -> Self {
+        let mut requests = Vec::new();
+        let mut rest = stream;
+
+        while let Some((request, len)) = Http1Request::parse(rest) {
+            requests.push(request);
+            rest = &rest[len..];
+        }
+
+        Self::new(requests)
+    }
+}
+
+impl Input for Http1Input {
+    fn generate_name(&self, idx: usize) -> String {
+        format!("http1-{}", idx)
+    }
+}
+
+impl HasPackets<NetworkPacket<Http1Request>> for Http1Input {
+    fn packets(&self) -> &[NetworkPacket<Http1Request>] {
+        &self.packets
+    }
+
+    fn packets_mut(&mut self) -> &mut Vec<NetworkPacket<Http1Request>> {
+        &mut self.packets
+    }
+}
+
+impl HasLen for Http1Input {
+    fn len(&self) -> usize {
+        self.packets.len()
+    }
+}
+
+impl HasPcapRepresentation<Http1Input> for Http1Input {
+    fn from_pcap(capture: Capture<Offline>) -> Result<Self, Error> {
+        let stream = first_tcp_connection(&capture_segments(capture)).concat();
+        Ok(Self::parse(&stream))
+    }
+}
+
+/// State inference function for the [`NetworkExecutor`](crate::NetworkExecutor)
+/// that uses the status code of the response as the state.
+pub fn status_code(response: &[u8]) -> Option<u32> {
+    let code = response.strip_prefix(b"HTTP/")?.splitn(3, |c| *c == b' ').nth(1)?;
+    std::str::from_utf8(code).ok()?.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let input = Http1Input::parse(b"GET / HTTP/1.1\r\nHost: a\r\n\r\nPOST /x HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n3\r\nabc\r\n2\r\nde\r\n0\r\n\r\nPUT /y HTTP/1.0\r\nContent-Length: 2\r\n\r\nhi");
+        let requests: Vec<&Http1Request> = input
+            .packets()
+            .iter()
+            .map(|packet| match packet {
+                NetworkPacket::Data(request) => request,
+                _ => unreachable!(),
+            })
+            .collect();
+
+        assert_eq!(requests.len(), 3);
+        assert_eq!(requests[0].payload(), b"GET / HTTP/1.1\r\nHost: a\r\n\r\n");
+        assert_eq!(requests[1].body().bytes(), b"abcde");
+        assert_eq!(requests[1].payload(), b"POST /x HTTP/1.1\r\nContent-Length: 5\r\n\r\nabcde");
+        assert_eq!(requests[2].method(), &HttpMethod::Put);
+        assert_eq!(requests[2].payload(), b"PUT /y HTTP/1.0\r\nContent-Length: 2\r\n\r\nhi");
+        assert_eq!(status_code(b"HTTP/1.1 404 Not Found\r\n"), Some(404));
+        assert_eq!(requests[0].validity(b"HTTP/1.1 404 Not Found\r\n"), Validity::Rejected);
+        assert_eq!(requests[0].validity(b"garbage"), Validity::Unknown);
+    }
+}
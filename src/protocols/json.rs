@@ -0,0 +1,872 @@
+//! Packet and input types for JSON-based protocols like JSON-RPC.
+//!
+//! __Only available with feature__: `protocol_json`
+//!
+//! Messages can be sent newline-delimited, with a `Content-Length` header
+//! like the language server protocol does or as WebSocket text frames.
+//! The mutators work on the parsed JSON tree: they delete keys, swap the types of values,
+//! nest values deeply and mutate strings and numbers. The top-level `id` and `jsonrpc`
+//! members are never mutated such that responses can still be matched to requests.
+//! Requests that refer to the id of an earlier response contain the placeholder [`RESPONSE_ID`],
+//! which the [`id_substitution()`] middleware replaces with the actual id.
+//!
+//! # Example
+//! ```
+//! let mut executor = NetworkExecutor::new(tuple_list!(state_observer), "127.0.0.1:4000".parse().unwrap(), "state", json::error_code);
+//! load_pcaps::<_, _, _, _, JsonInput, _>(&mut state, &mut fuzzer, &mut executor, &mut mgr, "pcaps").unwrap();
+//! ```
+
+use crate::{
+    executor::{HasPayload, NetworkPacket, TokenSubstitution},
+    input::{capture_segments, first_tcp_connection, HasPackets, HasPcapRepresentation},
+    mutators::{HasCrossoverInsertMutation, HasCrossoverReplaceMutation, HasHavocMutation, HasPostMutationFixup, HasSpliceMutation},
+};
+use libafl::{
+    bolts::{rands::Rand, HasLen},
+    inputs::{BytesInput, HasBytesVec, Input},
+    mutators::{MutationResult, MutatorsTuple},
+    state::{HasMaxSize, HasRand},
+    Error,
+};
+use pcap::{Capture, Offline};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+/// Top-level members that are never mutated
+const TRACKED_MEMBERS: [&str; 2] = ["id", "jsonrpc"];
+/// serde_json refuses to parse documents nested deeper than this
+const MAX_DEPTH: usize = 120;
+/// The masking key of all WebSocket frames we send
+const WEBSOCKET_MASK: [u8; 4] = [0x12, 0x34, 0x56, 0x78];
+
+/// Store JSON values as text since the binary formats libafl uses
+/// cannot deserialize self-describing values.
+mod json_text {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+    use serde_json::Value;
+
+    pub fn serialize<S: Serializer>(value: &Value, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&value.to_string())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Value, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        serde_json::from_str(&text).map_err(D::Error::custom)
+    }
+}
+
+/// How a JSON message is delimited on the wire
+#[derive(Hash, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum JsonFraming {
+    /// The message is followed by a `\n`
+    Line,
+    /// The message is preceded by a `Content-Length` header
+    ContentLength,
+    /// The message is sent in a masked WebSocket text frame
+    WebSocket,
+}
+
+/// A single JSON message
+#[derive(Hash, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JsonMessage {
+    #[serde(with = "json_text")]
+    value: Value,
+    framing: JsonFraming,
+}
+
+fn escape_pointer(key: &str) -> String {
+    key.replace('~', "~0").replace('/', "~1")
+}
+
+fn unescape_pointer(token: &str) -> String {
+    token.replace("~1", "/").replace("~0", "~")
+}
+
+/// Split a JSON pointer into the pointer of the parent and the last reference token
+fn split_pointer(pointer: &str) -> (&str, String) {
+    let idx = pointer.rfind('/').unwrap_or(0);
+    (&pointer[..idx], unescape_pointer(&pointer[(idx + 1).min(pointer.len())..]))
+}
+
+/// Collect the JSON pointers of all values below `value`
+fn collect_pointers(value: &Value, prefix: &str, out: &mut Vec<String>) {
+    match value {
+        Value::Array(elements) => {
+            for (idx, element) in elements.iter().enumerate() {
+                let pointer = format!("{}/{}", prefix, idx);
+                collect_pointers(element, &pointer, out);
+                out.push(pointer);
+            }
+        },
+        Value::Object(members) => {
+            for (key, member) in members {
+                if prefix.is_empty() && TRACKED_MEMBERS.contains(&key.as_str()) {
+                    continue;
+                }
+
+                let pointer = format!("{}/{}", prefix, escape_pointer(key));
+                collect_pointers(member, &pointer, out);
+                out.push(pointer);
+            }
+        },
+        _ => {},
+    }
+}
+
+fn depth(value: &Value) -> usize {
+    match value {
+        Value::Array(elements) => 1 + elements.iter().map(depth).max().unwrap_or(0),
+        Value::Object(members) => 1 + members.values().map(depth).max().unwrap_or(0),
+        _ => 0,
+    }
+}
+
+fn interesting_number<R: Rand>(rand: &mut R) -> Value {
+    match rand.below(10) {
+        0 => json!(0),
+        1 => json!(-1),
+        2 => json!(1),
+        3 => json!(i32::MAX),
+        4 => json!(i32::MIN),
+        5 => json!(i64::MAX),
+        6 => json!(i64::MIN),
+        7 => json!(u64::MAX),
+        8 => json!(0.5),
+        _ => json!(f64::MAX),
+    }
+}
+
+impl JsonMessage {
+    /// Create a new message
+    pub fn new(value: Value, framing: JsonFraming) -> Self {
+        Self {
+            value,
+            framing,
+        }
+    }
+
+    /// Get the JSON value
+    pub fn value(&self) -> &Value {
+        &self.value
+    }
+
+    /// Get the JSON value
+    pub fn value_mut(&mut self) -> &mut Value {
+        &mut self.value
+    }
+
+    /// Get the framing
+    pub fn framing(&self) -> JsonFraming {
+        self.framing
+    }
+
+    /// Get the JSON-RPC id of a request. Notifications don't have one.
+    pub fn id(&self) -> Option<&Value> {
+        self.value.get("id")
+    }
+
+    /// Get the JSON pointers of all values that may be mutated
+    fn pointers(&self) -> Vec<String> {
+        let mut ret = Vec::new();
+        collect_pointers(&self.value, "", &mut ret);
+        ret
+    }
+
+    /// Pick a random pointer to a value that satisfies `filter`
+    fn pick_pointer<S, F>(&self, state: &mut S, filter: F) -> Option<String>
+    where
+        S: HasRand,
+        F: Fn(&Value) -> bool,
+    {
+        let mut pointers = self.pointers();
+        pointers.retain(|pointer| filter(self.value.pointer(pointer).unwrap()));
+
+        if pointers.is_empty() {
+            None
+        } else {
+            let idx = state.rand_mut().below(pointers.len() as u64) as usize;
+            Some(pointers.swap_remove(idx))
+        }
+    }
+
+    fn mutate_delete<S: HasRand>(&mut self, state: &mut S) -> MutationResult {
+        let pointer = match self.pick_pointer(state, |_| true) {
+            Some(pointer) => pointer,
+            None => return MutationResult::Skipped,
+        };
+        let (parent, token) = split_pointer(&pointer);
+
+        match self.value.pointer_mut(parent) {
+            Some(Value::Object(members)) => {
+                members.remove(&token);
+            },
+            Some(Value::Array(elements)) => {
+                elements.remove(token.parse().unwrap());
+            },
+            _ => unreachable!(),
+        }
+
+        MutationResult::Mutated
+    }
+
+    fn mutate_type<S: HasRand>(&mut self, state: &mut S) -> MutationResult {
+        let pointer = match self.pick_pointer(state, |_| true) {
+            Some(pointer) => pointer,
+            None => return MutationResult::Skipped,
+        };
+        let kind = state.rand_mut().below(6);
+
+        // Wrapping the node in a container adds a level
+        if kind >= 4 && depth(&self.value) >= MAX_DEPTH {
+            return MutationResult::Skipped;
+        }
+
+        let node = self.value.pointer_mut(&pointer).unwrap();
+
+        // Keep the old content wherever the new type allows it
+        let new = match kind {
+            0 => Value::Null,
+            1 => Value::Bool(state.rand_mut().below(2) == 0),
+            2 => interesting_number(state.rand_mut()),
+            3 => Value::String(node.to_string()),
+            4 => Value::Array(vec![node.take()]),
+            _ => json!({ "": node.take() }),
+        };
+
+        if *node == new {
+            MutationResult::Skipped
+        } else {
+            *node = new;
+            MutationResult::Mutated
+        }
+    }
+
+    fn mutate_nesting<S: HasRand + HasMaxSize>(&mut self, state: &mut S) -> MutationResult {
+        let levels = 1 + state.rand_mut().below(64) as usize;
+
+        if depth(&self.value) + levels > MAX_DEPTH || self.value.to_string().len() + 2 * levels > state.max_size() {
+            return MutationResult::Skipped;
+        }
+
+        let pointer = match self.pick_pointer(state, |_| true) {
+            Some(pointer) => pointer,
+            None => return MutationResult::Skipped,
+        };
+        let node = self.value.pointer_mut(&pointer).unwrap();
+        let mut nested = node.take();
+
+        for _ in 0..levels {
+            nested = Value::Array(vec![nested]);
+        }
+
+        *node = nested;
+        MutationResult::Mutated
+    }
+
+    fn mutate_number<S: HasRand>(&mut self, state: &mut S) -> MutationResult {
+        let pointer = match self.pick_pointer(state, Value::is_number) {
+            Some(pointer) => pointer,
+            None => return MutationResult::Skipped,
+        };
+        let new = interesting_number(state.rand_mut());
+        let node = self.value.pointer_mut(&pointer).unwrap();
+
+        if *node == new {
+            MutationResult::Skipped
+        } else {
+            *node = new;
+            MutationResult::Mutated
+        }
+    }
+}
+
+impl HasPayload for JsonMessage {
+    fn payload(&self) -> Vec<u8> {
+        let text = self.value.to_string();
+
+        match self.framing {
+            JsonFraming::Line => format!("{}\n", text).into_bytes(),
+            JsonFraming::ContentLength => format!("Content-Length: {}\r\n\r\n{}", text.len(), text).into_bytes(),
+            JsonFraming::WebSocket => {
+                let mut ret = vec![0x81];
+                let len = text.len();
+
+                if len < 126 {
+                    ret.push(0x80 | len as u8);
+                } else if len <= u16::MAX as usize {
+                    ret.push(0x80 | 126);
+                    ret.extend_from_slice(&(len as u16).to_be_bytes());
+                } else {
+                    ret.push(0x80 | 127);
+                    ret.extend_from_slice(&(len as u64).to_be_bytes());
+                }
+
+                ret.extend_from_slice(&WEBSOCKET_MASK);
+                ret.extend(text.bytes().enumerate().map(|(idx, byte)| byte ^ WEBSOCKET_MASK[idx % 4]));
+                ret
+            },
+        }
+    }
+}
+
+impl<S> HasCrossoverInsertMutation<S> for JsonMessage
+where
+    S: HasRand + HasMaxSize,
+{
+    fn mutate_crossover_insert(&mut self, state: &mut S, other: &Self, _stage_idx: i32) -> Result<MutationResult, Error> {
+        let other_pointer = match other.pick_pointer(state, |_| true) {
+            Some(pointer) => pointer,
+            None => return Ok(MutationResult::Skipped),
+        };
+        let value = other.value.pointer(&other_pointer).unwrap().clone();
+
+        if depth(&self.value) + depth(&value) >= MAX_DEPTH || self.value.to_string().len() + value.to_string().len() > state.max_size() {
+            return Ok(MutationResult::Skipped);
+        }
+
+        // The root is a valid container too
+        let mut containers = self.pointers();
+        containers.push(String::new());
+        containers.retain(|pointer| matches!(self.value.pointer(pointer), Some(Value::Array(_)) | Some(Value::Object(_))));
+
+        if containers.is_empty() {
+            return Ok(MutationResult::Skipped);
+        }
+
+        let container = state.rand_mut().choose(&containers).clone();
+
+        match self.value.pointer_mut(&container).unwrap() {
+            Value::Array(elements) => {
+                let idx = state.rand_mut().below(elements.len() as u64 + 1) as usize;
+                elements.insert(idx, value);
+            },
+            Value::Object(members) => {
+                let (_, key) = split_pointer(&other_pointer);
+
+                if container.is_empty() && TRACKED_MEMBERS.contains(&key.as_str()) {
+                    return Ok(MutationResult::Skipped);
+                }
+
+                members.insert(key, value);
+            },
+            _ => unreachable!(),
+        }
+
+        Ok(MutationResult::Mutated)
+    }
+}
+
+impl<S> HasCrossoverReplaceMutation<S> for JsonMessage
+where
+    S: HasRand + HasMaxSize,
+{
+    fn mutate_crossover_replace(&mut self, state: &mut S, other: &Self, _stage_idx: i32) -> Result<MutationResult, Error> {
+        let (pointer, other_pointer) = match (self.pick_pointer(state, |_| true), other.pick_pointer(state, |_| true)) {
+            (Some(pointer), Some(other_pointer)) => (pointer, other_pointer),
+            _ => return Ok(MutationResult::Skipped),
+        };
+        let value = other.value.pointer(&other_pointer).unwrap();
+
+        if depth(&self.value) + depth(value) >= MAX_DEPTH {
+            return Ok(MutationResult::Skipped);
+        }
+
+        let node = self.value.pointer_mut(&pointer).unwrap();
+
+        if node == value {
+            Ok(MutationResult::Skipped)
+        } else {
+            *node = value.clone();
+            Ok(MutationResult::Mutated)
+        }
+    }
+}
+
+impl<S> HasSpliceMutation<S> for JsonMessage
+where
+    S: HasRand + HasMaxSize,
+{
+    fn mutate_splice(&mut self, state: &mut S, other: &Self, stage_idx: i32) -> Result<MutationResult, Error> {
+        let (pointer, other_pointer) = match (self.pick_pointer(state, Value::is_string), other.pick_pointer(state, Value::is_string)) {
+            (Some(pointer), Some(other_pointer)) => (pointer, other_pointer),
+            _ => return Ok(MutationResult::Skipped),
+        };
+        let node = self.value.pointer_mut(&pointer).unwrap();
+        let mut bytes = BytesInput::new(node.as_str().unwrap().as_bytes().to_vec());
+        let other_bytes = BytesInput::new(other.value.pointer(&other_pointer).unwrap().as_str().unwrap().as_bytes().to_vec());
+
+        let result = bytes.mutate_splice(state, &other_bytes, stage_idx)?;
+        *node = Value::String(String::from_utf8_lossy(bytes.bytes()).into_owned());
+        Ok(result)
+    }
+}
+
+impl<MT, S> HasHavocMutation<MT, S> for JsonMessage
+where
+    MT: MutatorsTuple<BytesInput, S>,
+    S: HasRand + HasMaxSize,
+{
+    fn mutate_havoc(&mut self, state: &mut S, mutations: &mut MT, mutation: usize, stage_idx: i32) -> Result<MutationResult, Error> {
+        match state.rand_mut().below(10) {
+            0 => Ok(self.mutate_delete(state)),
+            1 => Ok(self.mutate_type(state)),
+            2 => Ok(self.mutate_nesting(state)),
+            3 => Ok(self.mutate_number(state)),
+            _ => {
+                let pointer = match self.pick_pointer(state, Value::is_string) {
+                    Some(pointer) => pointer,
+                    None => return Ok(MutationResult::Skipped),
+                };
+                let node = self.value.pointer_mut(&pointer).unwrap();
+                let mut bytes = BytesInput::new(node.as_str().unwrap().as_bytes().to_vec());
+
+                let result = mutations.get_and_mutate(mutation, state, &mut bytes, stage_idx)?;
+                *node = Value::String(String::from_utf8_lossy(bytes.bytes()).into_owned());
+                Ok(result)
+            },
+        }
+    }
+}
+
+/// A packet of a JSON-based protocol
+#[derive(Hash, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum JsonPacket {
+    /// The HTTP request that upgrades the connection to a WebSocket
+    Upgrade {
+        /// Value of the `Host` header
+        host: Vec<u8>,
+        /// The requested resource
+        path: Vec<u8>,
+    },
+    /// A JSON message
+    Message(JsonMessage),
+}
+
+impl HasPayload for JsonPacket {
+    fn payload(&self) -> Vec<u8> {
+        match self {
+            JsonPacket::Upgrade {
+                host,
+                path,
+            } => {
+                let mut ret = b"GET ".to_vec();
+                ret.extend_from_slice(path);
+                ret.extend_from_slice(b" HTTP/1.1\r\nHost: ");
+                ret.extend_from_slice(host);
+                ret.extend_from_slice(b"\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n\r\n");
+                ret
+            },
+            JsonPacket::Message(message) => message.payload(),
+        }
+    }
+}
+
+impl HasPostMutationFixup for JsonPacket {}
+
+impl<S> HasCrossoverInsertMutation<S> for JsonPacket
+where
+    S: HasRand + HasMaxSize,
+{
+    fn mutate_crossover_insert(&mut self, state: &mut S, other: &Self, stage_idx: i32) -> Result<MutationResult, Error> {
+        match (self, other) {
+            (JsonPacket::Message(message), JsonPacket::Message(other)) => message.mutate_crossover_insert(state, other, stage_idx),
+            _ => Ok(MutationResult::Skipped),
+        }
+    }
+}
+
+impl<S> HasCrossoverReplaceMutation<S> for JsonPacket
+where
+    S: HasRand + HasMaxSize,
+{
+    fn mutate_crossover_replace(&mut self, state: &mut S, other: &Self, stage_idx: i32) -> Result<MutationResult, Error> {
+        match (self, other) {
+            (JsonPacket::Message(message), JsonPacket::Message(other)) => message.mutate_crossover_replace(state, other, stage_idx),
+            _ => Ok(MutationResult::Skipped),
+        }
+    }
+}
+
+impl<S> HasSpliceMutation<S> for JsonPacket
+where
+    S: HasRand + HasMaxSize,
+{
+    fn mutate_splice(&mut self, state: &mut S, other: &Self, stage_idx: i32) -> Result<MutationResult, Error> {
+        match (self, other) {
+            (JsonPacket::Message(message), JsonPacket::Message(other)) => message.mutate_splice(state, other, stage_idx),
+            _ => Ok(MutationResult::Skipped),
+        }
+    }
+}
+
+impl<MT, S> HasHavocMutation<MT, S> for JsonPacket
+where
+    MT: MutatorsTuple<BytesInput, S>,
+    S: HasRand + HasMaxSize,
+{
+    fn mutate_havoc(&mut self, state: &mut S, mutations: &mut MT, mutation: usize, stage_idx: i32) -> Result<MutationResult, Error> {
+        match self {
+            JsonPacket::Upgrade {
+                ..
+            } => Ok(MutationResult::Skipped),
+            JsonPacket::Message(message) => message.mutate_havoc(state, mutations, mutation, stage_idx),
+        }
+    }
+}
+
+/// Parse all JSON values in `stream` that directly follow each other
+fn parse_values(stream: &[u8]) -> (Vec<Value>, usize) {
+    let mut values = serde_json::Deserializer::from_slice(stream).into_iter::<Value>();
+    let mut ret = Vec::new();
+
+    while let Some(Ok(value)) = values.next() {
+        ret.push(value);
+    }
+
+    (ret, values.byte_offset())
+}
+
+/// Find the end of an HTTP header block
+fn header_end(stream: &[u8]) -> Option<usize> {
+    stream.windows(4).position(|w| w == b"\r\n\r\n").map(|idx| idx + 4)
+}
+
+/// Decode a WebSocket frame. Returns the opcode, the unmasked payload and the length of the frame.
+fn websocket_frame(stream: &[u8]) -> Option<(u8, Vec<u8>, usize)> {
+    let opcode = *stream.first()? & 0x0F;
+    let masked = *stream.get(1)? & 0x80 != 0;
+    let (len, mut pos) = match stream[1] & 0x7F {
+        126 => (u16::from_be_bytes(stream.get(2..4)?.try_into().unwrap()) as usize, 4),
+        127 => (u64::from_be_bytes(stream.get(2..10)?.try_into().unwrap()) as usize, 10),
+        len => (len as usize, 2),
+    };
+    let mut mask = [0; 4];
+
+    if masked {
+        mask.copy_from_slice(stream.get(pos..pos + 4)?);
+        pos += 4;
+    }
+
+    let payload = stream.get(pos..pos.checked_add(len)?)?.iter().enumerate().map(|(idx, byte)| byte ^ mask[idx % 4]).collect();
+    Some((opcode, payload, pos + len))
+}
+
+/// A sequence of JSON messages sent over one connection.
+///
+/// It can be loaded from pcap files, where all messages the client sent over
+/// the first TCP connection make up the input. The framing is detected automatically.
+#[derive(Hash, Debug, Clone, Serialize, Deserialize)]
+pub struct JsonInput {
+    packets: Vec<NetworkPacket<JsonPacket>>,
+}
+
+impl JsonInput {
+    /// Create a new JsonInput from a list of packets
+    pub fn new(packets: Vec<JsonPacket>) -> Self {
+        Self {
+            packets: packets.into_iter().map(NetworkPacket::Data).collect(),
+        }
+    }
+
+    /// Parse all messages in a byte stream.
+    ///
+    /// Fails if a stream with `Content-Length` framing has a message without a valid header.
+    pub fn parse(stream: &[u8]) -> Result<Self, Error> {
+        let mut packets = Vec::new();
+
+        if stream.starts_with(b"GET ") {
+            let end = match header_end(stream) {
+                Some(end) => end,
+                None => return Ok(Self::new(packets)),
+            };
+            let head = &stream[..end];
+            let path = head[4..].split(|c| *c == b' ').next().unwrap_or(b"/");
+            let host = head.split(|c| *c == b'\n').find_map(|line| if line.to_ascii_lowercase().starts_with(b"host:") { std::str::from_utf8(&line[5..]).ok().map(|host| host.trim().as_bytes()) } else { None }).unwrap_or(b"localhost");
+
+            packets.push(JsonPacket::Upgrade {
+                host: host.to_vec(),
+                path: path.to_vec(),
+            });
+
+            let mut rest = &stream[end..];
+
+            while let Some((opcode, payload, len)) = websocket_frame(rest) {
+                if opcode == 0x1 {
+                    packets.extend(parse_values(&payload).0.into_iter().map(|value| JsonPacket::Message(JsonMessage::new(value, JsonFraming::WebSocket))));
+                }
+
+                rest = &rest[len..];
+            }
+        } else if stream.to_ascii_lowercase().starts_with(b"content-length:") {
+            let mut rest = stream;
+
+            loop {
+                // Tolerate line breaks between and after the messages
+                rest = &rest[rest.iter().take_while(|c| c.is_ascii_whitespace()).count()..];
+
+                if rest.is_empty() {
+                    break;
+                }
+
+                if !rest.get(..15).is_some_and(|name| name.eq_ignore_ascii_case(b"content-length:")) {
+                    return Err(Error::illegal_argument("Expected a Content-Length header before every JSON message"));
+                }
+
+                let end = header_end(rest).ok_or_else(|| Error::illegal_argument("Content-Length header without an end"))?;
+                let len: usize = std::str::from_utf8(&rest[15..end]).ok().and_then(|len| len.split("\r\n").next()?.trim().parse().ok()).ok_or_else(|| Error::illegal_argument("Invalid Content-Length header"))?;
+                let body = &rest[end..(end + len).min(rest.len())];
+
+                if let Ok(value) = serde_json::from_slice(body) {
+                    packets.push(JsonPacket::Message(JsonMessage::new(value, JsonFraming::ContentLength)));
+                }
+
+                rest = &rest[end + body.len()..];
+            }
+        } else {
+            packets.extend(parse_values(stream).0.into_iter().map(|value| JsonPacket::Message(JsonMessage::new(value, JsonFraming::Line))));
+        }
+
+        Ok(Self::new(packets))
+    }
+
+    /// Give all requests that have an id a new, unique one, counting up from 1
+    pub fn renumber_ids(&mut self) {
+        let mut next_id = 1u64;
+
+        for packet in &mut self.packets {
+            if let NetworkPacket::Data(JsonPacket::Message(message)) = packet {
+                if let Some(id) = message.value.get_mut("id") {
+                    *id = json!(next_id);
+                    next_id += 1;
+                }
+            }
+        }
+    }
+}
+
+impl Input for JsonInput {
+    fn generate_name(&self, idx: usize) -> String {
+        format!("json-{}", idx)
+    }
+}
+
+impl HasPackets<NetworkPacket<JsonPacket>> for JsonInput {
+    fn packets(&self) -> &[NetworkPacket<JsonPacket>] {
+        &self.packets
+    }
+
+    fn packets_mut(&mut self) -> &mut Vec<NetworkPacket<JsonPacket>> {
+        &mut self.packets
+    }
+}
+
+impl HasLen for JsonInput {
+    fn len(&self) -> usize {
+        self.packets.len()
+    }
+}
+
+impl HasPcapRepresentation<JsonInput> for JsonInput {
+    fn from_pcap(capture: Capture<Offline>) -> Result<Self, Error> {
+        let stream = first_tcp_connection(&capture_segments(capture)).concat();
+        Self::parse(&stream)
+    }
+}
+
+/// Placeholder for the id of the last response, see [`id_substitution()`]
+pub const RESPONSE_ID: &str = "$RESPONSE_ID";
+
+/// A [`TokenSubstitution`] that echoes the ids that the target sends back, e.g. of subscriptions or sessions.
+///
+/// Every string value [`RESPONSE_ID`] in a request is replaced with the `id` member of the last response,
+/// number or string. Requests that are sent before the first response keep the placeholder.
+///
+/// # Example
+/// ```
+/// let mut executor = NetworkExecutor::new(tuple_list!(state_observer), "127.0.0.1:4000".parse().unwrap(), "state", json::error_code)
+///     .with_middleware(json::id_substitution());
+/// let unsubscribe = JsonMessage::new(json!({"jsonrpc": "2.0", "method": "unsubscribe", "params": [json::RESPONSE_ID]}), JsonFraming::Line);
+/// ```
+pub fn id_substitution() -> TokenSubstitution {
+    TokenSubstitution::new().with_token(format!("\"{}\"", RESPONSE_ID).as_bytes(), b"\"id\":", b",}\r\n")
+}
+
+/// State inference function for the [`NetworkExecutor`](crate::NetworkExecutor)
+/// that uses the JSON-RPC error code of the first response as the state.
+///
+/// Successful responses are state `0`, a completed WebSocket handshake is state `101`.
+/// The ids that servers echo back are ignored.
+pub fn error_code(response: &[u8]) -> Option<u32> {
+    let mut body = response;
+
+    if body.starts_with(b"HTTP/1.1 101") {
+        body = &body[header_end(body)?..];
+
+        if body.is_empty() {
+            return Some(101);
+        }
+    }
+
+    let frame;
+
+    if body.first() == Some(&0x81) {
+        frame = websocket_frame(body)?.1;
+        body = &frame;
+    } else if body.to_ascii_lowercase().starts_with(b"content-length:") {
+        body = &body[header_end(body)?..];
+    }
+
+    let (values, _) = parse_values(body);
+    let value = match values.into_iter().next()? {
+        Value::Array(mut batch) if !batch.is_empty() => batch.swap_remove(0),
+        value => value,
+    };
+
+    match value.get("error") {
+        Some(error) => error.get("code")?.as_i64().map(|code| code as u32),
+        None => value.get("result").map(|_| 0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ExecutorMiddleware;
+    use libafl::bolts::rands::StdRand;
+
+    struct TestState {
+        rand: StdRand,
+        max_size: usize,
+    }
+    impl HasRand for TestState {
+        type Rand = StdRand;
+
+        fn rand(&self) -> &StdRand {
+            &self.rand
+        }
+
+        fn rand_mut(&mut self) -> &mut StdRand {
+            &mut self.rand
+        }
+    }
+    impl HasMaxSize for TestState {
+        fn max_size(&self) -> usize {
+            self.max_size
+        }
+
+        fn set_max_size(&mut self, max_size: usize) {
+            self.max_size = max_size;
+        }
+    }
+
+    fn messages(input: &JsonInput) -> Vec<&JsonMessage> {
+        input
+            .packets()
+            .iter()
+            .filter_map(|packet| match packet {
+                NetworkPacket::Data(JsonPacket::Message(message)) => Some(message),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_parse() {
+        let mut input = JsonInput::parse(b"{\"jsonrpc\":\"2.0\",\"id\":7,\"method\":\"a\"}\n{\"jsonrpc\":\"2.0\",\"method\":\"b\"}\n{\"id\":7}").unwrap();
+        input.renumber_ids();
+        let parsed = messages(&input);
+
+        assert_eq!(parsed.len(), 3);
+        assert_eq!(parsed[0].id(), Some(&json!(1)));
+        assert_eq!(parsed[1].id(), None);
+        assert_eq!(parsed[2].payload(), b"{\"id\":2}\n");
+
+        let input = JsonInput::parse(b"Content-Length: 9\r\n\r\n{\"a\":[1]}Content-Length: 2\r\n\r\n{}").unwrap();
+        assert_eq!(messages(&input).len(), 2);
+        assert_eq!(messages(&input)[1].framing(), JsonFraming::ContentLength);
+
+        // Trailing line breaks and lowercase headers, but no header for the second message
+        let input = JsonInput::parse(b"content-length: 2\r\n\r\n{}\r\n\r\n").unwrap();
+        assert_eq!(messages(&input).len(), 1);
+        assert!(JsonInput::parse(b"Content-Length: 2\r\n\r\n{}{\"a\":1}\r\n\r\n").is_err());
+        assert!(JsonInput::parse(b"Content-Length: x\r\n\r\n{}").is_err());
+
+        let message = JsonMessage::new(json!({"a": "b"}), JsonFraming::WebSocket);
+        let mut stream = JsonPacket::Upgrade {
+            host: b"localhost".to_vec(),
+            path: b"/rpc".to_vec(),
+        }
+        .payload();
+        stream.extend(message.payload());
+
+        let input = JsonInput::parse(&stream).unwrap();
+        assert_eq!(input.packets().len(), 2);
+        assert_eq!(messages(&input)[0], &message);
+    }
+
+    #[test]
+    fn test_structured_mutations() {
+        let mut state = TestState {
+            rand: StdRand::with_seed(0),
+            max_size: 4096,
+        };
+        let other = JsonMessage::new(json!({"x": [true, null, {"y/z": "w"}]}), JsonFraming::Line);
+        let mut message = JsonMessage::new(json!({"jsonrpc": "2.0", "id": 1, "method": "m", "params": [1, "s", {"k": 2.5}]}), JsonFraming::Line);
+
+        for i in 0..2000 {
+            match i % 6 {
+                0 => message.mutate_delete(&mut state),
+                1 => message.mutate_type(&mut state),
+                2 => message.mutate_nesting(&mut state),
+                3 => message.mutate_number(&mut state),
+                4 => message.mutate_crossover_insert(&mut state, &other, 0).unwrap(),
+                _ => message.mutate_crossover_replace(&mut state, &other, 0).unwrap(),
+            };
+
+            assert_eq!(message.id(), Some(&json!(1)));
+            assert_eq!(message.value()["jsonrpc"], json!("2.0"));
+            assert!(depth(message.value()) <= MAX_DEPTH);
+            assert_eq!(parse_values(&message.payload()).0[0], message.value);
+        }
+    }
+
+    #[test]
+    fn test_type_depth() {
+        let mut state = TestState {
+            rand: StdRand::with_seed(0),
+            max_size: 4096,
+        };
+        let mut value = json!(1);
+
+        for _ in 0..MAX_DEPTH {
+            value = json!([value]);
+        }
+
+        let mut message = JsonMessage::new(value, JsonFraming::Line);
+
+        for _ in 0..1000 {
+            message.mutate_type(&mut state);
+            assert!(depth(message.value()) <= MAX_DEPTH);
+        }
+    }
+
+    #[test]
+    fn test_id_substitution() {
+        let mut tokens = id_substitution();
+        let mut request = JsonMessage::new(json!({"method": "unsubscribe", "params": [RESPONSE_ID]}), JsonFraming::Line).payload();
+
+        tokens.pre_exec();
+        tokens.on_receive(b"{\"jsonrpc\":\"2.0\",\"id\":\"sub-7\",\"result\":true}\n");
+        tokens.on_send(&mut request);
+        assert_eq!(parse_values(&request).0[0], json!({"method": "unsubscribe", "params": ["sub-7"]}));
+    }
+
+    #[test]
+    fn test_error_code() {
+        assert_eq!(error_code(b"{\"jsonrpc\":\"2.0\",\"id\":1,\"error\":{\"code\":-32601,\"message\":\"\"}}\n"), Some(-32601i32 as u32));
+        assert_eq!(error_code(b"Content-Length: 11\r\n\r\n{\"result\":1}"), Some(0));
+        assert_eq!(error_code(b"HTTP/1.1 101 Switching Protocols\r\n\r\n"), Some(101));
+        assert_eq!(error_code(b"\x81\x0c{\"result\":1}"), Some(0));
+        assert_eq!(error_code(b"garbage"), None);
+    }
+}
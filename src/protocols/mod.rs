@@ -0,0 +1,58 @@
+//! Ready-made packet and input types for common protocols.
+//!
+//! Every protocol lives in its own module that has to be enabled with a feature:
+//!
+//! | Module | Feature |
+//! |--------|---------|
+//! | `ftp` | `protocol_ftp` |
+//! | `smtp` | `protocol_smtp` |
+//! | `http1` | `protocol_http1` |
+//! | `dns` | `protocol_dns` |
+//! | `grpc` | `protocol_grpc` |
+//! | `json` | `protocol_json` |
+//! | `modbus` | `protocol_modbus` |
+//! | `mqtt` | `protocol_mqtt` |
+//! | `tls` | `protocol_tls` |
+//!
+//! Each module provides a packet type that implements all of butterflys mutation traits,
+//! an input type that can be loaded from pcap files and a state inference function
+//! for the [`NetworkExecutor`](crate::NetworkExecutor).
+//! The packets of connection-oriented protocols are wrapped in [`NetworkPacket`](crate::NetworkPacket)s such
+//! that the inputs can also express reconnects.
+
+#[cfg(feature = "protocol_dns")]
+pub mod dns;
+#[cfg(feature = "protocol_ftp")]
+pub mod ftp;
+#[cfg(feature = "protocol_grpc")]
+pub mod grpc;
+#[cfg(feature = "protocol_http1")]
+pub mod http1;
+#[cfg(feature = "protocol_json")]
+pub mod json;
+#[cfg(feature = "protocol_modbus")]
+pub mod modbus;
+#[cfg(feature = "protocol_mqtt")]
+pub mod mqtt;
+#[cfg(feature = "protocol_smtp")]
+pub mod smtp;
+#[cfg(feature = "protocol_tls")]
+pub mod tls;
+
+/// Parse the three-digit status code at the beginning of an FTP or SMTP reply
+#[cfg(any(feature = "protocol_ftp", feature = "protocol_smtp"))]
+fn parse_reply_code(response: &[u8]) -> Option<u32> {
+    let code = response.get(0..3)?;
+
+    if code.iter().all(u8::is_ascii_digit) {
+        std::str::from_utf8(code).ok()?.parse().ok()
+    } else {
+        None
+    }
+}
+
+/// Split a byte stream into lines, removing the line terminators
+#[cfg(any(feature = "protocol_ftp", feature = "protocol_smtp"))]
+fn lines(stream: &[u8]) -> impl Iterator<Item = &[u8]> {
+    stream.split(|c| *c == b'\n').map(|line| line.strip_suffix(b"\r").unwrap_or(line)).filter(|line| !line.is_empty())
+}
@@ -0,0 +1,344 @@
+//! Packet and input types for Modbus/TCP.
+//!
+//! __Only available with feature__: `protocol_modbus`
+//!
+//! # Example
+//! ```
+//! let mut executor = NetworkExecutor::new(tuple_list!(state_observer), "127.0.0.1:502".parse().unwrap(), "state", modbus::function_code);
+//! load_pcaps::<_, _, _, _, ModbusInput, _>(&mut state, &mut fuzzer, &mut executor, &mut mgr, "pcaps").unwrap();
+//! ```
+
+use crate::{
+    executor::{HasPayload, NetworkPacket},
+    input::{capture_segments, first_tcp_connection, HasPackets, HasPcapRepresentation},
+    mutators::{HasCrossoverInsertMutation, HasCrossoverReplaceMutation, HasHavocMutation, HasPostMutationFixup, HasSpliceMutation},
+};
+use libafl::{
+    bolts::{rands::Rand, HasLen},
+    inputs::{BytesInput, HasBytesVec, Input},
+    mutators::{MutationResult, MutatorsTuple},
+    state::{HasMaxSize, HasRand},
+    Error,
+};
+use pcap::{Capture, Offline};
+use serde::{Deserialize, Serialize};
+
+const MBAP_LEN: usize = 7;
+
+const READ_COILS: u8 = 0x01;
+const READ_DISCRETE_INPUTS: u8 = 0x02;
+const READ_HOLDING_REGISTERS: u8 = 0x03;
+const READ_INPUT_REGISTERS: u8 = 0x04;
+const WRITE_SINGLE_COIL: u8 = 0x05;
+const WRITE_SINGLE_REGISTER: u8 = 0x06;
+const WRITE_MULTIPLE_COILS: u8 = 0x0F;
+const WRITE_MULTIPLE_REGISTERS: u8 = 0x10;
+
+/// Function codes that a havoc mutation chooses from when it changes the function code
+const FUNCTION_CODES: [u8; 19] =
+    [READ_COILS, READ_DISCRETE_INPUTS, READ_HOLDING_REGISTERS, READ_INPUT_REGISTERS, WRITE_SINGLE_COIL, WRITE_SINGLE_REGISTER, 0x07, 0x08, 0x0B, 0x0C, WRITE_MULTIPLE_COILS, WRITE_MULTIPLE_REGISTERS, 0x11, 0x14, 0x15, 0x16, 0x17, 0x18, 0x2B];
+
+/// Values that a havoc mutation chooses from when it changes an address or quantity.
+/// These are the limits of the quantities in the specification and their neighbours.
+const INTERESTING_VALUES: [u16; 12] = [0, 1, 0x7B, 0x7C, 0x7D, 0x7E, 0x7B0, 0x7D0, 0x7D1, 0x7FFF, 0x8000, 0xFFFF];
+
+/// A Modbus/TCP request: the MBAP header and a PDU.
+///
+/// The length field of the MBAP header is computed when the packet is sent.
+/// For the function codes that address coils or registers the mutators know
+/// where the address and quantity fields are and change them with boundary values.
+/// The byte count of "write multiple" requests is kept in sync with the values.
+#[derive(Hash, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ModbusPacket {
+    transaction_id: u16,
+    unit_id: u8,
+    function_code: u8,
+    data: BytesInput,
+}
+
+impl ModbusPacket {
+    /// Create a new request
+    pub fn new(transaction_id: u16, unit_id: u8, function_code: u8, data: &[u8]) -> Self {
+        Self {
+            transaction_id,
+            unit_id,
+            function_code,
+            data: BytesInput::new(data.to_vec()),
+        }
+    }
+
+    /// Parse a single ADU. Returns the packet and the number of bytes consumed.
+    pub fn parse(stream: &[u8]) -> Option<(Self, usize)> {
+        let header = stream.get(..MBAP_LEN)?;
+        let len = u16::from_be_bytes([header[4], header[5]]) as usize;
+
+        // The length covers the unit id and the PDU
+        if len < 2 {
+            return None;
+        }
+
+        let end = (MBAP_LEN - 1 + len).min(stream.len());
+        let function_code = *stream.get(MBAP_LEN)?;
+
+        Some((Self::new(u16::from_be_bytes([header[0], header[1]]), header[6], function_code, &stream[MBAP_LEN + 1..end]), end))
+    }
+
+    /// Get the transaction identifier
+    pub fn transaction_id(&self) -> u16 {
+        self.transaction_id
+    }
+
+    /// Get the unit identifier
+    pub fn unit_id(&self) -> u8 {
+        self.unit_id
+    }
+
+    /// Get the function code
+    pub fn function_code(&self) -> u8 {
+        self.function_code
+    }
+
+    /// Get the data of the PDU after the function code
+    pub fn data(&self) -> &BytesInput {
+        &self.data
+    }
+
+    /// Get the number of 16-bit fields at the beginning of the data that
+    /// hold addresses and quantities for the current function code
+    fn num_fields(&self) -> usize {
+        let num = match self.function_code {
+            READ_COILS | READ_DISCRETE_INPUTS | READ_HOLDING_REGISTERS | READ_INPUT_REGISTERS | WRITE_SINGLE_COIL | WRITE_SINGLE_REGISTER | WRITE_MULTIPLE_COILS | WRITE_MULTIPLE_REGISTERS => 2,
+            _ => 0,
+        };
+        num.min(self.data.bytes().len() / 2)
+    }
+
+    /// Update the byte count of "write multiple" requests
+    fn fix_byte_count(&mut self) {
+        if matches!(self.function_code, WRITE_MULTIPLE_COILS | WRITE_MULTIPLE_REGISTERS) && self.data.bytes().len() >= 5 {
+            let count = (self.data.bytes().len() - 5).min(u8::MAX as usize) as u8;
+            self.data.bytes_mut()[4] = count;
+        }
+    }
+
+    fn mutate_function_code<S: HasRand>(&mut self, state: &mut S) -> MutationResult {
+        let function_code = match state.rand_mut().below(8) {
+            // Exception responses and reserved codes
+            0 => state.rand_mut().below(256) as u8,
+            _ => *state.rand_mut().choose(&FUNCTION_CODES),
+        };
+
+        if function_code == self.function_code {
+            MutationResult::Skipped
+        } else {
+            self.function_code = function_code;
+            MutationResult::Mutated
+        }
+    }
+
+    fn mutate_field<S: HasRand>(&mut self, state: &mut S) -> MutationResult {
+        let num_fields = self.num_fields();
+
+        if num_fields == 0 {
+            return MutationResult::Skipped;
+        }
+
+        let offset = 2 * state.rand_mut().below(num_fields as u64) as usize;
+        let old = u16::from_be_bytes([self.data.bytes()[offset], self.data.bytes()[offset + 1]]);
+        let new = match state.rand_mut().below(4) {
+            0 => old.wrapping_add(1),
+            1 => old.wrapping_sub(1),
+            _ => *state.rand_mut().choose(&INTERESTING_VALUES),
+        };
+
+        if new == old {
+            return MutationResult::Skipped;
+        }
+
+        self.data.bytes_mut()[offset..offset + 2].copy_from_slice(&new.to_be_bytes());
+        MutationResult::Mutated
+    }
+}
+
+impl HasPayload for ModbusPacket {
+    fn payload(&self) -> Vec<u8> {
+        let len = (2 + self.data.bytes().len()).min(u16::MAX as usize) as u16;
+        let mut ret = Vec::with_capacity(MBAP_LEN + 1 + self.data.bytes().len());
+
+        ret.extend_from_slice(&self.transaction_id.to_be_bytes());
+        ret.extend_from_slice(&[0, 0]);
+        ret.extend_from_slice(&len.to_be_bytes());
+        ret.push(self.unit_id);
+        ret.push(self.function_code);
+        ret.extend_from_slice(self.data.bytes());
+        ret
+    }
+}
+
+impl HasPostMutationFixup for ModbusPacket {}
+
+impl<S> HasCrossoverInsertMutation<S> for ModbusPacket
+where
+    S: HasRand + HasMaxSize,
+{
+    fn mutate_crossover_insert(&mut self, state: &mut S, other: &Self, stage_idx: i32) -> Result<MutationResult, Error> {
+        let result = self.data.mutate_crossover_insert(state, &other.data, stage_idx)?;
+        self.fix_byte_count();
+        Ok(result)
+    }
+}
+
+impl<S> HasCrossoverReplaceMutation<S> for ModbusPacket
+where
+    S: HasRand + HasMaxSize,
+{
+    fn mutate_crossover_replace(&mut self, state: &mut S, other: &Self, stage_idx: i32) -> Result<MutationResult, Error> {
+        let result = self.data.mutate_crossover_replace(state, &other.data, stage_idx)?;
+        self.fix_byte_count();
+        Ok(result)
+    }
+}
+
+impl<S> HasSpliceMutation<S> for ModbusPacket
+where
+    S: HasRand + HasMaxSize,
+{
+    fn mutate_splice(&mut self, state: &mut S, other: &Self, stage_idx: i32) -> Result<MutationResult, Error> {
+        let result = self.data.mutate_splice(state, &other.data, stage_idx)?;
+        self.fix_byte_count();
+        Ok(result)
+    }
+}
+
+impl<MT, S> HasHavocMutation<MT, S> for ModbusPacket
+where
+    MT: MutatorsTuple<BytesInput, S>,
+    S: HasRand + HasMaxSize,
+{
+    fn mutate_havoc(&mut self, state: &mut S, mutations: &mut MT, mutation: usize, stage_idx: i32) -> Result<MutationResult, Error> {
+        match state.rand_mut().below(8) {
+            0 => Ok(self.mutate_function_code(state)),
+            1 => {
+                let unit_id = state.rand_mut().below(256) as u8;
+
+                if unit_id == self.unit_id {
+                    Ok(MutationResult::Skipped)
+                } else {
+                    self.unit_id = unit_id;
+                    Ok(MutationResult::Mutated)
+                }
+            },
+            2 | 3 => Ok(self.mutate_field(state)),
+            _ => {
+                let result = mutations.get_and_mutate(mutation, state, &mut self.data, stage_idx)?;
+                self.fix_byte_count();
+                Ok(result)
+            },
+        }
+    }
+}
+
+/// A sequence of Modbus/TCP requests sent over one connection.
+///
+/// It can be loaded from pcap files, where all requests the client sent over
+/// the first TCP connection make up the input.
+#[derive(Hash, Debug, Clone, Serialize, Deserialize)]
+pub struct ModbusInput {
+    packets: Vec<NetworkPacket<ModbusPacket>>,
+}
+
+impl ModbusInput {
+    /// Create a new ModbusInput from a list of requests
+    pub fn new(requests: Vec<ModbusPacket>) -> Self {
+        Self {
+            packets: requests.into_iter().map(NetworkPacket::Data).collect(),
+        }
+    }
+
+    /// Parse all requests in a byte stream
+    pub fn parse(stream: &[u8]) -> Self {
+        let mut requests = Vec::new();
+        let mut rest = stream;
+
+        while let Some((request, len)) = ModbusPacket::parse(rest) {
+            requests.push(request);
+            rest = &rest[len..];
+        }
+
+        Self::new(requests)
+    }
+}
+
+impl Input for ModbusInput {
+    fn generate_name(&self, idx: usize) -> String {
+        format!("modbus-{}", idx)
+    }
+}
+
+impl HasPackets<NetworkPacket<ModbusPacket>> for ModbusInput {
+    fn packets(&self) -> &[NetworkPacket<ModbusPacket>] {
+        &self.packets
+    }
+
+    fn packets_mut(&mut self) -> &mut Vec<NetworkPacket<ModbusPacket>> {
+        &mut self.packets
+    }
+}
+
+impl HasLen for ModbusInput {
+    fn len(&self) -> usize {
+        self.packets.len()
+    }
+}
+
+impl HasPcapRepresentation<ModbusInput> for ModbusInput {
+    fn from_pcap(capture: Capture<Offline>) -> Result<Self, Error> {
+        let stream = first_tcp_connection(&capture_segments(capture)).concat();
+        Ok(Self::parse(&stream))
+    }
+}
+
+/// State inference function for the [`NetworkExecutor`](crate::NetworkExecutor)
+/// that uses the function code of the response as the state.
+///
+/// For exception responses the exception code is part of the state too.
+pub fn function_code(response: &[u8]) -> Option<u32> {
+    let function_code = *response.get(MBAP_LEN)? as u32;
+
+    if function_code & 0x80 != 0 {
+        Some(function_code << 8 | *response.get(MBAP_LEN + 1)? as u32)
+    } else {
+        Some(function_code)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        // Read holding registers 0-9, then write two registers at 1
+        let stream = b"\x00\x01\x00\x00\x00\x06\x01\x03\x00\x00\x00\x0a\x00\x02\x00\x00\x00\x0b\x01\x10\x00\x01\x00\x02\x04\x00\x0a\x01\x02";
+        let input = ModbusInput::parse(stream);
+
+        assert_eq!(input.len(), 2);
+
+        let mut payload = Vec::new();
+        for packet in input.packets() {
+            match packet {
+                NetworkPacket::Data(request) => payload.extend(request.payload()),
+                _ => unreachable!(),
+            }
+        }
+        assert_eq!(payload, stream);
+
+        let mut request = ModbusPacket::new(3, 1, WRITE_MULTIPLE_REGISTERS, b"\x00\x01\x00\x02\x04\x00\x0a");
+        request.fix_byte_count();
+        assert_eq!(request.data().bytes()[4], 2);
+        assert_eq!(&request.payload()[4..6], &[0, 9]);
+
+        assert_eq!(function_code(b"\x00\x01\x00\x00\x00\x03\x01\x83\x02"), Some(0x8302));
+        assert_eq!(function_code(b"\x00\x01\x00\x00\x00\x05\x01\x03\x02\x00\x00"), Some(3));
+    }
+}
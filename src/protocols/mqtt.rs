@@ -0,0 +1,327 @@
+//! Packet and input types for MQTT.
+//!
+//! __Only available with feature__: `protocol_mqtt`
+//!
+//! # Example
+//! ```
+//! let mut executor = NetworkExecutor::new(tuple_list!(state_observer), "127.0.0.1:1883".parse().unwrap(), "state", mqtt::packet_type);
+//! load_pcaps::<_, _, _, _, MqttInput, _>(&mut state, &mut fuzzer, &mut executor, &mut mgr, "pcaps").unwrap();
+//! ```
+
+use crate::{
+    executor::{HasPayload, NetworkPacket},
+    input::{capture_segments, first_tcp_connection, HasPackets, HasPcapRepresentation},
+    mutators::{HasCrossoverInsertMutation, HasCrossoverReplaceMutation, HasHavocMutation, HasPostMutationFixup, HasSpliceMutation},
+};
+use libafl::{
+    bolts::{rands::Rand, HasLen},
+    inputs::{BytesInput, HasBytesVec, Input},
+    mutators::{MutationResult, MutatorsTuple},
+    state::{HasMaxSize, HasRand},
+    Error,
+};
+use pcap::{Capture, Offline};
+use serde::{Deserialize, Serialize};
+
+const MQTT_PORT: u16 = 1883;
+/// The largest value the remaining length field can encode
+const MAX_REMAINING_LENGTH: usize = 268_435_455;
+
+const TYPE_CONNACK: u8 = 2;
+const TYPE_PUBLISH: u8 = 3;
+
+/// A single MQTT control packet.
+///
+/// The remaining length in the fixed header is not stored but computed
+/// from the body whenever the packet is sent, so it stays valid after every mutation.
+/// The topic of PUBLISH packets gets mutated separately and its length prefix is updated as well.
+#[derive(Hash, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MqttPacket {
+    packet_type: u8,
+    flags: u8,
+    body: BytesInput,
+}
+
+/// Encode the remaining length field
+fn encode_remaining_length(mut len: usize, buf: &mut Vec<u8>) {
+    loop {
+        let byte = (len % 128) as u8;
+        len /= 128;
+
+        if len > 0 {
+            buf.push(byte | 0x80);
+        } else {
+            buf.push(byte);
+            break;
+        }
+    }
+}
+
+/// Decode the remaining length field. Returns the length and the number of bytes it occupies.
+fn decode_remaining_length(buf: &[u8]) -> Option<(usize, usize)> {
+    let mut len = 0;
+
+    for (idx, byte) in buf.iter().take(4).enumerate() {
+        len |= ((byte & 0x7F) as usize) << (7 * idx);
+
+        if byte & 0x80 == 0 {
+            return Some((len, idx + 1));
+        }
+    }
+
+    None
+}
+
+impl MqttPacket {
+    /// Create a new packet with the given type (1-15), the flags of the fixed header and
+    /// the body (variable header and payload)
+    pub fn new(packet_type: u8, flags: u8, body: &[u8]) -> Self {
+        Self {
+            packet_type: packet_type & 0x0F,
+            flags: flags & 0x0F,
+            body: BytesInput::new(body.to_vec()),
+        }
+    }
+
+    /// Parse a single packet. Returns the packet and the number of bytes consumed.
+    pub fn parse(stream: &[u8]) -> Option<(Self, usize)> {
+        let header = *stream.first()?;
+        let (len, len_size) = decode_remaining_length(&stream[1..])?;
+        let start = 1 + len_size;
+        let end = (start + len).min(stream.len());
+
+        Some((Self::new(header >> 4, header, &stream[start..end]), end))
+    }
+
+    /// Get the packet type
+    pub fn packet_type(&self) -> u8 {
+        self.packet_type
+    }
+
+    /// Get the flags of the fixed header
+    pub fn flags(&self) -> u8 {
+        self.flags
+    }
+
+    /// Get the variable header and payload
+    pub fn body(&self) -> &BytesInput {
+        &self.body
+    }
+
+    /// Get the topic of a PUBLISH packet
+    pub fn topic(&self) -> Option<&[u8]> {
+        if self.packet_type != TYPE_PUBLISH {
+            return None;
+        }
+
+        let len = u16::from_be_bytes(self.body.bytes().get(0..2)?.try_into().unwrap()) as usize;
+        self.body.bytes().get(2..2 + len)
+    }
+
+    fn mutate_header<S: HasRand>(&mut self, state: &mut S) -> MutationResult {
+        let old = (self.packet_type, self.flags);
+
+        if state.rand_mut().below(2) == 0 {
+            self.packet_type = 1 + state.rand_mut().below(15) as u8;
+        } else {
+            // Mostly flip the DUP, QoS and RETAIN bits
+            self.flags ^= 1 << state.rand_mut().below(4);
+        }
+
+        if (self.packet_type, self.flags) == old {
+            MutationResult::Skipped
+        } else {
+            MutationResult::Mutated
+        }
+    }
+
+    fn mutate_topic<MT, S>(&mut self, state: &mut S, mutations: &mut MT, mutation: usize, stage_idx: i32) -> Result<MutationResult, Error>
+    where
+        MT: MutatorsTuple<BytesInput, S>,
+        S: HasRand + HasMaxSize,
+    {
+        let mut topic = match self.topic() {
+            Some(topic) => BytesInput::new(topic.to_vec()),
+            None => return Ok(MutationResult::Skipped),
+        };
+        let old_len = topic.bytes().len();
+
+        if mutations.get_and_mutate(mutation, state, &mut topic, stage_idx)? == MutationResult::Skipped {
+            return Ok(MutationResult::Skipped);
+        }
+
+        topic.bytes_mut().truncate(u16::MAX as usize);
+
+        let mut body = (topic.bytes().len() as u16).to_be_bytes().to_vec();
+        body.extend_from_slice(topic.bytes());
+        body.extend_from_slice(&self.body.bytes()[2 + old_len..]);
+        *self.body.bytes_mut() = body;
+
+        Ok(MutationResult::Mutated)
+    }
+}
+
+impl HasPayload for MqttPacket {
+    fn payload(&self) -> Vec<u8> {
+        let body = &self.body.bytes()[..self.body.bytes().len().min(MAX_REMAINING_LENGTH)];
+        let mut ret = Vec::with_capacity(5 + body.len());
+
+        ret.push(self.packet_type << 4 | self.flags);
+        encode_remaining_length(body.len(), &mut ret);
+        ret.extend_from_slice(body);
+        ret
+    }
+}
+
+impl HasPostMutationFixup for MqttPacket {}
+
+impl<S> HasCrossoverInsertMutation<S> for MqttPacket
+where
+    S: HasRand + HasMaxSize,
+{
+    fn mutate_crossover_insert(&mut self, state: &mut S, other: &Self, stage_idx: i32) -> Result<MutationResult, Error> {
+        self.body.mutate_crossover_insert(state, &other.body, stage_idx)
+    }
+}
+
+impl<S> HasCrossoverReplaceMutation<S> for MqttPacket
+where
+    S: HasRand + HasMaxSize,
+{
+    fn mutate_crossover_replace(&mut self, state: &mut S, other: &Self, stage_idx: i32) -> Result<MutationResult, Error> {
+        self.body.mutate_crossover_replace(state, &other.body, stage_idx)
+    }
+}
+
+impl<S> HasSpliceMutation<S> for MqttPacket
+where
+    S: HasRand + HasMaxSize,
+{
+    fn mutate_splice(&mut self, state: &mut S, other: &Self, stage_idx: i32) -> Result<MutationResult, Error> {
+        self.body.mutate_splice(state, &other.body, stage_idx)
+    }
+}
+
+impl<MT, S> HasHavocMutation<MT, S> for MqttPacket
+where
+    MT: MutatorsTuple<BytesInput, S>,
+    S: HasRand + HasMaxSize,
+{
+    fn mutate_havoc(&mut self, state: &mut S, mutations: &mut MT, mutation: usize, stage_idx: i32) -> Result<MutationResult, Error> {
+        match state.rand_mut().below(8) {
+            0 => Ok(self.mutate_header(state)),
+            1 | 2 if self.topic().is_some() => self.mutate_topic(state, mutations, mutation, stage_idx),
+            _ => mutations.get_and_mutate(mutation, state, &mut self.body, stage_idx),
+        }
+    }
+}
+
+/// A sequence of MQTT packets sent by a client over one connection.
+///
+/// It can be loaded from pcap files, where all packets the client sent over
+/// the first TCP connection to port 1883 make up the input.
+#[derive(Hash, Debug, Clone, Serialize, Deserialize)]
+pub struct MqttInput {
+    packets: Vec<NetworkPacket<MqttPacket>>,
+}
+
+impl MqttInput {
+    /// Create a new MqttInput from a list of packets
+    pub fn new(packets: Vec<MqttPacket>) -> Self {
+        Self {
+            packets: packets.into_iter().map(NetworkPacket::Data).collect(),
+        }
+    }
+
+    /// Parse all packets in a byte stream
+    pub fn parse(stream: &[u8]) -> Self {
+        let mut packets = Vec::new();
+        let mut rest = stream;
+
+        while let Some((packet, len)) = MqttPacket::parse(rest) {
+            packets.push(packet);
+            rest = &rest[len..];
+        }
+
+        Self::new(packets)
+    }
+}
+
+impl Input for MqttInput {
+    fn generate_name(&self, idx: usize) -> String {
+        format!("mqtt-{}", idx)
+    }
+}
+
+impl HasPackets<NetworkPacket<MqttPacket>> for MqttInput {
+    fn packets(&self) -> &[NetworkPacket<MqttPacket>] {
+        &self.packets
+    }
+
+    fn packets_mut(&mut self) -> &mut Vec<NetworkPacket<MqttPacket>> {
+        &mut self.packets
+    }
+}
+
+impl HasLen for MqttInput {
+    fn len(&self) -> usize {
+        self.packets.len()
+    }
+}
+
+impl HasPcapRepresentation<MqttInput> for MqttInput {
+    fn from_pcap(capture: Capture<Offline>) -> Result<Self, Error> {
+        let segments: Vec<_> = capture_segments(capture).into_iter().filter(|s| s.dst_port == MQTT_PORT).collect();
+        let stream = first_tcp_connection(&segments).concat();
+        Ok(Self::parse(&stream))
+    }
+}
+
+/// State inference function for the [`NetworkExecutor`](crate::NetworkExecutor)
+/// that uses the type of the first packet in the response as the state.
+///
+/// For CONNACK packets the return code is part of the state too.
+pub fn packet_type(response: &[u8]) -> Option<u32> {
+    let (packet, _) = MqttPacket::parse(response)?;
+    let state = (packet.packet_type as u32) << 8;
+
+    if packet.packet_type == TYPE_CONNACK {
+        Some(state | *packet.body.bytes().get(1)? as u32)
+    } else {
+        Some(state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        // CONNECT, PUBLISH "a/b" with a 200 byte payload, DISCONNECT
+        let mut stream = b"\x10\x0c\x00\x04MQTT\x04\x02\x00\x3c\x00\x00".to_vec();
+        stream.extend_from_slice(b"\x30\xcd\x01\x00\x03a/b");
+        stream.extend_from_slice(&[b'x'; 200]);
+        stream.extend_from_slice(b"\xe0\x00");
+
+        let input = MqttInput::parse(&stream);
+        let packets: Vec<&MqttPacket> = input
+            .packets()
+            .iter()
+            .map(|packet| match packet {
+                NetworkPacket::Data(packet) => packet,
+                _ => unreachable!(),
+            })
+            .collect();
+
+        assert_eq!(packets.len(), 3);
+        assert_eq!(packets[1].topic(), Some(&b"a/b"[..]));
+        assert_eq!(packets.iter().flat_map(|packet| packet.payload()).collect::<Vec<u8>>(), stream);
+
+        let mut packet = packets[1].clone();
+        packet.body.bytes_mut().truncate(10);
+        assert_eq!(&packet.payload()[..2], b"\x30\x0a");
+
+        assert_eq!(packet_type(b"\x20\x02\x00\x05"), Some(0x205));
+    }
+}
@@ -0,0 +1,411 @@
+//! Packet and input types for SMTP.
+//!
+//! __Only available with feature__: `protocol_smtp`
+//!
+//! # Example
+//! ```
+//! let mut executor = NetworkExecutor::new(tuple_list!(state_observer), "127.0.0.1:2525".parse().unwrap(), "state", smtp::status_code).with_greeting();
+//! load_pcaps::<_, _, _, _, SmtpInput, _>(&mut state, &mut fuzzer, &mut executor, &mut mgr, "pcaps").unwrap();
+//! ```
+
+use crate::{
+    executor::{status_code_validity, HasPayload, HasValidityOracle, NetworkPacket, Validity},
+    input::{capture_segments, first_tcp_connection, HasPackets, HasPcapRepresentation},
+    mutators::{HasCrossoverInsertMutation, HasCrossoverReplaceMutation, HasHavocMutation, HasKindConversion, HasPostMutationFixup, HasSpliceMutation, HasTerminalPacket},
+    protocols::{lines, parse_reply_code},
+};
+use libafl::{
+    bolts::{rands::Rand, HasLen},
+    inputs::{BytesInput, HasBytesVec, Input},
+    mutators::{MutationResult, MutatorsTuple},
+    state::{HasMaxSize, HasRand},
+    Error,
+};
+use pcap::{Capture, Offline};
+use serde::{Deserialize, Serialize};
+
+const END_OF_DATA: &[u8] = b"\r\n.\r\n";
+
+/// The verbs of all known commands, that commands get converted to
+const VERBS: [&[u8]; 13] = [b"HELO", b"EHLO", b"MAIL FROM:", b"RCPT TO:", b"DATA", b"RSET", b"VRFY", b"EXPN", b"HELP", b"AUTH", b"STARTTLS", b"NOOP", b"QUIT"];
+
+/// A command sent by an SMTP client.
+///
+/// The mail itself, that is sent after a `DATA` command, is a separate packet
+/// [`SmtpCommand::Message`]. It gets dot-stuffed and terminated automatically.
+/// Commands that are not known are stored as a whole line in [`SmtpCommand::Other`].
+/// Only the arguments of commands are mutated.
+#[derive(Hash, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[allow(missing_docs)]
+pub enum SmtpCommand {
+    Helo(BytesInput),
+    Ehlo(BytesInput),
+    /// `MAIL FROM:<argument>`
+    MailFrom(BytesInput),
+    /// `RCPT TO:<argument>`
+    RcptTo(BytesInput),
+    Data,
+    /// The content of a mail after `DATA`
+    Message(BytesInput),
+    Rset,
+    Vrfy(BytesInput),
+    Expn(BytesInput),
+    Help(Option<BytesInput>),
+    Auth(BytesInput),
+    StartTls,
+    Noop,
+    Quit,
+    /// Any other command line, without the terminating `\r\n`
+    Other(BytesInput),
+}
+
+impl SmtpCommand {
+    /// Parse a single command line. Returns `None` for empty lines.
+    ///
+    /// Note that this never returns [`SmtpCommand::Message`] since mails are not line-based.
+    pub fn from_line(line: &[u8]) -> Option<Self> {
+        let line = line.strip_suffix(b"\n").unwrap_or(line);
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+
+        if line.is_empty() {
+            return None;
+        }
+
+        let upper = line.to_ascii_uppercase();
+
+        if upper.starts_with(b"MAIL FROM:") {
+            return Some(SmtpCommand::MailFrom(BytesInput::new(line[10..].to_vec())));
+        } else if upper.starts_with(b"RCPT TO:") {
+            return Some(SmtpCommand::RcptTo(BytesInput::new(line[8..].to_vec())));
+        }
+
+        let (verb, arg) = match line.iter().position(|c| *c == b' ') {
+            Some(idx) => (&upper[..idx], Some(BytesInput::new(line[idx + 1..].to_vec()))),
+            None => (&upper[..], None),
+        };
+
+        let command = match (verb, arg) {
+            (b"HELO", Some(arg)) => SmtpCommand::Helo(arg),
+            (b"EHLO", Some(arg)) => SmtpCommand::Ehlo(arg),
+            (b"DATA", None) => SmtpCommand::Data,
+            (b"RSET", None) => SmtpCommand::Rset,
+            (b"VRFY", Some(arg)) => SmtpCommand::Vrfy(arg),
+            (b"EXPN", Some(arg)) => SmtpCommand::Expn(arg),
+            (b"HELP", arg) => SmtpCommand::Help(arg),
+            (b"AUTH", Some(arg)) => SmtpCommand::Auth(arg),
+            (b"STARTTLS", None) => SmtpCommand::StartTls,
+            (b"NOOP", None) => SmtpCommand::Noop,
+            (b"QUIT", None) => SmtpCommand::Quit,
+            _ => SmtpCommand::Other(BytesInput::new(line.to_vec())),
+        };
+
+        Some(command)
+    }
+
+    /// Get the verb of the command including the separator to the argument,
+    /// empty for [`SmtpCommand::Message`] and [`SmtpCommand::Other`]
+    pub fn verb(&self) -> &'static [u8] {
+        match self {
+            SmtpCommand::Helo(_) => b"HELO",
+            SmtpCommand::Ehlo(_) => b"EHLO",
+            SmtpCommand::MailFrom(_) => b"MAIL FROM:",
+            SmtpCommand::RcptTo(_) => b"RCPT TO:",
+            SmtpCommand::Data => b"DATA",
+            SmtpCommand::Message(_) => b"",
+            SmtpCommand::Rset => b"RSET",
+            SmtpCommand::Vrfy(_) => b"VRFY",
+            SmtpCommand::Expn(_) => b"EXPN",
+            SmtpCommand::Help(_) => b"HELP",
+            SmtpCommand::Auth(_) => b"AUTH",
+            SmtpCommand::StartTls => b"STARTTLS",
+            SmtpCommand::Noop => b"NOOP",
+            SmtpCommand::Quit => b"QUIT",
+            SmtpCommand::Other(_) => b"",
+        }
+    }
+
+    /// Get the argument of the command, if it has one
+    pub fn argument(&self) -> Option<&BytesInput> {
+        match self {
+            SmtpCommand::Helo(arg)
+            | SmtpCommand::Ehlo(arg)
+            | SmtpCommand::MailFrom(arg)
+            | SmtpCommand::RcptTo(arg)
+            | SmtpCommand::Message(arg)
+            | SmtpCommand::Vrfy(arg)
+            | SmtpCommand::Expn(arg)
+            | SmtpCommand::Help(Some(arg))
+            | SmtpCommand::Auth(arg)
+            | SmtpCommand::Other(arg) => Some(arg),
+            _ => None,
+        }
+    }
+
+    /// Get the argument of the command, if it has one (mutable)
+    pub fn argument_mut(&mut self) -> Option<&mut BytesInput> {
+        match self {
+            SmtpCommand::Helo(arg)
+            | SmtpCommand::Ehlo(arg)
+            | SmtpCommand::MailFrom(arg)
+            | SmtpCommand::RcptTo(arg)
+            | SmtpCommand::Message(arg)
+            | SmtpCommand::Vrfy(arg)
+            | SmtpCommand::Expn(arg)
+            | SmtpCommand::Help(Some(arg))
+            | SmtpCommand::Auth(arg)
+            | SmtpCommand::Other(arg) => Some(arg),
+            _ => None,
+        }
+    }
+}
+
+/// Escape lines starting with a dot and terminate the mail
+fn dot_stuff(message: &[u8]) -> Vec<u8> {
+    let mut ret = Vec::with_capacity(message.len() + END_OF_DATA.len());
+    let mut line_start = true;
+
+    for c in message {
+        if line_start && *c == b'.' {
+            ret.push(b'.');
+        }
+
+        ret.push(*c);
+        line_start = *c == b'\n';
+    }
+
+    ret.extend_from_slice(END_OF_DATA);
+    ret
+}
+
+/// Inverse of [`dot_stuff()`] without the terminator
+fn dot_unstuff(message: &[u8]) -> Vec<u8> {
+    let mut ret = Vec::with_capacity(message.len());
+    let mut line_start = true;
+
+    for (i, c) in message.iter().enumerate() {
+        if !(line_start && *c == b'.' && message.get(i + 1) == Some(&b'.')) {
+            ret.push(*c);
+        }
+
+        line_start = *c == b'\n';
+    }
+
+    ret
+}
+
+impl HasPayload for SmtpCommand {
+    fn payload(&self) -> Vec<u8> {
+        match self {
+            SmtpCommand::Message(message) => dot_stuff(message.bytes()),
+            SmtpCommand::Other(line) => [line.bytes(), b"\r\n"].concat(),
+            SmtpCommand::MailFrom(arg) | SmtpCommand::RcptTo(arg) => [self.verb(), arg.bytes(), b"\r\n"].concat(),
+            _ => match self.argument() {
+                Some(arg) => [self.verb(), b" ", arg.bytes(), b"\r\n"].concat(),
+                None => [self.verb(), b"\r\n"].concat(),
+            },
+        }
+    }
+}
+
+impl HasValidityOracle for SmtpCommand {
+    fn validity(&self, response: &[u8]) -> Validity {
+        status_code_validity(response.get(..3).unwrap_or_default(), b"45")
+    }
+}
+
+impl HasPostMutationFixup for SmtpCommand {}
+
+impl<S> HasKindConversion<S> for SmtpCommand
+where
+    S: HasRand,
+{
+    fn convert_kind(&mut self, state: &mut S) -> bool {
+        let verb = *state.rand_mut().choose(&VERBS);
+
+        if matches!(self, SmtpCommand::Message(_) | SmtpCommand::Other(_)) || verb == self.verb() {
+            return false;
+        }
+
+        // Keep the argument if the new command takes one, otherwise drop it or use an empty one.
+        // `MAIL FROM:` and `RCPT TO:` have no space before the argument.
+        let separator: &[u8] = if verb.ends_with(b":") { b"" } else { b" " };
+        let with_arg = self.argument().map(|arg| [verb, separator, arg.bytes()].concat());
+        let candidates = [with_arg, Some(verb.to_vec()), Some([verb, separator].concat())];
+
+        match candidates.iter().flatten().filter_map(|line| SmtpCommand::from_line(&[line, &b"\r\n"[..]].concat())).find(|command| !matches!(command, SmtpCommand::Other(_))) {
+            Some(command) => {
+                *self = command;
+                true
+            },
+            None => false,
+        }
+    }
+}
+
+impl HasTerminalPacket for SmtpCommand {
+    fn is_terminal(&self) -> bool {
+        matches!(self, SmtpCommand::Quit)
+    }
+}
+
+impl<S> HasCrossoverInsertMutation<S> for SmtpCommand
+where
+    S: HasRand + HasMaxSize,
+{
+    fn mutate_crossover_insert(&mut self, state: &mut S, other: &Self, stage_idx: i32) -> Result<MutationResult, Error> {
+        match (self.argument_mut(), other.argument()) {
+            (Some(arg), Some(other_arg)) => arg.mutate_crossover_insert(state, other_arg, stage_idx),
+            _ => Ok(MutationResult::Skipped),
+        }
+    }
+}
+
+impl<S> HasCrossoverReplaceMutation<S> for SmtpCommand
+where
+    S: HasRand + HasMaxSize,
+{
+    fn mutate_crossover_replace(&mut self, state: &mut S, other: &Self, stage_idx: i32) -> Result<MutationResult, Error> {
+        match (self.argument_mut(), other.argument()) {
+            (Some(arg), Some(other_arg)) => arg.mutate_crossover_replace(state, other_arg, stage_idx),
+            _ => Ok(MutationResult::Skipped),
+        }
+    }
+}
+
+impl<S> HasSpliceMutation<S> for SmtpCommand
+where
+    S: HasRand + HasMaxSize,
+{
+    fn mutate_splice(&mut self, state: &mut S, other: &Self, stage_idx: i32) -> Result<MutationResult, Error> {
+        match (self.argument_mut(), other.argument()) {
+            (Some(arg), Some(other_arg)) => arg.mutate_splice(state, other_arg, stage_idx),
+            _ => Ok(MutationResult::Skipped),
+        }
+    }
+}
+
+impl<MT, S> HasHavocMutation<MT, S> for SmtpCommand
+where
+    MT: MutatorsTuple<BytesInput, S>,
+    S: HasRand + HasMaxSize,
+{
+    fn mutate_havoc(&mut self, state: &mut S, mutations: &mut MT, mutation: usize, stage_idx: i32) -> Result<MutationResult, Error> {
+        match self.argument_mut() {
+            Some(arg) => arg.mutate_havoc(state, mutations, mutation, stage_idx),
+            None => Ok(MutationResult::Skipped),
+        }
+    }
+}
+
+/// An SMTP session.
+///
+/// It can be loaded from pcap files, where everything the client sent over
+/// the first TCP connection makes up the input.
+#[derive(Hash, Debug, Clone, Serialize, Deserialize)]
+pub struct SmtpInput {
+    packets: Vec<NetworkPacket<SmtpCommand>>,
+}
+
+impl SmtpInput {
+    /// Create a new SmtpInput from a list of commands
+    pub fn new(commands: Vec<SmtpCommand>) -> Self {
+        Self {
+            packets: commands.into_iter().map(NetworkPacket::Data).collect(),
+        }
+    }
+
+    /// Parse all commands and mails in a byte stream
+    pub fn parse(stream: &[u8]) -> Self {
+        let mut commands = Vec::new();
+        let mut rest = stream;
+
+        while !rest.is_empty() {
+            let line_len = rest.iter().position(|c| *c == b'\n').map(|idx| idx + 1).unwrap_or(rest.len());
+            let command = lines(&rest[..line_len]).next().and_then(SmtpCommand::from_line);
+            rest = &rest[line_len..];
+
+            if let Some(command) = command {
+                let is_data = command == SmtpCommand::Data;
+                commands.push(command);
+
+                if is_data {
+                    // The terminator may directly follow the DATA command for empty mails
+                    let (message, len) = if rest.starts_with(&END_OF_DATA[2..]) {
+                        (&rest[..0], END_OF_DATA.len() - 2)
+                    } else {
+                        match rest.windows(END_OF_DATA.len()).position(|w| w == END_OF_DATA) {
+                            Some(idx) => (&rest[..idx], idx + END_OF_DATA.len()),
+                            None => (rest, rest.len()),
+                        }
+                    };
+
+                    commands.push(SmtpCommand::Message(BytesInput::new(dot_unstuff(message))));
+                    rest = &rest[len..];
+                }
+            }
+        }
+
+        Self::new(commands)
+    }
+}
+
+impl Input for SmtpInput {
+    fn generate_name(&self, idx: usize) -> String {
+        format!("smtp-{}", idx)
+    }
+}
+
+impl HasPackets<NetworkPacket<SmtpCommand>> for SmtpInput {
+    fn packets(&self) -> &[NetworkPacket<SmtpCommand>] {
+        &self.packets
+    }
+
+    fn packets_mut(&mut self) -> &mut Vec<NetworkPacket<SmtpCommand>> {
+        &mut self.packets
+    }
+}
+
+impl HasLen for SmtpInput {
+    fn len(&self) -> usize {
+        self.packets.len()
+    }
+}
+
+impl HasPcapRepresentation<SmtpInput> for SmtpInput {
+    fn from_pcap(capture: Capture<Offline>) -> Result<Self, Error> {
+        let stream = first_tcp_connection(&capture_segments(capture)).concat();
+        Ok(Self::parse(&stream))
+    }
+}
+
+/// State inference function for the [`NetworkExecutor`](crate::NetworkExecutor)
+/// that uses the reply code of the server as the state.
+pub fn status_code(response: &[u8]) -> Option<u32> {
+    parse_reply_code(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let input = SmtpInput::parse(b"EHLO client\r\nMAIL FROM:<a@b>\r\nrcpt to:<c@d>\r\nDATA\r\nSubject: x\r\n..hidden\r\n.\r\nDATA\r\n.\r\nQUIT\r\n");
+        let commands: Vec<&SmtpCommand> = input
+            .packets()
+            .iter()
+            .map(|packet| match packet {
+                NetworkPacket::Data(command) => command,
+                _ => unreachable!(),
+            })
+            .collect();
+
+        assert_eq!(commands.len(), 8);
+        assert_eq!(commands[1], &SmtpCommand::MailFrom(BytesInput::new(b"<a@b>".to_vec())));
+        assert_eq!(commands[2].payload(), b"RCPT TO:<c@d>\r\n");
+        assert_eq!(commands[4], &SmtpCommand::Message(BytesInput::new(b"Subject: x\r\n.hidden".to_vec())));
+        assert_eq!(commands[4].payload(), b"Subject: x\r\n..hidden\r\n.\r\n");
+        assert_eq!(commands[6], &SmtpCommand::Message(BytesInput::new(Vec::new())));
+        assert_eq!(commands[7], &SmtpCommand::Quit);
+    }
+}
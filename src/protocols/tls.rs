@@ -0,0 +1,339 @@
+//! Packet and input types for TLS records.
+//!
+//! __Only available with feature__: `protocol_tls`
+//!
+//! This models the unencrypted parts of TLS: the record layer and
+//! the handshake messages sent in the clear. After the handshake
+//! completes the fragments are encrypted and only the record headers are meaningful.
+//!
+//! # Example
+//! ```
+//! let mut executor = NetworkExecutor::new(tuple_list!(state_observer), "127.0.0.1:443".parse().unwrap(), "state", tls::record_type);
+//! load_pcaps::<_, _, _, _, TlsInput, _>(&mut state, &mut fuzzer, &mut executor, &mut mgr, "pcaps").unwrap();
+//! ```
+
+use crate::{
+    executor::{HasPayload, NetworkPacket},
+    input::{capture_segments, first_tcp_connection, HasPackets, HasPcapRepresentation},
+    mutators::{HasCrossoverInsertMutation, HasCrossoverReplaceMutation, HasHavocMutation, HasPostMutationFixup, HasSpliceMutation},
+};
+use libafl::{
+    bolts::{rands::Rand, HasLen},
+    inputs::{BytesInput, HasBytesVec, Input},
+    mutators::{MutationResult, MutatorsTuple},
+    state::{HasMaxSize, HasRand},
+    Error,
+};
+use pcap::{Capture, Offline};
+use serde::{Deserialize, Serialize};
+
+const RECORD_HEADER_LEN: usize = 5;
+const HANDSHAKE_HEADER_LEN: usize = 4;
+/// Records must not be larger than 2^14 bytes plus some overhead
+const MAX_FRAGMENT_LEN: usize = 16384 + 2048;
+
+const CONTENT_ALERT: u8 = 21;
+const CONTENT_HANDSHAKE: u8 = 22;
+
+/// Content types that a havoc mutation chooses from: ChangeCipherSpec, Alert, Handshake, ApplicationData, Heartbeat
+const CONTENT_TYPES: [u8; 5] = [20, CONTENT_ALERT, CONTENT_HANDSHAKE, 23, 24];
+/// Versions that a havoc mutation chooses from: SSL 3.0 up to TLS 1.3
+const VERSIONS: [u16; 5] = [0x0300, 0x0301, 0x0302, 0x0303, 0x0304];
+/// Handshake types that a havoc mutation chooses from
+const HANDSHAKE_TYPES: [u8; 12] = [0, 1, 2, 4, 8, 11, 12, 13, 14, 15, 16, 20];
+
+/// A single TLS record.
+///
+/// The length in the record header is computed when the record is sent.
+/// If the record carries a single handshake message, the mutators
+/// work on the body of the handshake message and update its length too,
+/// unless they decide to mutate the raw fragment.
+#[derive(Hash, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TlsRecordPacket {
+    content_type: u8,
+    version: u16,
+    fragment: BytesInput,
+}
+
+impl TlsRecordPacket {
+    /// Create a new record
+    pub fn new(content_type: u8, version: u16, fragment: &[u8]) -> Self {
+        Self {
+            content_type,
+            version,
+            fragment: BytesInput::new(fragment.to_vec()),
+        }
+    }
+
+    /// Parse a single record. Returns the record and the number of bytes consumed.
+    pub fn parse(stream: &[u8]) -> Option<(Self, usize)> {
+        let header = stream.get(..RECORD_HEADER_LEN)?;
+        let len = u16::from_be_bytes([header[3], header[4]]) as usize;
+        let end = (RECORD_HEADER_LEN + len).min(stream.len());
+
+        Some((Self::new(header[0], u16::from_be_bytes([header[1], header[2]]), &stream[RECORD_HEADER_LEN..end]), end))
+    }
+
+    /// Get the content type
+    pub fn content_type(&self) -> u8 {
+        self.content_type
+    }
+
+    /// Get the protocol version of the record layer
+    pub fn version(&self) -> u16 {
+        self.version
+    }
+
+    /// Get the fragment
+    pub fn fragment(&self) -> &BytesInput {
+        &self.fragment
+    }
+
+    /// Returns the type of the handshake message if the record
+    /// contains exactly one complete handshake message
+    pub fn handshake_type(&self) -> Option<u8> {
+        let fragment = self.fragment.bytes();
+
+        if self.content_type != CONTENT_HANDSHAKE || fragment.len() < HANDSHAKE_HEADER_LEN {
+            return None;
+        }
+
+        let len = u32::from_be_bytes([0, fragment[1], fragment[2], fragment[3]]) as usize;
+
+        if HANDSHAKE_HEADER_LEN + len == fragment.len() {
+            Some(fragment[0])
+        } else {
+            None
+        }
+    }
+
+    /// Apply `mutate` to the body of the handshake message and recompute its length
+    fn mutate_handshake_body<F>(&mut self, mutate: F) -> Result<MutationResult, Error>
+    where
+        F: FnOnce(&mut BytesInput) -> Result<MutationResult, Error>,
+    {
+        let mut body = BytesInput::new(self.fragment.bytes()[HANDSHAKE_HEADER_LEN..].to_vec());
+        let result = mutate(&mut body)?;
+
+        if result == MutationResult::Mutated {
+            let fragment = self.fragment.bytes_mut();
+            let len = body.bytes().len().min(0xFF_FFFF) as u32;
+
+            fragment.truncate(HANDSHAKE_HEADER_LEN);
+            fragment[1..4].copy_from_slice(&len.to_be_bytes()[1..]);
+            fragment.extend_from_slice(&body.bytes()[..len as usize]);
+        }
+
+        Ok(result)
+    }
+
+    fn mutate_header<S: HasRand>(&mut self, state: &mut S) -> MutationResult {
+        let old = (self.content_type, self.version, self.fragment.bytes().first().copied());
+
+        match state.rand_mut().below(3) {
+            0 => self.content_type = *state.rand_mut().choose(&CONTENT_TYPES),
+            1 => self.version = *state.rand_mut().choose(&VERSIONS),
+            _ => {
+                if self.handshake_type().is_some() {
+                    self.fragment.bytes_mut()[0] = *state.rand_mut().choose(&HANDSHAKE_TYPES);
+                }
+            },
+        }
+
+        if (self.content_type, self.version, self.fragment.bytes().first().copied()) == old {
+            MutationResult::Skipped
+        } else {
+            MutationResult::Mutated
+        }
+    }
+}
+
+impl HasPayload for TlsRecordPacket {
+    fn payload(&self) -> Vec<u8> {
+        let fragment = &self.fragment.bytes()[..self.fragment.bytes().len().min(u16::MAX as usize)];
+        let mut ret = Vec::with_capacity(RECORD_HEADER_LEN + fragment.len());
+
+        ret.push(self.content_type);
+        ret.extend_from_slice(&self.version.to_be_bytes());
+        ret.extend_from_slice(&(fragment.len() as u16).to_be_bytes());
+        ret.extend_from_slice(fragment);
+        ret
+    }
+}
+
+impl HasPostMutationFixup for TlsRecordPacket {}
+
+impl<S> HasCrossoverInsertMutation<S> for TlsRecordPacket
+where
+    S: HasRand + HasMaxSize,
+{
+    fn mutate_crossover_insert(&mut self, state: &mut S, other: &Self, stage_idx: i32) -> Result<MutationResult, Error> {
+        if self.handshake_type().is_some() {
+            self.mutate_handshake_body(|body| body.mutate_crossover_insert(state, &other.fragment, stage_idx))
+        } else {
+            self.fragment.mutate_crossover_insert(state, &other.fragment, stage_idx)
+        }
+    }
+}
+
+impl<S> HasCrossoverReplaceMutation<S> for TlsRecordPacket
+where
+    S: HasRand + HasMaxSize,
+{
+    fn mutate_crossover_replace(&mut self, state: &mut S, other: &Self, stage_idx: i32) -> Result<MutationResult, Error> {
+        if self.handshake_type().is_some() {
+            self.mutate_handshake_body(|body| body.mutate_crossover_replace(state, &other.fragment, stage_idx))
+        } else {
+            self.fragment.mutate_crossover_replace(state, &other.fragment, stage_idx)
+        }
+    }
+}
+
+impl<S> HasSpliceMutation<S> for TlsRecordPacket
+where
+    S: HasRand + HasMaxSize,
+{
+    fn mutate_splice(&mut self, state: &mut S, other: &Self, stage_idx: i32) -> Result<MutationResult, Error> {
+        let other_fragment = match other.handshake_type() {
+            Some(_) => BytesInput::new(other.fragment.bytes()[HANDSHAKE_HEADER_LEN..].to_vec()),
+            None => other.fragment.clone(),
+        };
+
+        if self.handshake_type().is_some() {
+            self.mutate_handshake_body(|body| body.mutate_splice(state, &other_fragment, stage_idx))
+        } else {
+            self.fragment.mutate_splice(state, &other_fragment, stage_idx)
+        }
+    }
+}
+
+impl<MT, S> HasHavocMutation<MT, S> for TlsRecordPacket
+where
+    MT: MutatorsTuple<BytesInput, S>,
+    S: HasRand + HasMaxSize,
+{
+    fn mutate_havoc(&mut self, state: &mut S, mutations: &mut MT, mutation: usize, stage_idx: i32) -> Result<MutationResult, Error> {
+        if state.rand_mut().below(8) == 0 {
+            return Ok(self.mutate_header(state));
+        }
+
+        if self.handshake_type().is_some() && state.rand_mut().below(8) != 0 {
+            self.mutate_handshake_body(|body| mutations.get_and_mutate(mutation, state, body, stage_idx))
+        } else {
+            let result = mutations.get_and_mutate(mutation, state, &mut self.fragment, stage_idx)?;
+            self.fragment.bytes_mut().truncate(MAX_FRAGMENT_LEN);
+            Ok(result)
+        }
+    }
+}
+
+/// A sequence of TLS records sent by a client over one connection.
+///
+/// It can be loaded from pcap files, where all records the client sent over
+/// the first TCP connection make up the input.
+#[derive(Hash, Debug, Clone, Serialize, Deserialize)]
+pub struct TlsInput {
+    packets: Vec<NetworkPacket<TlsRecordPacket>>,
+}
+
+impl TlsInput {
+    /// Create a new TlsInput from a list of records
+    pub fn new(records: Vec<TlsRecordPacket>) -> Self {
+        Self {
+            packets: records.into_iter().map(NetworkPacket::Data).collect(),
+        }
+    }
+
+    /// Parse all records in a byte stream
+    pub fn parse(stream: &[u8]) -> Self {
+        let mut records = Vec::new();
+        let mut rest = stream;
+
+        while let Some((record, len)) = TlsRecordPacket::parse(rest) {
+            records.push(record);
+            rest = &rest[len..];
+        }
+
+        Self::new(records)
+    }
+}
+
+impl Input for TlsInput {
+    fn generate_name(&self, idx: usize) -> String {
+        format!("tls-{}", idx)
+    }
+}
+
+impl HasPackets<NetworkPacket<TlsRecordPacket>> for TlsInput {
+    fn packets(&self) -> &[NetworkPacket<TlsRecordPacket>] {
+        &self.packets
+    }
+
+    fn packets_mut(&mut self) -> &mut Vec<NetworkPacket<TlsRecordPacket>> {
+        &mut self.packets
+    }
+}
+
+impl HasLen for TlsInput {
+    fn len(&self) -> usize {
+        self.packets.len()
+    }
+}
+
+impl HasPcapRepresentation<TlsInput> for TlsInput {
+    fn from_pcap(capture: Capture<Offline>) -> Result<Self, Error> {
+        let stream = first_tcp_connection(&capture_segments(capture)).concat();
+        Ok(Self::parse(&stream))
+    }
+}
+
+/// State inference function for the [`NetworkExecutor`](crate::NetworkExecutor)
+/// that uses the content type of the first record in the response as the state,
+/// combined with the handshake type for handshake records
+/// and with the alert description for alerts.
+pub fn record_type(response: &[u8]) -> Option<u32> {
+    let (record, _) = TlsRecordPacket::parse(response)?;
+    let state = (record.content_type as u32) << 8;
+
+    match record.content_type {
+        CONTENT_HANDSHAKE => Some(state | *record.fragment.bytes().first()? as u32),
+        CONTENT_ALERT => Some(state | *record.fragment.bytes().get(1)? as u32),
+        _ => Some(state),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_handshake_length() {
+        // A ClientHello with a 6 byte body followed by a ChangeCipherSpec
+        let stream = b"\x16\x03\x01\x00\x0a\x01\x00\x00\x06\x03\x03abcd\x14\x03\x03\x00\x01\x01";
+        let input = TlsInput::parse(stream);
+        let mut records: Vec<TlsRecordPacket> = input
+            .packets()
+            .iter()
+            .map(|packet| match packet {
+                NetworkPacket::Data(record) => record.clone(),
+                _ => unreachable!(),
+            })
+            .collect();
+
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].handshake_type(), Some(1));
+        assert_eq!(records[1].handshake_type(), None);
+        assert_eq!(records.iter().flat_map(|record| record.payload()).collect::<Vec<u8>>(), stream);
+
+        records[0]
+            .mutate_handshake_body(|body| {
+                body.bytes_mut().extend_from_slice(b"xyz");
+                Ok(MutationResult::Mutated)
+            })
+            .unwrap();
+        assert_eq!(records[0].payload(), b"\x16\x03\x01\x00\x0d\x01\x00\x00\x09\x03\x03abcdxyz");
+        assert_eq!(records[0].handshake_type(), Some(1));
+
+        assert_eq!(record_type(b"\x15\x03\x03\x00\x02\x02\x28"), Some(0x1528));
+    }
+}
@@ -0,0 +1,195 @@
+use libafl::{
+    bolts::tuples::Named,
+    corpus::{Corpus, Testcase},
+    events::EventFirer,
+    executors::ExitKind,
+    feedbacks::Feedback,
+    impl_serdeany,
+    inputs::Input,
+    observers::ObserversTuple,
+    state::{HasClientPerfMonitor, HasCorpus, HasMetadata},
+    Error,
+};
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+
+/// A single mutation in the [`ProvenanceMetadata`] of a testcase
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProvenanceStep {
+    /// Name of the mutator that was applied
+    pub mutator: String,
+    /// Indices of the packets that differ from the input before the mutation.
+    /// Empty if packets were only removed.
+    pub packets: Vec<usize>,
+}
+
+/// The chain of mutations that produced a testcase, starting at the seed.
+///
+/// The [`PacketMutationScheduler`](crate::PacketMutationScheduler) records the mutations with
+/// [`with_provenance()`](crate::PacketMutationScheduler::with_provenance) and the [`ProvenanceFeedback`]
+/// attaches them to the testcases. Print it with [`dump_provenance()`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProvenanceMetadata {
+    /// The mutations in the order they were applied
+    pub steps: Vec<ProvenanceStep>,
+}
+
+impl_serdeany!(ProvenanceMetadata);
+
+impl Display for ProvenanceMetadata {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for (idx, step) in self.steps.iter().enumerate() {
+            writeln!(f, "{:>4}. {} on packets {:?}", idx + 1, step.mutator, step.packets)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The mutation of the input that is currently executed, stored in the metadata of the state
+/// between [`mutate()`](libafl::mutators::Mutator::mutate) and [`post_exec()`](libafl::mutators::Mutator::post_exec)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct PendingProvenance {
+    pub(crate) step: ProvenanceStep,
+}
+
+impl_serdeany!(PendingProvenance);
+
+/// Returns the indices of the packets that changed between two inputs, given the hashes of their packets.
+/// Packets that are equal at the start or the end of both inputs are not counted.
+pub(crate) fn changed_packets(before: &[u64], after: &[u64]) -> Vec<usize> {
+    let prefix = before.iter().zip(after).take_while(|(a, b)| a == b).count();
+    let suffix = before[prefix..].iter().rev().zip(after[prefix..].iter().rev()).take_while(|(a, b)| a == b).count();
+    (prefix..after.len() - suffix).collect()
+}
+
+/// Attaches the [`ProvenanceMetadata`] to every new testcase: the chain of its parent,
+/// i.e. the corpus entry that was mutated, followed by the mutation that produced the testcase.
+///
+/// Requires a [`PacketMutationScheduler`](crate::PacketMutationScheduler) with
+/// [`with_provenance()`](crate::PacketMutationScheduler::with_provenance).
+/// Never considers an input interesting, so combine it with the other feedbacks and objectives.
+///
+/// # Example
+/// ```
+/// let mutator = PacketMutationScheduler::new(mutations).with_provenance::<PacketType>();
+/// let mut feedback = feedback_or!(StateFeedback::new(&state_observer), ProvenanceFeedback::new());
+/// let mut objective = feedback_or!(CrashFeedback::new(), ProvenanceFeedback::new());
+///
+/// // later
+/// println!("{}", dump_provenance(state.solutions(), 0)?);
+/// ```
+#[derive(Debug, Default)]
+pub struct ProvenanceFeedback;
+
+impl ProvenanceFeedback {
+    /// Create a new ProvenanceFeedback
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl Named for ProvenanceFeedback {
+    fn name(&self) -> &str {
+        "ProvenanceFeedback"
+    }
+}
+
+impl<I, S> Feedback<I, S> for ProvenanceFeedback
+where
+    I: Input,
+    S: HasClientPerfMonitor + HasCorpus<I> + HasMetadata,
+{
+    fn is_interesting<EM, OT>(&mut self, _state: &mut S, _mgr: &mut EM, _input: &I, _observers: &OT, _exit_kind: &ExitKind) -> Result<bool, Error>
+    where
+        EM: EventFirer<I>,
+        OT: ObserversTuple<I, S>,
+    {
+        Ok(false)
+    }
+
+    fn append_metadata(&mut self, state: &mut S, testcase: &mut Testcase<I>) -> Result<(), Error> {
+        let step = match state.metadata().get::<PendingProvenance>().map(|pending| pending.step.clone()) {
+            Some(step) => step,
+            None => return Ok(()),
+        };
+
+        let mut metadata = match *state.corpus().current() {
+            Some(parent) => state.corpus().get(parent)?.borrow().metadata().get::<ProvenanceMetadata>().cloned().unwrap_or_default(),
+            None => ProvenanceMetadata::default(),
+        };
+
+        metadata.steps.push(step);
+        testcase.add_metadata(metadata);
+        Ok(())
+    }
+}
+
+/// Returns the chain of mutations that produced the entry `idx` of `corpus` in a human-readable format,
+/// one mutation per line. Works for the corpus as well as the solutions.
+pub fn dump_provenance<C, I>(corpus: &C, idx: usize) -> Result<String, Error>
+where
+    C: Corpus<I>,
+    I: Input,
+{
+    let testcase = corpus.get(idx)?.borrow();
+
+    match testcase.metadata().get::<ProvenanceMetadata>() {
+        Some(metadata) if !metadata.steps.is_empty() => Ok(metadata.to_string()),
+        _ => Ok("no mutations recorded\n".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{test_utils::TestInput, PacketDeleteMutator, PacketMutationScheduler};
+    use libafl::{
+        bolts::{rands::StdRand, tuples::tuple_list},
+        corpus::InMemoryCorpus,
+        inputs::BytesInput,
+        mutators::Mutator,
+        state::StdState,
+    };
+
+    #[test]
+    fn test_provenance() {
+        let mut state = StdState::new(StdRand::with_seed(0), InMemoryCorpus::<TestInput>::new(), InMemoryCorpus::<TestInput>::new(), &mut (), &mut ()).unwrap();
+        let mut scheduler = PacketMutationScheduler::new(tuple_list!(PacketDeleteMutator::new(1))).with_provenance::<BytesInput>();
+        let mut feedback = ProvenanceFeedback::new();
+        let mut input = TestInput {
+            packets: vec![BytesInput::new(b"a".to_vec()), BytesInput::new(b"b".to_vec())],
+        };
+
+        let mut parent = Testcase::new(input.clone());
+        parent.add_metadata(ProvenanceMetadata {
+            steps: vec![ProvenanceStep {
+                mutator: "PacketHavocMutator".to_string(),
+                packets: vec![1],
+            }],
+        });
+        state.corpus_mut().add(parent).unwrap();
+        *state.corpus_mut().current_mut() = Some(0);
+
+        scheduler.mutate(&mut state, &mut input, 0).unwrap();
+        let mut testcase = Testcase::new(input);
+        feedback.append_metadata(&mut state, &mut testcase).unwrap();
+        state.corpus_mut().add(testcase).unwrap();
+
+        let dump = dump_provenance(state.corpus(), 1).unwrap();
+        assert!(dump.starts_with("   1. PacketHavocMutator on packets [1]\n   2. PacketDeleteMutator on packets []\n"));
+
+        // Nothing is pending after the execution
+        scheduler.post_exec(&mut state, 0, None).unwrap();
+        assert!(!state.has_metadata::<PendingProvenance>());
+    }
+
+    #[test]
+    fn test_changed_packets() {
+        // Mutation, insertion, deletion
+        assert_eq!(changed_packets(&[1, 2, 3], &[1, 4, 3]), vec![1]);
+        assert_eq!(changed_packets(&[1, 2, 3], &[1, 2, 2, 3]), vec![2]);
+        assert_eq!(changed_packets(&[1, 2, 3], &[1, 3]), Vec::<usize>::new());
+        assert_eq!(changed_packets(&[1, 2, 3], &[3, 2, 1]), vec![0, 1, 2]);
+    }
+}
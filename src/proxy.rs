@@ -0,0 +1,250 @@
+use crate::input::HasRawRepresentation;
+use libafl::{inputs::Input, Error};
+use std::io::{Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::sync::{
+    atomic::{AtomicBool, AtomicUsize, Ordering},
+    Arc, Mutex,
+};
+use std::thread::JoinHandle;
+
+/// A man-in-the-middle proxy that records the sessions of a real client with the target
+/// and writes them as inputs into a corpus directory.
+///
+/// The proxy runs in a background thread. It forwards all traffic between the client and the target
+/// unchanged and collects everything the client sends. When the client closes its side of the
+/// connection, the collected data is split into packets by the `splitter` and turned into an input via
+/// [`HasRawRepresentation::from_raw_packets()`](crate::HasRawRepresentation::from_raw_packets), the same
+/// way [`load_raw_seeds`](crate::load_raw_seeds) does it.
+/// The input is stored with [`Input::to_file()`](libafl::inputs::Input::to_file) so that LibAFLs
+/// [`load_initial_inputs()`](libafl::state::StdState::load_initial_inputs) can load the corpus directory.
+///
+/// This is an alternative seeding path when capturing traffic into pcap files is inconvenient,
+/// e.g. because the client can only be pointed at a different port or because of missing privileges.
+///
+/// # Example
+/// ```
+/// // Point the client at port 2200 instead of 21.
+/// // MyInput implements HasRawRepresentation
+/// let proxy = RecordingProxy::spawn::<MyInput, _, _, _>("127.0.0.1:2200", "127.0.0.1:21".parse().unwrap(), "corpus", delimiter_splitter(b"\r\n")).unwrap();
+///
+/// // ... use the client ...
+///
+/// println!("Recorded {} sessions", proxy.sessions());
+/// ```
+#[derive(Debug)]
+pub struct RecordingProxy {
+    addr: SocketAddr,
+    running: Arc<AtomicBool>,
+    sessions: Arc<AtomicUsize>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl RecordingProxy {
+    /// Start the proxy on `listen`. Every client that connects gets its own connection to `target`
+    /// and its session gets recorded into `corpus_dir`, which is created if it doesn't exist.
+    pub fn spawn<I, A, P, F>(listen: A, target: SocketAddr, corpus_dir: P, splitter: F) -> Result<Self, Error>
+    where
+        I: Input + HasRawRepresentation<I>,
+        A: ToSocketAddrs,
+        P: Into<PathBuf>,
+        F: FnMut(&[u8]) -> Vec<Vec<u8>> + Send + 'static,
+    {
+        let corpus_dir = corpus_dir.into();
+        std::fs::create_dir_all(&corpus_dir)?;
+
+        let listener = TcpListener::bind(listen)?;
+        let addr = listener.local_addr()?;
+        let running = Arc::new(AtomicBool::new(true));
+        let sessions = Arc::new(AtomicUsize::new(0));
+        let splitter = Arc::new(Mutex::new(splitter));
+        let thread_running = running.clone();
+        let thread_sessions = sessions.clone();
+
+        let thread = std::thread::spawn(move || {
+            for client in listener.incoming() {
+                if !thread_running.load(Ordering::Relaxed) {
+                    break;
+                }
+
+                if let Ok(client) = client {
+                    let corpus_dir = corpus_dir.clone();
+                    let splitter = splitter.clone();
+                    let sessions = thread_sessions.clone();
+
+                    std::thread::spawn(move || {
+                        let result = record_session(client, target).and_then(|data| {
+                            // Holding the lock while saving also keeps concurrent sessions from picking the same name
+                            let mut splitter = splitter.lock().unwrap();
+                            save_session::<I>(&corpus_dir, splitter(&data), &sessions)
+                        });
+
+                        if let Err(e) = result {
+                            println!("[butterfly] Failed to record proxy session: {}", e);
+                        }
+                    });
+                }
+            }
+        });
+
+        println!("[butterfly] Recording proxy listening on {}, forwarding to {}", addr, target);
+
+        Ok(Self {
+            addr,
+            running,
+            sessions,
+            thread: Some(thread),
+        })
+    }
+
+    /// The address the proxy is listening on
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// The number of sessions that have been written into the corpus directory so far
+    pub fn sessions(&self) -> usize {
+        self.sessions.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for RecordingProxy {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::Relaxed);
+
+        // Wake up the accept loop so that it notices the shutdown
+        let _ = TcpStream::connect(self.addr);
+
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Forward traffic between `client` and `target` and return everything the client sent
+fn record_session(mut client: TcpStream, target: SocketAddr) -> Result<Vec<u8>, Error> {
+    let mut server = TcpStream::connect(target)?;
+    let mut server_reader = server.try_clone()?;
+    let mut client_writer = client.try_clone()?;
+
+    // The responses of the target are only passed through
+    std::thread::spawn(move || {
+        let _ = std::io::copy(&mut server_reader, &mut client_writer);
+        let _ = client_writer.shutdown(Shutdown::Write);
+    });
+
+    let mut data = Vec::new();
+    let mut buf = [0; 4096];
+
+    loop {
+        let len = client.read(&mut buf)?;
+
+        if len == 0 {
+            break;
+        }
+
+        data.extend_from_slice(&buf[..len]);
+        server.write_all(&buf[..len])?;
+    }
+
+    let _ = server.shutdown(Shutdown::Write);
+    Ok(data)
+}
+
+/// Turn the packets of a session into an input and write it into `corpus_dir` under an unused name
+fn save_session<I>(corpus_dir: &Path, packets: Vec<Vec<u8>>, sessions: &AtomicUsize) -> Result<(), Error>
+where
+    I: Input + HasRawRepresentation<I>,
+{
+    if packets.is_empty() {
+        return Ok(());
+    }
+
+    let input = I::from_raw_packets(packets)?;
+    let mut idx = sessions.load(Ordering::Relaxed);
+    let mut path = corpus_dir.join(input.generate_name(idx));
+
+    while path.exists() {
+        idx += 1;
+        path = corpus_dir.join(input.generate_name(idx));
+    }
+
+    input.to_file(&path)?;
+    sessions.fetch_add(1, Ordering::Relaxed);
+    println!("[butterfly] Recorded proxy session into {}", path.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{delimiter_splitter, input::HasPackets};
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Hash, Debug, Clone, Serialize, Deserialize)]
+    struct LineInput {
+        packets: Vec<Vec<u8>>,
+    }
+
+    impl Input for LineInput {
+        fn generate_name(&self, idx: usize) -> String {
+            format!("session-{}", idx)
+        }
+    }
+
+    impl HasPackets<Vec<u8>> for LineInput {
+        fn packets(&self) -> &[Vec<u8>] {
+            &self.packets
+        }
+
+        fn packets_mut(&mut self) -> &mut Vec<Vec<u8>> {
+            &mut self.packets
+        }
+    }
+
+    impl HasRawRepresentation<LineInput> for LineInput {
+        fn from_raw_packets(packets: Vec<Vec<u8>>) -> Result<LineInput, Error> {
+            Ok(LineInput {
+                packets,
+            })
+        }
+    }
+
+    #[test]
+    fn test_recording_proxy() {
+        let dir = std::env::temp_dir().join(format!("butterfly-proxy-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+
+        // A target that echoes everything
+        let target = TcpListener::bind("127.0.0.1:0").unwrap();
+        let target_addr = target.local_addr().unwrap();
+        std::thread::spawn(move || {
+            let (mut stream, _) = target.accept().unwrap();
+            let mut writer = stream.try_clone().unwrap();
+            let _ = std::io::copy(&mut stream, &mut writer);
+        });
+
+        let proxy = RecordingProxy::spawn::<LineInput, _, _, _>("127.0.0.1:0", target_addr, &dir, delimiter_splitter(b"\r\n")).unwrap();
+        let mut client = TcpStream::connect(proxy.addr()).unwrap();
+        client.write_all(b"USER a\r\nPASS b\r\n").unwrap();
+        client.shutdown(Shutdown::Write).unwrap();
+
+        let mut echo = Vec::new();
+        client.read_to_end(&mut echo).unwrap();
+        assert_eq!(echo, b"USER a\r\nPASS b\r\n");
+
+        for _ in 0..100 {
+            if proxy.sessions() > 0 {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+
+        let input = LineInput::from_file(dir.join("session-0")).unwrap();
+        assert_eq!(input.packets(), &[b"USER a\r\n".to_vec(), b"PASS b\r\n".to_vec()]);
+
+        drop(proxy);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,341 @@
+use crate::{observer::StateObserver, validate::execute_once};
+use libafl::{
+    executors::{Executor, ExitKind, HasObservers},
+    inputs::Input,
+    observers::ObserversTuple,
+    Error,
+};
+use serde::{Deserialize, Serialize};
+use std::cmp::Eq;
+use std::collections::HashSet;
+use std::fmt::{Debug, Display, Formatter};
+use std::hash::Hash;
+
+/// How a target processed a single input. Part of a [`BehaviorChange`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InputTrace<PS>
+where
+    PS: Clone + Debug + Eq + Hash,
+{
+    /// How the execution ended
+    pub exit_kind: ExitKind,
+    /// The states the target went through
+    pub states: Vec<PS>,
+}
+
+/// An input that the two targets processed differently. Part of a [`RegressionReport`].
+#[derive(Clone, Debug)]
+pub struct BehaviorChange<I, PS>
+where
+    I: Input,
+    PS: Clone + Debug + Eq + Hash,
+{
+    /// Index of the input in the corpus that was replayed
+    pub index: usize,
+    /// The input itself
+    pub input: I,
+    /// How the baseline target processed the input
+    pub baseline: InputTrace<PS>,
+    /// How the candidate target processed the input
+    pub candidate: InputTrace<PS>,
+}
+
+/// The result of [`compare_targets()`].
+#[derive(Clone, Debug)]
+pub struct RegressionReport<I, PS>
+where
+    I: Input,
+    PS: Clone + Debug + Eq + Hash,
+{
+    /// Number of inputs that were replayed
+    pub inputs: usize,
+    /// Inputs that were processed differently by the two targets
+    pub changes: Vec<BehaviorChange<I, PS>>,
+    /// Indices of inputs that the baseline did not process the same way twice.
+    /// They are not reported as changes and their states are not compared.
+    pub flaky: Vec<usize>,
+    /// States that only the baseline went through
+    pub removed_states: Vec<PS>,
+    /// States that only the candidate went through
+    pub added_states: Vec<PS>,
+    /// Transitions that only the baseline made
+    pub removed_transitions: Vec<(PS, PS)>,
+    /// Transitions that only the candidate made
+    pub added_transitions: Vec<(PS, PS)>,
+}
+
+impl<I, PS> RegressionReport<I, PS>
+where
+    I: Input,
+    PS: Clone + Debug + Eq + Hash,
+{
+    /// Returns whether both targets behaved the same on all inputs
+    pub fn is_unchanged(&self) -> bool {
+        self.changes.is_empty() && self.removed_states.is_empty() && self.added_states.is_empty() && self.removed_transitions.is_empty() && self.added_transitions.is_empty()
+    }
+}
+
+impl<I, PS> Display for RegressionReport<I, PS>
+where
+    I: Input,
+    PS: Clone + Debug + Eq + Hash,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
+        writeln!(f, "[butterfly] Regression report:")?;
+        writeln!(f, "  inputs:              {}", self.inputs)?;
+        writeln!(f, "  changed:             {}", self.changes.len())?;
+        writeln!(f, "  flaky:               {}", self.flaky.len())?;
+        writeln!(f, "  removed states:      {:?}", self.removed_states)?;
+        writeln!(f, "  added states:        {:?}", self.added_states)?;
+        writeln!(f, "  removed transitions: {:?}", self.removed_transitions)?;
+        write!(f, "  added transitions:   {:?}", self.added_transitions)?;
+
+        for change in &self.changes {
+            write!(f, "\n  CHANGED input #{}: {:?} {:?} -> {:?} {:?}", change.index, change.baseline.exit_kind, change.baseline.states, change.candidate.exit_kind, change.candidate.states)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The states and transitions of a set of traces in the order they first appeared
+struct TraceGraph<PS> {
+    states: Vec<PS>,
+    transitions: Vec<(PS, PS)>,
+}
+
+impl<PS> TraceGraph<PS>
+where
+    PS: Clone + Debug + Eq + Hash,
+{
+    fn new(traces: &[InputTrace<PS>]) -> Self {
+        let mut states = Vec::new();
+        let mut transitions = Vec::new();
+        let mut seen_states = HashSet::new();
+        let mut seen_transitions = HashSet::new();
+
+        for trace in traces {
+            for state in &trace.states {
+                if seen_states.insert(state) {
+                    states.push(state.clone());
+                }
+            }
+
+            for pair in trace.states.windows(2) {
+                if seen_transitions.insert((&pair[0], &pair[1])) {
+                    transitions.push((pair[0].clone(), pair[1].clone()));
+                }
+            }
+        }
+
+        Self {
+            states,
+            transitions,
+        }
+    }
+
+    /// Returns the states and transitions of `self` that are not in `other`
+    fn difference(&self, other: &Self) -> (Vec<PS>, Vec<(PS, PS)>) {
+        let states = self.states.iter().filter(|state| !other.states.contains(state)).cloned().collect();
+        let transitions = self.transitions.iter().filter(|transition| !other.transitions.contains(transition)).cloned().collect();
+        (states, transitions)
+    }
+}
+
+fn trace<E, EM, I, S, Z, OT, PS>(fuzzer: &mut Z, state: &mut S, executor: &mut E, mgr: &mut EM, input: &I, observer_name: &str) -> Result<InputTrace<PS>, Error>
+where
+    E: Executor<EM, I, S, Z> + HasObservers<I, OT, S>,
+    OT: ObserversTuple<I, S>,
+    I: Input,
+    PS: Clone + Debug + Eq + Hash + Serialize + for<'a> Deserialize<'a>,
+{
+    let exit_kind = execute_once(fuzzer, state, executor, mgr, input)?;
+    let observer = executor.observers().match_name::<StateObserver<PS>>(observer_name).ok_or_else(|| Error::key_not_found(format!("No StateObserver named \"{}\" with the given state type", observer_name)))?;
+
+    Ok(InputTrace {
+        exit_kind,
+        states: observer.path_states(),
+    })
+}
+
+/// Regression testing of protocol behavior: replays a corpus against two versions of a target and
+/// reports every input that the versions processed differently.
+///
+/// Every input is executed twice on the `baseline` and once on the `candidate`, without the fuzzer or
+/// its feedbacks being involved. An input counts as changed if the candidate ended with a different
+/// exit kind or went through different states than the baseline. Inputs whose two baseline runs differ
+/// are reported as flaky instead.
+/// Additionally, the states and transitions of the traces of all inputs that are not flaky are compared to tell
+/// which parts of the state-graph the candidate lost or gained.
+///
+/// Both executors need a [`StateObserver`] named `observer_name`.
+/// The report gets printed to stdout and returned.
+///
+/// # Example
+/// ```
+/// let mut baseline = NetworkExecutor::new(tuple_list!(StateObserver::<u32>::new("state")), "127.0.0.1:2121".parse().unwrap(), "state", ftp::status_code);
+/// let mut candidate = NetworkExecutor::new(tuple_list!(StateObserver::<u32>::new("state")), "127.0.0.1:2122".parse().unwrap(), "state", ftp::status_code);
+/// let inputs: Vec<FtpInput> = PcapLoader::new().find("corpus").unwrap().iter().map(|path| FtpInput::from_pcap(Capture::from_file(path).unwrap()).unwrap()).collect();
+///
+/// let report = compare_targets::<_, _, _, _, _, _, _, _, u32>(&mut fuzzer, &mut state, &mut baseline, &mut candidate, &mut mgr, &inputs, "state").unwrap();
+///
+/// for change in &report.changes {
+///     println!("input #{} changed from {:?} to {:?}", change.index, change.baseline.states, change.candidate.states);
+/// }
+/// ```
+pub fn compare_targets<E1, E2, EM, I, S, Z, OT1, OT2, PS>(fuzzer: &mut Z, state: &mut S, baseline: &mut E1, candidate: &mut E2, mgr: &mut EM, inputs: &[I], observer_name: &str) -> Result<RegressionReport<I, PS>, Error>
+where
+    E1: Executor<EM, I, S, Z> + HasObservers<I, OT1, S>,
+    E2: Executor<EM, I, S, Z> + HasObservers<I, OT2, S>,
+    OT1: ObserversTuple<I, S>,
+    OT2: ObserversTuple<I, S>,
+    I: Input,
+    PS: Clone + Debug + Eq + Hash + Serialize + for<'a> Deserialize<'a>,
+{
+    let mut baseline_traces = Vec::with_capacity(inputs.len());
+    let mut candidate_traces = Vec::with_capacity(inputs.len());
+    let mut changes = Vec::new();
+    let mut flaky = Vec::new();
+
+    for (index, input) in inputs.iter().enumerate() {
+        let first = trace(fuzzer, state, baseline, mgr, input, observer_name)?;
+        let second = trace(fuzzer, state, baseline, mgr, input, observer_name)?;
+        let after = trace(fuzzer, state, candidate, mgr, input, observer_name)?;
+
+        if first != second {
+            flaky.push(index);
+            continue;
+        }
+
+        if first != after {
+            changes.push(BehaviorChange {
+                index,
+                input: input.clone(),
+                baseline: first.clone(),
+                candidate: after.clone(),
+            });
+        }
+
+        baseline_traces.push(first);
+        candidate_traces.push(after);
+    }
+
+    let baseline_graph = TraceGraph::new(&baseline_traces);
+    let candidate_graph = TraceGraph::new(&candidate_traces);
+    let (removed_states, removed_transitions) = baseline_graph.difference(&candidate_graph);
+    let (added_states, added_transitions) = candidate_graph.difference(&baseline_graph);
+
+    let report = RegressionReport {
+        inputs: inputs.len(),
+        changes,
+        flaky,
+        removed_states,
+        added_states,
+        removed_transitions,
+        added_transitions,
+    };
+
+    println!("{}", report);
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libafl::inputs::BytesInput;
+
+    /// Records the next states of a script on every execution
+    #[derive(Debug)]
+    struct ScriptedExecutor {
+        observers: (StateObserver<u32>, ()),
+        script: Vec<(ExitKind, Vec<u32>)>,
+    }
+
+    impl ScriptedExecutor {
+        fn new(mut script: Vec<(ExitKind, Vec<u32>)>) -> Self {
+            script.reverse();
+
+            Self {
+                observers: (StateObserver::new("state"), ()),
+                script,
+            }
+        }
+    }
+
+    impl<EM, S, Z> Executor<EM, BytesInput, S, Z> for ScriptedExecutor {
+        fn run_target(&mut self, _fuzzer: &mut Z, _state: &mut S, _mgr: &mut EM, _input: &BytesInput) -> Result<ExitKind, Error> {
+            let (exit_kind, states) = self.script.pop().unwrap();
+            states.iter().for_each(|state| self.observers.0.record(state));
+            Ok(exit_kind)
+        }
+    }
+
+    impl<S> HasObservers<BytesInput, (StateObserver<u32>, ()), S> for ScriptedExecutor {
+        fn observers(&self) -> &(StateObserver<u32>, ()) {
+            &self.observers
+        }
+
+        fn observers_mut(&mut self) -> &mut (StateObserver<u32>, ()) {
+            &mut self.observers
+        }
+    }
+
+    fn ok(states: &[u32]) -> (ExitKind, Vec<u32>) {
+        (ExitKind::Ok, states.to_vec())
+    }
+
+    fn traces(traces: &[&[u32]]) -> TraceGraph<u32> {
+        let traces: Vec<_> = traces
+            .iter()
+            .map(|states| InputTrace {
+                exit_kind: ExitKind::Ok,
+                states: states.to_vec(),
+            })
+            .collect();
+        TraceGraph::new(&traces)
+    }
+
+    #[test]
+    fn test_difference() {
+        let baseline = traces(&[&[1, 2, 3], &[3, 1]]);
+        let candidate = traces(&[&[1, 3]]);
+
+        assert_eq!(baseline.difference(&candidate), (vec![2], vec![(1, 2), (2, 3), (3, 1)]));
+        assert_eq!(candidate.difference(&baseline), (vec![], vec![(1, 3)]));
+        assert_eq!(baseline.difference(&baseline), (vec![], vec![]));
+    }
+
+    #[test]
+    fn test_compare_targets() {
+        let inputs = vec![BytesInput::new(vec![0]); 3];
+        let mut baseline = ScriptedExecutor::new(vec![ok(&[1, 2]), ok(&[1, 2]), ok(&[1, 3]), ok(&[1, 4]), ok(&[1, 2, 6]), ok(&[1, 2, 6])]);
+        let mut candidate = ScriptedExecutor::new(vec![ok(&[1, 2]), ok(&[1, 5]), (ExitKind::Crash, vec![1, 2])]);
+
+        let report = compare_targets::<_, _, _, _, _, _, _, _, u32>(&mut (), &mut (), &mut baseline, &mut candidate, &mut (), &inputs, "state").unwrap();
+        assert!(!report.is_unchanged());
+        assert_eq!(report.flaky, [1]);
+        assert_eq!(report.changes.len(), 1);
+        assert_eq!(report.changes[0].index, 2);
+        assert_eq!(report.changes[0].candidate.exit_kind, ExitKind::Crash);
+
+        // The states of the flaky input are neither removed nor added
+        assert_eq!(report.removed_states, [6]);
+        assert_eq!(report.removed_transitions, [(2, 6)]);
+        assert!(report.added_states.is_empty());
+        assert!(report.added_transitions.is_empty());
+
+        // A flaky input alone doesn't make a change
+        let mut baseline = ScriptedExecutor::new(vec![ok(&[1, 2]), ok(&[1, 2]), ok(&[1, 3]), ok(&[1, 4])]);
+        let mut candidate = ScriptedExecutor::new(vec![ok(&[1, 2]), ok(&[1, 5])]);
+
+        let report = compare_targets::<_, _, _, _, _, _, _, _, u32>(&mut (), &mut (), &mut baseline, &mut candidate, &mut (), &inputs[..2], "state").unwrap();
+        assert!(report.is_unchanged());
+        assert_eq!(report.flaky, [1]);
+
+        // The observer must exist
+        let mut baseline = ScriptedExecutor::new(vec![ok(&[1])]);
+        let mut candidate = ScriptedExecutor::new(vec![ok(&[1])]);
+        assert!(compare_targets::<_, _, _, _, _, _, _, _, u32>(&mut (), &mut (), &mut baseline, &mut candidate, &mut (), &inputs[..1], "missing").is_err());
+    }
+}
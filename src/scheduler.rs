@@ -1,18 +1,177 @@
+use crate::{
+    config::{ComponentConfig, ConfigTuple, HasConfig},
+    executor::ValidityMetadata,
+    input::HasPackets,
+    mutators::{HasPostMutationFixup, PacketBounds},
+    phase::{Phase, PhaseMetadata},
+    provenance::{changed_packets, PendingProvenance, ProvenanceStep},
+};
+use ahash::AHasher;
 use libafl::{
-    bolts::rands::Rand,
+    bolts::{
+        rands::{Rand, StdRand},
+        tuples::NamedTuple,
+    },
+    impl_serdeany,
     inputs::Input,
     mutators::{ComposedByMutations, MutationResult, Mutator, MutatorsTuple, ScheduledMutator},
-    state::HasRand,
+    state::{HasMetadata, HasRand},
     Error,
 };
+use serde::{Deserialize, Serialize};
+use std::hash::{Hash, Hasher};
 use std::marker::PhantomData;
 
+/// Number of executions of a mutator before it can be penalized
+const MIN_PENALTY_EXECUTIONS: u64 = 100;
+/// A penalized mutator is only picked in 1 out of this many draws
+const PENALTY_ODDS: u64 = 8;
+
+/// Number of executions of every mutator before the adaptive selection kicks in
+const ADAPTIVE_WARMUP: u64 = 50;
+/// Share of the probability mass that the adaptive selection distributes uniformly,
+/// so that unsuccessful mutators still get a chance to recover
+const ADAPTIVE_EXPLORATION: f64 = 0.1;
+/// Factor by which older executions fade out of the success rate of a mutator
+const ADAPTIVE_DECAY: f64 = 0.999;
+
+/// What the scheduler needs to record the [`ProvenanceMetadata`](crate::ProvenanceMetadata) of its outputs
+struct Provenance<I> {
+    names: Vec<String>,
+    hashes: fn(&I) -> Vec<u64>,
+}
+
+/// Recent successes of a mutator for the adaptive selection.
+/// The recent counters decay with every execution so that they reflect the recent past.
+#[derive(Clone, Copy, Debug, Default)]
+struct Successes {
+    executions: u64,
+    recent_executions: f64,
+    recent_successes: f64,
+}
+
+/// How often the outputs of a mutator were rejected by the target
+#[derive(Clone, Copy, Debug, Default)]
+struct Rejections {
+    executions: u64,
+    rejected: u64,
+}
+
+/// Weights of the mutators of a [`PacketMutationScheduler`], stored in the metadata of the state.
+///
+/// The `i`-th weight belongs to the `i`-th mutator in the list of the scheduler and mutators are
+/// picked with a probability proportional to their weight. Weights are ignored if there are
+/// not exactly as many as there are mutators or if they are all zero.
+/// They can be changed at runtime, e.g. by a [`ControlStage`](crate::ControlStage).
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MutatorWeights {
+    /// Non-negative weight of each mutator
+    pub weights: Vec<f64>,
+}
+
+impl_serdeany!(MutatorWeights);
+
+impl MutatorWeights {
+    /// Create new MutatorWeights. Negative and non-finite weights are treated as 0.
+    pub fn new(weights: Vec<f64>) -> Self {
+        Self {
+            weights: weights.into_iter().map(|weight| if weight.is_finite() && weight > 0.0 { weight } else { 0.0 }).collect(),
+        }
+    }
+
+    /// Pick a mutator out of `len` mutators with probability proportional to its weight.
+    /// Returns `None` if the weights are not applicable.
+    fn pick<R: Rand>(&self, rand: &mut R, len: usize) -> Option<usize> {
+        let total: f64 = self.weights.iter().sum();
+
+        if self.weights.len() != len || total <= 0.0 {
+            return None;
+        }
+
+        let mut point = rand.next() as f64 / u64::MAX as f64 * total;
+
+        for (mutation, weight) in self.weights.iter().enumerate() {
+            if point < *weight {
+                return Some(mutation);
+            }
+            point -= weight;
+        }
+
+        self.weights.iter().rposition(|weight| *weight > 0.0)
+    }
+}
+
+/// How often the outputs of a mutator were executed and how often they were added to the corpus
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MutatorStat {
+    /// Name of the mutator
+    pub name: String,
+    /// Number of executions of its outputs
+    pub executions: u64,
+    /// Number of executions that were interesting, e.g. because [`StateFeedback`](crate::StateFeedback)
+    /// discovered new states or transitions
+    pub interesting: u64,
+}
+
+/// Effectiveness of the mutators of all [`PacketMutationScheduler`]s that were created
+/// with [`with_mutator_stats()`](PacketMutationScheduler::with_mutator_stats), stored in the metadata of the state.
+///
+/// Mutators with the same name share their statistics.
+/// The [`MutatorStatsFeedback`](crate::MutatorStatsFeedback) reports them to the monitor.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MutatorStatsMetadata {
+    /// Statistics of every mutator in the order they were first executed
+    pub stats: Vec<MutatorStat>,
+}
+
+impl_serdeany!(MutatorStatsMetadata);
+
+impl MutatorStatsMetadata {
+    /// Count an execution of an output of the mutator `name`
+    pub fn count(&mut self, name: &str, interesting: bool) {
+        let idx = match self.stats.iter().position(|stat| stat.name == name) {
+            Some(idx) => idx,
+            None => {
+                self.stats.push(MutatorStat {
+                    name: name.to_string(),
+                    ..MutatorStat::default()
+                });
+                self.stats.len() - 1
+            },
+        };
+
+        self.stats[idx].executions += 1;
+        self.stats[idx].interesting += interesting as u64;
+    }
+}
+
 /// A mutation scheduler for butterflys mutators.
 ///
 /// It schedules them in such a way that only one mutator in the list
 /// gets executed per run because the mutators may implement their own scheduling,
 /// like the [`PacketHavocMutator`](crate::PacketHavocMutator), which stacks
 /// havoc mutations on its own.
+///
+/// If the executor judges the packets with a [`HasValidityOracle`](crate::HasValidityOracle),
+/// the scheduler keeps track of how often the outputs of each mutator got rejected
+/// and with [`with_validity_penalty()`](PacketMutationScheduler::with_validity_penalty)
+/// it picks mutators less often whose outputs are overwhelmingly rejected.
+///
+/// A fixup registered with [`with_fixup()`](PacketMutationScheduler::with_fixup) or
+/// [`with_packet_fixups()`](PacketMutationScheduler::with_packet_fixups) is applied to every mutated input,
+/// no matter which mutator produced it.
+///
+/// With [`with_mutator_stats()`](PacketMutationScheduler::with_mutator_stats) it keeps count of how many outputs
+/// of each mutator were interesting in the [`MutatorStatsMetadata`] of the state.
+///
+/// Mutators are picked uniformly unless weights were given with [`with_weights()`](PacketMutationScheduler::with_weights),
+/// the adaptive selection of [`with_adaptive_selection()`](PacketMutationScheduler::with_adaptive_selection) is enabled
+/// or the state contains [`MutatorWeights`]. The latter take precedence over the other two.
+/// Weights given with [`with_phase_weights()`](PacketMutationScheduler::with_phase_weights) replace the
+/// weights of [`with_weights()`](PacketMutationScheduler::with_weights) while a [`PhaseStage`](crate::PhaseStage) is active.
+///
+/// The number of packets that the structural mutators keep inputs within can be declared once for all of them
+/// with [`with_bounds()`](PacketMutationScheduler::with_bounds).
 pub struct PacketMutationScheduler<I, MT, S>
 where
     I: Input,
@@ -20,6 +179,17 @@ where
     S: HasRand,
 {
     mutations: MT,
+    max_rejection: Option<f64>,
+    rejections: Vec<Rejections>,
+    last_mutation: Option<usize>,
+    fixup: Option<fn(&mut I)>,
+    weights: Option<MutatorWeights>,
+    adaptive: bool,
+    successes: Vec<Successes>,
+    stat_names: Option<Vec<String>>,
+    phase_weights: Option<(MutatorWeights, MutatorWeights)>,
+    bounds: Option<PacketBounds>,
+    provenance: Option<Provenance<I>>,
     phantom: PhantomData<(I, S)>,
 }
 
@@ -35,9 +205,244 @@ where
     pub fn new(mutations: MT) -> Self {
         Self {
             mutations,
+            max_rejection: None,
+            rejections: Vec::new(),
+            last_mutation: None,
+            fixup: None,
+            weights: None,
+            adaptive: false,
+            successes: Vec::new(),
+            stat_names: None,
+            phase_weights: None,
+            bounds: None,
+            provenance: None,
             phantom: PhantomData,
         }
     }
+
+    /// Pick the `i`-th mutator with a probability proportional to `weights[i]`
+    /// instead of uniformly, e.g. to select havoc far more often than structural mutators.
+    /// Weights are ignored if there are not exactly as many as there are mutators.
+    ///
+    /// [`MutatorWeights`] in the state take precedence, so that the weights can still be changed at runtime.
+    ///
+    /// # Example
+    /// ```
+    /// let mutator = PacketMutationScheduler::new(tuple_list!(
+    ///     PacketDeleteMutator::new(4),
+    ///     PacketReorderMutator::new(),
+    ///     PacketHavocMutator::new(supported_havoc_mutations()),
+    /// ))
+    /// .with_weights(vec![1.0, 1.0, 8.0]);
+    /// ```
+    pub fn with_weights(mut self, weights: Vec<f64>) -> Self {
+        self.weights = Some(MutatorWeights::new(weights));
+        self
+    }
+
+    /// Use different weights for the mutators in the exploration and the exploitation [`Phase`]
+    /// of a [`PhaseStage`](crate::PhaseStage), e.g. favor structural mutators during exploration and havoc during exploitation.
+    /// They replace the weights of [`with_weights()`](PacketMutationScheduler::with_weights) while the state contains a [`PhaseMetadata`].
+    pub fn with_phase_weights(mut self, exploration: Vec<f64>, exploitation: Vec<f64>) -> Self {
+        self.phase_weights = Some((MutatorWeights::new(exploration), MutatorWeights::new(exploitation)));
+        self
+    }
+
+    /// Learn the selection probabilities while fuzzing, similar to MOpt.
+    ///
+    /// The scheduler tracks the recent success rate of every mutator, i.e. how often one of its outputs
+    /// was added to the corpus because it discovered new states or transitions, and picks mutators
+    /// with a probability proportional to it. Older executions fade out, so the probabilities follow the
+    /// progress of the campaign. 10% of the picks remain uniform so that no mutator starves.
+    /// Until every mutator was executed 50 times, the weights of [`with_weights()`](PacketMutationScheduler::with_weights)
+    /// or a uniform distribution are used.
+    pub fn with_adaptive_selection(mut self) -> Self {
+        self.adaptive = true;
+        self
+    }
+
+    /// Count how many outputs of each mutator were executed and how many of them were added to the corpus
+    /// in the [`MutatorStatsMetadata`] of the state. Use a [`MutatorStatsFeedback`](crate::MutatorStatsFeedback)
+    /// to display them in the monitor.
+    pub fn with_mutator_stats(mut self) -> Self
+    where
+        MT: NamedTuple,
+    {
+        self.stat_names = Some((0..self.mutations.len()).map(|mutation| self.mutations.name(mutation).unwrap_or_default().to_string()).collect());
+        self
+    }
+
+    /// Record which mutator was applied to which packets, so that the [`ProvenanceFeedback`](crate::ProvenanceFeedback)
+    /// can attach the chain of mutations to every new testcase. Packets are compared by their hashes.
+    ///
+    /// # Example
+    /// ```
+    /// let mutator = PacketMutationScheduler::new(mutations).with_provenance::<TextLinePacket>();
+    /// ```
+    pub fn with_provenance<P>(mut self) -> Self
+    where
+        MT: NamedTuple,
+        I: HasPackets<P>,
+        P: Hash,
+    {
+        self.provenance = Some(Provenance {
+            names: (0..self.mutations.len()).map(|mutation| self.mutations.name(mutation).unwrap_or_default().to_string()).collect(),
+            hashes: |input: &I| {
+                input
+                    .packets()
+                    .iter()
+                    .map(|packet| {
+                        let mut hasher = AHasher::default();
+                        packet.hash(&mut hasher);
+                        hasher.finish()
+                    })
+                    .collect()
+            },
+        });
+        self
+    }
+
+    /// Returns the recent success rate of mutator `mutation` that the adaptive selection
+    /// is based on, `None` if it has not been executed yet or the adaptive selection is disabled
+    pub fn success_rate(&self, mutation: usize) -> Option<f64> {
+        match self.successes.get(mutation) {
+            Some(successes) if successes.executions > 0 => Some(successes.recent_successes / successes.recent_executions),
+            _ => None,
+        }
+    }
+
+    /// Selection probabilities of the adaptive selection, `None` while it is still warming up
+    fn adaptive_weights(&self) -> Option<MutatorWeights> {
+        let len = self.mutations.len();
+
+        if !self.adaptive || self.successes.len() != len || self.successes.iter().any(|successes| successes.executions < ADAPTIVE_WARMUP) {
+            return None;
+        }
+
+        let rates: Vec<f64> = (0..len).map(|mutation| self.success_rate(mutation).unwrap_or(0.0)).collect();
+        let total: f64 = rates.iter().sum();
+
+        if total <= 0.0 {
+            return None;
+        }
+
+        Some(MutatorWeights::new(rates.into_iter().map(|rate| (1.0 - ADAPTIVE_EXPLORATION) * rate / total + ADAPTIVE_EXPLORATION / len as f64).collect()))
+    }
+
+    /// Pick a mutator only rarely if the target rejected a packet in more than
+    /// `max_rejection` (between 0 and 1) of the executions of its outputs.
+    /// Requires [`NetworkExecutor::with_validity_oracle()`](crate::NetworkExecutor::with_validity_oracle).
+    pub fn with_validity_penalty(mut self, max_rejection: f64) -> Self {
+        self.max_rejection = Some(max_rejection);
+        self
+    }
+
+    /// Repair every mutated input with `fixup` before it gets executed,
+    /// e.g. to renumber message IDs that span multiple packets.
+    ///
+    /// # Example
+    /// ```
+    /// let mutator = PacketMutationScheduler::new(mutations).with_fixup(JsonInput::renumber_ids);
+    /// ```
+    pub fn with_fixup(mut self, fixup: fn(&mut I)) -> Self {
+        self.fixup = Some(fixup);
+        self
+    }
+
+    /// Call [`HasPostMutationFixup::fixup()`] on all packets of every mutated input.
+    /// Useful for external mutators that don't know about [`HasPostMutationFixup`].
+    pub fn with_packet_fixups<P>(mut self) -> Self
+    where
+        I: HasPackets<P>,
+        P: HasPostMutationFixup,
+    {
+        self.fixup = Some(|input: &mut I| input.packets_mut().iter_mut().for_each(|packet| packet.fixup()));
+        self
+    }
+
+    /// Keep all inputs within `bounds`, no matter which bounds were given to the constructors
+    /// of the structural mutators like the [`PacketDeleteMutator`](crate::PacketDeleteMutator) or the
+    /// [`PacketDuplicateMutator`](crate::PacketDuplicateMutator).
+    ///
+    /// The bounds are stored in the state before the first mutation, unless the state already has
+    /// [`PacketBounds`], e.g. from a [`PacketBoundsStage`](crate::PacketBoundsStage) that adapts them.
+    ///
+    /// # Example
+    /// ```
+    /// let mutator = PacketMutationScheduler::new(tuple_list!(
+    ///     PacketDeleteMutator::new(1),
+    ///     PacketDuplicateMutator::new(usize::MAX),
+    ///     PacketSpliceMutator::new(1),
+    /// ))
+    /// .with_bounds(PacketBounds::new(2, 16));
+    /// ```
+    pub fn with_bounds(mut self, bounds: PacketBounds) -> Self {
+        self.bounds = Some(bounds);
+        self
+    }
+
+    /// Returns the fraction of executions of the outputs of mutator `mutation`
+    /// where the target rejected at least one packet, `None` if nothing is known yet
+    pub fn rejection_rate(&self, mutation: usize) -> Option<f64> {
+        match self.rejections.get(mutation) {
+            Some(rejections) if rejections.executions > 0 => Some(rejections.rejected as f64 / rejections.executions as f64),
+            _ => None,
+        }
+    }
+
+    /// Draw a mutator, honoring the [`MutatorWeights`] in the state, the adaptive selection and the weights of the scheduler
+    fn draw(&self, state: &mut S) -> usize
+    where
+        S: HasMetadata,
+    {
+        let len = self.mutations.len();
+        let mut rand = StdRand::with_seed(state.rand_mut().next());
+        let picked = state.metadata().get::<MutatorWeights>().and_then(|weights| weights.pick(&mut rand, len)).or_else(|| self.adaptive_weights().and_then(|weights| weights.pick(&mut rand, len))).or_else(|| {
+            let weights = match (&self.phase_weights, state.metadata().get::<PhaseMetadata>()) {
+                (Some((exploration, _)), Some(metadata)) if metadata.phase == Phase::Exploration => Some(exploration),
+                (Some((_, exploitation)), Some(_)) => Some(exploitation),
+                _ => self.weights.as_ref(),
+            };
+            weights.and_then(|weights| weights.pick(&mut rand, len))
+        });
+
+        match picked {
+            Some(mutation) => mutation,
+            None => rand.below(len as u64) as usize,
+        }
+    }
+
+    fn is_penalized(&self, mutation: usize) -> bool {
+        match (self.max_rejection, self.rejections.get(mutation)) {
+            (Some(max_rejection), Some(rejections)) => rejections.executions >= MIN_PENALTY_EXECUTIONS && self.rejection_rate(mutation).unwrap_or(0.0) > max_rejection,
+            _ => false,
+        }
+    }
+}
+
+impl<I, MT, S> HasConfig for PacketMutationScheduler<I, MT, S>
+where
+    I: Input,
+    MT: MutatorsTuple<I, S> + ConfigTuple,
+    S: HasRand,
+{
+    fn config(&self) -> ComponentConfig {
+        let mut config = ComponentConfig::new("PacketMutationScheduler")
+            .with_setting("weights", self.weights.as_ref().map_or_else(|| "uniform".to_string(), |weights| format!("{:?}", weights.weights)))
+            .with_setting("phase_weights", self.phase_weights.as_ref().map_or_else(|| "none".to_string(), |(exploration, exploitation)| format!("{:?} / {:?}", exploration.weights, exploitation.weights)))
+            .with_setting("adaptive", self.adaptive)
+            .with_setting("validity_penalty", self.max_rejection.map_or_else(|| "none".to_string(), |max_rejection| max_rejection.to_string()))
+            .with_setting("fixup", self.fixup.is_some())
+            .with_setting("mutator_stats", self.stat_names.is_some())
+            .with_setting("provenance", self.provenance.is_some())
+            .with_setting("bounds", self.bounds.map_or_else(|| "none".to_string(), |bounds| format!("{}..={}", bounds.min_packets, bounds.max_packets)));
+
+        for mutator in self.mutations.configs() {
+            config = config.with_child(mutator);
+        }
+
+        config
+    }
 }
 
 impl<I, MT, S> ComposedByMutations<I, MT, S> for PacketMutationScheduler<I, MT, S>
@@ -59,35 +464,250 @@ impl<I, MT, S> Mutator<I, S> for PacketMutationScheduler<I, MT, S>
 where
     I: Input,
     MT: MutatorsTuple<I, S>,
-    S: HasRand,
+    S: HasRand + HasMetadata,
 {
     fn mutate(&mut self, state: &mut S, input: &mut I, stage_idx: i32) -> Result<MutationResult, Error> {
+        if let Some(bounds) = self.bounds {
+            if !state.has_metadata::<PacketBounds>() {
+                state.add_metadata(bounds);
+            }
+        }
+
         self.scheduled_mutate(state, input, stage_idx)
     }
+
+    fn post_exec(&mut self, state: &mut S, stage_idx: i32, corpus_idx: Option<usize>) -> Result<(), Error> {
+        let last_mutation = self.last_mutation.take();
+
+        if self.provenance.is_some() {
+            let _ = state.metadata_mut().remove::<PendingProvenance>();
+        }
+
+        if let (Some(mutation), Some(validity)) = (last_mutation, state.metadata().get::<ValidityMetadata>()) {
+            if self.rejections.len() <= mutation {
+                self.rejections.resize(self.mutations.len(), Rejections::default());
+            }
+
+            let rejections = &mut self.rejections[mutation];
+            rejections.executions += 1;
+            rejections.rejected += (validity.rejected > 0) as u64;
+        }
+
+        if let (Some(names), Some(mutation)) = (&self.stat_names, last_mutation) {
+            if !state.has_metadata::<MutatorStatsMetadata>() {
+                state.add_metadata(MutatorStatsMetadata::default());
+            }
+
+            let stats = state.metadata_mut().get_mut::<MutatorStatsMetadata>().unwrap();
+            stats.count(&names[mutation], corpus_idx.is_some());
+        }
+
+        // The input only made it into the corpus if it was interesting
+        if let (true, Some(mutation)) = (self.adaptive, last_mutation) {
+            if self.successes.len() <= mutation {
+                self.successes.resize(self.mutations.len(), Successes::default());
+            }
+
+            let successes = &mut self.successes[mutation];
+            successes.executions += 1;
+            successes.recent_executions = successes.recent_executions * ADAPTIVE_DECAY + 1.0;
+            successes.recent_successes = successes.recent_successes * ADAPTIVE_DECAY + corpus_idx.is_some() as u64 as f64;
+        }
+
+        self.mutations.post_exec_all(state, stage_idx, corpus_idx)
+    }
 }
 
 impl<I, MT, S> ScheduledMutator<I, MT, S> for PacketMutationScheduler<I, MT, S>
 where
     I: Input,
     MT: MutatorsTuple<I, S>,
-    S: HasRand,
+    S: HasRand + HasMetadata,
 {
     fn iterations(&self, _state: &mut S, _input: &I) -> u64 {
         1
     }
 
     fn schedule(&self, state: &mut S, _input: &I) -> usize {
-        state.rand_mut().below(self.mutations.len() as u64) as usize
+        let mut mutation = self.draw(state);
+
+        // Draw again if a penalized mutator was picked, but give it a small chance
+        for _ in 0..self.mutations.len() {
+            if !self.is_penalized(mutation) || state.rand_mut().below(PENALTY_ODDS) == 0 {
+                break;
+            }
+
+            mutation = self.draw(state);
+        }
+
+        mutation
     }
 
     fn scheduled_mutate(&mut self, state: &mut S, input: &mut I, stage_idx: i32) -> Result<MutationResult, Error> {
         let mut result = MutationResult::Skipped;
+        let before = self.provenance.as_ref().map(|provenance| (provenance.hashes)(input));
 
         while result == MutationResult::Skipped {
             let mutation = self.schedule(state, input);
             result = self.mutations.get_and_mutate(mutation, state, input, stage_idx)?;
+            self.last_mutation = Some(mutation);
+        }
+
+        if let Some(fixup) = self.fixup {
+            fixup(input);
+        }
+
+        if let (Some(provenance), Some(before), Some(mutation)) = (&self.provenance, before, self.last_mutation) {
+            state.add_metadata(PendingProvenance {
+                step: ProvenanceStep {
+                    mutator: provenance.names[mutation].clone(),
+                    packets: changed_packets(&before, &(provenance.hashes)(input)),
+                },
+            });
         }
 
         Ok(result)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{executor::Validity, test_utils::TestInput, PacketDeleteMutator, PacketDuplicateMutator};
+    use libafl::{
+        bolts::{tuples::tuple_list, HasLen},
+        corpus::InMemoryCorpus,
+        inputs::{BytesInput, HasBytesVec},
+        mutators::{BitFlipMutator, ByteFlipMutator},
+        state::StdState,
+    };
+
+    #[test]
+    fn test_validity_penalty() {
+        let mut state = StdState::new(StdRand::with_seed(0), InMemoryCorpus::<BytesInput>::new(), InMemoryCorpus::<BytesInput>::new(), &mut (), &mut ()).unwrap();
+        let mut scheduler = PacketMutationScheduler::<BytesInput, _, _>::new(tuple_list!(BitFlipMutator::new())).with_validity_penalty(0.9);
+        let mut validity = ValidityMetadata::default();
+        validity.count(Validity::Rejected);
+        state.add_metadata(validity);
+
+        for _ in 0..MIN_PENALTY_EXECUTIONS {
+            assert!(!scheduler.is_penalized(0));
+            scheduler.last_mutation = Some(0);
+            scheduler.post_exec(&mut state, 0, None).unwrap();
+        }
+
+        assert_eq!(scheduler.rejection_rate(0), Some(1.0));
+        assert!(scheduler.is_penalized(0));
+    }
+
+    #[test]
+    fn test_weights() {
+        let mut state = StdState::new(StdRand::with_seed(0), InMemoryCorpus::<BytesInput>::new(), InMemoryCorpus::<BytesInput>::new(), &mut (), &mut ()).unwrap();
+        let scheduler = PacketMutationScheduler::new(tuple_list!(BitFlipMutator::new(), ByteFlipMutator::new()));
+        let input = BytesInput::new(Vec::new());
+        state.add_metadata(MutatorWeights::new(vec![0.0, 1.0]));
+
+        for _ in 0..100 {
+            assert_eq!(scheduler.schedule(&mut state, &input), 1);
+        }
+
+        // Weights for the wrong number of mutators are ignored
+        state.add_metadata(MutatorWeights::new(vec![1.0]));
+        assert!((0..100).any(|_| scheduler.schedule(&mut state, &input) == 1));
+
+        // Phase weights replace the weights of the scheduler
+        state.add_metadata(MutatorWeights::new(Vec::new()));
+        let scheduler = scheduler.with_phase_weights(vec![1.0, 0.0], vec![0.0, 1.0]);
+        let mut phase = PhaseMetadata::default();
+        phase.phase = Phase::Exploitation;
+        state.add_metadata(phase);
+        assert!((0..100).all(|_| scheduler.schedule(&mut state, &input) == 1));
+        state.metadata_mut().get_mut::<PhaseMetadata>().unwrap().phase = Phase::Exploration;
+        assert!((0..100).all(|_| scheduler.schedule(&mut state, &input) == 0));
+        let _ = state.metadata_mut().remove::<PhaseMetadata>();
+
+        // The weights of the scheduler apply unless the state overrides them
+        let scheduler = scheduler.with_weights(vec![1.0, 0.0]);
+        assert!((0..100).all(|_| scheduler.schedule(&mut state, &input) == 0));
+        state.add_metadata(MutatorWeights::new(vec![0.0, 1.0]));
+        assert!((0..100).all(|_| scheduler.schedule(&mut state, &input) == 1));
+    }
+
+    #[test]
+    fn test_adaptive_selection() {
+        let mut state = StdState::new(StdRand::with_seed(0), InMemoryCorpus::<BytesInput>::new(), InMemoryCorpus::<BytesInput>::new(), &mut (), &mut ()).unwrap();
+        let mut scheduler = PacketMutationScheduler::new(tuple_list!(BitFlipMutator::new(), ByteFlipMutator::new())).with_adaptive_selection();
+        let input = BytesInput::new(Vec::new());
+
+        // Only the second mutator ever finds something
+        for mutation in [0, 1] {
+            for _ in 0..ADAPTIVE_WARMUP {
+                assert!(scheduler.adaptive_weights().is_none());
+                scheduler.last_mutation = Some(mutation);
+                scheduler.post_exec(&mut state, 0, if mutation == 1 { Some(0) } else { None }).unwrap();
+            }
+        }
+
+        assert_eq!(scheduler.success_rate(0), Some(0.0));
+        assert_eq!(scheduler.success_rate(1), Some(1.0));
+
+        let picks = (0..1000).filter(|_| scheduler.schedule(&mut state, &input) == 1).count();
+        assert!(picks > 900 && picks < 1000);
+    }
+
+    #[test]
+    fn test_mutator_stats() {
+        let mut state = StdState::new(StdRand::with_seed(0), InMemoryCorpus::<BytesInput>::new(), InMemoryCorpus::<BytesInput>::new(), &mut (), &mut ()).unwrap();
+        let mut scheduler = PacketMutationScheduler::<BytesInput, _, _>::new(tuple_list!(BitFlipMutator::new(), ByteFlipMutator::new())).with_mutator_stats();
+
+        for (mutation, corpus_idx) in [(1, None), (1, Some(0)), (0, None)] {
+            scheduler.last_mutation = Some(mutation);
+            scheduler.post_exec(&mut state, 0, corpus_idx).unwrap();
+        }
+
+        let stats = &state.metadata().get::<MutatorStatsMetadata>().unwrap().stats;
+        assert_eq!(stats.len(), 2);
+        assert_eq!((stats[0].name.as_str(), stats[0].executions, stats[0].interesting), ("ByteFlipMutator", 2, 1));
+        assert_eq!((stats[1].name.as_str(), stats[1].executions, stats[1].interesting), ("BitFlipMutator", 1, 0));
+    }
+
+    #[test]
+    fn test_fixup() {
+        let mut state = StdState::new(StdRand::with_seed(0), InMemoryCorpus::<BytesInput>::new(), InMemoryCorpus::<BytesInput>::new(), &mut (), &mut ()).unwrap();
+        let mut scheduler = PacketMutationScheduler::new(tuple_list!(BitFlipMutator::new())).with_fixup(|input: &mut BytesInput| {
+            if !input.bytes().ends_with(b"\r\n") {
+                input.bytes_mut().extend_from_slice(b"\r\n");
+            }
+        });
+
+        for _ in 0..100 {
+            let mut input = BytesInput::new(b"NOOP\r\n".to_vec());
+            assert_eq!(scheduler.mutate(&mut state, &mut input, 0).unwrap(), MutationResult::Mutated);
+            assert!(input.bytes().ends_with(b"\r\n"));
+        }
+    }
+
+    #[test]
+    fn test_bounds() {
+        let mut state = StdState::new(StdRand::with_seed(0), InMemoryCorpus::<TestInput>::new(), InMemoryCorpus::<TestInput>::new(), &mut (), &mut ()).unwrap();
+        let mut scheduler = PacketMutationScheduler::new(tuple_list!(PacketDeleteMutator::new(1), PacketDuplicateMutator::new(usize::MAX))).with_bounds(PacketBounds::new(2, 3));
+        let mut input = TestInput {
+            packets: vec![BytesInput::new(b"a".to_vec()), BytesInput::new(b"b".to_vec())],
+        };
+
+        for _ in 0..100 {
+            scheduler.mutate(&mut state, &mut input, 0).unwrap();
+            assert!(input.len() >= 2 && input.len() <= 3);
+        }
+
+        assert_eq!(scheduler.config().setting("bounds"), Some("2..=3"));
+
+        // Bounds in the state take precedence
+        state.add_metadata(PacketBounds::new(4, 4));
+        let mut input = TestInput {
+            packets: vec![BytesInput::new(b"a".to_vec()); 5],
+        };
+        scheduler.mutate(&mut state, &mut input, 0).unwrap();
+        assert_eq!(input.len(), 4);
+    }
+}
@@ -0,0 +1,166 @@
+use crate::observer::StateObserver;
+use libafl::{
+    events::{CustomBufEventResult, Event, EventFirer, HasCustomBufHandlers},
+    executors::HasObservers,
+    impl_serdeany,
+    inputs::Input,
+    observers::ObserversTuple,
+    stages::Stage,
+    state::HasMetadata,
+    Error,
+};
+use serde::{Deserialize, Serialize};
+use std::cmp::Eq;
+use std::fmt::Debug;
+use std::hash::Hash;
+use std::marker::PhantomData;
+use std::time::{Duration, Instant};
+
+/// Tag of the `CustomBuf` events that carry state-graphs between the clients of a campaign
+pub static GRAPH_SYNC_TAG: &str = "butterfly_stategraph";
+
+/// State-graphs of other clients that arrived since the last run of the [`GraphSyncStage`]
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub(crate) struct PendingGraphs {
+    pub(crate) graphs: Vec<Vec<u8>>,
+}
+
+impl_serdeany!(PendingGraphs);
+
+/// Store a state-graph that another client sent until the [`GraphSyncStage`] merges it
+fn handle_graph_buf<S: HasMetadata>(state: &mut S, tag: &str, buf: &[u8]) -> CustomBufEventResult {
+    if tag != GRAPH_SYNC_TAG {
+        return CustomBufEventResult::Next;
+    }
+
+    if !state.has_metadata::<PendingGraphs>() {
+        state.add_metadata(PendingGraphs::default());
+    }
+
+    state.metadata_mut().get_mut::<PendingGraphs>().unwrap().graphs.push(buf.to_vec());
+    CustomBufEventResult::Handled
+}
+
+/// Let the event manager of a client collect the state-graphs that other clients
+/// send with the [`GraphSyncStage`]. Must be called once per client before fuzzing.
+pub fn register_graph_sync<EM, S>(manager: &mut EM)
+where
+    EM: HasCustomBufHandlers<S>,
+    S: HasMetadata,
+{
+    manager.add_custom_buf_handler(Box::new(|state: &mut S, tag: &String, buf: &[u8]| Ok(handle_graph_buf(state, tag, buf))));
+}
+
+/// A stage that shares the state-graph of a [`StateObserver`] with all other clients of a multi-core campaign.
+///
+/// With LibAFLs `Launcher` every client builds its own state-graph, so every client has to
+/// discover every state and transition by itself before it stops considering them new.
+/// This stage periodically sends the exact state-graph of the observer with the name `observer_name`
+/// through the broker to the other clients, if it has grown since it was last sent, and
+/// [merges](StateObserver::merge_graph) the state-graphs it received from them.
+/// The event manager of every client must collect them via [`register_graph_sync()`].
+///
+/// The state-graph is sent as a whole, so keep the interval large for big state-graphs.
+///
+/// # Example
+/// ```
+/// let mut run_client = |state: Option<_>, mut mgr, _core_id| {
+///     register_graph_sync(&mut mgr);
+///     let mut stages = tuple_list!(
+///         GraphSyncStage::<_, _, u32>::new("state").with_interval(Duration::from_secs(30)),
+///         StdMutationalStage::new(mutator),
+///     );
+///     // ...
+/// };
+/// ```
+#[derive(Clone, Debug)]
+pub struct GraphSyncStage<I, OT, PS> {
+    observer_name: String,
+    interval: Duration,
+    last_sync: Option<Instant>,
+    last_info: (usize, usize),
+    phantom: PhantomData<(I, OT, PS)>,
+}
+
+impl<I, OT, PS> GraphSyncStage<I, OT, PS> {
+    /// Create a new GraphSyncStage for the [`StateObserver`] with the name `observer_name`
+    /// that sends its state-graph once per minute
+    pub fn new(observer_name: &str) -> Self {
+        Self {
+            observer_name: observer_name.to_string(),
+            interval: Duration::from_secs(60),
+            last_sync: None,
+            last_info: (0, 0),
+            phantom: PhantomData,
+        }
+    }
+
+    /// Send the state-graph every `interval` instead of once per minute
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+}
+
+impl<E, EM, I, OT, PS, S, Z> Stage<E, EM, S, Z> for GraphSyncStage<I, OT, PS>
+where
+    E: HasObservers<I, OT, S>,
+    EM: EventFirer<I>,
+    OT: ObserversTuple<I, S>,
+    I: Input,
+    PS: Clone + Debug + Eq + Hash + Serialize + for<'a> Deserialize<'a>,
+    S: HasMetadata,
+{
+    fn perform(&mut self, _fuzzer: &mut Z, executor: &mut E, state: &mut S, manager: &mut EM, _corpus_idx: usize) -> Result<(), Error> {
+        let observer = executor.observers_mut().match_name_mut::<StateObserver<PS>>(&self.observer_name).ok_or_else(|| Error::key_not_found(format!("No StateObserver named \"{}\" with the given state type", self.observer_name)))?;
+
+        if let Some(pending) = state.metadata_mut().get_mut::<PendingGraphs>() {
+            for graph in pending.graphs.drain(..) {
+                if let Err(err) = observer.merge_graph(&graph) {
+                    println!("[butterfly] Could not merge the state-graph of another client: {}", err);
+                }
+            }
+        }
+
+        if self.last_sync.is_some_and(|last_sync| last_sync.elapsed() < self.interval) {
+            return Ok(());
+        }
+
+        self.last_sync = Some(Instant::now());
+        // The exact state-graph is sent, which can grow without the active abstraction level changing
+        let info = observer.exact_info();
+
+        if info != self.last_info {
+            self.last_info = info;
+            let buf = observer.export_graph()?;
+
+            manager.fire(
+                state,
+                Event::CustomBuf {
+                    buf,
+                    tag: GRAPH_SYNC_TAG.to_string(),
+                },
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libafl::{bolts::rands::StdRand, corpus::InMemoryCorpus, inputs::BytesInput, state::StdState};
+
+    #[test]
+    fn test_graph_buf_handler() {
+        let mut state = StdState::new(StdRand::with_seed(0), InMemoryCorpus::<BytesInput>::new(), InMemoryCorpus::<BytesInput>::new(), &mut (), &mut ()).unwrap();
+
+        assert_eq!(handle_graph_buf(&mut state, "other", b"graph"), CustomBufEventResult::Next);
+        assert!(!state.has_metadata::<PendingGraphs>());
+
+        assert_eq!(handle_graph_buf(&mut state, GRAPH_SYNC_TAG, b"graph"), CustomBufEventResult::Handled);
+        assert_eq!(handle_graph_buf(&mut state, GRAPH_SYNC_TAG, b"graph"), CustomBufEventResult::Handled);
+        assert_eq!(state.metadata().get::<PendingGraphs>().unwrap().graphs.len(), 2);
+    }
+}
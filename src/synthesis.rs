@@ -0,0 +1,438 @@
+use crate::{executor::HasPayload, input::HasPackets, mutators::choose_weighted, observer::StateObserver, validate::execute_once};
+use libafl::{
+    bolts::rands::Rand,
+    executors::{Executor, HasObservers},
+    generators::Generator,
+    inputs::Input,
+    observers::ObserversTuple,
+    state::HasRand,
+    Error,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::hash::Hash;
+use std::marker::PhantomData;
+
+/// How many different packet sequences are stored per transition
+const MAX_SEGMENTS: usize = 4;
+/// A walk ends early with a probability of 1 / STOP_ODDS after every transition
+const STOP_ODDS: u64 = 8;
+/// How many example packets are stored per packet type
+const MAX_EXAMPLES: usize = 16;
+
+/// A generator that synthesizes new seeds from the state-graph.
+///
+/// It learns from the traces of existing inputs which packets made the target take which transition.
+/// Then it walks the state-graph randomly and concatenates the packets of every transition on the walk,
+/// which creates sessions that combine transitions that were never seen together in a single input.
+///
+/// The `n`-th packet of an input is assumed to cause the `n`-th state of the path, which holds if the executor
+/// records one state per packet. Packets that don't change the state are prepended to the packets of the next transition.
+/// The state ids depend on the abstraction level of the [`StateObserver`], so don't switch levels while learning.
+///
+/// # Example
+/// ```
+/// let mut synthesizer = TraceSynthesizer::<_, _, u32>::new(32);
+///
+/// for seed in &seeds {
+///     synthesizer.learn(&mut fuzzer, &mut state, &mut executor, &mut mgr, seed, "state")?;
+/// }
+///
+/// state.generate_initial_inputs(&mut fuzzer, &mut executor, &mut synthesizer, &mut mgr, 100)?;
+/// ```
+#[derive(Clone, Debug)]
+pub struct TraceSynthesizer<I, P, PS> {
+    max_packets: usize,
+    template: Option<I>,
+    entries: Vec<(u32, Vec<P>)>,
+    transitions: HashMap<u32, Vec<(u32, Vec<P>)>>,
+    phantom: PhantomData<PS>,
+}
+
+impl<I, P, PS> TraceSynthesizer<I, P, PS>
+where
+    I: Input + HasPackets<P>,
+    P: Clone,
+    PS: Clone + Debug + Eq + Hash + Serialize + for<'a> Deserialize<'a>,
+{
+    /// Create a new TraceSynthesizer that generates seeds with at most `max_packets` packets
+    pub fn new(max_packets: usize) -> Self {
+        Self {
+            max_packets: max_packets.max(1),
+            template: None,
+            entries: Vec::new(),
+            transitions: HashMap::new(),
+            phantom: PhantomData,
+        }
+    }
+
+    /// Learn the transitions that `input` made the target take, given the `path` of state ids it went through
+    pub fn record(&mut self, input: &I, path: &[u32]) {
+        let packets = input.packets();
+        let mut segment = Vec::new();
+
+        for (i, (packet, node)) in packets.iter().zip(path).enumerate() {
+            segment.push(packet.clone());
+
+            if i == 0 {
+                if !self.entries.iter().any(|(entry, _)| entry == node) {
+                    self.entries.push((*node, std::mem::take(&mut segment)));
+                }
+                segment.clear();
+            } else if path[i - 1] != *node {
+                let outgoing = self.transitions.entry(path[i - 1]).or_default();
+
+                if outgoing.iter().filter(|(to, _)| to == node).count() < MAX_SEGMENTS {
+                    outgoing.push((*node, std::mem::take(&mut segment)));
+                }
+                segment.clear();
+            }
+        }
+
+        if self.template.is_none() {
+            self.template = Some(input.clone());
+        }
+    }
+
+    /// Execute `input` and [`record()`](TraceSynthesizer::record) the path that the
+    /// [`StateObserver`] with the name `observer_name` observed
+    pub fn learn<E, EM, S, Z, OT>(&mut self, fuzzer: &mut Z, state: &mut S, executor: &mut E, mgr: &mut EM, input: &I, observer_name: &str) -> Result<(), Error>
+    where
+        E: Executor<EM, I, S, Z> + HasObservers<I, OT, S>,
+        OT: ObserversTuple<I, S>,
+    {
+        execute_once(fuzzer, state, executor, mgr, input)?;
+
+        let observer = executor.observers().match_name::<StateObserver<PS>>(observer_name).ok_or_else(|| Error::key_not_found(format!("No StateObserver named \"{}\" with the given state type", observer_name)))?;
+        let path = observer.path().to_vec();
+        self.record(input, &path);
+        Ok(())
+    }
+
+    /// Returns the number of transitions that have been learned
+    pub fn transitions(&self) -> usize {
+        self.transitions.values().map(Vec::len).sum()
+    }
+
+    /// Walk the state-graph randomly and return the packets of the walk, or `None` if nothing has been learned yet
+    pub fn synthesize<R: Rand>(&self, rand: &mut R) -> Option<I> {
+        if self.entries.is_empty() {
+            return None;
+        }
+
+        let mut input = self.template.clone()?;
+        let (entry, prefix) = rand.choose(&self.entries);
+        let mut node = *entry;
+        let mut packets = prefix.clone();
+
+        while let Some(outgoing) = self.transitions.get(&node) {
+            let (to, segment) = rand.choose(outgoing);
+
+            if packets.len() + segment.len() > self.max_packets {
+                break;
+            }
+
+            packets.extend_from_slice(segment);
+            node = *to;
+
+            if rand.below(STOP_ODDS) == 0 {
+                break;
+            }
+        }
+
+        packets.truncate(self.max_packets);
+        *input.packets_mut() = packets;
+        Some(input)
+    }
+}
+
+impl<I, P, PS, S> Generator<I, S> for TraceSynthesizer<I, P, PS>
+where
+    I: Input + HasPackets<P>,
+    P: Clone,
+    PS: Clone + Debug + Eq + Hash + Serialize + for<'a> Deserialize<'a>,
+    S: HasRand,
+{
+    fn generate(&mut self, state: &mut S) -> Result<I, Error> {
+        self.synthesize(state.rand_mut()).ok_or_else(|| Error::empty("TraceSynthesizer has not learned any traces".to_string()))
+    }
+
+    /// Returns the first input that was recorded without packets
+    fn generate_dummy(&self, _state: &mut S) -> I {
+        let mut input = self.template.clone().expect("TraceSynthesizer has not learned any traces");
+        input.packets_mut().clear();
+        input
+    }
+}
+
+/// What the [`StatisticalGenerator`] knows about one type of packet
+#[derive(Clone, Debug)]
+struct PacketKind<P> {
+    examples: Vec<P>,
+    /// Number of packets of this type, not only the examples
+    count: u64,
+    mean_len: f64,
+    /// Sum of the squared differences from the mean, see Welford's algorithm
+    m2_len: f64,
+}
+
+impl<P> PacketKind<P> {
+    fn new() -> Self {
+        Self {
+            examples: Vec::new(),
+            count: 0,
+            mean_len: 0.0,
+            m2_len: 0.0,
+        }
+    }
+
+    fn add_len(&mut self, len: usize) {
+        self.count += 1;
+        let delta = len as f64 - self.mean_len;
+        self.mean_len += delta / self.count as f64;
+        self.m2_len += delta * (len as f64 - self.mean_len);
+    }
+
+    fn std_dev_len(&self) -> f64 {
+        if self.count < 2 {
+            0.0
+        } else {
+            (self.m2_len / (self.count - 1) as f64).sqrt()
+        }
+    }
+}
+
+/// A generator that synthesizes new sessions that are statistically similar to the seeds.
+///
+/// It learns from the seed corpus
+/// - how many packets the sessions have,
+/// - how likely every packet type is at every position of a session and
+/// - the mean and standard deviation of the payload length of every packet type.
+///
+/// A new session gets a length drawn from the observed lengths and a packet type per position drawn from
+/// the distribution at that position. Positions behind the longest seed use the distribution of the last position.
+/// For every packet it draws a payload length from a normal distribution with the statistics of the type and
+/// takes the stored example of that type whose payload length is closest.
+/// Packets are never modified, so this needs no grammar but relies on the mutators to create new payloads.
+///
+/// The type of a packet is given by a function, e.g. the keyword of a text command or the message id of a binary protocol.
+///
+/// # Example
+/// ```
+/// let mut generator = StatisticalGenerator::<PacketInput, _>::new(|packet: &TextLinePacket| String::from_utf8_lossy(packet.keyword()).into_owned());
+///
+/// for seed in &seeds {
+///     generator.learn(seed);
+/// }
+///
+/// state.generate_initial_inputs(&mut fuzzer, &mut executor, &mut generator, &mut mgr, 100)?;
+/// ```
+#[derive(Clone, Debug)]
+pub struct StatisticalGenerator<I, P> {
+    kind: fn(&P) -> String,
+    max_packets: usize,
+    template: Option<I>,
+    kinds: HashMap<String, PacketKind<P>>,
+    /// Names of the packet types in the order they were discovered
+    names: Vec<String>,
+    /// How often a session had a given number of packets
+    session_lens: Vec<u64>,
+    /// How often each packet type occurred at a position, indexed like `names`
+    positions: Vec<Vec<u64>>,
+}
+
+impl<I, P> StatisticalGenerator<I, P>
+where
+    I: Input + HasPackets<P>,
+    P: Clone + HasPayload,
+{
+    /// Create a new StatisticalGenerator that determines the type of a packet with `kind`
+    pub fn new(kind: fn(&P) -> String) -> Self {
+        Self {
+            kind,
+            max_packets: usize::MAX,
+            template: None,
+            kinds: HashMap::new(),
+            names: Vec::new(),
+            session_lens: Vec::new(),
+            positions: Vec::new(),
+        }
+    }
+
+    /// Generate sessions with at most `max_packets` packets
+    pub fn with_max_packets(mut self, max_packets: usize) -> Self {
+        self.max_packets = max_packets.max(1);
+        self
+    }
+
+    /// Learn the statistics of a seed
+    pub fn learn(&mut self, input: &I) {
+        let packets = input.packets();
+
+        if packets.is_empty() {
+            return;
+        }
+
+        if packets.len() >= self.session_lens.len() {
+            self.session_lens.resize(packets.len() + 1, 0);
+        }
+        self.session_lens[packets.len()] += 1;
+
+        for (position, packet) in packets.iter().enumerate() {
+            let name = (self.kind)(packet);
+            let idx = match self.names.iter().position(|known| *known == name) {
+                Some(idx) => idx,
+                None => {
+                    self.names.push(name.clone());
+                    self.names.len() - 1
+                },
+            };
+
+            let kind = self.kinds.entry(name).or_insert_with(PacketKind::new);
+            kind.add_len(packet.payload().len());
+
+            if kind.examples.len() < MAX_EXAMPLES {
+                kind.examples.push(packet.clone());
+            }
+
+            if position >= self.positions.len() {
+                self.positions.push(Vec::new());
+            }
+
+            let counts = &mut self.positions[position];
+
+            if idx >= counts.len() {
+                counts.resize(idx + 1, 0);
+            }
+            counts[idx] += 1;
+        }
+
+        if self.template.is_none() {
+            self.template = Some(input.clone());
+        }
+    }
+
+    /// Returns the number of distinct packet types that have been learned
+    pub fn packet_kinds(&self) -> usize {
+        self.names.len()
+    }
+
+    /// Draw a payload length for `kind` from a normal distribution with its statistics
+    fn draw_len<R: Rand>(rand: &mut R, kind: &PacketKind<P>) -> f64 {
+        // Irwin-Hall approximation of a standard normal distribution
+        let normal: f64 = (0..12).map(|_| rand.below(1 << 20) as f64 / (1 << 20) as f64).sum::<f64>() - 6.0;
+        kind.mean_len + normal * kind.std_dev_len()
+    }
+
+    /// Create a new session from the learned statistics, or `None` if nothing has been learned yet
+    pub fn synthesize<R: Rand>(&self, rand: &mut R) -> Option<I> {
+        let mut input = self.template.clone()?;
+        let len = choose_weighted(rand, &self.session_lens).min(self.max_packets);
+        let mut packets = Vec::with_capacity(len);
+
+        for position in 0..len {
+            let counts = &self.positions[position.min(self.positions.len() - 1)];
+            let kind = &self.kinds[&self.names[choose_weighted(rand, counts)]];
+            let target = Self::draw_len(rand, kind);
+
+            let closest = kind.examples.iter().map(|example| (example.payload().len() as f64 - target).abs()).fold(f64::INFINITY, f64::min);
+            let candidates: Vec<&P> = kind.examples.iter().filter(|example| (example.payload().len() as f64 - target).abs() == closest).collect();
+            packets.push(rand.choose(candidates).clone());
+        }
+
+        *input.packets_mut() = packets;
+        Some(input)
+    }
+}
+
+impl<I, P, S> Generator<I, S> for StatisticalGenerator<I, P>
+where
+    I: Input + HasPackets<P>,
+    P: Clone + HasPayload,
+    S: HasRand,
+{
+    fn generate(&mut self, state: &mut S) -> Result<I, Error> {
+        self.synthesize(state.rand_mut()).ok_or_else(|| Error::empty("StatisticalGenerator has not learned any seeds".to_string()))
+    }
+
+    /// Returns the first seed that was learned without packets
+    fn generate_dummy(&self, _state: &mut S) -> I {
+        let mut input = self.template.clone().expect("StatisticalGenerator has not learned any seeds");
+        input.packets_mut().clear();
+        input
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::TestInput;
+    use libafl::{
+        bolts::rands::StdRand,
+        inputs::{BytesInput, HasBytesVec},
+    };
+
+    fn input(lines: &[&[u8]]) -> TestInput {
+        TestInput {
+            packets: lines.iter().map(|line| BytesInput::new(line.to_vec())).collect(),
+        }
+    }
+
+    #[test]
+    fn test_synthesize() {
+        let mut rand = StdRand::with_seed(0);
+        let mut synthesizer = TraceSynthesizer::<TestInput, BytesInput, u32>::new(8);
+        assert!(synthesizer.synthesize(&mut rand).is_none());
+
+        // A NOOP that does not change the state and a failed login attempt
+        synthesizer.record(&input(&[b"USER", b"PASS", b"NOOP", b"LIST"]), &[0, 1, 1, 2]);
+        synthesizer.record(&input(&[b"USER", b"FAIL", b"PASS"]), &[0, 3, 1]);
+        assert_eq!(synthesizer.transitions(), 4);
+
+        let mut seen = Vec::new();
+
+        for _ in 0..100 {
+            let packets: Vec<Vec<u8>> = synthesizer.synthesize(&mut rand).unwrap().packets.into_iter().map(|packet| packet.bytes().to_vec()).collect();
+            assert!(packets.len() <= 8);
+            assert_eq!(packets[0], b"USER");
+            seen.push(packets);
+        }
+
+        // The failed login has never been followed by a LIST
+        assert!(seen.contains(&vec![b"USER".to_vec(), b"PASS".to_vec(), b"NOOP".to_vec(), b"LIST".to_vec()]));
+        assert!(seen.contains(&vec![b"USER".to_vec(), b"FAIL".to_vec(), b"PASS".to_vec(), b"NOOP".to_vec(), b"LIST".to_vec()]));
+    }
+
+    #[test]
+    fn test_statistical_generator() {
+        let mut rand = StdRand::with_seed(0);
+        let mut generator = StatisticalGenerator::<TestInput, BytesInput>::new(|packet| String::from_utf8_lossy(&packet.bytes()[..4]).into_owned());
+        assert!(generator.synthesize(&mut rand).is_none());
+
+        generator.learn(&input(&[b"USER a", b"PASS secret", b"LIST"]));
+        generator.learn(&input(&[b"USER bb", b"PASS password", b"RETR f"]));
+        generator.learn(&input(&[b"USER ccc", b"PASS pw", b"LIST", b"QUIT"]));
+        assert_eq!(generator.packet_kinds(), 5);
+
+        let mut lens = [0; 5];
+
+        for _ in 0..300 {
+            let packets = generator.synthesize(&mut rand).unwrap().packets;
+            lens[packets.len()] += 1;
+            assert!(packets[0].bytes().starts_with(b"USER"));
+            assert!(packets[1].bytes().starts_with(b"PASS"));
+
+            if packets.len() == 4 {
+                assert!(packets[3].bytes().starts_with(b"QUIT"));
+            }
+        }
+
+        // Two of the seeds have three packets
+        assert_eq!(lens[..3], [0, 0, 0]);
+        assert!(lens[3] > lens[4] && lens[4] > 0);
+
+        let generator = generator.with_max_packets(2);
+        assert_eq!(generator.synthesize(&mut rand).unwrap().packets.len(), 2);
+    }
+}
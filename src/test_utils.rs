@@ -0,0 +1,38 @@
+use crate::input::HasPackets;
+use libafl::{
+    bolts::HasLen,
+    inputs::{BytesInput, Input},
+};
+use serde::{Deserialize, Serialize};
+use std::fmt::Debug;
+
+/// An input for the unit tests that consists of nothing but packets of type `P`
+#[derive(Hash, Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct TestInput<P = BytesInput> {
+    pub(crate) packets: Vec<P>,
+}
+
+impl<P> Input for TestInput<P>
+where
+    P: Clone + Debug + Serialize + for<'a> Deserialize<'a>,
+{
+    fn generate_name(&self, idx: usize) -> String {
+        format!("test-{}", idx)
+    }
+}
+
+impl<P> HasPackets<P> for TestInput<P> {
+    fn packets(&self) -> &[P] {
+        &self.packets
+    }
+
+    fn packets_mut(&mut self) -> &mut Vec<P> {
+        &mut self.packets
+    }
+}
+
+impl<P> HasLen for TestInput<P> {
+    fn len(&self) -> usize {
+        self.packets.len()
+    }
+}
@@ -0,0 +1,365 @@
+use crate::{
+    executor::HasPayload,
+    mutators::{HasCrossoverInsertMutation, HasCrossoverReplaceMutation, HasHavocMutation, HasKindConversion, HasPacketGenerator, HasPostMutationFixup, HasSpliceMutation, HasTerminalPacket},
+};
+use libafl::{
+    bolts::rands::Rand,
+    inputs::{BytesInput, HasBytesVec},
+    mutators::{MutationResult, MutatorsTuple},
+    state::{HasMaxSize, HasMetadata, HasRand},
+    Error,
+};
+use serde::{Deserialize, Serialize};
+
+/// Characters of the arguments of generated packets
+const GENERATED_ARG_CHARS: &[u8] = b"abcdefghijklmnopqrstuvwxyz0123456789";
+
+/// A dictionary of keywords that [`TextLinePacket`]s draw from
+/// when their keyword gets mutated.
+///
+/// It must be stored as metadata in the state, otherwise keywords
+/// will not be mutated:
+/// ```
+/// state.add_metadata(KeywordDictionary::new(&[b"USER", b"PASS", b"CWD", b"LIST", b"QUIT"]));
+/// ```
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct KeywordDictionary {
+    keywords: Vec<Vec<u8>>,
+}
+
+libafl::impl_serdeany!(KeywordDictionary);
+
+impl KeywordDictionary {
+    /// Create a new KeywordDictionary from a list of keywords
+    pub fn new<K>(keywords: &[K]) -> Self
+    where
+        K: AsRef<[u8]>,
+    {
+        let mut ret = Self::default();
+
+        for keyword in keywords {
+            ret.add_keyword(keyword.as_ref());
+        }
+
+        ret
+    }
+
+    /// Add a keyword to the dictionary if it is not already present
+    pub fn add_keyword(&mut self, keyword: &[u8]) {
+        if !self.keywords.iter().any(|k| k == keyword) {
+            self.keywords.push(keyword.to_vec());
+        }
+    }
+
+    /// Get all keywords
+    pub fn keywords(&self) -> &[Vec<u8>] {
+        &self.keywords
+    }
+}
+
+/// A packet type for line-based text protocols like FTP, SMTP, IMAP or POP3
+/// where a message consists of a keyword, arguments separated by spaces
+/// and a terminating `\r\n`.
+///
+/// The keyword gets mutated by replacing it with another keyword from the
+/// [`KeywordDictionary`] in the state, the arguments are mutated by havoc,
+/// crossover and splice mutations.
+///
+/// # Example
+/// ```
+/// let packets = TextLinePacket::parse(b"USER anonymous\r\nPASS secret\r\n");
+/// assert_eq!(packets[0].keyword(), b"USER");
+/// assert_eq!(packets[1].to_bytes(), b"PASS secret\r\n");
+/// ```
+#[derive(Hash, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TextLinePacket {
+    keyword: Vec<u8>,
+    args: Vec<BytesInput>,
+}
+
+impl TextLinePacket {
+    /// Create a new TextLinePacket from a keyword and its arguments
+    pub fn new(keyword: &[u8], args: Vec<BytesInput>) -> Self {
+        Self {
+            keyword: keyword.to_vec(),
+            args,
+        }
+    }
+
+    /// Parse a single line. A trailing `\r\n` or `\n` is removed, the rest
+    /// is split at spaces. Returns `None` for empty lines.
+    pub fn from_line(line: &[u8]) -> Option<Self> {
+        let mut line = line;
+
+        while let Some(b'\n' | b'\r') = line.last() {
+            line = &line[..line.len() - 1];
+        }
+
+        if line.is_empty() {
+            return None;
+        }
+
+        let mut parts = line.split(|c| *c == b' ');
+        let keyword = parts.next()?;
+        let args = parts.map(|arg| BytesInput::new(arg.to_vec())).collect();
+
+        Some(Self::new(keyword, args))
+    }
+
+    /// Parse a payload that may contain multiple lines, like the payload of a TCP segment.
+    pub fn parse(payload: &[u8]) -> Vec<Self> {
+        payload.split(|c| *c == b'\n').filter_map(Self::from_line).collect()
+    }
+
+    /// Get the keyword
+    pub fn keyword(&self) -> &[u8] {
+        &self.keyword
+    }
+
+    /// Get the arguments
+    pub fn args(&self) -> &[BytesInput] {
+        &self.args
+    }
+
+    /// Get the arguments (mutable)
+    pub fn args_mut(&mut self) -> &mut Vec<BytesInput> {
+        &mut self.args
+    }
+
+    /// Serialize the packet into the format expected on the wire, including the terminating `\r\n`.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut ret = self.keyword.clone();
+
+        for arg in &self.args {
+            ret.push(b' ');
+            ret.extend_from_slice(arg.bytes());
+        }
+
+        ret.extend_from_slice(b"\r\n");
+        ret
+    }
+
+    /// Replace the keyword with a random different keyword from the dictionary in the state
+    fn mutate_keyword<S>(&mut self, state: &mut S) -> MutationResult
+    where
+        S: HasRand + HasMetadata,
+    {
+        let len = match state.metadata().get::<KeywordDictionary>() {
+            Some(dict) if !dict.keywords.is_empty() => dict.keywords.len(),
+            _ => return MutationResult::Skipped,
+        };
+        let idx = state.rand_mut().below(len as u64) as usize;
+        let keyword = &state.metadata().get::<KeywordDictionary>().unwrap().keywords[idx];
+
+        if *keyword == self.keyword {
+            return MutationResult::Skipped;
+        }
+
+        self.keyword = keyword.clone();
+        MutationResult::Mutated
+    }
+
+    /// Pick a random argument of self and other
+    fn pick_args<S>(&self, state: &mut S, other: &Self) -> Option<(usize, usize)>
+    where
+        S: HasRand,
+    {
+        if self.args.is_empty() || other.args.is_empty() {
+            return None;
+        }
+
+        let arg = state.rand_mut().below(self.args.len() as u64) as usize;
+        let other_arg = state.rand_mut().below(other.args.len() as u64) as usize;
+        Some((arg, other_arg))
+    }
+}
+
+impl HasPayload for TextLinePacket {
+    fn payload(&self) -> Vec<u8> {
+        self.to_bytes()
+    }
+}
+
+impl HasPostMutationFixup for TextLinePacket {}
+
+impl HasTerminalPacket for TextLinePacket {
+    fn is_terminal(&self) -> bool {
+        self.keyword.eq_ignore_ascii_case(b"QUIT") || self.keyword.eq_ignore_ascii_case(b"LOGOUT")
+    }
+}
+
+impl<MT, S> HasHavocMutation<MT, S> for TextLinePacket
+where
+    MT: MutatorsTuple<BytesInput, S>,
+    S: HasRand + HasMaxSize + HasMetadata,
+{
+    fn mutate_havoc(&mut self, state: &mut S, mutations: &mut MT, mutation: usize, stage_idx: i32) -> Result<MutationResult, Error> {
+        // Either mutate the keyword or one of the arguments
+        let target = state.rand_mut().below(self.args.len() as u64 + 1) as usize;
+
+        if target == self.args.len() {
+            Ok(self.mutate_keyword(state))
+        } else {
+            mutations.get_and_mutate(mutation, state, &mut self.args[target], stage_idx)
+        }
+    }
+}
+
+impl<S> HasKindConversion<S> for TextLinePacket
+where
+    S: HasRand + HasMetadata,
+{
+    fn convert_kind(&mut self, state: &mut S) -> bool {
+        self.mutate_keyword(state) == MutationResult::Mutated
+    }
+}
+
+impl<S> HasPacketGenerator<S> for TextLinePacket
+where
+    S: HasRand + HasMetadata,
+{
+    fn generate_packet(state: &mut S) -> Option<Self> {
+        let len = match state.metadata().get::<KeywordDictionary>() {
+            Some(dict) if !dict.keywords.is_empty() => dict.keywords.len(),
+            _ => return None,
+        };
+        let idx = state.rand_mut().below(len as u64) as usize;
+        let keyword = state.metadata().get::<KeywordDictionary>().unwrap().keywords[idx].clone();
+
+        // Up to two short alphanumeric arguments
+        let num_args = state.rand_mut().below(3);
+        let args = (0..num_args)
+            .map(|_| {
+                let arg_len = 1 + state.rand_mut().below(8);
+                BytesInput::new((0..arg_len).map(|_| *state.rand_mut().choose(GENERATED_ARG_CHARS)).collect())
+            })
+            .collect();
+
+        Some(Self::new(&keyword, args))
+    }
+}
+
+impl<S> HasCrossoverInsertMutation<S> for TextLinePacket
+where
+    S: HasRand + HasMaxSize,
+{
+    fn mutate_crossover_insert(&mut self, state: &mut S, other: &Self, stage_idx: i32) -> Result<MutationResult, Error> {
+        match self.pick_args(state, other) {
+            Some((arg, other_arg)) => self.args[arg].mutate_crossover_insert(state, &other.args[other_arg], stage_idx),
+            None => Ok(MutationResult::Skipped),
+        }
+    }
+}
+
+impl<S> HasCrossoverReplaceMutation<S> for TextLinePacket
+where
+    S: HasRand + HasMaxSize,
+{
+    fn mutate_crossover_replace(&mut self, state: &mut S, other: &Self, stage_idx: i32) -> Result<MutationResult, Error> {
+        match self.pick_args(state, other) {
+            Some((arg, other_arg)) => self.args[arg].mutate_crossover_replace(state, &other.args[other_arg], stage_idx),
+            None => Ok(MutationResult::Skipped),
+        }
+    }
+}
+
+impl<S> HasSpliceMutation<S> for TextLinePacket
+where
+    S: HasRand + HasMaxSize,
+{
+    fn mutate_splice(&mut self, state: &mut S, other: &Self, stage_idx: i32) -> Result<MutationResult, Error> {
+        match self.pick_args(state, other) {
+            Some((arg, other_arg)) => self.args[arg].mutate_splice(state, &other.args[other_arg], stage_idx),
+            None => Ok(MutationResult::Skipped),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libafl::bolts::{rands::StdRand, serdeany::SerdeAnyMap};
+
+    struct TestState {
+        rand: StdRand,
+        max_size: usize,
+        metadata: SerdeAnyMap,
+    }
+    impl TestState {
+        fn new() -> Self {
+            Self {
+                rand: StdRand::with_seed(0),
+                max_size: 0,
+                metadata: SerdeAnyMap::new(),
+            }
+        }
+    }
+    impl HasRand for TestState {
+        type Rand = StdRand;
+
+        fn rand(&self) -> &StdRand {
+            &self.rand
+        }
+
+        fn rand_mut(&mut self) -> &mut StdRand {
+            &mut self.rand
+        }
+    }
+    impl HasMaxSize for TestState {
+        fn max_size(&self) -> usize {
+            self.max_size
+        }
+
+        fn set_max_size(&mut self, max_size: usize) {
+            self.max_size = max_size;
+        }
+    }
+    impl HasMetadata for TestState {
+        fn metadata(&self) -> &SerdeAnyMap {
+            &self.metadata
+        }
+
+        fn metadata_mut(&mut self) -> &mut SerdeAnyMap {
+            &mut self.metadata
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        let packets = TextLinePacket::parse(b"USER anonymous\r\nPASV\r\n\r\nRNFR a b\r\n");
+        assert_eq!(packets.len(), 3);
+        assert_eq!(packets[0].keyword(), b"USER");
+        assert_eq!(packets[0].args(), &[BytesInput::new(b"anonymous".to_vec())]);
+        assert!(packets[1].args().is_empty());
+        assert_eq!(packets[2].args().len(), 2);
+        assert_eq!(packets[2].to_bytes(), b"RNFR a b\r\n");
+    }
+
+    #[test]
+    fn test_mutate_keyword() {
+        let mut state = TestState::new();
+        let mut packet = TextLinePacket::from_line(b"USER").unwrap();
+
+        assert_eq!(packet.mutate_keyword(&mut state), MutationResult::Skipped);
+
+        state.add_metadata(KeywordDictionary::new(&[b"USER", b"PASS"]));
+
+        while packet.mutate_keyword(&mut state) == MutationResult::Skipped {}
+        assert_eq!(packet.keyword(), b"PASS");
+    }
+
+    #[test]
+    fn test_generate_packet() {
+        let mut state = TestState::new();
+        assert!(TextLinePacket::generate_packet(&mut state).is_none());
+
+        state.add_metadata(KeywordDictionary::new(&[b"NOOP"]));
+
+        for _ in 0..16 {
+            let packet = TextLinePacket::generate_packet(&mut state).unwrap();
+            assert_eq!(packet.keyword(), b"NOOP");
+            assert!(packet.args().len() <= 2);
+            assert!(packet.args().iter().all(|arg| !arg.bytes().is_empty() && arg.bytes().iter().all(u8::is_ascii_alphanumeric)));
+        }
+    }
+}
@@ -0,0 +1,160 @@
+use libafl::Error;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+use std::thread::JoinHandle;
+
+/// Arguments of CWD that are longer than this "crash" the server.
+const CWD_BUFFER_SIZE: usize = 64;
+
+/// A tiny, stateful FTP-like server that runs in a background thread.
+///
+/// __Only available with feature__: `toy_target`
+///
+/// It needs no external target and is meant for testing harnesses
+/// and for trying out butterfly. Every connection gets greeted with `220` and
+/// then the following commands are understood:
+///
+/// | Command | Reply |
+/// |---------|-------|
+/// | `USER <name>` | `331` |
+/// | `PASS <password>` | `230` directly after `USER`, `503` otherwise |
+/// | `CWD <dir>` | `250` when logged in, `530` otherwise |
+/// | `LIST` | `226` when logged in, `530` otherwise |
+/// | `QUIT` | `221` and the connection gets closed |
+/// | anything else | `500` |
+///
+/// It also has a planted bug: when a logged in user sends a `CWD` with an argument
+/// longer than 64 bytes the server closes the connection without replying, which
+/// a harness can report as a crash.
+///
+/// # Example
+/// ```
+/// let server = ToyFtpServer::spawn().unwrap();
+/// let stream = TcpStream::connect(server.addr()).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct ToyFtpServer {
+    addr: SocketAddr,
+    running: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl ToyFtpServer {
+    /// Start the server on a random port on localhost.
+    pub fn spawn() -> Result<Self, Error> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let addr = listener.local_addr()?;
+        let running = Arc::new(AtomicBool::new(true));
+        let thread_running = running.clone();
+
+        let thread = std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                if !thread_running.load(Ordering::Relaxed) {
+                    break;
+                }
+
+                if let Ok(stream) = stream {
+                    std::thread::spawn(move || {
+                        let _ = handle_session(stream);
+                    });
+                }
+            }
+        });
+
+        Ok(Self {
+            addr,
+            running,
+            thread: Some(thread),
+        })
+    }
+
+    /// The address the server is listening on.
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+}
+
+impl Drop for ToyFtpServer {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::Relaxed);
+
+        // Wake up the accept loop so that it notices the shutdown
+        let _ = TcpStream::connect(self.addr);
+
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn handle_session(stream: TcpStream) -> Result<(), std::io::Error> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = stream;
+    let mut line = Vec::new();
+    let mut user_given = false;
+    let mut logged_in = false;
+
+    writer.write_all(b"220 toy ftp ready\r\n")?;
+
+    loop {
+        line.clear();
+
+        if reader.read_until(b'\n', &mut line)? == 0 {
+            return Ok(());
+        }
+
+        while line.last() == Some(&b'\n') || line.last() == Some(&b'\r') {
+            line.pop();
+        }
+
+        let (verb, arg) = match line.iter().position(|c| *c == b' ') {
+            Some(idx) => (&line[..idx], &line[idx + 1..]),
+            None => (&line[..], &line[line.len()..]),
+        };
+
+        let reply: &[u8] = match verb {
+            b"USER" => {
+                user_given = true;
+                logged_in = false;
+                b"331 password required\r\n"
+            },
+            b"PASS" => {
+                if user_given {
+                    user_given = false;
+                    logged_in = true;
+                    b"230 logged in\r\n"
+                } else {
+                    b"503 login with USER first\r\n"
+                }
+            },
+            b"CWD" => {
+                if !logged_in {
+                    b"530 not logged in\r\n"
+                } else if arg.len() > CWD_BUFFER_SIZE {
+                    // The planted bug
+                    return Ok(());
+                } else {
+                    b"250 directory changed\r\n"
+                }
+            },
+            b"LIST" => {
+                if logged_in {
+                    b"226 transfer complete\r\n"
+                } else {
+                    b"530 not logged in\r\n"
+                }
+            },
+            b"QUIT" => {
+                writer.write_all(b"221 goodbye\r\n")?;
+                return Ok(());
+            },
+            _ => b"500 unknown command\r\n",
+        };
+
+        writer.write_all(reply)?;
+    }
+}
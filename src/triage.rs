@@ -0,0 +1,412 @@
+use crate::{
+    observer::StateObserver,
+    output::{client_dir, create_unique_dir},
+};
+use libafl::{
+    bolts::tuples::Named,
+    corpus::Testcase,
+    events::EventFirer,
+    executors::ExitKind,
+    feedbacks::Feedback,
+    inputs::Input,
+    observers::{ObserversTuple, StdOutObserver},
+    state::HasClientPerfMonitor,
+    Error,
+};
+use serde::{Deserialize, Serialize};
+use std::cmp::Eq;
+use std::fmt::Debug;
+use std::fs::File;
+use std::hash::Hash;
+use std::io::Write;
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// A hook that gets invoked whenever an objective is saved.
+///
+/// Use it in conjunction with [`TriageFeedback`] to plug your own
+/// crash triage pipeline into a campaign.
+///
+/// butterfly already provides the following hooks:
+/// - [`BundleTriageHook`]: writes everything into a directory
+/// - [`ScriptTriageHook`]: writes a bundle and then invokes an external script on it
+///
+/// # Example
+/// ```
+/// #[derive(Debug)]
+/// struct PrintHook;
+///
+/// impl<I, PS> CrashTriageHook<I, PS> for PrintHook
+/// where
+///     I: Input,
+///     PS: Debug,
+/// {
+///     fn on_objective(&mut self, input: &I, states: &[PS], _output: Option<&str>) -> Result<(), Error> {
+///         println!("{:?} crashed after going through {:?}", input, states);
+///         Ok(())
+///     }
+/// }
+/// ```
+pub trait CrashTriageHook<I, PS>: Debug
+where
+    I: Input,
+{
+    /// Handle a new objective
+    ///
+    /// # Arguments
+    /// - `input`: the input that triggered the objective
+    /// - `states`: the states the target went through while processing `input`
+    /// - `output`: the output of the target, if an output observer was configured
+    ///
+    /// An error gets logged by the [`TriageFeedback`] but doesn't stop the campaign.
+    fn on_objective(&mut self, input: &I, states: &[PS], output: Option<&str>) -> Result<(), Error>;
+}
+
+/// A triage hook that writes every objective into its own directory.
+///
+/// A bundle is a new directory `<out_dir>/crash-<n>` that contains
+/// - `input`: the serialized input
+/// - `states.txt`: the states the target went through, one per line
+/// - `output.txt`: the output of the target, if available
+///
+/// Existing bundles, e.g. from before a client restart, are never overwritten.
+#[derive(Debug)]
+pub struct BundleTriageHook {
+    out_dir: PathBuf,
+    counter: usize,
+}
+
+impl BundleTriageHook {
+    /// Create a new BundleTriageHook that writes its bundles into `out_dir`
+    pub fn new<P>(out_dir: P) -> Self
+    where
+        P: Into<PathBuf>,
+    {
+        Self {
+            out_dir: out_dir.into(),
+            counter: 0,
+        }
+    }
+
+    /// Write into `<out_dir>/client-<client_id>` so that the clients of a multi-core campaign don't overwrite
+    /// each other's bundles, see [`client_dir()`](crate::client_dir)
+    pub fn with_client_id(mut self, client_id: usize) -> Self {
+        self.out_dir = client_dir(&self.out_dir, client_id);
+        self
+    }
+
+    /// Write a bundle and return its path
+    fn write_bundle<I, PS>(&mut self, input: &I, states: &[PS], output: Option<&str>) -> Result<PathBuf, Error>
+    where
+        I: Input,
+        PS: Debug,
+    {
+        let bundle = create_unique_dir(&self.out_dir, "crash", &mut self.counter)?;
+        input.to_file(bundle.join("input"))?;
+
+        let mut file = File::create(bundle.join("states.txt"))?;
+        for state in states {
+            writeln!(&mut file, "{:?}", state)?;
+        }
+
+        if let Some(output) = output {
+            std::fs::write(bundle.join("output.txt"), output)?;
+        }
+
+        Ok(bundle)
+    }
+}
+
+impl<I, PS> CrashTriageHook<I, PS> for BundleTriageHook
+where
+    I: Input,
+    PS: Debug,
+{
+    fn on_objective(&mut self, input: &I, states: &[PS], output: Option<&str>) -> Result<(), Error> {
+        let bundle = self.write_bundle(input, states, output)?;
+        println!("[butterfly] Wrote crash bundle {}", bundle.display());
+        Ok(())
+    }
+}
+
+/// A triage hook that writes a bundle like [`BundleTriageHook`] and
+/// then invokes an external script with the path of the bundle as its only argument.
+///
+/// # Example
+/// ```
+/// // Calls ./triage.sh crashes/crash-<n> for every objective
+/// let hook = ScriptTriageHook::new("crashes", "./triage.sh");
+/// ```
+#[derive(Debug)]
+pub struct ScriptTriageHook {
+    bundles: BundleTriageHook,
+    script: PathBuf,
+}
+
+impl ScriptTriageHook {
+    /// Create a new ScriptTriageHook
+    ///
+    /// # Arguments
+    /// - `out_dir`: directory where the bundles are written to
+    /// - `script`: path to the script that is invoked for every bundle
+    pub fn new<P, Q>(out_dir: P, script: Q) -> Self
+    where
+        P: Into<PathBuf>,
+        Q: AsRef<Path>,
+    {
+        Self {
+            bundles: BundleTriageHook::new(out_dir),
+            script: script.as_ref().to_path_buf(),
+        }
+    }
+
+    /// Write the bundles into `<out_dir>/client-<client_id>`, see [`BundleTriageHook::with_client_id()`]
+    pub fn with_client_id(mut self, client_id: usize) -> Self {
+        self.bundles = self.bundles.with_client_id(client_id);
+        self
+    }
+}
+
+impl<I, PS> CrashTriageHook<I, PS> for ScriptTriageHook
+where
+    I: Input,
+    PS: Debug,
+{
+    fn on_objective(&mut self, input: &I, states: &[PS], output: Option<&str>) -> Result<(), Error> {
+        let bundle = self.bundles.write_bundle(input, states, output)?;
+        let status = Command::new(&self.script).arg(&bundle).status()?;
+
+        if !status.success() {
+            println!("[butterfly] Triage script {} failed on {}: {}", self.script.display(), bundle.display(), status);
+        }
+
+        Ok(())
+    }
+}
+
+/// Wraps an objective feedback and invokes a [`CrashTriageHook`] whenever
+/// the objective gets saved.
+///
+/// # Example
+/// ```
+/// let state_observer = StateObserver::<u32>::new("state");
+/// let mut objective = TriageFeedback::new(
+///     CrashFeedback::new(),
+///     &state_observer,
+///     BundleTriageHook::new("crashes"),
+/// );
+/// ```
+#[derive(Debug)]
+pub struct TriageFeedback<F, H, PS>
+where
+    PS: Debug + Clone + Eq + Hash + Serialize + for<'a> Deserialize<'a>,
+{
+    objective: F,
+    hook: H,
+    observer_name: String,
+    output_observer_name: Option<String>,
+    pending: Option<(Vec<PS>, Option<String>)>,
+    phantom: PhantomData<PS>,
+}
+
+impl<F, H, PS> TriageFeedback<F, H, PS>
+where
+    PS: Debug + Clone + Eq + Hash + Serialize + for<'a> Deserialize<'a>,
+{
+    /// Create a new TriageFeedback
+    ///
+    /// # Arguments
+    /// - `objective`: the objective feedback that decides what a solution is
+    /// - `observer`: the StateObserver from which the states are taken
+    /// - `hook`: the hook to invoke for every saved objective
+    pub fn new(objective: F, observer: &StateObserver<PS>, hook: H) -> Self {
+        Self {
+            objective,
+            hook,
+            observer_name: observer.name().to_string(),
+            output_observer_name: None,
+            pending: None,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Like [`new()`](crate::TriageFeedback::new) but additionally passes the
+    /// output captured by a [`StdOutObserver`](libafl::observers::StdOutObserver) to the hook.
+    pub fn with_output(objective: F, observer: &StateObserver<PS>, output_observer: &StdOutObserver, hook: H) -> Self {
+        let mut ret = Self::new(objective, observer, hook);
+        ret.output_observer_name = Some(output_observer.name().to_string());
+        ret
+    }
+}
+
+impl<F, H, PS> Named for TriageFeedback<F, H, PS>
+where
+    PS: Debug + Clone + Eq + Hash + Serialize + for<'a> Deserialize<'a>,
+{
+    fn name(&self) -> &str {
+        "TriageFeedback"
+    }
+}
+
+impl<I, S, F, H, PS> Feedback<I, S> for TriageFeedback<F, H, PS>
+where
+    I: Input,
+    S: HasClientPerfMonitor,
+    F: Feedback<I, S>,
+    H: CrashTriageHook<I, PS>,
+    PS: Debug + Clone + Eq + Hash + Serialize + for<'a> Deserialize<'a>,
+{
+    fn init_state(&mut self, state: &mut S) -> Result<(), Error> {
+        self.objective.init_state(state)
+    }
+
+    fn is_interesting<EM, OT>(&mut self, state: &mut S, mgr: &mut EM, input: &I, observers: &OT, exit_kind: &ExitKind) -> Result<bool, Error>
+    where
+        EM: EventFirer<I>,
+        OT: ObserversTuple<I, S>,
+    {
+        let ret = self.objective.is_interesting(state, mgr, input, observers, exit_kind)?;
+
+        if ret {
+            let state_observer = observers.match_name::<StateObserver<PS>>(&self.observer_name).ok_or_else(|| Error::key_not_found(format!("No StateObserver named \"{}\" with the given state type", self.observer_name)))?;
+            let output = match &self.output_observer_name {
+                Some(name) => observers.match_name::<StdOutObserver>(name).ok_or_else(|| Error::key_not_found(format!("No StdOutObserver named \"{}\"", name)))?.stdout.clone(),
+                None => None,
+            };
+
+            self.pending = Some((state_observer.path_states(), output));
+        }
+
+        Ok(ret)
+    }
+
+    fn append_metadata(&mut self, state: &mut S, testcase: &mut Testcase<I>) -> Result<(), Error> {
+        self.objective.append_metadata(state, testcase)?;
+
+        if let (Some((states, output)), Some(input)) = (self.pending.take(), testcase.input()) {
+            if let Err(err) = self.hook.on_objective(input, &states, output.as_deref()) {
+                println!("[butterfly] Crash triage hook failed: {}", err);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn discard_metadata(&mut self, state: &mut S, input: &I) -> Result<(), Error> {
+        self.pending = None;
+        self.objective.discard_metadata(state, input)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libafl::{
+        bolts::{rands::StdRand, tuples::tuple_list},
+        corpus::InMemoryCorpus,
+        events::NopEventManager,
+        feedbacks::CrashFeedback,
+        inputs::BytesInput,
+        observers::Observer,
+        state::StdState,
+    };
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    /// Remembers the states of every objective and fails if told so
+    #[derive(Debug, Default)]
+    struct RecordingHook {
+        objectives: Rc<RefCell<Vec<Vec<u32>>>>,
+        fail: bool,
+    }
+
+    impl CrashTriageHook<BytesInput, u32> for RecordingHook {
+        fn on_objective(&mut self, _input: &BytesInput, states: &[u32], _output: Option<&str>) -> Result<(), Error> {
+            self.objectives.borrow_mut().push(states.to_vec());
+
+            if self.fail {
+                Err(Error::illegal_state("triage pipeline is down"))
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    #[test]
+    fn test_triage_feedback() {
+        let mut state = StdState::new(StdRand::with_seed(0), InMemoryCorpus::<BytesInput>::new(), InMemoryCorpus::<BytesInput>::new(), &mut (), &mut ()).unwrap();
+        let mut mgr = NopEventManager {};
+        let input = BytesInput::new(b"QUIT".to_vec());
+        let mut observers = tuple_list!(StateObserver::<u32>::new("state"));
+        let hook = RecordingHook::default();
+        let objectives = hook.objectives.clone();
+        let mut feedback = TriageFeedback::new(CrashFeedback::new(), &observers.0, hook);
+
+        let mut run = |observers: &mut (StateObserver<u32>, ()), states: &[u32], exit_kind: ExitKind, saved: bool| {
+            Observer::<BytesInput, _>::pre_exec(&mut observers.0, &mut state, &input).unwrap();
+            states.iter().for_each(|s| observers.0.record(s));
+
+            if feedback.is_interesting(&mut state, &mut mgr, &input, observers, &exit_kind).unwrap() {
+                if saved {
+                    feedback.append_metadata(&mut state, &mut Testcase::new(input.clone())).unwrap();
+                } else {
+                    feedback.discard_metadata(&mut state, &input).unwrap();
+                }
+            }
+
+            // A testcase without a pending objective doesn't invoke the hook again
+            feedback.append_metadata(&mut state, &mut Testcase::new(input.clone())).unwrap();
+        };
+
+        run(&mut observers, &[1, 2], ExitKind::Ok, true);
+        run(&mut observers, &[1, 3], ExitKind::Crash, true);
+        run(&mut observers, &[1, 4], ExitKind::Crash, false);
+        run(&mut observers, &[1, 5], ExitKind::Crash, true);
+        assert_eq!(*objectives.borrow(), [vec![1, 3], vec![1, 5]]);
+
+        // A failing hook doesn't stop the campaign
+        let hook = RecordingHook {
+            fail: true,
+            ..RecordingHook::default()
+        };
+        let objectives = hook.objectives.clone();
+        let mut feedback = TriageFeedback::new(CrashFeedback::new(), &observers.0, hook);
+        assert!(feedback.is_interesting(&mut state, &mut mgr, &input, &observers, &ExitKind::Crash).unwrap());
+        assert!(feedback.append_metadata(&mut state, &mut Testcase::new(input.clone())).is_ok());
+        assert_eq!(objectives.borrow().len(), 1);
+
+        // The observer must exist
+        let other = StateObserver::<u32>::new("other");
+        let mut feedback = TriageFeedback::new(CrashFeedback::new(), &other, RecordingHook::default());
+        assert!(feedback.is_interesting(&mut state, &mut mgr, &input, &observers, &ExitKind::Crash).is_err());
+    }
+
+    #[test]
+    fn test_bundles() {
+        let dir = std::env::temp_dir().join(format!("butterfly-triage-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let mut hook = BundleTriageHook::new(&dir).with_client_id(1);
+        let input = BytesInput::new(b"QUIT".to_vec());
+
+        CrashTriageHook::<_, u32>::on_objective(&mut hook, &input, &[220, 221], Some("bye")).unwrap();
+        CrashTriageHook::<_, u32>::on_objective(&mut hook, &input, &[220], None).unwrap();
+
+        // Every objective gets its own bundle
+        let first = dir.join("client-1").join("crash-0");
+        let second = dir.join("client-1").join("crash-1");
+        assert_eq!(BytesInput::from_file(first.join("input")).unwrap(), input);
+        assert_eq!(std::fs::read_to_string(first.join("states.txt")).unwrap(), "220\n221\n");
+        assert_eq!(std::fs::read_to_string(first.join("output.txt")).unwrap(), "bye");
+        assert_eq!(std::fs::read_to_string(second.join("states.txt")).unwrap(), "220\n");
+        assert!(!second.join("output.txt").exists());
+
+        // A restarted client doesn't overwrite the bundles of its previous run
+        let mut hook = BundleTriageHook::new(&dir).with_client_id(1);
+        CrashTriageHook::<_, u32>::on_objective(&mut hook, &input, &[230], None).unwrap();
+        assert_eq!(std::fs::read_to_string(first.join("states.txt")).unwrap(), "220\n221\n");
+        assert_eq!(std::fs::read_to_string(dir.join("client-1").join("crash-2").join("states.txt")).unwrap(), "230\n");
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
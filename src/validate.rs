@@ -0,0 +1,442 @@
+use crate::{
+    feedback::StateFeedback,
+    input::{HasPcapRepresentation, PcapLoader},
+    observer::StateObserver,
+};
+use libafl::{
+    bolts::HasLen,
+    events::EventFirer,
+    executors::{Executor, ExitKind, HasObservers},
+    feedbacks::Feedback,
+    inputs::Input,
+    observers::ObserversTuple,
+    state::HasClientPerfMonitor,
+    Error, Evaluator,
+};
+use serde::{Deserialize, Serialize};
+use std::cmp::Eq;
+use std::fmt::{Debug, Display, Formatter};
+use std::hash::Hash;
+use std::path::PathBuf;
+
+/// The result of [`validate_harness()`].
+///
+/// It contains everything that was observed during the dry-run
+/// and can tell you what might be wrong with your harness via [`HarnessReport::problems()`].
+#[derive(Clone, Debug)]
+pub struct HarnessReport<PS>
+where
+    PS: Clone + Debug + Eq + Hash,
+{
+    /// Name of the StateObserver that was looked up
+    pub observer_name: String,
+    /// Whether a StateObserver with the given name and state type was found
+    pub observer_found: bool,
+    /// Number of packets in the seed
+    pub packets: usize,
+    /// How the first execution ended
+    pub exit_kind: ExitKind,
+    /// The states recorded in the first execution
+    pub states: Vec<PS>,
+    /// Whether a state was recorded for every packet, i.e. a response to every packet was received
+    pub responses_received: bool,
+    /// Whether a [`StateFeedback`](crate::StateFeedback) considered the first execution interesting
+    pub feedback_fired: bool,
+    /// Whether a second execution of the same seed went through the same states
+    pub deterministic: bool,
+}
+
+impl<PS> HarnessReport<PS>
+where
+    PS: Clone + Debug + Eq + Hash,
+{
+    /// Returns a human-readable description of every misconfiguration that was detected.
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+
+        if !self.observer_found {
+            problems.push(format!("No StateObserver named \"{}\" with the given state type was found in the executors observers", self.observer_name));
+            return problems;
+        }
+
+        if self.exit_kind != ExitKind::Ok {
+            problems.push(format!("The seed did not execute cleanly but ended with {:?}", self.exit_kind));
+        }
+
+        if self.states.is_empty() {
+            problems.push("No states were recorded, does the executor call StateObserver::record()?".to_string());
+        } else if !self.responses_received {
+            problems.push(format!("Only {} states were recorded for {} packets, are all responses received?", self.states.len(), self.packets));
+        }
+
+        if !self.feedback_fired {
+            problems.push("StateFeedback did not consider the seed interesting, it will not be added to the corpus".to_string());
+        }
+
+        if !self.deterministic {
+            problems.push("Executing the seed twice resulted in different states, the state-graph will be noisy".to_string());
+        }
+
+        problems
+    }
+
+    /// Returns whether no problems were detected.
+    pub fn is_ok(&self) -> bool {
+        self.problems().is_empty()
+    }
+}
+
+impl<PS> Display for HarnessReport<PS>
+where
+    PS: Clone + Debug + Eq + Hash,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
+        writeln!(f, "[butterfly] Harness validation report:")?;
+        writeln!(f, "  observer:        {} ({})", self.observer_name, if self.observer_found { "found" } else { "missing" })?;
+        writeln!(f, "  packets:         {}", self.packets)?;
+        writeln!(f, "  exit kind:       {:?}", self.exit_kind)?;
+        writeln!(f, "  states:          {:?}", self.states)?;
+        writeln!(f, "  responses:       {}", if self.responses_received { "received" } else { "missing" })?;
+        writeln!(f, "  feedback fired:  {}", self.feedback_fired)?;
+        writeln!(f, "  deterministic:   {}", self.deterministic)?;
+
+        let problems = self.problems();
+
+        if problems.is_empty() {
+            write!(f, "  no problems found")
+        } else {
+            for problem in &problems {
+                writeln!(f, "  PROBLEM: {}", problem)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Run the executor once with all observers attached and return the exit kind.
+pub(crate) fn execute_once<E, EM, I, S, Z, OT>(fuzzer: &mut Z, state: &mut S, executor: &mut E, mgr: &mut EM, input: &I) -> Result<ExitKind, Error>
+where
+    E: Executor<EM, I, S, Z> + HasObservers<I, OT, S>,
+    OT: ObserversTuple<I, S>,
+    I: Input,
+{
+    executor.observers_mut().pre_exec_all(state, input)?;
+    let exit_kind = executor.run_target(fuzzer, state, mgr, input)?;
+    executor.observers_mut().post_exec_all(state, input, &exit_kind)?;
+    Ok(exit_kind)
+}
+
+/// Dry-run that checks whether a harness is wired up correctly.
+///
+/// It runs a single seed through the executor twice, without the fuzzer or its feedbacks
+/// being involved, and checks that
+/// - a [`StateObserver`] with name `observer_name` and state type `PS` is attached to the executor
+/// - the execution ended with [`ExitKind::Ok`](libafl::executors::ExitKind::Ok)
+/// - states were recorded, at least one per packet, which tells that the responses were received
+/// - a fresh [`StateFeedback`](crate::StateFeedback) considers the first execution interesting
+/// - the same states were recorded in both runs
+///
+/// The report gets printed to stdout and returned.
+/// Afterwards the state-graph of the observer is rolled back to what it was before the dry-run,
+/// so the seed stays interesting when the corpus gets loaded.
+/// Call this before loading the corpus, otherwise the state-graph may already know all transitions.
+///
+/// # Example
+/// ```
+/// let report: HarnessReport<u32> = validate_harness(&mut fuzzer, &mut state, &mut executor, &mut mgr, &seed, "state").unwrap();
+/// assert!(report.is_ok());
+/// ```
+pub fn validate_harness<E, EM, I, S, Z, OT, PS>(fuzzer: &mut Z, state: &mut S, executor: &mut E, mgr: &mut EM, input: &I, observer_name: &str) -> Result<HarnessReport<PS>, Error>
+where
+    E: Executor<EM, I, S, Z> + HasObservers<I, OT, S>,
+    EM: EventFirer<I>,
+    OT: ObserversTuple<I, S>,
+    I: Input + HasLen,
+    S: HasClientPerfMonitor,
+    PS: Clone + Debug + Eq + Hash + Serialize + for<'a> Deserialize<'a>,
+{
+    let mut report = HarnessReport {
+        observer_name: observer_name.to_string(),
+        observer_found: executor.observers().match_name::<StateObserver<PS>>(observer_name).is_some(),
+        packets: input.len(),
+        exit_kind: ExitKind::Ok,
+        states: Vec::new(),
+        responses_received: false,
+        feedback_fired: false,
+        deterministic: true,
+    };
+    let missing = || Error::key_not_found(format!("No StateObserver named \"{}\" with the given state type", observer_name));
+
+    if report.observer_found {
+        let snapshot = executor.observers().match_name::<StateObserver<PS>>(observer_name).ok_or_else(missing)?.snapshot()?;
+        report.exit_kind = execute_once(fuzzer, state, executor, mgr, input)?;
+
+        let observer = executor.observers().match_name::<StateObserver<PS>>(observer_name).ok_or_else(missing)?;
+        report.states = observer.path_states();
+        report.responses_received = report.states.len() >= report.packets;
+        report.feedback_fired = StateFeedback::new(observer).is_interesting(state, mgr, input, executor.observers(), &report.exit_kind)?;
+
+        execute_once(fuzzer, state, executor, mgr, input)?;
+
+        let observer = executor.observers_mut().match_name_mut::<StateObserver<PS>>(observer_name).ok_or_else(missing)?;
+        report.deterministic = observer.path_states() == report.states;
+        observer.rollback(&snapshot)?;
+    }
+
+    println!("{}", report);
+
+    Ok(report)
+}
+
+/// What a single seed contributed to the state-graph. Part of a [`SeedReport`].
+#[derive(Clone, Debug)]
+pub struct SeedCoverage<PS>
+where
+    PS: Clone + Debug + Eq + Hash,
+{
+    /// Path of the seed file
+    pub path: PathBuf,
+    /// The states the target went through while processing the seed
+    pub states: Vec<PS>,
+    /// Number of vertices this seed added to the state-graph
+    pub new_nodes: usize,
+    /// Number of edges this seed added to the state-graph
+    pub new_edges: usize,
+}
+
+impl<PS> SeedCoverage<PS>
+where
+    PS: Clone + Debug + Eq + Hash,
+{
+    /// Returns whether the seed discovered neither new vertices nor new edges.
+    pub fn is_useless(&self) -> bool {
+        self.new_nodes == 0 && self.new_edges == 0
+    }
+}
+
+/// The result of [`validate_seeds()`].
+#[derive(Clone, Debug)]
+pub struct SeedReport<PS>
+where
+    PS: Clone + Debug + Eq + Hash,
+{
+    /// Coverage of every seed in the order the seeds were loaded
+    pub seeds: Vec<SeedCoverage<PS>>,
+}
+
+impl<PS> SeedReport<PS>
+where
+    PS: Clone + Debug + Eq + Hash,
+{
+    /// Returns all seeds that did not contribute anything to the state-graph.
+    ///
+    /// Note that seeds are evaluated in order, so a seed is only useless
+    /// relative to the seeds that were loaded before it.
+    pub fn useless_seeds(&self) -> Vec<&SeedCoverage<PS>> {
+        self.seeds.iter().filter(|seed| seed.is_useless()).collect()
+    }
+}
+
+impl<PS> Display for SeedReport<PS>
+where
+    PS: Clone + Debug + Eq + Hash,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
+        writeln!(f, "[butterfly] Seed report:")?;
+
+        for seed in &self.seeds {
+            writeln!(f, "  {}: {} states | new nodes: {} | new edges: {}", seed.path.display(), seed.states.len(), seed.new_nodes, seed.new_edges)?;
+        }
+
+        write!(f, "  {} of {} seeds contributed nothing", self.useless_seeds().len(), self.seeds.len())
+    }
+}
+
+/// Returns how many vertices and edges the last run added to the state-graph of `observer`, given its size before the run.
+///
+/// The state-graph can also shrink, when states were evicted to stay within the node budget or the observer
+/// switched to a coarser abstraction level, so a run that discovered something counts at least once.
+fn coverage_delta<PS>(observer: &StateObserver<PS>, before: (usize, usize)) -> (usize, usize)
+where
+    PS: Clone + Debug + Eq + Hash + Serialize + for<'a> Deserialize<'a>,
+{
+    let (nodes, edges) = observer.info();
+    (nodes.saturating_sub(before.0).max(observer.had_new_nodes() as usize), edges.saturating_sub(before.1).max(observer.had_new_edges() as usize))
+}
+
+/// Like [`load_pcaps()`](crate::load_pcaps) but additionally reports what every seed contributed
+/// to the state-graph.
+///
+/// Each seed gets evaluated by the fuzzer as usual and files that are not valid captures are skipped,
+/// so this can be used as a drop-in replacement for [`load_pcaps()`](crate::load_pcaps).
+/// Like it, this uses the default settings of [`PcapLoader`], see [`PcapLoader::validate()`] for more control.
+///
+/// # Arguments
+/// - `state`: libafls state
+/// - `fuzzer`: libafls fuzzer
+/// - `executor`: libafls executor
+/// - `mgr`: libafls event manager
+/// - `in_dir`: path to directory with pcap files
+/// - `observer_name`: name of the [`StateObserver`] attached to the executor
+///
+/// # Example
+/// ```
+/// let report: SeedReport<u32> = validate_seeds(&mut state, &mut fuzzer, &mut executor, &mut mgr, "pcaps", "state").unwrap();
+///
+/// for seed in report.useless_seeds() {
+///     println!("{} is useless", seed.path.display());
+/// }
+/// ```
+pub fn validate_seeds<S, Z, E, EM, I, OT, P, PS>(state: &mut S, fuzzer: &mut Z, executor: &mut E, mgr: &mut EM, in_dir: P, observer_name: &str) -> Result<SeedReport<PS>, Error>
+where
+    Z: Evaluator<E, EM, I, S>,
+    E: HasObservers<I, OT, S>,
+    OT: ObserversTuple<I, S>,
+    I: HasPcapRepresentation<I>,
+    P: Into<PathBuf>,
+    PS: Clone + Debug + Eq + Hash + Serialize + for<'a> Deserialize<'a>,
+{
+    PcapLoader::new().validate(state, fuzzer, executor, mgr, in_dir, observer_name)
+}
+
+impl PcapLoader {
+    /// Load all pcap files in `in_dir` into the corpus and report their coverage like [`validate_seeds()`]
+    pub fn validate<S, Z, E, EM, I, OT, P, PS>(&self, state: &mut S, fuzzer: &mut Z, executor: &mut E, mgr: &mut EM, in_dir: P, observer_name: &str) -> Result<SeedReport<PS>, Error>
+    where
+        Z: Evaluator<E, EM, I, S>,
+        E: HasObservers<I, OT, S>,
+        OT: ObserversTuple<I, S>,
+        I: HasPcapRepresentation<I>,
+        P: Into<PathBuf>,
+        PS: Clone + Debug + Eq + Hash + Serialize + for<'a> Deserialize<'a>,
+    {
+        let mut report = SeedReport {
+            seeds: Vec::new(),
+        };
+
+        let missing = || Error::key_not_found(format!("No StateObserver named \"{}\" with the given state type", observer_name));
+
+        for path in self.find(in_dir)? {
+            let before = executor.observers().match_name::<StateObserver<PS>>(observer_name).ok_or_else(missing)?.info();
+
+            let input: I = match self.parse(&path)? {
+                Some(input) => input,
+                None => continue,
+            };
+            let _ = fuzzer.evaluate_input(state, executor, mgr, input)?;
+
+            let observer = executor.observers().match_name::<StateObserver<PS>>(observer_name).ok_or_else(missing)?;
+            let (new_nodes, new_edges) = coverage_delta(observer, before);
+
+            report.seeds.push(SeedCoverage {
+                path,
+                states: observer.path_states(),
+                new_nodes,
+                new_edges,
+            });
+        }
+
+        println!("{}", report);
+
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::observer::NodeBudgetPolicy;
+    use libafl::{
+        bolts::rands::StdRand,
+        corpus::InMemoryCorpus,
+        events::NopEventManager,
+        inputs::{BytesInput, HasBytesVec},
+        observers::Observer,
+        state::StdState,
+    };
+
+    /// Records every byte of the input as a state, except for zeros that get no response
+    #[derive(Debug)]
+    struct ByteExecutor {
+        observers: (StateObserver<u32>, ()),
+    }
+
+    impl<EM, S, Z> Executor<EM, BytesInput, S, Z> for ByteExecutor {
+        fn run_target(&mut self, _fuzzer: &mut Z, _state: &mut S, _mgr: &mut EM, input: &BytesInput) -> Result<ExitKind, Error> {
+            input.bytes().iter().filter(|byte| **byte != 0).for_each(|byte| self.observers.0.record(&(*byte as u32)));
+            Ok(ExitKind::Ok)
+        }
+    }
+
+    impl<S> HasObservers<BytesInput, (StateObserver<u32>, ()), S> for ByteExecutor {
+        fn observers(&self) -> &(StateObserver<u32>, ()) {
+            &self.observers
+        }
+
+        fn observers_mut(&mut self) -> &mut (StateObserver<u32>, ()) {
+            &mut self.observers
+        }
+    }
+
+    fn run(observer: &mut StateObserver<u32>, states: &[u32]) -> (usize, usize) {
+        let before = observer.info();
+        Observer::<(), ()>::pre_exec(observer, &mut (), &()).unwrap();
+
+        for state in states {
+            observer.record(state);
+        }
+
+        coverage_delta(observer, before)
+    }
+
+    #[test]
+    fn test_coverage_delta() {
+        let mut observer = StateObserver::<u32>::new("state");
+        assert_eq!(run(&mut observer, &[1, 2, 3]), (3, 2));
+        assert_eq!(run(&mut observer, &[1, 2, 3]), (0, 0));
+
+        // Evicting a state for a new one removes its edges
+        let mut observer = StateObserver::<u32>::new("state").with_node_budget(2, NodeBudgetPolicy::EvictColdest);
+        assert_eq!(run(&mut observer, &[1, 2]), (2, 1));
+        assert_eq!(run(&mut observer, &[3]), (1, 0));
+
+        // The coarser abstraction level that the observer switches to is smaller
+        let mut observer = StateObserver::<u32>::new("state").with_abstraction_level(|code| code / 100 * 100).with_abstraction_switching(1000, 2);
+        assert_eq!(run(&mut observer, &[200, 201, 202]), (3, 2));
+        assert_eq!(run(&mut observer, &[200, 201]), (0, 0));
+        assert_eq!(observer.abstraction_level(), 1);
+    }
+
+    #[test]
+    fn test_validate_harness() {
+        let mut state = StdState::new(StdRand::with_seed(0), InMemoryCorpus::<BytesInput>::new(), InMemoryCorpus::<BytesInput>::new(), &mut (), &mut ()).unwrap();
+        let mut mgr = NopEventManager {};
+        let mut executor = ByteExecutor {
+            observers: (StateObserver::new("state"), ()),
+        };
+
+        let report: HarnessReport<u32> = validate_harness(&mut (), &mut state, &mut executor, &mut mgr, &BytesInput::new(vec![1, 2, 3]), "state").unwrap();
+        assert!(report.is_ok());
+        assert_eq!(report.states, [1, 2, 3]);
+
+        // The dry-run leaves the state-graph untouched, so the seed is still interesting afterwards
+        assert_eq!(executor.observers.0.info(), StateObserver::<u32>::new("state").info());
+        assert!(validate_harness::<_, _, _, _, _, _, u32>(&mut (), &mut state, &mut executor, &mut mgr, &BytesInput::new(vec![1, 2, 3]), "state").unwrap().feedback_fired);
+
+        // A packet without a response
+        let report: HarnessReport<u32> = validate_harness(&mut (), &mut state, &mut executor, &mut mgr, &BytesInput::new(vec![1, 0, 3]), "state").unwrap();
+        assert!(!report.responses_received);
+        assert!(report.feedback_fired);
+        assert_eq!(report.problems().len(), 1);
+
+        // Another seed that was loaded before makes the seed uninteresting
+        executor.observers.0.record(&1);
+        executor.observers.0.record(&2);
+        let report: HarnessReport<u32> = validate_harness(&mut (), &mut state, &mut executor, &mut mgr, &BytesInput::new(vec![1, 2]), "state").unwrap();
+        assert!(report.responses_received);
+        assert!(!report.feedback_fired);
+
+        let report: HarnessReport<u32> = validate_harness(&mut (), &mut state, &mut executor, &mut mgr, &BytesInput::new(vec![1]), "other").unwrap();
+        assert!(!report.observer_found);
+    }
+}
@@ -0,0 +1,472 @@
+//! End-to-end tests of the butterfly components against the built-in [`ToyFtpServer`].
+#![cfg(feature = "toy_target")]
+
+use butterfly_fuzz::{
+    supported_havoc_mutations, ConformanceChecker, ConformanceFeedback, ConformanceMetadata, HasCrossoverInsertMutation, HasCrossoverReplaceMutation, HasHavocMutation, HasPackets, HasPostMutationFixup, HasSpliceMutation, NetworkExecutor, NetworkPacket,
+    PacketCrossoverInsertMutator, PacketCrossoverReplaceMutator, PacketDeleteMutator, PacketDuplicateMutator, PacketHavocMutator, PacketMutationScheduler, PacketReconnectMutator, PacketReorderMutator, PacketSpliceMutator, StateFeedback, StateMonitor,
+    StateObserver, TargetSelection, TextLinePacket, ToyFtpServer, TransportEvent,
+};
+use libafl::{
+    bolts::{
+        rands::StdRand,
+        tuples::{tuple_list, MatchName},
+        HasLen,
+    },
+    corpus::{Corpus, InMemoryCorpus},
+    events::SimpleEventManager,
+    executors::{Executor, ExitKind, HasObservers},
+    feedbacks::CrashFeedback,
+    fuzzer::{Evaluator, ExecuteInputResult},
+    inputs::{BytesInput, HasBytesVec, Input},
+    mutators::{MutationResult, MutatorsTuple},
+    observers::ObserversTuple,
+    schedulers::QueueScheduler,
+    stages::StdMutationalStage,
+    state::{HasMaxSize, HasMetadata, HasRand, HasSolutions, StdState},
+    Error, Fuzzer, StdFuzzer,
+};
+use serde::{Deserialize, Serialize};
+use std::fmt::{Debug, Formatter};
+use std::io::{BufRead, BufReader, Write};
+use std::marker::PhantomData;
+use std::net::{SocketAddr, TcpStream};
+
+#[derive(Hash, Debug, Clone, Serialize, Deserialize)]
+enum ToyCommand {
+    User(BytesInput),
+    Pass(BytesInput),
+    Cwd(BytesInput),
+    List,
+    Quit,
+}
+
+impl ToyCommand {
+    fn inner_data(&self) -> Option<&BytesInput> {
+        match self {
+            ToyCommand::User(data) | ToyCommand::Pass(data) | ToyCommand::Cwd(data) => Some(data),
+            _ => None,
+        }
+    }
+
+    fn inner_data_mut(&mut self) -> Option<&mut BytesInput> {
+        match self {
+            ToyCommand::User(data) | ToyCommand::Pass(data) | ToyCommand::Cwd(data) => Some(data),
+            _ => None,
+        }
+    }
+}
+
+impl<S> HasCrossoverInsertMutation<S> for ToyCommand
+where
+    S: HasRand + HasMaxSize,
+{
+    fn mutate_crossover_insert(&mut self, state: &mut S, other: &Self, stage_idx: i32) -> Result<MutationResult, Error> {
+        match (self.inner_data_mut(), other.inner_data()) {
+            (Some(data), Some(other_data)) => data.mutate_crossover_insert(state, other_data, stage_idx),
+            _ => Ok(MutationResult::Skipped),
+        }
+    }
+}
+
+impl<S> HasCrossoverReplaceMutation<S> for ToyCommand
+where
+    S: HasRand + HasMaxSize,
+{
+    fn mutate_crossover_replace(&mut self, state: &mut S, other: &Self, stage_idx: i32) -> Result<MutationResult, Error> {
+        match (self.inner_data_mut(), other.inner_data()) {
+            (Some(data), Some(other_data)) => data.mutate_crossover_replace(state, other_data, stage_idx),
+            _ => Ok(MutationResult::Skipped),
+        }
+    }
+}
+
+impl<S> HasSpliceMutation<S> for ToyCommand
+where
+    S: HasRand + HasMaxSize,
+{
+    fn mutate_splice(&mut self, state: &mut S, other: &Self, stage_idx: i32) -> Result<MutationResult, Error> {
+        match (self.inner_data_mut(), other.inner_data()) {
+            (Some(data), Some(other_data)) => data.mutate_splice(state, other_data, stage_idx),
+            _ => Ok(MutationResult::Skipped),
+        }
+    }
+}
+
+impl HasPostMutationFixup for ToyCommand {}
+
+impl<MT, S> HasHavocMutation<MT, S> for ToyCommand
+where
+    MT: MutatorsTuple<BytesInput, S>,
+    S: HasRand + HasMaxSize,
+{
+    fn mutate_havoc(&mut self, state: &mut S, mutations: &mut MT, mutation: usize, stage_idx: i32) -> Result<MutationResult, Error> {
+        match self.inner_data_mut() {
+            Some(data) => data.mutate_havoc(state, mutations, mutation, stage_idx),
+            None => Ok(MutationResult::Skipped),
+        }
+    }
+}
+
+#[derive(Hash, Debug, Clone, Serialize, Deserialize)]
+struct ToyInput {
+    packets: Vec<ToyCommand>,
+}
+
+impl Input for ToyInput {
+    fn generate_name(&self, idx: usize) -> String {
+        format!("toyinput-{}", idx)
+    }
+}
+
+impl HasPackets<ToyCommand> for ToyInput {
+    fn packets(&self) -> &[ToyCommand] {
+        &self.packets
+    }
+
+    fn packets_mut(&mut self) -> &mut Vec<ToyCommand> {
+        &mut self.packets
+    }
+}
+
+impl HasLen for ToyInput {
+    fn len(&self) -> usize {
+        self.packets.len()
+    }
+}
+
+struct ToyExecutor<OT, S>
+where
+    OT: ObserversTuple<ToyInput, S>,
+{
+    observers: OT,
+    addr: SocketAddr,
+    phantom: PhantomData<S>,
+}
+
+impl<OT, S> ToyExecutor<OT, S>
+where
+    OT: ObserversTuple<ToyInput, S>,
+{
+    fn new(observers: OT, addr: SocketAddr) -> Self {
+        Self {
+            observers,
+            addr,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Read a reply and record its status code. Returns false if the server hung up.
+    fn get_response(&mut self, reader: &mut BufReader<TcpStream>) -> bool {
+        let mut line = Vec::new();
+
+        match reader.read_until(b'\n', &mut line) {
+            Ok(0) | Err(_) => false,
+            Ok(_) => {
+                let code = std::str::from_utf8(&line[0..3]).unwrap().parse::<u32>().unwrap();
+                let state_observer: &mut StateObserver<u32> = self.observers.match_name_mut("state").unwrap();
+                state_observer.record(&code);
+                true
+            },
+        }
+    }
+}
+
+impl<OT, S> Debug for ToyExecutor<OT, S>
+where
+    OT: ObserversTuple<ToyInput, S>,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
+        write!(f, "ToyExecutor {{ }}")
+    }
+}
+
+impl<OT, S> HasObservers<ToyInput, OT, S> for ToyExecutor<OT, S>
+where
+    OT: ObserversTuple<ToyInput, S>,
+{
+    fn observers(&self) -> &OT {
+        &self.observers
+    }
+
+    fn observers_mut(&mut self) -> &mut OT {
+        &mut self.observers
+    }
+}
+
+impl<OT, S, EM, Z> Executor<EM, ToyInput, S, Z> for ToyExecutor<OT, S>
+where
+    OT: ObserversTuple<ToyInput, S>,
+{
+    fn run_target(&mut self, _fuzzer: &mut Z, _state: &mut S, _mgr: &mut EM, input: &ToyInput) -> Result<ExitKind, Error> {
+        let mut conn = TcpStream::connect(self.addr)?;
+        let mut reader = BufReader::new(conn.try_clone()?);
+
+        if !self.get_response(&mut reader) {
+            return Ok(ExitKind::Crash);
+        }
+
+        for packet in input.packets() {
+            let (verb, arg): (&[u8], Option<&BytesInput>) = match packet {
+                ToyCommand::User(data) => (b"USER", Some(data)),
+                ToyCommand::Pass(data) => (b"PASS", Some(data)),
+                ToyCommand::Cwd(data) => (b"CWD", Some(data)),
+                ToyCommand::List => (b"LIST", None),
+                ToyCommand::Quit => (b"QUIT", None),
+            };
+
+            let mut line = verb.to_vec();
+            if let Some(arg) = arg {
+                line.push(b' ');
+                line.extend(arg.bytes().iter().filter(|c| **c != b'\n'));
+            }
+            line.extend_from_slice(b"\r\n");
+
+            if conn.write_all(&line).is_err() || !self.get_response(&mut reader) {
+                return Ok(ExitKind::Crash);
+            }
+
+            if let ToyCommand::Quit = packet {
+                break;
+            }
+        }
+
+        Ok(ExitKind::Ok)
+    }
+}
+
+fn seed() -> ToyInput {
+    ToyInput {
+        packets: vec![ToyCommand::User(BytesInput::new(b"anonymous".to_vec())), ToyCommand::Pass(BytesInput::new(b"anonymous".to_vec())), ToyCommand::Cwd(BytesInput::new(b"/tmp".to_vec())), ToyCommand::List, ToyCommand::Quit],
+    }
+}
+
+#[test]
+fn test_seed_builds_stategraph() {
+    let server = ToyFtpServer::spawn().unwrap();
+    let mut mgr = SimpleEventManager::new(StateMonitor::new());
+    let state_observer = StateObserver::<u32>::new("state");
+    let mut feedback = StateFeedback::new(&state_observer);
+    let mut objective = CrashFeedback::new();
+    let mut state = StdState::new(StdRand::with_seed(0), InMemoryCorpus::new(), InMemoryCorpus::new(), &mut feedback, &mut objective).unwrap();
+    let mut fuzzer = StdFuzzer::new(QueueScheduler::new(), feedback, objective);
+    let mut executor = ToyExecutor::new(tuple_list!(state_observer), server.addr());
+
+    let (result, _) = fuzzer.evaluate_input(&mut state, &mut executor, &mut mgr, seed()).unwrap();
+    assert!(matches!(result, ExecuteInputResult::Corpus));
+
+    let state_observer: &StateObserver<u32> = executor.observers().match_name("state").unwrap();
+    assert_eq!(state_observer.path(), &[0, 1, 2, 3, 4, 5]);
+    assert_eq!(state_observer.info(), (6, 5));
+}
+
+#[test]
+fn test_planted_bug_is_objective() {
+    let server = ToyFtpServer::spawn().unwrap();
+    let mut mgr = SimpleEventManager::new(StateMonitor::new());
+    let state_observer = StateObserver::<u32>::new("state");
+    let mut feedback = StateFeedback::new(&state_observer);
+    let mut objective = CrashFeedback::new();
+    let mut state = StdState::new(StdRand::with_seed(0), InMemoryCorpus::new(), InMemoryCorpus::new(), &mut feedback, &mut objective).unwrap();
+    let mut fuzzer = StdFuzzer::new(QueueScheduler::new(), feedback, objective);
+    let mut executor = ToyExecutor::new(tuple_list!(state_observer), server.addr());
+
+    let mut input = seed();
+    input.packets[2] = ToyCommand::Cwd(BytesInput::new(vec![b'A'; 128]));
+
+    let (result, _) = fuzzer.evaluate_input(&mut state, &mut executor, &mut mgr, input).unwrap();
+    assert!(matches!(result, ExecuteInputResult::Solution));
+    assert_eq!(state.solutions().count(), 1);
+}
+
+#[test]
+fn test_fuzz_loop() {
+    let server = ToyFtpServer::spawn().unwrap();
+    let mut mgr = SimpleEventManager::new(StateMonitor::new());
+    let state_observer = StateObserver::<u32>::new("state");
+    let mut feedback = StateFeedback::new(&state_observer);
+    let mut objective = CrashFeedback::new();
+    let mut state = StdState::new(StdRand::with_seed(0), InMemoryCorpus::new(), InMemoryCorpus::new(), &mut feedback, &mut objective).unwrap();
+    let mut fuzzer = StdFuzzer::new(QueueScheduler::new(), feedback, objective);
+    let mutator = PacketMutationScheduler::new(tuple_list!(
+        PacketReorderMutator::new(),
+        PacketDeleteMutator::new(1),
+        PacketDuplicateMutator::new(16),
+        PacketCrossoverInsertMutator::new(),
+        PacketCrossoverReplaceMutator::new(),
+        PacketSpliceMutator::new(1),
+        PacketHavocMutator::new(supported_havoc_mutations())
+    ));
+    let mut stages = tuple_list!(StdMutationalStage::new(mutator));
+    let mut executor = ToyExecutor::new(tuple_list!(state_observer), server.addr());
+
+    fuzzer.evaluate_input(&mut state, &mut executor, &mut mgr, seed()).unwrap();
+    fuzzer.fuzz_loop_for(&mut stages, &mut executor, &mut state, &mut mgr, 20).unwrap();
+
+    let state_observer: &StateObserver<u32> = executor.observers().match_name("state").unwrap();
+    let (nodes, edges) = state_observer.info();
+    assert!(nodes >= 6);
+    assert!(edges >= 5);
+}
+
+#[derive(Hash, Debug, Clone, Serialize, Deserialize)]
+struct NetInput {
+    packets: Vec<NetworkPacket<TextLinePacket>>,
+}
+
+impl Input for NetInput {
+    fn generate_name(&self, idx: usize) -> String {
+        format!("netinput-{}", idx)
+    }
+}
+
+impl HasPackets<NetworkPacket<TextLinePacket>> for NetInput {
+    fn packets(&self) -> &[NetworkPacket<TextLinePacket>] {
+        &self.packets
+    }
+
+    fn packets_mut(&mut self) -> &mut Vec<NetworkPacket<TextLinePacket>> {
+        &mut self.packets
+    }
+}
+
+impl HasLen for NetInput {
+    fn len(&self) -> usize {
+        self.packets.len()
+    }
+}
+
+fn net_seed(lines: &[u8]) -> NetInput {
+    NetInput {
+        packets: TextLinePacket::parse(lines).into_iter().map(NetworkPacket::Data).collect(),
+    }
+}
+
+fn status_code(response: &[u8]) -> Option<u32> {
+    std::str::from_utf8(response.get(0..3)?).ok()?.parse().ok()
+}
+
+#[test]
+fn test_network_executor_reconnect() {
+    let server = ToyFtpServer::spawn().unwrap();
+    let mut mgr = SimpleEventManager::new(StateMonitor::new());
+    let state_observer = StateObserver::<u32>::new("state");
+    let mut feedback = StateFeedback::new(&state_observer);
+    let mut objective = CrashFeedback::new();
+    let mut state = StdState::new(StdRand::with_seed(0), InMemoryCorpus::new(), InMemoryCorpus::new(), &mut feedback, &mut objective).unwrap();
+    let mut fuzzer = StdFuzzer::new(QueueScheduler::new(), feedback, objective);
+    let mut executor = NetworkExecutor::new(tuple_list!(state_observer), server.addr(), "state", status_code).with_greeting();
+
+    // The login must not survive the reconnect
+    let mut input = net_seed(b"USER anonymous\r\nPASS anonymous\r\nLIST\r\n");
+    input.packets.insert(2, NetworkPacket::Disconnect);
+    input.packets.insert(3, NetworkPacket::Connect);
+
+    let (result, _) = fuzzer.evaluate_input(&mut state, &mut executor, &mut mgr, input).unwrap();
+    assert!(matches!(result, ExecuteInputResult::Corpus));
+
+    let state_observer: &StateObserver<u32> = executor.observers().match_name("state").unwrap();
+    assert_eq!(state_observer.path_states(), vec![220, 331, 230, 220, 530]);
+}
+
+#[test]
+fn test_network_executor_fuzz_loop() {
+    let server = ToyFtpServer::spawn().unwrap();
+    let mut mgr = SimpleEventManager::new(StateMonitor::new());
+    let state_observer = StateObserver::<u32>::new("state");
+    let mut feedback = StateFeedback::new(&state_observer);
+    let mut objective = CrashFeedback::new();
+    let mut state = StdState::new(StdRand::with_seed(0), InMemoryCorpus::new(), InMemoryCorpus::new(), &mut feedback, &mut objective).unwrap();
+    let mut fuzzer = StdFuzzer::new(QueueScheduler::new(), feedback, objective);
+    let mutator = PacketMutationScheduler::new(tuple_list!(PacketReconnectMutator::new(16), PacketDuplicateMutator::new(16), PacketHavocMutator::new(supported_havoc_mutations())));
+    let mut stages = tuple_list!(StdMutationalStage::new(mutator));
+    let mut executor = NetworkExecutor::new(tuple_list!(state_observer), server.addr(), "state", status_code).with_greeting();
+
+    fuzzer.evaluate_input(&mut state, &mut executor, &mut mgr, net_seed(b"USER a\r\nPASS b\r\nCWD /\r\nQUIT\r\n")).unwrap();
+    fuzzer.fuzz_loop_for(&mut stages, &mut executor, &mut state, &mut mgr, 20).unwrap();
+}
+
+#[test]
+fn test_network_executor_failover() {
+    let server = ToyFtpServer::spawn().unwrap();
+    // An address that refuses connections
+    let dead = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+    let mut mgr = SimpleEventManager::new(StateMonitor::new());
+    let state_observer = StateObserver::<u32>::new("state");
+    let mut feedback = StateFeedback::new(&state_observer);
+    let mut objective = CrashFeedback::new();
+    let mut state = StdState::new(StdRand::with_seed(0), InMemoryCorpus::new(), InMemoryCorpus::new(), &mut feedback, &mut objective).unwrap();
+    let mut fuzzer = StdFuzzer::new(QueueScheduler::new(), feedback, objective);
+    let mut executor = NetworkExecutor::new(tuple_list!(state_observer), server.addr(), "state", status_code).with_greeting().with_targets(vec![dead, server.addr()], TargetSelection::RoundRobin);
+
+    // Both executions must reach the live server, no matter which address was picked first
+    for _ in 0..2 {
+        let (result, _) = fuzzer.evaluate_input(&mut state, &mut executor, &mut mgr, net_seed(b"USER anonymous\r\nPASS anonymous\r\n")).unwrap();
+        assert!(!matches!(result, ExecuteInputResult::Solution));
+
+        let state_observer: &StateObserver<u32> = executor.observers().match_name("state").unwrap();
+        assert_eq!(state_observer.path_states(), vec![220, 331, 230]);
+    }
+}
+
+#[test]
+fn test_network_executor_transport_states() {
+    let server = ToyFtpServer::spawn().unwrap();
+    let dead = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+    let mut mgr = SimpleEventManager::new(StateMonitor::new());
+    let state_observer = StateObserver::<u32>::new("state");
+    let mut feedback = StateFeedback::new(&state_observer);
+    let mut objective = CrashFeedback::new();
+    let mut state = StdState::new(StdRand::with_seed(0), InMemoryCorpus::new(), InMemoryCorpus::new(), &mut feedback, &mut objective).unwrap();
+    let mut fuzzer = StdFuzzer::new(QueueScheduler::new(), feedback, objective);
+    let transport_state = |event| match event {
+        TransportEvent::Timeout => None,
+        event => Some(1000 + event as u32),
+    };
+    let mut executor = NetworkExecutor::new(tuple_list!(state_observer), server.addr(), "state", status_code).with_greeting().with_transport_states(transport_state);
+
+    // The planted bug closes the connection
+    let mut input = net_seed(b"USER anonymous\r\nPASS anonymous\r\n");
+    input.packets.push(NetworkPacket::Data(TextLinePacket::parse(format!("CWD {}\r\n", "A".repeat(100)).as_bytes()).remove(0)));
+    let (result, _) = fuzzer.evaluate_input(&mut state, &mut executor, &mut mgr, input).unwrap();
+    assert!(matches!(result, ExecuteInputResult::Solution));
+
+    let state_observer: &StateObserver<u32> = executor.observers().match_name("state").unwrap();
+    assert_eq!(state_observer.path_states(), vec![220, 331, 230, 1001]);
+
+    let mut executor = NetworkExecutor::new(tuple_list!(StateObserver::<u32>::new("state")), dead, "state", status_code).with_transport_states(transport_state);
+    let exit_kind = executor.run_target(&mut fuzzer, &mut state, &mut mgr, &net_seed(b"USER anonymous\r\n")).unwrap();
+    assert_eq!(exit_kind, ExitKind::Crash);
+
+    let state_observer: &StateObserver<u32> = executor.observers().match_name("state").unwrap();
+    assert_eq!(state_observer.path_states(), vec![1000]);
+}
+
+#[test]
+fn test_network_executor_conformance() {
+    let server = ToyFtpServer::spawn().unwrap();
+    let mut mgr = SimpleEventManager::new(StateMonitor::new());
+    let state_observer = StateObserver::<u32>::new("state");
+    let mut feedback = StateFeedback::new(&state_observer);
+    let mut objective = ConformanceFeedback::new();
+    let mut state = StdState::new(StdRand::with_seed(0), InMemoryCorpus::new(), InMemoryCorpus::new(), &mut feedback, &mut objective).unwrap();
+    let mut fuzzer = StdFuzzer::new(QueueScheduler::new(), feedback, objective);
+    let checker = ConformanceChecker::new().with_rule(
+        "PASS must be answered with 230 or 530",
+        |packet: &NetworkPacket<TextLinePacket>| matches!(packet, NetworkPacket::Data(line) if line.keyword() == b"PASS"),
+        |response| response.starts_with(b"230") || response.starts_with(b"530"),
+    );
+    let mut executor = NetworkExecutor::new(tuple_list!(state_observer), server.addr(), "state", status_code).with_greeting().with_conformance_checker(checker);
+
+    let (result, _) = fuzzer.evaluate_input(&mut state, &mut executor, &mut mgr, net_seed(b"USER anonymous\r\nPASS anonymous\r\n")).unwrap();
+    assert!(!matches!(result, ExecuteInputResult::Solution));
+
+    // The toy server answers a PASS without USER with 503
+    let (result, _) = fuzzer.evaluate_input(&mut state, &mut executor, &mut mgr, net_seed(b"PASS anonymous\r\n")).unwrap();
+    assert!(matches!(result, ExecuteInputResult::Solution));
+
+    let solution = state.solutions().get(0).unwrap().borrow();
+    let violations = &solution.metadata().get::<ConformanceMetadata>().unwrap().violations;
+    assert_eq!(violations.len(), 1);
+    assert_eq!(violations[0].request, b"PASS anonymous\r\n");
+    assert!(violations[0].response.starts_with(b"503"));
+}